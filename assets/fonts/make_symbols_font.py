@@ -0,0 +1,303 @@
+#!/usr/bin/env python3
+"""Generates WeztermSymbols-Regular.ttf, a small fallback font with the
+geometric symbols from the Nerd Fonts Private Use Area that are popular
+in shell prompts and status lines:
+
+* The Powerline Extra separators U+E0B4-U+E0C8, U+E0CA, U+E0CC,
+  U+E0CD, U+E0D2 and U+E0D4
+* The progress bar and spinner symbols U+EE00-U+EE0B
+
+The core Powerline symbols U+E0A0-U+E0A2 and U+E0B0-U+E0B3 are already
+provided by JetBrains Mono, which always comes first in the fallback
+list, so they are not included here.
+
+The glyphs are simple geometric shapes that are one cell wide; the
+separators span the full line height of JetBrains Mono, so that
+adjacent separators join up seamlessly.  The icon sets that Nerd Fonts
+patch in, such as Font Awesome and Devicons, are not included.
+
+This uses the shared helpers in ../../test-data/sfnt.py.
+
+Usage: python3 make_symbols_font.py
+"""
+import math
+import os
+import struct
+import sys
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+sys.path.insert(0, os.path.join(HERE, "..", "..", "test-data"))
+
+from sfnt import (  # noqa: E402
+    bbox,
+    build_sfnt,
+    cmap_table,
+    glyf_loca,
+    head_table,
+    hhea_table,
+    maxp_table,
+    name_table,
+    os2_table,
+    post_table,
+    simple_glyph,
+)
+
+ADVANCE = 600
+# The ascender and descender of JetBrains Mono
+ASCENDER = 1020
+DESCENDER = -300
+MIDDLE = (ASCENDER + DESCENDER) // 2
+# The thickness of the thin separators
+STROKE = 80
+ARC_STEPS = 32
+
+
+def clockwise(points):
+    """TrueType fills contours that run clockwise"""
+    area = sum(x0 * y1 - x1 * y0 for (x0, y0), (x1, y1) in zip(points, points[1:] + points[:1]))
+    return list(reversed(points)) if area > 0 else points
+
+
+def counter_clockwise(points):
+    """Contours that run counter-clockwise cut holes in the fill"""
+    return list(reversed(clockwise(points)))
+
+
+def mirror(contours):
+    return [[(ADVANCE - x, y) for x, y in points] for points in contours]
+
+
+def box(x0, y0, x1, y1):
+    return [(x0, y0), (x0, y1), (x1, y1), (x1, y0)]
+
+
+def arc(rx, ry):
+    """The right half of an ellipse centered on the left edge of the
+    cell, from the top to the bottom"""
+    return [
+        (
+            round(rx * math.cos(math.pi / 2 - math.pi * i / ARC_STEPS)),
+            round(MIDDLE + ry * math.sin(math.pi / 2 - math.pi * i / ARC_STEPS)),
+        )
+        for i in range(ARC_STEPS + 1)
+    ]
+
+
+def right_edge(xs):
+    """The area between the left edge of the cell and a jagged right
+    edge, which passes through each of `xs` from the top to the bottom"""
+    step = (ASCENDER - DESCENDER) / (len(xs) - 1)
+    edge = [(x, round(ASCENDER - i * step)) for i, x in enumerate(xs)]
+    return [(0, DESCENDER), (0, ASCENDER)] + edge
+
+
+def thin_edge(xs):
+    """Just the jagged right edge of `right_edge`"""
+    step = (ASCENDER - DESCENDER) / (len(xs) - 1)
+    edge = [(x, round(ASCENDER - i * step)) for i, x in enumerate(xs)]
+    return edge + [(x - STROKE, y) for x, y in reversed(edge)]
+
+
+def steps(widths):
+    """A column of blocks along the left edge of the cell, one row
+    per entry in `widths`, from the top to the bottom"""
+    height = (ASCENDER - DESCENDER) / len(widths)
+    return [
+        box(0, round(ASCENDER - (i + 1) * height), width, round(ASCENDER - i * height))
+        for i, width in enumerate(widths)
+    ]
+
+
+def hexagon(r):
+    cx, cy = ADVANCE // 2, MIDDLE
+    return [
+        (round(cx + r * math.cos(math.pi / 3 * i)), round(cy + r * math.sin(math.pi / 3 * i)))
+        for i in range(6)
+    ]
+
+
+RX = ADVANCE
+RY = ASCENDER - MIDDLE
+HALF_CIRCLE = [arc(RX, RY)]
+THIN_HALF_CIRCLE = [arc(RX, RY) + list(reversed(arc(RX - STROKE, RY - STROKE)))]
+BACKSLASH = [[(0, ASCENDER), (STROKE, ASCENDER), (ADVANCE, DESCENDER), (ADVANCE - STROKE, DESCENDER)]]
+SLASH = [[(0, DESCENDER), (STROKE, DESCENDER), (ADVANCE, ASCENDER), (ADVANCE - STROKE, ASCENDER)]]
+FLAME = [300, 600, 380, 560, 300, 600, 420, 520, 300]
+ICE = [520, 600, 360, 560, 440, 600, 300, 520, 420, 600, 340]
+BIG_PIXELS = steps([200, 500, 300, 600, 400, 200])
+SMALL_PIXELS = steps([200, 400, 300, 500, 200, 600, 300, 500, 400, 200, 300, 400])
+
+# The progress bar is drawn within these lines, and its pieces join
+# up with each other across cells
+BAR_BOTTOM = -120
+BAR_TOP = 840
+BAR_STROKE = 60
+BAR_LEFT = 100
+
+
+def bar_end(filled):
+    """The left end of the progress bar"""
+    s = BAR_STROKE
+    outline = [
+        (BAR_LEFT, BAR_BOTTOM),
+        (BAR_LEFT, BAR_TOP),
+        (ADVANCE, BAR_TOP),
+        (ADVANCE, BAR_TOP - s),
+        (BAR_LEFT + s, BAR_TOP - s),
+        (BAR_LEFT + s, BAR_BOTTOM + s),
+        (ADVANCE, BAR_BOTTOM + s),
+        (ADVANCE, BAR_BOTTOM),
+    ]
+    fill = [box(BAR_LEFT + 2 * s, BAR_BOTTOM + 2 * s, ADVANCE, BAR_TOP - 2 * s)]
+    return [outline] + (fill if filled else [])
+
+
+def bar_middle(filled):
+    s = BAR_STROKE
+    lines = [box(0, BAR_TOP - s, ADVANCE, BAR_TOP), box(0, BAR_BOTTOM, ADVANCE, BAR_BOTTOM + s)]
+    fill = [box(0, BAR_BOTTOM + 2 * s, ADVANCE, BAR_TOP - 2 * s)]
+    return lines + (fill if filled else [])
+
+
+def spinner(frame):
+    """A third of a ring, rotated by a sixth of a turn for each frame"""
+    cx, cy = ADVANCE // 2, (BAR_TOP + BAR_BOTTOM) // 2
+    outer = (ADVANCE - BAR_LEFT) // 2
+    inner = outer - BAR_STROKE * 2
+    start = math.pi / 2 - frame * math.pi / 3
+    angles = [start - i * (2 * math.pi / 3) / ARC_STEPS for i in range(ARC_STEPS + 1)]
+
+    def ring(r):
+        return [(round(cx + r * math.cos(a)), round(cy + r * math.sin(a))) for a in angles]
+
+    return [ring(outer) + list(reversed(ring(inner)))]
+
+
+NOTDEF = [box(50, 0, 550, 700)]
+
+# (codepoint, contours); a contour that starts with None is a hole
+SYMBOLS = [
+    (0xE0B4, HALF_CIRCLE),
+    (0xE0B5, THIN_HALF_CIRCLE),
+    (0xE0B6, mirror(HALF_CIRCLE)),
+    (0xE0B7, mirror(THIN_HALF_CIRCLE)),
+    # lower left triangle
+    (0xE0B8, [[(0, DESCENDER), (0, ASCENDER), (ADVANCE, DESCENDER)]]),
+    (0xE0B9, BACKSLASH),
+    # lower right triangle
+    (0xE0BA, [[(0, DESCENDER), (ADVANCE, ASCENDER), (ADVANCE, DESCENDER)]]),
+    (0xE0BB, SLASH),
+    # upper left triangle
+    (0xE0BC, [[(0, DESCENDER), (0, ASCENDER), (ADVANCE, ASCENDER)]]),
+    (0xE0BD, SLASH),
+    # upper right triangle
+    (0xE0BE, [[(0, ASCENDER), (ADVANCE, ASCENDER), (ADVANCE, DESCENDER)]]),
+    (0xE0BF, BACKSLASH),
+    (0xE0C0, [right_edge(FLAME)]),
+    (0xE0C1, [thin_edge(FLAME)]),
+    (0xE0C2, mirror([right_edge(FLAME)])),
+    (0xE0C3, mirror([thin_edge(FLAME)])),
+    (0xE0C4, BIG_PIXELS),
+    (0xE0C5, mirror(BIG_PIXELS)),
+    (0xE0C6, SMALL_PIXELS),
+    (0xE0C7, mirror(SMALL_PIXELS)),
+    (0xE0C8, [right_edge(ICE)]),
+    (0xE0CA, mirror([right_edge(ICE)])),
+    (0xE0CC, [hexagon(ADVANCE // 2)]),
+    (0xE0CD, [hexagon(ADVANCE // 2), [None] + hexagon(ADVANCE // 2 - STROKE)]),
+    # trapezoids that are narrower at the bottom
+    (0xE0D2, [[(0, DESCENDER), (0, ASCENDER), (ADVANCE, ASCENDER), (ADVANCE // 2, DESCENDER)]]),
+    (0xE0D4, mirror([[(0, DESCENDER), (0, ASCENDER), (ADVANCE, ASCENDER), (ADVANCE // 2, DESCENDER)]])),
+    (0xEE00, bar_end(False)),
+    (0xEE01, bar_middle(False)),
+    (0xEE02, mirror(bar_end(False))),
+    (0xEE03, bar_end(True)),
+    (0xEE04, bar_middle(True)),
+    (0xEE05, mirror(bar_end(True))),
+] + [(0xEE06 + frame, spinner(frame)) for frame in range(6)]
+
+
+def outline(contours):
+    """Orients each contour and marks its points as being on the curve"""
+    out = []
+    for points in contours:
+        if points and points[0] is None:
+            points = counter_clockwise(points[1:])
+        else:
+            points = clockwise(points)
+        out.append([(x, y, True) for x, y in points])
+    return out
+
+
+OUTLINES = [outline(NOTDEF)] + [outline(contours) for _, contours in SYMBOLS]
+NUM_GLYPHS = len(OUTLINES)
+CODEPOINTS = [cp for cp, _ in SYMBOLS]
+assert CODEPOINTS == sorted(CODEPOINTS)
+
+
+def cmap_segments():
+    """Groups consecutive codepoints, which map to consecutive glyphs"""
+    segments = []
+    for glyph, cp in enumerate(CODEPOINTS, 1):
+        if segments and segments[-1][1] == cp - 1:
+            start, _, first = segments[-1]
+            segments[-1] = (start, cp, first)
+        else:
+            segments.append((cp, cp, glyph))
+    return segments + [(0xFFFF, 0xFFFF, 0)]
+
+
+def font_tables():
+    glyf, loca = glyf_loca([simple_glyph(contours) for contours in OUTLINES])
+    all_bbox = bbox([c for contours in OUTLINES for c in contours])
+    return [
+        (b"cmap", cmap_table(cmap_segments())),
+        (b"head", head_table(all_bbox)),
+        (
+            b"hhea",
+            hhea_table(ASCENDER, DESCENDER, ADVANCE, all_bbox[0], ADVANCE - all_bbox[2], all_bbox[2], NUM_GLYPHS),
+        ),
+        (b"hmtx", b"".join(struct.pack(">Hh", ADVANCE, bbox(c)[0]) for c in OUTLINES)),
+        (
+            b"maxp",
+            maxp_table(
+                NUM_GLYPHS,
+                max_points=max(sum(len(c) for c in contours) for contours in OUTLINES),
+                max_contours=max(len(contours) for contours in OUTLINES),
+            ),
+        ),
+        (
+            b"name",
+            name_table(
+                "Wezterm Symbols",
+                {
+                    0: "Generated by make_symbols_font.py in the wezterm repository",
+                    5: "Version 1.0",
+                },
+            ),
+        ),
+        (
+            b"OS/2",
+            os2_table(
+                ADVANCE,
+                CODEPOINTS[0],
+                CODEPOINTS[-1],
+                ASCENDER,
+                DESCENDER,
+                # ulUnicodeRange bit 60: Private Use Area
+                unicode_ranges=(0, 1 << (60 - 32), 0, 0),
+            ),
+        ),
+        (b"post", post_table()),
+        (b"glyf", glyf),
+        (b"loca", loca),
+    ]
+
+
+def main():
+    with open(os.path.join(HERE, "WeztermSymbols-Regular.ttf"), "wb") as f:
+        f.write(build_sfnt(font_tables()))
+
+
+if __name__ == "__main__":
+    main()
@@ -137,6 +137,7 @@ TOC = [
             ),
             Page("Scrollback", "scrollback.markdown"),
             Page("Copy Mode", "copymode.markdown"),
+            Page("Quick Select Mode", "quickselect.markdown"),
             Page("Hyperlinks", "hyperlinks.markdown"),
             Page("Shell Integration", "shell-integration.markdown"),
             Page("iTerm Image Protocol", "imgcat.markdown"),
@@ -37,6 +37,139 @@ impl Default for FontAntiAliasing {
     }
 }
 
+/// Selects the advance that determines the width of a cell
+/// from the metrics of the primary font
+#[derive(Debug, Copy, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
+pub enum CellWidthReference {
+    /// The advance of the specified character.  If the font doesn't
+    /// have that character, the widest ASCII character is used.
+    Char(char),
+    /// The advance of the widest of the printable ASCII characters
+    MaxAscii,
+    /// The `xAvgCharWidth` field of the `OS/2` table.  If the font
+    /// doesn't have that table, the widest ASCII character is used.
+    AverageCharWidth,
+}
+impl_lua_conversion!(CellWidthReference);
+
+impl Default for CellWidthReference {
+    fn default() -> Self {
+        Self::Char('M')
+    }
+}
+
+#[derive(Debug, Copy, Deserialize, Serialize, Clone, PartialEq, Eq, Hash)]
+pub enum FontStretch {
+    UltraCondensed,
+    ExtraCondensed,
+    Condensed,
+    SemiCondensed,
+    Normal,
+    SemiExpanded,
+    Expanded,
+    ExtraExpanded,
+    UltraExpanded,
+}
+impl_lua_conversion!(FontStretch);
+
+impl Default for FontStretch {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+impl FontStretch {
+    /// Returns the equivalent OpenType usWidthClass value, which
+    /// ranges from 1 for UltraCondensed to 9 for UltraExpanded
+    pub fn to_opentype_width(self) -> u16 {
+        match self {
+            Self::UltraCondensed => 1,
+            Self::ExtraCondensed => 2,
+            Self::Condensed => 3,
+            Self::SemiCondensed => 4,
+            Self::Normal => 5,
+            Self::SemiExpanded => 6,
+            Self::Expanded => 7,
+            Self::ExtraExpanded => 8,
+            Self::UltraExpanded => 9,
+        }
+    }
+}
+
+/// The design coordinate of one of the variation axes of a variable
+/// font.  It is written in the configuration as `tag=value`, such as
+/// `wght=350`.
+#[derive(Debug, Copy, Clone)]
+pub struct FontVariation {
+    /// The axis tag, such as `wght`, padded with spaces to four bytes
+    pub tag: [u8; 4],
+    /// The design coordinate on that axis
+    pub value: f32,
+}
+
+impl PartialEq for FontVariation {
+    fn eq(&self, other: &Self) -> bool {
+        self.tag == other.tag && self.value.to_bits() == other.value.to_bits()
+    }
+}
+
+impl Eq for FontVariation {}
+
+impl std::hash::Hash for FontVariation {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.tag.hash(state);
+        self.value.to_bits().hash(state);
+    }
+}
+
+impl std::fmt::Display for FontVariation {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        let tag = String::from_utf8_lossy(&self.tag);
+        write!(fmt, "{}={}", tag.trim_end(), self.value)
+    }
+}
+
+impl std::str::FromStr for FontVariation {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut iter = s.splitn(2, '=');
+        let name = iter.next().unwrap_or("").trim();
+        let value = iter.next().map(str::trim);
+        let value: f32 = match value.and_then(|value| value.parse().ok()) {
+            Some(value) if value.is_finite() => value,
+            _ => bail!(
+                "{:?} is not a valid font variation; expected eg: \"wght=350\"",
+                s
+            ),
+        };
+        if name.is_empty() || name.len() > 4 || !name.bytes().all(|b| b.is_ascii_graphic()) {
+            bail!("{:?} is not a valid font variation axis tag", name);
+        }
+        let mut tag = *b"    ";
+        tag[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(Self { tag, value })
+    }
+}
+
+impl<'de> Deserialize<'de> for FontVariation {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let setting = String::deserialize(deserializer)?;
+        setting.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for FontVariation {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct FontAttributes {
     /// The font family name
@@ -47,6 +180,22 @@ pub struct FontAttributes {
     /// Whether the font should be an italic variant
     #[serde(default)]
     pub italic: bool,
+    /// The numeric weight of the font, on the usual OpenType scale
+    /// where 400 is regular and 700 is bold.  If not specified, it
+    /// is derived from `bold`.
+    #[serde(default)]
+    pub weight: Option<u16>,
+    /// The width of the font; whether it is condensed or expanded
+    #[serde(default)]
+    pub stretch: FontStretch,
+    /// The named instance of a variable font to use, such as
+    /// "SemiBold"
+    #[serde(default)]
+    pub instance: Option<String>,
+    /// The design coordinates of the variation axes of a variable
+    /// font, such as `wght=350`; these override the named instance
+    #[serde(default)]
+    pub variations: Vec<FontVariation>,
     pub is_fallback: bool,
 }
 impl_lua_conversion!(FontAttributes);
@@ -55,9 +204,27 @@ impl std::fmt::Display for FontAttributes {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         write!(
             fmt,
-            "wezterm.font('{}', {{bold={}, italic={}}})",
+            "wezterm.font('{}', {{bold={}, italic={}",
             self.family, self.bold, self.italic
-        )
+        )?;
+        if let Some(weight) = self.weight {
+            write!(fmt, ", weight={}", weight)?;
+        }
+        if self.stretch != FontStretch::Normal {
+            write!(fmt, ", stretch=\"{:?}\"", self.stretch)?;
+        }
+        if let Some(instance) = &self.instance {
+            write!(fmt, ", instance={:?}", instance)?;
+        }
+        if !self.variations.is_empty() {
+            let variations = self
+                .variations
+                .iter()
+                .map(|v| format!("{:?}", v.to_string()))
+                .collect::<Vec<_>>();
+            write!(fmt, ", variations={{{}}}", variations.join(", "))?;
+        }
+        write!(fmt, "}})")
     }
 }
 
@@ -67,6 +234,10 @@ impl FontAttributes {
             family: family.into(),
             bold: false,
             italic: false,
+            weight: None,
+            stretch: FontStretch::Normal,
+            instance: None,
+            variations: vec![],
             is_fallback: false,
         }
     }
@@ -76,9 +247,24 @@ impl FontAttributes {
             family: family.into(),
             bold: false,
             italic: false,
+            weight: None,
+            stretch: FontStretch::Normal,
+            instance: None,
+            variations: vec![],
             is_fallback: true,
         }
     }
+
+    /// Returns the numeric weight that should be matched; this is
+    /// either the explicitly configured weight, or 700 for bold and
+    /// 400 for regular fonts.
+    pub fn weight(&self) -> u16 {
+        match self.weight {
+            Some(weight) => weight,
+            None if self.bold => 700,
+            None => 400,
+        }
+    }
 }
 
 impl Default for FontAttributes {
@@ -87,6 +273,10 @@ impl Default for FontAttributes {
             family: "JetBrains Mono".into(),
             bold: false,
             italic: false,
+            weight: None,
+            stretch: FontStretch::Normal,
+            instance: None,
+            variations: vec![],
             is_fallback: false,
         }
     }
@@ -103,6 +293,14 @@ pub struct TextStyle {
     /// useful in a `[[font_rules]]` section to implement changing
     /// the text color for eg: bold text.
     pub foreground: Option<RgbColor>,
+
+    /// A list of OpenType features to enable or disable when shaping
+    /// text in this style, using the same syntax as `harfbuzz_features`;
+    /// eg: `{"calt=0", "clig=0", "liga=0"}` to disable ligatures.
+    /// These are applied after `harfbuzz_features` and so can be used
+    /// to override those settings for a particular style.
+    #[serde(default)]
+    pub font_features: Vec<String>,
 }
 impl_lua_conversion!(TextStyle);
 
@@ -111,6 +309,7 @@ impl Default for TextStyle {
         Self {
             foreground: None,
             font: vec![FontAttributes::default()],
+            font_features: vec![],
         }
     }
 }
@@ -126,9 +325,11 @@ impl TextStyle {
                 .map(|attr| {
                     let mut attr = attr.clone();
                     attr.bold = true;
+                    attr.weight = attr.weight.map(|w| w.max(700));
                     attr
                 })
                 .collect(),
+            font_features: self.font_features.clone(),
         }
     }
 
@@ -145,11 +346,12 @@ impl TextStyle {
                     attr
                 })
                 .collect(),
+            font_features: self.font_features.clone(),
         }
     }
 
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::let_and_return))]
-    pub fn font_with_fallback(&self) -> Vec<FontAttributes> {
+    pub fn font_with_fallback(&self, config: &Config) -> Vec<FontAttributes> {
         let mut font = self.font.clone();
 
         let mut default_font = FontAttributes::default();
@@ -182,6 +384,12 @@ impl TextStyle {
         // We bundle this emoji font as an in-memory fallback
         font.push(FontAttributes::new_fallback("Noto Color Emoji"));
 
+        // We also bundle some of the Nerd Font symbols, which must
+        // come before the last resort font that claims all of them
+        if config.font_symbol_fallback {
+            font.push(FontAttributes::new_fallback("Wezterm Symbols"));
+        }
+
         // And finally, a last resort fallback font
         font.push(FontAttributes::new_fallback("Last Resort High-Efficiency"));
 
@@ -228,12 +436,35 @@ pub struct StyleRule {
     /// If present, this rule matches when CellAttributes::invisible holds
     /// a value that matches this rule.
     pub invisible: Option<bool>,
+    /// If present, this rule matches when CellAttributes::foreground is
+    /// the color with this palette index; 0-15 are the ANSI colors.
+    pub foreground: Option<u8>,
+    /// If present, this rule matches when CellAttributes::background is
+    /// the color with this palette index; 0-15 are the ANSI colors.
+    pub background: Option<u8>,
 
     /// When this rule matches, `font` specifies the styling to be used.
     pub font: TextStyle,
 }
 impl_lua_conversion!(StyleRule);
 
+/// Deserializes one of the font system selections from its name.
+/// A name that isn't recognized, perhaps because that implementation
+/// isn't available in this build, selects the default with a warning
+/// rather than failing to load the whole configuration.
+fn deserialize_selection<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: std::str::FromStr<Err = Error> + Default + std::fmt::Debug,
+{
+    let name = String::deserialize(deserializer)?;
+    Ok(name.parse().unwrap_or_else(|err| {
+        let fallback = T::default();
+        log::warn!("{:#}; using {:?} instead", err, fallback);
+        fallback
+    }))
+}
+
 #[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum FontLocatorSelection {
     /// Use fontconfig APIs to resolve fonts (!macos, posix systems)
@@ -295,13 +526,13 @@ impl std::str::FromStr for FontLocatorSelection {
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FontRasterizerSelection {
     FreeType,
 }
 
 lazy_static::lazy_static! {
-    static ref DEFAULT_RASTER: Mutex<FontRasterizerSelection> = Mutex::new(Default::default());
+    static ref DEFAULT_RASTER: Mutex<Option<FontRasterizerSelection>> = Mutex::new(None);
 }
 
 impl Default for FontRasterizerSelection {
@@ -311,14 +542,23 @@ impl Default for FontRasterizerSelection {
 }
 
 impl FontRasterizerSelection {
+    /// Overrides the `font_rasterizer` configuration, such as when
+    /// the rasterizer was selected on the command line
     pub fn set_default(self) {
         let mut def = DEFAULT_RASTER.lock().unwrap();
-        *def = self;
+        def.replace(self);
     }
 
     pub fn get_default() -> Self {
         let def = DEFAULT_RASTER.lock().unwrap();
-        *def
+        def.unwrap_or_default()
+    }
+
+    /// Returns the rasterizer selected by the configuration, unless
+    /// it was overridden by `set_default`
+    pub fn from_config(config: &Config) -> Self {
+        let def = DEFAULT_RASTER.lock().unwrap();
+        def.unwrap_or(config.font_rasterizer)
     }
 
     pub fn variants() -> Vec<&'static str> {
@@ -326,6 +566,15 @@ impl FontRasterizerSelection {
     }
 }
 
+impl<'de> Deserialize<'de> for FontRasterizerSelection {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserialize_selection(deserializer)
+    }
+}
+
 impl std::str::FromStr for FontRasterizerSelection {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -340,14 +589,14 @@ impl std::str::FromStr for FontRasterizerSelection {
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FontShaperSelection {
     Allsorts,
     Harfbuzz,
 }
 
 lazy_static::lazy_static! {
-    static ref DEFAULT_SHAPER: Mutex<FontShaperSelection> = Mutex::new(Default::default());
+    static ref DEFAULT_SHAPER: Mutex<Option<FontShaperSelection>> = Mutex::new(None);
 }
 
 impl Default for FontShaperSelection {
@@ -357,14 +606,23 @@ impl Default for FontShaperSelection {
 }
 
 impl FontShaperSelection {
+    /// Overrides the `font_shaper` configuration, such as when
+    /// the shaper was selected on the command line
     pub fn set_default(self) {
         let mut def = DEFAULT_SHAPER.lock().unwrap();
-        *def = self;
+        def.replace(self);
     }
 
     pub fn get_default() -> Self {
         let def = DEFAULT_SHAPER.lock().unwrap();
-        *def
+        def.unwrap_or_default()
+    }
+
+    /// Returns the shaper selected by the configuration, unless
+    /// it was overridden by `set_default`
+    pub fn from_config(config: &Config) -> Self {
+        let def = DEFAULT_SHAPER.lock().unwrap();
+        def.unwrap_or(config.font_shaper)
     }
 
     pub fn variants() -> Vec<&'static str> {
@@ -372,6 +630,15 @@ impl FontShaperSelection {
     }
 }
 
+impl<'de> Deserialize<'de> for FontShaperSelection {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserialize_selection(deserializer)
+    }
+}
+
 impl std::str::FromStr for FontShaperSelection {
     type Err = Error;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
@@ -386,3 +653,82 @@ impl std::str::FromStr for FontShaperSelection {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::de::value::{Error as DeError, StrDeserializer};
+
+    fn parse<'de, T: Deserialize<'de>>(name: &'de str) -> T {
+        T::deserialize(StrDeserializer::<DeError>::new(name)).unwrap()
+    }
+
+    #[test]
+    fn font_variations() {
+        let variation = parse::<FontVariation>("wght=350");
+        assert_eq!(
+            variation,
+            FontVariation {
+                tag: *b"wght",
+                value: 350.
+            }
+        );
+        assert_eq!(variation.to_string(), "wght=350");
+
+        // Short tags are padded with spaces, as in the font tables
+        let variation: FontVariation = " ital = 0.5 ".parse().unwrap();
+        assert_eq!(&variation.tag, b"ital");
+        let variation: FontVariation = "abc=1".parse().unwrap();
+        assert_eq!(&variation.tag, b"abc ");
+        assert_eq!(variation.to_string(), "abc=1");
+
+        for invalid in &[
+            "wght",
+            "wght=",
+            "wght=heavy",
+            "=350",
+            "weight=350",
+            "wght=inf",
+        ] {
+            assert!(invalid.parse::<FontVariation>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn font_system_selections() {
+        assert_eq!(
+            parse::<FontShaperSelection>("Allsorts"),
+            FontShaperSelection::Allsorts
+        );
+        assert_eq!(
+            parse::<FontShaperSelection>("harfbuzz"),
+            FontShaperSelection::Harfbuzz
+        );
+        assert_eq!(
+            parse::<FontRasterizerSelection>("FreeType"),
+            FontRasterizerSelection::FreeType
+        );
+
+        // Unknown names fall back to the default, rather than
+        // failing to load the configuration
+        assert_eq!(
+            parse::<FontShaperSelection>("Coretext"),
+            FontShaperSelection::Harfbuzz
+        );
+        assert_eq!(
+            parse::<FontRasterizerSelection>("DirectWrite"),
+            FontRasterizerSelection::FreeType
+        );
+
+        let mut config = Config::default_config();
+        config.font_shaper = FontShaperSelection::Allsorts;
+        assert_eq!(
+            FontShaperSelection::from_config(&config),
+            FontShaperSelection::Allsorts
+        );
+        assert_eq!(
+            FontRasterizerSelection::from_config(&config),
+            FontRasterizerSelection::FreeType
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use crate::configuration;
-use crate::LeaderKey;
+use crate::{Config, LeaderKey};
 use luahelper::impl_lua_conversion;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -15,6 +15,18 @@ pub enum SelectionMode {
     SemanticZone,
 }
 
+/// The format in which the selection is copied to the clipboard
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CopyFormat {
+    /// Plain text
+    Text,
+    /// Text with escape sequences that reproduce the colors and styles
+    Ansi,
+    /// An HTML fragment with the colors and styles applied inline.
+    /// This is placed on the clipboard as plain text markup.
+    Html,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub enum Pattern {
     CaseSensitiveString(String),
@@ -103,6 +115,29 @@ pub struct SpawnCommand {
     pub domain: SpawnTabDomain,
 }
 
+/// What to include when dumping the contents of a pane,
+/// and where to put it
+#[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct DumpScreen {
+    /// Include the scrollback as well as the visible screen
+    #[serde(default)]
+    pub scrollback: bool,
+
+    /// Include escape sequences that reproduce the colors and styles
+    /// of the text
+    #[serde(default)]
+    pub ansi: bool,
+
+    /// Keep the whitespace at the end of each line, which is
+    /// otherwise trimmed
+    #[serde(default)]
+    pub keep_trailing_whitespace: bool,
+
+    /// The file to write the dump to.
+    /// If omitted, the dump is copied to the clipboard.
+    pub path: Option<PathBuf>,
+}
+
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
 pub enum PaneDirection {
     Up,
@@ -117,6 +152,7 @@ pub enum KeyAssignment {
     SpawnWindow,
     ToggleFullScreen,
     Copy,
+    CopyAs(CopyFormat),
     Paste,
     PastePrimarySelection,
     ActivateTabRelative(isize),
@@ -135,6 +171,8 @@ pub enum KeyAssignment {
     MoveTab(usize),
     ScrollByPage(isize),
     ScrollToPrompt(isize),
+    ScrollToTop,
+    ScrollToBottom,
     ShowTabNavigator,
     HideApplication,
     QuitApplication,
@@ -144,8 +182,10 @@ pub enum KeyAssignment {
     SplitVertical(SpawnCommand),
     ShowLauncher,
     ClearScrollback,
+    DumpScreen(DumpScreen),
     Search(Pattern),
     ActivateCopyMode,
+    QuickSelect,
 
     SelectTextAtMouseCursor(SelectionMode),
     ExtendSelectionToMouseCursor(Option<SelectionMode>),
@@ -169,7 +209,12 @@ pub struct InputMap {
 
 impl InputMap {
     pub fn new() -> Self {
-        let config = configuration();
+        Self::with_config(&configuration())
+    }
+
+    /// Build the input map from the bindings in the specified config,
+    /// filling in the default bindings unless they have been disabled
+    pub fn with_config(config: &Config) -> Self {
         let mut mouse = config
             .mouse_bindings()
             .expect("mouse_bindings section of the config to be valid");
@@ -308,6 +353,7 @@ impl InputMap {
                 [KeyModifiers::SHIFT, KeyCode::PageDown, ScrollByPage(1)],
                 [KeyModifiers::ALT, KeyCode::Char('9'), ShowTabNavigator],
                 [KeyModifiers::CTRL, KeyCode::Char('X'), ActivateCopyMode],
+                [ctrl_shift, KeyCode::Char(' '), QuickSelect],
                 [
                     KeyModifiers::CTRL | KeyModifiers::ALT | KeyModifiers::SHIFT,
                     KeyCode::Char('"'),
@@ -459,7 +505,7 @@ impl InputMap {
                         streak: 1,
                         button: MouseButton::Middle
                     },
-                    Paste
+                    PastePrimarySelection
                 ],
             );
         }
@@ -499,3 +545,106 @@ impl InputMap {
         self.mouse.get(&(event, mods)).cloned()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Mouse;
+
+    fn left_down(streak: usize) -> MouseEventTrigger {
+        MouseEventTrigger::Down {
+            streak,
+            button: MouseButton::Left,
+        }
+    }
+
+    #[test]
+    fn mouse_binding_lookup() {
+        let mut config = Config::default_config();
+        config.mouse_bindings = vec![
+            // Ctrl+Right click pastes
+            Mouse {
+                event: MouseEventTrigger::Down {
+                    streak: 1,
+                    button: MouseButton::Right,
+                },
+                mods: KeyModifiers::CTRL,
+                action: KeyAssignment::Paste,
+            },
+            // Replace a default
+            Mouse {
+                event: left_down(2),
+                mods: KeyModifiers::NONE,
+                action: KeyAssignment::SelectTextAtMouseCursor(SelectionMode::SemanticZone),
+            },
+            // Remove a default
+            Mouse {
+                event: MouseEventTrigger::Down {
+                    streak: 1,
+                    button: MouseButton::Middle,
+                },
+                mods: KeyModifiers::NONE,
+                action: KeyAssignment::DisableDefaultAssignment,
+            },
+        ];
+        let map = InputMap::with_config(&config);
+
+        // Defaults that weren't overridden are still present,
+        // and are distinguished by the streak and modifiers
+        assert_eq!(
+            map.lookup_mouse(left_down(1), KeyModifiers::NONE),
+            Some(KeyAssignment::SelectTextAtMouseCursor(SelectionMode::Cell))
+        );
+        assert_eq!(
+            map.lookup_mouse(left_down(1), KeyModifiers::SHIFT),
+            Some(KeyAssignment::ExtendSelectionToMouseCursor(None))
+        );
+        assert_eq!(
+            map.lookup_mouse(left_down(3), KeyModifiers::NONE),
+            Some(KeyAssignment::SelectTextAtMouseCursor(SelectionMode::Line))
+        );
+        assert_eq!(map.lookup_mouse(left_down(1), KeyModifiers::ALT), None);
+
+        assert_eq!(
+            map.lookup_mouse(
+                MouseEventTrigger::Down {
+                    streak: 1,
+                    button: MouseButton::Right,
+                },
+                KeyModifiers::CTRL
+            ),
+            Some(KeyAssignment::Paste)
+        );
+        assert_eq!(
+            map.lookup_mouse(left_down(2), KeyModifiers::NONE),
+            Some(KeyAssignment::SelectTextAtMouseCursor(
+                SelectionMode::SemanticZone
+            ))
+        );
+        assert_eq!(
+            map.lookup_mouse(
+                MouseEventTrigger::Down {
+                    streak: 1,
+                    button: MouseButton::Middle,
+                },
+                KeyModifiers::NONE
+            ),
+            None
+        );
+
+        // Disabling the defaults leaves only the configured bindings
+        config.disable_default_mouse_bindings = true;
+        let map = InputMap::with_config(&config);
+        assert_eq!(map.lookup_mouse(left_down(1), KeyModifiers::NONE), None);
+        assert_eq!(
+            map.lookup_mouse(
+                MouseEventTrigger::Down {
+                    streak: 1,
+                    button: MouseButton::Right,
+                },
+                KeyModifiers::CTRL
+            ),
+            Some(KeyAssignment::Paste)
+        );
+    }
+}
@@ -22,6 +22,7 @@ use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use termwiz::color::RgbColor;
 use termwiz::hyperlink;
 use termwiz::input::{KeyCode, Modifiers};
 use termwiz::surface::CursorShape;
@@ -58,6 +59,7 @@ lazy_static! {
     pub static ref HOME_DIR: PathBuf = dirs::home_dir().expect("can't find HOME dir");
     pub static ref CONFIG_DIR: PathBuf = xdg_config_home();
     pub static ref RUNTIME_DIR: PathBuf = compute_runtime_dir().unwrap();
+    pub static ref CACHE_DIR: PathBuf = compute_cache_dir();
     static ref CONFIG: Configuration = Configuration::new();
     static ref MAKE_LUA: Mutex<Option<LuaFactory>> = Mutex::new(Some(lua::make_lua_context));
     static ref SHOW_ERROR: Mutex<Option<ErrorCallback>> =
@@ -335,6 +337,7 @@ impl ConfigInner {
                 self.config = Arc::new(config);
                 self.error.take();
                 self.generation += 1;
+                self.config.apply_global_settings();
 
                 // If we loaded a user config, publish this latest version of
                 // the lua state to the LUA_PIPE.  This allows a subsequent
@@ -369,6 +372,7 @@ impl ConfigInner {
         self.config = Arc::new(Config::default_config());
         self.error.take();
         self.generation += 1;
+        self.config.apply_global_settings();
     }
 }
 
@@ -456,6 +460,12 @@ pub struct Config {
     #[serde(default)]
     pub font_dirs: Vec<PathBuf>,
 
+    /// When true, the font_dirs are watched for fonts being added,
+    /// changed or removed, and fonts are resolved again the next
+    /// time they are needed.
+    #[serde(default)]
+    pub font_dirs_watch: bool,
+
     #[serde(default)]
     pub color_scheme_dirs: Vec<PathBuf>,
 
@@ -467,6 +477,58 @@ pub struct Config {
     #[serde(default)]
     pub font: TextStyle,
 
+    /// The maximum number of distinct text styles whose loaded
+    /// fonts are retained in memory.  When more styles than this
+    /// are in use, the least recently used style is discarded and
+    /// will be reloaded on demand.
+    #[serde(default = "default_font_cache_size")]
+    pub font_cache_size: usize,
+
+    /// When true, the bundled "Wezterm Symbols" font is used as a low
+    /// priority fallback for the Powerline Extra separators and the
+    /// progress symbols that are used by prompts and status lines
+    /// designed for Nerd Fonts
+    #[serde(default)]
+    pub font_symbol_fallback: bool,
+
+    /// When a bold variant of a font is requested but the font family
+    /// doesn't provide one, use the regular variant and embolden its
+    /// glyph outlines instead of falling back to another font.
+    #[serde(default)]
+    pub synthesize_bold: bool,
+
+    /// When an italic variant of a font is requested but the font
+    /// family doesn't provide one, use the upright variant and slant
+    /// its glyph outlines instead of falling back to another font.
+    #[serde(default)]
+    pub synthesize_italic: bool,
+
+    /// The angle, in degrees, by which glyphs are slanted when
+    /// `synthesize_italic` is in effect.
+    #[serde(default = "default_synthetic_italic_angle")]
+    pub synthetic_italic_angle: f64,
+
+    /// When true, fallback fonts are rasterized at a size adjusted
+    /// so that their x-height (or cap height, if either font lacks
+    /// an x-height) matches that of the primary font.
+    #[serde(default)]
+    pub font_size_adjust: bool,
+
+    /// Scales the height of the cells computed from the font metrics.
+    /// Values larger than 1.0 add space between the rows, which is
+    /// divided evenly above and below the text.
+    #[serde(default = "default_one_point_oh_f64")]
+    pub line_height: f64,
+
+    /// Scales the width of the cells computed from the font metrics.
+    #[serde(default = "default_one_point_oh_f64")]
+    pub cell_width: f64,
+
+    /// Selects the glyph advance, or font metric, of the primary
+    /// font from which the width of the cells is computed
+    #[serde(default)]
+    pub cell_width_reference: CellWidthReference,
+
     /// An optional set of style rules to select the font based
     /// on the cell attributes
     #[serde(default)]
@@ -478,6 +540,14 @@ pub struct Config {
     #[serde(default = "default_true")]
     pub bold_brightens_ansi_colors: bool,
 
+    /// The minimum WCAG contrast ratio between the text and its
+    /// background.  When a foreground color has less contrast than
+    /// this, its lightness is adjusted until it meets the ratio.
+    /// This is applied after `dim_factor` and `unfocused_text_dim`.
+    /// The default of 1.0 leaves all colors as they are.
+    #[serde(default = "default_one_point_oh")]
+    pub minimum_contrast_ratio: f32,
+
     /// The color palette
     pub colors: Option<Palette>,
 
@@ -521,9 +591,42 @@ pub struct Config {
     #[serde(default = "default_initial_cols")]
     pub initial_cols: u16,
 
+    /// If true, the position and size of the first window are saved
+    /// when they change, and restored the next time that wezterm is
+    /// started, in preference to initial_rows and initial_cols.
+    #[serde(default)]
+    pub restore_window_state: bool,
+
     #[serde(default = "default_hyperlink_rules")]
     pub hyperlink_rules: Vec<hyperlink::Rule>,
 
+    /// Additional regular expressions that match the text that can be
+    /// picked by the QuickSelect key assignment
+    #[serde(default)]
+    pub quick_select_patterns: Vec<String>,
+
+    /// The characters that are used to build the labels of the
+    /// matches in QuickSelect mode
+    #[serde(default = "default_quick_select_alphabet")]
+    pub quick_select_alphabet: String,
+
+    /// The set of characters that delimit a word when double clicking
+    /// to select a word
+    #[serde(default = "default_selection_word_boundary")]
+    pub selection_word_boundary: String,
+
+    /// When a selection is completed, the selected text always becomes
+    /// the primary selection.  If this is true, the text is also
+    /// copied to the clipboard.
+    #[serde(default = "default_true")]
+    pub copy_on_select: bool,
+
+    /// If true, ask for confirmation before pasting text that contains
+    /// a newline into a pane where the application hasn't enabled
+    /// bracketed paste mode, as the shell may run it immediately.
+    #[serde(default)]
+    pub confirm_multiline_paste: bool,
+
     /// What to set the TERM variable to
     #[serde(default = "default_term")]
     pub term: String,
@@ -538,6 +641,19 @@ pub struct Config {
     pub font_hinting: FontHinting,
     #[serde(default)]
     pub font_antialias: FontAntiAliasing,
+    /// When true, antialiased text is blended with the background
+    /// in linear light rather than in the sRGB encoded color space.
+    /// This makes light text on a dark background look less thin,
+    /// and dark text on a light background less heavy.
+    #[serde(default)]
+    pub font_linear_blending: bool,
+
+    /// When true, box drawing and block element characters are drawn
+    /// from geometry computed from the cell size rather than using the
+    /// glyphs from the font, so that they join up seamlessly with the
+    /// same characters in adjacent cells.
+    #[serde(default)]
+    pub custom_block_glyphs: bool,
 
     /// Specify the features to enable when using harfbuzz for font shaping.
     /// There is some light documentation here:
@@ -622,6 +738,13 @@ pub struct Config {
     #[serde(default)]
     pub disable_default_mouse_bindings: bool,
 
+    /// If set, a left click on a hyperlink only opens it when exactly
+    /// these modifiers are held, and an unmodified click just selects
+    /// text.  The default is no modifiers, where a click opens the
+    /// link unless it was used to select some text.
+    #[serde(deserialize_with = "keys::de_modifiers", default)]
+    pub open_link_modifier: Modifiers,
+
     #[serde(default)]
     pub daemon_options: DaemonOptions,
 
@@ -669,9 +792,37 @@ pub struct Config {
     #[serde(default)]
     pub hide_tab_bar_if_only_one_tab: bool,
 
+    /// If set, the window title is produced by expanding the
+    /// `{index}`, `{count}`, `{title}` and `{cwd}` placeholders in
+    /// this template, rather than showing the title of the active pane
+    /// along with the tab position when there are multiple tabs.
+    #[serde(default)]
+    pub window_title_template: Option<String>,
+
+    /// If set, titles set by applications are truncated to this many
+    /// characters before they are shown in the window title.
+    /// Control characters are removed from those titles regardless.
+    #[serde(default)]
+    pub window_title_max_length: Option<usize>,
+
+    /// If true, the tab bar is displayed at the bottom of the window
+    /// rather than the top.
+    #[serde(default)]
+    pub tab_bar_at_bottom: bool,
+
     #[serde(default)]
     pub enable_scroll_bar: bool,
 
+    /// If true, scrolling the viewport with the mouse wheel or
+    /// ScrollByPage animates rather than jumping to the new position
+    #[serde(default)]
+    pub smooth_scroll: bool,
+
+    /// The maximum frame rate used when animating, such as when
+    /// smooth scrolling
+    #[serde(default = "default_animation_fps")]
+    pub animation_fps: u8,
+
     /// If false, do not try to use a Wayland protocol connection
     /// when starting the gui frontend, and instead use X11.
     /// This option is only considered on X11/Wayland systems and
@@ -691,6 +842,12 @@ pub struct Config {
     #[serde(default)]
     pub window_padding: WindowPadding,
 
+    /// Controls the color used to fill the window padding.
+    /// The default fills it with the background color of the palette,
+    /// the same as the rest of the window background.
+    #[serde(default)]
+    pub window_padding_color: WindowPaddingColor,
+
     /// Specifies the path to a background image attachment file.
     /// The file can be any image format that the rust `image`
     /// crate is able to identify and load.
@@ -716,6 +873,12 @@ pub struct Config {
     #[serde(default = "default_one_point_oh")]
     pub window_background_opacity: f32,
 
+    /// Controls whether the title bar and window frame use the dark
+    /// or light theme on Windows.  `System` follows the
+    /// "default app mode" chosen in the Windows settings.
+    #[serde(default)]
+    pub window_frame_theme: WindowFrameTheme,
+
     /// inactive_pane_hue, inactive_pane_saturation and
     /// inactive_pane_brightness allow for transforming the color
     /// of inactive panes.
@@ -748,6 +911,19 @@ pub struct Config {
     #[serde(default = "default_inactive_pane_hsb")]
     pub inactive_pane_hsb: HsbTransform,
 
+    /// When the window doesn't have the focus, the text colors are
+    /// moved this fraction of the way toward their background color,
+    /// in the range 0.0 (no dimming) to 1.0 (invisible).
+    /// The cursor cell is not dimmed.
+    #[serde(default)]
+    pub unfocused_text_dim: f32,
+
+    /// Half intensity text (SGR 2) is drawn this fraction of the way
+    /// from its color toward its background color, in the range 0.0
+    /// (no dimming) to 1.0 (invisible).
+    #[serde(default = "default_dim_factor")]
+    pub dim_factor: f32,
+
     #[serde(default = "default_one_point_oh")]
     pub text_background_opacity: f32,
 
@@ -760,6 +936,32 @@ pub struct Config {
     #[serde(default = "default_cursor_blink_rate")]
     pub cursor_blink_rate: u64,
 
+    /// Specifies how often text with the slow blink attribute (SGR 5)
+    /// transitions between visible and invisible, expressed in
+    /// milliseconds.  Setting this to 0 disables blinking.
+    #[serde(default = "default_text_blink_rate")]
+    pub text_blink_rate: u64,
+
+    /// Like text_blink_rate, but for text with the rapid blink
+    /// attribute (SGR 6).
+    #[serde(default = "default_text_blink_rate_rapid")]
+    pub text_blink_rate_rapid: u64,
+
+    /// If true, the system alert sound is played when a program
+    /// running in the terminal emits the BEL control character.
+    /// The default is false.
+    #[serde(default)]
+    pub audible_bell: bool,
+
+    /// If true, the window background briefly flashes when a program
+    /// running in the terminal emits the BEL control character.
+    #[serde(default)]
+    pub visual_bell: bool,
+
+    /// How long the visual bell takes to fade out, in milliseconds
+    #[serde(default = "default_visual_bell_duration_ms")]
+    pub visual_bell_duration_ms: u64,
+
     /// Specifies the default cursor style.  various escape sequences
     /// can override the default style in different situations (eg:
     /// an editor can change it depending on the mode), but this value
@@ -771,6 +973,23 @@ pub struct Config {
     #[serde(default)]
     pub default_cursor_style: DefaultCursorStyle,
 
+    /// The thickness, in pixels, of the bar and underline cursor
+    /// shapes.  If not specified, it is derived from the underline
+    /// thickness of the font.
+    #[serde(default)]
+    pub cursor_thickness: Option<u16>,
+
+    /// If true, the mouse cursor is hidden while typing in the
+    /// window, and shown again when the mouse is moved
+    #[serde(default)]
+    pub hide_mouse_cursor_when_typing: bool,
+
+    /// When true, a ligature that covers the cell under the cursor
+    /// is broken up so that the character in that cell is drawn with
+    /// its own glyph, making it clear which character the cursor is on.
+    #[serde(default)]
+    pub cursor_splits_ligatures: bool,
+
     /// If non-zero, specifies the period (in seconds) at which various
     /// statistics are logged.  Note that there is a minimum period of
     /// 10 seconds.
@@ -815,12 +1034,46 @@ pub struct Config {
     /// mapping annoying in vim :-p
     #[serde(default)]
     pub enable_csi_u_key_encoding: bool,
+
+    /// When set to true (the default), applications may set and clear
+    /// the clipboard using the OSC 52 escape sequence.
+    #[serde(default = "default_true")]
+    pub enable_osc52_clipboard: bool,
+
+    /// When set to true, applications may read the clipboard using the
+    /// OSC 52 escape sequence.  This is off by default because it allows
+    /// anything that can write to the terminal to retrieve the clipboard.
+    #[serde(default)]
+    pub enable_osc52_clipboard_reading: bool,
+
+    /// When set to true, characters with the East Asian Width property
+    /// of "Ambiguous" are treated as occupying two cells rather than one.
+    /// This affects both the terminal model and text shaping, and is
+    /// useful when running applications that assume a CJK locale.
+    #[serde(default)]
+    pub treat_east_asian_ambiguous_width_as_wide: bool,
 }
 
 fn default_one_point_oh() -> f32 {
     1.0
 }
 
+fn default_one_point_oh_f64() -> f64 {
+    1.0
+}
+
+fn default_dim_factor() -> f32 {
+    0.5
+}
+
+fn default_font_cache_size() -> usize {
+    64
+}
+
+fn default_synthetic_italic_angle() -> f64 {
+    12.0
+}
+
 fn default_tab_max_width() -> usize {
     16
 }
@@ -870,6 +1123,39 @@ impl DefaultCursorStyle {
     }
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowFrameTheme {
+    System,
+    Dark,
+    Light,
+}
+impl_lua_conversion!(WindowFrameTheme);
+
+impl Default for WindowFrameTheme {
+    fn default() -> Self {
+        WindowFrameTheme::Dark
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowPaddingColor {
+    /// The background color of the palette
+    DefaultBackground,
+    /// The background color of the bottom left cell of the active pane,
+    /// so that the padding matches an application that fills the
+    /// screen with its own background color
+    BottomLeftCell,
+    /// A specific color
+    Color(RgbColor),
+}
+impl_lua_conversion!(WindowPaddingColor);
+
+impl Default for WindowPaddingColor {
+    fn default() -> Self {
+        WindowPaddingColor::DefaultBackground
+    }
+}
+
 #[derive(Default, Deserialize, Serialize, Clone, Copy, Debug)]
 pub struct WindowPadding {
     #[serde(default)]
@@ -983,6 +1269,14 @@ impl Config {
         Self::default().compute_extra_defaults(None)
     }
 
+    /// Apply settings that are held in process-wide state by the
+    /// crates that we depend upon.
+    fn apply_global_settings(&self) {
+        termwiz::cell::set_treat_east_asian_ambiguous_width_as_wide(
+            self.treat_east_asian_ambiguous_width_as_wide,
+        );
+    }
+
     pub fn key_bindings(&self) -> anyhow::Result<HashMap<(KeyCode, Modifiers), KeyAssignment>> {
         let mut map = HashMap::new();
 
@@ -1205,6 +1499,22 @@ fn default_cursor_blink_rate() -> u64 {
     800
 }
 
+fn default_text_blink_rate() -> u64 {
+    500
+}
+
+fn default_text_blink_rate_rapid() -> u64 {
+    250
+}
+
+fn default_visual_bell_duration_ms() -> u64 {
+    150
+}
+
+fn default_animation_fps() -> u8 {
+    60
+}
+
 fn default_swap_backspace_and_delete() -> bool {
     // cfg!(target_os = "macos")
     // See: https://github.com/wez/wezterm/issues/88
@@ -1223,6 +1533,15 @@ fn default_initial_cols() -> u16 {
     80
 }
 
+fn default_selection_word_boundary() -> String {
+    " \t\n{}[]()\"'".to_string()
+}
+
+fn default_quick_select_alphabet() -> String {
+    // The home row first, then the keys that are nearest to it
+    "asdfqwerzxcvjklmiuopghtybn".to_string()
+}
+
 fn default_hyperlink_rules() -> Vec<hyperlink::Rule> {
     vec![
         // URL with a protocol
@@ -1259,6 +1578,13 @@ fn compute_runtime_dir() -> Result<PathBuf, Error> {
     Ok(HOME_DIR.join(".local/share/wezterm"))
 }
 
+fn compute_cache_dir() -> PathBuf {
+    match dirs::cache_dir() {
+        Some(cache) => cache.join("wezterm"),
+        None => HOME_DIR.join(".cache/wezterm"),
+    }
+}
+
 pub fn pki_dir() -> anyhow::Result<PathBuf> {
     compute_runtime_dir().map(|d| d.join("pki"))
 }
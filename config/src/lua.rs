@@ -1,4 +1,4 @@
-use crate::{FontAttributes, TextStyle};
+use crate::{FontAttributes, FontStretch, FontVariation, TextStyle};
 use anyhow::anyhow;
 use bstr::BString;
 pub use luahelper::*;
@@ -150,19 +150,35 @@ struct TextStyleAttributes {
     /// Whether the font should be an italic variant
     #[serde(default)]
     pub italic: bool,
+    /// The numeric weight of the font; 400 is regular, 700 is bold
+    #[serde(default)]
+    pub weight: Option<u16>,
+    /// Whether to select a condensed or expanded variant of the font
+    #[serde(default)]
+    pub stretch: FontStretch,
+    /// The named instance of a variable font, such as "SemiBold"
+    #[serde(default)]
+    pub instance: Option<String>,
+    /// Variation axis settings for a variable font, such as `wght=350`
+    #[serde(default)]
+    pub variations: Vec<FontVariation>,
     /// If set, when rendering text that is set to the default
     /// foreground color, use this color instead.  This is most
     /// useful in a `[[font_rules]]` section to implement changing
     /// the text color for eg: bold text.
     pub foreground: Option<termwiz::color::RgbColor>,
+    /// OpenType features to enable or disable for this style
+    #[serde(default)]
+    pub font_features: Vec<String>,
 }
 impl_lua_conversion!(TextStyleAttributes);
 
 /// Given a simple font family name, returns a text style instance.
 /// The second optional argument is a list of the other TextStyle
-/// fields, which at the time of writing includes only the
+/// fields, which at the time of writing includes the
 /// `foreground` color that can be used to force a particular
-/// color to be used for this text style.
+/// color to be used for this text style, and the list of
+/// `font_features` to use when shaping text in this style.
 ///
 /// `wezterm.font("foo", {foreground="tomato"})`
 /// yields:
@@ -179,9 +195,14 @@ fn font<'lua>(
         family,
         bold: attrs.bold,
         italic: attrs.italic,
+        weight: attrs.weight,
+        stretch: attrs.stretch,
+        instance: attrs.instance,
+        variations: attrs.variations,
         is_fallback: false,
     });
     text_style.foreground = attrs.foreground;
+    text_style.font_features = attrs.font_features;
 
     Ok(text_style)
 }
@@ -206,10 +227,15 @@ fn font_with_fallback<'lua>(
             family,
             bold: attrs.bold,
             italic: attrs.italic,
+            weight: attrs.weight,
+            stretch: attrs.stretch,
+            instance: attrs.instance.clone(),
+            variations: attrs.variations.clone(),
             is_fallback: idx != 0,
         });
     }
     text_style.foreground = attrs.foreground;
+    text_style.font_features = attrs.font_features;
 
     Ok(text_style)
 }
@@ -25,6 +25,14 @@ impl wezterm_term::TerminalConfiguration for TermConfig {
         configuration().enable_csi_u_key_encoding
     }
 
+    fn enable_osc52_clipboard(&self) -> bool {
+        configuration().enable_osc52_clipboard
+    }
+
+    fn enable_osc52_clipboard_reading(&self) -> bool {
+        configuration().enable_osc52_clipboard_reading
+    }
+
     fn color_palette(&self) -> ColorPalette {
         let config = configuration();
 
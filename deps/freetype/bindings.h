@@ -1,6 +1,7 @@
 #include <ft2build.h>
 #include <freetype/freetype.h>
 #include <freetype/ftlcdfil.h>
+#include <freetype/ftmm.h>
 #include <freetype/tttables.h>
 #include <freetype/ftmodapi.h>
 #include <freetype/ftoutln.h>
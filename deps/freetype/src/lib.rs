@@ -1121,6 +1121,20 @@ extern "C" {
 extern "C" {
     pub fn FT_Face_GetCharsOfVariant(face: FT_Face, variantSelector: FT_ULong) -> *mut FT_UInt32;
 }
+extern "C" {
+    pub fn FT_Set_Var_Design_Coordinates(
+        face: FT_Face,
+        num_coords: FT_UInt,
+        coords: *mut FT_Fixed,
+    ) -> FT_Error;
+}
+extern "C" {
+    pub fn FT_Get_Var_Design_Coordinates(
+        face: FT_Face,
+        num_coords: FT_UInt,
+        coords: *mut FT_Fixed,
+    ) -> FT_Error;
+}
 extern "C" {
     pub fn FT_MulDiv(a: FT_Long, b: FT_Long, c: FT_Long) -> FT_Long;
 }
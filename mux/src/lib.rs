@@ -33,6 +33,10 @@ use crate::activity::Activity;
 pub enum MuxNotification {
     PaneOutput(PaneId),
     WindowCreated(WindowId),
+    Alert {
+        pane_id: PaneId,
+        alert: wezterm_term::Alert,
+    },
 }
 
 static SUB_ID: AtomicUsize = AtomicUsize::new(0);
@@ -66,6 +66,10 @@ impl Pane for LocalPane {
         (first, lines)
     }
 
+    fn make_line_dirty(&self, row: StableRowIndex) {
+        self.terminal.borrow_mut().make_line_dirty(row);
+    }
+
     fn get_dimensions(&self) -> RenderableDimensions {
         terminal_get_dimensions(&mut self.terminal.borrow_mut())
     }
@@ -163,6 +167,10 @@ impl Pane for LocalPane {
         }
     }
 
+    fn is_bracketed_paste_enabled(&self) -> bool {
+        self.terminal.borrow().bracketed_paste_enabled()
+    }
+
     fn get_current_working_dir(&self) -> Option<Url> {
         self.terminal
             .borrow()
@@ -176,51 +184,81 @@ impl Pane for LocalPane {
         term.get_semantic_zones()
     }
 
-    async fn search(&self, mut pattern: Pattern) -> anyhow::Result<Vec<SearchResult>> {
+    async fn search(&self, pattern: Pattern) -> anyhow::Result<Vec<SearchResult>> {
         let term = self.terminal.borrow();
         let screen = term.screen();
+        let lines = screen
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(idx, line)| (screen.phys_to_stable_row_index(idx), line));
+        Ok(search_lines(lines, pattern))
+    }
+}
 
-        if let Pattern::CaseInSensitiveString(s) = &mut pattern {
-            // normalize the case so we match everything lowercase
-            *s = s.to_lowercase()
-        }
+/// Finds the matches for `pattern` in `lines`, which are paired with their
+/// stable row indices.  A line that wraps is joined to the line after it,
+/// so that a match can span the wrap.  Regex patterns are matched against
+/// all of the lines at once, separated by newlines, so that they can
+/// match across lines too.
+fn search_lines<'a>(
+    lines: impl Iterator<Item = (StableRowIndex, &'a Line)>,
+    mut pattern: Pattern,
+) -> Vec<SearchResult> {
+    if let Pattern::CaseInSensitiveString(s) = &mut pattern {
+        // normalize the case so we match everything lowercase
+        *s = s.to_lowercase()
+    }
 
-        let mut results = vec![];
-        let mut haystack = String::new();
-        let mut coords = vec![];
+    let mut results = vec![];
+    let mut haystack = String::new();
+    let mut coords = vec![];
 
-        struct Coord {
-            byte_idx: usize,
-            grapheme_idx: usize,
-            stable_row: StableRowIndex,
-        }
+    struct Coord {
+        byte_idx: usize,
+        grapheme_idx: usize,
+        stable_row: StableRowIndex,
+    }
 
-        fn haystack_idx_to_coord(idx: usize, coords: &[Coord]) -> (usize, StableRowIndex) {
-            let c = coords
-                .binary_search_by(|ele| ele.byte_idx.cmp(&idx))
-                .or_else(|i| -> Result<usize, usize> { Ok(i) })
-                .unwrap();
-            let coord = coords.get(c).or_else(|| coords.last()).unwrap();
-            (coord.grapheme_idx, coord.stable_row)
-        }
+    fn haystack_idx_to_coord(idx: usize, coords: &[Coord]) -> (usize, StableRowIndex) {
+        let c = coords
+            .binary_search_by(|ele| ele.byte_idx.cmp(&idx))
+            .or_else(|i| -> Result<usize, usize> { Ok(i) })
+            .unwrap();
+        let coord = coords.get(c).or_else(|| coords.last()).unwrap();
+        (coord.grapheme_idx, coord.stable_row)
+    }
 
-        fn collect_matches(
-            results: &mut Vec<SearchResult>,
-            pattern: &Pattern,
-            haystack: &str,
-            coords: &[Coord],
-        ) {
-            if haystack.is_empty() {
-                return;
+    fn collect_matches(
+        results: &mut Vec<SearchResult>,
+        pattern: &Pattern,
+        haystack: &str,
+        coords: &[Coord],
+    ) {
+        if haystack.is_empty() {
+            return;
+        }
+        match pattern {
+            // Rust only provides a case sensitive match_indices function, so
+            // we have to pre-arrange to lowercase both the pattern and the
+            // haystack strings
+            Pattern::CaseInSensitiveString(s) | Pattern::CaseSensitiveString(s) => {
+                for (idx, s) in haystack.match_indices(s) {
+                    let (start_x, start_y) = haystack_idx_to_coord(idx, coords);
+                    let (end_x, end_y) = haystack_idx_to_coord(idx + s.len(), coords);
+                    results.push(SearchResult {
+                        start_x,
+                        start_y,
+                        end_x,
+                        end_y,
+                    });
+                }
             }
-            match pattern {
-                // Rust only provides a case sensitive match_indices function, so
-                // we have to pre-arrange to lowercase both the pattern and the
-                // haystack strings
-                Pattern::CaseInSensitiveString(s) | Pattern::CaseSensitiveString(s) => {
-                    for (idx, s) in haystack.match_indices(s) {
-                        let (start_x, start_y) = haystack_idx_to_coord(idx, coords);
-                        let (end_x, end_y) = haystack_idx_to_coord(idx + s.len(), coords);
+            Pattern::Regex(r) => {
+                if let Ok(re) = regex::Regex::new(r) {
+                    for m in re.find_iter(haystack) {
+                        let (start_x, start_y) = haystack_idx_to_coord(m.start(), coords);
+                        let (end_x, end_y) = haystack_idx_to_coord(m.end(), coords);
                         results.push(SearchResult {
                             start_x,
                             start_y,
@@ -229,58 +267,53 @@ impl Pane for LocalPane {
                         });
                     }
                 }
-                Pattern::Regex(r) => {
-                    if let Ok(re) = regex::Regex::new(r) {
-                        for m in re.find_iter(haystack) {
-                            let (start_x, start_y) = haystack_idx_to_coord(m.start(), coords);
-                            let (end_x, end_y) = haystack_idx_to_coord(m.end(), coords);
-                            results.push(SearchResult {
-                                start_x,
-                                start_y,
-                                end_x,
-                                end_y,
-                            });
-                        }
-                    }
-                }
             }
         }
+    }
 
-        for (idx, line) in screen.lines.iter().enumerate() {
-            let stable_row = screen.phys_to_stable_row_index(idx);
-
-            let mut wrapped = false;
-            for (grapheme_idx, cell) in line.visible_cells() {
-                coords.push(Coord {
-                    byte_idx: haystack.len(),
-                    grapheme_idx,
-                    stable_row,
-                });
-
-                let s = cell.str();
-                if let Pattern::CaseInSensitiveString(_) = &pattern {
-                    // normalize the case so we match everything lowercase
-                    haystack.push_str(&s.to_lowercase());
-                } else {
-                    haystack.push_str(cell.str());
-                }
-                wrapped = cell.attrs().wrapped();
+    let mut lines = lines.peekable();
+    while let Some((stable_row, line)) = lines.next() {
+        let mut wrapped = false;
+        let mut line_end = 0;
+        for (grapheme_idx, cell) in line.visible_cells() {
+            coords.push(Coord {
+                byte_idx: haystack.len(),
+                grapheme_idx,
+                stable_row,
+            });
+
+            let s = cell.str();
+            if let Pattern::CaseInSensitiveString(_) = &pattern {
+                // normalize the case so we match everything lowercase
+                haystack.push_str(&s.to_lowercase());
+            } else {
+                haystack.push_str(cell.str());
             }
+            wrapped = cell.attrs().wrapped();
+            line_end = grapheme_idx + cell.width();
+        }
 
-            if !wrapped {
-                if let Pattern::Regex(_) = &pattern {
-                    haystack.push('\n');
-                } else {
-                    collect_matches(&mut results, &pattern, &haystack, &coords);
-                    haystack.clear();
-                    coords.clear();
-                }
+        if !wrapped || lines.peek().is_none() {
+            // Record where the line ends, so that a match that runs
+            // up to the end of the line ends after its last cell
+            coords.push(Coord {
+                byte_idx: haystack.len(),
+                grapheme_idx: line_end,
+                stable_row,
+            });
+
+            if let Pattern::Regex(_) = &pattern {
+                haystack.push('\n');
+            } else {
+                collect_matches(&mut results, &pattern, &haystack, &coords);
+                haystack.clear();
+                coords.clear();
             }
         }
-
-        collect_matches(&mut results, &pattern, &haystack, &coords);
-        Ok(results)
     }
+
+    collect_matches(&mut results, &pattern, &haystack, &coords);
+    results
 }
 
 struct LocalPaneDCSHandler {
@@ -351,6 +384,21 @@ impl wezterm_term::DeviceControlHandler for LocalPaneDCSHandler {
     }
 }
 
+struct LocalPaneAlertHandler {
+    pane_id: PaneId,
+}
+
+impl wezterm_term::AlertHandler for LocalPaneAlertHandler {
+    fn alert(&mut self, alert: wezterm_term::Alert) {
+        if let Some(mux) = Mux::get() {
+            mux.notify(crate::MuxNotification::Alert {
+                pane_id: self.pane_id,
+                alert,
+            });
+        }
+    }
+}
+
 impl LocalPane {
     pub fn new(
         pane_id: PaneId,
@@ -363,6 +411,7 @@ impl LocalPane {
             pane_id,
             tmux_domain: None,
         }));
+        terminal.set_alert_handler(Box::new(LocalPaneAlertHandler { pane_id }));
         Self {
             pane_id,
             terminal: RefCell::new(terminal),
@@ -478,3 +527,113 @@ impl Drop for LocalPane {
         self.process.borrow_mut().wait().ok();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn search(lines: &[Line], pattern: Pattern) -> Vec<(StableRowIndex, Range<usize>)> {
+        let lines = lines
+            .iter()
+            .enumerate()
+            .map(|(idx, line)| (idx as StableRowIndex + 10, line));
+        search_lines(lines, pattern)
+            .into_iter()
+            .map(|r| {
+                assert_eq!(r.start_y, r.end_y, "{:?} spans lines", r);
+                (r.start_y, r.start_x..r.end_x)
+            })
+            .collect()
+    }
+
+    fn lines(text: &[&str]) -> Vec<Line> {
+        text.iter()
+            .map(|s| Line::from_text(s, &CellAttributes::default()))
+            .collect()
+    }
+
+    #[test]
+    fn search_for_substring() {
+        let lines = lines(&["hello world", "Hello again, hello", "bye"]);
+
+        assert_eq!(
+            search(&lines, Pattern::CaseSensitiveString("hello".into())),
+            vec![(10, 0..5), (11, 13..18)]
+        );
+        assert_eq!(
+            search(&lines, Pattern::CaseInSensitiveString("HELLO".into())),
+            vec![(10, 0..5), (11, 0..5), (11, 13..18)]
+        );
+        // A match at the very end of the text includes the last cell
+        assert_eq!(
+            search(&lines, Pattern::CaseSensitiveString("bye".into())),
+            vec![(12, 0..3)]
+        );
+        assert_eq!(
+            search(&lines, Pattern::CaseSensitiveString("nope".into())),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn search_for_regex() {
+        let lines = lines(&["testing 123", "no match", "ending"]);
+
+        assert_eq!(
+            search(&lines, Pattern::Regex("[a-z]+ing".into())),
+            vec![(10, 0..7), (12, 0..6)]
+        );
+        assert_eq!(
+            search(&lines, Pattern::Regex(r"(?m)\d+$".into())),
+            vec![(10, 8..11)]
+        );
+    }
+
+    #[test]
+    fn search_across_lines() {
+        // A match can span a wrapped line
+        let attrs = CellAttributes::default();
+        let lines = [
+            Line::from_text_with_wrapped_last_col("say hel", &attrs),
+            Line::from_text("lo there", &attrs),
+        ];
+        let results = search_lines(
+            lines
+                .iter()
+                .enumerate()
+                .map(|(idx, line)| (idx as isize, line)),
+            Pattern::CaseSensitiveString("hello".into()),
+        );
+        assert_eq!(
+            results,
+            vec![SearchResult {
+                start_y: 0,
+                start_x: 4,
+                end_y: 1,
+                end_x: 2,
+            }]
+        );
+
+        // and a regex can match the end of one line and the start of the next
+        let lines = [
+            Line::from_text("one", &attrs),
+            Line::from_text("two", &attrs),
+        ];
+        let results = search_lines(
+            lines
+                .iter()
+                .enumerate()
+                .map(|(idx, line)| (idx as isize, line)),
+            Pattern::Regex("e\ntw".into()),
+        );
+        assert_eq!(
+            results,
+            vec![SearchResult {
+                start_y: 0,
+                start_x: 2,
+                end_y: 1,
+                end_x: 2,
+            }]
+        );
+    }
+}
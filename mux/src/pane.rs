@@ -98,6 +98,10 @@ pub trait Pane: Downcast {
     /// have its dirty bit set appropriately.
     fn get_lines(&self, lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>);
 
+    /// Mark the specified line as dirty so that it will be repainted;
+    /// used to redraw just the line holding a blinking cursor.
+    fn make_line_dirty(&self, _row: StableRowIndex) {}
+
     /// Returns render related dimensions
     fn get_dimensions(&self) -> RenderableDimensions;
 
@@ -140,6 +144,13 @@ pub trait Pane: Downcast {
     /// handling of clicks.
     fn is_mouse_grabbed(&self) -> bool;
 
+    /// Returns true if the application has enabled bracketed paste
+    /// mode, and will therefore be able to distinguish pasted text
+    /// from typed text.
+    fn is_bracketed_paste_enabled(&self) -> bool {
+        false
+    }
+
     fn set_clipboard(&self, _clipboard: &Arc<dyn Clipboard>) {}
 
     fn get_current_working_dir(&self) -> Option<Url>;
@@ -151,6 +151,10 @@ impl Pane for TermWizTerminalPane {
         terminal_get_lines(&mut self.terminal.borrow_mut(), lines)
     }
 
+    fn make_line_dirty(&self, row: StableRowIndex) {
+        self.terminal.borrow_mut().make_line_dirty(row);
+    }
+
     fn get_dimensions(&self) -> RenderableDimensions {
         terminal_get_dimensions(&mut self.terminal.borrow_mut())
     }
@@ -87,6 +87,35 @@ impl Window {
         self.tabs.remove(idx)
     }
 
+    /// Moves the tab at `old_idx` so that it is at `new_idx`, shifting
+    /// the tabs in between.  The active tab remains active.
+    pub fn move_tab(&mut self, old_idx: usize, new_idx: usize) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            old_idx < self.tabs.len() && new_idx < self.tabs.len(),
+            "cannot move tab {} to {} in a window with {} tabs",
+            old_idx,
+            new_idx,
+            self.tabs.len()
+        );
+        if old_idx == new_idx {
+            return Ok(());
+        }
+        let tab = self.tabs.remove(old_idx);
+        self.tabs.insert(new_idx, tab);
+
+        self.active = if self.active == old_idx {
+            new_idx
+        } else if old_idx < self.active && self.active <= new_idx {
+            self.active - 1
+        } else if new_idx <= self.active && self.active < old_idx {
+            self.active + 1
+        } else {
+            self.active
+        };
+        self.invalidated = true;
+        Ok(())
+    }
+
     pub fn remove_by_id(&mut self, id: TabId) -> bool {
         if let Some(idx) = self.idx_by_id(id) {
             self.tabs.remove(idx);
@@ -170,3 +199,67 @@ impl Window {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use portable_pty::PtySize;
+
+    fn window_with_tabs(n: usize) -> (Window, Vec<TabId>) {
+        let mut window = Window::new();
+        let mut ids = vec![];
+        for _ in 0..n {
+            let tab = Rc::new(Tab::new(&PtySize::default()));
+            ids.push(tab.tab_id());
+            window.push(&tab);
+        }
+        (window, ids)
+    }
+
+    fn tab_ids(window: &Window) -> Vec<TabId> {
+        window.iter().map(|tab| tab.tab_id()).collect()
+    }
+
+    #[test]
+    fn move_tab_forwards() {
+        let (mut window, ids) = window_with_tabs(4);
+
+        // Drag the first tab past the third one
+        window.set_active(0);
+        window.move_tab(0, 2).unwrap();
+        assert_eq!(tab_ids(&window), vec![ids[1], ids[2], ids[0], ids[3]]);
+        assert_eq!(window.get_active_idx(), 2);
+
+        // A tab that was in between shifts to make room
+        window.set_active(1);
+        window.move_tab(0, 3).unwrap();
+        assert_eq!(tab_ids(&window), vec![ids[2], ids[0], ids[3], ids[1]]);
+        assert_eq!(window.get_active().unwrap().tab_id(), ids[2]);
+    }
+
+    #[test]
+    fn move_tab_backwards() {
+        let (mut window, ids) = window_with_tabs(4);
+
+        window.set_active(1);
+        window.move_tab(3, 0).unwrap();
+        assert_eq!(tab_ids(&window), vec![ids[3], ids[0], ids[1], ids[2]]);
+        assert_eq!(window.get_active().unwrap().tab_id(), ids[1]);
+
+        // Tabs outside of the moved range are unaffected
+        window.move_tab(2, 1).unwrap();
+        assert_eq!(tab_ids(&window), vec![ids[3], ids[1], ids[0], ids[2]]);
+        assert_eq!(window.get_active().unwrap().tab_id(), ids[1]);
+        window.set_active(3);
+        window.move_tab(1, 0).unwrap();
+        assert_eq!(window.get_active().unwrap().tab_id(), ids[2]);
+    }
+
+    #[test]
+    fn move_tab_out_of_range() {
+        let (mut window, ids) = window_with_tabs(2);
+        assert!(window.move_tab(0, 2).is_err());
+        assert!(window.move_tab(2, 0).is_err());
+        assert_eq!(tab_ids(&window), ids);
+    }
+}
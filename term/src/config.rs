@@ -28,6 +28,20 @@ pub trait TerminalConfiguration: std::fmt::Debug {
         false
     }
 
+    /// Return true if applications may set or clear the clipboard
+    /// using OSC 52.
+    fn enable_osc52_clipboard(&self) -> bool {
+        true
+    }
+
+    /// Return true if applications may read the clipboard using OSC 52.
+    /// This allows anything that can write to the terminal, such as
+    /// the output of a command run on a remote host, to retrieve the
+    /// clipboard, so it is off by default.
+    fn enable_osc52_clipboard_reading(&self) -> bool {
+        false
+    }
+
     /// Returns the current generation and its associated hyperlink rules.
     /// hyperlink rules are used to recognize and automatically generate
     /// hyperlink attributes for runs of text that match the provided rules.
@@ -2,9 +2,25 @@ use super::*;
 use std::sync::Arc;
 use termwiz::escape::parser::Parser;
 
+/// Identifies which of the system clipboards is being manipulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "use_serde", derive(Deserialize, Serialize))]
+pub enum ClipboardSelection {
+    /// The clipboard that is populated by an explicit copy operation
+    Clipboard,
+    /// The X11 style primary selection, which is populated by
+    /// selecting text and is typically pasted with a middle click.
+    /// Systems without this concept may ignore it.
+    PrimarySelection,
+}
+
 pub trait Clipboard {
     fn get_contents(&self) -> anyhow::Result<String>;
-    fn set_contents(&self, data: Option<String>) -> anyhow::Result<()>;
+    fn set_contents(
+        &self,
+        selection: ClipboardSelection,
+        data: Option<String>,
+    ) -> anyhow::Result<()>;
 }
 
 impl Clipboard for Box<dyn Clipboard> {
@@ -12,8 +28,12 @@ impl Clipboard for Box<dyn Clipboard> {
         self.as_ref().get_contents()
     }
 
-    fn set_contents(&self, data: Option<String>) -> anyhow::Result<()> {
-        self.as_ref().set_contents(data)
+    fn set_contents(
+        &self,
+        selection: ClipboardSelection,
+        data: Option<String>,
+    ) -> anyhow::Result<()> {
+        self.as_ref().set_contents(selection, data)
     }
 }
 
@@ -21,6 +41,18 @@ pub trait DeviceControlHandler {
     fn handle_device_control(&mut self, _control: termwiz::escape::DeviceControlMode);
 }
 
+/// Something that the program running in the terminal would like
+/// to bring to the attention of the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alert {
+    /// The program emitted BEL
+    Bell,
+}
+
+pub trait AlertHandler {
+    fn alert(&mut self, alert: Alert);
+}
+
 /// Represents an instance of a terminal emulator.
 pub struct Terminal {
     /// The terminal model/state
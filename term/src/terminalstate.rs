@@ -17,13 +17,14 @@ use termwiz::escape::csi::{
 };
 use termwiz::escape::osc::{
     ChangeColorPair, ColorOrQuery, FinalTermSemanticPrompt, ITermFileData, ITermProprietary,
+    Selection,
 };
 use termwiz::escape::{
     Action, ControlCode, DeviceControlMode, Esc, EscCode, OneBased, OperatingSystemCommand, Sixel,
     SixelData, CSI,
 };
 use termwiz::image::{ImageCell, ImageData, TextureCoordinate};
-use termwiz::surface::{CursorShape, CursorVisibility};
+use termwiz::surface::{CursorShape, CursorVisibility, LineSize};
 use url::Url;
 
 struct TabStop {
@@ -287,6 +288,7 @@ pub struct TerminalState {
 
     clipboard: Option<Arc<dyn Clipboard>>,
     device_control_handler: Option<Box<dyn DeviceControlHandler>>,
+    alert_handler: Option<Box<dyn AlertHandler>>,
 
     current_dir: Option<Url>,
 
@@ -385,6 +387,7 @@ impl TerminalState {
             pixel_width,
             clipboard: None,
             device_control_handler: None,
+            alert_handler: None,
             current_dir: None,
             term_program: term_program.to_string(),
             term_version: term_version.to_string(),
@@ -401,6 +404,10 @@ impl TerminalState {
         self.device_control_handler.replace(handler);
     }
 
+    pub fn set_alert_handler(&mut self, handler: Box<dyn AlertHandler>) {
+        self.alert_handler.replace(handler);
+    }
+
     /// Returns the title text associated with the terminal session.
     /// The title can be changed by the application using a number
     /// of escape sequences:
@@ -465,13 +472,49 @@ impl TerminalState {
         &mut self.screen
     }
 
-    fn set_clipboard_contents(&self, text: Option<String>) -> anyhow::Result<()> {
+    fn set_clipboard_contents(
+        &self,
+        selection: Selection,
+        text: Option<String>,
+    ) -> anyhow::Result<()> {
         if let Some(clip) = self.clipboard.as_ref() {
-            clip.set_contents(text)?;
+            if selection.contains(Selection::PRIMARY) {
+                clip.set_contents(ClipboardSelection::PrimarySelection, text.clone())?;
+            }
+            // The clipboard is also used for the cut buffers and
+            // the default selection
+            if selection.intersects(!Selection::PRIMARY) || selection.is_empty() {
+                clip.set_contents(ClipboardSelection::Clipboard, text)?;
+            }
         }
         Ok(())
     }
 
+    /// Responds to an OSC 52 query by sending the clipboard contents
+    /// back to the application.  Only the clipboard can be read, so
+    /// a query for just the primary selection gets no reply.
+    fn reply_with_clipboard_contents(&mut self, selection: Selection) {
+        let selection = selection - Selection::PRIMARY;
+        if selection.is_empty() {
+            return;
+        }
+        let contents = match self.clipboard.as_ref().map(|clip| clip.get_contents()) {
+            Some(Ok(contents)) => contents,
+            Some(Err(err)) => {
+                error!("failed to read clipboard in response to OSC 52: {:?}", err);
+                return;
+            }
+            None => return,
+        };
+        write!(
+            self.writer,
+            "{}",
+            OperatingSystemCommand::SetSelection(selection, contents)
+        )
+        .ok();
+        self.writer.flush().ok();
+    }
+
     fn legacy_mouse_coord(position: i64) -> char {
         let pos = if position < 0 || position > 255 - 32 {
             0 as u8
@@ -1027,6 +1070,15 @@ impl TerminalState {
         }
     }
 
+    /// Mark a single line as dirty, such as the line holding a
+    /// blinking cursor.  Does nothing if the line is no longer valid.
+    pub fn make_line_dirty(&mut self, row: StableRowIndex) {
+        let screen = self.screen_mut();
+        if let Some(phys) = screen.stable_row_to_phys(row) {
+            screen.lines[phys].set_dirty();
+        }
+    }
+
     /// Returns the 0-based cursor position relative to the top left of
     /// the visible screen
     pub fn cursor_pos(&self) -> CursorPosition {
@@ -1110,7 +1162,8 @@ impl TerminalState {
             .max(0),
         };
 
-        self.set_cursor_position_absolute(x as usize, y);
+        let x = (x as usize).min(self.line_width(y) - 1);
+        self.set_cursor_position_absolute(x, y);
     }
 
     fn scroll_up(&mut self, num_rows: usize) {
@@ -1216,8 +1269,10 @@ impl TerminalState {
             Some(x) => x,
             None => self.left_and_right_margins.end - 1,
         };
-        self.cursor.x = x.min(self.left_and_right_margins.end - 1);
         let y = self.cursor.y;
+        self.cursor.x = x
+            .min(self.left_and_right_margins.end - 1)
+            .min(self.line_width(y) - 1);
         self.screen_mut().dirty_line(y);
     }
 
@@ -1967,6 +2022,10 @@ impl TerminalState {
             let screen = self.screen_mut();
             for y in row_range.clone() {
                 screen.clear_line(y, col_range.clone(), &pen);
+                // Lines that are erased in their entirety revert
+                // to single width
+                let line_idx = screen.phys_row(y);
+                screen.line_mut(line_idx).set_line_size(LineSize::Normal);
             }
         }
     }
@@ -2429,6 +2488,33 @@ impl TerminalState {
         }
     }
 
+    /// DECSWL, DECDWL and DECDHL change the size of the line
+    /// containing the cursor.
+    /// https://vt100.net/docs/vt510-rm/DECDWL.html
+    fn set_line_size(&mut self, size: LineSize) {
+        let y = self.cursor.y;
+        let screen = self.screen_mut();
+        let line_idx = screen.phys_row(y);
+        screen.line_mut(line_idx).set_line_size(size);
+        self.cursor.x = self.cursor.x.min(self.line_width(y) - 1);
+    }
+
+    /// Returns the number of cells that fit on the line at `y`;
+    /// each cell of a double width line occupies two columns of
+    /// the display, so only half as many cells are visible.
+    fn line_width(&self, y: VisibleRowIndex) -> usize {
+        let screen = self.screen();
+        let cols = screen.physical_cols;
+        if screen.lines[screen.phys_row(y)]
+            .line_size()
+            .is_double_width()
+        {
+            (cols / 2).max(1)
+        } else {
+            cols
+        }
+    }
+
     /// https://vt100.net/docs/vt510-rm/DECSC.html
     fn dec_save_cursor(&mut self) {
         let saved = SavedCursor {
@@ -2513,6 +2599,9 @@ impl TerminalState {
             Sgr::Background(col) => {
                 self.pen.set_background(col);
             }
+            Sgr::UnderlineColor(col) => {
+                self.pen.set_underline_color(col);
+            }
             Sgr::Font(_) => {}
         }
     }
@@ -2651,7 +2740,7 @@ impl<'a> Performer<'a> {
 
             let x = self.cursor.x;
             let y = self.cursor.y;
-            let width = self.left_and_right_margins.end;
+            let width = self.left_and_right_margins.end.min(self.line_width(y));
 
             let mut pen = self.pen.clone();
             // the max(1) here is to ensure that we advance to the next cell
@@ -2839,7 +2928,10 @@ impl<'a> Performer<'a> {
             ControlCode::HTS => self.c1_hts(),
             ControlCode::IND => self.c1_index(),
             ControlCode::NEL => self.c1_nel(),
-            ControlCode::Bell => error!("Ding! (this is the bell)"),
+            ControlCode::Bell => match self.alert_handler.as_mut() {
+                Some(handler) => handler.alert(Alert::Bell),
+                None => log::trace!("Ding! (this is the bell)"),
+            },
             ControlCode::RI => self.c1_reverse_index(),
             _ => error!("unhandled ControlCode {:?}", control),
         }
@@ -2889,6 +2981,15 @@ impl<'a> Performer<'a> {
             Esc::Code(EscCode::DecSaveCursorPosition) => self.dec_save_cursor(),
             Esc::Code(EscCode::DecRestoreCursorPosition) => self.dec_restore_cursor(),
 
+            Esc::Code(EscCode::DecSingleWidthLine) => self.set_line_size(LineSize::Normal),
+            Esc::Code(EscCode::DecDoubleWidthLine) => self.set_line_size(LineSize::DoubleWidth),
+            Esc::Code(EscCode::DecDoubleHeightTopHalfLine) => {
+                self.set_line_size(LineSize::DoubleHeightTop)
+            }
+            Esc::Code(EscCode::DecDoubleHeightBottomHalfLine) => {
+                self.set_line_size(LineSize::DoubleHeightBottom)
+            }
+
             Esc::Code(EscCode::DecScreenAlignmentDisplay) => {
                 // This one is just to make vttest happy;
                 // its original purpose was for aligning the CRT.
@@ -2982,14 +3083,24 @@ impl<'a> Performer<'a> {
                 error!("{}", output);
             }
 
-            OperatingSystemCommand::ClearSelection(_) => {
-                self.set_clipboard_contents(None).ok();
+            OperatingSystemCommand::ClearSelection(selection) => {
+                if self.config.enable_osc52_clipboard() {
+                    self.set_clipboard_contents(selection, None).ok();
+                }
             }
-            OperatingSystemCommand::QuerySelection(_) => {}
-            OperatingSystemCommand::SetSelection(_, selection_data) => {
-                match self.set_clipboard_contents(Some(selection_data)) {
-                    Ok(_) => (),
-                    Err(err) => error!("failed to set clipboard in response to OSC 52: {:?}", err),
+            OperatingSystemCommand::QuerySelection(selection) => {
+                if self.config.enable_osc52_clipboard_reading() {
+                    self.reply_with_clipboard_contents(selection);
+                }
+            }
+            OperatingSystemCommand::SetSelection(selection, selection_data) => {
+                if self.config.enable_osc52_clipboard() {
+                    match self.set_clipboard_contents(selection, Some(selection_data)) {
+                        Ok(_) => (),
+                        Err(err) => {
+                            error!("failed to set clipboard in response to OSC 52: {:?}", err)
+                        }
+                    }
                 }
             }
             OperatingSystemCommand::ITermProprietary(iterm) => match iterm {
@@ -10,27 +10,37 @@ mod csi;
 use crate::color::ColorPalette;
 use pretty_assertions::assert_eq;
 use std::cell::RefCell;
+use std::rc::Rc;
 use std::sync::Arc;
 use termwiz::escape::csi::{Edit, EraseInDisplay, EraseInLine};
 use termwiz::escape::{OneBased, OperatingSystemCommand, CSI};
-use termwiz::surface::{CursorShape, CursorVisibility};
+use termwiz::surface::{CursorShape, CursorVisibility, LineSize};
 
 #[derive(Debug)]
 struct LocalClip {
     clip: RefCell<Option<String>>,
+    primary: RefCell<Option<String>>,
 }
 
 impl LocalClip {
     fn new() -> Self {
         Self {
             clip: RefCell::new(None),
+            primary: RefCell::new(None),
         }
     }
 }
 
 impl Clipboard for LocalClip {
-    fn set_contents(&self, clip: Option<String>) -> anyhow::Result<()> {
-        *self.clip.borrow_mut() = clip;
+    fn set_contents(
+        &self,
+        selection: ClipboardSelection,
+        clip: Option<String>,
+    ) -> anyhow::Result<()> {
+        match selection {
+            ClipboardSelection::Clipboard => *self.clip.borrow_mut() = clip,
+            ClipboardSelection::PrimarySelection => *self.primary.borrow_mut() = clip,
+        }
         Ok(())
     }
 
@@ -43,19 +53,45 @@ impl Clipboard for LocalClip {
     }
 }
 
+/// Records the data that the terminal sends to the application
+#[derive(Clone, Default)]
+struct HostOutput(Rc<RefCell<Vec<u8>>>);
+
+impl std::io::Write for HostOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 struct TestTerm {
     term: Terminal,
+    clip: Arc<LocalClip>,
+    output: HostOutput,
 }
 
 #[derive(Debug)]
 struct TestTermConfig {
     scrollback: usize,
+    osc52_clipboard: bool,
+    osc52_clipboard_reading: bool,
 }
 impl TerminalConfiguration for TestTermConfig {
     fn scrollback_size(&self) -> usize {
         self.scrollback
     }
 
+    fn enable_osc52_clipboard(&self) -> bool {
+        self.osc52_clipboard
+    }
+
+    fn enable_osc52_clipboard_reading(&self) -> bool {
+        self.osc52_clipboard_reading
+    }
+
     fn color_palette(&self) -> ColorPalette {
         ColorPalette::default()
     }
@@ -63,25 +99,39 @@ impl TerminalConfiguration for TestTermConfig {
 
 impl TestTerm {
     fn new(height: usize, width: usize, scrollback: usize) -> Self {
+        Self::with_config(
+            height,
+            width,
+            TestTermConfig {
+                scrollback,
+                osc52_clipboard: true,
+                osc52_clipboard_reading: false,
+            },
+        )
+    }
+
+    fn with_config(height: usize, width: usize, config: TestTermConfig) -> Self {
         let _ = pretty_env_logger::formatted_builder()
             .is_test(true)
             .filter_level(log::LevelFilter::Trace)
             .try_init();
 
+        let output = HostOutput::default();
         let mut term = Terminal::new(
             height,
             width,
             height * 16,
             width * 8,
-            Arc::new(TestTermConfig { scrollback }),
+            Arc::new(config),
             "WezTerm",
             "O_o",
-            Box::new(Vec::new()),
+            Box::new(output.clone()),
         );
-        let clip: Arc<dyn Clipboard> = Arc::new(LocalClip::new());
-        term.set_clipboard(&clip);
+        let clip = Arc::new(LocalClip::new());
+        let dyn_clip: Arc<dyn Clipboard> = Arc::clone(&clip) as _;
+        term.set_clipboard(&dyn_clip);
 
-        let mut term = Self { term };
+        let mut term = Self { term, clip, output };
 
         term.set_auto_wrap(true);
 
@@ -567,6 +617,19 @@ fn cursor_movement_damage() {
     term.assert_dirty_lines(&[0, 1], Some("cursor movement dirties old and new lines"));
 }
 
+/// A cursor blink should only need to repaint the line holding the cursor
+#[test]
+fn cursor_blink_damage() {
+    let mut term = TestTerm::new(3, 3, 0);
+
+    term.print("a\r\nb");
+    term.clean_dirty_lines();
+
+    let cursor_row = term.screen().visible_row_to_stable_row(term.cursor_pos().y);
+    term.make_line_dirty(cursor_row);
+    term.assert_dirty_lines(&[1], Some("only the cursor line is dirtied"));
+}
+
 /// Replicates a bug I initially found via:
 /// $ vim
 /// :help
@@ -895,3 +958,174 @@ fn test_hyperlinks() {
         Compare::TEXT | Compare::ATTRS,
     );
 }
+
+#[test]
+fn test_osc52_selection() {
+    let mut term = TestTerm::new(3, 10, 0);
+
+    // "aGVsbG8=" is "hello" in base64
+    term.print("\x1b]52;c;aGVsbG8=\x1b\\");
+    assert_eq!(*term.clip.clip.borrow(), Some("hello".to_string()));
+    assert_eq!(*term.clip.primary.borrow(), None);
+
+    // "d29ybGQ=" is "world"
+    term.print("\x1b]52;p;d29ybGQ=\x1b\\");
+    assert_eq!(*term.clip.clip.borrow(), Some("hello".to_string()));
+    assert_eq!(*term.clip.primary.borrow(), Some("world".to_string()));
+
+    // Both at once, then clear the clipboard only
+    term.print("\x1b]52;pc;aGVsbG8=\x1b\\");
+    assert_eq!(*term.clip.primary.borrow(), Some("hello".to_string()));
+    term.print("\x1b]52;c\x1b\\");
+    assert_eq!(*term.clip.clip.borrow(), None);
+    assert_eq!(*term.clip.primary.borrow(), Some("hello".to_string()));
+}
+
+#[test]
+fn test_osc52_selection_disabled() {
+    let mut term = TestTerm::with_config(
+        3,
+        10,
+        TestTermConfig {
+            scrollback: 0,
+            osc52_clipboard: false,
+            osc52_clipboard_reading: false,
+        },
+    );
+
+    term.print("\x1b]52;c;aGVsbG8=\x1b\\");
+    assert_eq!(*term.clip.clip.borrow(), None);
+
+    // Clearing is also ignored
+    *term.clip.clip.borrow_mut() = Some("hello".to_string());
+    term.print("\x1b]52;c\x1b\\");
+    assert_eq!(*term.clip.clip.borrow(), Some("hello".to_string()));
+}
+
+#[test]
+fn test_osc52_query() {
+    let mut term = TestTerm::new(3, 10, 0);
+    *term.clip.clip.borrow_mut() = Some("hello".to_string());
+
+    // Reading the clipboard is off by default
+    term.print("\x1b]52;c;?\x1b\\");
+    assert!(term.output.0.borrow().is_empty());
+
+    let mut term = TestTerm::with_config(
+        3,
+        10,
+        TestTermConfig {
+            scrollback: 0,
+            osc52_clipboard: true,
+            osc52_clipboard_reading: true,
+        },
+    );
+    *term.clip.clip.borrow_mut() = Some("hello".to_string());
+    term.print("\x1b]52;c;?\x1b\\");
+    assert_eq!(
+        String::from_utf8(term.output.0.borrow().clone()).unwrap(),
+        "\x1b]52;c;aGVsbG8=\x1b\\"
+    );
+    term.output.0.borrow_mut().clear();
+
+    // The primary selection can't be read back
+    *term.clip.primary.borrow_mut() = Some("world".to_string());
+    term.print("\x1b]52;p;?\x1b\\");
+    assert!(term.output.0.borrow().is_empty());
+
+    // and is left out of a query that also names the clipboard
+    term.print("\x1b]52;pc;?\x1b\\");
+    assert_eq!(
+        String::from_utf8(term.output.0.borrow().clone()).unwrap(),
+        "\x1b]52;c;aGVsbG8=\x1b\\"
+    );
+}
+
+struct RecordingAlertHandler {
+    alerts: Rc<RefCell<Vec<Alert>>>,
+}
+
+impl AlertHandler for RecordingAlertHandler {
+    fn alert(&mut self, alert: Alert) {
+        self.alerts.borrow_mut().push(alert);
+    }
+}
+
+#[test]
+fn test_bell() {
+    let mut term = TestTerm::new(1, 3, 0);
+    let alerts = Rc::new(RefCell::new(vec![]));
+    term.set_alert_handler(Box::new(RecordingAlertHandler {
+        alerts: Rc::clone(&alerts),
+    }));
+
+    term.print("a\x07b");
+    assert_eq!(*alerts.borrow(), vec![Alert::Bell]);
+    assert_visible_contents(&term, file!(), line!(), &["ab "]);
+}
+
+#[test]
+fn test_line_size() {
+    let mut term = TestTerm::new(4, 4, 0);
+    term.print("\x1b#6a\r\n\x1b#3b\r\n\x1b#4b\r\nc");
+
+    let sizes: Vec<LineSize> = term
+        .screen()
+        .visible_lines()
+        .iter()
+        .map(|line| line.line_size())
+        .collect();
+    assert_eq!(
+        sizes,
+        vec![
+            LineSize::DoubleWidth,
+            LineSize::DoubleHeightTop,
+            LineSize::DoubleHeightBottom,
+            LineSize::Normal
+        ]
+    );
+
+    term.cup(0, 0);
+    term.print("\x1b#5");
+    assert_eq!(
+        term.screen().visible_lines()[0].line_size(),
+        LineSize::Normal
+    );
+    assert_visible_contents(&term, file!(), line!(), &["a   ", "b   ", "b   ", "c   "]);
+
+    // Erasing the display makes every line single width again
+    term.print("\x1b#6\x1b[2J");
+    assert!(term
+        .screen()
+        .visible_lines()
+        .iter()
+        .all(|line| line.line_size() == LineSize::Normal));
+}
+
+#[test]
+fn test_double_width_line_margin() {
+    let mut term = TestTerm::new(3, 4, 0);
+
+    // Only half of the columns fit on a double width line,
+    // so the text wraps after the second cell
+    term.print("\x1b#6abc");
+    term.assert_cursor_pos(1, 1, None);
+    assert_visible_contents(&term, file!(), line!(), &["ab  ", "c   ", "    "]);
+
+    // Cursor positioning is limited to the visible cells
+    term.cup(3, 0);
+    term.assert_cursor_pos(1, 0, None);
+
+    // as is the cursor when the line becomes double width
+    term.cup(3, 2);
+    term.print("\x1b#3");
+    term.assert_cursor_pos(1, 2, None);
+
+    // Full reset restores single width lines
+    term.print("\x1bc");
+    assert!(term
+        .screen()
+        .visible_lines()
+        .iter()
+        .all(|line| line.line_size() == LineSize::Normal));
+}
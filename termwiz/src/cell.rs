@@ -6,6 +6,7 @@ use crate::image::ImageCell;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std;
 use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use unicode_width::UnicodeWidthStr;
 
@@ -56,6 +57,9 @@ struct FatAttributes {
     hyperlink: Option<Arc<Hyperlink>>,
     /// The image data, if any
     image: Option<Box<ImageCell>>,
+    /// The color of the underline.  If Default, then the
+    /// underline is drawn using the foreground color.
+    underline_color: ColorAttribute,
 }
 
 /// Define getter and setter for the attributes bitfield.
@@ -162,6 +166,9 @@ pub enum Underline {
     Single = 1,
     /// The cell is underlined with two lines
     Double = 2,
+    /// The cell is underlined with a wavy line; this is often
+    /// used to highlight spelling mistakes
+    Curly = 3,
 }
 
 impl Default for Underline {
@@ -233,6 +240,7 @@ impl CellAttributes {
             self.fat.replace(Box::new(FatAttributes {
                 hyperlink: None,
                 image: None,
+                underline_color: ColorAttribute::Default,
             }));
         }
     }
@@ -241,7 +249,11 @@ impl CellAttributes {
         let deallocate = self
             .fat
             .as_ref()
-            .map(|fat| fat.image.is_none() && fat.hyperlink.is_none())
+            .map(|fat| {
+                fat.image.is_none()
+                    && fat.hyperlink.is_none()
+                    && fat.underline_color == ColorAttribute::Default
+            })
             .unwrap_or(false);
         if deallocate {
             self.fat.take();
@@ -270,6 +282,20 @@ impl CellAttributes {
         }
     }
 
+    /// Set the color of the underline.  `ColorAttribute::Default`
+    /// causes the underline to be drawn in the foreground color.
+    pub fn set_underline_color<C: Into<ColorAttribute>>(&mut self, color: C) -> &mut Self {
+        let color = color.into();
+        if color == ColorAttribute::Default && self.fat.is_none() {
+            self
+        } else {
+            self.allocate_fat_attributes();
+            self.fat.as_mut().unwrap().underline_color = color;
+            self.deallocate_fat_attributes_if_none();
+            self
+        }
+    }
+
     /// Clone the attributes, but exclude fancy extras such
     /// as hyperlinks or future sprite things
     pub fn clone_sgr_only(&self) -> Self {
@@ -279,6 +305,8 @@ impl CellAttributes {
             background: self.background,
             fat: None,
         };
+        // The underline color is set via SGR, so it is retained
+        res.set_underline_color(self.underline_color());
         // Reset the semantic type; clone_sgr_only is used primarily
         // to create a "blank" cell when clearing and we want that to
         // be deterministically tagged as Output so that we have an
@@ -291,6 +319,13 @@ impl CellAttributes {
         self.fat.as_ref().and_then(|fat| fat.hyperlink.as_ref())
     }
 
+    pub fn underline_color(&self) -> ColorAttribute {
+        self.fat
+            .as_ref()
+            .map(|fat| fat.underline_color)
+            .unwrap_or(ColorAttribute::Default)
+    }
+
     pub fn image(&self) -> Option<&ImageCell> {
         self.fat
             .as_ref()
@@ -514,6 +549,22 @@ impl Cell {
     }
 }
 
+static EAST_ASIAN_AMBIGUOUS_IS_WIDE: AtomicBool = AtomicBool::new(false);
+
+/// Specify whether characters with the East Asian Width property of
+/// "Ambiguous" should be treated as occupying two cells rather than one.
+/// This is a process-wide setting that affects `unicode_column_width`,
+/// `grapheme_column_width` and `Cell::width`; it should be set before
+/// any text is placed into the model so that cell accounting is consistent.
+pub fn set_treat_east_asian_ambiguous_width_as_wide(wide: bool) {
+    EAST_ASIAN_AMBIGUOUS_IS_WIDE.store(wide, Ordering::Relaxed);
+}
+
+/// Returns true if ambiguous width characters are treated as double width
+pub fn treat_east_asian_ambiguous_width_as_wide() -> bool {
+    EAST_ASIAN_AMBIGUOUS_IS_WIDE.load(Ordering::Relaxed)
+}
+
 /// Returns the number of cells visually occupied by a sequence
 /// of graphemes
 pub fn unicode_column_width(s: &str) -> usize {
@@ -524,6 +575,10 @@ pub fn unicode_column_width(s: &str) -> usize {
 /// Returns the number of cells visually occupied by a grapheme.
 /// The input string must be a single grapheme.
 pub fn grapheme_column_width(s: &str) -> usize {
+    grapheme_column_width_with_ambiguous(s, treat_east_asian_ambiguous_width_as_wide())
+}
+
+fn grapheme_column_width_with_ambiguous(s: &str, ambiguous_is_wide: bool) -> usize {
     // Due to this issue:
     // https://github.com/unicode-rs/unicode-width/issues/4
     // we cannot simply use the unicode-width crate to compute
@@ -540,7 +595,11 @@ pub fn grapheme_column_width(s: &str) -> usize {
             emoji = true;
         }
     }
-    let width = UnicodeWidthStr::width(s);
+    let width = if ambiguous_is_wide {
+        UnicodeWidthStr::width_cjk(s)
+    } else {
+        UnicodeWidthStr::width(s)
+    };
     if emoji {
         // For sequences such as "deaf man", UnicodeWidthStr::width()
         // returns 3 because of the widths of the component glyphs,
@@ -658,4 +717,17 @@ mod test {
         eprintln!("font_awesome_star {}", font_awesome_star.escape_debug());
         assert_eq!(unicode_column_width(font_awesome_star), 1);
     }
+
+    #[test]
+    fn ambiguous_width() {
+        // GREEK SMALL LETTER ALPHA has East Asian Width "Ambiguous"
+        let alpha = "\u{3b1}";
+        assert_eq!(grapheme_column_width_with_ambiguous(alpha, false), 1);
+        assert_eq!(grapheme_column_width_with_ambiguous(alpha, true), 2);
+
+        // Narrow and wide characters are not affected
+        assert_eq!(grapheme_column_width_with_ambiguous("a", true), 1);
+        assert_eq!(grapheme_column_width_with_ambiguous("\u{4e00}", false), 2);
+        assert_eq!(grapheme_column_width_with_ambiguous("\u{4e00}", true), 2);
+    }
 }
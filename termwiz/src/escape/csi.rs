@@ -1094,6 +1094,9 @@ pub enum Sgr {
     Font(Font),
     Foreground(ColorSpec),
     Background(ColorSpec),
+    /// The color of the underline; `ColorSpec::Default`
+    /// means that the foreground color is used
+    UnderlineColor(ColorSpec),
     Overline(bool),
 }
 
@@ -1125,6 +1128,7 @@ impl Display for Sgr {
             Sgr::Underline(Underline::Single) => code!(UnderlineOn),
             Sgr::Underline(Underline::Double) => code!(UnderlineDouble),
             Sgr::Underline(Underline::None) => code!(UnderlineOff),
+            Sgr::Underline(Underline::Curly) => write!(f, "{}:3m", SgrCode::UnderlineOn as i64)?,
             Sgr::Blink(Blink::Slow) => code!(BlinkOn),
             Sgr::Blink(Blink::Rapid) => code!(RapidBlinkOn),
             Sgr::Blink(Blink::None) => code!(BlinkOff),
@@ -1213,6 +1217,21 @@ impl Display for Sgr {
                 c.green,
                 c.blue
             )?,
+            Sgr::UnderlineColor(ColorSpec::Default) => code!(ResetUnderlineColor),
+            // Terminals that don't know about underline colors will
+            // ignore these colon separated forms, whereas they would
+            // misinterpret the semicolon separated equivalents.
+            Sgr::UnderlineColor(ColorSpec::PaletteIndex(idx)) => {
+                write!(f, "{}:5:{}m", SgrCode::UnderlineColor as i64, *idx)?
+            }
+            Sgr::UnderlineColor(ColorSpec::TrueColor(c)) => write!(
+                f,
+                "{}:2::{}:{}:{}m",
+                SgrCode::UnderlineColor as i64,
+                c.red,
+                c.green,
+                c.blue
+            )?,
         }
         Ok(())
     }
@@ -1242,6 +1261,10 @@ struct CSIParser<'a> {
     /// default values, especially for SGR, so we need to be careful not
     /// to update params to an empty slice.
     params: Option<&'a [i64]>,
+    /// Parallel to the full set of params passed to the parser;
+    /// `subparams[i]` is true if the ith parameter was separated from
+    /// its predecessor by a colon.  Empty if there were no colons.
+    subparams: &'a [bool],
 }
 
 impl CSI {
@@ -1262,6 +1285,29 @@ impl CSI {
             ignored_extra_intermediates,
             control,
             params: Some(params),
+            subparams: &[],
+        }
+    }
+
+    /// Parse a CSI sequence in which some of the parameters were
+    /// separated by colons rather than semicolons, such as `CSI 4:3 m`.
+    /// `subparams` must be the same length as `params`; `subparams[i]`
+    /// is true if `params[i]` is a sub-parameter of the parameter that
+    /// precedes it.
+    pub fn parse_with_subparams<'a>(
+        params: &'a [i64],
+        subparams: &'a [bool],
+        intermediates: &'a [u8],
+        ignored_extra_intermediates: bool,
+        control: char,
+    ) -> impl Iterator<Item = CSI> + 'a {
+        debug_assert_eq!(params.len(), subparams.len());
+        CSIParser {
+            intermediates,
+            ignored_extra_intermediates,
+            control,
+            params: Some(params),
+            subparams,
         }
     }
 }
@@ -1425,6 +1471,21 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    /// Returns the number of sub-parameters that immediately follow
+    /// `params[0]`.  `params` is the remaining portion of the params
+    /// that were passed to the parser.
+    fn num_subparams(&self, params: &[i64]) -> usize {
+        if self.subparams.len() < params.len() {
+            return 0;
+        }
+        let offset = self.subparams.len() - params.len();
+        self.subparams[offset..]
+            .iter()
+            .skip(1)
+            .take_while(|&&sub| sub)
+            .count()
+    }
+
     /// Consume some number of elements from params and update it.
     /// Take care to avoid setting params back to an empty slice
     /// as this would trigger returning a default value and/or
@@ -1715,6 +1776,24 @@ impl<'a> CSIParser<'a> {
         }
     }
 
+    /// Parse the colon separated form of an extended color, such
+    /// as `38:2::r:g:b` or `38:5:idx`.  The color space id in the
+    /// true color form is optional and ignored.
+    fn parse_sgr_color_subparams(
+        &mut self,
+        params: &'a [i64],
+        num_subparams: usize,
+    ) -> Result<ColorSpec, ()> {
+        let color = match &params[1..=num_subparams] {
+            [2, _, red, green, blue, ..] | [2, red, green, blue] => {
+                RgbColor::new(to_u8(*red)?, to_u8(*green)?, to_u8(*blue)?).into()
+            }
+            [5, idx] => ColorSpec::PaletteIndex(to_u8(*idx)?),
+            _ => return Err(()),
+        };
+        Ok(self.advance_by(1 + num_subparams, params, color))
+    }
+
     fn window(&mut self, params: &'a [i64]) -> Result<Window, ()> {
         if params.is_empty() {
             Err(())
@@ -1805,8 +1884,32 @@ impl<'a> CSIParser<'a> {
                 };
             };
 
+            let num_subparams = self.num_subparams(params);
+
             match FromPrimitive::from_i64(params[0]) {
                 None => Err(()),
+                // `CSI 4:n m` selects the underline style
+                Some(SgrCode::UnderlineOn) if num_subparams > 0 => {
+                    let underline = match params[1] {
+                        0 => Underline::None,
+                        2 => Underline::Double,
+                        3 => Underline::Curly,
+                        // We don't have dotted or dashed underlines,
+                        // so render those as a single underline
+                        _ => Underline::Single,
+                    };
+                    Ok(self.advance_by(1 + num_subparams, params, Sgr::Underline(underline)))
+                }
+                Some(SgrCode::ForegroundColor) if num_subparams > 0 => self
+                    .parse_sgr_color_subparams(params, num_subparams)
+                    .map(Sgr::Foreground),
+                Some(SgrCode::BackgroundColor) if num_subparams > 0 => self
+                    .parse_sgr_color_subparams(params, num_subparams)
+                    .map(Sgr::Background),
+                Some(SgrCode::UnderlineColor) if num_subparams > 0 => self
+                    .parse_sgr_color_subparams(params, num_subparams)
+                    .map(Sgr::UnderlineColor),
+                Some(_) if num_subparams > 0 => Err(()),
                 Some(sgr) => match sgr {
                     SgrCode::Reset => one!(Sgr::Reset),
                     SgrCode::IntensityBold => one!(Sgr::Intensity(Intensity::Bold)),
@@ -1876,6 +1979,12 @@ impl<'a> CSIParser<'a> {
                     SgrCode::InvisibleOff => one!(Sgr::Invisible(false)),
                     SgrCode::StrikeThroughOn => one!(Sgr::StrikeThrough(true)),
                     SgrCode::StrikeThroughOff => one!(Sgr::StrikeThrough(false)),
+                    SgrCode::UnderlineColor => {
+                        self.parse_sgr_color(params).map(Sgr::UnderlineColor)
+                    }
+                    SgrCode::ResetUnderlineColor => {
+                        one!(Sgr::UnderlineColor(ColorSpec::Default))
+                    }
                     SgrCode::OverlineOn => one!(Sgr::Overline(true)),
                     SgrCode::OverlineOff => one!(Sgr::Overline(false)),
                     SgrCode::DefaultFont => one!(Sgr::Font(Font::Default)),
@@ -1970,6 +2079,8 @@ pub enum SgrCode {
     /// a sequence describing a true color rgb value
     ForegroundColor = 38,
     BackgroundColor = 48,
+    UnderlineColor = 58,
+    ResetUnderlineColor = 59,
 }
 
 impl<'a> Iterator for CSIParser<'a> {
@@ -2105,6 +2216,102 @@ mod test {
         );
     }
 
+    fn parse_sub(control: char, params: &[i64], subparams: &[bool], expected: &str) -> Vec<CSI> {
+        let res = CSI::parse_with_subparams(params, subparams, &[], false, control).collect();
+        assert_eq!(encode(&res), expected);
+        res
+    }
+
+    #[test]
+    fn underline_style() {
+        assert_eq!(
+            parse_sub('m', &[4, 3], &[false, true], "\x1b[4:3m"),
+            vec![CSI::Sgr(Sgr::Underline(Underline::Curly))]
+        );
+        assert_eq!(
+            parse_sub('m', &[4, 0], &[false, true], "\x1b[24m"),
+            vec![CSI::Sgr(Sgr::Underline(Underline::None))]
+        );
+        assert_eq!(
+            parse_sub('m', &[1, 4, 2], &[false, false, true], "\x1b[1m\x1b[21m"),
+            vec![
+                CSI::Sgr(Sgr::Intensity(Intensity::Bold)),
+                CSI::Sgr(Sgr::Underline(Underline::Double)),
+            ]
+        );
+        // Dotted underlines are shown as a single underline
+        assert_eq!(
+            parse_sub('m', &[4, 4, 1], &[false, true, false], "\x1b[4m\x1b[1m"),
+            vec![
+                CSI::Sgr(Sgr::Underline(Underline::Single)),
+                CSI::Sgr(Sgr::Intensity(Intensity::Bold)),
+            ]
+        );
+        // Sub-parameters on codes that don't take them are not recognized
+        assert_eq!(
+            parse_sub('m', &[1, 2], &[false, true], "\x1b[1;2m"),
+            vec![CSI::Unspecified(Box::new(Unspecified {
+                params: [1, 2].to_vec(),
+                intermediates: vec![],
+                ignored_extra_intermediates: false,
+                control: 'm',
+            }))]
+        );
+    }
+
+    #[test]
+    fn underline_color() {
+        assert_eq!(
+            parse('m', &[58, 2, 255, 0, 128], "\x1b[58:2::255:0:128m"),
+            vec![CSI::Sgr(Sgr::UnderlineColor(ColorSpec::TrueColor(
+                RgbColor::new(255, 0, 128),
+            )))]
+        );
+        assert_eq!(
+            parse('m', &[58, 5, 220], "\x1b[58:5:220m"),
+            vec![CSI::Sgr(Sgr::UnderlineColor(ColorSpec::PaletteIndex(220)))]
+        );
+        assert_eq!(
+            parse('m', &[59], "\x1b[59m"),
+            vec![CSI::Sgr(Sgr::UnderlineColor(ColorSpec::Default))]
+        );
+        // The color space id is optional in the colon separated form
+        assert_eq!(
+            parse_sub(
+                'm',
+                &[58, 2, 0, 255, 0, 128],
+                &[false, true, true, true, true, true],
+                "\x1b[58:2::255:0:128m"
+            ),
+            vec![CSI::Sgr(Sgr::UnderlineColor(ColorSpec::TrueColor(
+                RgbColor::new(255, 0, 128),
+            )))]
+        );
+        assert_eq!(
+            parse_sub(
+                'm',
+                &[58, 2, 255, 0, 128],
+                &[false, true, true, true, true],
+                "\x1b[58:2::255:0:128m"
+            ),
+            vec![CSI::Sgr(Sgr::UnderlineColor(ColorSpec::TrueColor(
+                RgbColor::new(255, 0, 128),
+            )))]
+        );
+        assert_eq!(
+            parse_sub(
+                'm',
+                &[38, 5, 220, 4],
+                &[false, true, true, false],
+                "\x1b[38;5;220m\x1b[4m"
+            ),
+            vec![
+                CSI::Sgr(Sgr::Foreground(ColorSpec::PaletteIndex(220))),
+                CSI::Sgr(Sgr::Underline(Underline::Single)),
+            ]
+        );
+    }
+
     #[test]
     fn edit() {
         assert_eq!(
@@ -73,6 +73,14 @@ pub enum EscCode {
     /// Designate Character Set – US ASCII
     AsciiCharacterSet = esc!('(', 'B'),
 
+    /// DECDHL - Top half of a double-width, double-height line
+    DecDoubleHeightTopHalfLine = esc!('#', '3'),
+    /// DECDHL - Bottom half of a double-width, double-height line
+    DecDoubleHeightBottomHalfLine = esc!('#', '4'),
+    /// DECSWL - Single-width, single-height line
+    DecSingleWidthLine = esc!('#', '5'),
+    /// DECDWL - Double-width, single-height line
+    DecDoubleWidthLine = esc!('#', '6'),
     /// https://vt100.net/docs/vt510-rm/DECALN.html
     DecScreenAlignmentDisplay = esc!('#', '8'),
 
@@ -172,5 +180,7 @@ mod test {
     fn test() {
         assert_eq!(parse("(0"), Esc::Code(EscCode::DecLineDrawing));
         assert_eq!(parse("(B"), Esc::Code(EscCode::AsciiCharacterSet));
+        assert_eq!(parse("#6"), Esc::Code(EscCode::DecDoubleWidthLine));
+        assert_eq!(parse("#3"), Esc::Code(EscCode::DecDoubleHeightTopHalfLine));
     }
 }
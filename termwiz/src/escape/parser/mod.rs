@@ -228,6 +228,25 @@ impl<'a, F: FnMut(Action)> VTActor for Performer<'a, F> {
         }
     }
 
+    fn csi_dispatch_with_subparams(
+        &mut self,
+        params: &[i64],
+        subparams: &[bool],
+        intermediates: &[u8],
+        ignored_extra_intermediates: bool,
+        control: u8,
+    ) {
+        for action in CSI::parse_with_subparams(
+            params,
+            subparams,
+            intermediates,
+            ignored_extra_intermediates,
+            control as char,
+        ) {
+            (self.callback)(Action::CSI(action));
+        }
+    }
+
     fn esc_dispatch(
         &mut self,
         _params: &[i64],
@@ -417,7 +436,7 @@ impl SixelBuilder {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::cell::Intensity;
+    use crate::cell::{Intensity, Underline};
     use crate::escape::csi::Sgr;
     use crate::escape::EscCode;
     use std::io::Write;
@@ -461,6 +480,21 @@ mod test {
         assert_eq!(encode(&actions), "\x1b[1mb");
     }
 
+    #[test]
+    fn curly_underline() {
+        let mut p = Parser::new();
+        let actions = p.parse_as_vec(b"\x1b[4:3mb\x1b[4m");
+        assert_eq!(
+            vec![
+                Action::CSI(CSI::Sgr(Sgr::Underline(Underline::Curly))),
+                Action::Print('b'),
+                Action::CSI(CSI::Sgr(Sgr::Underline(Underline::Single))),
+            ],
+            actions
+        );
+        assert_eq!(encode(&actions), "\x1b[4:3mb\x1b[4m");
+    }
+
     #[test]
     fn basic_bold_italic() {
         let mut p = Parser::new();
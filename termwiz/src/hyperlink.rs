@@ -267,4 +267,28 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn parse_custom_rule() {
+        let rules = vec![
+            Rule::new(r"\b\w+://(?:[\w.-]+)\.[a-z]{2,15}\S*\b", "$0").unwrap(),
+            Rule::new(r"#(\d+)\b", "https://github.com/wez/wezterm/issues/$1").unwrap(),
+        ];
+
+        assert_eq!(
+            Rule::match_hyperlinks("see https://wezfurlong.org/wezterm/ and #123", &rules),
+            vec![
+                RuleMatch {
+                    range: 4..34,
+                    link: Arc::new(Hyperlink::new_implicit("https://wezfurlong.org/wezterm")),
+                },
+                RuleMatch {
+                    range: 40..44,
+                    link: Arc::new(Hyperlink::new_implicit(
+                        "https://github.com/wez/wezterm/issues/123"
+                    )),
+                },
+            ]
+        );
+    }
 }
@@ -70,12 +70,18 @@ impl TerminfoRenderer {
                 current_foreground = ColorAttribute::Default;
                 current_background = ColorAttribute::Default;
 
+                // Not every terminal understands the curly underline sequence,
+                // and those that don't may misinterpret it, so we fall back
+                // to a single underline.
+                let single_underline =
+                    attr.underline() == Underline::Single || attr.underline() == Underline::Curly;
+
                 // The SetAttributes capability can only handle single underline and slow blink.
                 if let Some(sgr) = self.get_capability::<cap::SetAttributes>() {
                     sgr.expand()
                         .bold(attr.intensity() == Intensity::Bold)
                         .dim(attr.intensity() == Intensity::Half)
-                        .underline(attr.underline() == Underline::Single)
+                        .underline(single_underline)
                         .blink(attr.blink() == Blink::Slow)
                         .reverse(attr.reverse())
                         .invisible(attr.invisible())
@@ -89,7 +95,7 @@ impl TerminfoRenderer {
                         _ => {}
                     }
 
-                    if attr.underline() == Underline::Single {
+                    if single_underline {
                         attr_on!(Sgr::Underline(Underline::Single));
                     }
 
@@ -22,6 +22,43 @@ bitflags! {
         const SCANNED_IMPLICIT_HYPERLINKS = 1<<2;
         /// true if we found implicit hyperlinks in the last scan
         const HAS_IMPLICIT_HYPERLINKS = 1<<3;
+        /// The line is rendered at double width (DECDWL)
+        const DOUBLE_WIDTH = 1<<4;
+        /// The line is the top half of a double height line (DECDHL)
+        const DOUBLE_HEIGHT_TOP = 1<<5;
+        /// The line is the bottom half of a double height line (DECDHL)
+        const DOUBLE_HEIGHT_BOTTOM = 1<<6;
+        const LINE_SIZE = Self::DOUBLE_WIDTH.bits
+            | Self::DOUBLE_HEIGHT_TOP.bits
+            | Self::DOUBLE_HEIGHT_BOTTOM.bits;
+    }
+}
+
+/// The size at which the cells of a line are rendered.
+/// Double height lines are also double width; the same text is
+/// expected to be present on both halves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineSize {
+    /// DECSWL - Single-width, single-height
+    Normal,
+    /// DECDWL - Double-width, single-height
+    DoubleWidth,
+    /// DECDHL - Top half of a double-width, double-height line
+    DoubleHeightTop,
+    /// DECDHL - Bottom half of a double-width, double-height line
+    DoubleHeightBottom,
+}
+
+impl Default for LineSize {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+impl LineSize {
+    /// Returns true if each cell occupies two columns of the display
+    pub fn is_double_width(self) -> bool {
+        self != LineSize::Normal
     }
 }
 
@@ -218,6 +255,31 @@ impl Line {
         }
     }
 
+    /// Returns the size at which the line is rendered
+    pub fn line_size(&self) -> LineSize {
+        if self.bits.contains(LineBits::DOUBLE_WIDTH) {
+            LineSize::DoubleWidth
+        } else if self.bits.contains(LineBits::DOUBLE_HEIGHT_TOP) {
+            LineSize::DoubleHeightTop
+        } else if self.bits.contains(LineBits::DOUBLE_HEIGHT_BOTTOM) {
+            LineSize::DoubleHeightBottom
+        } else {
+            LineSize::Normal
+        }
+    }
+
+    /// Changes the size at which the line is rendered
+    pub fn set_line_size(&mut self, size: LineSize) {
+        self.bits &= !LineBits::LINE_SIZE;
+        self.bits |= match size {
+            LineSize::Normal => LineBits::NONE,
+            LineSize::DoubleWidth => LineBits::DOUBLE_WIDTH,
+            LineSize::DoubleHeightTop => LineBits::DOUBLE_HEIGHT_TOP,
+            LineSize::DoubleHeightBottom => LineBits::DOUBLE_HEIGHT_BOTTOM,
+        };
+        self.bits |= LineBits::DIRTY;
+    }
+
     /// Returns true if the line contains a hyperlink
     #[inline]
     pub fn has_hyperlink(&self) -> bool {
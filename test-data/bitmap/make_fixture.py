@@ -0,0 +1,132 @@
+#!/usr/bin/env python3
+"""Generates a tiny bitmap-only TrueType font, for use by the embedded
+bitmap strike tests in wezterm-font.
+
+The font has no glyf or CFF outlines; its glyphs are only available
+as monochrome bitmaps in the EBLC/EBDT tables, with a 10 pixel strike
+and a 16 pixel strike.  `A` maps to a glyph that is drawn as a hollow
+box whose size differs between the two strikes.
+
+This uses the shared helpers in ../sfnt.py.
+
+Usage: python3 make_fixture.py
+"""
+import os
+import struct
+import sys
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+sys.path.insert(0, os.path.join(HERE, ".."))
+
+from sfnt import (  # noqa: E402
+    UNITS_PER_EM,
+    build_sfnt,
+    cmap_table,
+    head_table,
+    hhea_table,
+    name_table,
+    post_table,
+)
+
+ADVANCE = 500
+NUM_GLYPHS = 2
+
+# ppem, ascender, descender, and the (width, height) of the glyph
+# bitmaps; the advance is ADVANCE scaled to the ppem
+STRIKES = [
+    (10, 8, -2, (5, 8)),
+    (16, 13, -3, (8, 12)),
+]
+
+
+def hollow_box(width, height):
+    """Returns the rows of a byte aligned 1 bit per pixel bitmap"""
+    stride = (width + 7) // 8
+    rows = []
+    for y in range(height):
+        bits = 0
+        for x in range(width):
+            if y in (0, height - 1) or x in (0, width - 1):
+                bits |= 1 << (stride * 8 - 1 - x)
+        rows.append(bits.to_bytes(stride, "big"))
+    return b"".join(rows)
+
+
+def line_metrics(ascender, descender, width_max):
+    return struct.pack(
+        ">bbBbbbbbbbbb",
+        ascender,
+        descender,
+        width_max,
+        1,  # caretSlopeNumerator
+        0,  # caretSlopeDenominator
+        0,  # caretOffset
+        0,  # minOriginSB
+        0,  # minAdvanceSB
+        ascender,  # maxBeforeBL
+        descender,  # minAfterBL
+        0,
+        0,
+    )
+
+
+def bitmap_tables():
+    ebdt = struct.pack(">I", 0x00020000)
+    eblc_sizes = b""
+    eblc_subtables = b""
+    first_subtable = 8 + 48 * len(STRIKES)
+    for ppem, ascender, descender, (width, height) in STRIKES:
+        advance = ADVANCE * ppem // UNITS_PER_EM
+        image_offset = len(ebdt)
+        offsets = []
+        for _glyph in range(NUM_GLYPHS):
+            offsets.append(len(ebdt) - image_offset)
+            # Format 1: small metrics and byte aligned rows
+            ebdt += struct.pack(">BBbbB", height, width, 0, height, advance)
+            ebdt += hollow_box(width, height)
+        offsets.append(len(ebdt) - image_offset)
+
+        # A single IndexSubTableArray entry covering every glyph,
+        # followed by its format 1 IndexSubTable
+        subtable = struct.pack(">HHI", 0, NUM_GLYPHS - 1, 8)
+        subtable += struct.pack(">HHI", 1, 1, image_offset)
+        subtable += b"".join(struct.pack(">I", o) for o in offsets)
+
+        eblc_sizes += struct.pack(
+            ">IIII", first_subtable + len(eblc_subtables), len(subtable), 1, 0
+        )
+        eblc_sizes += line_metrics(ascender, descender, width)
+        eblc_sizes += line_metrics(ascender, descender, width)
+        eblc_sizes += struct.pack(">HHBBBb", 0, NUM_GLYPHS - 1, ppem, ppem, 1, 1)
+        eblc_subtables += subtable
+
+    eblc = struct.pack(">II", 0x00020000, len(STRIKES)) + eblc_sizes + eblc_subtables
+    return eblc, ebdt
+
+
+def font_tables():
+    ascender = 800
+    descender = -200
+    eblc, ebdt = bitmap_tables()
+    return [
+        # Maps only `A` to glyph 1
+        (b"cmap", cmap_table([(0x41, 0x41, 1), (0xFFFF, 0xFFFF, 0)])),
+        (b"head", head_table((0, descender, ADVANCE, ascender))),
+        (b"hhea", hhea_table(ascender, descender, ADVANCE, 0, 0, ADVANCE, NUM_GLYPHS)),
+        (b"hmtx", struct.pack(">Hh", ADVANCE, 0) * NUM_GLYPHS),
+        # Version 0.5, as there are no TrueType outlines
+        (b"maxp", struct.pack(">IH", 0x00005000, NUM_GLYPHS)),
+        (b"name", name_table("Bitmap Test")),
+        (b"post", post_table()),
+        (b"EBDT", ebdt),
+        (b"EBLC", eblc),
+    ]
+
+
+def main():
+    with open(os.path.join(HERE, "BitmapTest-Regular.ttf"), "wb") as f:
+        f.write(build_sfnt(font_tables()))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,88 @@
+#!/usr/bin/env python3
+"""Generates a tiny TrueType font whose digits are wider than its
+letters, for use by the cell width tests in wezterm-font.
+
+`M` has an advance of 500 units and the digits `0`-`9` have an advance
+of 700 units, while the OS/2 xAvgCharWidth is 550 units, so that each
+way of choosing the cell width produces a different result.
+
+This uses the shared helpers in ../sfnt.py.
+
+Usage: python3 make_fixture.py
+"""
+import os
+import struct
+import sys
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+sys.path.insert(0, os.path.join(HERE, ".."))
+
+from sfnt import (  # noqa: E402
+    bbox,
+    build_sfnt,
+    cmap_table,
+    glyf_loca,
+    head_table,
+    hhea_table,
+    maxp_table,
+    name_table,
+    os2_table,
+    post_table,
+    rect,
+    simple_glyph,
+)
+
+LETTER_ADVANCE = 500
+DIGIT_ADVANCE = 700
+AVG_CHAR_WIDTH = 550
+ASCENDER = 800
+DESCENDER = -200
+
+
+NOTDEF = rect(50, 0, 450, 700)
+LETTER = rect(50, 0, 450, 700)
+DIGIT = rect(50, 0, 650, 700)
+# glyph 1 is `M`, glyphs 2-11 are the digits
+OUTLINES = [NOTDEF, LETTER] + [DIGIT] * 10
+ADVANCES = [LETTER_ADVANCE, LETTER_ADVANCE] + [DIGIT_ADVANCE] * 10
+NUM_GLYPHS = len(OUTLINES)
+
+
+def font_tables():
+    glyf, loca = glyf_loca([simple_glyph(contours) for contours in OUTLINES])
+    all_bbox = bbox([c for contours in OUTLINES for c in contours])
+    hmtx = b"".join(struct.pack(">Hh", advance, bbox(c)[0]) for advance, c in zip(ADVANCES, OUTLINES))
+    return [
+        # Maps the digits to glyphs 2-11 and `M` to glyph 1
+        (b"cmap", cmap_table([(0x30, 0x39, 2), (0x4D, 0x4D, 1), (0xFFFF, 0xFFFF, 0)])),
+        (b"head", head_table(all_bbox)),
+        (
+            b"hhea",
+            hhea_table(
+                ASCENDER,
+                DESCENDER,
+                DIGIT_ADVANCE,
+                all_bbox[0],
+                LETTER_ADVANCE - LETTER[0][2][0],
+                all_bbox[2],
+                NUM_GLYPHS,
+            ),
+        ),
+        (b"hmtx", hmtx),
+        (b"maxp", maxp_table(NUM_GLYPHS)),
+        (b"name", name_table("Cell Width Test")),
+        # A panose proportion of 3 means that this is not monospaced
+        (b"OS/2", os2_table(AVG_CHAR_WIDTH, 0x30, 0x4D, ASCENDER, DESCENDER, proportion=3)),
+        (b"post", post_table(fixed_pitch=False)),
+        (b"glyf", glyf),
+        (b"loca", loca),
+    ]
+
+
+def main():
+    with open(os.path.join(HERE, "CellWidthTest-Regular.ttf"), "wb") as f:
+        f.write(build_sfnt(font_tables()))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,106 @@
+#!/usr/bin/env python3
+"""Generates a tiny TrueType font with COLRv0 color glyphs, for use
+by the color layer rasterization tests in wezterm-font.
+
+`A` and `B` map to glyphs whose outline is a plain square, which is
+what is drawn when the COLR table is ignored.  The COLR table replaces
+`A` with a red layer covering the left half of the square and a blue
+layer covering the right half.  `B` has the same red left half, while
+its right half is drawn in the color of the text.
+
+This uses the shared helpers in ../sfnt.py.
+
+Usage: python3 make_fixture.py
+"""
+import os
+import struct
+import sys
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+sys.path.insert(0, os.path.join(HERE, ".."))
+
+from sfnt import (  # noqa: E402
+    bbox,
+    build_sfnt,
+    cmap_table,
+    glyf_loca,
+    head_table,
+    hhea_table,
+    maxp_table,
+    name_table,
+    os2_table,
+    post_table,
+    rect,
+    simple_glyph,
+)
+
+ADVANCE = 600
+
+NOTDEF = rect(50, 0, 550, 700)
+SQUARE = rect(50, 0, 550, 700)
+LEFT = rect(50, 0, 300, 700)
+RIGHT = rect(300, 0, 550, 700)
+OUTLINES = [NOTDEF, SQUARE, LEFT, RIGHT, SQUARE]
+
+# CPAL colors as (blue, green, red, alpha)
+PALETTE = [(0, 0, 0xFF, 0xFF), (0xFF, 0, 0, 0xFF)]
+# The palette index that selects the color of the text
+FOREGROUND = 0xFFFF
+# The layers of each color glyph, as (glyph, palette index)
+COLOR_GLYPHS = [
+    (1, [(2, 0), (3, 1)]),
+    (4, [(2, 0), (3, FOREGROUND)]),
+]
+
+
+def colr_table():
+    base_offset = 14
+    layer_offset = base_offset + 6 * len(COLOR_GLYPHS)
+    num_layers = sum(len(layers) for _, layers in COLOR_GLYPHS)
+    header = struct.pack(">HHIIH", 0, len(COLOR_GLYPHS), base_offset, layer_offset, num_layers)
+    base = b""
+    layers = b""
+    first_layer = 0
+    for glyph, glyph_layers in COLOR_GLYPHS:
+        base += struct.pack(">3H", glyph, first_layer, len(glyph_layers))
+        layers += b"".join(struct.pack(">2H", layer, color) for layer, color in glyph_layers)
+        first_layer += len(glyph_layers)
+    return header + base + layers
+
+
+def cpal_table():
+    header = struct.pack(">4HI", 0, len(PALETTE), 1, len(PALETTE), 14)
+    indices = struct.pack(">H", 0)
+    colors = b"".join(bytes(c) for c in PALETTE)
+    return header + indices + colors
+
+
+def font_tables():
+    glyf, loca = glyf_loca([simple_glyph(contours) for contours in OUTLINES])
+    all_bbox = bbox([c for contours in OUTLINES for c in contours])
+    return [
+        (b"cmap", cmap_table([(0x41, 0x41, 1), (0x42, 0x42, 4), (0xFFFF, 0xFFFF, 0)])),
+        (b"head", head_table(all_bbox)),
+        (
+            b"hhea",
+            hhea_table(800, -200, ADVANCE, all_bbox[0], ADVANCE - all_bbox[2], all_bbox[2], len(OUTLINES)),
+        ),
+        (b"hmtx", b"".join(struct.pack(">Hh", ADVANCE, bbox(c)[0]) for c in OUTLINES)),
+        (b"maxp", maxp_table(len(OUTLINES))),
+        (b"name", name_table("Colr Test")),
+        (b"OS/2", os2_table(ADVANCE, 0x41, 0x42, 800, -200)),
+        (b"post", post_table()),
+        (b"glyf", glyf),
+        (b"loca", loca),
+        (b"COLR", colr_table()),
+        (b"CPAL", cpal_table()),
+    ]
+
+
+def main():
+    with open(os.path.join(HERE, "ColrTest-Regular.ttf"), "wb") as f:
+        f.write(build_sfnt(font_tables()))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,225 @@
+"""Helpers shared by the scripts that generate the tiny TrueType fonts
+used as fixtures by the wezterm-font tests.
+
+Each script describes its glyphs and any special tables, and uses these
+helpers to produce the common tables and to assemble the font.
+"""
+import struct
+
+UNITS_PER_EM = 1000
+
+
+def be16(v):
+    return struct.pack(">H", v & 0xFFFF)
+
+
+def checksum(data):
+    data = data + b"\0" * (-len(data) % 4)
+    return sum(struct.unpack(">%dI" % (len(data) // 4), data)) & 0xFFFFFFFF
+
+
+def bbox(contours):
+    xs = [p[0] for c in contours for p in c]
+    ys = [p[1] for c in contours for p in c]
+    return min(xs), min(ys), max(xs), max(ys)
+
+
+def rect(x0, y0, x1, y1):
+    """A single rectangular contour"""
+    return [[(x0, y0, True), (x0, y1, True), (x1, y1, True), (x1, y0, True)]]
+
+
+def simple_glyph(contours, instructions=b""):
+    """Encodes a glyf table entry for the contours, each of which is
+    a list of (x, y, on_curve)"""
+    out = struct.pack(">h4h", len(contours), *bbox(contours))
+    end = -1
+    for c in contours:
+        end += len(c)
+        out += be16(end)
+    out += be16(len(instructions)) + instructions
+    flags = b""
+    xs = b""
+    ys = b""
+    lx = ly = 0
+    for c in contours:
+        for x, y, on in c:
+            # Uses 16-bit coordinates throughout, for simplicity
+            flags += bytes([1 if on else 0])
+            xs += struct.pack(">h", x - lx)
+            ys += struct.pack(">h", y - ly)
+            lx, ly = x, y
+    return out + flags + xs + ys
+
+
+def glyf_loca(glyphs):
+    """Builds the glyf and short loca tables from encoded glyphs"""
+    glyf = b""
+    loca = b""
+    for g in glyphs:
+        loca += be16(len(glyf) // 2)
+        glyf += g + b"\0" * (-len(g) % 4)
+    loca += be16(len(glyf) // 2)
+    return glyf, loca
+
+
+def name_table(family, extra=None):
+    """Builds a name table for the Regular face of `family`, along
+    with any `extra` names, keyed by name id"""
+    names = {
+        1: family,
+        2: "Regular",
+        3: "%s Regular;1.0" % family,
+        4: "%s Regular" % family,
+        6: "%s-Regular" % family.replace(" ", ""),
+    }
+    names.update(extra or {})
+    records = []
+    strings = b""
+    for platform, encoding, language, codec in [(1, 0, 0, "mac_roman"), (3, 1, 0x409, "utf-16-be")]:
+        for name_id, value in sorted(names.items()):
+            data = value.encode(codec)
+            records.append(struct.pack(">6H", platform, encoding, language, name_id, len(data), len(strings)))
+            strings += data
+    header = struct.pack(">3H", 0, len(records), 6 + 12 * len(records))
+    return header + b"".join(records) + strings
+
+
+def cmap_table(segments):
+    """Builds a format 4 cmap from (start, end, first glyph) segments,
+    which must be sorted and must end with the 0xFFFF segment"""
+    seg_count = len(segments)
+    entry_selector = seg_count.bit_length() - 1
+    search_range = 2 << entry_selector
+    sub = struct.pack(
+        ">7H",
+        4,
+        16 + 8 * seg_count,
+        0,
+        seg_count * 2,
+        search_range,
+        entry_selector,
+        seg_count * 2 - search_range,
+    )
+    sub += b"".join(be16(end) for _, end, _ in segments)  # endCode
+    sub += be16(0)  # reservedPad
+    sub += b"".join(be16(start) for start, _, _ in segments)  # startCode
+    sub += b"".join(be16(glyph - start) for start, _, glyph in segments)  # idDelta
+    sub += b"".join(be16(0) for _ in segments)  # idRangeOffset
+    return struct.pack(">HHHHI", 0, 1, 3, 1, 12) + sub
+
+
+def os2_table(
+    avg_char_width, first_char, last_char, ascender, descender, proportion=9, unicode_ranges=(1, 0, 0, 0)
+):
+    """Builds a version 4 OS/2 table; `proportion` is the panose
+    proportion, where 9 means monospaced, and `unicode_ranges` are
+    the four ulUnicodeRange words, which default to Basic Latin"""
+    t = struct.pack(
+        ">hhHHHhhhhhhhhhhh",
+        4,  # version
+        avg_char_width,  # xAvgCharWidth
+        400,  # usWeightClass
+        5,  # usWidthClass
+        0,  # fsType
+        650, 600, 0, 75, 650, 600, 0, 350,  # sub/superscript
+        50, 250,  # strikeout
+        0,  # sFamilyClass
+    )
+    t += bytes([2, 0, 5, proportion, 0, 0, 0, 0, 0, 0])  # panose
+    t += struct.pack(">4I", *unicode_ranges)  # ulUnicodeRange
+    t += b"NONE"
+    t += struct.pack(">HHH", 0x40, first_char, last_char)  # fsSelection REGULAR
+    t += struct.pack(">hhhHH", ascender, descender, 0, ascender, -descender)
+    t += struct.pack(">2I", 1, 0)  # ulCodePageRange
+    t += struct.pack(">hhHHH", 500, 700, 0, 32, 1)
+    assert len(t) == 96
+    return t
+
+
+def head_table(font_bbox):
+    return struct.pack(
+        ">IIIIHHQQhhhhHHhhh",
+        0x00010000,  # version
+        0x00010000,  # fontRevision
+        0,  # checksumAdjustment, fixed up by build_sfnt
+        0x5F0F3CF5,  # magic
+        0x000B,  # flags
+        UNITS_PER_EM,
+        0,  # created
+        0,  # modified
+        *font_bbox,
+        0,  # macStyle
+        8,  # lowestRecPPEM
+        2,  # fontDirectionHint
+        0,  # indexToLocFormat: short
+        0,  # glyphDataFormat
+    )
+
+
+def hhea_table(ascender, descender, advance_max, min_lsb, min_rsb, x_max_extent, num_hmetrics):
+    return struct.pack(
+        ">Ihhh H hhh hhh hhhh hH",
+        0x00010000,
+        ascender,
+        descender,
+        0,  # lineGap
+        advance_max,
+        min_lsb,
+        min_rsb,
+        x_max_extent,
+        1,  # caretSlopeRise
+        0,  # caretSlopeRun
+        0,  # caretOffset
+        0, 0, 0, 0,
+        0,  # metricDataFormat
+        num_hmetrics,
+    )
+
+
+def maxp_table(num_glyphs, max_points=4, max_contours=1, max_instructions=0, max_components=0):
+    """Builds a version 1.0 maxp table; glyphs with components may
+    only refer to simple glyphs"""
+    return struct.pack(
+        ">I14H",
+        0x00010000,
+        num_glyphs,
+        max_points,
+        max_contours,
+        max_points if max_components else 0,  # maxCompositePoints
+        max_contours if max_components else 0,  # maxCompositeContours
+        2,  # maxZones
+        0,  # maxTwilightPoints
+        0,  # maxStorage
+        0,  # maxFunctionDefs
+        0,  # maxInstructionDefs
+        0,  # maxStackElements
+        max_instructions,  # maxSizeOfInstructions
+        max_components,  # maxComponentElements
+        1 if max_components else 0,  # maxComponentDepth
+    )
+
+
+def post_table(fixed_pitch=True):
+    return struct.pack(">IIhhIIIII", 0x00030000, 0, -100, 50, 1 if fixed_pitch else 0, 0, 0, 0, 0)
+
+
+def build_sfnt(tables):
+    """Assembles (tag, data) tables into a TrueType font, fixing up
+    the checksumAdjustment in the head table"""
+    tables = sorted(tables)
+    num = len(tables)
+    selector = num.bit_length() - 1
+    search = (1 << selector) * 16
+    out = struct.pack(">IHHHH", 0x00010000, num, search, selector, num * 16 - search)
+    offset = 12 + 16 * num
+    data = b""
+    for tag, t in tables:
+        out += tag + struct.pack(">III", checksum(t), offset + len(data), len(t))
+        data += t + b"\0" * (-len(t) % 4)
+    font = bytearray(out + data)
+    head_offset = 12 + 16 * num + sum(
+        len(t) + (-len(t) % 4) for tag, t in tables[: [tag for tag, _ in tables].index(b"head")]
+    )
+    struct.pack_into(">I", font, head_offset + 8, (0xB1B0AFBA - checksum(bytes(font))) & 0xFFFFFFFF)
+    return bytes(font)
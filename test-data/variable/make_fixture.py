@@ -0,0 +1,193 @@
+#!/usr/bin/env python3
+"""Generates a tiny variable TrueType font, for use by the variable
+font tests in wezterm-font.
+
+The font has a single `wght` axis ranging from 100 to 900, with the
+default instance at 400, and named Thin, Regular and Black instances.
+`A` maps to a glyph that is a single vertical stem; its gvar deltas
+make the stem 20 units wide at the Thin end of the axis, 100 units at
+the default and 300 units at the Black end.  The advance shrinks to
+500 units when Thin and grows to 800 units when Black, both through
+the gvar phantom points and the HVAR table, and the MVAR table raises
+the ascender by 100 units when Black.
+
+The avar table maps the normalized coordinate 0.5 (wght 650) to 0.75,
+so that the non-linear mapping can be observed.
+
+This uses the shared helpers in ../sfnt.py.
+
+Usage: python3 make_fixture.py
+"""
+import os
+import struct
+import sys
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+sys.path.insert(0, os.path.join(HERE, ".."))
+
+from sfnt import (  # noqa: E402
+    bbox,
+    be16,
+    build_sfnt,
+    cmap_table,
+    glyf_loca,
+    head_table,
+    hhea_table,
+    maxp_table,
+    name_table,
+    post_table,
+    rect,
+    simple_glyph,
+)
+
+ADVANCE = 600
+ASCENDER = 800
+DESCENDER = -200
+
+
+NOTDEF = rect(50, 0, 550, 700)
+STEM = rect(250, 0, 350, 700)
+OUTLINES = [NOTDEF, STEM]
+NUM_GLYPHS = len(OUTLINES)
+
+# (tag, min, default, max, name id)
+AXES = [(b"wght", 100, 400, 900, 256)]
+# (name id, subfamily, wght)
+INSTANCES = [(257, "Thin", 100), (258, "Regular", 400), (259, "Black", 900)]
+
+# The peak of each region, along with the x deltas of the four points
+# of the stem followed by the four phantom points; the second phantom
+# point carries the advance
+STEM_DELTAS = [
+    (1.0, [-100, -100, 100, 100, 0, 200, 0, 0]),
+    (-1.0, [40, 40, -40, -40, 0, -100, 0, 0]),
+]
+# The HVAR advance deltas for each glyph, in each of the regions above
+ADVANCE_DELTAS = [(0, 0), (200, -100)]
+# The MVAR deltas for each metric, in each of the regions above
+METRIC_DELTAS = [(b"hasc", (100, 0))]
+
+
+def fixed(v):
+    return struct.pack(">i", int(v * 65536))
+
+
+def f2dot14(v):
+    return struct.pack(">h", int(round(v * 16384)))
+
+
+def fvar_table():
+    axis_size = 20
+    instance_size = 4 + 4 * len(AXES)
+    out = struct.pack(">6H", 1, 0, 16, 2, len(AXES), axis_size)
+    out += struct.pack(">2H", len(INSTANCES), instance_size)
+    for tag, lo, default, hi, name_id in AXES:
+        out += tag + fixed(lo) + fixed(default) + fixed(hi) + struct.pack(">2H", 0, name_id)
+    for name_id, _, wght in INSTANCES:
+        out += struct.pack(">2H", name_id, 0) + fixed(wght)
+    return out
+
+
+def avar_table():
+    maps = [(-1.0, -1.0), (0.0, 0.0), (0.5, 0.75), (1.0, 1.0)]
+    out = struct.pack(">4H", 1, 0, 0, len(AXES))
+    out += be16(len(maps))
+    for src, dst in maps:
+        out += f2dot14(src) + f2dot14(dst)
+    return out
+
+
+def packed_words(values):
+    # A single run of 16-bit deltas
+    return bytes([0x40 | (len(values) - 1)]) + b"".join(struct.pack(">h", v) for v in values)
+
+
+def gvar_table():
+    # Only the stem varies; every point is listed in each tuple
+    tuples = []
+    data = b""
+    for peak, xs in STEM_DELTAS:
+        serialized = b"\0" + packed_words(xs) + packed_words([0] * len(xs))
+        # EMBEDDED_PEAK_TUPLE | PRIVATE_POINT_NUMBERS
+        tuples.append(struct.pack(">2H", len(serialized), 0x8000 | 0x2000) + f2dot14(peak))
+        data += serialized
+    headers = b"".join(tuples)
+    stem = struct.pack(">2H", len(STEM_DELTAS), 4 + len(headers)) + headers + data
+    stem += b"\0" * (-len(stem) % 2)
+
+    offsets = [0, 0, len(stem)]
+    header_size = 20
+    array_offset = header_size + 2 * len(offsets)
+    out = struct.pack(">4HI2HI", 1, 0, len(AXES), 0, array_offset, NUM_GLYPHS, 0, array_offset)
+    out += b"".join(be16(o // 2) for o in offsets)
+    return out + stem
+
+
+def item_variation_store(deltas):
+    """Builds an ItemVariationStore with one region for each entry in
+    STEM_DELTAS and a single ItemVariationData holding `deltas`, a list
+    of items that each have a 16-bit delta for every region"""
+    regions = len(STEM_DELTAS)
+    region_list = struct.pack(">2H", len(AXES), regions)
+    for peak, _ in STEM_DELTAS:
+        start, end = (0.0, peak) if peak > 0 else (peak, 0.0)
+        region_list += f2dot14(start) + f2dot14(peak) + f2dot14(end)
+    data = struct.pack(">3H", len(deltas), regions, regions)
+    data += b"".join(be16(i) for i in range(regions))
+    for item in deltas:
+        data += b"".join(struct.pack(">h", d) for d in item)
+    header_size = 8 + 4
+    out = struct.pack(">HIHI", 1, header_size, 1, header_size + len(region_list))
+    return out + region_list + data
+
+
+def hvar_table():
+    # Without an advance width mapping, the glyph id is the inner index
+    return struct.pack(">2H4I", 1, 0, 20, 0, 0, 0) + item_variation_store(ADVANCE_DELTAS)
+
+
+def mvar_table():
+    record_size = 8
+    header_size = 12
+    store_offset = header_size + record_size * len(METRIC_DELTAS)
+    out = struct.pack(">6H", 1, 0, 0, record_size, len(METRIC_DELTAS), store_offset)
+    for idx, (tag, _) in enumerate(METRIC_DELTAS):
+        out += tag + struct.pack(">2H", 0, idx)
+    return out + item_variation_store([d for _, d in METRIC_DELTAS])
+
+
+def font_tables():
+    glyf, loca = glyf_loca([simple_glyph(contours) for contours in OUTLINES])
+    all_bbox = bbox([c for contours in OUTLINES for c in contours])
+    names = {256: "Weight"}
+    for name_id, subfamily, _ in INSTANCES:
+        names[name_id] = subfamily
+    return [
+        # Maps only `A` to glyph 1
+        (b"cmap", cmap_table([(0x41, 0x41, 1), (0xFFFF, 0xFFFF, 0)])),
+        (b"head", head_table(all_bbox)),
+        (
+            b"hhea",
+            hhea_table(ASCENDER, DESCENDER, ADVANCE, all_bbox[0], ADVANCE - all_bbox[2], all_bbox[2], NUM_GLYPHS),
+        ),
+        (b"hmtx", b"".join(struct.pack(">Hh", ADVANCE, bbox(c)[0]) for c in OUTLINES)),
+        (b"maxp", maxp_table(NUM_GLYPHS)),
+        (b"name", name_table("Variable Test", names)),
+        (b"post", post_table()),
+        (b"glyf", glyf),
+        (b"loca", loca),
+        (b"fvar", fvar_table()),
+        (b"avar", avar_table()),
+        (b"gvar", gvar_table()),
+        (b"HVAR", hvar_table()),
+        (b"MVAR", mvar_table()),
+    ]
+
+
+def main():
+    with open(os.path.join(HERE, "VariableTest-Regular.ttf"), "wb") as f:
+        f.write(build_sfnt(font_tables()))
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,306 @@
+#!/usr/bin/env python3
+"""Generates a tiny TrueType font along with equivalent WOFF and WOFF2
+encodings of it, for use by the WOFF decoding tests in wezterm-font.
+
+`A` maps to a simple glyph with two contours and `B` to a composite
+glyph that shifts `A` to the right.  WoffTest-Regular.woff2 stores
+every table untransformed, while WoffTest-Transformed.woff2 applies
+the glyf, loca and hmtx transforms in the same way as the reference
+WOFF2 encoder.  Since brotli may not be available, the brotli stream
+is made up of uncompressed meta-blocks, which is valid, if not very
+compact.
+
+Usage: python3 make_fixtures.py
+"""
+import os
+import struct
+import sys
+import zlib
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+sys.path.insert(0, os.path.join(HERE, ".."))
+
+from sfnt import (  # noqa: E402
+    bbox,
+    be16,
+    build_sfnt,
+    checksum,
+    cmap_table,
+    glyf_loca,
+    head_table,
+    hhea_table,
+    maxp_table,
+    name_table,
+    os2_table,
+    post_table,
+    simple_glyph,
+)
+
+ADVANCE = 600
+
+NOTDEF = [[(50, 0, True), (50, 700, True), (550, 700, True), (550, 0, True)]]
+GLYPH_A = [
+    [
+        (0, 0, True),
+        (0, 700, True),
+        (600, 700, True),
+        (640, 740, False),
+        (1000, 1300, True),
+        (3000, -1000, True),
+        (-2000, 0, True),
+        (-1990, -5, False),
+    ],
+    [(200, 100, True), (300, 400, True), (400, 100, True)],
+]
+A_INSTRUCTIONS = bytes([0x00, 0x01])  # SVTCA[y], SVTCA[x]
+
+# Glyph 2 is a composite that shifts glyph 1 to the right
+COMPOSITE_DX = 50
+COMPOSITE_FLAGS = 0x0001 | 0x0002 | 0x0008  # words, xy values, scale
+COMPONENTS = struct.pack(">HHhhH", COMPOSITE_FLAGS, 1, COMPOSITE_DX, 0, 0x4000)
+A_BBOX = bbox(GLYPH_A)
+COMPOSITE_BBOX = (A_BBOX[0] + COMPOSITE_DX, A_BBOX[1], A_BBOX[2] + COMPOSITE_DX, A_BBOX[3])
+
+GLYPHS = [
+    simple_glyph(NOTDEF),
+    simple_glyph(GLYPH_A, A_INSTRUCTIONS),
+    struct.pack(">h4h", -1, *COMPOSITE_BBOX) + COMPONENTS,
+]
+X_MINS = [bbox(NOTDEF)[0], A_BBOX[0], COMPOSITE_BBOX[0]]
+
+
+def font_tables():
+    glyf, loca = glyf_loca(GLYPHS)
+    all_bbox = bbox(NOTDEF + GLYPH_A + [[(p[0] + COMPOSITE_DX, p[1], p[2]) for p in c] for c in GLYPH_A])
+    max_points = sum(len(c) for c in GLYPH_A)
+    return [
+        (b"cmap", cmap_table([(0x41, 0x42, 1), (0xFFFF, 0xFFFF, 0)])),
+        (b"head", head_table(all_bbox)),
+        (
+            b"hhea",
+            hhea_table(800, -200, ADVANCE, all_bbox[0], ADVANCE - all_bbox[2], all_bbox[2], len(GLYPHS)),
+        ),
+        (b"hmtx", b"".join(struct.pack(">Hh", ADVANCE, x_min) for x_min in X_MINS)),
+        (
+            b"maxp",
+            maxp_table(
+                len(GLYPHS),
+                max_points=max_points,
+                max_contours=len(GLYPH_A),
+                max_instructions=len(A_INSTRUCTIONS),
+                max_components=1,
+            ),
+        ),
+        (b"name", name_table("Woff Test")),
+        (b"OS/2", os2_table(ADVANCE, 0x41, 0x42, 800, -200)),
+        (b"post", post_table()),
+        (b"glyf", glyf),
+        (b"loca", loca),
+    ]
+
+
+def woff(sfnt_tables, sfnt):
+    tables = sorted(sfnt_tables)
+    header_size = 44 + 20 * len(tables)
+    directory = b""
+    data = b""
+    for tag, t in tables:
+        compressed = zlib.compress(t, 9)
+        if len(compressed) >= len(t):
+            compressed = t
+        directory += tag + struct.pack(
+            ">IIII", header_size + len(data), len(compressed), len(t), checksum(t)
+        )
+        data += compressed + b"\0" * (-len(compressed) % 4)
+    length = header_size + len(data)
+    header = struct.pack(
+        ">IIIHHIHHIIIII", 0x774F4646, 0x00010000, length, len(tables), 0, len(sfnt), 1, 0, 0, 0, 0, 0, 0
+    )
+    return header + directory + data
+
+
+def base128(v):
+    out = [v & 0x7F]
+    v >>= 7
+    while v:
+        out.append(0x80 | (v & 0x7F))
+        v >>= 7
+    return bytes(reversed(out))
+
+
+def u255(v):
+    if v < 253:
+        return bytes([v])
+    if v < 253 * 2:
+        return bytes([255, v - 253])
+    if v < 253 * 3:
+        return bytes([254, v - 253 * 2])
+    return bytes([253]) + be16(v)
+
+
+def triplet(dx, dy, on_curve):
+    """Encode a point delta as in the reference WOFF2 encoder"""
+    on = 0 if on_curve else 128
+    ax, ay = abs(dx), abs(dy)
+    xs = 0 if dx < 0 else 1
+    ys = 0 if dy < 0 else 1
+    xy = xs + 2 * ys
+    if dx == 0 and ay < 1280:
+        return on + ((ay & 0xF00) >> 7) + ys, bytes([ay & 0xFF])
+    if dy == 0 and ax < 1280:
+        return on + 10 + ((ax & 0xF00) >> 7) + xs, bytes([ax & 0xFF])
+    if ax < 65 and ay < 65:
+        flag = on + 20 + ((ax - 1) & 0x30) + (((ay - 1) & 0x30) >> 2) + xy
+        return flag, bytes([(((ax - 1) & 0xF) << 4) | ((ay - 1) & 0xF)])
+    if ax < 769 and ay < 769:
+        flag = on + 84 + 12 * (((ax - 1) & 0x300) >> 8) + (((ay - 1) & 0x300) >> 6) + xy
+        return flag, bytes([(ax - 1) & 0xFF, (ay - 1) & 0xFF])
+    if ax < 4096 and ay < 4096:
+        return on + 120 + xy, bytes([ax >> 4, ((ax & 0xF) << 4) | (ay >> 8), ay & 0xFF])
+    return on + 124 + xy, bytes([ax >> 8, ax & 0xFF, ay >> 8, ay & 0xFF])
+
+
+def transformed_glyf():
+    n_contours = b""
+    n_points = b""
+    flags = b""
+    glyphs = b""
+    composites = b""
+    bbox_bitmap = bytearray(((len(GLYPHS) + 31) // 32) * 4)
+    bboxes = b""
+    instructions = b""
+
+    def add_simple(contours, instr=b""):
+        nonlocal n_contours, n_points, flags, glyphs, instructions
+        n_contours += struct.pack(">h", len(contours))
+        lx = ly = 0
+        for c in contours:
+            n_points += u255(len(c))
+            for x, y, on in c:
+                flag, data = triplet(x - lx, y - ly, on)
+                flags += bytes([flag])
+                glyphs += data
+                lx, ly = x, y
+        glyphs += u255(len(instr))
+        instructions += instr
+
+    add_simple(NOTDEF)
+    add_simple(GLYPH_A, A_INSTRUCTIONS)
+
+    # The composite glyph always has an explicit bounding box
+    n_contours += struct.pack(">h", -1)
+    bbox_bitmap[2 >> 3] |= 0x80 >> (2 & 7)
+    bboxes += struct.pack(">4h", *COMPOSITE_BBOX)
+    composites += COMPONENTS
+
+    bbox_stream = bytes(bbox_bitmap) + bboxes
+    streams = [n_contours, n_points, flags, glyphs, composites, bbox_stream, instructions]
+    header = struct.pack(">HHHH", 0, 0, len(GLYPHS), 0) + b"".join(struct.pack(">I", len(s)) for s in streams)
+    return header + b"".join(streams)
+
+
+def transformed_hmtx():
+    # Flags 3: both lsb arrays are omitted as they match the glyph xMin
+    return bytes([3]) + b"".join(be16(ADVANCE) for _ in range(len(GLYPHS)))
+
+
+def brotli_uncompressed(data):
+    """Produce a brotli stream that stores data in uncompressed meta-blocks"""
+    bits = []
+
+    def put(value, count):
+        for i in range(count):
+            bits.append((value >> i) & 1)
+
+    def flush():
+        while len(bits) % 8:
+            bits.append(0)
+        out = bytearray()
+        for i in range(0, len(bits), 8):
+            out.append(sum(b << j for j, b in enumerate(bits[i : i + 8])))
+        bits.clear()
+        return bytes(out)
+
+    out = b""
+    put(0, 1)  # WBITS = 16
+    pos = 0
+    while pos < len(data):
+        chunk = data[pos : pos + 65536]
+        put(0, 1)  # ISLAST
+        put(0, 2)  # MNIBBLES = 4
+        put(len(chunk) - 1, 16)
+        put(1, 1)  # ISUNCOMPRESSED
+        out += flush() + chunk
+        pos += len(chunk)
+    put(1, 1)  # ISLAST
+    put(1, 1)  # ISLASTEMPTY
+    return out + flush()
+
+
+def woff2(sfnt_tables, sfnt, transform):
+    known = {
+        b"cmap": 0, b"head": 1, b"hhea": 2, b"hmtx": 3, b"maxp": 4,
+        b"name": 5, b"OS/2": 6, b"post": 7, b"glyf": 10, b"loca": 11,
+    }
+    directory = b""
+    stream = b""
+    for tag, t in sfnt_tables:
+        if transform and tag == b"glyf":
+            data = transformed_glyf()
+            directory += bytes([known[tag]]) + base128(len(t)) + base128(len(data))
+        elif transform and tag == b"loca":
+            data = b""
+            directory += bytes([known[tag]]) + base128(len(t)) + base128(0)
+        elif transform and tag == b"hmtx":
+            data = transformed_hmtx()
+            directory += bytes([known[tag] | 0x40]) + base128(len(t)) + base128(len(data))
+        else:
+            # Transform version 3 is the null transform for glyf and loca,
+            # while version 0 is the null transform for everything else
+            version = 3 if tag in (b"glyf", b"loca") else 0
+            data = t
+            directory += bytes([known[tag] | (version << 6)]) + base128(len(t))
+        stream += data
+    compressed = brotli_uncompressed(stream)
+    body = directory + compressed
+    length = 48 + len(body)
+    padding = -length % 4
+    header = struct.pack(
+        ">IIIHHIIHHIIIII",
+        0x774F4632,
+        0x00010000,
+        length + padding,
+        len(sfnt_tables),
+        0,
+        len(sfnt),
+        len(compressed),
+        1,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    )
+    return header + body + b"\0" * padding
+
+
+def main():
+    tables = font_tables()
+    sfnt = build_sfnt(tables)
+    # Use the head table with the fixed up checksumAdjustment
+    # in the WOFF and WOFF2 encodings too
+    adjustment = sfnt[sfnt.index(struct.pack(">I", 0x5F0F3CF5)) - 4 :][:4]
+    tables = [(tag, t[:8] + adjustment + t[12:] if tag == b"head" else t) for tag, t in tables]
+    for name, data in [
+        ("WoffTest-Regular.ttf", sfnt),
+        ("WoffTest-Regular.woff", woff(tables, sfnt)),
+        ("WoffTest-Regular.woff2", woff2(tables, sfnt, transform=False)),
+        ("WoffTest-Transformed.woff2", woff2(tables, sfnt, transform=True)),
+    ]:
+        with open(os.path.join(HERE, name), "wb") as f:
+            f.write(data)
+
+
+if __name__ == "__main__":
+    main()
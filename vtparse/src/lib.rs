@@ -157,6 +157,27 @@ pub trait VTActor {
         byte: u8,
     );
 
+    /// Called in place of `csi_dispatch` when one or more of the parameters
+    /// were separated by a colon rather than a semicolon.  Colons introduce
+    /// sub-parameters, as in `CSI 4:3 m` (curly underline) or
+    /// `CSI 58:2::255:0:0 m` (underline color).
+    ///
+    /// `subparams` has the same length as `params`; `subparams[i]` is true
+    /// when `params[i]` was preceded by a colon, making it a sub-parameter
+    /// of the parameter that precedes it.
+    ///
+    /// The default implementation ignores the sequence, which is how these
+    /// sequences were handled before sub-parameters were recognized.
+    fn csi_dispatch_with_subparams(
+        &mut self,
+        _params: &[i64],
+        _subparams: &[bool],
+        _intermediates: &[u8],
+        _ignored_excess_intermediates: bool,
+        _byte: u8,
+    ) {
+    }
+
     /// Called when an OSC string is terminated by ST, CAN, SUB or ESC.
     ///
     /// `params` is an array of byte strings (which may also be valid utf-8)
@@ -192,6 +213,13 @@ pub enum VTAction {
         ignored_excess_intermediates: bool,
         byte: u8,
     },
+    CsiDispatchWithSubparams {
+        params: Vec<i64>,
+        subparams: Vec<bool>,
+        intermediates: Vec<u8>,
+        ignored_excess_intermediates: bool,
+        byte: u8,
+    },
     OscDispatch(Vec<Vec<u8>>),
 }
 
@@ -281,6 +309,23 @@ impl VTActor for CollectingVTActor {
         });
     }
 
+    fn csi_dispatch_with_subparams(
+        &mut self,
+        params: &[i64],
+        subparams: &[bool],
+        intermediates: &[u8],
+        ignored_excess_intermediates: bool,
+        byte: u8,
+    ) {
+        self.actions.push(VTAction::CsiDispatchWithSubparams {
+            params: params.to_vec(),
+            subparams: subparams.to_vec(),
+            intermediates: intermediates.to_vec(),
+            ignored_excess_intermediates,
+            byte,
+        });
+    }
+
     fn osc_dispatch(&mut self, params: &[&[u8]]) {
         self.actions.push(VTAction::OscDispatch(
             params.iter().map(|i| i.to_vec()).collect(),
@@ -334,6 +379,8 @@ pub struct VTParser {
     osc: OscState,
 
     params: [i64; MAX_PARAMS],
+    /// `subparams[i]` is true if `params[i]` was preceded by a colon
+    subparams: [bool; MAX_PARAMS],
     num_params: usize,
     current_param: Option<i64>,
     params_full: bool,
@@ -364,6 +411,7 @@ impl VTParser {
             },
 
             params,
+            subparams: [false; MAX_PARAMS],
             num_params: 0,
             params_full: false,
             current_param: None,
@@ -392,6 +440,7 @@ impl VTParser {
                 self.osc.num_params = 0;
                 self.osc.full = false;
                 self.num_params = 0;
+                self.subparams = [false; MAX_PARAMS];
                 self.params_full = false;
                 self.current_param.take();
             }
@@ -407,12 +456,17 @@ impl VTParser {
                 if self.params_full {
                     return;
                 }
-                if param == b';' {
+                if param == b';' || param == b':' {
                     if self.num_params + 1 > MAX_OSC {
                         self.params_full = true;
                     } else {
                         self.params[self.num_params] = self.current_param.take().unwrap_or(0);
                         self.num_params += 1;
+                        // A colon makes the parameter that follows it a
+                        // sub-parameter of the one that we just finished
+                        if self.num_params < MAX_PARAMS {
+                            self.subparams[self.num_params] = param == b':';
+                        }
                     }
                 } else {
                     let current = self.current_param.take().unwrap_or(0);
@@ -445,12 +499,23 @@ impl VTParser {
             }
             Action::CsiDispatch => {
                 self.finish_param();
-                actor.csi_dispatch(
-                    &self.params[0..self.num_params],
-                    &self.intermediates[0..self.num_intermediates],
-                    self.ignored_excess_intermediates,
-                    param,
-                );
+                let subparams = &self.subparams[0..self.num_params];
+                if subparams.iter().any(|&s| s) {
+                    actor.csi_dispatch_with_subparams(
+                        &self.params[0..self.num_params],
+                        subparams,
+                        &self.intermediates[0..self.num_intermediates],
+                        self.ignored_excess_intermediates,
+                        param,
+                    );
+                } else {
+                    actor.csi_dispatch(
+                        &self.params[0..self.num_params],
+                        &self.intermediates[0..self.num_intermediates],
+                        self.ignored_excess_intermediates,
+                        param,
+                    );
+                }
             }
             Action::Unhook => actor.dcs_unhook(),
             Action::OscStart => {
@@ -721,6 +786,41 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_csi_subparams() {
+        assert_eq!(
+            parse_as_vec(b"\x1b[4:3m"),
+            vec![VTAction::CsiDispatchWithSubparams {
+                params: vec![4, 3],
+                subparams: vec![false, true],
+                intermediates: b"".to_vec(),
+                ignored_excess_intermediates: false,
+                byte: b'm'
+            }]
+        );
+        assert_eq!(
+            parse_as_vec(b"\x1b[1;58:2::255:0:128m"),
+            vec![VTAction::CsiDispatchWithSubparams {
+                // The omitted color space id defaults to 0
+                params: vec![1, 58, 2, 0, 255, 0, 128],
+                subparams: vec![false, false, true, true, true, true, true],
+                intermediates: b"".to_vec(),
+                ignored_excess_intermediates: false,
+                byte: b'm'
+            }]
+        );
+        // The sub-parameter state doesn't leak into the next sequence
+        assert_eq!(
+            parse_as_vec(b"\x1b[4:3m\x1b[4;3m")[1],
+            VTAction::CsiDispatch {
+                params: vec![4, 3],
+                intermediates: b"".to_vec(),
+                ignored_excess_intermediates: false,
+                byte: b'm'
+            }
+        );
+    }
+
     #[test]
     fn test_csi_too_many_params() {
         assert_eq!(
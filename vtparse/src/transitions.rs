@@ -183,9 +183,7 @@ define_function! {
         0x1c..=0x1f => (Execute, CsiEntry),
         0x7f        => (Ignore, CsiEntry),
         0x20..=0x2f => (Collect, CsiIntermediate),
-        0x3a        => (None, CsiIgnore),
-        0x30..=0x39 => (Param, CsiParam),
-        0x3b        => (Param, CsiParam),
+        0x30..=0x3b => (Param, CsiParam),
         0x3c..=0x3f => (Collect, CsiParam),
         0x40..=0x7e => (CsiDispatch, Ground),
     }
@@ -194,10 +192,8 @@ define_function! {
         0x00..=0x17 => (Execute, CsiParam),
         0x19        => (Execute, CsiParam),
         0x1c..=0x1f => (Execute, CsiParam),
-        0x30..=0x39 => (Param, CsiParam),
-        0x3b        => (Param, CsiParam),
+        0x30..=0x3b => (Param, CsiParam),
         0x7f        => (Ignore, CsiParam),
-        0x3a        => (None, CsiIgnore),
         0x3c..=0x3f => (None, CsiIgnore),
         0x20..=0x2f => (Collect, CsiIntermediate),
         0x40..=0x7e => (CsiDispatch, Ground),
@@ -0,0 +1,83 @@
+//! Watches the `font_dirs` for fonts being added, changed or removed,
+//! so that they can be picked up without reloading the configuration.
+//! The filesystem events are debounced, and any number of them only
+//! raise a flag that is checked the next time a font is resolved, so
+//! installing many fonts at once causes a single re-scan.
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long a path must be quiet before its change is reported
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+pub struct FontDirsWatcher {
+    dirs: Vec<PathBuf>,
+    changed: Arc<AtomicBool>,
+    _watcher: RecommendedWatcher,
+}
+
+impl FontDirsWatcher {
+    /// Start watching the specified directories and their contents
+    pub fn new(dirs: Vec<PathBuf>) -> anyhow::Result<Self> {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::watcher(tx, DEBOUNCE)?;
+        for dir in &dirs {
+            if let Err(err) = watcher.watch(dir, RecursiveMode::Recursive) {
+                log::warn!("unable to watch font dir {}: {:#}", dir.display(), err);
+            }
+        }
+
+        let changed = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&changed);
+        // The channel is closed when the watcher is dropped,
+        // which ends the thread
+        std::thread::spawn(move || {
+            while let Ok(event) = rx.recv() {
+                handle_event(&flag, event);
+            }
+        });
+
+        Ok(Self {
+            dirs,
+            changed,
+            _watcher: watcher,
+        })
+    }
+
+    /// Returns the directories that are being watched
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// Returns true if the contents of the directories have changed
+    /// since the last call
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::SeqCst)
+    }
+
+    #[cfg(test)]
+    pub fn simulate_event(&self, event: DebouncedEvent) {
+        handle_event(&self.changed, event);
+    }
+}
+
+fn handle_event(changed: &AtomicBool, event: DebouncedEvent) {
+    log::trace!("font dirs event: {:?}", event);
+    match event {
+        // The notices are followed by the corresponding
+        // Write or Remove once the path has settled
+        DebouncedEvent::NoticeWrite(_)
+        | DebouncedEvent::NoticeRemove(_)
+        | DebouncedEvent::Error(..) => {}
+        DebouncedEvent::Create(_)
+        | DebouncedEvent::Write(_)
+        | DebouncedEvent::Chmod(_)
+        | DebouncedEvent::Remove(_)
+        | DebouncedEvent::Rename(..)
+        | DebouncedEvent::Rescan => {
+            changed.store(true, Ordering::SeqCst);
+        }
+    }
+}
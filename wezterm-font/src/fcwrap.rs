@@ -7,6 +7,7 @@ use std::ffi::{CStr, CString};
 use std::fmt;
 use std::mem;
 use std::os::raw::{c_char, c_int};
+use std::path::PathBuf;
 use std::ptr;
 use std::sync::Mutex;
 
@@ -105,6 +106,37 @@ impl FontSet {
     }
 }
 
+/// Returns the list of font directories that are configured in the
+/// fontconfig configuration for the current user and system.
+pub fn font_dirs() -> Result<Vec<PathBuf>, Error> {
+    unsafe {
+        ensure!(FcInit() != 0, "FcInit failed");
+        add_object();
+        // FcInit created the current config; we hold a reference to
+        // the library via NUM_OBJECTS until we're done walking the list
+        let list = FcConfigGetFontDirs(ptr::null_mut());
+        if list.is_null() {
+            release_object();
+            return Err(anyhow!("FcConfigGetFontDirs failed"));
+        }
+
+        let mut dirs = vec![];
+        loop {
+            let s = FcStrListNext(list);
+            if s.is_null() {
+                break;
+            }
+            let dir = CStr::from_ptr(s as *const c_char)
+                .to_string_lossy()
+                .into_owned();
+            dirs.push(PathBuf::from(dir));
+        }
+        FcStrListDone(list);
+        release_object();
+        Ok(dirs)
+    }
+}
+
 #[repr(C)]
 pub enum MatchKind {
     Pattern = FcMatchPattern as isize,
@@ -259,6 +291,30 @@ impl Pattern {
         }
     }
 
+    /// Returns all of the fonts that match this pattern, with only
+    /// the specified properties of each font filled in
+    pub fn list(&self, properties: &[&str]) -> Result<FontSet, Error> {
+        let properties = properties
+            .iter()
+            .map(|p| CString::new(*p))
+            .collect::<Result<Vec<_>, _>>()?;
+        unsafe {
+            let objects = FcObjectSetCreate();
+            ensure!(!objects.is_null(), "FcObjectSetCreate failed");
+            for prop in &properties {
+                if FcObjectSetAdd(objects, prop.as_ptr()) == 0 {
+                    FcObjectSetDestroy(objects);
+                    return Err(anyhow!("failed to add {:?} to the object set", prop));
+                }
+            }
+            let fonts = FcFontList(ptr::null_mut(), self.pat, objects);
+            FcObjectSetDestroy(objects);
+            ensure!(!fonts.is_null(), "FcFontList failed");
+            add_object();
+            Ok(FontSet { fonts })
+        }
+    }
+
     pub fn get_file(&self) -> Result<String, Error> {
         self.get_string("file")
     }
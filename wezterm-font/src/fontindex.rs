@@ -0,0 +1,295 @@
+//! A persistent index of the fonts found in the `font_dirs`.
+//! Parsing the name tables of every font in a large font directory
+//! is slow, so the extracted names are recorded alongside the size and
+//! modification time of each file, and a file is only parsed again
+//! when either of those changes.
+use crate::locator::FontDataHandle;
+use crate::parser::{parse_font_faces, Names};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+/// Bump this when the format of the index, or the information
+/// extracted from the fonts, changes
+const INDEX_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedFace {
+    index: u32,
+    names: Names,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexEntry {
+    modified: SystemTime,
+    size: u64,
+    /// The faces contained in the file.  This is empty for files
+    /// that couldn't be parsed, so that we don't keep trying.
+    faces: Vec<IndexedFace>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexData {
+    version: u32,
+    entries: HashMap<PathBuf, IndexEntry>,
+}
+
+/// Returns a path alongside `path` that is unique to this process
+/// and to this call, to which the index can be written before it
+/// is renamed into place
+fn temp_path(path: &Path) -> PathBuf {
+    static SEQ: AtomicUsize = AtomicUsize::new(0);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        SEQ.fetch_add(1, Ordering::Relaxed)
+    ));
+    path.with_file_name(name)
+}
+
+#[derive(Debug)]
+pub struct FontIndex {
+    path: PathBuf,
+    data: IndexData,
+    dirty: bool,
+}
+
+impl FontIndex {
+    /// Returns the location of the index in the cache directory
+    pub fn default_path() -> PathBuf {
+        config::CACHE_DIR.join("font-index.json")
+    }
+
+    /// Returns the location of the index of the fonts found in the
+    /// font directories configured in fontconfig
+    pub fn system_path() -> PathBuf {
+        config::CACHE_DIR.join("system-font-index.json")
+    }
+
+    /// Load the index from the specified path.  A missing or
+    /// unreadable index results in an empty index.
+    pub fn load(path: &Path) -> Self {
+        let data = match std::fs::read(path) {
+            Ok(data) => match serde_json::from_slice::<IndexData>(&data) {
+                Ok(data) if data.version == INDEX_VERSION => data,
+                Ok(_) => IndexData::default(),
+                Err(err) => {
+                    log::warn!("ignoring font index {}: {}", path.display(), err);
+                    IndexData::default()
+                }
+            },
+            Err(_) => IndexData::default(),
+        };
+        Self {
+            path: path.to_path_buf(),
+            data,
+            dirty: false,
+        }
+    }
+
+    /// Write the index back to disk, if it has changed
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let path = &self.path;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating {}", parent.display()))?;
+        }
+        self.data.version = INDEX_VERSION;
+        let data = serde_json::to_vec(&self.data)?;
+        // Write to a temporary file and then rename it into place, so
+        // that a concurrent reader never sees a partially written index.
+        // The temporary file is unique to this save, so that concurrent
+        // writers, such as another wezterm process, can't interleave
+        // their writes into the same file.
+        let temp = temp_path(path);
+        if let Err(err) = std::fs::write(&temp, data) {
+            std::fs::remove_file(&temp).ok();
+            return Err(err).with_context(|| format!("writing {}", temp.display()));
+        }
+        if let Err(err) = std::fs::rename(&temp, path) {
+            std::fs::remove_file(&temp).ok();
+            return Err(err).with_context(|| format!("renaming to {}", path.display()));
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Forget everything, so that the next scan parses every file
+    pub fn clear(&mut self) {
+        self.data.entries.clear();
+        self.dirty = true;
+    }
+
+    /// Walk the specified directories, parsing any files that are
+    /// new or have changed since they were last indexed, and forgetting
+    /// files that are no longer present.
+    /// Returns the list of files that were parsed.
+    pub fn scan(&mut self, dirs: &[PathBuf]) -> Vec<PathBuf> {
+        let mut parsed = vec![];
+        let mut seen = HashSet::new();
+
+        for dir in dirs {
+            for entry in walkdir::WalkDir::new(dir).into_iter() {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(_) => continue,
+                };
+                let meta = match entry.metadata() {
+                    Ok(meta) if meta.is_file() => meta,
+                    _ => continue,
+                };
+                let modified = match meta.modified() {
+                    Ok(modified) => modified,
+                    Err(_) => continue,
+                };
+                let size = meta.len();
+
+                let path = entry.path();
+                seen.insert(path.to_path_buf());
+
+                if let Some(existing) = self.data.entries.get(path) {
+                    if existing.modified == modified && existing.size == size {
+                        continue;
+                    }
+                }
+
+                let faces = match parse_font_faces(path) {
+                    Ok(faces) => faces
+                        .into_iter()
+                        .map(|(index, names)| IndexedFace { index, names })
+                        .collect(),
+                    Err(err) => {
+                        log::trace!("failed to read {}: {}", path.display(), err);
+                        vec![]
+                    }
+                };
+                self.data.entries.insert(
+                    path.to_path_buf(),
+                    IndexEntry {
+                        modified,
+                        size,
+                        faces,
+                    },
+                );
+                self.dirty = true;
+                parsed.push(path.to_path_buf());
+            }
+        }
+
+        let before = self.data.entries.len();
+        self.data.entries.retain(|path, _| seen.contains(path));
+        if self.data.entries.len() != before {
+            self.dirty = true;
+        }
+
+        parsed
+    }
+
+    /// Returns the names and handles of the faces in the index
+    pub fn font_info(&self) -> Vec<(Names, PathBuf, FontDataHandle)> {
+        let mut font_info = vec![];
+        for (path, entry) in &self.data.entries {
+            for face in &entry.faces {
+                font_info.push((
+                    face.names.clone(),
+                    path.clone(),
+                    FontDataHandle::OnDisk {
+                        path: path.clone(),
+                        index: face.index,
+                    },
+                ));
+            }
+        }
+        font_info
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use filetime::FileTime;
+
+    const FONT: &[u8] = include_bytes!("../../test-data/woff/WoffTest-Regular.ttf");
+
+    fn sorted(mut paths: Vec<PathBuf>) -> Vec<PathBuf> {
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn temp_paths_are_unique() {
+        let path = Path::new("/cache/font-index.json");
+        let a = temp_path(path);
+        let b = temp_path(path);
+        assert_ne!(a, b);
+        assert_eq!(a.parent(), path.parent());
+        assert!(a
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("font-index.json."));
+    }
+
+    #[test]
+    fn rescan_only_changed_files() {
+        let dir = tempdir::TempDir::new("font-index").unwrap();
+        let fonts = dir.path().join("fonts");
+        std::fs::create_dir(&fonts).unwrap();
+        let one = fonts.join("one.ttf");
+        let two = fonts.join("two.ttf");
+        let readme = fonts.join("README");
+        std::fs::write(&one, FONT).unwrap();
+        std::fs::write(&two, FONT).unwrap();
+        std::fs::write(&readme, "not a font").unwrap();
+
+        let index_path = dir.path().join("cache").join("font-index.json");
+        let dirs = vec![fonts.clone()];
+
+        let mut index = FontIndex::load(&index_path);
+        assert_eq!(
+            sorted(index.scan(&dirs)),
+            vec![readme.clone(), one.clone(), two.clone()]
+        );
+        assert_eq!(index.font_info().len(), 2);
+        index.save().unwrap();
+        // Only the index itself is left in the cache directory
+        let cached = std::fs::read_dir(index_path.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(cached, vec![index_path.clone()]);
+
+        // A fresh index loaded from disk doesn't need to parse anything
+        let mut index = FontIndex::load(&index_path);
+        assert_eq!(index.scan(&dirs), Vec::<PathBuf>::new());
+        let names = index
+            .font_info()
+            .into_iter()
+            .map(|(names, _, _)| names.full_name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Woff Test Regular", "Woff Test Regular"]);
+
+        // Touching a file causes only that file to be parsed again
+        let modified = std::fs::metadata(&one).unwrap().modified().unwrap();
+        let touched = FileTime::from_system_time(modified + std::time::Duration::from_secs(10));
+        filetime::set_file_mtime(&one, touched).unwrap();
+        assert_eq!(index.scan(&dirs), vec![one.clone()]);
+        assert_eq!(index.scan(&dirs), Vec::<PathBuf>::new());
+
+        // Removed files are dropped from the index
+        std::fs::remove_file(&two).unwrap();
+        assert_eq!(index.scan(&dirs), Vec::<PathBuf>::new());
+        assert_eq!(index.font_info().len(), 1);
+
+        // Clearing forces everything to be parsed again
+        index.clear();
+        assert_eq!(sorted(index.scan(&dirs)), vec![readme, one]);
+    }
+}
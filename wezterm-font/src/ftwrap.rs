@@ -1,8 +1,10 @@
 //! Higher level freetype bindings
 
 use crate::locator::FontDataHandle;
+use crate::outline::PathCommand;
+use crate::rasterizer::SyntheticStyle;
 use anyhow::{anyhow, Context};
-use config::{configuration, FontAntiAliasing, FontHinting};
+use config::{configuration, CellWidthReference, FontAntiAliasing, FontHinting};
 pub use freetype::*;
 use std::ptr;
 
@@ -30,14 +32,22 @@ fn render_mode_to_load_target(render_mode: FT_Render_Mode) -> u32 {
 
 pub fn compute_load_flags_from_config() -> (i32, FT_Render_Mode) {
     let config = configuration();
+    compute_load_flags(config.font_antialias, config.font_hinting)
+}
 
-    let render = match config.font_antialias {
+/// Returns the load flags and render mode that produce glyphs
+/// with the specified antialiasing and hinting
+pub fn compute_load_flags(
+    antialias: FontAntiAliasing,
+    hinting: FontHinting,
+) -> (i32, FT_Render_Mode) {
+    let render = match antialias {
         FontAntiAliasing::None => FT_Render_Mode::FT_RENDER_MODE_MONO,
         FontAntiAliasing::Greyscale => FT_Render_Mode::FT_RENDER_MODE_NORMAL,
         FontAntiAliasing::Subpixel => FT_Render_Mode::FT_RENDER_MODE_LCD,
     };
 
-    let flags = match config.font_hinting {
+    let flags = match hinting {
         FontHinting::None => {
             render_mode_to_load_target(FT_Render_Mode::FT_RENDER_MODE_NORMAL) | FT_LOAD_NO_HINTING
         }
@@ -50,7 +60,7 @@ pub fn compute_load_flags_from_config() -> (i32, FT_Render_Mode) {
     // If the bitmaps are in color, we want those!
     let flags = flags | FT_LOAD_COLOR;
 
-    let flags = if config.font_antialias == FontAntiAliasing::None {
+    let flags = if antialias == FontAntiAliasing::None {
         // When AA is disabled, force outline rendering to monochrome
         flags | FT_LOAD_MONOCHROME
     } else {
@@ -78,6 +88,19 @@ impl Face {
     /// that accounts for some weirdness with eg: color emoji
     pub fn set_font_size(&mut self, size: f64, dpi: u32) -> anyhow::Result<(f64, f64)> {
         log::debug!("set_char_size {} dpi={}", size, dpi);
+        let pixel_size = size * f64::from(dpi) / 72.0;
+
+        // A font that only has bitmap strikes can't be scaled, so we use
+        // the strike that is closest to the requested size.  Color
+        // bitmap fonts are excluded here, as they are scaled to fit the
+        // cell and are handled by the fallback below.
+        if !self.is_scalable() && !self.has_color() {
+            if let Some(idx) = self.nearest_fixed_size(pixel_size) {
+                self.select_size(idx)?;
+                return Ok(self.strike_cell_metrics());
+            }
+        }
+
         // Scaling before truncating to integer minimizes the chances of hitting
         // the fallback code for set_pixel_sizes below.
         let size = (size * 64.0) as FT_F26Dot6;
@@ -140,46 +163,173 @@ impl Face {
         )
     }
 
+    /// Select the instance of a variable font at the specified design
+    /// coordinates, one per axis.  This must be done before sizing the
+    /// face or loading glyphs.  An empty list leaves the default
+    /// instance selected.
+    pub fn set_design_coords(&mut self, coords: &[f64]) -> anyhow::Result<()> {
+        if coords.is_empty() {
+            return Ok(());
+        }
+        let mut fixed: Vec<FT_Fixed> = coords
+            .iter()
+            .map(|&c| (c * 65536.0).round() as FT_Fixed)
+            .collect();
+        ft_result(
+            unsafe {
+                FT_Set_Var_Design_Coordinates(self.face, fixed.len() as FT_UInt, fixed.as_mut_ptr())
+            },
+            (),
+        )
+    }
+
     fn select_size(&mut self, idx: usize) -> anyhow::Result<()> {
         ft_result(unsafe { FT_Select_Size(self.face, idx as i32) }, ())
     }
 
+    fn is_scalable(&self) -> bool {
+        unsafe { ((*self.face).face_flags as u32) & (FT_FACE_FLAG_SCALABLE as u32) != 0 }
+    }
+
+    fn has_color(&self) -> bool {
+        unsafe { ((*self.face).face_flags as u32) & (FT_FACE_FLAG_COLOR as u32) != 0 }
+    }
+
+    /// Returns the index of the bitmap strike whose pixel size is
+    /// closest to `pixel_size`, or None if the face has no strikes
+    fn nearest_fixed_size(&self, pixel_size: f64) -> Option<usize> {
+        let sizes = unsafe {
+            let rec = &(*self.face);
+            std::slice::from_raw_parts(rec.available_sizes, rec.num_fixed_sizes as usize)
+        };
+        let distance = |info: &FT_Bitmap_Size| (info.y_ppem as f64 / 64.0 - pixel_size).abs();
+        sizes
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| distance(a).partial_cmp(&distance(b)).unwrap())
+            .map(|(idx, _)| idx)
+    }
+
+    /// Computes the metrics for the nominal monospace cell from the
+    /// line metrics of the selected bitmap strike
+    fn strike_cell_metrics(&mut self) -> (f64, f64) {
+        let height = unsafe { (*(*self.face).size).metrics.height as f64 / 64.0 };
+        (self.max_ascii_advance(), height)
+    }
+
     pub fn load_and_render_glyph(
         &mut self,
         glyph_index: FT_UInt,
         load_flags: FT_Int32,
         render_mode: FT_Render_Mode,
+        synthesize: &SyntheticStyle,
     ) -> anyhow::Result<&FT_GlyphSlotRec_> {
         unsafe {
             let res = FT_Load_Glyph(self.face, glyph_index, load_flags);
             let slot = ft_result(res, &mut *(*self.face).glyph)?;
+            if slot.format == FT_Glyph_Format::FT_GLYPH_FORMAT_OUTLINE {
+                if synthesize.bold {
+                    // This is the same strength that FT_GlyphSlot_Embolden
+                    // uses; it isn't available in our bindings.
+                    let strength = FT_MulFix(
+                        (*self.face).units_per_EM as FT_Long,
+                        (*(*self.face).size).metrics.y_scale,
+                    ) / 24;
+                    FT_Outline_Embolden(&mut slot.outline, strength);
+                }
+                if let Some(angle) = synthesize.italic {
+                    // Shear horizontally around the baseline; the advance
+                    // is unchanged, and the bitmap bearing produced by
+                    // FT_Render_Glyph reflects the slanted extents.
+                    let shear = FT_Matrix {
+                        xx: 0x10000,
+                        xy: (angle.to_radians().tan() * f64::from(0x10000)) as FT_Fixed,
+                        yx: 0,
+                        yy: 0x10000,
+                    };
+                    FT_Outline_Transform(&slot.outline, &shear);
+                }
+            }
             ft_result(FT_Render_Glyph(slot, render_mode), slot)
         }
     }
 
+    /// Loads the outline of a glyph, in font units, without hinting
+    pub fn glyph_outline(&mut self, glyph_index: FT_UInt) -> anyhow::Result<Vec<PathCommand>> {
+        unsafe {
+            let res = FT_Load_Glyph(self.face, glyph_index, FT_LOAD_NO_SCALE as FT_Int32);
+            let slot = ft_result(res, &mut *(*self.face).glyph)
+                .with_context(|| format!("FT_Load_Glyph for glyph {}", glyph_index))?;
+            if slot.format != FT_Glyph_Format::FT_GLYPH_FORMAT_OUTLINE {
+                anyhow::bail!("glyph {} is not an outline: {:?}", glyph_index, slot.format);
+            }
+            crate::outline::decompose(&mut slot.outline)
+        }
+    }
+
     pub fn cell_metrics(&mut self) -> (f64, f64) {
         unsafe {
             let metrics = &(*(*self.face).size).metrics;
             let height = (metrics.y_scale as f64 * f64::from((*self.face).height))
                 / (f64::from(0x1_0000) * 64.0);
+            (
+                self.reference_advance(configuration().cell_width_reference),
+                height,
+            )
+        }
+    }
 
-            let mut width = 0.0;
-            for i in 32..128 {
-                let glyph_pos = FT_Get_Char_Index(self.face, i);
-                if glyph_pos == 0 {
-                    continue;
-                }
-                let res = FT_Load_Glyph(self.face, glyph_pos, FT_LOAD_COLOR as i32);
-                if succeeded(res) {
-                    let glyph = &(*(*self.face).glyph);
-                    if glyph.metrics.horiAdvance as f64 > width {
-                        width = glyph.metrics.horiAdvance as f64;
-                    }
-                }
+    /// Returns the advance, in pixels, from which the cell width
+    /// is computed for the specified reference
+    fn reference_advance(&mut self, reference: CellWidthReference) -> f64 {
+        let advance = match reference {
+            CellWidthReference::Char(c) => self.char_advance(c),
+            CellWidthReference::AverageCharWidth => self.avg_char_width(),
+            CellWidthReference::MaxAscii => None,
+        };
+        advance.unwrap_or_else(|| self.max_ascii_advance())
+    }
+
+    /// Returns the advance of the glyph for the specified character,
+    /// in pixels, or None if the font doesn't have that character
+    fn char_advance(&mut self, c: char) -> Option<f64> {
+        unsafe {
+            let glyph_pos = FT_Get_Char_Index(self.face, c as FT_ULong);
+            if glyph_pos == 0 {
+                return None;
+            }
+            let res = FT_Load_Glyph(self.face, glyph_pos, FT_LOAD_COLOR as i32);
+            if succeeded(res) {
+                Some((*(*self.face).glyph).metrics.horiAdvance as f64 / 64.0)
+            } else {
+                None
             }
-            (width / 64.0, height)
         }
     }
+
+    /// Returns the xAvgCharWidth from the OS/2 table, in pixels
+    fn avg_char_width(&mut self) -> Option<f64> {
+        let mut data = [0u8; 2];
+        let mut len = data.len() as FT_ULong;
+        let tag = u32::from_be_bytes(*b"OS/2") as FT_ULong;
+        let res = unsafe { FT_Load_Sfnt_Table(self.face, tag, 2, data.as_mut_ptr(), &mut len) };
+        if !succeeded(res) {
+            return None;
+        }
+        let width = i16::from_be_bytes(data);
+        if width <= 0 {
+            return None;
+        }
+        let x_scale = unsafe { (*(*self.face).size).metrics.x_scale as f64 };
+        Some(f64::from(width) * x_scale / (f64::from(0x1_0000) * 64.0))
+    }
+
+    /// Returns the largest advance of the ASCII glyphs, in pixels
+    fn max_ascii_advance(&mut self) -> f64 {
+        (32..128u8)
+            .filter_map(|i| self.char_advance(i as char))
+            .fold(0.0, f64::max)
+    }
 }
 
 pub struct Library {
@@ -244,6 +394,11 @@ impl Library {
         // floating around for a long time!
         let data = std::fs::read(path)?;
         log::trace!("Loading {} for freetype!", path.display());
+        let data = if crate::woff::is_woff(&data) {
+            crate::woff::decode(&data)?
+        } else {
+            data
+        };
 
         let res = unsafe {
             FT_New_Memory_Face(
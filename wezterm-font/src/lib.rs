@@ -1,21 +1,37 @@
-use crate::locator::{new_locator, FontDataHandle, FontLocator, FontLocatorSelection};
-use crate::rasterizer::{new_rasterizer, FontRasterizer};
+use crate::dirwatch::FontDirsWatcher;
+use crate::fontindex::FontIndex;
+use crate::locator::{
+    dedup_font_entries, new_locator, CachingLocator, FontDataHandle, FontDataKey, FontEnumEntry,
+    FontLocator, FontLocatorSelection,
+};
+use crate::parser::{ColorGlyphs, RgbaColor};
+use crate::rasterizer::{new_rasterizer, FontRasterizer, SyntheticStyle};
 use crate::shaper::{new_shaper, FontShaper, FontShaperSelection};
+use crate::units::PixelLength;
 use anyhow::{anyhow, Error};
-use config::{configuration, ConfigHandle, FontRasterizerSelection, TextStyle};
+use config::{
+    configuration, ConfigHandle, FontAttributes, FontRasterizerSelection, StyleRule, TextStyle,
+};
+use lru::LruCache;
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
+use termwiz::color::ColorAttribute;
 use wezterm_term::CellAttributes;
 
 mod hbwrap;
 
+pub mod dirwatch;
+pub mod fontindex;
 pub mod ftwrap;
 pub mod locator;
+pub mod outline;
 pub mod parser;
 pub mod rasterizer;
 pub mod shaper;
 pub mod units;
+pub mod variation;
+pub mod woff;
 
 #[cfg(all(unix, not(target_os = "macos")))]
 pub mod fcwrap;
@@ -23,13 +39,98 @@ pub mod fcwrap;
 pub use crate::rasterizer::RasterizedGlyph;
 pub use crate::shaper::{FallbackIdx, FontMetrics, GlyphInfo};
 
+/// Describes how a font in the fallback chain was located
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontSource {
+    /// Found by scanning the `font_dirs` configuration
+    FontDirs,
+    /// Found by the system font locator
+    Locator,
+    /// Found by scanning the font directories configured in fontconfig
+    SystemFontDirs,
+    /// One of the fonts that are compiled in to wezterm
+    BuiltIn,
+}
+
+/// Describes an entry in the resolved fallback chain for a style
+#[derive(Debug, Clone)]
+pub struct FallbackInfo {
+    pub name: String,
+    pub handle: FontDataHandle,
+    pub source: FontSource,
+}
+
+/// Produce a human readable name for a font handle, preferring
+/// the full name from the font's name table
+fn font_handle_name(handle: &FontDataHandle) -> String {
+    match parser::ParsedFont::from_locator(handle) {
+        Ok(parsed) => parsed.names().full_name().to_string(),
+        Err(_) => match handle {
+            FontDataHandle::OnDisk { path, .. } => path.display().to_string(),
+            FontDataHandle::Memory { name, .. } => name.clone(),
+        },
+    }
+}
+
+/// The layered color glyphs of each font that has been rasterized,
+/// shared by the LoadedFonts of a FontConfiguration so that each
+/// font is only parsed once
+type ColorGlyphsCache = Rc<RefCell<HashMap<FontDataKey, Option<Rc<ColorGlyphs>>>>>;
+
+/// Returns the layered color glyphs of the font, using the cache
+/// if the font has been seen before
+fn cached_color_glyphs(
+    cache: &ColorGlyphsCache,
+    handle: &FontDataHandle,
+) -> Option<Rc<ColorGlyphs>> {
+    cache
+        .borrow_mut()
+        .entry(handle.key())
+        .or_insert_with(|| {
+            parser::ParsedFont::from_locator(handle)
+                .ok()
+                .and_then(|parsed| parsed.color_glyphs().cloned())
+                .map(Rc::new)
+        })
+        .clone()
+}
+
+/// The OpenType features that produce ligatures, whether through
+/// ligature substitution or contextual alternates
+const NO_LIGATURE_FEATURES: &[&str] = &["liga=0", "clig=0", "dlig=0", "calt=0"];
+
+/// VARIATION SELECTOR-15, which requests the text presentation
+const VS15: char = '\u{fe0e}';
+/// VARIATION SELECTOR-16, which requests the emoji presentation
+const VS16: char = '\u{fe0f}';
+
+/// Returns the set of clusters that contain at least one `.notdef` glyph
+fn missing_clusters(glyphs: &[GlyphInfo]) -> HashSet<u32> {
+    glyphs
+        .iter()
+        .filter(|g| g.glyph_pos == 0)
+        .map(|g| g.cluster)
+        .collect()
+}
+
 pub struct LoadedFont {
     rasterizers: Vec<RefCell<Option<Box<dyn FontRasterizer>>>>,
     handles: Vec<FontDataHandle>,
+    sources: Vec<FontSource>,
+    synthesize: Vec<SyntheticStyle>,
+    /// The design coordinates of the selected instance of each
+    /// variable font; empty for the default instance
+    design_coords: Vec<Vec<f64>>,
+    color_glyphs: ColorGlyphsCache,
+    /// Lazily computed per-fallback font size scaling factors
+    scales: RefCell<Vec<Option<f64>>>,
+    font_size_adjust: bool,
     shaper: Box<dyn FontShaper>,
+    rasterizer_selection: FontRasterizerSelection,
     metrics: FontMetrics,
     font_size: f64,
     dpi: u32,
+    features: Vec<String>,
 }
 
 impl LoadedFont {
@@ -38,60 +139,515 @@ impl LoadedFont {
     }
 
     pub fn shape(&self, text: &str) -> anyhow::Result<Vec<GlyphInfo>> {
-        self.shaper.shape(text, self.font_size, self.dpi)
+        let mut glyphs = self
+            .shaper
+            .shape(text, self.font_size, self.dpi, &self.features)?;
+        self.rescale_fallbacks(&mut glyphs, self.font_size)?;
+        let glyphs = self.reshape_missing(text, glyphs)?;
+        self.apply_presentation(text, glyphs, &self.features)
+    }
+
+    /// Shape the text with the ligature features disabled, so that
+    /// each character is drawn using its own glyph
+    pub fn shape_without_ligatures(&self, text: &str) -> anyhow::Result<Vec<GlyphInfo>> {
+        let features = self
+            .features
+            .iter()
+            .cloned()
+            .chain(NO_LIGATURE_FEATURES.iter().map(|f| f.to_string()))
+            .collect::<Vec<_>>();
+        let mut glyphs = self
+            .shaper
+            .shape(text, self.font_size, self.dpi, &features)?;
+        self.rescale_fallbacks(&mut glyphs, self.font_size)?;
+        let glyphs = self.reshape_missing(text, glyphs)?;
+        self.apply_presentation(text, glyphs, &features)
+    }
+
+    /// Returns the width of `text` once it has been shaped: the sum
+    /// of the advances of its glyphs, so that combining marks, which
+    /// don't advance, don't add to the width.
+    pub fn measure(&self, text: &str) -> anyhow::Result<PixelLength> {
+        Ok(self
+            .shape(text)?
+            .iter()
+            .fold(PixelLength::new(0.), |width, glyph| width + glyph.x_advance))
+    }
+
+    /// Given `glyphs`, the result of shaping `text`, find the glyph(s)
+    /// that cover the character at `byte_idx` and replace them with
+    /// the result of shaping the text they represent without ligatures.
+    /// This is used to break up a ligature that sits under the cursor
+    /// so that the character in the cursor cell can be seen.
+    pub fn split_ligature_at(
+        &self,
+        text: &str,
+        glyphs: &[GlyphInfo],
+        byte_idx: usize,
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
+        let start = glyphs
+            .iter()
+            .map(|g| g.cluster as usize)
+            .filter(|&c| c <= byte_idx)
+            .max();
+        let start = match start {
+            Some(start) => start,
+            None => return Ok(glyphs.to_vec()),
+        };
+        let end = glyphs
+            .iter()
+            .map(|g| g.cluster as usize)
+            .find(|&c| c > byte_idx)
+            .unwrap_or_else(|| text.len());
+
+        let mut split = self.shape_without_ligatures(&text[start..end])?;
+        for glyph in &mut split {
+            glyph.cluster += start as u32;
+        }
+
+        let mut result = Vec::with_capacity(glyphs.len() + split.len());
+        result.extend(
+            glyphs
+                .iter()
+                .filter(|g| (g.cluster as usize) < start)
+                .cloned(),
+        );
+        result.append(&mut split);
+        result.extend(glyphs.iter().filter(|g| g.cluster as usize >= end).cloned());
+        Ok(result)
+    }
+
+    /// Glyph 0 is the `.notdef` glyph that a font uses for codepoints
+    /// that it doesn't cover.  Runs of clusters that contain it are
+    /// shaped again using the fallback fonts that follow the font that
+    /// produced them, and the first complete result is spliced back in
+    /// place of the run.  Runs that no fallback can improve upon are
+    /// left as they are.
+    fn reshape_missing(
+        &self,
+        text: &str,
+        glyphs: Vec<GlyphInfo>,
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
+        if glyphs.iter().all(|g| g.glyph_pos != 0) {
+            return Ok(glyphs);
+        }
+
+        let missing = missing_clusters(&glyphs);
+        let mut result = Vec::with_capacity(glyphs.len());
+        let mut idx = 0;
+        while idx < glyphs.len() {
+            if !missing.contains(&glyphs[idx].cluster) {
+                result.push(glyphs[idx].clone());
+                idx += 1;
+                continue;
+            }
+
+            // Extend the run over adjacent clusters that are also missing
+            let start = idx;
+            while idx < glyphs.len() && missing.contains(&glyphs[idx].cluster) {
+                idx += 1;
+            }
+            let run = &glyphs[start..idx];
+            let byte_start = run[0].cluster as usize;
+            let byte_end = glyphs
+                .get(idx)
+                .map(|g| g.cluster as usize)
+                .unwrap_or_else(|| text.len());
+            let substr = &text[byte_start..byte_end];
+
+            let first_fallback = run.iter().map(|g| g.font_idx).max().unwrap_or(0) + 1;
+            match self.shape_run_from(first_fallback, substr)? {
+                Some(mut shaped) => {
+                    for glyph in &mut shaped {
+                        glyph.cluster += byte_start as u32;
+                    }
+                    result.append(&mut shaped);
+                }
+                None => result.extend_from_slice(run),
+            }
+        }
+        Ok(result)
+    }
+
+    /// A variation selector following a character requests either its
+    /// emoji presentation (VS16) or its text presentation (VS15).
+    /// Clusters whose font doesn't provide the requested presentation
+    /// are shaped again using the first font in the fallback chain that
+    /// is a color font for emoji, or a monochrome font for text, and
+    /// that has glyphs for the whole cluster.  If there is no such font
+    /// then the cluster is left as it is.
+    fn apply_presentation(
+        &self,
+        text: &str,
+        glyphs: Vec<GlyphInfo>,
+        features: &[String],
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
+        if !text.contains(&[VS15, VS16][..]) {
+            return Ok(glyphs);
+        }
+
+        let mut result = Vec::with_capacity(glyphs.len());
+        let mut idx = 0;
+        while idx < glyphs.len() {
+            let cluster = glyphs[idx].cluster;
+            let start = idx;
+            while idx < glyphs.len() && glyphs[idx].cluster == cluster {
+                idx += 1;
+            }
+            let run = &glyphs[start..idx];
+            let byte_start = cluster as usize;
+            let byte_end = glyphs
+                .get(idx)
+                .map(|g| g.cluster as usize)
+                .unwrap_or_else(|| text.len());
+            let substr = match text.get(byte_start..byte_end) {
+                Some(substr) => substr,
+                None => {
+                    result.extend_from_slice(run);
+                    continue;
+                }
+            };
+
+            let want_color = if substr.contains(VS16) {
+                true
+            } else if substr.contains(VS15) {
+                false
+            } else {
+                result.extend_from_slice(run);
+                continue;
+            };
+
+            let mut satisfied = true;
+            for g in run {
+                if self.has_color(g.font_idx)? != want_color {
+                    satisfied = false;
+                    break;
+                }
+            }
+            if satisfied {
+                result.extend_from_slice(run);
+                continue;
+            }
+
+            match self.shape_run_with_presentation(substr, want_color, features)? {
+                Some(mut shaped) => {
+                    for glyph in &mut shaped {
+                        glyph.cluster += byte_start as u32;
+                    }
+                    result.append(&mut shaped);
+                }
+                None => result.extend_from_slice(run),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Returns the shaping of `text` by the first font in the fallback
+    /// chain whose use of color matches `want_color` and which has
+    /// glyphs for all of `text`
+    fn shape_run_with_presentation(
+        &self,
+        text: &str,
+        want_color: bool,
+        features: &[String],
+    ) -> anyhow::Result<Option<Vec<GlyphInfo>>> {
+        for font_idx in 0..self.handles.len() {
+            match self.has_color(font_idx) {
+                Ok(has_color) if has_color == want_color => {}
+                _ => continue,
+            }
+            let size = self.font_size * self.fallback_scale(font_idx)?;
+            let shaped = match self
+                .shaper
+                .shape_from(font_idx, text, size, self.dpi, features)
+            {
+                Ok(mut shaped) => {
+                    self.rescale_fallbacks(&mut shaped, size)?;
+                    shaped
+                }
+                Err(err) => {
+                    log::trace!("shaping {:?} with fallback {}: {:#}", text, font_idx, err);
+                    continue;
+                }
+            };
+            if !shaped.is_empty()
+                && shaped
+                    .iter()
+                    .all(|g| g.glyph_pos != 0 && g.font_idx == font_idx)
+            {
+                return Ok(Some(shaped));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Try each of the fallback fonts from `first_fallback` onwards,
+    /// returning the first shaping of `text` that has no missing glyphs
+    fn shape_run_from(
+        &self,
+        first_fallback: FallbackIdx,
+        text: &str,
+    ) -> anyhow::Result<Option<Vec<GlyphInfo>>> {
+        for font_idx in first_fallback..self.handles.len() {
+            let size = self.font_size * self.fallback_scale(font_idx)?;
+            let shaped =
+                match self
+                    .shaper
+                    .shape_from(font_idx, text, size, self.dpi, &self.features)
+                {
+                    Ok(mut shaped) => {
+                        self.rescale_fallbacks(&mut shaped, size)?;
+                        shaped
+                    }
+                    Err(err) => {
+                        log::trace!("shaping {:?} with fallback {}: {:#}", text, font_idx, err);
+                        continue;
+                    }
+                };
+            if !shaped.is_empty() && shaped.iter().all(|g| g.glyph_pos != 0) {
+                return Ok(Some(shaped));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The shaper falls back to later fonts in the chain on its own,
+    /// shaping them at `shaped_size`, but each fallback is rasterized at
+    /// its own adjusted size.  Scale the advances and offsets of the
+    /// glyphs so that they agree with the size at which they are drawn.
+    fn rescale_fallbacks(&self, glyphs: &mut [GlyphInfo], shaped_size: f64) -> anyhow::Result<()> {
+        for glyph in glyphs {
+            let factor = self.font_size * self.fallback_scale(glyph.font_idx)? / shaped_size;
+            glyph.x_advance = PixelLength::new(glyph.x_advance.get() * factor);
+            glyph.y_advance = PixelLength::new(glyph.y_advance.get() * factor);
+            glyph.x_offset = PixelLength::new(glyph.x_offset.get() * factor);
+            glyph.y_offset = PixelLength::new(glyph.y_offset.get() * factor);
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the fonts in the fallback chain, in the
+    /// order in which they are consulted
+    pub fn fallback_names(&self) -> Vec<String> {
+        self.handles.iter().map(font_handle_name).collect()
+    }
+
+    /// Returns the factor by which the font size is multiplied for
+    /// the specified fallback when `font_size_adjust` is enabled, and
+    /// 1.0 otherwise.
+    fn fallback_scale(&self, fallback: FallbackIdx) -> anyhow::Result<f64> {
+        if !self.font_size_adjust {
+            return Ok(1.0);
+        }
+        Ok(self.size_adjust_scale(fallback))
+    }
+
+    /// Returns the factor by which the font size is multiplied for
+    /// the specified fallback so that its x-height matches that of
+    /// the primary font, or its cap height if either font doesn't
+    /// specify an x-height.  This allows the fallback to be rasterized
+    /// at its natural size rather than having the bitmap scaled later.
+    fn size_adjust_scale(&self, fallback: FallbackIdx) -> f64 {
+        if fallback == 0 {
+            return 1.0;
+        }
+        if let Some(Some(scale)) = self.scales.borrow().get(fallback) {
+            return *scale;
+        }
+
+        let (primary_x, primary_cap) = self.em_heights(0);
+        let (fallback_x, fallback_cap) = self.em_heights(fallback);
+        let scale = match (primary_x, fallback_x, primary_cap, fallback_cap) {
+            (Some(primary), Some(fallback), _, _) | (_, _, Some(primary), Some(fallback)) => {
+                primary / fallback
+            }
+            _ => 1.0,
+        };
+
+        if let Some(slot) = self.scales.borrow_mut().get_mut(fallback) {
+            slot.replace(scale);
+        }
+        scale
+    }
+
+    /// Returns the x-height and cap height of the specified fallback,
+    /// as fractions of the em
+    fn em_heights(&self, fallback: FallbackIdx) -> (Option<f64>, Option<f64>) {
+        match parser::ParsedFont::from_locator(&self.handles[fallback]) {
+            Ok(mut parsed) => {
+                parsed.set_design_coords(&self.design_coords[fallback]);
+                (parsed.x_height(), parsed.cap_height())
+            }
+            Err(err) => {
+                log::warn!(
+                    "unable to read the x-height of {}: {:#}",
+                    font_handle_name(&self.handles[fallback]),
+                    err
+                );
+                (None, None)
+            }
+        }
     }
 
     pub fn metrics_for_idx(&self, font_idx: usize) -> anyhow::Result<FontMetrics> {
+        let scale = self.fallback_scale(font_idx)?;
         self.shaper
-            .metrics_for_idx(font_idx, self.font_size, self.dpi)
+            .metrics_for_idx(font_idx, self.font_size * scale, self.dpi)
     }
 
+    /// Layers of a color glyph that are drawn in the color of the
+    /// text are filled with `foreground`
     pub fn rasterize_glyph(
         &self,
         glyph_pos: u32,
         fallback: FallbackIdx,
+        foreground: RgbaColor,
     ) -> anyhow::Result<RasterizedGlyph> {
+        let scale = self.fallback_scale(fallback)?;
+        self.with_rasterizer(fallback, |raster| {
+            raster.rasterize_glyph(glyph_pos, self.font_size * scale, self.dpi, foreground)
+        })
+    }
+
+    /// Returns true if the rasterized glyph depends on the color of
+    /// the text, and so must be rasterized for each color it is used in
+    pub fn uses_foreground(&self, glyph_pos: u32, fallback: FallbackIdx) -> anyhow::Result<bool> {
+        self.with_rasterizer(fallback, |raster| Ok(raster.uses_foreground(glyph_pos)))
+    }
+
+    /// Returns true if the specified fallback is a color font,
+    /// such as an emoji font
+    pub fn has_color(&self, fallback: FallbackIdx) -> anyhow::Result<bool> {
+        self.with_rasterizer(fallback, |raster| Ok(raster.has_color()))
+    }
+
+    /// Calls `func` with the rasterizer for the specified fallback,
+    /// creating the rasterizer first if it hasn't been used before
+    fn with_rasterizer<T>(
+        &self,
+        fallback: FallbackIdx,
+        func: impl FnOnce(&dyn FontRasterizer) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
         let cell = self
             .rasterizers
             .get(fallback)
             .ok_or_else(|| anyhow!("no such fallback index: {}", fallback))?;
         let mut opt_raster = cell.borrow_mut();
         if opt_raster.is_none() {
+            let handle = &self.handles[fallback];
             let raster = new_rasterizer(
-                FontRasterizerSelection::get_default(),
-                &self.handles[fallback],
+                self.rasterizer_selection,
+                handle,
+                self.synthesize[fallback],
+                &self.design_coords[fallback],
+                cached_color_glyphs(&self.color_glyphs, handle),
             )?;
             opt_raster.replace(raster);
         }
-
-        opt_raster
-            .as_ref()
-            .unwrap()
-            .rasterize_glyph(glyph_pos, self.font_size, self.dpi)
+        func(opt_raster.as_ref().unwrap().as_ref())
     }
 }
 
 /// Matches and loads fonts for a given input style
 pub struct FontConfiguration {
-    fonts: RefCell<HashMap<TextStyle, Rc<LoadedFont>>>,
+    /// The most recently resolved styles; bounded by the
+    /// `font_cache_size` configuration
+    fonts: RefCell<LruCache<TextStyle, Rc<LoadedFont>>>,
     metrics: RefCell<Option<FontMetrics>>,
     dpi_scale: RefCell<f64>,
     font_scale: RefCell<f64>,
     config_generation: RefCell<usize>,
     locator: Box<dyn FontLocator>,
+    /// Names of the fonts found in the font_dirs, persisted
+    /// across runs so that unchanged files needn't be parsed
+    font_index: RefCell<FontIndex>,
+    /// Names of the fonts found in the fontconfig font directories
+    system_font_index: RefCell<FontIndex>,
+    /// Watches the font_dirs when `font_dirs_watch` is enabled
+    font_dirs_watcher: RefCell<Option<FontDirsWatcher>>,
+    /// Incremented each time the font_dirs are found to have changed
+    font_dirs_generation: RefCell<usize>,
+    color_glyphs: ColorGlyphsCache,
+    /// The design coordinates of the variable font instances that
+    /// have been selected, keyed by font, instance and variations
+    design_coords: RefCell<HashMap<(FontDataKey, Option<String>, Vec<String>), Vec<f64>>>,
 }
 
 impl FontConfiguration {
     /// Create a new empty configuration
     pub fn new() -> Self {
         let locator = new_locator(FontLocatorSelection::get_default());
-        Self {
-            fonts: RefCell::new(HashMap::new()),
+        Self::with_locator(Box::new(CachingLocator::new(locator)))
+    }
+
+    fn with_locator(locator: Box<dyn FontLocator>) -> Self {
+        let config = configuration();
+        let fonts = Self {
+            fonts: RefCell::new(LruCache::new(config.font_cache_size.max(1))),
             locator,
             metrics: RefCell::new(None),
             font_scale: RefCell::new(1.0),
             dpi_scale: RefCell::new(1.0),
-            config_generation: RefCell::new(configuration().generation()),
+            config_generation: RefCell::new(config.generation()),
+            font_index: RefCell::new(FontIndex::load(&FontIndex::default_path())),
+            system_font_index: RefCell::new(FontIndex::load(&FontIndex::system_path())),
+            font_dirs_watcher: RefCell::new(None),
+            font_dirs_generation: RefCell::new(0),
+            color_glyphs: Rc::new(RefCell::new(HashMap::new())),
+            design_coords: RefCell::new(HashMap::new()),
+        };
+        fonts.update_font_dirs_watcher(&config);
+        fonts
+    }
+
+    /// Starts, stops or re-targets the font_dirs watcher so that
+    /// it matches the configuration
+    fn update_font_dirs_watcher(&self, config: &ConfigHandle) {
+        let mut watcher = self.font_dirs_watcher.borrow_mut();
+        if !config.font_dirs_watch || config.font_dirs.is_empty() {
+            watcher.take();
+            return;
+        }
+        if let Some(w) = watcher.as_ref() {
+            if w.dirs() == config.font_dirs.as_slice() {
+                return;
+            }
+        }
+        *watcher = match FontDirsWatcher::new(config.font_dirs.clone()) {
+            Ok(w) => Some(w),
+            Err(err) => {
+                log::error!("unable to watch font_dirs: {:#}", err);
+                None
+            }
+        };
+    }
+
+    /// Returns a counter that is incremented each time fonts are added
+    /// to or removed from the font_dirs.  Anything shaped or rasterized
+    /// before the counter changed may have used fonts that are gone,
+    /// and should be discarded.
+    pub fn font_dirs_generation(&self) -> usize {
+        self.check_font_dirs(&mut self.fonts.borrow_mut());
+        *self.font_dirs_generation.borrow()
+    }
+
+    fn check_font_dirs(&self, fonts: &mut LruCache<TextStyle, Rc<LoadedFont>>) {
+        let dirs_changed = self
+            .font_dirs_watcher
+            .borrow()
+            .as_ref()
+            .map(FontDirsWatcher::take_changed)
+            .unwrap_or(false);
+        if dirs_changed {
+            // Fonts were added to or removed from the font_dirs;
+            // forget what we resolved so that they are scanned again
+            log::trace!("font_dirs changed, invalidating font caches");
+            fonts.clear();
+            self.color_glyphs.borrow_mut().clear();
+            self.design_coords.borrow_mut().clear();
+            self.metrics.borrow_mut().take();
+            self.locator.purge_cache();
+            *self.font_dirs_generation.borrow_mut() += 1;
         }
     }
 
@@ -105,15 +661,22 @@ impl FontConfiguration {
         if current_generation != *self.config_generation.borrow() {
             // Config was reloaded, invalidate our caches
             fonts.clear();
+            self.color_glyphs.borrow_mut().clear();
+            self.design_coords.borrow_mut().clear();
+            fonts.resize(config.font_cache_size.max(1));
             self.metrics.borrow_mut().take();
+            self.locator.purge_cache();
+            self.update_font_dirs_watcher(&config);
             *self.config_generation.borrow_mut() = current_generation;
         }
 
+        self.check_font_dirs(&mut fonts);
+
         if let Some(entry) = fonts.get(style) {
             return Ok(Rc::clone(entry));
         }
 
-        let attributes = style.font_with_fallback();
+        let attributes = style.font_with_fallback(&config);
         let preferred_attributes = attributes
             .iter()
             .filter(|a| !a.is_fallback)
@@ -126,30 +689,70 @@ impl FontConfiguration {
             .collect::<Vec<_>>();
         let mut loaded = HashSet::new();
 
-        let mut handles =
-            parser::ParsedFont::load_fonts(&config, &preferred_attributes, &mut loaded)?;
-        handles.append(
-            &mut self
-                .locator
-                .load_fonts(&preferred_attributes, &mut loaded)?,
+        let mut handles = vec![];
+        let mut sources = vec![];
+        let mut synthesize = vec![];
+        let mut design_coords = vec![];
+        let mut add_handles = |found: Vec<(FontDataHandle, FontSource, FontAttributes)>,
+                               synth: SyntheticStyle| {
+            for (handle, source, attr) in found {
+                design_coords.push(self.variation_design_coords(&handle, &attr));
+                handles.push(handle);
+                sources.push(source);
+                synthesize.push(synth);
+            }
+        };
+
+        add_handles(
+            self.load_handles(&config, &preferred_attributes, &mut loaded)?,
+            SyntheticStyle::default(),
+        );
+
+        // If a bold or italic variant was requested but could not be
+        // found, we can use the regular variant of the same font and
+        // embolden and/or slant it
+        let mut synthesized = HashSet::new();
+        let mut synthetic_loaded = HashSet::new();
+        for attr in &preferred_attributes {
+            if loaded.contains(attr) {
+                continue;
+            }
+            let synth = SyntheticStyle {
+                bold: attr.bold && config.synthesize_bold,
+                italic: if attr.italic && config.synthesize_italic {
+                    Some(config.synthetic_italic_angle)
+                } else {
+                    None
+                },
+            };
+            if synth == SyntheticStyle::default() {
+                continue;
+            }
+            let regular = FontAttributes {
+                bold: attr.bold && !synth.bold,
+                italic: attr.italic && synth.italic.is_none(),
+                weight: if synth.bold { None } else { attr.weight },
+                ..attr.clone()
+            };
+            let found = self.load_handles(
+                &config,
+                std::slice::from_ref(&regular),
+                &mut synthetic_loaded,
+            )?;
+            if !found.is_empty() {
+                log::trace!("synthesizing {:?} for {}", synth, attr);
+                synthesized.insert(attr.clone());
+            }
+            add_handles(found, synth);
+        }
+
+        add_handles(
+            self.load_handles(&config, &fallback_attributes, &mut loaded)?,
+            SyntheticStyle::default(),
         );
-        handles.append(&mut parser::ParsedFont::load_built_in_fonts(
-            &preferred_attributes,
-            &mut loaded,
-        )?);
-        handles.append(&mut parser::ParsedFont::load_fonts(
-            &config,
-            &fallback_attributes,
-            &mut loaded,
-        )?);
-        handles.append(&mut self.locator.load_fonts(&fallback_attributes, &mut loaded)?);
-        handles.append(&mut parser::ParsedFont::load_built_in_fonts(
-            &fallback_attributes,
-            &mut loaded,
-        )?);
 
         for attr in &attributes {
-            if !attr.is_fallback && !loaded.contains(attr) {
+            if !attr.is_fallback && !loaded.contains(attr) && !synthesized.contains(attr) {
                 let styled_extra = if attr.bold || attr.italic {
                     ". A bold or italic variant of the font was requested; \
                     TrueType and OpenType fonts don't have an automatic way to \
@@ -197,27 +800,176 @@ impl FontConfiguration {
         for _ in &handles {
             rasterizers.push(RefCell::new(None));
         }
-        let shaper = new_shaper(FontShaperSelection::get_default(), &handles)?;
+        // The shaper and rasterizer selections are part of the config,
+        // so changing them invalidates the cache along with everything else
+        let shaper_selection = FontShaperSelection::from_config(&config);
+        let shaper = match new_shaper(shaper_selection, &handles, &design_coords) {
+            Ok(shaper) => shaper,
+            Err(err) if shaper_selection != FontShaperSelection::default() => {
+                log::warn!(
+                    "Unable to use font_shaper={:?}: {:#}; using {:?} instead",
+                    shaper_selection,
+                    err,
+                    FontShaperSelection::default()
+                );
+                new_shaper(FontShaperSelection::default(), &handles, &design_coords)?
+            }
+            Err(err) => return Err(err),
+        };
+        let rasterizer_selection = FontRasterizerSelection::from_config(&config);
 
-        let config = configuration();
         let font_size = config.font_size * *self.font_scale.borrow();
-        let dpi = *self.dpi_scale.borrow() as u32 * config.dpi as u32;
+        let dpi = self.effective_dpi(&config);
         let metrics = shaper.metrics(font_size, dpi)?;
 
+        let scales = RefCell::new(vec![None; handles.len()]);
         let loaded = Rc::new(LoadedFont {
             rasterizers,
             handles,
+            sources,
+            synthesize,
+            design_coords,
+            color_glyphs: Rc::clone(&self.color_glyphs),
+            scales,
+            font_size_adjust: config.font_size_adjust,
             shaper,
+            rasterizer_selection,
             metrics,
             font_size,
             dpi,
+            features: style.font_features.clone(),
         });
 
-        fonts.insert(style.clone(), Rc::clone(&loaded));
+        fonts.put(style.clone(), Rc::clone(&loaded));
 
         Ok(loaded)
     }
 
+    /// Locate fonts matching the supplied attributes from each of
+    /// the available sources, in order of preference.  Every attribute
+    /// is tried against a source before moving on to the next source.
+    /// Each font is returned along with the attributes it was found for.
+    fn load_handles(
+        &self,
+        config: &ConfigHandle,
+        attributes: &[FontAttributes],
+        loaded: &mut HashSet<FontAttributes>,
+    ) -> anyhow::Result<Vec<(FontDataHandle, FontSource, FontAttributes)>> {
+        let mut handles = vec![];
+        let mut index = self.font_index.borrow_mut();
+        for (handle, attr) in
+            parser::ParsedFont::load_fonts(config, &mut index, attributes, loaded)?
+        {
+            handles.push((handle, FontSource::FontDirs, attr));
+        }
+        // The locator doesn't say which attributes its fonts were
+        // found for, so ask about each of them in turn
+        for attr in attributes {
+            for handle in self
+                .locator
+                .load_fonts(std::slice::from_ref(attr), loaded)?
+            {
+                handles.push((handle, FontSource::Locator, attr.clone()));
+            }
+        }
+        // Only walk the system font directories for the fonts that
+        // the locator couldn't find
+        let missing = attributes
+            .iter()
+            .filter(|attr| !loaded.contains(attr))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            let mut index = self.system_font_index.borrow_mut();
+            for (handle, attr) in
+                parser::ParsedFont::load_system_fonts(config, &mut index, &missing, loaded)?
+            {
+                handles.push((handle, FontSource::SystemFontDirs, attr));
+            }
+        }
+        for (handle, attr) in parser::ParsedFont::load_built_in_fonts(attributes, loaded)? {
+            handles.push((handle, FontSource::BuiltIn, attr));
+        }
+        Ok(handles)
+    }
+
+    /// Returns the design coordinates of the instance of a variable font
+    /// that is selected by `attr`, or an empty list for the default
+    /// instance.  Finding them means parsing the font, so they are cached.
+    fn variation_design_coords(&self, handle: &FontDataHandle, attr: &FontAttributes) -> Vec<f64> {
+        if attr.instance.is_none() && attr.variations.is_empty() {
+            return vec![];
+        }
+        self.design_coords
+            .borrow_mut()
+            .entry((handle.key(), attr.instance.clone(), attr.variations.clone()))
+            .or_insert_with(|| match parser::ParsedFont::from_locator(handle) {
+                Ok(parsed) => parsed.design_coords(attr),
+                Err(err) => {
+                    log::warn!("unable to apply variations to {:?}: {:#}", handle, err);
+                    vec![]
+                }
+            })
+            .clone()
+    }
+
+    /// Resolve the font for the specified style and explain which
+    /// fonts make up its fallback chain, and where they came from.
+    pub fn explain_resolution(&self, style: &TextStyle) -> anyhow::Result<Vec<FallbackInfo>> {
+        let font = self.resolve_font(style)?;
+        Ok(font
+            .handles
+            .iter()
+            .zip(font.sources.iter())
+            .map(|(handle, source)| FallbackInfo {
+                name: font_handle_name(handle),
+                handle: handle.clone(),
+                source: *source,
+            })
+            .collect())
+    }
+
+    /// Returns the fonts that are available to be loaded from the
+    /// font_dirs, the system font locator and those built in to
+    /// wezterm, in that order, de-duplicated by family and sub-family
+    pub fn list_available(&self) -> anyhow::Result<Vec<FontEnumEntry>> {
+        let mut entries = vec![];
+        {
+            let mut index = self.font_index.borrow_mut();
+            index.scan(&configuration().font_dirs);
+            if let Err(err) = index.save() {
+                log::warn!("failed to save font index: {:#}", err);
+            }
+            let mut font_info = index.font_info();
+            font_info.sort_by(|a, b| a.1.cmp(&b.1));
+            entries.extend(
+                font_info
+                    .into_iter()
+                    .map(|(names, _, handle)| FontEnumEntry::from_names(&names, handle)),
+            );
+        }
+        entries.extend(self.locator.list_available()?);
+        entries.extend(parser::ParsedFont::list_built_in_fonts());
+        Ok(dedup_font_entries(entries))
+    }
+
+    /// Discard the font index and parse all of the fonts in the
+    /// font_dirs again.  Fonts are resolved again on next use.
+    pub fn rebuild_font_index(&self) -> anyhow::Result<()> {
+        let mut index = self.font_index.borrow_mut();
+        index.clear();
+        index.scan(&configuration().font_dirs);
+        index.save()?;
+        // The system font directories are scanned again on demand
+        let mut system_index = self.system_font_index.borrow_mut();
+        system_index.clear();
+        system_index.save()?;
+        self.locator.purge_cache();
+        self.fonts.borrow_mut().clear();
+        self.metrics.borrow_mut().take();
+        Ok(())
+    }
+
     pub fn change_scaling(&self, font_scale: f64, dpi_scale: f64) {
         *self.dpi_scale.borrow_mut() = dpi_scale;
         *self.font_scale.borrow_mut() = font_scale;
@@ -234,6 +986,13 @@ impl FontConfiguration {
         *self.font_scale.borrow()
     }
 
+    /// Returns the DPI at which fonts are rendered; this is the
+    /// configured DPI multiplied by the display scale factor, which
+    /// may be fractional, eg: 1.25 or 1.5
+    fn effective_dpi(&self, config: &ConfigHandle) -> u32 {
+        (*self.dpi_scale.borrow() * config.dpi).round() as u32
+    }
+
     pub fn default_font_metrics(&self) -> Result<FontMetrics, Error> {
         {
             let metrics = self.metrics.borrow();
@@ -258,35 +1017,622 @@ impl FontConfiguration {
         config: &'a ConfigHandle,
         attrs: &CellAttributes,
     ) -> &'a TextStyle {
-        // a little macro to avoid boilerplate for matching the rules.
-        // If the rule doesn't specify a value for an attribute then
-        // it will implicitly match.  If it specifies an attribute
-        // then it has to have the same value as that in the input attrs.
-        macro_rules! attr_match {
-            ($ident:ident, $rule:expr) => {
-                if let Some($ident) = $rule.$ident {
-                    if $ident != attrs.$ident() {
-                        // Does not match
-                        continue;
-                    }
+        config
+            .font_rules
+            .iter()
+            .find(|rule| style_rule_matches(rule, attrs))
+            .map_or(&config.font, |rule| &rule.font)
+    }
+}
+
+/// Returns the palette index used by a color, if any
+fn palette_index(color: ColorAttribute) -> Option<u8> {
+    match color {
+        ColorAttribute::PaletteIndex(idx) => Some(idx),
+        _ => None,
+    }
+}
+
+/// Returns true if all of the attributes specified by the rule
+/// match those of the supplied input cell attributes.
+fn style_rule_matches(rule: &StyleRule, attrs: &CellAttributes) -> bool {
+    // a little macro to avoid boilerplate for matching the rules.
+    // If the rule doesn't specify a value for an attribute then
+    // it will implicitly match.  If it specifies an attribute
+    // then it has to have the same value as that in the input attrs.
+    macro_rules! attr_match {
+        ($ident:ident, $rule:expr) => {
+            if let Some($ident) = $rule.$ident {
+                if $ident != attrs.$ident() {
+                    // Does not match
+                    return false;
                 }
-                // matches so far...
-            };
+            }
+            // matches so far...
+        };
+    };
+
+    // Likewise for the colors, which match on the palette index;
+    // a rule that specifies one doesn't match the default color
+    // or a true color.
+    macro_rules! color_match {
+        ($ident:ident, $rule:expr) => {
+            if let Some($ident) = $rule.$ident {
+                if Some($ident) != palette_index(attrs.$ident) {
+                    return false;
+                }
+            }
         };
+    };
+
+    attr_match!(intensity, &rule);
+    attr_match!(underline, &rule);
+    attr_match!(italic, &rule);
+    attr_match!(blink, &rule);
+    attr_match!(reverse, &rule);
+    attr_match!(strikethrough, &rule);
+    attr_match!(invisible, &rule);
+    color_match!(foreground, &rule);
+    color_match!(background, &rule);
+
+    // If we get here, then none of the rules didn't match,
+    // so we therefore assume that it did match overall.
+    true
+}
 
-        for rule in &config.font_rules {
-            attr_match!(intensity, &rule);
-            attr_match!(underline, &rule);
-            attr_match!(italic, &rule);
-            attr_match!(blink, &rule);
-            attr_match!(reverse, &rule);
-            attr_match!(strikethrough, &rule);
-            attr_match!(invisible, &rule);
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::color::RgbColor;
 
-            // If we get here, then none of the rules didn't match,
-            // so we therefore assume that it did match overall.
-            return &rule.font;
+    #[test]
+    fn font_cache_evicts_least_recently_used() {
+        // Avoid scanning the system fonts; the built-in fonts are
+        // sufficient to resolve the default style.
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+        let cap = configuration().font_cache_size;
+
+        let styles: Vec<TextStyle> = (0..cap + 4)
+            .map(|i| TextStyle {
+                foreground: Some(RgbColor::new(i as u8, 0, 0)),
+                ..TextStyle::default()
+            })
+            .collect();
+
+        for style in &styles {
+            fonts.resolve_font(style).unwrap();
+        }
+
+        let cache = fonts.fonts.borrow();
+        assert_eq!(cache.len(), cap);
+        for style in &styles[..4] {
+            assert!(!cache.contains(style), "{:?} should be evicted", style);
+        }
+        for style in &styles[4..] {
+            assert!(cache.contains(style), "{:?} should be cached", style);
+        }
+    }
+
+    #[test]
+    fn fractional_dpi_scale() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+        let config = configuration();
+        assert_eq!(config.dpi, 96.);
+
+        let font = fonts.resolve_font(&TextStyle::default()).unwrap();
+        assert_eq!(font.dpi, 96);
+        let height = font.metrics().cell_height.get();
+
+        fonts.change_scaling(1.0, 1.5);
+        assert_eq!(fonts.effective_dpi(&config), 144);
+        let font = fonts.resolve_font(&TextStyle::default()).unwrap();
+        assert_eq!(font.dpi, 144);
+        assert!(font.metrics().cell_height.get() > height * 1.4);
+        assert_eq!(fonts.default_font_metrics().unwrap(), font.metrics());
+
+        fonts.change_scaling(1.0, 1.25);
+        assert_eq!(fonts.effective_dpi(&config), 120);
+    }
+
+    #[test]
+    fn explain_default_resolution() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+
+        let info = fonts.explain_resolution(&TextStyle::default()).unwrap();
+        // The default font is our bundled JetBrains Mono, followed by
+        // the bundled emoji and last resort fallbacks
+        assert_eq!(info[0].name, "JetBrains Mono Regular");
+        assert_eq!(info[0].source, FontSource::BuiltIn);
+        assert_eq!(info.last().unwrap().source, FontSource::BuiltIn);
+
+        let names = fonts
+            .resolve_font(&TextStyle::default())
+            .unwrap()
+            .fallback_names();
+        assert_eq!(
+            names,
+            info.iter().map(|i| i.name.clone()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn list_built_in_fonts() {
+        // The system fonts vary from one machine to the next, so only
+        // the built-in fonts are listed here
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+
+        let entries = fonts.list_available().unwrap();
+        let styles = entries
+            .iter()
+            .filter(|e| e.family == "JetBrains Mono")
+            .map(|e| e.sub_family.as_str())
+            .collect::<HashSet<_>>();
+        for style in &["Regular", "Bold", "Italic", "Bold Italic"] {
+            assert!(
+                styles.contains(style),
+                "JetBrains Mono {} should be listed in {:?}",
+                style,
+                styles
+            );
+        }
+        assert!(entries.iter().any(|e| e.family == "Noto Color Emoji"));
+
+        // There are no duplicates of a family and sub-family
+        let unique = entries
+            .iter()
+            .map(|e| (&e.family, &e.sub_family))
+            .collect::<HashSet<_>>();
+        assert_eq!(unique.len(), entries.len());
+    }
+
+    /// A locator that finds nothing, but counts the attributes that
+    /// it was asked to find
+    struct CountingLocator {
+        requests: Rc<RefCell<usize>>,
+    }
+
+    impl FontLocator for CountingLocator {
+        fn load_fonts(
+            &self,
+            fonts_selection: &[FontAttributes],
+            _loaded: &mut HashSet<FontAttributes>,
+        ) -> anyhow::Result<Vec<FontDataHandle>> {
+            *self.requests.borrow_mut() += fonts_selection.len();
+            Ok(vec![])
+        }
+
+        fn list_available(&self) -> anyhow::Result<Vec<FontEnumEntry>> {
+            Ok(vec![])
         }
-        &config.font
+    }
+
+    #[test]
+    fn locator_results_are_cached() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let requests = Rc::new(RefCell::new(0));
+        let fonts = FontConfiguration::with_locator(Box::new(CachingLocator::new(Box::new(
+            CountingLocator {
+                requests: Rc::clone(&requests),
+            },
+        ))));
+
+        let style = |red| TextStyle {
+            foreground: Some(RgbColor::new(red, 0, 0)),
+            ..TextStyle::default()
+        };
+
+        fonts.resolve_font(&style(1)).unwrap();
+        let first = *requests.borrow();
+        assert!(first > 0);
+
+        // A different style that uses the same fonts isn't in the
+        // cache of resolved fonts, but the locator isn't asked again
+        fonts.resolve_font(&style(2)).unwrap();
+        assert_eq!(*requests.borrow(), first);
+
+        fonts.locator.purge_cache();
+        fonts.resolve_font(&style(3)).unwrap();
+        assert_eq!(*requests.borrow(), 2 * first);
+    }
+
+    #[test]
+    fn font_dirs_change_clears_cache() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let requests = Rc::new(RefCell::new(0));
+        let fonts = FontConfiguration::with_locator(Box::new(CachingLocator::new(Box::new(
+            CountingLocator {
+                requests: Rc::clone(&requests),
+            },
+        ))));
+        let dir = tempdir::TempDir::new("font_dirs").unwrap();
+        fonts.font_dirs_watcher.replace(Some(
+            FontDirsWatcher::new(vec![dir.path().to_path_buf()]).unwrap(),
+        ));
+
+        let style = |red| TextStyle {
+            foreground: Some(RgbColor::new(red, 0, 0)),
+            ..TextStyle::default()
+        };
+
+        fonts.resolve_font(&style(1)).unwrap();
+        let first = *requests.borrow();
+        assert!(fonts.fonts.borrow().contains(&style(1)));
+
+        // Notices precede the real event and don't invalidate anything
+        let font_path = dir.path().join("new.ttf");
+        fonts
+            .font_dirs_watcher
+            .borrow()
+            .as_ref()
+            .unwrap()
+            .simulate_event(notify::DebouncedEvent::NoticeWrite(font_path.clone()));
+        fonts.resolve_font(&style(2)).unwrap();
+        assert!(fonts.fonts.borrow().contains(&style(1)));
+        assert_eq!(*requests.borrow(), first);
+        assert_eq!(fonts.font_dirs_generation(), 0);
+
+        fonts
+            .font_dirs_watcher
+            .borrow()
+            .as_ref()
+            .unwrap()
+            .simulate_event(notify::DebouncedEvent::Create(font_path));
+        // The window polls the generation so that it can discard
+        // the glyphs that it shaped and rasterized from the old fonts
+        assert_eq!(fonts.font_dirs_generation(), 1);
+        assert_eq!(fonts.font_dirs_generation(), 1);
+        fonts.resolve_font(&style(3)).unwrap();
+        let cache = fonts.fonts.borrow();
+        assert!(!cache.contains(&style(1)));
+        assert!(!cache.contains(&style(2)));
+        assert!(cache.contains(&style(3)));
+        // The locator was asked again
+        assert_eq!(*requests.borrow(), 2 * first);
+    }
+
+    /// Finds the variable font fixture for the "Variable Test" family
+    struct VariableTestLocator;
+
+    impl FontLocator for VariableTestLocator {
+        fn load_fonts(
+            &self,
+            fonts_selection: &[FontAttributes],
+            loaded: &mut HashSet<FontAttributes>,
+        ) -> anyhow::Result<Vec<FontDataHandle>> {
+            let mut handles = vec![];
+            for attr in fonts_selection {
+                if attr.family == "Variable Test" {
+                    handles.push(FontDataHandle::Memory {
+                        data: include_bytes!("../../test-data/variable/VariableTest-Regular.ttf")
+                            .to_vec(),
+                        index: 0,
+                        name: "VariableTest-Regular".to_string(),
+                    });
+                    loaded.insert(attr.clone());
+                }
+            }
+            Ok(handles)
+        }
+
+        fn list_available(&self) -> anyhow::Result<Vec<FontEnumEntry>> {
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn sources_are_tried_in_order() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::with_locator(Box::new(VariableTestLocator));
+        let style = TextStyle {
+            font: vec![
+                FontAttributes::new("JetBrains Mono"),
+                FontAttributes {
+                    variations: vec!["wght=700".parse().unwrap()],
+                    ..FontAttributes::new("Variable Test")
+                },
+            ],
+            ..TextStyle::default()
+        };
+
+        // Every family is looked up by the locator before falling
+        // back to the built-in fonts, so the located font comes first
+        // even though it is listed second
+        let info = fonts.explain_resolution(&style).unwrap();
+        assert_eq!(info[0].name, "Variable Test Regular");
+        assert_eq!(info[0].source, FontSource::Locator);
+        assert_eq!(info[1].name, "JetBrains Mono Regular");
+        assert_eq!(info[1].source, FontSource::BuiltIn);
+
+        // The located font uses the variations of the attributes that
+        // it was found for, which are remembered for the next style
+        let font = fonts.resolve_font(&style).unwrap();
+        assert_eq!(font.design_coords[0], vec![700.]);
+        assert!(font.design_coords[1].is_empty());
+        let bold = TextStyle {
+            foreground: Some(RgbColor::new(1, 0, 0)),
+            ..style
+        };
+        fonts.resolve_font(&bold).unwrap();
+        assert_eq!(fonts.design_coords.borrow().len(), 1);
+    }
+
+    #[test]
+    fn fallback_x_heights_converge() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+
+        // Last Resort has a larger x-height than JetBrains Mono,
+        // which is added to the fallback chain after it
+        let style = TextStyle {
+            font: vec![FontAttributes::new("Last Resort High-Efficiency")],
+            ..TextStyle::default()
+        };
+        let font = fonts.resolve_font(&style).unwrap();
+        assert_eq!(font.fallback_names()[1], "JetBrains Mono Regular");
+        let (primary, _) = font.em_heights(0);
+        let (fallback, _) = font.em_heights(1);
+        assert!((primary.unwrap() - 1195. / 2048.).abs() < 1e-9);
+        assert!((fallback.unwrap() - 0.55).abs() < 1e-9);
+
+        let scale = font.size_adjust_scale(1);
+        assert!(scale > 1.0);
+        assert!((fallback.unwrap() * scale - primary.unwrap()).abs() < 1e-9);
+
+        // font_size_adjust is disabled by default
+        assert!((font.fallback_scale(1).unwrap() - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn fallback_cap_heights_converge() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+        let font = fonts.resolve_font(&TextStyle::default()).unwrap();
+        let emoji = font
+            .fallback_names()
+            .iter()
+            .position(|name| name.starts_with("Noto Color Emoji"))
+            .unwrap();
+
+        // The emoji font has no x-height, so the cap heights are matched
+        let (x_height, cap_height) = font.em_heights(emoji);
+        assert_eq!(x_height, None);
+        let scale = font.size_adjust_scale(emoji);
+        assert!((cap_height.unwrap() * scale - 0.73).abs() < 1e-9);
+    }
+
+    #[test]
+    fn fallback_glyphs_use_the_adjusted_size() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+        let font = fonts.resolve_font(&TextStyle::default()).unwrap();
+
+        // The emoji comes from a fallback that the shaper reaches on
+        // its own, so it is shaped at the primary font size; its advance
+        // must match shaping it directly at the fallback's adjusted size
+        let glyphs = font.shape("\u{1F600}").unwrap();
+        let font_idx = glyphs[0].font_idx;
+        assert_ne!(font_idx, 0);
+
+        let size = font.font_size * font.fallback_scale(font_idx).unwrap();
+        let direct = font
+            .shaper
+            .shape_from(font_idx, "\u{1F600}", size, font.dpi, &font.features)
+            .unwrap();
+        assert!(
+            (glyphs[0].x_advance.get() - direct[0].x_advance.get()).abs() <= 1.0,
+            "fallback advance {} should match {} at the adjusted size",
+            glyphs[0].x_advance.get(),
+            direct[0].x_advance.get()
+        );
+    }
+
+    #[test]
+    fn shape_falls_back_for_missing_glyphs() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+        let font = fonts.resolve_font(&TextStyle::default()).unwrap();
+
+        let text = "a\u{1F600}b";
+        let emoji_cluster = 1;
+        let b_cluster = 1 + '\u{1F600}'.len_utf8() as u32;
+
+        let glyphs = font.shape(text).unwrap();
+        let clusters = glyphs.iter().map(|g| g.cluster).collect::<Vec<_>>();
+        assert_eq!(clusters, vec![0, emoji_cluster, b_cluster]);
+        assert_eq!(glyphs[0].font_idx, 0);
+        assert_ne!(glyphs[1].font_idx, 0);
+        assert_eq!(glyphs[2].font_idx, 0);
+
+        // Simulate the primary font producing .notdef for the emoji
+        // and verify that it is replaced by the fallback's glyph
+        let mut missing = glyphs.clone();
+        missing[1].font_idx = 0;
+        missing[1].glyph_pos = 0;
+        let fixed = font.reshape_missing(text, missing).unwrap();
+        assert_eq!(
+            fixed.iter().map(|g| g.cluster).collect::<Vec<_>>(),
+            clusters
+        );
+        assert_eq!(fixed[1].font_idx, glyphs[1].font_idx);
+        assert_eq!(fixed[1].glyph_pos, glyphs[1].glyph_pos);
+    }
+
+    #[test]
+    fn variation_selector_presentation() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+        let font = fonts.resolve_font(&TextStyle::default()).unwrap();
+
+        // JetBrains Mono and Noto Color Emoji both have a glyph
+        // for the play button, U+25B6
+        let plain = font.shape("\u{25b6}").unwrap();
+        assert_eq!(plain[0].font_idx, 0);
+        assert!(!font.has_color(0).unwrap());
+
+        let emoji = font.shape("\u{25b6}\u{fe0f}").unwrap();
+        let text = font.shape("\u{25b6}\u{fe0e}").unwrap();
+        assert_ne!(emoji[0].font_idx, text[0].font_idx);
+        assert!(font.has_color(emoji[0].font_idx).unwrap());
+        assert!(!font.has_color(text[0].font_idx).unwrap());
+        assert!(emoji.iter().chain(text.iter()).all(|g| g.cluster == 0));
+
+        // The presentation applies only to the character that it follows
+        let glyphs = font.shape("a\u{25b6}\u{fe0f}b").unwrap();
+        let idx_for = |cluster: u32| {
+            glyphs
+                .iter()
+                .find(|g| g.cluster == cluster)
+                .map(|g| g.font_idx)
+                .unwrap()
+        };
+        assert_eq!(idx_for(0), 0);
+        assert_eq!(idx_for(1), emoji[0].font_idx);
+        assert_eq!(idx_for(7), 0);
+    }
+
+    #[test]
+    fn symbol_fallback() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+
+        // The bundled symbols are opt-in, so without them a rounded
+        // Powerline separator is only found in the last resort font
+        let font = fonts.resolve_font(&TextStyle::default()).unwrap();
+        assert!(!font
+            .fallback_names()
+            .contains(&"Wezterm Symbols Regular".to_string()));
+
+        // Enabling them adds the bundled font to the fallbacks
+        let mut config = config::Config::default_config();
+        config.font_symbol_fallback = true;
+        let mut style = TextStyle::default();
+        style.font = style.font_with_fallback(&config);
+        let font = fonts.resolve_font(&style).unwrap();
+        let names = font.fallback_names();
+        let position = |name: &str| names.iter().position(|n| n.starts_with(name)).unwrap();
+        let symbols = position("Wezterm Symbols");
+        assert!(symbols < position("Last Resort"));
+
+        // A separator, a flame, a hexagon, a progress bar and a spinner
+        for text in &["\u{e0b4}", "\u{e0c0}", "\u{e0cc}", "\u{ee04}", "\u{ee0b}"] {
+            let glyphs = font.shape(text).unwrap();
+            assert_eq!(glyphs.len(), 1);
+            assert_eq!(glyphs[0].font_idx, symbols, "{:?}", text);
+            assert_ne!(glyphs[0].glyph_pos, 0);
+        }
+
+        // JetBrains Mono has the core Powerline symbols, which the
+        // bundled font leaves out, and the Nerd Font icons aren't
+        // bundled at all
+        let glyphs = font.shape("\u{e0b0}").unwrap();
+        assert_eq!(glyphs[0].font_idx, 0);
+        let glyphs = font.shape("\u{f015}").unwrap();
+        assert_ne!(glyphs[0].font_idx, symbols);
+    }
+
+    #[test]
+    fn split_ligature_under_cursor() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+        let font = fonts.resolve_font(&TextStyle::default()).unwrap();
+
+        let glyph_for = |glyphs: &[GlyphInfo], cluster: u32| {
+            glyphs
+                .iter()
+                .find(|g| g.cluster == cluster)
+                .map(|g| g.glyph_pos)
+        };
+        let plain_arrow = font.shape_without_ligatures("->").unwrap();
+        let plain_hyphen = glyph_for(&plain_arrow, 0).unwrap();
+        let plain_greater = glyph_for(&plain_arrow, 1).unwrap();
+
+        // JetBrains Mono renders "->" as an arrow ligature
+        let text = "a->b";
+        let glyphs = font.shape(text).unwrap();
+        assert_ne!(glyph_for(&glyphs, 2), Some(plain_greater));
+
+        // With the cursor on the ">", that cell gets its own glyph
+        let split = font.split_ligature_at(text, &glyphs, 2).unwrap();
+        assert_eq!(glyph_for(&split, 2), Some(plain_greater));
+        let clusters = split.iter().map(|g| g.cluster).collect::<Vec<_>>();
+        let mut sorted = clusters.clone();
+        sorted.sort();
+        assert_eq!(clusters, sorted);
+        assert_eq!(glyph_for(&split, 0), glyph_for(&glyphs, 0));
+        assert_eq!(glyph_for(&split, 3), glyph_for(&glyphs, 3));
+
+        // Likewise for the "-"
+        let split = font.split_ligature_at(text, &glyphs, 1).unwrap();
+        assert_eq!(glyph_for(&split, 1), Some(plain_hyphen));
+    }
+
+    #[test]
+    fn font_rules_match_colors() {
+        use termwiz::color::AnsiColor;
+
+        let comments = StyleRule {
+            foreground: Some(AnsiColor::Green as u8),
+            ..StyleRule::default()
+        };
+        let mut attrs = CellAttributes::default();
+        assert!(!style_rule_matches(&comments, &attrs));
+
+        attrs.set_foreground(AnsiColor::Green);
+        assert!(style_rule_matches(&comments, &attrs));
+        // The background doesn't matter to this rule
+        attrs.set_background(AnsiColor::Navy);
+        assert!(style_rule_matches(&comments, &attrs));
+
+        attrs.set_foreground(AnsiColor::Red);
+        assert!(!style_rule_matches(&comments, &attrs));
+        // A true color doesn't match a palette index
+        attrs.set_foreground(ColorAttribute::TrueColorWithDefaultFallback(RgbColor::new(
+            0, 0x80, 0,
+        )));
+        assert!(!style_rule_matches(&comments, &attrs));
+
+        // The colors combine with the other attributes
+        let highlight = StyleRule {
+            background: Some(AnsiColor::Navy as u8),
+            italic: Some(true),
+            ..StyleRule::default()
+        };
+        assert!(!style_rule_matches(&highlight, &attrs));
+        attrs.set_italic(true);
+        assert!(style_rule_matches(&highlight, &attrs));
+        attrs.set_background(ColorAttribute::Default);
+        assert!(!style_rule_matches(&highlight, &attrs));
+
+        // A rule without any attributes matches everything
+        assert!(style_rule_matches(&StyleRule::default(), &attrs));
+    }
+
+    #[test]
+    fn measure_text() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = FontConfiguration::new();
+        let font = fonts.resolve_font(&TextStyle::default()).unwrap();
+        let cell_width = font.metrics().cell_width.get();
+
+        let width = font.measure("ab").unwrap().get();
+        assert!(
+            (width - 2. * cell_width).abs() < 0.01,
+            "{} should be twice {}",
+            width,
+            cell_width
+        );
+
+        // The combining acute accent doesn't advance
+        let width = font.measure("e\u{301}").unwrap().get();
+        assert!(
+            (width - cell_width).abs() < 0.01,
+            "{} should be {}",
+            width,
+            cell_width
+        );
     }
 }
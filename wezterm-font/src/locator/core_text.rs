@@ -1,6 +1,6 @@
 #![cfg(target_os = "macos")]
 
-use crate::locator::{FontDataHandle, FontLocator};
+use crate::locator::{dedup_font_entries, FontDataHandle, FontEnumEntry, FontLocator};
 use config::FontAttributes;
 use core_foundation::base::{CFType, TCFType};
 use core_foundation::dictionary::CFDictionary;
@@ -22,7 +22,11 @@ fn descriptor_from_attr(attr: &FontAttributes) -> anyhow::Result<CTFontDescripto
         .map_err(|_| anyhow::anyhow!("failed to parse family name {} as CFString", attr.family))?;
 
     let symbolic_traits: CTFontSymbolicTraits = kCTFontMonoSpaceTrait
-        | if attr.bold { kCTFontBoldTrait } else { 0 }
+        | if attr.weight() >= 600 {
+            kCTFontBoldTrait
+        } else {
+            0
+        }
         | if attr.italic { kCTFontItalicTrait } else { 0 };
 
     let family_attr: CFString = unsafe { TCFType::wrap_under_get_rule(kCTFontFamilyNameAttribute) };
@@ -90,4 +94,23 @@ impl FontLocator for CoreTextFontLocator {
 
         Ok(fonts)
     }
+
+    fn list_available(&self) -> anyhow::Result<Vec<FontEnumEntry>> {
+        let collection = core_text::font_collection::create_for_all_families();
+        let descriptors = match collection.get_descriptors() {
+            Some(descriptors) => descriptors,
+            None => return Ok(vec![]),
+        };
+        let mut entries = vec![];
+        for descriptor in descriptors.iter() {
+            if let Ok(path) = font_path_from_descriptor(&descriptor) {
+                entries.push(FontEnumEntry {
+                    family: descriptor.family_name(),
+                    sub_family: descriptor.style_name(),
+                    handle: FontDataHandle::OnDisk { path, index: 0 },
+                });
+            }
+        }
+        Ok(dedup_font_entries(entries))
+    }
 }
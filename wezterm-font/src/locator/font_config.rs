@@ -1,6 +1,6 @@
 use crate::fcwrap;
-use crate::locator::{FontDataHandle, FontLocator};
-use config::FontAttributes;
+use crate::locator::{dedup_font_entries, FontDataHandle, FontEnumEntry, FontLocator};
+use config::{FontAttributes, FontStretch};
 use fcwrap::Pattern as FontPattern;
 use std::collections::HashSet;
 use std::convert::TryInto;
@@ -9,6 +9,57 @@ use std::convert::TryInto;
 /// functions provided by font-config
 pub struct FontConfigFontLocator {}
 
+/// Map an OpenType weight to the closest fontconfig weight constant
+fn fc_weight(weight: u16) -> i32 {
+    const WEIGHTS: &[(u16, i32)] = &[
+        (100, 0),   // FC_WEIGHT_THIN
+        (200, 40),  // FC_WEIGHT_EXTRALIGHT
+        (300, 50),  // FC_WEIGHT_LIGHT
+        (350, 55),  // FC_WEIGHT_DEMILIGHT
+        (380, 75),  // FC_WEIGHT_BOOK
+        (400, 80),  // FC_WEIGHT_REGULAR
+        (500, 100), // FC_WEIGHT_MEDIUM
+        (600, 180), // FC_WEIGHT_DEMIBOLD
+        (700, 200), // FC_WEIGHT_BOLD
+        (800, 205), // FC_WEIGHT_EXTRABOLD
+        (900, 210), // FC_WEIGHT_BLACK
+    ];
+    WEIGHTS
+        .iter()
+        .min_by_key(|(ot, _)| (i32::from(*ot) - i32::from(weight)).abs())
+        .map(|(_, fc)| *fc)
+        .unwrap_or(80)
+}
+
+/// Map a stretch to the corresponding fontconfig width constant
+fn fc_width(stretch: FontStretch) -> i32 {
+    match stretch {
+        FontStretch::UltraCondensed => 50,
+        FontStretch::ExtraCondensed => 63,
+        FontStretch::Condensed => 75,
+        FontStretch::SemiCondensed => 87,
+        FontStretch::Normal => 100,
+        FontStretch::SemiExpanded => 113,
+        FontStretch::Expanded => 125,
+        FontStretch::ExtraExpanded => 150,
+        FontStretch::UltraExpanded => 200,
+    }
+}
+
+/// Produce the listing for a font returned by FcFontList.
+/// The family and style may have values in several languages;
+/// the first of them is the name that fontconfig prefers.
+fn enum_entry(pat: &FontPattern) -> anyhow::Result<FontEnumEntry> {
+    Ok(FontEnumEntry {
+        family: pat.get_string("family")?,
+        sub_family: pat.get_string("style")?,
+        handle: FontDataHandle::OnDisk {
+            path: pat.get_file()?.into(),
+            index: pat.get_integer("index")?.try_into()?,
+        },
+    })
+}
+
 impl FontLocator for FontConfigFontLocator {
     fn load_fonts(
         &self,
@@ -20,7 +71,11 @@ impl FontLocator for FontConfigFontLocator {
         for attr in fonts_selection {
             let mut pattern = FontPattern::new()?;
             pattern.family(&attr.family)?;
-            pattern.add_integer("weight", if attr.bold { 200 } else { 80 })?;
+            // The family may be the PostScript name of a face, which
+            // fontconfig considers right after the family when sorting
+            pattern.add_string("postscriptname", &attr.family)?;
+            pattern.add_integer("weight", fc_weight(attr.weight()))?;
+            pattern.add_integer("width", fc_width(attr.stretch))?;
             pattern.add_integer("slant", if attr.italic { 100 } else { 0 })?;
             /*
             pattern.add_double("size", config.font_size * font_scale)?;
@@ -56,4 +111,16 @@ impl FontLocator for FontConfigFontLocator {
 
         Ok(fonts)
     }
+
+    fn list_available(&self) -> anyhow::Result<Vec<FontEnumEntry>> {
+        let pattern = FontPattern::new()?;
+        let mut entries = vec![];
+        for pat in pattern.list(&["family", "style", "file", "index"])?.iter() {
+            match enum_entry(&pat) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => log::trace!("skipping {:?}: {:#}", pat, err),
+            }
+        }
+        Ok(dedup_font_entries(entries))
+    }
 }
@@ -1,6 +1,6 @@
 #![cfg(windows)]
 
-use crate::locator::{FontDataHandle, FontLocator};
+use crate::locator::{dedup_font_entries, FontDataHandle, FontEnumEntry, FontLocator};
 use config::FontAttributes;
 use std::collections::HashSet;
 use winapi::shared::windef::HFONT;
@@ -58,7 +58,7 @@ impl FontLocator for GdiFontLocator {
                 lfWidth: 0,
                 lfEscapement: 0,
                 lfOrientation: 0,
-                lfWeight: if font_attr.bold { 700 } else { 0 },
+                lfWeight: i32::from(font_attr.weight()),
                 lfItalic: if font_attr.italic { 1 } else { 0 },
                 lfUnderline: 0,
                 lfStrikeOut: 0,
@@ -101,4 +101,33 @@ impl FontLocator for GdiFontLocator {
 
         Ok(fonts)
     }
+
+    fn list_available(&self) -> anyhow::Result<Vec<FontEnumEntry>> {
+        // GDI can only give us the data of a font, rather than its
+        // location, so we ask DirectWrite for the font files instead
+        let collection = dwrote::FontCollection::system();
+        let mut entries = vec![];
+        for family in collection.families_iter() {
+            let family_name = family.name();
+            for idx in 0..family.get_font_count() {
+                let font = family.get_font(idx);
+                let face = font.create_font_face();
+                let path = face
+                    .get_files()
+                    .iter()
+                    .find_map(|file| file.get_font_file_path());
+                if let Some(path) = path {
+                    entries.push(FontEnumEntry {
+                        family: family_name.clone(),
+                        sub_family: font.face_name(),
+                        handle: FontDataHandle::OnDisk {
+                            path,
+                            index: face.get_index(),
+                        },
+                    });
+                }
+            }
+        }
+        Ok(dedup_font_entries(entries))
+    }
 }
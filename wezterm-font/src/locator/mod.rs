@@ -1,5 +1,7 @@
+use crate::parser::Names;
 use config::FontAttributes;
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
 pub mod core_text;
@@ -27,6 +29,30 @@ pub enum FontDataHandle {
     },
 }
 
+/// Identifies the font that a FontDataHandle refers to, without
+/// holding on to its data, so that information about the font
+/// can be cached
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FontDataKey {
+    OnDisk { path: PathBuf, index: u32 },
+    Memory { name: String, index: u32 },
+}
+
+impl FontDataHandle {
+    pub fn key(&self) -> FontDataKey {
+        match self {
+            Self::OnDisk { path, index } => FontDataKey::OnDisk {
+                path: path.clone(),
+                index: *index,
+            },
+            Self::Memory { name, index, .. } => FontDataKey::Memory {
+                name: name.clone(),
+                index: *index,
+            },
+        }
+    }
+}
+
 impl std::fmt::Debug for FontDataHandle {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         match self {
@@ -45,6 +71,36 @@ impl std::fmt::Debug for FontDataHandle {
     }
 }
 
+/// Describes an installed font, such as might be shown in a font picker
+#[derive(Debug, Clone)]
+pub struct FontEnumEntry {
+    /// The family name, eg: "JetBrains Mono"
+    pub family: String,
+    /// The style within the family, eg: "Bold Italic"
+    pub sub_family: String,
+    pub handle: FontDataHandle,
+}
+
+impl FontEnumEntry {
+    pub fn from_names(names: &Names, handle: FontDataHandle) -> Self {
+        Self {
+            family: names.family().to_string(),
+            sub_family: names.sub_family().to_string(),
+            handle,
+        }
+    }
+}
+
+/// Remove the entries whose family and sub-family are the same as
+/// those of an earlier entry, preserving the order of the others
+pub fn dedup_font_entries(entries: Vec<FontEnumEntry>) -> Vec<FontEnumEntry> {
+    let mut seen = HashSet::new();
+    entries
+        .into_iter()
+        .filter(|entry| seen.insert((entry.family.clone(), entry.sub_family.clone())))
+        .collect()
+}
+
 pub trait FontLocator {
     /// Given a font selection, return the list of successfully loadable
     /// FontDataHandle's that correspond to it
@@ -53,6 +109,70 @@ pub trait FontLocator {
         fonts_selection: &[FontAttributes],
         loaded: &mut HashSet<FontAttributes>,
     ) -> anyhow::Result<Vec<FontDataHandle>>;
+
+    /// Return all of the fonts that this locator is able to find,
+    /// de-duplicated by family and sub-family
+    fn list_available(&self) -> anyhow::Result<Vec<FontEnumEntry>>;
+
+    /// Forget any previously discovered fonts, so that the next
+    /// request finds them again
+    fn purge_cache(&self) {}
+}
+
+/// Wraps a locator and remembers the fonts that it found for each of
+/// the requested attributes, so that the system fonts needn't be
+/// enumerated again each time a style is resolved
+pub struct CachingLocator {
+    inner: Box<dyn FontLocator>,
+    cache: RefCell<HashMap<FontAttributes, Vec<FontDataHandle>>>,
+}
+
+impl CachingLocator {
+    pub fn new(inner: Box<dyn FontLocator>) -> Self {
+        Self {
+            inner,
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl FontLocator for CachingLocator {
+    fn load_fonts(
+        &self,
+        fonts_selection: &[FontAttributes],
+        loaded: &mut HashSet<FontAttributes>,
+    ) -> anyhow::Result<Vec<FontDataHandle>> {
+        let mut fonts = vec![];
+        for attr in fonts_selection {
+            if !self.cache.borrow().contains_key(attr) {
+                // The locators consider each attribute independently,
+                // so the results can be remembered per attribute.
+                // Misses are remembered too, as those are the most
+                // costly to look up.
+                let mut found = HashSet::new();
+                let handles = self
+                    .inner
+                    .load_fonts(std::slice::from_ref(attr), &mut found)?;
+                self.cache.borrow_mut().insert(attr.clone(), handles);
+            }
+            let cache = self.cache.borrow();
+            let handles = &cache[attr];
+            if !handles.is_empty() {
+                fonts.extend(handles.iter().cloned());
+                loaded.insert(attr.clone());
+            }
+        }
+        Ok(fonts)
+    }
+
+    fn list_available(&self) -> anyhow::Result<Vec<FontEnumEntry>> {
+        self.inner.list_available()
+    }
+
+    fn purge_cache(&self) {
+        self.cache.borrow_mut().clear();
+        self.inner.purge_cache();
+    }
 }
 
 pub fn new_locator(locator: FontLocatorSelection) -> Box<dyn FontLocator> {
@@ -91,4 +211,8 @@ impl FontLocator for NopSystemSource {
     ) -> anyhow::Result<Vec<FontDataHandle>> {
         Ok(vec![])
     }
+
+    fn list_available(&self) -> anyhow::Result<Vec<FontEnumEntry>> {
+        Ok(vec![])
+    }
 }
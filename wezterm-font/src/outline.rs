@@ -0,0 +1,174 @@
+//! Extracts glyph outlines by having FreeType decompose them.
+//!
+//! This is intended for tooling that wants to inspect or export the
+//! shape of a glyph rather than for rendering, which is left to the
+//! rasterizer.  The outlines are those of the default instance of a
+//! variable font and are expressed in font units, with the y axis
+//! pointing up, just as they are stored in the font.
+use crate::ftwrap::{
+    succeeded, FT_Outline, FT_Outline_Decompose, FT_Outline_Funcs, FT_Pos, FT_Vector,
+};
+use anyhow::bail;
+use std::os::raw::{c_int, c_void};
+
+/// A single step in the path that makes up a glyph outline.
+/// Each contour starts with a `MoveTo` and ends with a `Close`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathCommand {
+    MoveTo {
+        x: f32,
+        y: f32,
+    },
+    LineTo {
+        x: f32,
+        y: f32,
+    },
+    /// A quadratic bezier curve with a single control point,
+    /// as used by TrueType outlines
+    QuadTo {
+        x1: f32,
+        y1: f32,
+        x: f32,
+        y: f32,
+    },
+    /// A cubic bezier curve with two control points,
+    /// as used by CFF outlines
+    CubicTo {
+        x1: f32,
+        y1: f32,
+        x2: f32,
+        y2: f32,
+        x: f32,
+        y: f32,
+    },
+    Close,
+}
+
+/// The outline of a glyph along with its horizontal advance,
+/// both in font units
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphOutline {
+    pub commands: Vec<PathCommand>,
+    pub advance: f64,
+}
+
+impl GlyphOutline {
+    /// Returns the number of contours in the outline
+    pub fn num_contours(&self) -> usize {
+        self.commands
+            .iter()
+            .filter(|cmd| matches!(cmd, PathCommand::MoveTo { .. }))
+            .count()
+    }
+}
+
+/// Accumulates the commands produced by FT_Outline_Decompose
+#[derive(Default)]
+struct Decomposer {
+    commands: Vec<PathCommand>,
+    /// The first and most recent points of the current contour
+    start: Option<(FT_Pos, FT_Pos)>,
+    last: (FT_Pos, FT_Pos),
+}
+
+impl Decomposer {
+    /// Ends the current contour, if any.  FreeType closes each contour
+    /// with an explicit line back to its start, which is dropped in
+    /// favor of `Close`
+    fn close(&mut self) {
+        if let Some(start) = self.start.take() {
+            if start == self.last
+                && matches!(self.commands.last(), Some(PathCommand::LineTo { .. }))
+            {
+                self.commands.pop();
+            }
+            self.commands.push(PathCommand::Close);
+        }
+    }
+
+    /// Records `v` as the most recent point and returns it as a
+    /// pair of coordinates
+    unsafe fn point(&mut self, v: *const FT_Vector) -> (f32, f32) {
+        let v = &*v;
+        self.last = (v.x, v.y);
+        (v.x as f32, v.y as f32)
+    }
+}
+
+unsafe extern "C" fn move_to(to: *const FT_Vector, user: *mut c_void) -> c_int {
+    let decomposer = &mut *(user as *mut Decomposer);
+    decomposer.close();
+    let (x, y) = decomposer.point(to);
+    decomposer.start = Some(decomposer.last);
+    decomposer.commands.push(PathCommand::MoveTo { x, y });
+    0
+}
+
+unsafe extern "C" fn line_to(to: *const FT_Vector, user: *mut c_void) -> c_int {
+    let decomposer = &mut *(user as *mut Decomposer);
+    let (x, y) = decomposer.point(to);
+    decomposer.commands.push(PathCommand::LineTo { x, y });
+    0
+}
+
+unsafe extern "C" fn conic_to(
+    control: *const FT_Vector,
+    to: *const FT_Vector,
+    user: *mut c_void,
+) -> c_int {
+    let decomposer = &mut *(user as *mut Decomposer);
+    let (x1, y1) = decomposer.point(control);
+    let (x, y) = decomposer.point(to);
+    decomposer
+        .commands
+        .push(PathCommand::QuadTo { x1, y1, x, y });
+    0
+}
+
+unsafe extern "C" fn cubic_to(
+    control1: *const FT_Vector,
+    control2: *const FT_Vector,
+    to: *const FT_Vector,
+    user: *mut c_void,
+) -> c_int {
+    let decomposer = &mut *(user as *mut Decomposer);
+    let (x1, y1) = decomposer.point(control1);
+    let (x2, y2) = decomposer.point(control2);
+    let (x, y) = decomposer.point(to);
+    decomposer.commands.push(PathCommand::CubicTo {
+        x1,
+        y1,
+        x2,
+        y2,
+        x,
+        y,
+    });
+    0
+}
+
+/// Converts a FreeType outline into path commands.  The coordinates
+/// are taken as they are, so a glyph loaded with FT_LOAD_NO_SCALE
+/// produces commands in font units.
+pub fn decompose(outline: &mut FT_Outline) -> anyhow::Result<Vec<PathCommand>> {
+    let funcs = FT_Outline_Funcs {
+        move_to: Some(move_to),
+        line_to: Some(line_to),
+        conic_to: Some(conic_to),
+        cubic_to: Some(cubic_to),
+        shift: 0,
+        delta: 0,
+    };
+    let mut decomposer = Decomposer::default();
+    let res = unsafe {
+        FT_Outline_Decompose(
+            outline,
+            &funcs,
+            &mut decomposer as *mut Decomposer as *mut c_void,
+        )
+    };
+    if !succeeded(res) {
+        bail!("FT_Outline_Decompose failed: 0x{:x}", res);
+    }
+    decomposer.close();
+    Ok(decomposer.commands)
+}
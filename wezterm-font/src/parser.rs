@@ -1,10 +1,14 @@
 //! This module uses the allsorts crate to parse font data.
-//! At this time it is used only to extract name information,
-//! but in the future I'd like to use its shaping functionality
+//! It is used to extract name and metrics information, and its
+//! shaping functionality drives the `Allsorts` font shaper.
 #![allow(dead_code)]
-use crate::locator::FontDataHandle;
+use crate::fontindex::FontIndex;
+use crate::ftwrap::Library;
+use crate::locator::{FontDataHandle, FontEnumEntry};
+use crate::outline::GlyphOutline;
 use crate::shaper::{FallbackIdx, FontMetrics, GlyphInfo};
 use crate::units::*;
+use crate::variation::{Avar, Fvar, Hvar, Mvar, VariationAxis};
 use allsorts::binary::read::{ReadScope, ReadScopeOwned};
 use allsorts::font_data_impl::read_cmap_subtable;
 use allsorts::gpos::{gpos_apply, Info, Placement};
@@ -17,8 +21,9 @@ use allsorts::tables::{
 };
 use allsorts::tag;
 use anyhow::anyhow;
-use config::{Config, FontAttributes};
-use std::collections::HashSet;
+use config::{configuration, CellWidthReference, Config, FontAttributes};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::path::{Path, PathBuf};
 use termwiz::cell::unicode_column_width;
@@ -31,6 +36,96 @@ pub enum MaybeShaped {
     Unresolved { raw: String, slice_start: usize },
 }
 
+/// Returns the directories configured in fontconfig, when the fontconfig
+/// locator is in use, excluding any that are already in `font_dirs`.
+/// These are only scanned for fonts that the locator was unable to find.
+pub(crate) fn system_font_dirs(config: &Config) -> Vec<PathBuf> {
+    fontconfig_font_dirs()
+        .into_iter()
+        .filter(|dir| !config.font_dirs.contains(dir))
+        .collect()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn fontconfig_font_dirs() -> Vec<PathBuf> {
+    if config::FontLocatorSelection::get_default() != config::FontLocatorSelection::FontConfig {
+        return vec![];
+    }
+    match crate::fcwrap::font_dirs() {
+        Ok(dirs) => dirs,
+        Err(err) => {
+            log::warn!("unable to query fontconfig font dirs: {:#}", err);
+            vec![]
+        }
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn fontconfig_font_dirs() -> Vec<PathBuf> {
+    vec![]
+}
+
+/// Parse a feature setting in the syntax accepted by harfbuzz,
+/// eg: `liga`, `+liga`, `-liga`, `liga=0`, `liga=1`, and return
+/// the feature tag along with whether it is being enabled.
+fn parse_feature_setting(setting: &str) -> Option<(&str, bool)> {
+    let setting = setting.trim();
+    let (tag, enable) = if let Some(tag) = setting.strip_prefix('-') {
+        (tag, false)
+    } else if let Some(tag) = setting.strip_prefix('+') {
+        (tag, true)
+    } else if let Some(eq) = setting.find('=') {
+        let value = setting[eq + 1..].trim();
+        (&setting[..eq], value != "0" && value != "off")
+    } else {
+        (setting, true)
+    };
+    let tag = tag.trim();
+    if tag.len() == 4 && tag.is_ascii() {
+        Some((tag, enable))
+    } else {
+        None
+    }
+}
+
+/// Compute the set of GSUB features that allsorts should apply,
+/// starting from its default set and applying the supplied list of
+/// feature settings in order.
+/// Features that are not supported by allsorts are ignored.
+pub fn feature_mask_from_features<'a>(features: impl Iterator<Item = &'a str>) -> GsubFeatureMask {
+    let mut mask = GsubFeatureMask::default();
+    for setting in features {
+        let (tag, enable) = match parse_feature_setting(setting) {
+            Some(parsed) => parsed,
+            None => {
+                log::warn!("ignoring invalid font feature setting {:?}", setting);
+                continue;
+            }
+        };
+        let flag = match tag {
+            "c2sc" => GsubFeatureMask::C2SC,
+            "calt" => GsubFeatureMask::CALT,
+            "clig" => GsubFeatureMask::CLIG,
+            "dlig" => GsubFeatureMask::DLIG,
+            "frac" => GsubFeatureMask::FRAC,
+            "liga" => GsubFeatureMask::LIGA,
+            "lnum" => GsubFeatureMask::LNUM,
+            "onum" => GsubFeatureMask::ONUM,
+            "pnum" => GsubFeatureMask::PNUM,
+            "rlig" => GsubFeatureMask::RLIG,
+            "smcp" => GsubFeatureMask::SMCP,
+            "tnum" => GsubFeatureMask::TNUM,
+            "zero" => GsubFeatureMask::ZERO,
+            _ => {
+                log::trace!("font feature {} is not supported by allsorts", tag);
+                continue;
+            }
+        };
+        mask.set(flag, enable);
+    }
+    mask
+}
+
 /// Represents a parsed font
 pub struct ParsedFont {
     otf: OffsetTable<'static>,
@@ -45,79 +140,371 @@ pub struct ParsedFont {
     hhea: HheaTable,
     num_glyphs: u16,
     units_per_em: u16,
+    /// The xAvgCharWidth field of the OS/2 table
+    avg_char_width: Option<i16>,
+    /// The sxHeight and sCapHeight fields of a version 2 or later
+    /// OS/2 table
+    x_height: Option<i16>,
+    cap_height: Option<i16>,
+    color_glyphs: Option<ColorGlyphs>,
+    /// The bitmap strikes of a font that has no outlines; this is
+    /// empty for scalable fonts
+    bitmap_strikes: Vec<BitmapStrike>,
+    /// The axes and named instances of a variable font
+    fvar: Option<Fvar>,
+    avar: Option<Avar>,
+    hvar: Option<Hvar>,
+    mvar: Option<Mvar>,
+    /// The normalized coordinates of the selected instance of a
+    /// variable font; empty for the default instance
+    coords: Vec<f64>,
+    /// The index of this face within its font collection
+    index: usize,
 
     // Must be last: this keeps the 'static items alive
     _scope: ReadScopeOwned,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Names {
     full_name: String,
     unique: Option<String>,
     family: Option<String>,
     sub_family: Option<String>,
+    typographic_family: Option<String>,
     postscript_name: Option<String>,
+    /// The OS/2 usWeightClass, or a value derived from the sub_family
+    weight: u16,
+    /// The OS/2 usWidthClass; 5 is normal width
+    width: u16,
+    italic: bool,
 }
 
 impl Names {
+    /// Returns the full name of the font, eg: "JetBrains Mono Bold"
+    pub fn full_name(&self) -> &str {
+        &self.full_name
+    }
+
+    /// Returns the family name of the font, eg: "JetBrains Mono",
+    /// or the full name if it has no family name
+    pub fn family(&self) -> &str {
+        self.family.as_deref().unwrap_or(&self.full_name)
+    }
+
+    /// Returns the sub-family name of the font, eg: "Bold Italic"
+    pub fn sub_family(&self) -> &str {
+        self.sub_family.as_deref().unwrap_or("Regular")
+    }
+
     fn from_name_table_data(name_table: &[u8]) -> anyhow::Result<Names> {
+        let sub_family = get_name(name_table, 2).ok();
+        // The typographic sub-family, if present, is more descriptive
+        // than the sub-family, which is limited to the four basic styles
+        let style = get_name(name_table, 17).ok().or_else(|| sub_family.clone());
+        let (weight, italic) = match style.as_ref() {
+            Some(style) => style_from_sub_family(style),
+            None => (400, false),
+        };
         Ok(Names {
             full_name: get_name(name_table, 4)?,
             unique: get_name(name_table, 3).ok(),
             family: get_name(name_table, 1).ok(),
-            sub_family: get_name(name_table, 2).ok(),
+            sub_family,
+            typographic_family: get_name(name_table, 16).ok(),
             postscript_name: get_name(name_table, 6).ok(),
+            weight,
+            width: 5,
+            italic,
         })
     }
+
+    /// Extract the names from a font, along with its style
+    /// information from the OS/2 table, if present
+    fn from_offset_table<'a>(
+        otf: &OffsetTable<'a>,
+        scope: &ReadScope<'a>,
+    ) -> anyhow::Result<Names> {
+        let mut names = Self::from_name_table_data(name_table_data(otf, scope)?)?;
+        if let Some((weight, width, italic)) = otf
+            .read_table(scope, tag::OS_2)?
+            .and_then(|os2| parse_os2_style(os2.data()))
+        {
+            names.weight = weight;
+            names.width = width;
+            // Some oblique faces don't set the italic or oblique bits
+            // in fsSelection, but do say so in their sub-family
+            names.italic |= italic;
+        }
+        Ok(names)
+    }
+}
+
+/// Guess the weight and italic-ness of a font from its sub-family
+/// name; this is used for fonts that lack an OS/2 table
+fn style_from_sub_family(sub_family: &str) -> (u16, bool) {
+    let style = sub_family
+        .to_lowercase()
+        .replace(|c: char| c == ' ' || c == '-', "");
+    let italic = style.contains("italic") || style.contains("oblique");
+    let weight = [
+        ("thin", 100),
+        ("hairline", 100),
+        ("extralight", 200),
+        ("ultralight", 200),
+        ("semilight", 350),
+        ("demilight", 350),
+        ("light", 300),
+        ("medium", 500),
+        ("semibold", 600),
+        ("demibold", 600),
+        ("extrabold", 800),
+        ("ultrabold", 800),
+        ("bold", 700),
+        ("black", 900),
+        ("heavy", 900),
+    ]
+    .iter()
+    .find(|(name, _)| style.contains(name))
+    .map(|(_, weight)| *weight)
+    .unwrap_or(400);
+    (weight, italic)
+}
+
+/// Read the big-endian u16 at `offset` in the table `data`
+pub(crate) fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset.checked_add(2)?)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
+
+/// Read the big-endian i16 at `offset` in the table `data`
+pub(crate) fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    read_u16(data, offset).map(|v| v as i16)
+}
+
+/// Read the big-endian u32 at `offset` in the table `data`
+pub(crate) fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset.checked_add(4)?)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Extract the usWeightClass, usWidthClass and italic-ness from
+/// the OS/2 table data
+fn parse_os2_style(os2: &[u8]) -> Option<(u16, u16, bool)> {
+    let weight = read_u16(os2, 4)?;
+    let width = read_u16(os2, 6)?;
+    let fs_selection = read_u16(os2, 62)?;
+    // Bit 0 is ITALIC, bit 9 is OBLIQUE
+    let italic = fs_selection & (1 | 1 << 9) != 0;
+    Some((weight, width, italic))
+}
+
+const COLR: u32 = u32::from_be_bytes(*b"COLR");
+const CPAL: u32 = u32::from_be_bytes(*b"CPAL");
+
+const EBLC: u32 = u32::from_be_bytes(*b"EBLC");
+const GLYF: u32 = u32::from_be_bytes(*b"glyf");
+const CFF: u32 = u32::from_be_bytes(*b"CFF ");
+const CFF2: u32 = u32::from_be_bytes(*b"CFF2");
+const FVAR: u32 = u32::from_be_bytes(*b"fvar");
+const AVAR: u32 = u32::from_be_bytes(*b"avar");
+const HVAR: u32 = u32::from_be_bytes(*b"HVAR");
+const MVAR: u32 = u32::from_be_bytes(*b"MVAR");
+
+/// An embedded bitmap strike described by the EBLC table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitmapStrike {
+    /// The size of the strike in pixels per em
+    pub ppem: u8,
+    /// The distance from the baseline to the top of the line, in pixels
+    pub ascender: i8,
+    /// The distance from the baseline to the bottom of the line, in
+    /// pixels; this is negative
+    pub descender: i8,
+}
+
+/// Parse the horizontal line metrics of the strikes in the EBLC table.
+/// Returns None if the table is malformed.
+fn parse_bitmap_strikes(eblc: &[u8]) -> Option<Vec<BitmapStrike>> {
+    let num_sizes = read_u32(eblc, 4)? as usize;
+    (0..num_sizes)
+        .map(|i| {
+            // Each BitmapSize record is 48 bytes, and the hori
+            // SbitLineMetrics start 16 bytes into the record
+            let record = eblc.get(8 + 48 * i..8 + 48 * (i + 1))?;
+            let ascender = record[16] as i8;
+            // Some fonts record the descender as a positive distance
+            let descender = -(record[17] as i8).saturating_abs();
+            Some(BitmapStrike {
+                ppem: record[45],
+                ascender,
+                descender,
+            })
+        })
+        .collect()
+}
+
+/// A color with red, green, blue and alpha components
+pub type RgbaColor = (u8, u8, u8, u8);
+
+/// A layer of a color glyph, which is drawn by filling the outline
+/// of `glyph` with `color`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorLayer {
+    pub glyph: u16,
+    /// The color in RGBA order, or `None` if the layer is drawn
+    /// in the color of the text
+    pub color: Option<RgbaColor>,
+}
+
+/// The layered color glyphs described by the COLR table, with their
+/// colors resolved from the first palette in the CPAL table
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColorGlyphs {
+    layers: HashMap<u16, Vec<ColorLayer>>,
+}
+
+impl ColorGlyphs {
+    /// Parse the version 0 portion of the COLR table, which describes
+    /// each color glyph as a stack of solid color layers.
+    /// Returns None if the tables are malformed.
+    fn parse(colr: &[u8], cpal: &[u8]) -> Option<Self> {
+        let read_u16 = |data: &[u8], offset: usize| read_u16(data, offset).map(usize::from);
+        let read_u32 = |data: &[u8], offset: usize| read_u32(data, offset).map(|v| v as usize);
+
+        let num_palette_entries = read_u16(cpal, 2)?;
+        let color_records = read_u32(cpal, 8)?;
+        let first_palette = read_u16(cpal, 12)?;
+        // The color records are stored in BGRA order
+        let palette = (0..num_palette_entries)
+            .map(|i| {
+                let offset = color_records + 4 * (first_palette + i);
+                cpal.get(offset..offset + 4)
+                    .map(|bgra| (bgra[2], bgra[1], bgra[0], bgra[3]))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let num_base_glyphs = read_u16(colr, 2)?;
+        let base_glyphs = read_u32(colr, 4)?;
+        let layer_records = read_u32(colr, 8)?;
+        let num_layers = read_u16(colr, 12)?;
+
+        let mut layers = HashMap::new();
+        for i in 0..num_base_glyphs {
+            let record = base_glyphs + 6 * i;
+            let glyph = read_u16(colr, record)? as u16;
+            let first_layer = read_u16(colr, record + 2)?;
+            let count = read_u16(colr, record + 4)?;
+            if first_layer + count > num_layers {
+                return None;
+            }
+
+            let glyph_layers = (first_layer..first_layer + count)
+                .map(|layer| {
+                    let record = layer_records + 4 * layer;
+                    let glyph = read_u16(colr, record)? as u16;
+                    let color = match read_u16(colr, record + 2)? {
+                        0xffff => None,
+                        idx => Some(*palette.get(idx)?),
+                    };
+                    Some(ColorLayer { glyph, color })
+                })
+                .collect::<Option<Vec<_>>>()?;
+            layers.insert(glyph, glyph_layers);
+        }
+
+        Some(Self { layers })
+    }
+
+    /// Returns the layers that make up `glyph`, from bottom to top,
+    /// or None if it isn't a color glyph
+    pub fn layers(&self, glyph: u16) -> Option<&[ColorLayer]> {
+        self.layers.get(&glyph).map(|layers| layers.as_slice())
+    }
+
+    /// Returns true if any of the layers of `glyph` are drawn in the
+    /// color of the text
+    pub fn uses_foreground(&self, glyph: u16) -> bool {
+        self.layers(glyph)
+            .map(|layers| layers.iter().any(|layer| layer.color.is_none()))
+            .unwrap_or(false)
+    }
 }
 
 impl ParsedFont {
     /// Load FontDataHandle's for fonts that match the configuration
-    /// and that are found in the config font_dirs list.
+    /// and that are found in the config font_dirs list, along with
+    /// the attributes that each of them was matched for.
+    /// The index is updated to reflect the current contents of those
+    /// directories, and is used to avoid parsing unchanged files.
     pub fn load_fonts(
         config: &Config,
+        index: &mut FontIndex,
+        fonts_selection: &[FontAttributes],
+        loaded: &mut HashSet<FontAttributes>,
+    ) -> anyhow::Result<Vec<(FontDataHandle, FontAttributes)>> {
+        Self::load_fonts_in_dirs(&config.font_dirs, index, fonts_selection, loaded)
+    }
+
+    /// Load FontDataHandle's for fonts that match the configuration
+    /// and that are found in the directories configured in fontconfig.
+    /// This is slower than asking the locator, so it is intended to
+    /// be used only for fonts that the locator couldn't find.
+    pub fn load_system_fonts(
+        config: &Config,
+        index: &mut FontIndex,
+        fonts_selection: &[FontAttributes],
+        loaded: &mut HashSet<FontAttributes>,
+    ) -> anyhow::Result<Vec<(FontDataHandle, FontAttributes)>> {
+        let dirs = system_font_dirs(config);
+        if dirs.is_empty() {
+            return Ok(vec![]);
+        }
+        Self::load_fonts_in_dirs(&dirs, index, fonts_selection, loaded)
+    }
+
+    fn load_fonts_in_dirs(
+        dirs: &[PathBuf],
+        index: &mut FontIndex,
         fonts_selection: &[FontAttributes],
         loaded: &mut HashSet<FontAttributes>,
-    ) -> anyhow::Result<Vec<FontDataHandle>> {
+    ) -> anyhow::Result<Vec<(FontDataHandle, FontAttributes)>> {
         // First discover the available fonts
-        let mut font_info = vec![];
-        for path in &config.font_dirs {
-            for entry in walkdir::WalkDir::new(path).into_iter() {
-                let entry = match entry {
-                    Ok(entry) => entry,
-                    Err(_) => continue,
-                };
-
-                let path = entry.path();
-                parse_and_collect_font_info(path, &mut font_info)
-                    .map_err(|err| {
-                        log::trace!("failed to read {}: {}", path.display(), err);
-                        err
-                    })
-                    .ok();
-            }
+        index.scan(dirs);
+        if let Err(err) = index.save() {
+            log::warn!("failed to save font index: {:#}", err);
         }
 
-        Self::match_font_info(fonts_selection, font_info, loaded)
+        Self::match_font_info(fonts_selection, index.font_info(), loaded)
     }
 
     pub fn load_built_in_fonts(
         fonts_selection: &[FontAttributes],
         loaded: &mut HashSet<FontAttributes>,
-    ) -> anyhow::Result<Vec<FontDataHandle>> {
+    ) -> anyhow::Result<Vec<(FontDataHandle, FontAttributes)>> {
         let mut font_info = vec![];
         load_built_in_fonts(&mut font_info).ok();
         Self::match_font_info(fonts_selection, font_info, loaded)
     }
 
+    /// Returns the fonts that are compiled in to wezterm
+    pub fn list_built_in_fonts() -> Vec<FontEnumEntry> {
+        let mut font_info = vec![];
+        load_built_in_fonts(&mut font_info).ok();
+        font_info
+            .into_iter()
+            .map(|(names, _, handle)| FontEnumEntry::from_names(&names, handle))
+            .collect()
+    }
+
     fn match_font_info(
         fonts_selection: &[FontAttributes],
         mut font_info: Vec<(Names, std::path::PathBuf, FontDataHandle)>,
         loaded: &mut HashSet<FontAttributes>,
-    ) -> anyhow::Result<Vec<FontDataHandle>> {
+    ) -> anyhow::Result<Vec<(FontDataHandle, FontAttributes)>> {
         font_info.sort_by_key(|(names, _, _)| names.full_name.clone());
         for (names, _, _) in &font_info {
-            log::warn!("available font: {}", names.full_name);
+            log::trace!("available font: {}", names.full_name);
         }
 
         // Second, apply matching rules in order. We can't match
@@ -126,18 +513,22 @@ impl ParsedFont {
         // fonts_selection is strictly ordered
         let mut handles = vec![];
         for attr in fonts_selection {
-            for (names, path, handle) in &font_info {
-                if font_info_matches(attr, &names) {
-                    log::warn!(
-                        "Using {} from {} for {:?}",
-                        names.full_name,
-                        path.display(),
-                        attr
-                    );
-                    handles.push(handle.clone());
-                    loaded.insert(attr.clone());
-                    break;
-                }
+            // Select the face whose style is the closest match;
+            // min_by_key picks the first of equally close faces,
+            // so this is stable with respect to the sort above
+            let best = font_info
+                .iter()
+                .filter_map(|info| font_match_distance(attr, &info.0).map(|d| (d, info)))
+                .min_by_key(|(distance, _)| *distance);
+            if let Some((_, (names, path, handle))) = best {
+                log::warn!(
+                    "Using {} from {} for {:?}",
+                    names.full_name,
+                    path.display(),
+                    attr
+                );
+                handles.push((handle.clone(), attr.clone()));
+                loaded.insert(attr.clone());
             }
         }
         Ok(handles)
@@ -151,6 +542,11 @@ impl ParsedFont {
                 (data, *index)
             }
         };
+        let data = if crate::woff::is_woff(&data) {
+            crate::woff::decode(&data)?
+        } else {
+            data
+        };
 
         let index = index as usize;
 
@@ -164,8 +560,7 @@ impl ParsedFont {
             unsafe { std::mem::transmute(owned_scope.scope().read::<OpenTypeFile>()?) };
 
         let otf = locate_offset_table(&file, index)?;
-        let name_table = name_table_data(&otf, &file.scope)?;
-        let names = Names::from_name_table_data(name_table)?;
+        let names = Names::from_offset_table(&otf, &file.scope)?;
 
         let head = otf
             .read_table(&file.scope, tag::HEAD)?
@@ -226,6 +621,59 @@ impl ParsedFont {
             .transpose()?
             .map(new_layout_cache);
 
+        let os2 = otf.read_table(&file.scope, tag::OS_2)?;
+        let os2_field = |offset: usize| os2.as_ref().and_then(|os2| read_i16(os2.data(), offset));
+        let avg_char_width = os2_field(2);
+        // sxHeight and sCapHeight were added in version 2
+        let (x_height, cap_height) = match os2_field(0) {
+            Some(version) if version >= 2 => (os2_field(86), os2_field(88)),
+            _ => (None, None),
+        };
+
+        let color_glyphs = match (
+            otf.read_table(&file.scope, COLR)?,
+            otf.read_table(&file.scope, CPAL)?,
+        ) {
+            (Some(colr), Some(cpal)) => {
+                let color_glyphs = ColorGlyphs::parse(colr.data(), cpal.data());
+                if color_glyphs.is_none() {
+                    log::warn!("ignoring malformed COLR table in {}", names.full_name);
+                }
+                color_glyphs
+            }
+            _ => None,
+        };
+
+        let has_outlines = [GLYF, CFF, CFF2]
+            .iter()
+            .any(|&table| otf.find_table_record(table).is_some());
+        let bitmap_strikes = match otf.read_table(&file.scope, EBLC)? {
+            Some(eblc) if !has_outlines => parse_bitmap_strikes(eblc.data()).unwrap_or_else(|| {
+                log::warn!("ignoring malformed EBLC table in {}", names.full_name);
+                vec![]
+            }),
+            _ => vec![],
+        };
+
+        // The variation tables are optional, and a malformed one
+        // only means that the font can't be varied
+        let fvar = otf
+            .read_table(&file.scope, FVAR)?
+            .and_then(|fvar| Fvar::parse(fvar.data()))
+            .filter(|fvar| !fvar.axes.is_empty());
+        let (avar, hvar, mvar) = if fvar.is_some() {
+            (
+                otf.read_table(&file.scope, AVAR)?
+                    .and_then(|avar| Avar::parse(avar.data())),
+                otf.read_table(&file.scope, HVAR)?
+                    .and_then(|hvar| Hvar::parse(hvar.data())),
+                otf.read_table(&file.scope, MVAR)?
+                    .and_then(|mvar| Mvar::parse(mvar.data())),
+            )
+        } else {
+            (None, None, None)
+        };
+
         Ok(Self {
             otf,
             names,
@@ -238,6 +686,17 @@ impl ParsedFont {
             gdef_table,
             num_glyphs,
             units_per_em: head.units_per_em,
+            avg_char_width,
+            x_height,
+            cap_height,
+            color_glyphs,
+            bitmap_strikes,
+            fvar,
+            avar,
+            hvar,
+            mvar,
+            coords: vec![],
+            index,
             _scope: owned_scope,
         })
     }
@@ -246,6 +705,160 @@ impl ParsedFont {
         &self.names
     }
 
+    /// Returns the layered color glyphs defined by the COLR table,
+    /// if the font has one
+    pub fn color_glyphs(&self) -> Option<&ColorGlyphs> {
+        self.color_glyphs.as_ref()
+    }
+
+    /// Returns the OS/2 usWeightClass of the font, eg: 400 for regular
+    pub fn weight(&self) -> u16 {
+        self.names.weight
+    }
+
+    /// Returns the OS/2 usWidthClass of the font, eg: 5 for normal width
+    pub fn width(&self) -> u16 {
+        self.names.width
+    }
+
+    /// Returns true if this is an italic or oblique face
+    pub fn italic(&self) -> bool {
+        self.names.italic
+    }
+
+    /// Returns the axes of a variable font, which is empty for
+    /// fonts that don't vary
+    pub fn variation_axes(&self) -> &[VariationAxis] {
+        match &self.fvar {
+            Some(fvar) => &fvar.axes,
+            None => &[],
+        }
+    }
+
+    /// Returns the design coordinates, one per axis, of the instance
+    /// of a variable font that is selected by the `instance` and
+    /// `variations` of `attr`.  The named instance, if any, is applied
+    /// first and the individual axis settings then override it.
+    /// Returns an empty list if the font doesn't vary, or if `attr`
+    /// doesn't select anything other than the default instance.
+    pub fn design_coords(&self, attr: &FontAttributes) -> Vec<f64> {
+        let fvar = match &self.fvar {
+            Some(fvar) => fvar,
+            None => return vec![],
+        };
+        if attr.instance.is_none() && attr.variations.is_empty() {
+            return vec![];
+        }
+
+        let mut coords: Vec<f64> = fvar.axes.iter().map(|axis| axis.default).collect();
+
+        if let Some(name) = &attr.instance {
+            let name_table = name_table_data(&self.otf, &self._scope.scope()).ok();
+            // Instances are selected by their sub-family name, such as
+            // "SemiBold", or by their PostScript name
+            let matches = |id| {
+                name_table
+                    .and_then(|data| get_name(data, id).ok())
+                    .map_or(false, |candidate| candidate.eq_ignore_ascii_case(name))
+            };
+            let found = fvar.instances.iter().find(|instance| {
+                matches(instance.subfamily_name_id)
+                    || instance.postscript_name_id.map_or(false, matches)
+            });
+            match found {
+                Some(instance) => coords.copy_from_slice(&instance.coords),
+                None => log::warn!("{} has no named instance {:?}", self.names.full_name, name),
+            }
+        }
+
+        for variation in &attr.variations {
+            match fvar.axes.iter().position(|axis| axis.tag == variation.tag) {
+                Some(idx) => {
+                    let axis = &fvar.axes[idx];
+                    coords[idx] = f64::from(variation.value).max(axis.min).min(axis.max);
+                }
+                None => log::trace!("{} has no axis for {}", self.names.full_name, variation),
+            }
+        }
+
+        coords
+    }
+
+    /// Select the instance of a variable font at the specified design
+    /// coordinates, so that the metrics and advances reflect it.
+    /// An empty list selects the default instance.
+    pub fn set_design_coords(&mut self, design_coords: &[f64]) {
+        self.coords = match &self.fvar {
+            Some(fvar) if !design_coords.is_empty() => {
+                fvar.normalize(design_coords, self.avar.as_ref())
+            }
+            _ => vec![],
+        };
+    }
+
+    /// Returns the x-height of the selected instance as a fraction of
+    /// the em, if the OS/2 table specifies one
+    pub fn x_height(&self) -> Option<f64> {
+        self.em_fraction(self.x_height, b"xhgt")
+    }
+
+    /// Returns the cap height of the selected instance as a fraction
+    /// of the em, if the OS/2 table specifies one
+    pub fn cap_height(&self) -> Option<f64> {
+        self.em_fraction(self.cap_height, b"cpht")
+    }
+
+    fn em_fraction(&self, value: Option<i16>, tag: &[u8; 4]) -> Option<f64> {
+        value
+            .map(|value| (f64::from(value) + self.metric_delta(tag)) / f64::from(self.units_per_em))
+            .filter(|&fraction| fraction > 0.)
+    }
+
+    /// Returns the change to the font wide metric identified by `tag`
+    /// for the selected instance, in font units
+    fn metric_delta(&self, tag: &[u8; 4]) -> f64 {
+        match &self.mvar {
+            Some(mvar) if !self.coords.is_empty() => mvar.delta(tag, &self.coords),
+            _ => 0.,
+        }
+    }
+
+    /// Returns the advance of a glyph for the selected instance,
+    /// in font units
+    fn advance_width(&self, glyph_index: u16) -> anyhow::Result<f64> {
+        let advance = f64::from(
+            self.hmtx
+                .horizontal_advance(glyph_index, self.hhea.num_h_metrics)?,
+        );
+        Ok(match &self.hvar {
+            Some(hvar) if !self.coords.is_empty() => {
+                advance + hvar.advance_delta(glyph_index, &self.coords)
+            }
+            _ => advance,
+        })
+    }
+
+    /// Returns the outline of a glyph, as loaded by FreeType,
+    /// along with its advance, in font units.
+    /// For a variable font, this is the outline of the default instance.
+    pub fn glyph_outline(&self, glyph_index: u16) -> anyhow::Result<GlyphOutline> {
+        if glyph_index >= self.num_glyphs {
+            anyhow::bail!("{} has no glyph {}", self.names.full_name, glyph_index);
+        }
+        let lib = Library::new()?;
+        let mut face = lib.new_face_from_slice(self._scope.scope().data(), self.index as _)?;
+        let commands = face.glyph_outline(glyph_index.into())?;
+
+        let advance = self
+            .hmtx
+            .horizontal_advance(glyph_index, self.hhea.num_h_metrics)?;
+
+        Ok(GlyphOutline {
+            commands,
+            advance: f64::from(advance),
+        })
+    }
+
     /// Resolve a char to the corresponding glyph in the font
     pub fn glyph_index_for_char(&self, c: char) -> anyhow::Result<u16> {
         let glyph = self
@@ -261,16 +874,81 @@ impl ParsedFont {
         }
     }
 
+    /// Returns the bitmap strike that is closest to the requested size,
+    /// if the font has no outlines
+    pub fn bitmap_strike(&self, point_size: f64, dpi: u32) -> Option<&BitmapStrike> {
+        let pixel_size = (dpi as f64 / 72.) * point_size;
+        self.bitmap_strikes.iter().min_by(|a, b| {
+            let distance = |strike: &BitmapStrike| (f64::from(strike.ppem) - pixel_size).abs();
+            distance(a).partial_cmp(&distance(b)).unwrap()
+        })
+    }
+
+    /// Returns the number of pixels per font unit at the requested
+    /// size.  Bitmap fonts are drawn at the size of their closest
+    /// strike, so that is used in place of the requested size.
+    fn pixel_scale(&self, point_size: f64, dpi: u32) -> f64 {
+        match self.bitmap_strike(point_size, dpi) {
+            Some(strike) => f64::from(strike.ppem) / self.units_per_em as f64,
+            None => (dpi as f64 / 72.) * point_size / self.units_per_em as f64,
+        }
+    }
+
     pub fn get_metrics(&self, point_size: f64, dpi: u32) -> FontMetrics {
-        let pixel_scale = (dpi as f64 / 72.) * point_size / self.units_per_em as f64;
-        let underline_thickness =
-            PixelLength::new(self.post.header.underline_thickness as f64 * pixel_scale);
-        let underline_position =
-            PixelLength::new(self.post.header.underline_position as f64 * pixel_scale);
-        let descender = PixelLength::new(self.hhea.descender as f64 * pixel_scale);
-        let cell_height = PixelLength::new(
-            (self.hhea.ascender - self.hhea.descender + self.hhea.line_gap) as f64 * pixel_scale,
+        self.get_metrics_with_reference(point_size, dpi, configuration().cell_width_reference)
+    }
+
+    /// Returns the advance, in font units, from which the cell width
+    /// is computed for the specified reference
+    fn reference_advance(&self, reference: CellWidthReference) -> f64 {
+        let advance_of = |c: char| {
+            self.glyph_index_for_char(c)
+                .and_then(|glyph_index| self.advance_width(glyph_index))
+                .ok()
+        };
+        let reference = match reference {
+            CellWidthReference::Char(c) => advance_of(c),
+            CellWidthReference::AverageCharWidth => self
+                .avg_char_width
+                .filter(|&width| width > 0)
+                .map(f64::from),
+            CellWidthReference::MaxAscii => None,
+        };
+        reference.unwrap_or_else(|| {
+            // Compute the max width based on ascii chars
+            (0x20..0x7fu8)
+                .filter_map(|i| advance_of(i as char))
+                .fold(0., f64::max)
+        })
+    }
+
+    pub(crate) fn get_metrics_with_reference(
+        &self,
+        point_size: f64,
+        dpi: u32,
+        reference: CellWidthReference,
+    ) -> FontMetrics {
+        let pixel_scale = self.pixel_scale(point_size, dpi);
+        let underline_thickness = PixelLength::new(
+            (self.post.header.underline_thickness as f64 + self.metric_delta(b"unds"))
+                * pixel_scale,
+        );
+        let underline_position = PixelLength::new(
+            (self.post.header.underline_position as f64 + self.metric_delta(b"undo")) * pixel_scale,
         );
+        let ascent = self.hhea.ascender as f64 + self.metric_delta(b"hasc");
+        let descent = self.hhea.descender as f64 + self.metric_delta(b"hdsc");
+        let line_gap = self.hhea.line_gap as f64 + self.metric_delta(b"hlgp");
+        let (descender, cell_height) = match self.bitmap_strike(point_size, dpi) {
+            Some(strike) => (
+                PixelLength::new(f64::from(strike.descender)),
+                PixelLength::new(f64::from(strike.ascender) - f64::from(strike.descender)),
+            ),
+            None => (
+                PixelLength::new(descent * pixel_scale),
+                PixelLength::new((ascent - descent + line_gap) * pixel_scale),
+            ),
+        };
         log::trace!(
             "hhea: ascender={} descender={} line_gap={} \
              advance_width_max={} min_lsb={} min_rsb={} \
@@ -284,23 +962,9 @@ impl ParsedFont {
             self.hhea.x_max_extent
         );
 
-        let mut cell_width = 0;
-        // Compute the max width based on ascii chars
-        for i in 0x20..0x7fu8 {
-            if let Ok(glyph_index) = self.glyph_index_for_char(i as char) {
-                if let Ok(h) = self
-                    .hmtx
-                    .horizontal_advance(glyph_index, self.hhea.num_h_metrics)
-                {
-                    cell_width = cell_width.max(h);
-                }
-            }
-        }
-        let cell_width = PixelLength::new(
-            (PixelLength::new(cell_width as f64) * pixel_scale)
-                .get()
-                .floor(),
-        );
+        let cell_width = self.reference_advance(reference);
+        let cell_width =
+            PixelLength::new((PixelLength::new(cell_width) * pixel_scale).get().floor());
 
         let metrics = FontMetrics {
             cell_width,
@@ -325,6 +989,7 @@ impl ParsedFont {
         lang: u32,
         point_size: f64,
         dpi: u32,
+        feature_mask: GsubFeatureMask,
     ) -> anyhow::Result<Vec<MaybeShaped>> {
         #[derive(Debug)]
         enum Run {
@@ -424,8 +1089,7 @@ impl ParsedFont {
             }
         }
 
-        // TODO: construct from configuation
-        let feature_mask = GsubFeatureMask::default();
+        let text = text.as_ref();
         let mut pos = Vec::new();
         let mut cluster = slice_index;
 
@@ -474,10 +1138,7 @@ impl ParsedFont {
                     for glyph_info in infos.into_iter() {
                         let glyph_index = glyph_info.glyph.glyph_index;
 
-                        let horizontal_advance = i32::from(
-                            self.hmtx
-                                .horizontal_advance(glyph_index, self.hhea.num_h_metrics)?,
-                        );
+                        let horizontal_advance = self.advance_width(glyph_index)?.round() as i32;
 
                         // Adjust for distance placement
                         let (x_advance, y_advance) = match glyph_info.placement {
@@ -485,18 +1146,26 @@ impl ParsedFont {
                             Placement::Anchor(_, _) | Placement::None => (horizontal_advance, 0),
                         };
 
-                        let text = reverse_engineer_glyph_text(&glyph_info.glyph);
-                        let text_len = text.len();
-                        let num_cells = unicode_column_width(&text);
+                        let glyph_text = reverse_engineer_glyph_text(&glyph_info.glyph);
+                        // The variation selector that followed the character
+                        // isn't part of `unicodes`, but it is part of the cluster
+                        let selector_len = match glyph_info.glyph.unicodes.first() {
+                            Some(c) if glyph_info.glyph.variation.is_some() => text
+                                .get(cluster - slice_index + c.len_utf8()..)
+                                .and_then(|rest| rest.chars().next())
+                                .map_or(0, char::len_utf8),
+                            _ => 0,
+                        };
+                        let text_len = glyph_text.len() + selector_len;
+                        let num_cells = unicode_column_width(&glyph_text);
 
-                        let pixel_scale =
-                            (dpi as f64 / 72.) * point_size / self.units_per_em as f64;
+                        let pixel_scale = self.pixel_scale(point_size, dpi);
                         let x_advance = PixelLength::new(x_advance as f64 * pixel_scale);
                         let y_advance = PixelLength::new(y_advance as f64 * pixel_scale);
 
                         let info = GlyphInfo {
                             #[cfg(debug_assertions)]
-                            text,
+                            text: glyph_text,
                             cluster: cluster as u32,
                             num_cells: num_cells as u8,
                             font_idx: font_index,
@@ -518,50 +1187,51 @@ impl ParsedFont {
     }
 }
 
-fn collect_font_info(
-    name_table_data: &[u8],
-    path: &Path,
-    index: usize,
-    infos: &mut Vec<(Names, PathBuf, FontDataHandle)>,
-) -> anyhow::Result<()> {
-    let names = Names::from_name_table_data(name_table_data)?;
-    infos.push((
-        names,
-        path.to_path_buf(),
-        FontDataHandle::OnDisk {
-            path: path.to_path_buf(),
-            index: index.try_into()?,
-        },
-    ));
-    Ok(())
-}
+/// The largest difference in weight between the requested attributes
+/// and a font for it to be considered a match.  This allows eg: a
+/// SemiBold face to satisfy a request for Bold, but prevents a Regular
+/// face from being used for Bold.
+const MAX_WEIGHT_DISTANCE: u16 = 150;
 
-pub fn font_info_matches(attr: &FontAttributes, names: &Names) -> bool {
-    if let Some(fam) = names.family.as_ref() {
-        // TODO: correctly match using family and sub-family;
-        // this is a pretty rough approximation
-        if attr.family == *fam {
-            match names.sub_family.as_ref().map(String::as_str) {
-                Some("Italic") if attr.italic && !attr.bold => return true,
-                Some("Bold") if attr.bold && !attr.italic => return true,
-                Some("Bold Italic") if attr.bold && attr.italic => return true,
-                Some("Medium") | Some("Regular") | None if !attr.italic && !attr.bold => {
-                    return true
-                }
-                _ => {}
-            }
+/// Computes how closely the font described by `names` matches the
+/// requested attributes.  Returns `None` if the font doesn't match
+/// at all, otherwise a distance where smaller values are better.
+/// Faces whose width matches the requested stretch are preferred over
+/// those that are closer in weight, as is the case for CSS font matching.
+/// A family that is the PostScript name of a face selects exactly
+/// that face, ahead of any faces that match by family.
+pub fn font_match_distance(attr: &FontAttributes, names: &Names) -> Option<u32> {
+    if names.postscript_name.as_ref() == Some(&attr.family) {
+        return Some(0);
+    }
+    let family_matches = names.family.as_ref() == Some(&attr.family)
+        || names.typographic_family.as_ref() == Some(&attr.family);
+    if family_matches {
+        if names.italic != attr.italic {
+            return None;
+        }
+        let weight_distance = (i32::from(names.weight) - i32::from(attr.weight())).abs() as u16;
+        if weight_distance <= MAX_WEIGHT_DISTANCE {
+            let width_distance =
+                (i32::from(names.width) - i32::from(attr.stretch.to_opentype_width())).abs();
+            return Some(1 + width_distance as u32 * 1000 + u32::from(weight_distance));
         }
     }
     if attr.family == names.full_name && !attr.bold && !attr.italic {
-        true
+        // An exact match on the full name selects that face
+        Some(1)
     } else {
-        false
+        None
     }
 }
 
+pub fn font_info_matches(attr: &FontAttributes, names: &Names) -> bool {
+    font_match_distance(attr, names).is_some()
+}
+
 /// In case the user has a broken configuration, or no configuration,
 /// we bundle JetBrains Mono and Noto Color Emoji to act as reasonably
-/// sane fallback fonts.
+/// sane fallback fonts, along with some Nerd Font symbols.
 /// This function loads those.
 fn load_built_in_fonts(
     font_info: &mut Vec<(Names, PathBuf, FontDataHandle)>,
@@ -587,6 +1257,7 @@ fn load_built_in_fonts(
         font!("../../assets/fonts/JetBrainsMono-SemiLight-Italic.ttf"),
         font!("../../assets/fonts/JetBrainsMono-SemiLight.ttf"),
         font!("../../assets/fonts/NotoColorEmoji.ttf"),
+        font!("../../assets/fonts/WeztermSymbols-Regular.ttf"),
         font!("../../assets/fonts/LastResortHE-Regular.ttf"),
     ] {
         let scope = allsorts::binary::read::ReadScope::new(&data);
@@ -595,11 +1266,7 @@ fn load_built_in_fonts(
 
         match &file.font {
             OpenTypeFont::Single(ttf) => {
-                let name_table_data = ttf
-                    .read_table(&file.scope, allsorts::tag::NAME)?
-                    .ok_or_else(|| anyhow!("name table is not present"))?;
-
-                let names = Names::from_name_table_data(name_table_data.data())?;
+                let names = Names::from_offset_table(ttf, &file.scope)?;
                 font_info.push((
                     names,
                     path.to_path_buf(),
@@ -616,10 +1283,7 @@ fn load_built_in_fonts(
                         .scope
                         .offset(offset_table_offset as usize)
                         .read::<OffsetTable>()?;
-                    let name_table_data = ttf
-                        .read_table(&file.scope, allsorts::tag::NAME)?
-                        .ok_or_else(|| anyhow!("name table is not present"))?;
-                    let names = Names::from_name_table_data(name_table_data.data())?;
+                    let names = Names::from_offset_table(&ttf, &file.scope)?;
                     font_info.push((
                         names,
                         path.to_path_buf(),
@@ -637,20 +1301,22 @@ fn load_built_in_fonts(
     Ok(())
 }
 
-fn parse_and_collect_font_info(
-    path: &Path,
-    font_info: &mut Vec<(Names, PathBuf, FontDataHandle)>,
-) -> anyhow::Result<()> {
+/// Parse the font file at the specified path, returning the names
+/// of each of the faces that it contains, along with their index
+pub(crate) fn parse_font_faces(path: &Path) -> anyhow::Result<Vec<(u32, Names)>> {
     let data = std::fs::read(path)?;
+    let data = if crate::woff::is_woff(&data) {
+        crate::woff::decode(&data)?
+    } else {
+        data
+    };
     let scope = allsorts::binary::read::ReadScope::new(&data);
     let file = scope.read::<OpenTypeFile>()?;
 
+    let mut faces = vec![];
     match &file.font {
         OpenTypeFont::Single(ttf) => {
-            let data = ttf
-                .read_table(&file.scope, allsorts::tag::NAME)?
-                .ok_or_else(|| anyhow!("name table is not present"))?;
-            collect_font_info(data.data(), path, 0, font_info)?;
+            faces.push((0, Names::from_offset_table(ttf, &file.scope)?));
         }
         OpenTypeFont::Collection(ttc) => {
             for (index, offset_table_offset) in ttc.offset_tables.iter().enumerate() {
@@ -658,15 +1324,14 @@ fn parse_and_collect_font_info(
                     .scope
                     .offset(offset_table_offset as usize)
                     .read::<OffsetTable>()?;
-                let data = ttf
-                    .read_table(&file.scope, allsorts::tag::NAME)?
-                    .ok_or_else(|| anyhow!("name table is not present"))?;
-                collect_font_info(data.data(), path, index, font_info).ok();
+                if let Ok(names) = Names::from_offset_table(&ttf, &file.scope) {
+                    faces.push((index.try_into()?, names));
+                }
             }
         }
     }
 
-    Ok(())
+    Ok(faces)
 }
 
 fn locate_offset_table<'a>(f: &OpenTypeFile<'a>, idx: usize) -> anyhow::Result<OffsetTable<'a>> {
@@ -694,10 +1359,718 @@ fn name_table_data<'a>(otf: &OffsetTable<'a>, scope: &ReadScope<'a>) -> anyhow::
     Ok(data.data())
 }
 
-/// Extract a name from the name table
+/// Extracts the name with the specified name_id from the name table.
+/// A font may have a record for the name for each platform and
+/// language, so we prefer the US English name from the Windows
+/// platform, followed by the English Macintosh name, and then any
+/// other decodable record.
 fn get_name(name_table_data: &[u8], name_id: u16) -> anyhow::Result<String> {
-    let cstr = allsorts::get_name::fontcode_get_name(name_table_data, name_id)?
-        .ok_or_else(|| anyhow!("name_id {} not found", name_id))?;
-    cstr.into_string()
-        .map_err(|e| anyhow!("name_id {} is not representable as String: {}", name_id, e))
+    let read_u16 = |offset: usize| read_u16(name_table_data, offset);
+    let count = read_u16(2).ok_or_else(|| anyhow!("name table is truncated"))?;
+    let storage = read_u16(4).ok_or_else(|| anyhow!("name table is truncated"))? as usize;
+
+    let mut best: Option<(u8, String)> = None;
+    for i in 0..usize::from(count) {
+        let record = 6 + 12 * i;
+        let field = |idx: usize| {
+            read_u16(record + 2 * idx).ok_or_else(|| anyhow!("name record {} is truncated", i))
+        };
+        if field(3)? != name_id {
+            continue;
+        }
+        let (platform, encoding, language) = (field(0)?, field(1)?, field(2)?);
+        let rank = match (platform, encoding, language) {
+            (3, 1, 0x409) => 0,
+            (3, 10, 0x409) => 1,
+            (1, 0, 0) => 2,
+            (0, _, _) => 3,
+            (3, 1, _) | (3, 10, _) => 4,
+            (1, 0, _) => 5,
+            (3, 0, _) => 6,
+            _ => continue,
+        };
+        match &best {
+            Some((best_rank, _)) if rank >= *best_rank => continue,
+            _ => {}
+        }
+        let start = storage + field(5)? as usize;
+        let data = match name_table_data.get(start..start + field(4)? as usize) {
+            Some(data) => data,
+            None => continue,
+        };
+        if let Some(name) = decode_name(platform, data) {
+            best = Some((rank, name));
+        }
+    }
+
+    best.map(|(_, name)| name)
+        .ok_or_else(|| anyhow!("name_id {} not found", name_id))
+}
+
+/// Decode the string data of a name record.  The Unicode and Windows
+/// platforms use UTF-16BE, while we only consider Macintosh records
+/// in the Roman encoding.
+fn decode_name(platform: u16, data: &[u8]) -> Option<String> {
+    if platform == 1 {
+        return Some(
+            data.iter()
+                .map(|&b| match b {
+                    0..=0x7f => b as char,
+                    _ => MAC_ROMAN[usize::from(b - 0x80)],
+                })
+                .collect(),
+        );
+    }
+    let units = data
+        .chunks_exact(2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]));
+    std::char::decode_utf16(units)
+        .collect::<Result<_, _>>()
+        .ok()
+}
+
+/// The characters for bytes 0x80-0xff in the Mac OS Roman encoding
+#[rustfmt::skip]
+const MAC_ROMAN: [char; 128] = [
+    'Ä', 'Å', 'Ç', 'É', 'Ñ', 'Ö', 'Ü', 'á', 'à', 'â', 'ä', 'ã', 'å', 'ç', 'é', 'è',
+    'ê', 'ë', 'í', 'ì', 'î', 'ï', 'ñ', 'ó', 'ò', 'ô', 'ö', 'õ', 'ú', 'ù', 'û', 'ü',
+    '†', '°', '¢', '£', '§', '•', '¶', 'ß', '®', '©', '™', '´', '¨', '≠', 'Æ', 'Ø',
+    '∞', '±', '≤', '≥', '¥', 'µ', '∂', '∑', '∏', 'π', '∫', 'ª', 'º', 'Ω', 'æ', 'ø',
+    '¿', '¡', '¬', '√', 'ƒ', '≈', '∆', '«', '»', '…', '\u{a0}', 'À', 'Ã', 'Õ', 'Œ', 'œ',
+    '–', '—', '“', '”', '‘', '’', '÷', '◊', 'ÿ', 'Ÿ', '⁄', '€', '‹', '›', 'ﬁ', 'ﬂ',
+    '‡', '·', '‚', '„', '‰', 'Â', 'Ê', 'Á', 'Ë', 'È', 'Í', 'Î', 'Ï', 'Ì', 'Ó', 'Ô',
+    '\u{f8ff}', 'Ò', 'Ú', 'Û', 'Ù', 'ı', 'ˆ', '˜', '¯', '˘', '˙', '˚', '¸', '˝', '˛', 'ˇ',
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use config::FontStretch;
+
+    fn jetbrains_mono() -> ParsedFont {
+        let handle = FontDataHandle::Memory {
+            data: include_bytes!("../../assets/fonts/JetBrainsMono-Regular.ttf").to_vec(),
+            index: 0,
+            name: "JetBrainsMono-Regular".to_string(),
+        };
+        ParsedFont::from_locator(&handle).unwrap()
+    }
+
+    fn glyph_ids(font: &ParsedFont, text: &str, feature_mask: GsubFeatureMask) -> Vec<u32> {
+        font.shape_text(
+            text,
+            0,
+            0,
+            allsorts::tag::LATN,
+            allsorts::tag::DFLT,
+            10.,
+            72,
+            feature_mask,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|shaped| match shaped {
+            MaybeShaped::Resolved(info) => info.glyph_pos,
+            MaybeShaped::Unresolved { raw, .. } => panic!("unresolved {:?}", raw),
+        })
+        .collect()
+    }
+
+    #[test]
+    fn feature_settings() {
+        assert_eq!(parse_feature_setting("calt"), Some(("calt", true)));
+        assert_eq!(parse_feature_setting("+calt"), Some(("calt", true)));
+        assert_eq!(parse_feature_setting("-calt"), Some(("calt", false)));
+        assert_eq!(parse_feature_setting("calt=0"), Some(("calt", false)));
+        assert_eq!(parse_feature_setting("calt=1"), Some(("calt", true)));
+        assert_eq!(parse_feature_setting("nope=0"), Some(("nope", false)));
+        assert_eq!(parse_feature_setting("toolong"), None);
+
+        let mask = feature_mask_from_features(["calt=0", "dlig"].iter().copied());
+        assert!(!mask.contains(GsubFeatureMask::CALT));
+        assert!(mask.contains(GsubFeatureMask::DLIG));
+        assert!(mask.contains(GsubFeatureMask::LIGA));
+    }
+
+    #[test]
+    fn disable_calt() {
+        let font = jetbrains_mono();
+
+        let with_calt = glyph_ids(&font, "!=", feature_mask_from_features(std::iter::empty()));
+        let without_calt = glyph_ids(
+            &font,
+            "!=",
+            feature_mask_from_features(["calt=0"].iter().copied()),
+        );
+
+        let plain = vec![
+            font.glyph_index_for_char('!').unwrap() as u32,
+            font.glyph_index_for_char('=').unwrap() as u32,
+        ];
+        assert_eq!(without_calt, plain);
+        assert_ne!(with_calt, without_calt);
+    }
+
+    fn face(
+        family: &str,
+        style: &str,
+        weight: u16,
+        italic: bool,
+    ) -> (Names, PathBuf, FontDataHandle) {
+        let full_name = format!("{} {}", family, style);
+        (
+            Names {
+                full_name: full_name.clone(),
+                unique: None,
+                family: Some(family.to_string()),
+                sub_family: Some(style.to_string()),
+                typographic_family: None,
+                postscript_name: None,
+                weight,
+                width: 5,
+                italic,
+            },
+            PathBuf::from("memory"),
+            FontDataHandle::Memory {
+                data: vec![],
+                index: 0,
+                name: full_name,
+            },
+        )
+    }
+
+    fn select(
+        attr: FontAttributes,
+        faces: Vec<(Names, PathBuf, FontDataHandle)>,
+    ) -> Option<String> {
+        let mut loaded = HashSet::new();
+        ParsedFont::match_font_info(&[attr], faces, &mut loaded)
+            .unwrap()
+            .into_iter()
+            .next()
+            .map(|(handle, _)| match handle {
+                FontDataHandle::Memory { name, .. } => name,
+                FontDataHandle::OnDisk { .. } => unreachable!(),
+            })
+    }
+
+    #[test]
+    fn os2_style() {
+        let font = jetbrains_mono();
+        assert_eq!(font.weight(), 400);
+        assert_eq!(font.width(), 5);
+        assert!(!font.italic());
+    }
+
+    #[test]
+    fn sub_family_weights() {
+        assert_eq!(style_from_sub_family("Regular"), (400, false));
+        assert_eq!(style_from_sub_family("Bold"), (700, false));
+        assert_eq!(style_from_sub_family("SemiBold Italic"), (600, true));
+        assert_eq!(style_from_sub_family("Extra Light"), (200, false));
+        assert_eq!(style_from_sub_family("Black"), (900, false));
+        assert_eq!(style_from_sub_family("Thin Oblique"), (100, true));
+    }
+
+    #[test]
+    fn sub_family_matching() {
+        let names = |sub_family: &str| {
+            let family = utf16("Test");
+            let style = utf16(sub_family);
+            let full_name = utf16(&format!("Test {}", sub_family));
+            Names::from_name_table_data(&name_table(&[
+                (3, 1, 0x409, 1, &family),
+                (3, 1, 0x409, 2, &style),
+                (3, 1, 0x409, 4, &full_name),
+            ]))
+            .unwrap()
+        };
+        let attr = |bold, italic| FontAttributes {
+            bold,
+            italic,
+            ..FontAttributes::new("Test")
+        };
+        let matches = |sub_family: &str| {
+            let names = names(sub_family);
+            [(false, false), (false, true), (true, false), (true, true)]
+                .iter()
+                .filter(|(bold, italic)| font_info_matches(&attr(*bold, *italic), &names))
+                .copied()
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(matches("Regular"), vec![(false, false)]);
+        assert_eq!(matches("Italic"), vec![(false, true)]);
+        assert_eq!(matches("Bold"), vec![(true, false)]);
+        assert_eq!(matches("Bold Italic"), vec![(true, true)]);
+        assert_eq!(matches("Italic Bold"), vec![(true, true)]);
+        assert_eq!(matches("Oblique"), vec![(false, true)]);
+        assert_eq!(matches("BoldOblique"), vec![(true, true)]);
+        assert_eq!(matches("Bold-Oblique"), vec![(true, true)]);
+        // Medium is close enough to regular to satisfy it, but
+        // too far from bold
+        assert_eq!(matches("Medium Italic"), vec![(false, true)]);
+        assert_eq!(
+            select(
+                FontAttributes {
+                    weight: Some(500),
+                    ..attr(false, true)
+                },
+                vec![
+                    face("Test", "Italic", 400, true),
+                    face("Test", "Medium Italic", 500, true),
+                ]
+            )
+            .as_deref(),
+            Some("Test Medium Italic")
+        );
+    }
+
+    #[test]
+    fn closest_weight() {
+        let faces = || {
+            vec![
+                face("Test", "Black", 900, false),
+                face("Test", "Italic", 400, true),
+                face("Test", "Regular", 400, false),
+                face("Test", "SemiBold", 600, false),
+                face("Test", "Thin", 100, false),
+            ]
+        };
+        let attr = |bold, italic, weight| FontAttributes {
+            bold,
+            italic,
+            weight,
+            ..FontAttributes::new("Test")
+        };
+
+        assert_eq!(
+            select(attr(false, false, None), faces()).as_deref(),
+            Some("Test Regular")
+        );
+        assert_eq!(
+            select(attr(false, true, None), faces()).as_deref(),
+            Some("Test Italic")
+        );
+        // There is no Bold face; SemiBold is the closest
+        assert_eq!(
+            select(attr(true, false, None), faces()).as_deref(),
+            Some("Test SemiBold")
+        );
+        assert_eq!(
+            select(attr(false, false, Some(850)), faces()).as_deref(),
+            Some("Test Black")
+        );
+        assert_eq!(
+            select(attr(false, false, Some(200)), faces()).as_deref(),
+            Some("Test Thin")
+        );
+
+        // Regular is too far from Bold to be considered a match
+        assert_eq!(
+            select(
+                attr(true, false, None),
+                vec![face("Test", "Regular", 400, false)]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn closest_stretch() {
+        let stretched = |style: &str, width| {
+            let mut face = face("Test", style, 400, false);
+            face.0.width = width;
+            face
+        };
+        let faces = || {
+            vec![
+                stretched("Condensed", 3),
+                stretched("Expanded", 7),
+                stretched("Regular", 5),
+                stretched("SemiCondensed", 4),
+            ]
+        };
+        let attr = |stretch| FontAttributes {
+            stretch,
+            ..FontAttributes::new("Test")
+        };
+
+        assert_eq!(
+            select(attr(FontStretch::Normal), faces()).as_deref(),
+            Some("Test Regular")
+        );
+        assert_eq!(
+            select(attr(FontStretch::Condensed), faces()).as_deref(),
+            Some("Test Condensed")
+        );
+        assert_eq!(
+            select(attr(FontStretch::Expanded), faces()).as_deref(),
+            Some("Test Expanded")
+        );
+        // There is no UltraCondensed face; Condensed is the closest
+        assert_eq!(
+            select(attr(FontStretch::UltraCondensed), faces()).as_deref(),
+            Some("Test Condensed")
+        );
+
+        // A matching width is preferred over a closer weight
+        let mut bold_condensed = stretched("Bold Condensed", 3);
+        bold_condensed.0.weight = 700;
+        let mut semibold = stretched("SemiBold", 5);
+        semibold.0.weight = 600;
+        assert_eq!(
+            select(
+                FontAttributes {
+                    bold: true,
+                    ..attr(FontStretch::Condensed)
+                },
+                vec![bold_condensed, semibold]
+            )
+            .as_deref(),
+            Some("Test Bold Condensed")
+        );
+    }
+
+    #[test]
+    fn postscript_name() {
+        let named = |style: &str, postscript_name: &str| {
+            let mut face = face("Test", style, 400, false);
+            face.0.postscript_name = Some(postscript_name.to_string());
+            face
+        };
+        let faces = || {
+            // A different family whose name is the same as the
+            // PostScript name of one of the Test faces, and which
+            // sorts ahead of them
+            let mut other = named("Regular", "Other-Regular");
+            other.0.family = Some("Test-Book".to_string());
+            other.0.full_name = "A Test Book".to_string();
+            if let FontDataHandle::Memory { name, .. } = &mut other.2 {
+                *name = other.0.full_name.clone();
+            }
+            vec![
+                other,
+                named("Book", "Test-Book"),
+                named("Regular", "Test-Regular"),
+            ]
+        };
+
+        // Both Test faces are equally good matches for the family
+        assert_eq!(
+            select(FontAttributes::new("Test"), faces()).as_deref(),
+            Some("Test Book")
+        );
+        assert_eq!(
+            select(FontAttributes::new("Test-Regular"), faces()).as_deref(),
+            Some("Test Regular")
+        );
+        // The PostScript name takes precedence over the family, and
+        // selects the face regardless of the requested style
+        assert_eq!(
+            select(FontAttributes::new("Test-Book"), faces()).as_deref(),
+            Some("Test Book")
+        );
+        assert_eq!(
+            select(
+                FontAttributes {
+                    bold: true,
+                    italic: true,
+                    ..FontAttributes::new("Test-Regular")
+                },
+                faces()
+            )
+            .as_deref(),
+            Some("Test Regular")
+        );
+    }
+
+    #[test]
+    fn woff_in_font_dirs() {
+        let path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("../test-data/woff/WoffTest-Regular.woff2");
+        let faces = parse_font_faces(&path).unwrap();
+        assert_eq!(faces.len(), 1);
+        assert_eq!(faces[0].1.full_name(), "Woff Test Regular");
+
+        // FreeType is handed the decoded font when loading from disk
+        let handle = FontDataHandle::OnDisk { path, index: 0 };
+        let lib = crate::ftwrap::Library::new().unwrap();
+        lib.face_from_locator(&handle).unwrap();
+    }
+
+    #[test]
+    fn colr_layers() {
+        assert!(jetbrains_mono().color_glyphs().is_none());
+
+        let handle = FontDataHandle::Memory {
+            data: include_bytes!("../../test-data/colr/ColrTest-Regular.ttf").to_vec(),
+            index: 0,
+            name: "ColrTest-Regular".to_string(),
+        };
+        let font = ParsedFont::from_locator(&handle).unwrap();
+        let colr = font.color_glyphs().unwrap();
+        let glyph = font.glyph_index_for_char('A').unwrap();
+        assert_eq!(
+            colr.layers(glyph),
+            Some(
+                &[
+                    ColorLayer {
+                        glyph: 2,
+                        color: Some((0xff, 0, 0, 0xff))
+                    },
+                    ColorLayer {
+                        glyph: 3,
+                        color: Some((0, 0, 0xff, 0xff))
+                    }
+                ][..]
+            )
+        );
+        // The layer glyphs are not color glyphs themselves
+        assert_eq!(colr.layers(2), None);
+        assert!(!colr.uses_foreground(glyph));
+
+        // The right half of B is drawn in the color of the text
+        let glyph = font.glyph_index_for_char('B').unwrap();
+        assert_eq!(
+            colr.layers(glyph).map(|layers| layers[1]),
+            Some(ColorLayer {
+                glyph: 3,
+                color: None
+            })
+        );
+        assert!(colr.uses_foreground(glyph));
+    }
+
+    fn name_table(records: &[(u16, u16, u16, u16, &[u8])]) -> Vec<u8> {
+        let mut header = vec![];
+        let mut storage = vec![];
+        let count = records.len() as u16;
+        for &v in &[0, count, 6 + 12 * count] {
+            header.extend_from_slice(&v.to_be_bytes());
+        }
+        for (platform, encoding, language, name_id, data) in records {
+            let fields = [
+                *platform,
+                *encoding,
+                *language,
+                *name_id,
+                data.len() as u16,
+                storage.len() as u16,
+            ];
+            for v in &fields {
+                header.extend_from_slice(&v.to_be_bytes());
+            }
+            storage.extend_from_slice(data);
+        }
+        header.extend(storage);
+        header
+    }
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16()
+            .flat_map(|u| u.to_be_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn name_table_platforms() {
+        let japanese = utf16("等幅フォント");
+        let windows = utf16("Windows Name");
+        let table = name_table(&[
+            (3, 1, 0x411, 1, &japanese),
+            (1, 0, 0, 1, b"Mac Family"),
+            (3, 1, 0x411, 4, &japanese),
+            (1, 0, 0, 4, b"Mac Name"),
+            (3, 1, 0x409, 4, &windows),
+        ]);
+        let names = Names::from_name_table_data(&table).unwrap();
+        // The US English Windows name is preferred over the others,
+        // regardless of the order of the records
+        assert_eq!(names.full_name(), "Windows Name");
+        // Without one, the English Macintosh name is used rather than
+        // the Windows name in another language
+        assert_eq!(names.family.as_deref(), Some("Mac Family"));
+        // Other languages are used when there is nothing else
+        assert_eq!(
+            get_name(&name_table(&[(3, 1, 0x411, 4, &japanese)]), 4).unwrap(),
+            "等幅フォント"
+        );
+        assert!(get_name(&table, 6).is_err());
+    }
+
+    #[test]
+    fn name_table_mac_roman() {
+        let table = name_table(&[(1, 0, 0, 4, b"Caf\x8e \xa5 Na\x95ve")]);
+        assert_eq!(get_name(&table, 4).unwrap(), "Café • Naïve");
+    }
+
+    #[test]
+    fn bitmap_strike_metrics() {
+        assert!(jetbrains_mono().bitmap_strike(12., 96).is_none());
+
+        let handle = FontDataHandle::Memory {
+            data: include_bytes!("../../test-data/bitmap/BitmapTest-Regular.ttf").to_vec(),
+            index: 0,
+            name: "BitmapTest-Regular".to_string(),
+        };
+        let font = ParsedFont::from_locator(&handle).unwrap();
+
+        // 12pt at 96 dpi is 16px, which is exactly the larger strike
+        let metrics = font.get_metrics(12., 96);
+        assert_eq!(metrics.cell_height.get(), 16.);
+        assert_eq!(metrics.cell_width.get(), 8.);
+        assert_eq!(metrics.descender.get(), -3.);
+
+        // 8pt at 96 dpi is 10.67px, which is closest to the 10px strike
+        let metrics = font.get_metrics(8., 96);
+        assert_eq!(metrics.cell_height.get(), 10.);
+        assert_eq!(metrics.cell_width.get(), 5.);
+        assert_eq!(metrics.descender.get(), -2.);
+    }
+
+    #[test]
+    fn cell_width_reference() {
+        let handle = FontDataHandle::Memory {
+            data: include_bytes!("../../test-data/cellwidth/CellWidthTest-Regular.ttf").to_vec(),
+            index: 0,
+            name: "CellWidthTest-Regular".to_string(),
+        };
+        let font = ParsedFont::from_locator(&handle).unwrap();
+
+        // 10pt at 96 dpi is 13.33px per em.  `M` is 500 units wide,
+        // the digits are 700 units wide and xAvgCharWidth is 550 units;
+        // the cell width is always a whole number of pixels
+        let cell_width = |reference| {
+            font.get_metrics_with_reference(10., 96, reference)
+                .cell_width
+                .get()
+        };
+        assert_eq!(cell_width(CellWidthReference::default()), 6.);
+        assert_eq!(cell_width(CellWidthReference::Char('M')), 6.);
+        assert_eq!(cell_width(CellWidthReference::Char('0')), 9.);
+        assert_eq!(cell_width(CellWidthReference::MaxAscii), 9.);
+        assert_eq!(cell_width(CellWidthReference::AverageCharWidth), 7.);
+        // A character that the font doesn't have falls back to
+        // the widest ASCII character
+        assert_eq!(cell_width(CellWidthReference::Char('A')), 9.);
+
+        // The reference makes no difference to a monospace font
+        let font = jetbrains_mono();
+        for &reference in &[
+            CellWidthReference::Char('M'),
+            CellWidthReference::MaxAscii,
+            CellWidthReference::AverageCharWidth,
+        ] {
+            assert_eq!(
+                font.get_metrics_with_reference(75., 96, reference)
+                    .cell_width
+                    .get(),
+                60.
+            );
+        }
+    }
+
+    #[test]
+    fn variable_font_instances() {
+        let handle = FontDataHandle::Memory {
+            data: include_bytes!("../../test-data/variable/VariableTest-Regular.ttf").to_vec(),
+            index: 0,
+            name: "VariableTest-Regular".to_string(),
+        };
+        let mut font = ParsedFont::from_locator(&handle).unwrap();
+        assert_eq!(
+            font.variation_axes()
+                .iter()
+                .map(|axis| (&axis.tag, axis.min, axis.default, axis.max))
+                .collect::<Vec<_>>(),
+            vec![(b"wght", 100., 400., 900.)]
+        );
+        assert!(jetbrains_mono().variation_axes().is_empty());
+
+        let attr = |instance: Option<&str>, variations: &[&str]| FontAttributes {
+            instance: instance.map(|s| s.to_string()),
+            variations: variations.iter().map(|s| s.parse().unwrap()).collect(),
+            ..FontAttributes::new("Variable Test")
+        };
+        assert!(font.design_coords(&attr(None, &[])).is_empty());
+        assert_eq!(font.design_coords(&attr(Some("black"), &[])), vec![900.]);
+        assert_eq!(font.design_coords(&attr(None, &["wght=350"])), vec![350.]);
+        // Axis settings override the named instance, and are clamped
+        // to the range of the axis
+        assert_eq!(
+            font.design_coords(&attr(Some("Thin"), &["wght=2000"])),
+            vec![900.]
+        );
+        // Unknown instances and axes are ignored
+        assert_eq!(
+            font.design_coords(&attr(Some("Nope"), &["wdth=50"])),
+            vec![400.]
+        );
+
+        // 75pt at 96 dpi is 100px per em
+        let metrics = |font: &ParsedFont| {
+            let metrics = font.get_metrics(75., 96);
+            (metrics.cell_width.get(), metrics.cell_height.get())
+        };
+        assert_eq!(metrics(&font), (60., 100.));
+        font.set_design_coords(&[900.]);
+        assert_eq!(metrics(&font), (80., 110.));
+        // avar maps wght=650 to 0.75 of the way to the maximum,
+        // rather than half way
+        font.set_design_coords(&[650.]);
+        assert_eq!(metrics(&font), (75., 107.5));
+        font.set_design_coords(&[]);
+        assert_eq!(metrics(&font), (60., 100.));
+    }
+
+    #[test]
+    fn glyph_outline() {
+        use crate::outline::PathCommand;
+
+        let font = jetbrains_mono();
+        let glyph = font.glyph_index_for_char('I').unwrap();
+        let outline = font.glyph_outline(glyph).unwrap();
+
+        assert_eq!(outline.advance, 600.);
+        // The serifed I is a single contour of straight lines
+        assert_eq!(outline.num_contours(), 1);
+        let points: Vec<(f32, f32)> = outline
+            .commands
+            .iter()
+            .filter_map(|cmd| match *cmd {
+                PathCommand::MoveTo { x, y } | PathCommand::LineTo { x, y } => Some((x, y)),
+                PathCommand::Close => None,
+                _ => panic!("unexpected curve {:?}", cmd),
+            })
+            .collect();
+        assert_eq!(points.len(), 12);
+        assert_eq!(outline.commands.last(), Some(&PathCommand::Close));
+        // It lies on the baseline, spans the cap height and fits
+        // within the advance
+        for &(x, y) in &points {
+            assert!(x > 0. && x < 600., "x={}", x);
+            assert!(y >= 0. && y <= 730., "y={}", y);
+        }
+        assert!(points.iter().any(|&(_, y)| y == 0.));
+        assert!(points.iter().any(|&(_, y)| y == 730.));
+
+        let space = font
+            .glyph_outline(font.glyph_index_for_char(' ').unwrap())
+            .unwrap();
+        assert!(space.commands.is_empty());
+        assert_eq!(space.advance, 600.);
+
+        // The O is an outer and an inner ring of quadratic curves
+        let o = font
+            .glyph_outline(font.glyph_index_for_char('O').unwrap())
+            .unwrap();
+        assert_eq!(o.num_contours(), 2);
+        assert!(o
+            .commands
+            .iter()
+            .any(|cmd| matches!(cmd, PathCommand::QuadTo { .. })));
+
+        assert!(font.glyph_outline(u16::MAX).is_err());
+    }
 }
@@ -1,17 +1,21 @@
 use crate::locator::FontDataHandle;
-use crate::rasterizer::FontRasterizer;
+use crate::parser::{ColorGlyphs, ColorLayer, RgbaColor};
+use crate::rasterizer::{FontRasterizer, SyntheticStyle};
 use crate::units::*;
 use crate::{ftwrap, RasterizedGlyph};
 use ::freetype::FT_GlyphSlotRec_;
 use anyhow::bail;
 use std::cell::RefCell;
 use std::mem;
+use std::rc::Rc;
 use std::slice;
 
 pub struct FreeTypeRasterizer {
     has_color: bool,
+    color_glyphs: Option<Rc<ColorGlyphs>>,
     face: RefCell<ftwrap::Face>,
     _lib: ftwrap::Library,
+    synthesize: SyntheticStyle,
 }
 
 impl FontRasterizer for FreeTypeRasterizer {
@@ -20,26 +24,52 @@ impl FontRasterizer for FreeTypeRasterizer {
         glyph_pos: u32,
         size: f64,
         dpi: u32,
+        foreground: RgbaColor,
+    ) -> anyhow::Result<RasterizedGlyph> {
+        let (load_flags, render_mode) = ftwrap::compute_load_flags_from_config();
+        self.rasterize_glyph_with_flags(glyph_pos, size, dpi, load_flags, render_mode, foreground)
+    }
+
+    fn has_color(&self) -> bool {
+        self.has_color || self.color_glyphs.is_some()
+    }
+
+    fn uses_foreground(&self, glyph_pos: u32) -> bool {
+        self.color_glyphs
+            .as_ref()
+            .map(|colr| colr.uses_foreground(glyph_pos as u16))
+            .unwrap_or(false)
+    }
+}
+
+impl FreeTypeRasterizer {
+    fn rasterize_glyph_with_flags(
+        &self,
+        glyph_pos: u32,
+        size: f64,
+        dpi: u32,
+        load_flags: i32,
+        render_mode: ftwrap::FT_Render_Mode,
+        foreground: RgbaColor,
     ) -> anyhow::Result<RasterizedGlyph> {
         self.face.borrow_mut().set_font_size(size, dpi)?;
 
-        let (load_flags, render_mode) = ftwrap::compute_load_flags_from_config();
+        if let Some(layers) = self
+            .color_glyphs
+            .as_ref()
+            .and_then(|colr| colr.layers(glyph_pos as u16))
+        {
+            return self.rasterize_color_layers(layers, load_flags, render_mode, foreground);
+        }
 
         let mut face = self.face.borrow_mut();
         let descender = unsafe { (*(*face.face).size).metrics.descender as f64 / 64.0 };
-        let ft_glyph = face.load_and_render_glyph(glyph_pos, load_flags, render_mode)?;
+        let ft_glyph =
+            face.load_and_render_glyph(glyph_pos, load_flags, render_mode, &self.synthesize)?;
 
         let mode: ftwrap::FT_Pixel_Mode =
             unsafe { mem::transmute(u32::from(ft_glyph.bitmap.pixel_mode)) };
-
-        // pitch is the number of bytes per source row
-        let pitch = ft_glyph.bitmap.pitch.abs() as usize;
-        let data = unsafe {
-            slice::from_raw_parts_mut(
-                ft_glyph.bitmap.buffer,
-                ft_glyph.bitmap.rows as usize * pitch,
-            )
-        };
+        let (pitch, data) = bitmap_data(ft_glyph);
 
         let glyph = match mode {
             ftwrap::FT_Pixel_Mode::FT_PIXEL_MODE_LCD => self.rasterize_lcd(pitch, ft_glyph, data),
@@ -52,9 +82,55 @@ impl FontRasterizer for FreeTypeRasterizer {
         };
         Ok(glyph)
     }
-}
 
-impl FreeTypeRasterizer {
+    /// Render each of the layers of a COLR glyph as a coverage mask
+    /// and composite them, filled with their colors, into a color bitmap.
+    /// Layers in the color of the text are filled with `foreground`.
+    fn rasterize_color_layers(
+        &self,
+        layers: &[ColorLayer],
+        load_flags: i32,
+        render_mode: ftwrap::FT_Render_Mode,
+        foreground: RgbaColor,
+    ) -> anyhow::Result<RasterizedGlyph> {
+        // The layers are masks, so there is no use for subpixel coverage
+        // or for any color bitmaps for the layer glyphs
+        let load_flags = load_flags & !(ftwrap::FT_LOAD_COLOR as i32);
+        let render_mode = match render_mode {
+            ftwrap::FT_Render_Mode::FT_RENDER_MODE_LCD
+            | ftwrap::FT_Render_Mode::FT_RENDER_MODE_LCD_V => {
+                ftwrap::FT_Render_Mode::FT_RENDER_MODE_NORMAL
+            }
+            mode => mode,
+        };
+
+        let mut face = self.face.borrow_mut();
+        let mut masks = vec![];
+        for layer in layers {
+            let ft_glyph = face.load_and_render_glyph(
+                u32::from(layer.glyph),
+                load_flags,
+                render_mode,
+                &self.synthesize,
+            )?;
+            let mode: ftwrap::FT_Pixel_Mode =
+                unsafe { mem::transmute(u32::from(ft_glyph.bitmap.pixel_mode)) };
+            let (pitch, data) = bitmap_data(ft_glyph);
+            let mask = match mode {
+                ftwrap::FT_Pixel_Mode::FT_PIXEL_MODE_GRAY => {
+                    self.rasterize_gray(pitch, ft_glyph, data)
+                }
+                ftwrap::FT_Pixel_Mode::FT_PIXEL_MODE_MONO => {
+                    self.rasterize_mono(pitch, ft_glyph, data)
+                }
+                mode => bail!("unhandled pixel mode for color layer: {:?}", mode),
+            };
+            masks.push((mask, layer.color.unwrap_or(foreground)));
+        }
+
+        Ok(composite_color_layers(&masks))
+    }
+
     fn rasterize_mono(
         &self,
         pitch: usize,
@@ -150,7 +226,9 @@ impl FreeTypeRasterizer {
                 let red = data[src_offset + (x * 3)];
                 let green = data[src_offset + (x * 3) + 1];
                 let blue = data[src_offset + (x * 3) + 2];
-                let alpha = red.min(green).min(blue);
+                // The renderer blends each channel by its own coverage;
+                // the alpha bounds the area touched by any of them.
+                let alpha = red.max(green).max(blue);
                 rgba[dest_offset + (x * 4)] = red;
                 rgba[dest_offset + (x * 4) + 1] = green;
                 rgba[dest_offset + (x * 4) + 2] = blue;
@@ -274,10 +352,18 @@ impl FreeTypeRasterizer {
         }
     }
 
-    pub fn from_locator(handle: &FontDataHandle) -> anyhow::Result<Self> {
+    /// FreeType renders only the base outline of a layered COLR glyph,
+    /// so the layers in `color_glyphs` are composited by the rasterizer
+    pub fn from_locator(
+        handle: &FontDataHandle,
+        synthesize: SyntheticStyle,
+        design_coords: &[f64],
+        color_glyphs: Option<Rc<ColorGlyphs>>,
+    ) -> anyhow::Result<Self> {
         log::trace!("Rasterizier wants {:?}", handle);
         let lib = ftwrap::Library::new()?;
-        let face = lib.face_from_locator(handle)?;
+        let mut face = lib.face_from_locator(handle)?;
+        face.set_design_coords(design_coords)?;
         let has_color = unsafe {
             (((*face.face).face_flags as u32) & (ftwrap::FT_FACE_FLAG_COLOR as u32)) != 0
         };
@@ -285,6 +371,377 @@ impl FreeTypeRasterizer {
             _lib: lib,
             face: RefCell::new(face),
             has_color,
+            color_glyphs,
+            synthesize,
         })
     }
 }
+
+/// Returns the pitch, which is the number of bytes per row, along
+/// with the pixel data of the bitmap in the glyph slot
+fn bitmap_data(ft_glyph: &FT_GlyphSlotRec_) -> (usize, &[u8]) {
+    let pitch = ft_glyph.bitmap.pitch.abs() as usize;
+    let data = unsafe {
+        slice::from_raw_parts(
+            ft_glyph.bitmap.buffer,
+            ft_glyph.bitmap.rows as usize * pitch,
+        )
+    };
+    (pitch, data)
+}
+
+/// Composite coverage masks, from bottom to top, filling each with its
+/// RGBA color.  The result covers the union of the bounds of the masks.
+fn composite_color_layers(layers: &[(RasterizedGlyph, RgbaColor)]) -> RasterizedGlyph {
+    let inked = || {
+        layers
+            .iter()
+            .map(|(mask, _)| mask)
+            .filter(|mask| mask.width > 0 && mask.height > 0)
+    };
+    let left = inked().map(|m| m.bearing_x.get() as isize).min();
+    let top = inked().map(|m| m.bearing_y.get() as isize).max();
+    let right = inked()
+        .map(|m| m.bearing_x.get() as isize + m.width as isize)
+        .max();
+    let bottom = inked()
+        .map(|m| m.bearing_y.get() as isize - m.height as isize)
+        .min();
+
+    let (left, top, right, bottom) = match (left, top, right, bottom) {
+        (Some(left), Some(top), Some(right), Some(bottom)) => (left, top, right, bottom),
+        _ => (0, 0, 0, 0),
+    };
+    let width = (right - left) as usize;
+    let height = (top - bottom) as usize;
+    let mut rgba = vec![0u8; width * height * 4];
+
+    for (mask, (red, green, blue, alpha)) in layers {
+        let x_offset = (mask.bearing_x.get() as isize - left) as usize;
+        let y_offset = (top - mask.bearing_y.get() as isize) as usize;
+        for y in 0..mask.height {
+            for x in 0..mask.width {
+                let coverage = mask.data[(y * mask.width + x) * 4 + 3];
+                if coverage == 0 {
+                    continue;
+                }
+                // Source over, with pre-multiplied alpha
+                let src_alpha = f32::from(*alpha) * f32::from(coverage) / (255. * 255.);
+                let dest = ((y + y_offset) * width + x + x_offset) * 4;
+                for (i, component) in [*red, *green, *blue, 0xff].iter().enumerate() {
+                    let src = f32::from(*component) * src_alpha;
+                    let dst = f32::from(rgba[dest + i]) * (1. - src_alpha);
+                    rgba[dest + i] = (src + dst).round().min(255.) as u8;
+                }
+            }
+        }
+    }
+
+    RasterizedGlyph {
+        data: rgba,
+        height,
+        width,
+        bearing_x: PixelLength::new(left as f64),
+        bearing_y: PixelLength::new(top as f64),
+        has_color: true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::ParsedFont;
+    use config::{FontAntiAliasing, FontHinting};
+    use std::collections::HashSet;
+
+    const WHITE: RgbaColor = (0xff, 0xff, 0xff, 0xff);
+
+    fn inked_pixels(glyph: &RasterizedGlyph) -> usize {
+        glyph.data.chunks(4).filter(|px| px[3] != 0).count()
+    }
+
+    fn rasterize(c: char, synthesize: SyntheticStyle) -> RasterizedGlyph {
+        let (load_flags, render_mode) = ftwrap::compute_load_flags_from_config();
+        rasterize_with_flags(c, synthesize, load_flags, render_mode)
+    }
+
+    fn rasterize_with_flags(
+        c: char,
+        synthesize: SyntheticStyle,
+        load_flags: i32,
+        render_mode: ftwrap::FT_Render_Mode,
+    ) -> RasterizedGlyph {
+        let handle = FontDataHandle::Memory {
+            data: include_bytes!("../../../assets/fonts/JetBrainsMono-Regular.ttf").to_vec(),
+            index: 0,
+            name: "JetBrainsMono-Regular".to_string(),
+        };
+        rasterize_font(&handle, c, synthesize, load_flags, render_mode)
+    }
+
+    fn rasterize_font(
+        handle: &FontDataHandle,
+        c: char,
+        synthesize: SyntheticStyle,
+        load_flags: i32,
+        render_mode: ftwrap::FT_Render_Mode,
+    ) -> RasterizedGlyph {
+        rasterize_font_in_color(handle, c, synthesize, load_flags, render_mode, WHITE)
+    }
+
+    fn rasterize_font_in_color(
+        handle: &FontDataHandle,
+        c: char,
+        synthesize: SyntheticStyle,
+        load_flags: i32,
+        render_mode: ftwrap::FT_Render_Mode,
+        foreground: RgbaColor,
+    ) -> RasterizedGlyph {
+        let parsed = ParsedFont::from_locator(handle).unwrap();
+        let glyph_pos = parsed.glyph_index_for_char(c).unwrap() as u32;
+        let color_glyphs = parsed.color_glyphs().cloned().map(Rc::new);
+
+        FreeTypeRasterizer::from_locator(handle, synthesize, &[], color_glyphs)
+            .unwrap()
+            .rasterize_glyph_with_flags(glyph_pos, 12., 96, load_flags, render_mode, foreground)
+            .unwrap()
+    }
+
+    #[test]
+    fn subpixel_coverage() {
+        let (load_flags, render_mode) =
+            ftwrap::compute_load_flags(FontAntiAliasing::Subpixel, FontHinting::Full);
+        let stem = rasterize_with_flags('|', SyntheticStyle::default(), load_flags, render_mode);
+
+        // The edges of a vertical stem only partially cover the
+        // subpixels of the columns they fall in, so the red, green
+        // and blue coverage differs there
+        let fringes = stem
+            .data
+            .chunks(4)
+            .filter(|px| px[0] != px[1] || px[1] != px[2])
+            .count();
+        assert!(
+            fringes > 0,
+            "expected per-channel coverage in the {}x{} stem",
+            stem.width,
+            stem.height
+        );
+
+        // The alpha covers whichever channel has the most coverage
+        for px in stem.data.chunks(4) {
+            assert_eq!(px[3], px[0].max(px[1]).max(px[2]));
+        }
+
+        let (load_flags, render_mode) =
+            ftwrap::compute_load_flags(FontAntiAliasing::Greyscale, FontHinting::Full);
+        let stem = rasterize_with_flags('|', SyntheticStyle::default(), load_flags, render_mode);
+        assert!(stem
+            .data
+            .chunks(4)
+            .all(|px| px[0] == px[1] && px[1] == px[2] && px[2] == px[3]));
+    }
+
+    #[test]
+    fn synthetic_bold() {
+        let regular = rasterize('A', SyntheticStyle::default());
+        let bold = rasterize(
+            'A',
+            SyntheticStyle {
+                bold: true,
+                ..Default::default()
+            },
+        );
+
+        assert!(
+            inked_pixels(&bold) > inked_pixels(&regular),
+            "bold {} should have more inked pixels than regular {}",
+            inked_pixels(&bold),
+            inked_pixels(&regular)
+        );
+    }
+
+    #[test]
+    fn synthetic_italic() {
+        let upright = rasterize('l', SyntheticStyle::default());
+        let italic = rasterize(
+            'l',
+            SyntheticStyle {
+                italic: Some(12.),
+                ..Default::default()
+            },
+        );
+
+        assert!(
+            italic.width > upright.width,
+            "italic width {} should be wider than upright {}",
+            italic.width,
+            upright.width
+        );
+        // The shear is around the baseline, so the top of the glyph
+        // is pushed to the right and the right edge moves with it
+        let right_edge = |g: &RasterizedGlyph| g.bearing_x.get() + g.width as f64;
+        assert!(
+            right_edge(&italic) > right_edge(&upright),
+            "italic right edge {} should be offset from upright {}",
+            right_edge(&italic),
+            right_edge(&upright)
+        );
+        assert_eq!(italic.height, upright.height);
+    }
+
+    #[test]
+    fn hinting() {
+        let bounds =
+            |g: &RasterizedGlyph| (g.bearing_x.get(), g.bearing_y.get(), g.width, g.height);
+        let rasterize_hinted = |hinting| {
+            let (load_flags, render_mode) =
+                ftwrap::compute_load_flags(FontAntiAliasing::Greyscale, hinting);
+            rasterize_with_flags('H', SyntheticStyle::default(), load_flags, render_mode)
+        };
+
+        let unhinted = rasterize_hinted(FontHinting::None);
+        let hinted = rasterize_hinted(FontHinting::Full);
+
+        // Hinting snaps the stems and the cap height to the pixel
+        // grid, which changes the inked pixels
+        assert!(
+            hinted.data != unhinted.data,
+            "hinted {:?} and unhinted {:?} glyphs should differ",
+            bounds(&hinted),
+            bounds(&unhinted)
+        );
+    }
+
+    #[test]
+    fn colr_layers() {
+        let handle = FontDataHandle::Memory {
+            data: include_bytes!("../../../test-data/colr/ColrTest-Regular.ttf").to_vec(),
+            index: 0,
+            name: "ColrTest-Regular".to_string(),
+        };
+        let (load_flags, render_mode) =
+            ftwrap::compute_load_flags(FontAntiAliasing::Subpixel, FontHinting::Full);
+        let glyph = rasterize_font(
+            &handle,
+            'A',
+            SyntheticStyle::default(),
+            load_flags,
+            render_mode,
+        );
+        assert!(glyph.has_color);
+
+        // The glyph is a red layer beside a blue layer, rather than
+        // the single color of the base outline
+        let colors: HashSet<_> = glyph
+            .data
+            .chunks(4)
+            .filter(|px| px[3] == 0xff)
+            .map(|px| (px[0], px[1], px[2]))
+            .collect();
+        assert!(
+            colors.contains(&(0xff, 0, 0)) && colors.contains(&(0, 0, 0xff)),
+            "expected red and blue in the {}x{} glyph, found {:?}",
+            glyph.width,
+            glyph.height,
+            colors
+        );
+
+        // The layers are side by side, so the left column is red
+        // and the right column is blue
+        let pixel = |x: usize, y: usize| {
+            let offset = (y * glyph.width + x) * 4;
+            (
+                glyph.data[offset],
+                glyph.data[offset + 1],
+                glyph.data[offset + 2],
+            )
+        };
+        let y = glyph.height / 2;
+        assert_eq!(pixel(1, y), (0xff, 0, 0));
+        assert_eq!(pixel(glyph.width - 2, y), (0, 0, 0xff));
+
+        // The right half of B is filled with the color of the text
+        let glyph = rasterize_font_in_color(
+            &handle,
+            'B',
+            SyntheticStyle::default(),
+            load_flags,
+            render_mode,
+            (0, 0x80, 0, 0xff),
+        );
+        let offset = ((glyph.height / 2) * glyph.width + glyph.width - 2) * 4;
+        assert_eq!(&glyph.data[offset..offset + 4], &[0, 0x80, 0, 0xff]);
+    }
+
+    #[test]
+    fn bitmap_strikes() {
+        let handle = FontDataHandle::Memory {
+            data: include_bytes!("../../../test-data/bitmap/BitmapTest-Regular.ttf").to_vec(),
+            index: 0,
+            name: "BitmapTest-Regular".to_string(),
+        };
+        let glyph_pos = ParsedFont::from_locator(&handle)
+            .unwrap()
+            .glyph_index_for_char('A')
+            .unwrap() as u32;
+        let rasterizer =
+            FreeTypeRasterizer::from_locator(&handle, SyntheticStyle::default(), &[], None)
+                .unwrap();
+        let (load_flags, render_mode) =
+            ftwrap::compute_load_flags(FontAntiAliasing::Greyscale, FontHinting::Full);
+
+        // The glyph is the box from the strike closest to the requested
+        // size: 16px for 12pt and 10px for 8pt at 96 dpi
+        for &(size, width, height, cell) in &[(12., 8, 12, (8., 16.)), (8., 5, 8, (5., 10.))] {
+            let glyph = rasterizer
+                .rasterize_glyph_with_flags(glyph_pos, size, 96, load_flags, render_mode, WHITE)
+                .unwrap();
+            assert_eq!((glyph.width, glyph.height), (width, height));
+            assert_eq!(glyph.bearing_y.get(), height as f64);
+            assert_eq!(inked_pixels(&glyph), 2 * (width + height) - 4);
+
+            let metrics = rasterizer
+                .face
+                .borrow_mut()
+                .set_font_size(size, 96)
+                .unwrap();
+            assert_eq!(metrics, cell);
+        }
+    }
+
+    #[test]
+    fn variable_font_weights() {
+        let handle = FontDataHandle::Memory {
+            data: include_bytes!("../../../test-data/variable/VariableTest-Regular.ttf").to_vec(),
+            index: 0,
+            name: "VariableTest-Regular".to_string(),
+        };
+        let glyph_pos = ParsedFont::from_locator(&handle)
+            .unwrap()
+            .glyph_index_for_char('A')
+            .unwrap() as u32;
+        let (load_flags, render_mode) =
+            ftwrap::compute_load_flags(FontAntiAliasing::Greyscale, FontHinting::None);
+        let stem_width = |design_coords: &[f64]| {
+            FreeTypeRasterizer::from_locator(
+                &handle,
+                SyntheticStyle::default(),
+                design_coords,
+                None,
+            )
+            .unwrap()
+            .rasterize_glyph_with_flags(glyph_pos, 75., 96, load_flags, render_mode, WHITE)
+            .unwrap()
+            .width
+        };
+
+        // The stem is 20 units wide at wght=100, 100 units by default
+        // and 300 units at wght=900; at 100px per em that is 2, 10
+        // and 30 pixels
+        let thin = stem_width(&[100.]);
+        let regular = stem_width(&[]);
+        let black = stem_width(&[900.]);
+        assert_eq!((thin, regular, black), (2, 10, 30));
+    }
+}
@@ -1,6 +1,8 @@
 use crate::locator::FontDataHandle;
+use crate::parser::{ColorGlyphs, RgbaColor};
 use crate::units::*;
 use config::FontRasterizerSelection;
+use std::rc::Rc;
 
 pub mod freetype;
 
@@ -15,24 +17,55 @@ pub struct RasterizedGlyph {
     pub has_color: bool,
 }
 
+/// Describes styling that should be synthesized by the rasterizer
+/// because the font doesn't provide a face with that style.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SyntheticStyle {
+    /// Embolden the glyph outlines
+    pub bold: bool,
+    /// Slant the glyph outlines to the right by this angle,
+    /// expressed in degrees
+    pub italic: Option<f64>,
+}
+
 /// Rasterizes the specified glyph index in the associated font
 /// and returns the generated bitmap
 pub trait FontRasterizer {
+    /// Layers of a color glyph that are drawn in the color of the
+    /// text are filled with `foreground`
     fn rasterize_glyph(
         &self,
         glyph_pos: u32,
         size: f64,
         dpi: u32,
+        foreground: RgbaColor,
     ) -> anyhow::Result<RasterizedGlyph>;
+
+    /// Returns true if the font has color glyphs, such as an emoji font
+    fn has_color(&self) -> bool;
+
+    /// Returns true if the rasterized glyph depends on the color of
+    /// the text, because some of its color layers are drawn in it
+    fn uses_foreground(&self, glyph_pos: u32) -> bool;
 }
 
+/// `color_glyphs` are the layered color glyphs of the font, which
+/// are parsed once by the caller and shared between rasterizers
 pub fn new_rasterizer(
     rasterizer: FontRasterizerSelection,
     handle: &FontDataHandle,
+    synthesize: SyntheticStyle,
+    design_coords: &[f64],
+    color_glyphs: Option<Rc<ColorGlyphs>>,
 ) -> anyhow::Result<Box<dyn FontRasterizer>> {
     match rasterizer {
-        FontRasterizerSelection::FreeType => Ok(Box::new(
-            freetype::FreeTypeRasterizer::from_locator(handle)?,
-        )),
+        FontRasterizerSelection::FreeType => {
+            Ok(Box::new(freetype::FreeTypeRasterizer::from_locator(
+                handle,
+                synthesize,
+                design_coords,
+                color_glyphs,
+            )?))
+        }
     }
 }
@@ -1,19 +1,25 @@
 use crate::locator::FontDataHandle;
 use crate::parser::*;
 use crate::shaper::{FallbackIdx, FontMetrics, FontShaper, GlyphInfo};
+use allsorts::gsub::GsubFeatureMask;
 use anyhow::{anyhow, bail};
+use config::configuration;
+use termwiz::cell::unicode_column_width;
 
 pub struct AllsortsShaper {
     fonts: Vec<Option<ParsedFont>>,
 }
 
 impl AllsortsShaper {
-    pub fn new(handles: &[FontDataHandle]) -> anyhow::Result<Self> {
+    pub fn new(handles: &[FontDataHandle], design_coords: &[Vec<f64>]) -> anyhow::Result<Self> {
         let mut fonts = vec![];
         let mut success = false;
-        for handle in handles {
+        for (idx, handle) in handles.iter().enumerate() {
             match ParsedFont::from_locator(handle) {
-                Ok(font) => {
+                Ok(mut font) => {
+                    if let Some(coords) = design_coords.get(idx) {
+                        font.set_design_coords(coords);
+                    }
                     fonts.push(Some(font));
                     success = true;
                 }
@@ -39,6 +45,7 @@ impl AllsortsShaper {
         lang: u32,
         font_size: f64,
         dpi: u32,
+        feature_mask: GsubFeatureMask,
         results: &mut Vec<GlyphInfo>,
     ) -> anyhow::Result<()> {
         let font = match self.fonts.get(font_index) {
@@ -52,34 +59,55 @@ impl AllsortsShaper {
                     lang,
                     font_size,
                     dpi,
+                    feature_mask,
                     results,
                 );
             }
             None => {
                 // We ran out of fallback fonts, so use a replacement
                 // character that is likely to be in one of those fonts
-                let mut alt_text = String::new();
-                for _c in s.chars() {
-                    alt_text.push('?');
-                }
-                if alt_text == s {
+                if s.chars().all(|c| c == '?') {
                     // We already tried to fallback to this and failed
                     return Err(anyhow!("could not fallback to ? character"));
                 }
-                return self.shape_into(
-                    0,
-                    &alt_text,
-                    slice_index,
-                    script,
-                    lang,
-                    font_size,
-                    dpi,
-                    results,
-                );
+                // Each character is replaced separately so that the
+                // clusters and cell widths still describe the original
+                // text; zero width characters such as variation selectors
+                // and combining marks don't get a replacement of their own
+                for (idx, c) in s.char_indices() {
+                    let num_cells = unicode_column_width(c.encode_utf8(&mut [0; 4]));
+                    if num_cells == 0 {
+                        continue;
+                    }
+                    let start = results.len();
+                    self.shape_into(
+                        0,
+                        "?",
+                        slice_index + idx,
+                        script,
+                        lang,
+                        font_size,
+                        dpi,
+                        feature_mask,
+                        results,
+                    )?;
+                    for info in &mut results[start..] {
+                        info.num_cells = num_cells as u8;
+                    }
+                }
+                return Ok(());
             }
         };
-        let first_pass =
-            font.shape_text(s, slice_index, font_index, script, lang, font_size, dpi)?;
+        let first_pass = font.shape_text(
+            s,
+            slice_index,
+            font_index,
+            script,
+            lang,
+            font_size,
+            dpi,
+            feature_mask,
+        )?;
 
         let mut item_iter = first_pass.into_iter();
         while let Some(item) = item_iter.next() {
@@ -89,8 +117,9 @@ impl AllsortsShaper {
                 }
                 MaybeShaped::Unresolved { raw, slice_start } => {
                     // There was no glyph in that font, so we'll need to shape
-                    // using a fallback.  Let's collect together any potential
-                    // run of unresolved entries first
+                    // using a fallback.  `shape_text` has already collected
+                    // adjacent unresolved characters together into this run,
+                    // and `slice_start` is its offset in the original text
                     self.shape_into(
                         font_index + 1,
                         &raw,
@@ -99,6 +128,7 @@ impl AllsortsShaper {
                         lang,
                         font_size,
                         dpi,
+                        feature_mask,
                         results,
                     )?;
                 }
@@ -110,11 +140,46 @@ impl AllsortsShaper {
 }
 
 impl FontShaper for AllsortsShaper {
-    fn shape(&self, text: &str, size: f64, dpi: u32) -> anyhow::Result<Vec<GlyphInfo>> {
+    fn shape(
+        &self,
+        text: &str,
+        size: f64,
+        dpi: u32,
+        features: &[String],
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
+        self.shape_from(0, text, size, dpi, features)
+    }
+
+    fn shape_from(
+        &self,
+        font_idx: FallbackIdx,
+        text: &str,
+        size: f64,
+        dpi: u32,
+        features: &[String],
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
         let mut results = vec![];
         let script = allsorts::tag::LATN;
         let lang = allsorts::tag::DFLT;
-        self.shape_into(0, text, 0, script, lang, size, dpi, &mut results)?;
+        let config = configuration();
+        let feature_mask = feature_mask_from_features(
+            config
+                .harfbuzz_features
+                .iter()
+                .chain(features.iter())
+                .map(String::as_str),
+        );
+        self.shape_into(
+            font_idx,
+            text,
+            0,
+            script,
+            lang,
+            size,
+            dpi,
+            feature_mask,
+            &mut results,
+        )?;
         // log::error!("shape {} into {:?}", text, results);
         Ok(results)
     }
@@ -139,3 +204,89 @@ impl FontShaper for AllsortsShaper {
         bail!("no fonts available for collecting metrics!?");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shaper() -> AllsortsShaper {
+        let handles = [
+            FontDataHandle::Memory {
+                data: include_bytes!("../../../assets/fonts/JetBrainsMono-Regular.ttf").to_vec(),
+                index: 0,
+                name: "JetBrainsMono-Regular".to_string(),
+            },
+            FontDataHandle::Memory {
+                data: include_bytes!("../../../assets/fonts/WeztermSymbols-Regular.ttf").to_vec(),
+                index: 0,
+                name: "WeztermSymbols-Regular".to_string(),
+            },
+        ];
+        AllsortsShaper::new(&handles, &[]).unwrap()
+    }
+
+    fn shape(shaper: &AllsortsShaper, text: &str) -> Vec<GlyphInfo> {
+        shaper.shape(text, 10., 72, &[]).unwrap()
+    }
+
+    fn clusters(glyphs: &[GlyphInfo]) -> Vec<u32> {
+        glyphs.iter().map(|g| g.cluster).collect()
+    }
+
+    fn font_indices(glyphs: &[GlyphInfo]) -> Vec<FallbackIdx> {
+        glyphs.iter().map(|g| g.font_idx).collect()
+    }
+
+    fn num_cells(glyphs: &[GlyphInfo]) -> Vec<u8> {
+        glyphs.iter().map(|g| g.num_cells).collect()
+    }
+
+    #[test]
+    fn latin_with_ligatures() {
+        let shaper = shaper();
+        let bang = shape(&shaper, "!")[0].glyph_pos;
+        let equals = shape(&shaper, "=")[0].glyph_pos;
+
+        // JetBrains Mono substitutes a glyph per cell for the
+        // parts of the "!=" ligature
+        let glyphs = shape(&shaper, "a!=b");
+        assert_eq!(glyphs.len(), 4);
+        assert_eq!(clusters(&glyphs), vec![0, 1, 2, 3]);
+        assert_eq!(num_cells(&glyphs), vec![1, 1, 1, 1]);
+        assert_eq!(font_indices(&glyphs), vec![0, 0, 0, 0]);
+        assert_ne!((glyphs[1].glyph_pos, glyphs[2].glyph_pos), (bang, equals));
+
+        // The variation selector is part of the cluster of the 'a'
+        let glyphs = shape(&shaper, "a\u{fe0e}b");
+        assert_eq!(glyphs.len(), 2);
+        assert_eq!(clusters(&glyphs), vec![0, 4]);
+    }
+
+    #[test]
+    fn unresolved_runs_use_fallback() {
+        let shaper = shaper();
+
+        // The adjacent separators are shaped together by the fallback
+        let glyphs = shape(&shaper, "a\u{e0b4}\u{e0b6}b");
+        assert_eq!(glyphs.len(), 4);
+        assert_eq!(clusters(&glyphs), vec![0, 1, 4, 7]);
+        assert_eq!(font_indices(&glyphs), vec![0, 1, 1, 0]);
+        assert_eq!(num_cells(&glyphs), vec![1, 1, 1, 1]);
+        assert!(glyphs.iter().all(|g| g.glyph_pos != 0));
+
+        // Shaping can start part way along the fallback chain
+        let glyphs = shaper.shape_from(1, "\u{e0b4}", 10., 72, &[]).unwrap();
+        assert_eq!(font_indices(&glyphs), vec![1]);
+        assert_eq!(clusters(&glyphs), vec![0]);
+
+        // None of the fonts have CJK ideographs, so each of them is
+        // replaced by a '?' that retains its cluster and width
+        let question = shape(&shaper, "?")[0].glyph_pos;
+        let glyphs = shape(&shaper, "x\u{4e00}\u{4e01}y");
+        assert_eq!(glyphs.len(), 4);
+        assert_eq!(clusters(&glyphs), vec![0, 1, 4, 7]);
+        assert_eq!(num_cells(&glyphs), vec![1, 2, 2, 1]);
+        assert_eq!(glyphs[1].glyph_pos, question);
+        assert_eq!(glyphs[2].glyph_pos, question);
+    }
+}
@@ -56,6 +56,7 @@ struct FontPair {
 
 pub struct HarfbuzzShaper {
     handles: Vec<FontDataHandle>,
+    design_coords: Vec<Vec<f64>>,
     fonts: Vec<RefCell<Option<FontPair>>>,
     lib: ftwrap::Library,
 }
@@ -94,9 +95,10 @@ fn is_question_string(s: &str) -> bool {
 }
 
 impl HarfbuzzShaper {
-    pub fn new(handles: &[FontDataHandle]) -> anyhow::Result<Self> {
+    pub fn new(handles: &[FontDataHandle], design_coords: &[Vec<f64>]) -> anyhow::Result<Self> {
         let lib = ftwrap::Library::new()?;
         let handles = handles.to_vec();
+        let design_coords = design_coords.to_vec();
         let mut fonts = vec![];
         for _ in 0..handles.len() {
             fonts.push(RefCell::new(None));
@@ -104,6 +106,7 @@ impl HarfbuzzShaper {
         Ok(Self {
             fonts,
             handles,
+            design_coords,
             lib,
         })
     }
@@ -118,7 +121,12 @@ impl HarfbuzzShaper {
                 let mut opt_pair = opt_pair.borrow_mut();
                 if opt_pair.is_none() {
                     log::trace!("shaper wants {} {:?}", font_idx, &self.handles[font_idx]);
-                    let face = self.lib.face_from_locator(&self.handles[font_idx])?;
+                    let mut face = self.lib.face_from_locator(&self.handles[font_idx])?;
+                    // The harfbuzz font picks up the variation
+                    // coordinates of the face when it is created
+                    if let Some(coords) = self.design_coords.get(font_idx) {
+                        face.set_design_coords(coords)?;
+                    }
                     let mut font = harfbuzz::Font::new(face.face);
                     let (load_flags, _) = ftwrap::compute_load_flags_from_config();
                     font.set_load_flags(load_flags);
@@ -145,11 +153,13 @@ impl HarfbuzzShaper {
         s: &str,
         font_size: f64,
         dpi: u32,
+        style_features: &[String],
     ) -> anyhow::Result<Vec<GlyphInfo>> {
         let config = configuration();
         let features: Vec<harfbuzz::hb_feature_t> = config
             .harfbuzz_features
             .iter()
+            .chain(style_features.iter())
             .filter_map(|s| harfbuzz::feature_from_string(s).ok())
             .collect();
 
@@ -259,13 +269,20 @@ impl HarfbuzzShaper {
                 }
                 */
 
-                let mut shape = match self.do_shape(font_idx + 1, substr, font_size, dpi) {
-                    Ok(shape) => Ok(shape),
-                    Err(e) => {
-                        error!("{:?} for {:?}", e, substr);
-                        self.do_shape(0, &make_question_string(substr), font_size, dpi)
-                    }
-                }?;
+                let mut shape =
+                    match self.do_shape(font_idx + 1, substr, font_size, dpi, style_features) {
+                        Ok(shape) => Ok(shape),
+                        Err(e) => {
+                            error!("{:?} for {:?}", e, substr);
+                            self.do_shape(
+                                0,
+                                &make_question_string(substr),
+                                font_size,
+                                dpi,
+                                style_features,
+                            )
+                        }
+                    }?;
 
                 // Fixup the cluster member to match our current offset
                 for mut info in &mut shape {
@@ -317,9 +334,15 @@ impl HarfbuzzShaper {
 }
 
 impl FontShaper for HarfbuzzShaper {
-    fn shape(&self, text: &str, size: f64, dpi: u32) -> anyhow::Result<Vec<GlyphInfo>> {
+    fn shape(
+        &self,
+        text: &str,
+        size: f64,
+        dpi: u32,
+        features: &[String],
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
         let start = std::time::Instant::now();
-        let result = self.do_shape(0, text, size, dpi);
+        let result = self.do_shape(0, text, size, dpi, features);
         metrics::value!("shape.harfbuzz", start.elapsed());
         /*
         if let Ok(glyphs) = &result {
@@ -334,6 +357,17 @@ impl FontShaper for HarfbuzzShaper {
         result
     }
 
+    fn shape_from(
+        &self,
+        font_idx: FallbackIdx,
+        text: &str,
+        size: f64,
+        dpi: u32,
+        features: &[String],
+    ) -> anyhow::Result<Vec<GlyphInfo>> {
+        self.do_shape(font_idx, text, size, dpi, features)
+    }
+
     fn metrics_for_idx(&self, font_idx: usize, size: f64, dpi: u32) -> anyhow::Result<FontMetrics> {
         let mut pair = self
             .load_fallback(font_idx)?
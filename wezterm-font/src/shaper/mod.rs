@@ -53,8 +53,29 @@ pub struct FontMetrics {
 }
 
 pub trait FontShaper {
-    /// Shape text and return a vector of GlyphInfo
-    fn shape(&self, text: &str, size: f64, dpi: u32) -> anyhow::Result<Vec<GlyphInfo>>;
+    /// Shape text and return a vector of GlyphInfo.
+    /// `features` is a list of OpenType feature settings, such as
+    /// `calt=0`, that should be applied in addition to any features
+    /// that are enabled by default.
+    fn shape(
+        &self,
+        text: &str,
+        size: f64,
+        dpi: u32,
+        features: &[String],
+    ) -> anyhow::Result<Vec<GlyphInfo>>;
+
+    /// Shape text in the same way as `shape`, but starting with the
+    /// fallback font at `font_idx` rather than the preferred font.
+    /// The clusters in the result are relative to the start of `text`.
+    fn shape_from(
+        &self,
+        font_idx: FallbackIdx,
+        text: &str,
+        size: f64,
+        dpi: u32,
+        features: &[String],
+    ) -> anyhow::Result<Vec<GlyphInfo>>;
 
     /// Compute the font metrics for the preferred font
     /// at the specified size.
@@ -66,12 +87,23 @@ pub trait FontShaper {
 
 pub use config::FontShaperSelection;
 
+/// Create a shaper for the fallback chain in `handles`.
+/// `design_coords` holds the design coordinates of the instance to use
+/// for each of the handles that is a variable font; an empty list, or
+/// a missing entry, selects the default instance.
 pub fn new_shaper(
     shaper: FontShaperSelection,
     handles: &[FontDataHandle],
+    design_coords: &[Vec<f64>],
 ) -> anyhow::Result<Box<dyn FontShaper>> {
     match shaper {
-        FontShaperSelection::Harfbuzz => Ok(Box::new(harfbuzz::HarfbuzzShaper::new(handles)?)),
-        FontShaperSelection::Allsorts => Ok(Box::new(allsorts::AllsortsShaper::new(handles)?)),
+        FontShaperSelection::Harfbuzz => Ok(Box::new(harfbuzz::HarfbuzzShaper::new(
+            handles,
+            design_coords,
+        )?)),
+        FontShaperSelection::Allsorts => Ok(Box::new(allsorts::AllsortsShaper::new(
+            handles,
+            design_coords,
+        )?)),
     }
 }
@@ -0,0 +1,598 @@
+//! Support for OpenType variable fonts.
+//!
+//! A variable font describes a design space through the axes in its
+//! `fvar` table, such as weight or width, along with named instances
+//! at particular positions in that space.  Selecting an instance means
+//! choosing a design coordinate for each axis.  The rasterizer and the
+//! harfbuzz shaper are given the design coordinates and apply the
+//! variations themselves; here we parse just enough to resolve the
+//! configured instance, and to apply the `HVAR` and `MVAR` deltas to the
+//! metrics that we compute from the font tables ourselves.
+
+use crate::parser::{read_i16, read_u16, read_u32};
+
+/// Reads a 16.16 fixed point number
+fn read_fixed(data: &[u8], offset: usize) -> Option<f64> {
+    read_u32(data, offset).map(|v| f64::from(v as i32) / 65536.)
+}
+
+/// Reads a 2.14 fixed point number
+fn read_f2dot14(data: &[u8], offset: usize) -> Option<f64> {
+    read_i16(data, offset).map(|v| f64::from(v) / 16384.)
+}
+
+/// An axis of variation, as described by the `fvar` table
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariationAxis {
+    /// The axis tag, eg: `wght`
+    pub tag: [u8; 4],
+    pub min: f64,
+    pub default: f64,
+    pub max: f64,
+}
+
+impl VariationAxis {
+    /// Map a design coordinate on this axis into the normalized
+    /// range -1.0 to 1.0, where 0.0 is the default
+    fn normalize(&self, value: f64) -> f64 {
+        let value = value.max(self.min).min(self.max);
+        if value < self.default {
+            if self.default > self.min {
+                (value - self.default) / (self.default - self.min)
+            } else {
+                0.
+            }
+        } else if self.max > self.default {
+            (value - self.default) / (self.max - self.default)
+        } else {
+            0.
+        }
+    }
+}
+
+/// A named instance, as described by the `fvar` table
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedInstance {
+    /// The name table id of the sub-family name of the instance,
+    /// eg: "SemiBold"
+    pub subfamily_name_id: u16,
+    /// The name table id of the PostScript name of the instance
+    pub postscript_name_id: Option<u16>,
+    /// The design coordinate for each axis
+    pub coords: Vec<f64>,
+}
+
+/// The axes and named instances of a variable font
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fvar {
+    pub axes: Vec<VariationAxis>,
+    pub instances: Vec<NamedInstance>,
+}
+
+impl Fvar {
+    /// Parse the `fvar` table.  Returns None if it is malformed.
+    pub fn parse(fvar: &[u8]) -> Option<Self> {
+        let axes_offset = read_u16(fvar, 4)? as usize;
+        let axis_count = read_u16(fvar, 8)? as usize;
+        let axis_size = read_u16(fvar, 10)? as usize;
+        let instance_count = read_u16(fvar, 12)? as usize;
+        let instance_size = read_u16(fvar, 14)? as usize;
+        if axis_size < 20 || instance_size < 4 + 4 * axis_count {
+            return None;
+        }
+
+        let axes = (0..axis_count)
+            .map(|i| {
+                let record = axes_offset + i * axis_size;
+                let tag = fvar.get(record..record + 4)?;
+                Some(VariationAxis {
+                    tag: [tag[0], tag[1], tag[2], tag[3]],
+                    min: read_fixed(fvar, record + 4)?,
+                    default: read_fixed(fvar, record + 8)?,
+                    max: read_fixed(fvar, record + 12)?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let instances_offset = axes_offset + axis_count * axis_size;
+        let instances = (0..instance_count)
+            .map(|i| {
+                let record = instances_offset + i * instance_size;
+                let coords = (0..axis_count)
+                    .map(|a| read_fixed(fvar, record + 4 + 4 * a))
+                    .collect::<Option<Vec<_>>>()?;
+                // The PostScript name id is present only when the
+                // record has room for it
+                let postscript_name_id = if instance_size >= 6 + 4 * axis_count {
+                    match read_u16(fvar, record + 4 + 4 * axis_count)? {
+                        0xffff => None,
+                        id => Some(id),
+                    }
+                } else {
+                    None
+                };
+                Some(NamedInstance {
+                    subfamily_name_id: read_u16(fvar, record)?,
+                    postscript_name_id,
+                    coords,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self { axes, instances })
+    }
+
+    /// Map design coordinates into normalized coordinates, applying
+    /// the `avar` segment maps, if any
+    pub fn normalize(&self, design_coords: &[f64], avar: Option<&Avar>) -> Vec<f64> {
+        self.axes
+            .iter()
+            .enumerate()
+            .map(|(idx, axis)| {
+                let value = design_coords.get(idx).copied().unwrap_or(axis.default);
+                let normalized = axis.normalize(value);
+                match avar {
+                    Some(avar) => avar.map(idx, normalized),
+                    None => normalized,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The `avar` segment maps, which adjust the normalized coordinate
+/// of each axis so that it needn't be linear
+#[derive(Debug, Clone, PartialEq)]
+pub struct Avar {
+    segments: Vec<Vec<(f64, f64)>>,
+}
+
+impl Avar {
+    /// Parse the `avar` table.  Returns None if it is malformed.
+    pub fn parse(avar: &[u8]) -> Option<Self> {
+        let axis_count = read_u16(avar, 6)? as usize;
+        let mut offset = 8;
+        let mut segments = vec![];
+        for _ in 0..axis_count {
+            let count = read_u16(avar, offset)? as usize;
+            offset += 2;
+            let map = (0..count)
+                .map(|i| {
+                    Some((
+                        read_f2dot14(avar, offset + 4 * i)?,
+                        read_f2dot14(avar, offset + 4 * i + 2)?,
+                    ))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            offset += 4 * count;
+            segments.push(map);
+        }
+        Some(Self { segments })
+    }
+
+    fn map(&self, axis: usize, value: f64) -> f64 {
+        let map = match self.segments.get(axis) {
+            Some(map) if !map.is_empty() => map,
+            _ => return value,
+        };
+        // Interpolate between the pair of mappings that surround the value
+        for pair in map.windows(2) {
+            let ((from0, to0), (from1, to1)) = (pair[0], pair[1]);
+            if value >= from0 && value <= from1 {
+                if from1 == from0 {
+                    return to0;
+                }
+                return to0 + (to1 - to0) * (value - from0) / (from1 - from0);
+            }
+        }
+        value
+    }
+}
+
+/// Holds the deltas for the `HVAR` and `MVAR` tables, which are
+/// scaled according to the region of the design space that they
+/// apply to
+#[derive(Debug, Clone, PartialEq)]
+struct ItemVariationStore {
+    /// For each region, the (start, peak, end) of each axis
+    regions: Vec<Vec<(f64, f64, f64)>>,
+    data: Vec<ItemVariationData>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ItemVariationData {
+    region_indices: Vec<usize>,
+    /// The deltas of each item, one per region in `region_indices`
+    deltas: Vec<Vec<i32>>,
+}
+
+impl ItemVariationStore {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let region_list = read_u32(data, 2)? as usize;
+        let data_count = read_u16(data, 6)? as usize;
+
+        let axis_count = read_u16(data, region_list)? as usize;
+        let region_count = read_u16(data, region_list + 2)? as usize;
+        let regions = (0..region_count)
+            .map(|r| {
+                (0..axis_count)
+                    .map(|a| {
+                        let record = region_list + 4 + 6 * (r * axis_count + a);
+                        Some((
+                            read_f2dot14(data, record)?,
+                            read_f2dot14(data, record + 2)?,
+                            read_f2dot14(data, record + 4)?,
+                        ))
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let data = (0..data_count)
+            .map(|i| {
+                let offset = read_u32(data, 8 + 4 * i)? as usize;
+                Self::parse_data(data, offset)
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self { regions, data })
+    }
+
+    fn parse_data(data: &[u8], offset: usize) -> Option<ItemVariationData> {
+        let item_count = read_u16(data, offset)? as usize;
+        let word_delta_count = read_u16(data, offset + 2)?;
+        let region_index_count = read_u16(data, offset + 4)? as usize;
+        // When the high bit is set, the "words" are 32 bits and the
+        // remaining deltas are 16 bits
+        let long_words = word_delta_count & 0x8000 != 0;
+        let word_count = (word_delta_count & 0x7fff) as usize;
+        let (word_size, short_size) = if long_words { (4, 2) } else { (2, 1) };
+
+        let region_indices = (0..region_index_count)
+            .map(|i| read_u16(data, offset + 6 + 2 * i).map(usize::from))
+            .collect::<Option<Vec<_>>>()?;
+
+        let row_size =
+            word_count * word_size + region_index_count.saturating_sub(word_count) * short_size;
+        let rows = offset + 6 + 2 * region_index_count;
+        let deltas = (0..item_count)
+            .map(|item| {
+                let mut pos = rows + item * row_size;
+                (0..region_index_count)
+                    .map(|r| {
+                        let size = if r < word_count {
+                            word_size
+                        } else {
+                            short_size
+                        };
+                        let delta = match size {
+                            4 => read_u32(data, pos)? as i32,
+                            2 => i32::from(read_i16(data, pos)?),
+                            _ => i32::from(*data.get(pos)? as i8),
+                        };
+                        pos += size;
+                        Some(delta)
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(ItemVariationData {
+            region_indices,
+            deltas,
+        })
+    }
+
+    /// Returns how much of the region applies at the normalized coords
+    fn region_scalar(&self, region: usize, coords: &[f64]) -> f64 {
+        let axes = match self.regions.get(region) {
+            Some(axes) => axes,
+            None => return 0.,
+        };
+        let mut scalar = 1.;
+        for (idx, &(start, peak, end)) in axes.iter().enumerate() {
+            let coord = coords.get(idx).copied().unwrap_or(0.);
+            if peak == 0. || coord == peak {
+                continue;
+            }
+            if start > peak || peak > end || (start < 0. && end > 0.) {
+                // Invalid regions are ignored
+                continue;
+            }
+            if coord <= start || coord >= end {
+                return 0.;
+            }
+            scalar *= if coord < peak {
+                (coord - start) / (peak - start)
+            } else {
+                (end - coord) / (end - peak)
+            };
+        }
+        scalar
+    }
+
+    /// Returns the delta for the specified item at the normalized coords
+    fn delta(&self, outer: usize, inner: usize, coords: &[f64]) -> f64 {
+        let data = match self.data.get(outer) {
+            Some(data) => data,
+            None => return 0.,
+        };
+        let deltas = match data.deltas.get(inner) {
+            Some(deltas) => deltas,
+            None => return 0.,
+        };
+        data.region_indices
+            .iter()
+            .zip(deltas.iter())
+            .map(|(&region, &delta)| f64::from(delta) * self.region_scalar(region, coords))
+            .sum()
+    }
+}
+
+/// Maps a glyph id to the (outer, inner) indices of its deltas in an
+/// ItemVariationStore
+#[derive(Debug, Clone, PartialEq)]
+struct DeltaSetIndexMap {
+    entries: Vec<(usize, usize)>,
+}
+
+impl DeltaSetIndexMap {
+    fn parse(data: &[u8], offset: usize) -> Option<Self> {
+        let format = *data.get(offset)?;
+        let entry_format = *data.get(offset + 1)?;
+        let (count, entries) = match format {
+            0 => (read_u16(data, offset + 2)? as usize, offset + 4),
+            1 => (read_u32(data, offset + 2)? as usize, offset + 6),
+            _ => return None,
+        };
+        let entry_size = usize::from(((entry_format >> 4) & 3) + 1);
+        let inner_bits = u32::from(entry_format & 0xf) + 1;
+        let entries = (0..count)
+            .map(|i| {
+                let pos = entries + i * entry_size;
+                let bytes = data.get(pos..pos + entry_size)?;
+                let entry = bytes.iter().fold(0u32, |acc, &b| (acc << 8) | u32::from(b));
+                Some((
+                    (entry >> inner_bits) as usize,
+                    (entry & ((1 << inner_bits) - 1)) as usize,
+                ))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { entries })
+    }
+
+    fn get(&self, glyph: u16) -> Option<(usize, usize)> {
+        // Glyphs beyond the end of the map use its last entry
+        self.entries
+            .get(usize::from(glyph))
+            .or_else(|| self.entries.last())
+            .copied()
+    }
+}
+
+/// The `HVAR` table, which holds the advance width deltas
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hvar {
+    store: ItemVariationStore,
+    advance_map: Option<DeltaSetIndexMap>,
+}
+
+impl Hvar {
+    /// Parse the `HVAR` table.  Returns None if it is malformed.
+    pub fn parse(hvar: &[u8]) -> Option<Self> {
+        let store_offset = read_u32(hvar, 4)? as usize;
+        let map_offset = read_u32(hvar, 8)? as usize;
+        let store = ItemVariationStore::parse(hvar.get(store_offset..)?)?;
+        let advance_map = if map_offset != 0 {
+            Some(DeltaSetIndexMap::parse(hvar, map_offset)?)
+        } else {
+            None
+        };
+        Some(Self { store, advance_map })
+    }
+
+    /// Returns the change in the advance of `glyph`, in font units
+    pub fn advance_delta(&self, glyph: u16, coords: &[f64]) -> f64 {
+        // Without a mapping, the glyph id is the inner index
+        let (outer, inner) = match &self.advance_map {
+            Some(map) => match map.get(glyph) {
+                Some(idx) => idx,
+                None => return 0.,
+            },
+            None => (0, usize::from(glyph)),
+        };
+        self.store.delta(outer, inner, coords)
+    }
+}
+
+/// The `MVAR` table, which holds the deltas for the font wide metrics
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mvar {
+    store: ItemVariationStore,
+    records: Vec<([u8; 4], usize, usize)>,
+}
+
+impl Mvar {
+    /// Parse the `MVAR` table.  Returns None if it is malformed.
+    pub fn parse(mvar: &[u8]) -> Option<Self> {
+        let record_size = read_u16(mvar, 6)? as usize;
+        let record_count = read_u16(mvar, 8)? as usize;
+        let store_offset = read_u16(mvar, 10)? as usize;
+        if record_size < 8 {
+            return None;
+        }
+        let records = (0..record_count)
+            .map(|i| {
+                let record = 12 + i * record_size;
+                let tag = mvar.get(record..record + 4)?;
+                Some((
+                    [tag[0], tag[1], tag[2], tag[3]],
+                    read_u16(mvar, record + 4)? as usize,
+                    read_u16(mvar, record + 6)? as usize,
+                ))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let store = if record_count > 0 {
+            ItemVariationStore::parse(mvar.get(store_offset..)?)?
+        } else {
+            ItemVariationStore {
+                regions: vec![],
+                data: vec![],
+            }
+        };
+        Some(Self { store, records })
+    }
+
+    /// Returns the change in the metric identified by `tag`, such
+    /// as `hasc` for the ascender, in font units
+    pub fn delta(&self, tag: &[u8; 4], coords: &[f64]) -> f64 {
+        self.records
+            .iter()
+            .find(|(t, _, _)| t == tag)
+            .map(|&(_, outer, inner)| self.store.delta(outer, inner, coords))
+            .unwrap_or(0.)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fixed(value: f64) -> [u8; 4] {
+        ((value * 65536.) as i32).to_be_bytes()
+    }
+
+    fn f2dot14(value: f64) -> [u8; 2] {
+        ((value * 16384.) as i16).to_be_bytes()
+    }
+
+    /// Builds an fvar table with wght and wdth axes, and a named
+    /// instance at each of `instances`
+    fn fvar_table(instances: &[(u16, [f64; 2], Option<u16>)], instance_size: u16) -> Vec<u8> {
+        let mut fvar = vec![];
+        for field in &[1, 0, 16, 2, 2, 20, instances.len() as u16, instance_size] {
+            fvar.extend_from_slice(&u16::to_be_bytes(*field));
+        }
+        for (tag, min, default, max) in &[(b"wght", 100., 400., 900.), (b"wdth", 50., 100., 200.)] {
+            fvar.extend_from_slice(*tag);
+            for value in &[min, default, max] {
+                fvar.extend_from_slice(&fixed(**value));
+            }
+            fvar.extend_from_slice(&[0, 0, 1, 0]);
+        }
+        for (subfamily, coords, postscript) in instances {
+            fvar.extend_from_slice(&subfamily.to_be_bytes());
+            fvar.extend_from_slice(&[0, 0]);
+            for coord in coords {
+                fvar.extend_from_slice(&fixed(*coord));
+            }
+            if instance_size == 14 {
+                fvar.extend_from_slice(&postscript.unwrap_or(0xffff).to_be_bytes());
+            }
+        }
+        fvar
+    }
+
+    #[test]
+    fn parse_fvar() {
+        let data = fvar_table(
+            &[(258, [700., 100.], Some(259)), (260, [100., 50.], None)],
+            14,
+        );
+        let fvar = Fvar::parse(&data).unwrap();
+        assert_eq!(
+            fvar.axes,
+            vec![
+                VariationAxis {
+                    tag: *b"wght",
+                    min: 100.,
+                    default: 400.,
+                    max: 900.,
+                },
+                VariationAxis {
+                    tag: *b"wdth",
+                    min: 50.,
+                    default: 100.,
+                    max: 200.,
+                },
+            ]
+        );
+        assert_eq!(
+            fvar.instances,
+            vec![
+                NamedInstance {
+                    subfamily_name_id: 258,
+                    postscript_name_id: Some(259),
+                    coords: vec![700., 100.],
+                },
+                NamedInstance {
+                    subfamily_name_id: 260,
+                    postscript_name_id: None,
+                    coords: vec![100., 50.],
+                },
+            ]
+        );
+
+        // Without room for it, there is no PostScript name id
+        let data = fvar_table(&[(258, [700., 100.], Some(259))], 12);
+        let fvar = Fvar::parse(&data).unwrap();
+        assert_eq!(fvar.instances[0].postscript_name_id, None);
+
+        // Instance records must have room for every coordinate, and
+        // the table must hold all of its records
+        assert_eq!(Fvar::parse(&fvar_with_instance_size(8)), None);
+        let data = fvar_with_instance_size(14);
+        assert_eq!(Fvar::parse(&data[..data.len() - 1]), None);
+    }
+
+    fn fvar_with_instance_size(instance_size: u16) -> Vec<u8> {
+        fvar_table(&[(258, [700., 100.], Some(259))], instance_size)
+    }
+
+    #[test]
+    fn normalize_without_avar() {
+        let fvar = Fvar::parse(&fvar_table(&[], 12)).unwrap();
+        let normalize = |wght, wdth| fvar.normalize(&[wght, wdth], None);
+        assert_eq!(normalize(400., 100.), vec![0., 0.]);
+        assert_eq!(normalize(250., 150.), vec![-0.5, 0.5]);
+        assert_eq!(normalize(900., 50.), vec![1., -1.]);
+        // Coordinates are clamped to the range of the axis
+        assert_eq!(normalize(1000., 10.), vec![1., -1.]);
+        // Missing coordinates use the default
+        assert_eq!(fvar.normalize(&[650.], None), vec![0.5, 0.]);
+    }
+
+    /// Builds an avar table with a segment map for each axis
+    fn avar_table(maps: &[&[(f64, f64)]]) -> Vec<u8> {
+        let mut avar = vec![0, 1, 0, 0, 0, 0];
+        avar.extend_from_slice(&(maps.len() as u16).to_be_bytes());
+        for map in maps {
+            avar.extend_from_slice(&(map.len() as u16).to_be_bytes());
+            for (from, to) in map.iter() {
+                avar.extend_from_slice(&f2dot14(*from));
+                avar.extend_from_slice(&f2dot14(*to));
+            }
+        }
+        avar
+    }
+
+    #[test]
+    fn normalize_with_avar() {
+        let fvar = Fvar::parse(&fvar_table(&[], 12)).unwrap();
+        let data = avar_table(&[&[(-1., -1.), (0., 0.), (0.5, 0.75), (1., 1.)], &[]]);
+        let avar = Avar::parse(&data).unwrap();
+        let normalize = |wght, wdth| fvar.normalize(&[wght, wdth], Some(&avar));
+
+        // The mapped points are adjusted, and the values between
+        // them are interpolated; the wdth axis has no map
+        assert_eq!(normalize(650., 150.), vec![0.75, 0.5]);
+        assert_eq!(normalize(525., 50.), vec![0.375, -1.]);
+        assert_eq!(normalize(775., 100.), vec![0.875, 0.]);
+        assert_eq!(normalize(250., 100.), vec![-0.5, 0.]);
+        assert_eq!(normalize(900., 200.), vec![1., 1.]);
+
+        // A truncated segment map is malformed
+        assert_eq!(Avar::parse(&data[..data.len() - 3]), None);
+        assert_eq!(Avar::parse(&data[..7]), None);
+    }
+}
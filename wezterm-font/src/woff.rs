@@ -0,0 +1,811 @@
+//! Decoding of fonts that are packaged in the WOFF and WOFF2
+//! container formats.  The tables are decompressed and reassembled
+//! into a regular SFNT (TrueType/OpenType) font so that the parser,
+//! as well as FreeType and Harfbuzz, can consume them.
+use anyhow::{anyhow, bail, ensure};
+use std::io::Read;
+
+const WOFF_SIGNATURE: u32 = 0x774f_4646; // 'wOFF'
+const WOFF2_SIGNATURE: u32 = 0x774f_4632; // 'wOF2'
+const TTC_FLAVOR: u32 = 0x7474_6366; // 'ttcf'
+
+const GLYF: u32 = 0x676c_7966; // 'glyf'
+const LOCA: u32 = 0x6c6f_6361; // 'loca'
+const HMTX: u32 = 0x686d_7478; // 'hmtx'
+const HHEA: u32 = 0x6868_6561; // 'hhea'
+
+/// The searchRange and rangeShift fields of the SFNT header are 16 bit
+/// values computed from numTables * 16, which limits the number of
+/// tables that an SFNT can describe
+const MAX_TABLES: usize = 0xffff / 16;
+
+/// The tags that can be referenced by index in a WOFF2 table directory
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+    b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+    b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+    b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+    b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+    b"Gloc", b"Feat", b"Sill",
+];
+
+/// Returns true if the data has the signature of a WOFF or WOFF2 font
+pub fn is_woff(data: &[u8]) -> bool {
+    match Reader::new(data).u32() {
+        Ok(sig) => sig == WOFF_SIGNATURE || sig == WOFF2_SIGNATURE,
+        Err(_) => false,
+    }
+}
+
+/// Decode a WOFF or WOFF2 font into the equivalent SFNT font data
+pub fn decode(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    match Reader::new(data).u32()? {
+        WOFF_SIGNATURE => decode_woff(data),
+        WOFF2_SIGNATURE => decode_woff2(data),
+        sig => bail!("0x{:08x} is not a WOFF or WOFF2 signature", sig),
+    }
+}
+
+/// A simple cursor for reading big-endian values from a byte slice
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| anyhow!("unexpected end of font data"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> anyhow::Result<i16> {
+        Ok(self.u16()? as i16)
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Read a WOFF2 UIntBase128 value
+    fn base128(&mut self) -> anyhow::Result<u32> {
+        let mut value: u32 = 0;
+        for i in 0..5 {
+            let b = self.u8()?;
+            ensure!(i != 0 || b != 0x80, "UIntBase128 has leading zeros");
+            ensure!(value & 0xfe00_0000 == 0, "UIntBase128 overflows");
+            value = (value << 7) | u32::from(b & 0x7f);
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("UIntBase128 is longer than 5 bytes")
+    }
+
+    /// Read a WOFF2 255UInt16 value
+    fn u255(&mut self) -> anyhow::Result<u16> {
+        match self.u8()? {
+            253 => self.u16(),
+            254 => Ok(u16::from(self.u8()?) + 253 * 2),
+            255 => Ok(u16::from(self.u8()?) + 253),
+            code => Ok(u16::from(code)),
+        }
+    }
+}
+
+fn pad4(data: &mut Vec<u8>) {
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// Assemble tables into an SFNT font with the specified flavor
+fn build_sfnt(flavor: u32, mut tables: Vec<(u32, Vec<u8>)>) -> anyhow::Result<Vec<u8>> {
+    ensure!(
+        !tables.is_empty() && tables.len() <= MAX_TABLES,
+        "font has an implausible number of tables: {}",
+        tables.len()
+    );
+    tables.sort_by_key(|(tag, _)| *tag);
+
+    let num_tables = tables.len() as u32;
+    let mut entry_selector = 0u32;
+    while (2u32 << entry_selector) <= num_tables {
+        entry_selector += 1;
+    }
+    let search_range = (1u32 << entry_selector) * 16;
+    let range_shift = num_tables * 16 - search_range;
+
+    let mut sfnt = vec![];
+    sfnt.extend_from_slice(&flavor.to_be_bytes());
+    sfnt.extend_from_slice(&(num_tables as u16).to_be_bytes());
+    sfnt.extend_from_slice(&(search_range as u16).to_be_bytes());
+    sfnt.extend_from_slice(&(entry_selector as u16).to_be_bytes());
+    sfnt.extend_from_slice(&(range_shift as u16).to_be_bytes());
+
+    let mut offset = 12 + 16 * tables.len();
+    for (tag, data) in &tables {
+        sfnt.extend_from_slice(&tag.to_be_bytes());
+        sfnt.extend_from_slice(&checksum(data).to_be_bytes());
+        sfnt.extend_from_slice(&(offset as u32).to_be_bytes());
+        sfnt.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += (data.len() + 3) & !3;
+    }
+    for (_, data) in &tables {
+        sfnt.extend_from_slice(data);
+        pad4(&mut sfnt);
+    }
+    Ok(sfnt)
+}
+
+fn inflate(data: &[u8], len: usize) -> anyhow::Result<Vec<u8>> {
+    read_exactly(flate2::read::ZlibDecoder::new(data), len)
+}
+
+fn brotli_decompress(data: &[u8], len: usize) -> anyhow::Result<Vec<u8>> {
+    read_exactly(brotli_decompressor::Decompressor::new(data, 4096), len)
+}
+
+/// Reads the decompressed data, which must be exactly `len` bytes
+/// long.  The read is bounded so that bogus compressed data can't
+/// cause us to allocate more than the font claims to need.
+fn read_exactly<R: Read>(reader: R, len: usize) -> anyhow::Result<Vec<u8>> {
+    let mut decoded = vec![];
+    reader.take(len as u64 + 1).read_to_end(&mut decoded)?;
+    ensure!(
+        decoded.len() == len,
+        "decompressed {} bytes but expected {}",
+        decoded.len(),
+        len
+    );
+    Ok(decoded)
+}
+
+fn decode_woff(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut header = Reader::new(data);
+    let _signature = header.u32()?;
+    let flavor = header.u32()?;
+    let _length = header.u32()?;
+    let num_tables = header.u16()?;
+    // reserved, totalSfntSize, version, metadata and private data
+    header.bytes(2 + 4 + 2 + 2 + 4 * 5)?;
+
+    let mut tables = vec![];
+    for _ in 0..num_tables {
+        let tag = header.u32()?;
+        let offset = header.u32()? as usize;
+        let comp_length = header.u32()? as usize;
+        let orig_length = header.u32()? as usize;
+        let _orig_checksum = header.u32()?;
+
+        let mut table = Reader::new(data);
+        table.bytes(offset)?;
+        let table = table.bytes(comp_length)?;
+        let table = if comp_length < orig_length {
+            inflate(table, orig_length)?
+        } else {
+            table.to_vec()
+        };
+        ensure!(
+            table.len() == orig_length,
+            "WOFF table {:?} decoded to {} bytes rather than {}",
+            String::from_utf8_lossy(&tag.to_be_bytes()),
+            table.len(),
+            orig_length
+        );
+        tables.push((tag, table));
+    }
+
+    build_sfnt(flavor, tables)
+}
+
+struct Woff2Table {
+    tag: u32,
+    orig_length: usize,
+    length: usize,
+    transformed: bool,
+}
+
+fn decode_woff2(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut header = Reader::new(data);
+    let _signature = header.u32()?;
+    let flavor = header.u32()?;
+    ensure!(
+        flavor != TTC_FLAVOR,
+        "WOFF2 font collections are not supported"
+    );
+    let _length = header.u32()?;
+    let num_tables = header.u16()?;
+    let _reserved = header.u16()?;
+    let _total_sfnt_size = header.u32()?;
+    let total_compressed_size = header.u32()? as usize;
+    // version, metadata and private data
+    header.bytes(2 + 2 + 4 * 5)?;
+
+    let mut directory = vec![];
+    for _ in 0..num_tables {
+        let flags = header.u8()?;
+        let tag = match flags & 0x3f {
+            63 => header.u32()?,
+            idx => u32::from_be_bytes(*KNOWN_TAGS[idx as usize]),
+        };
+        // For glyf and loca, transform version 0 means that the
+        // table is transformed; for all others it means that it isn't
+        let version = flags >> 6;
+        let transformed = if tag == GLYF || tag == LOCA {
+            version == 0
+        } else {
+            version != 0
+        };
+        let orig_length = header.base128()? as usize;
+        let length = if transformed {
+            header.base128()? as usize
+        } else {
+            orig_length
+        };
+        directory.push(Woff2Table {
+            tag,
+            orig_length,
+            length,
+            transformed,
+        });
+    }
+
+    let total_length = directory
+        .iter()
+        .try_fold(0usize, |total, table| total.checked_add(table.length))
+        .ok_or_else(|| anyhow!("WOFF2 table lengths overflow"))?;
+    let stream = brotli_decompress(header.bytes(total_compressed_size)?, total_length)?;
+    let mut stream = Reader::new(&stream);
+
+    let mut raw_tables = vec![];
+    for table in &directory {
+        raw_tables.push((table, stream.bytes(table.length)?));
+    }
+
+    let find_table = |tag: u32| {
+        raw_tables
+            .iter()
+            .find(|(table, _)| table.tag == tag)
+            .map(|(table, data)| (*table, *data))
+    };
+
+    let mut glyphs = None;
+    if let Some((glyf, data)) = find_table(GLYF) {
+        if glyf.transformed {
+            glyphs.replace(reconstruct_glyf(data)?);
+        }
+    }
+
+    let mut tables = vec![];
+    for (table, data) in &raw_tables {
+        if !table.transformed {
+            tables.push((table.tag, data.to_vec()));
+            continue;
+        }
+        let glyphs = glyphs
+            .as_ref()
+            .ok_or_else(|| anyhow!("WOFF2 font has transformed tables but no glyf table"))?;
+        match table.tag {
+            GLYF => tables.push((GLYF, glyphs.glyf.clone())),
+            LOCA => {
+                ensure!(
+                    glyphs.loca.len() == table.orig_length,
+                    "reconstructed loca table is {} bytes rather than {}",
+                    glyphs.loca.len(),
+                    table.orig_length
+                );
+                tables.push((LOCA, glyphs.loca.clone()));
+            }
+            HMTX => {
+                let hhea = find_table(HHEA)
+                    .ok_or_else(|| anyhow!("WOFF2 font has no hhea table"))?
+                    .1;
+                let mut hhea = Reader::new(hhea);
+                hhea.bytes(34)?;
+                let num_hmetrics = hhea.u16()? as usize;
+                tables.push((HMTX, reconstruct_hmtx(data, num_hmetrics, &glyphs.x_mins)?));
+            }
+            tag => bail!(
+                "unsupported WOFF2 transform for table {:?}",
+                String::from_utf8_lossy(&tag.to_be_bytes())
+            ),
+        }
+    }
+
+    build_sfnt(flavor, tables)
+}
+
+/// The glyf and loca tables reconstructed from a transformed WOFF2
+/// glyf table, along with the xMin of each glyph, which is needed
+/// to reconstruct a transformed hmtx table
+struct Glyphs {
+    glyf: Vec<u8>,
+    loca: Vec<u8>,
+    x_mins: Vec<i16>,
+}
+
+const ON_CURVE_POINT: u8 = 0x01;
+const X_SHORT_VECTOR: u8 = 0x02;
+const Y_SHORT_VECTOR: u8 = 0x04;
+const X_IS_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_IS_SAME_OR_POSITIVE: u8 = 0x20;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+fn reconstruct_glyf(data: &[u8]) -> anyhow::Result<Glyphs> {
+    let mut header = Reader::new(data);
+    let _reserved = header.u16()?;
+    let option_flags = header.u16()?;
+    let num_glyphs = header.u16()? as usize;
+    let index_format = header.u16()?;
+    let mut stream_sizes = [0usize; 7];
+    for size in &mut stream_sizes {
+        *size = header.u32()? as usize;
+    }
+    let mut n_contour_stream = Reader::new(header.bytes(stream_sizes[0])?);
+    let mut n_points_stream = Reader::new(header.bytes(stream_sizes[1])?);
+    let mut flag_stream = Reader::new(header.bytes(stream_sizes[2])?);
+    let mut glyph_stream = Reader::new(header.bytes(stream_sizes[3])?);
+    let mut composite_stream = Reader::new(header.bytes(stream_sizes[4])?);
+    let mut bbox_stream = Reader::new(header.bytes(stream_sizes[5])?);
+    let mut instruction_stream = Reader::new(header.bytes(stream_sizes[6])?);
+    let overlap_bitmap = if option_flags & 1 != 0 {
+        Some(header.bytes((num_glyphs + 7) / 8)?)
+    } else {
+        None
+    };
+    let bbox_bitmap = bbox_stream.bytes(((num_glyphs + 31) / 32) * 4)?;
+    let has_bit = |bitmap: &[u8], idx: usize| bitmap[idx >> 3] & (0x80 >> (idx & 7)) != 0;
+
+    let mut glyf = vec![];
+    let mut offsets = Vec::with_capacity(num_glyphs + 1);
+    let mut x_mins = Vec::with_capacity(num_glyphs);
+
+    for glyph_id in 0..num_glyphs {
+        offsets.push(glyf.len());
+        let num_contours = n_contour_stream.i16()?;
+        let explicit_bbox = if has_bit(bbox_bitmap, glyph_id) {
+            Some([
+                bbox_stream.i16()?,
+                bbox_stream.i16()?,
+                bbox_stream.i16()?,
+                bbox_stream.i16()?,
+            ])
+        } else {
+            None
+        };
+
+        if num_contours == 0 {
+            ensure!(
+                explicit_bbox.is_none(),
+                "empty glyph {} has a bounding box",
+                glyph_id
+            );
+            x_mins.push(0);
+        } else if num_contours == -1 {
+            let bbox = explicit_bbox
+                .ok_or_else(|| anyhow!("composite glyph {} has no bounding box", glyph_id))?;
+            let (components, have_instructions) = read_composite(&mut composite_stream)?;
+
+            glyf.extend_from_slice(&num_contours.to_be_bytes());
+            for value in &bbox {
+                glyf.extend_from_slice(&value.to_be_bytes());
+            }
+            glyf.extend_from_slice(components);
+            if have_instructions {
+                let len = glyph_stream.u255()?;
+                glyf.extend_from_slice(&len.to_be_bytes());
+                glyf.extend_from_slice(instruction_stream.bytes(len as usize)?);
+            }
+            x_mins.push(bbox[0]);
+        } else if num_contours > 0 {
+            let mut end_points = vec![];
+            let mut num_points = 0usize;
+            for _ in 0..num_contours {
+                num_points += n_points_stream.u255()? as usize;
+                end_points.push(
+                    num_points
+                        .checked_sub(1)
+                        .filter(|&end| end <= 0xffff)
+                        .ok_or_else(|| anyhow!("glyph {} has invalid contours", glyph_id))?
+                        as u16,
+                );
+            }
+
+            let mut points = Vec::with_capacity(num_points);
+            let (mut x, mut y) = (0i32, 0i32);
+            for _ in 0..num_points {
+                let flag = flag_stream.u8()?;
+                let on_curve = flag & 0x80 == 0;
+                let (dx, dy) = decode_triplet(flag & 0x7f, &mut glyph_stream)?;
+                x += dx;
+                y += dy;
+                ensure!(
+                    x >= i32::from(i16::min_value())
+                        && x <= i32::from(i16::max_value())
+                        && y >= i32::from(i16::min_value())
+                        && y <= i32::from(i16::max_value()),
+                    "glyph {} has out of range coordinates",
+                    glyph_id
+                );
+                points.push((x, y, on_curve));
+            }
+
+            let instruction_len = glyph_stream.u255()?;
+            let instructions = instruction_stream.bytes(instruction_len as usize)?;
+
+            let bbox = match explicit_bbox {
+                Some(bbox) => bbox,
+                None => {
+                    let x_min = points.iter().map(|p| p.0).min().unwrap_or(0);
+                    let y_min = points.iter().map(|p| p.1).min().unwrap_or(0);
+                    let x_max = points.iter().map(|p| p.0).max().unwrap_or(0);
+                    let y_max = points.iter().map(|p| p.1).max().unwrap_or(0);
+                    [x_min as i16, y_min as i16, x_max as i16, y_max as i16]
+                }
+            };
+
+            glyf.extend_from_slice(&num_contours.to_be_bytes());
+            for value in &bbox {
+                glyf.extend_from_slice(&value.to_be_bytes());
+            }
+            for end in &end_points {
+                glyf.extend_from_slice(&end.to_be_bytes());
+            }
+            glyf.extend_from_slice(&instruction_len.to_be_bytes());
+            glyf.extend_from_slice(instructions);
+
+            let overlap = overlap_bitmap.map_or(false, |bitmap| has_bit(bitmap, glyph_id));
+            encode_points(&points, overlap, &mut glyf);
+            x_mins.push(bbox[0]);
+        } else {
+            bail!("glyph {} has {} contours", glyph_id, num_contours);
+        }
+
+        pad4(&mut glyf);
+    }
+    offsets.push(glyf.len());
+
+    let mut loca = vec![];
+    for offset in offsets {
+        if index_format == 0 {
+            ensure!(
+                offset / 2 <= 0xffff,
+                "glyf table is too large for short loca"
+            );
+            loca.extend_from_slice(&((offset / 2) as u16).to_be_bytes());
+        } else {
+            loca.extend_from_slice(&(offset as u32).to_be_bytes());
+        }
+    }
+
+    Ok(Glyphs { glyf, loca, x_mins })
+}
+
+/// Decode a point delta using the WOFF2 triplet encoding
+fn decode_triplet(flag: u8, glyph_stream: &mut Reader) -> anyhow::Result<(i32, i32)> {
+    fn with_sign(flag: u8, value: i32) -> i32 {
+        if flag & 1 != 0 {
+            value
+        } else {
+            -value
+        }
+    }
+
+    Ok(if flag < 10 {
+        let b0 = i32::from(glyph_stream.u8()?);
+        (0, with_sign(flag, (i32::from(flag & 14) << 7) + b0))
+    } else if flag < 20 {
+        let b0 = i32::from(glyph_stream.u8()?);
+        (with_sign(flag, (i32::from((flag - 10) & 14) << 7) + b0), 0)
+    } else if flag < 84 {
+        let b0 = i32::from(flag - 20);
+        let b1 = i32::from(glyph_stream.u8()?);
+        (
+            with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+            with_sign(flag >> 1, 1 + ((b0 & 0x0c) << 2) + (b1 & 0x0f)),
+        )
+    } else if flag < 120 {
+        let b0 = i32::from(flag - 84);
+        let b1 = i32::from(glyph_stream.u8()?);
+        let b2 = i32::from(glyph_stream.u8()?);
+        (
+            with_sign(flag, 1 + ((b0 / 12) << 8) + b1),
+            with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2),
+        )
+    } else if flag < 124 {
+        let b1 = i32::from(glyph_stream.u8()?);
+        let b2 = i32::from(glyph_stream.u8()?);
+        let b3 = i32::from(glyph_stream.u8()?);
+        (
+            with_sign(flag, (b1 << 4) + (b2 >> 4)),
+            with_sign(flag >> 1, ((b2 & 0x0f) << 8) + b3),
+        )
+    } else {
+        let b1 = i32::from(glyph_stream.u8()?);
+        let b2 = i32::from(glyph_stream.u8()?);
+        let b3 = i32::from(glyph_stream.u8()?);
+        let b4 = i32::from(glyph_stream.u8()?);
+        (
+            with_sign(flag, (b1 << 8) + b2),
+            with_sign(flag >> 1, (b3 << 8) + b4),
+        )
+    })
+}
+
+/// Encode the flags and coordinates of a simple glyph, as they
+/// appear in the glyf table
+fn encode_points(points: &[(i32, i32, bool)], overlap: bool, glyf: &mut Vec<u8>) {
+    let mut flags = vec![];
+    let mut x_coords = vec![];
+    let mut y_coords = vec![];
+    let (mut last_x, mut last_y) = (0, 0);
+
+    for (idx, &(x, y, on_curve)) in points.iter().enumerate() {
+        let mut flag = if on_curve { ON_CURVE_POINT } else { 0 };
+        if idx == 0 && overlap {
+            flag |= OVERLAP_SIMPLE;
+        }
+
+        let dx = x - last_x;
+        if dx == 0 {
+            flag |= X_IS_SAME_OR_POSITIVE;
+        } else if dx.abs() <= 0xff {
+            flag |= X_SHORT_VECTOR;
+            if dx > 0 {
+                flag |= X_IS_SAME_OR_POSITIVE;
+            }
+            x_coords.push(dx.abs() as u8);
+        } else {
+            x_coords.extend_from_slice(&(dx as i16).to_be_bytes());
+        }
+
+        let dy = y - last_y;
+        if dy == 0 {
+            flag |= Y_IS_SAME_OR_POSITIVE;
+        } else if dy.abs() <= 0xff {
+            flag |= Y_SHORT_VECTOR;
+            if dy > 0 {
+                flag |= Y_IS_SAME_OR_POSITIVE;
+            }
+            y_coords.push(dy.abs() as u8);
+        } else {
+            y_coords.extend_from_slice(&(dy as i16).to_be_bytes());
+        }
+
+        flags.push(flag);
+        last_x = x;
+        last_y = y;
+    }
+
+    glyf.extend_from_slice(&flags);
+    glyf.extend_from_slice(&x_coords);
+    glyf.extend_from_slice(&y_coords);
+}
+
+/// Read the component records of a composite glyph; these are
+/// stored in the composite stream in their original form
+fn read_composite<'a>(composite_stream: &mut Reader<'a>) -> anyhow::Result<(&'a [u8], bool)> {
+    let start = composite_stream.pos;
+    let mut have_instructions = false;
+    loop {
+        let flags = composite_stream.u16()?;
+        let _glyph_index = composite_stream.u16()?;
+        have_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+
+        let mut len = if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            4
+        } else {
+            2
+        };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            len += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            len += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            len += 8;
+        }
+        composite_stream.bytes(len)?;
+
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    Ok((
+        &composite_stream.data[start..composite_stream.pos],
+        have_instructions,
+    ))
+}
+
+/// Reconstruct an hmtx table from its WOFF2 transformed representation,
+/// in which the left side bearings may be omitted because they are
+/// equal to the xMin of the corresponding glyphs
+fn reconstruct_hmtx(data: &[u8], num_hmetrics: usize, x_mins: &[i16]) -> anyhow::Result<Vec<u8>> {
+    let num_glyphs = x_mins.len();
+    ensure!(
+        num_hmetrics >= 1 && num_hmetrics <= num_glyphs,
+        "invalid numberOfHMetrics {} for {} glyphs",
+        num_hmetrics,
+        num_glyphs
+    );
+
+    let mut transformed = Reader::new(data);
+    let flags = transformed.u8()?;
+    let mut advances = vec![];
+    for _ in 0..num_hmetrics {
+        advances.push(transformed.u16()?);
+    }
+    let mut lsbs = vec![];
+    for (idx, x_min) in x_mins.iter().enumerate() {
+        let omitted = if idx < num_hmetrics {
+            flags & 1 != 0
+        } else {
+            flags & 2 != 0
+        };
+        lsbs.push(if omitted { *x_min } else { transformed.i16()? });
+    }
+
+    let mut hmtx = vec![];
+    for (idx, lsb) in lsbs.iter().enumerate() {
+        if idx < num_hmetrics {
+            hmtx.extend_from_slice(&advances[idx].to_be_bytes());
+        }
+        hmtx.extend_from_slice(&lsb.to_be_bytes());
+    }
+    Ok(hmtx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::locator::FontDataHandle;
+    use crate::parser::ParsedFont;
+    use crate::rasterizer::freetype::FreeTypeRasterizer;
+    use crate::rasterizer::{FontRasterizer, SyntheticStyle};
+
+    const TTF: &[u8] = include_bytes!("../../test-data/woff/WoffTest-Regular.ttf");
+    const WOFF: &[u8] = include_bytes!("../../test-data/woff/WoffTest-Regular.woff");
+    const WOFF2: &[u8] = include_bytes!("../../test-data/woff/WoffTest-Regular.woff2");
+    const TRANSFORMED: &[u8] = include_bytes!("../../test-data/woff/WoffTest-Transformed.woff2");
+
+    fn handle(data: Vec<u8>) -> FontDataHandle {
+        FontDataHandle::Memory {
+            data,
+            index: 0,
+            name: "WoffTest-Regular".to_string(),
+        }
+    }
+
+    fn rasterized_pixels(data: Vec<u8>, c: char) -> Vec<u8> {
+        let handle = handle(data);
+        let glyph_pos = ParsedFont::from_locator(&handle)
+            .unwrap()
+            .glyph_index_for_char(c)
+            .unwrap() as u32;
+        FreeTypeRasterizer::from_locator(&handle, SyntheticStyle::default(), &[], None)
+            .unwrap()
+            .rasterize_glyph(glyph_pos, 12., 96, (0xff, 0xff, 0xff, 0xff))
+            .unwrap()
+            .data
+    }
+
+    #[test]
+    fn signature() {
+        assert!(!is_woff(TTF));
+        assert!(is_woff(WOFF));
+        assert!(is_woff(WOFF2));
+        assert!(is_woff(TRANSFORMED));
+        assert!(decode(TTF).is_err());
+    }
+
+    #[test]
+    fn parse_decoded() {
+        for data in &[WOFF, WOFF2, TRANSFORMED] {
+            let font = ParsedFont::from_locator(&handle(data.to_vec())).unwrap();
+            assert_eq!(font.names().full_name(), "Woff Test Regular");
+            assert_eq!(font.glyph_index_for_char('A').unwrap(), 1);
+            assert_eq!(font.glyph_index_for_char('B').unwrap(), 2);
+        }
+    }
+
+    #[test]
+    fn outlines_match_ttf() {
+        for c in &['A', 'B'] {
+            let expected = rasterized_pixels(TTF.to_vec(), *c);
+            assert_eq!(rasterized_pixels(decode(WOFF).unwrap(), *c), expected);
+            assert_eq!(rasterized_pixels(decode(WOFF2).unwrap(), *c), expected);
+            assert_eq!(
+                rasterized_pixels(decode(TRANSFORMED).unwrap(), *c),
+                expected
+            );
+        }
+    }
+
+    /// Returns the data of the table with the specified tag in an SFNT font
+    fn sfnt_table(sfnt: &[u8], tag: &[u8; 4]) -> Vec<u8> {
+        let mut header = Reader::new(sfnt);
+        let _flavor = header.u32().unwrap();
+        let num_tables = header.u16().unwrap();
+        header.bytes(6).unwrap();
+        for _ in 0..num_tables {
+            let table_tag = header.u32().unwrap();
+            let _checksum = header.u32().unwrap();
+            let offset = header.u32().unwrap() as usize;
+            let length = header.u32().unwrap() as usize;
+            if table_tag == u32::from_be_bytes(*tag) {
+                return sfnt[offset..offset + length].to_vec();
+            }
+        }
+        panic!("no {:?} table", String::from_utf8_lossy(tag));
+    }
+
+    /// Returns the numberOfContours and bounding box of each glyph
+    fn glyph_headers(sfnt: &[u8]) -> Vec<Vec<u8>> {
+        let glyf = sfnt_table(sfnt, b"glyf");
+        sfnt_table(sfnt, b"loca")
+            .chunks(2)
+            .map(|offset| u16::from_be_bytes([offset[0], offset[1]]) as usize * 2)
+            .filter(|&offset| offset < glyf.len())
+            .map(|offset| glyf[offset..offset + 10].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn reconstructed_tables() {
+        let decoded = decode(TRANSFORMED).unwrap();
+        assert_eq!(sfnt_table(&decoded, b"hmtx"), sfnt_table(TTF, b"hmtx"));
+        // The reconstructed glyf table encodes the points more compactly
+        // than the original does, so only the glyph headers match
+        assert_eq!(glyph_headers(&decoded), glyph_headers(TTF));
+        assert_eq!(glyph_headers(TTF).len(), 3);
+    }
+
+    #[test]
+    fn too_many_tables() {
+        assert!(build_sfnt(0x0001_0000, vec![(0, vec![]); MAX_TABLES]).is_ok());
+        assert!(build_sfnt(0x0001_0000, vec![(0, vec![]); MAX_TABLES + 1]).is_err());
+        assert!(build_sfnt(0x0001_0000, vec![(0, vec![]); 0x1_0000]).is_err());
+    }
+
+    #[test]
+    fn truncated() {
+        assert!(decode(&WOFF[..WOFF.len() / 2]).is_err());
+        assert!(decode(&WOFF2[..WOFF2.len() / 2]).is_err());
+        assert!(decode(&TRANSFORMED[..TRANSFORMED.len() / 2]).is_err());
+    }
+}
@@ -55,6 +55,12 @@ pub struct StartCommand {
     #[structopt(long = "class")]
     pub class: Option<String>,
 
+    /// Feed a synthetic stream of output to the initial pane for
+    /// the specified number of seconds, then print rendering stats
+    /// and exit.  This is intended to help with performance tuning.
+    #[structopt(long = "benchmark-render", hidden = true)]
+    pub benchmark_render: Option<u64>,
+
     /// Instead of executing your shell, run PROG.
     /// For example: `wezterm start -- bash -l` will spawn bash
     /// as if it were a login shell.
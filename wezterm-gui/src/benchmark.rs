@@ -0,0 +1,83 @@
+//! A hidden diagnostic mode that feeds a synthetic stream of output
+//! into the terminal and then reports the rendering stats that were
+//! gathered while doing so.
+//! This is enabled via `wezterm start --benchmark-render SECONDS`.
+use ::window::{Connection, ConnectionOps};
+use mux::Mux;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How much data to feed to the pane before yielding to the
+/// event loop so that it has a chance to paint.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Set while a benchmark is running, so that the more detailed
+/// counters are only reported when they are wanted
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Returns true if `--benchmark-render` is in progress
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Produces a chunk of output that exercises a mixture of
+/// attributes, colors, wide characters and ligature candidates
+/// so that shaping, the glyph cache and the painter are kept busy.
+fn synthetic_chunk(seq: usize) -> String {
+    let mut s = String::with_capacity(CHUNK_SIZE + 256);
+    let mut line = seq;
+    while s.len() < CHUNK_SIZE {
+        let color = line % 216 + 16;
+        s.push_str(&format!(
+            "\x1b[38;5;{}m{:08} \x1b[1mbold\x1b[22m \x1b[3mitalic\x1b[23m \
+             \x1b[4munderline\x1b[24m -> != == >= \u{4e2d}\u{6587} \u{1f600} \
+             \u{2500}\u{2502}\u{250c} the quick brown fox {}\x1b[0m\r\n",
+            color,
+            line,
+            line.wrapping_mul(2654435761) % 100000
+        ));
+        line += 1;
+    }
+    s
+}
+
+pub async fn run(duration: Duration) -> anyhow::Result<()> {
+    // Wait for the initial pane to be spawned
+    let pane = loop {
+        let mux = Mux::get().expect("running on main thread");
+        if let Some(pane) = mux.iter_panes().into_iter().next() {
+            break pane;
+        }
+        smol::Timer::after(Duration::from_millis(100)).await;
+    };
+
+    crate::stats::Stats::reset();
+    ACTIVE.store(true, Ordering::Relaxed);
+    let start = Instant::now();
+    let mut seq = 0;
+    let mut bytes = 0;
+
+    while start.elapsed() < duration {
+        let chunk = synthetic_chunk(seq);
+        bytes += chunk.len();
+        seq += chunk.len();
+        pane.advance_bytes(chunk.as_bytes());
+        // Allow the event loop to paint
+        smol::Timer::after(Duration::from_millis(1)).await;
+    }
+
+    let elapsed = start.elapsed();
+    ACTIVE.store(false, Ordering::Relaxed);
+    println!(
+        "benchmark: fed {} bytes in {:?} ({:.2} MB/s)",
+        bytes,
+        elapsed,
+        bytes as f64 / (1024. * 1024.) / elapsed.as_secs_f64()
+    );
+    crate::stats::Stats::print_now(&mut std::io::stdout().lock());
+
+    Connection::get()
+        .expect("running on main thread")
+        .terminate_message_loop();
+    Ok(())
+}
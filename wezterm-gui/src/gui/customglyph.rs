@@ -0,0 +1,603 @@
+//! Box drawing and block element characters are intended to join up
+//! with the same characters in adjacent cells, but fonts frequently
+//! leave gaps or use inconsistent stroke thickness.  When
+//! `custom_block_glyphs` is enabled we draw them from geometry computed
+//! from the cell size instead of using the glyphs from the font.
+use super::utilsprites::RenderMetrics;
+use ::window::bitmaps::{BitmapImage, Image};
+use ::window::color::Color;
+
+/// Returns true if `c` is drawn by `draw_custom_glyph` rather than
+/// rasterized from the font
+pub fn is_custom_glyph(c: char) -> bool {
+    ('\u{2500}'..='\u{259f}').contains(&c)
+}
+
+/// Draw the coverage for the box drawing or block element character
+/// `c` into `buffer`, which must be the size of a cell.
+/// Returns false if `c` is not a character that we know how to draw.
+pub fn draw_custom_glyph(buffer: &mut Image, metrics: &RenderMetrics, c: char) -> bool {
+    let mut canvas = Canvas::new(buffer);
+    let light = metrics.underline_height.max(1);
+
+    if let Some(arms) = box_arms(c) {
+        canvas.draw_arms(arms, light, false);
+        canvas.draw_arms(arms.transpose(), light, true);
+        return true;
+    }
+
+    match c {
+        '\u{2504}' => canvas.draw_dashes(3, light, false),
+        '\u{2505}' => canvas.draw_dashes(3, 2 * light, false),
+        '\u{2506}' => canvas.draw_dashes(3, light, true),
+        '\u{2507}' => canvas.draw_dashes(3, 2 * light, true),
+        '\u{2508}' => canvas.draw_dashes(4, light, false),
+        '\u{2509}' => canvas.draw_dashes(4, 2 * light, false),
+        '\u{250a}' => canvas.draw_dashes(4, light, true),
+        '\u{250b}' => canvas.draw_dashes(4, 2 * light, true),
+        '\u{254c}' => canvas.draw_dashes(2, light, false),
+        '\u{254d}' => canvas.draw_dashes(2, 2 * light, false),
+        '\u{254e}' => canvas.draw_dashes(2, light, true),
+        '\u{254f}' => canvas.draw_dashes(2, 2 * light, true),
+
+        '\u{256d}' => canvas.draw_arc(1., 1., light),
+        '\u{256e}' => canvas.draw_arc(-1., 1., light),
+        '\u{256f}' => canvas.draw_arc(-1., -1., light),
+        '\u{2570}' => canvas.draw_arc(1., -1., light),
+
+        '\u{2571}' => canvas.draw_diagonal(true, light),
+        '\u{2572}' => canvas.draw_diagonal(false, light),
+        '\u{2573}' => {
+            canvas.draw_diagonal(true, light);
+            canvas.draw_diagonal(false, light);
+        }
+
+        '\u{2580}'..='\u{259f}' => canvas.draw_block(c),
+
+        _ => return false,
+    }
+    true
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Weight {
+    Light,
+    Heavy,
+    Double,
+}
+
+const N: Option<Weight> = None;
+const L: Option<Weight> = Some(Weight::Light);
+const H: Option<Weight> = Some(Weight::Heavy);
+const D: Option<Weight> = Some(Weight::Double);
+
+/// The strokes that extend from the center of the cell out to
+/// each of its edges
+#[derive(Copy, Clone, Debug)]
+struct Arms {
+    left: Option<Weight>,
+    right: Option<Weight>,
+    up: Option<Weight>,
+    down: Option<Weight>,
+}
+
+impl Arms {
+    /// Swap the horizontal and vertical arms, so that the vertical
+    /// arms can be drawn by the same code as the horizontal ones
+    fn transpose(self) -> Self {
+        Self {
+            left: self.up,
+            right: self.down,
+            up: self.left,
+            down: self.right,
+        }
+    }
+}
+
+/// Describes the characters that are made up of solid strokes
+/// joining the center of the cell to its edges
+fn box_arms(c: char) -> Option<Arms> {
+    let (left, right, up, down) = match c {
+        '\u{2500}' => (L, L, N, N),
+        '\u{2501}' => (H, H, N, N),
+        '\u{2502}' => (N, N, L, L),
+        '\u{2503}' => (N, N, H, H),
+        '\u{250c}' => (N, L, N, L),
+        '\u{250d}' => (N, H, N, L),
+        '\u{250e}' => (N, L, N, H),
+        '\u{250f}' => (N, H, N, H),
+        '\u{2510}' => (L, N, N, L),
+        '\u{2511}' => (H, N, N, L),
+        '\u{2512}' => (L, N, N, H),
+        '\u{2513}' => (H, N, N, H),
+        '\u{2514}' => (N, L, L, N),
+        '\u{2515}' => (N, H, L, N),
+        '\u{2516}' => (N, L, H, N),
+        '\u{2517}' => (N, H, H, N),
+        '\u{2518}' => (L, N, L, N),
+        '\u{2519}' => (H, N, L, N),
+        '\u{251a}' => (L, N, H, N),
+        '\u{251b}' => (H, N, H, N),
+        '\u{251c}' => (N, L, L, L),
+        '\u{251d}' => (N, H, L, L),
+        '\u{251e}' => (N, L, H, L),
+        '\u{251f}' => (N, L, L, H),
+        '\u{2520}' => (N, L, H, H),
+        '\u{2521}' => (N, H, H, L),
+        '\u{2522}' => (N, H, L, H),
+        '\u{2523}' => (N, H, H, H),
+        '\u{2524}' => (L, N, L, L),
+        '\u{2525}' => (H, N, L, L),
+        '\u{2526}' => (L, N, H, L),
+        '\u{2527}' => (L, N, L, H),
+        '\u{2528}' => (L, N, H, H),
+        '\u{2529}' => (H, N, H, L),
+        '\u{252a}' => (H, N, L, H),
+        '\u{252b}' => (H, N, H, H),
+        '\u{252c}' => (L, L, N, L),
+        '\u{252d}' => (H, L, N, L),
+        '\u{252e}' => (L, H, N, L),
+        '\u{252f}' => (H, H, N, L),
+        '\u{2530}' => (L, L, N, H),
+        '\u{2531}' => (H, L, N, H),
+        '\u{2532}' => (L, H, N, H),
+        '\u{2533}' => (H, H, N, H),
+        '\u{2534}' => (L, L, L, N),
+        '\u{2535}' => (H, L, L, N),
+        '\u{2536}' => (L, H, L, N),
+        '\u{2537}' => (H, H, L, N),
+        '\u{2538}' => (L, L, H, N),
+        '\u{2539}' => (H, L, H, N),
+        '\u{253a}' => (L, H, H, N),
+        '\u{253b}' => (H, H, H, N),
+        '\u{253c}' => (L, L, L, L),
+        '\u{253d}' => (H, L, L, L),
+        '\u{253e}' => (L, H, L, L),
+        '\u{253f}' => (H, H, L, L),
+        '\u{2540}' => (L, L, H, L),
+        '\u{2541}' => (L, L, L, H),
+        '\u{2542}' => (L, L, H, H),
+        '\u{2543}' => (H, L, H, L),
+        '\u{2544}' => (L, H, H, L),
+        '\u{2545}' => (H, L, L, H),
+        '\u{2546}' => (L, H, L, H),
+        '\u{2547}' => (H, H, H, L),
+        '\u{2548}' => (H, H, L, H),
+        '\u{2549}' => (H, L, H, H),
+        '\u{254a}' => (L, H, H, H),
+        '\u{254b}' => (H, H, H, H),
+        '\u{2550}' => (D, D, N, N),
+        '\u{2551}' => (N, N, D, D),
+        '\u{2552}' => (N, D, N, L),
+        '\u{2553}' => (N, L, N, D),
+        '\u{2554}' => (N, D, N, D),
+        '\u{2555}' => (D, N, N, L),
+        '\u{2556}' => (L, N, N, D),
+        '\u{2557}' => (D, N, N, D),
+        '\u{2558}' => (N, D, L, N),
+        '\u{2559}' => (N, L, D, N),
+        '\u{255a}' => (N, D, D, N),
+        '\u{255b}' => (D, N, L, N),
+        '\u{255c}' => (L, N, D, N),
+        '\u{255d}' => (D, N, D, N),
+        '\u{255e}' => (N, D, L, L),
+        '\u{255f}' => (N, L, D, D),
+        '\u{2560}' => (N, D, D, D),
+        '\u{2561}' => (D, N, L, L),
+        '\u{2562}' => (L, N, D, D),
+        '\u{2563}' => (D, N, D, D),
+        '\u{2564}' => (D, D, N, L),
+        '\u{2565}' => (L, L, N, D),
+        '\u{2566}' => (D, D, N, D),
+        '\u{2567}' => (D, D, L, N),
+        '\u{2568}' => (L, L, D, N),
+        '\u{2569}' => (D, D, D, N),
+        '\u{256a}' => (D, D, L, L),
+        '\u{256b}' => (L, L, D, D),
+        '\u{256c}' => (D, D, D, D),
+        '\u{2574}' => (L, N, N, N),
+        '\u{2575}' => (N, N, L, N),
+        '\u{2576}' => (N, L, N, N),
+        '\u{2577}' => (N, N, N, L),
+        '\u{2578}' => (H, N, N, N),
+        '\u{2579}' => (N, N, H, N),
+        '\u{257a}' => (N, H, N, N),
+        '\u{257b}' => (N, N, N, H),
+        '\u{257c}' => (L, H, N, N),
+        '\u{257d}' => (N, N, L, H),
+        '\u{257e}' => (H, L, N, N),
+        '\u{257f}' => (N, N, H, L),
+        _ => return None,
+    };
+    Some(Arms {
+        left,
+        right,
+        up,
+        down,
+    })
+}
+
+/// Returns the range of pixels occupied by a stroke that is
+/// `thickness` pixels thick and centered within `length` pixels
+fn centered(length: isize, thickness: isize) -> (isize, isize) {
+    let start = (length - thickness) / 2;
+    (start, start + thickness)
+}
+
+type Strokes = [(isize, isize); 2];
+
+/// Returns the ranges of pixels occupied by the strokes of a line of
+/// the specified weight, centered within `length` pixels.
+/// The first element is the stroke nearest to the start of the range
+/// and the second is the one nearest to the end; they are the same
+/// stroke unless the line is doubled.
+fn strokes(weight: Weight, length: isize, light: isize) -> Strokes {
+    match weight {
+        Weight::Light => [centered(length, light); 2],
+        Weight::Heavy => [centered(length, 2 * light); 2],
+        Weight::Double => {
+            let (start, _) = centered(length, 3 * light);
+            [
+                (start, start + light),
+                (start + 2 * light, start + 3 * light),
+            ]
+        }
+    }
+}
+
+/// Rounds `n` eighths of `length` to a whole number of pixels
+fn eighths(length: isize, n: isize) -> isize {
+    (length * n + 4) / 8
+}
+
+/// The number of samples taken along each axis of a pixel when
+/// computing the coverage of curves and diagonals
+const SUPERSAMPLE: usize = 4;
+
+struct Canvas<'a> {
+    buffer: &'a mut Image,
+    width: isize,
+    height: isize,
+}
+
+impl<'a> Canvas<'a> {
+    fn new(buffer: &'a mut Image) -> Self {
+        let (width, height) = buffer.image_dimensions();
+        Self {
+            buffer,
+            width: width as isize,
+            height: height as isize,
+        }
+    }
+
+    /// Raise the coverage of the pixel to `alpha`
+    fn cover(&mut self, x: isize, y: isize, alpha: u8) {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        let pix = self.buffer.pixel_mut(x as usize, y as usize);
+        if Color(*pix).as_rgba().3 < alpha {
+            *pix = Color::rgba(alpha, alpha, alpha, alpha).0;
+        }
+    }
+
+    fn fill_rect(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, alpha: u8) {
+        for y in y0.max(0)..y1.min(self.height) {
+            for x in x0.max(0)..x1.min(self.width) {
+                self.cover(x, y, alpha);
+            }
+        }
+    }
+
+    /// Fill a rectangle specified by its extent `along` and `across`
+    /// a horizontal line, or a vertical line if `transposed` is true
+    fn fill_line(&mut self, along: (isize, isize), across: (isize, isize), transposed: bool) {
+        if transposed {
+            self.fill_rect(across.0, along.0, across.1, along.1, 0xff);
+        } else {
+            self.fill_rect(along.0, across.0, along.1, across.1, 0xff);
+        }
+    }
+
+    /// Fill each pixel according to the fraction of the sample points
+    /// within it for which `inside` returns true
+    fn fill_with<F: Fn(f64, f64) -> bool>(&mut self, inside: F) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut hits = 0;
+                for sy in 0..SUPERSAMPLE {
+                    for sx in 0..SUPERSAMPLE {
+                        let px = x as f64 + (sx as f64 + 0.5) / SUPERSAMPLE as f64;
+                        let py = y as f64 + (sy as f64 + 0.5) / SUPERSAMPLE as f64;
+                        if inside(px, py) {
+                            hits += 1;
+                        }
+                    }
+                }
+                let alpha = (255 * hits / (SUPERSAMPLE * SUPERSAMPLE)) as u8;
+                self.cover(x, y, alpha);
+            }
+        }
+    }
+
+    /// Draw the left and right arms of `arms`, or the up and down arms
+    /// of the untransposed character if `transposed` is true.
+    fn draw_arms(&mut self, arms: Arms, light: isize, transposed: bool) {
+        let (along, across) = if transposed {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        let perpendicular = |w: Option<Weight>| w.map(|w| strokes(w, along, light));
+        let up = perpendicular(arms.up);
+        let down = perpendicular(arms.down);
+
+        for &(weight, forwards) in &[(arms.left, false), (arms.right, true)] {
+            let weight = match weight {
+                Some(weight) => weight,
+                None => continue,
+            };
+            let [first, last] = strokes(weight, across, light);
+            let own = strokes(weight, along, light)[0];
+
+            // Compute where the strokes finish near the center of the cell
+            let ends = if weight == Weight::Double {
+                // Each of the pair of strokes stops at the nearest stroke
+                // of a perpendicular line on its own side, turns the
+                // corner into the outermost stroke of one on the other
+                // side, or otherwise meets its opposite arm at the center
+                let end = |near: Option<Strokes>, far: Option<Strokes>| match (near, far) {
+                    (Some(near), _) if forwards => near[1].0,
+                    (Some(near), _) => near[0].1,
+                    (None, Some(far)) if forwards => far[0].0,
+                    (None, Some(far)) => far[1].1,
+                    (None, None) if forwards => own.0,
+                    (None, None) => own.1,
+                };
+                [end(up, down), end(down, up)]
+            } else {
+                // A solid stroke reaches into the nearest stroke of the
+                // perpendicular lines
+                let end = if forwards {
+                    up.iter()
+                        .chain(down.iter())
+                        .map(|s| s[1].0)
+                        .min()
+                        .unwrap_or(own.0)
+                } else {
+                    up.iter()
+                        .chain(down.iter())
+                        .map(|s| s[0].1)
+                        .max()
+                        .unwrap_or(own.1)
+                };
+                [end, end]
+            };
+
+            let spans = if weight == Weight::Double {
+                vec![(first, ends[0]), (last, ends[1])]
+            } else {
+                vec![(first, ends[0])]
+            };
+            for (stroke, end) in spans {
+                let extent = if forwards { (end, along) } else { (0, end) };
+                self.fill_line(extent, stroke, transposed);
+            }
+        }
+    }
+
+    /// Draw a horizontal line, or a vertical line if `transposed`,
+    /// made of `count` dashes that are evenly spaced across the cell
+    fn draw_dashes(&mut self, count: isize, thickness: isize, transposed: bool) {
+        let (along, across) = if transposed {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
+        let stroke = centered(across, thickness);
+        // The gap is split between the two ends of each dash so that
+        // the gaps are also evenly spaced across adjacent cells
+        let gap = (along / (count * 4)).max(1);
+        for i in 0..count {
+            let start = along * i / count + gap / 2;
+            let end = along * (i + 1) / count - (gap - gap / 2);
+            self.fill_line((start, end), stroke, transposed);
+        }
+    }
+
+    /// Draw a light arc that joins the center of the horizontal edge in
+    /// direction `dx` with the center of the vertical edge in direction
+    /// `dy`, where the directions are 1.0 for right or down and -1.0 for
+    /// left or up.
+    fn draw_arc(&mut self, dx: f64, dy: f64, light: isize) {
+        let (x0, x1) = centered(self.width, light);
+        let (y0, y1) = centered(self.height, light);
+        let cx = (x0 + x1) as f64 / 2.;
+        let cy = (y0 + y1) as f64 / 2.;
+        let half = light as f64 / 2.;
+
+        let reach_x = if dx > 0. { self.width as f64 - cx } else { cx };
+        let reach_y = if dy > 0. { self.height as f64 - cy } else { cy };
+        let radius = reach_x.min(reach_y);
+
+        self.fill_with(|px, py| {
+            // Distances from the center lines towards the arms
+            let u = (px - cx) * dx;
+            let v = (py - cy) * dy;
+            if u <= radius && v <= radius {
+                let d = ((u - radius).powi(2) + (v - radius).powi(2)).sqrt();
+                (d - radius).abs() <= half
+            } else if v <= radius {
+                v.abs() <= half
+            } else if u <= radius {
+                u.abs() <= half
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Draw a light line between opposite corners of the cell; from the
+    /// top right to the bottom left if `rising`, otherwise from the top
+    /// left to the bottom right
+    fn draw_diagonal(&mut self, rising: bool, light: isize) {
+        let (width, height) = (self.width as f64, self.height as f64);
+        let length = (width * width + height * height).sqrt();
+        let half = light as f64 / 2.;
+        self.fill_with(|px, py| {
+            let px = if rising { width - px } else { px };
+            // Distance from the line through (0,0) and (width,height)
+            (px * height - py * width).abs() / length <= half
+        });
+    }
+
+    fn draw_block(&mut self, c: char) {
+        let (width, height) = (self.width, self.height);
+        let mid_x = eighths(width, 4);
+        let mid_y = eighths(height, 4);
+        let quadrants = |canvas: &mut Self, ul: bool, ur: bool, ll: bool, lr: bool| {
+            if ul {
+                canvas.fill_rect(0, 0, mid_x, mid_y, 0xff);
+            }
+            if ur {
+                canvas.fill_rect(mid_x, 0, width, mid_y, 0xff);
+            }
+            if ll {
+                canvas.fill_rect(0, mid_y, mid_x, height, 0xff);
+            }
+            if lr {
+                canvas.fill_rect(mid_x, mid_y, width, height, 0xff);
+            }
+        };
+
+        match c {
+            '\u{2580}' => self.fill_rect(0, 0, width, mid_y, 0xff),
+            '\u{2581}'..='\u{2588}' => {
+                // Lower one eighth through to the full block
+                let n = c as isize - 0x2580;
+                self.fill_rect(0, height - eighths(height, n), width, height, 0xff)
+            }
+            '\u{2589}'..='\u{258f}' => {
+                // Left seven eighths down to left one eighth
+                let n = 0x2590 - c as isize;
+                self.fill_rect(0, 0, eighths(width, n), height, 0xff)
+            }
+            '\u{2590}' => self.fill_rect(mid_x, 0, width, height, 0xff),
+            '\u{2591}' => self.fill_rect(0, 0, width, height, 0x40),
+            '\u{2592}' => self.fill_rect(0, 0, width, height, 0x80),
+            '\u{2593}' => self.fill_rect(0, 0, width, height, 0xc0),
+            '\u{2594}' => self.fill_rect(0, 0, width, eighths(height, 1), 0xff),
+            '\u{2595}' => self.fill_rect(width - eighths(width, 1), 0, width, height, 0xff),
+            '\u{2596}' => quadrants(self, false, false, true, false),
+            '\u{2597}' => quadrants(self, false, false, false, true),
+            '\u{2598}' => quadrants(self, true, false, false, false),
+            '\u{2599}' => quadrants(self, true, false, true, true),
+            '\u{259a}' => quadrants(self, true, false, false, true),
+            '\u{259b}' => quadrants(self, true, true, true, false),
+            '\u{259c}' => quadrants(self, true, true, false, true),
+            '\u{259d}' => quadrants(self, false, true, false, false),
+            '\u{259e}' => quadrants(self, false, true, true, false),
+            '\u{259f}' => quadrants(self, false, true, true, true),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::window::{Point, Rect};
+
+    fn draw(c: char) -> Image {
+        let metrics = RenderMetrics::for_cell(16, 30, 6);
+        let mut buffer = Image::new(16, 30);
+        buffer.clear_rect(
+            Rect::new(Point::new(0, 0), metrics.cell_size),
+            Color::rgba(0, 0, 0, 0),
+        );
+        assert!(draw_custom_glyph(&mut buffer, &metrics, c));
+        buffer
+    }
+
+    fn alpha(buffer: &Image, x: usize, y: usize) -> u8 {
+        Color(*buffer.pixel(x, y)).as_rgba().3
+    }
+
+    #[test]
+    fn horizontal_line_fills_the_center_row() {
+        let buffer = draw('\u{2500}');
+        for y in 0..30 {
+            for x in 0..16 {
+                let expected = if y == 14 { 0xff } else { 0 };
+                assert_eq!(alpha(&buffer, x, y), expected, "pixel {},{}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn vertical_line_fills_the_center_column() {
+        let buffer = draw('\u{2502}');
+        for y in 0..30 {
+            for x in 0..16 {
+                let expected = if x == 7 { 0xff } else { 0 };
+                assert_eq!(alpha(&buffer, x, y), expected, "pixel {},{}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn double_corner_is_closed() {
+        // ╔ is a pair of nested corners; each pair of strokes meets
+        // without a gap or overshoot
+        let buffer = draw('\u{2554}');
+        let inked = |x: usize, y: usize| alpha(&buffer, x, y) == 0xff;
+
+        // The strokes are centered: the horizontal pair is on rows
+        // 13 and 15 and the vertical pair on columns 6 and 8
+        for x in 6..16 {
+            assert!(inked(x, 13), "outer stroke at {},13", x);
+        }
+        for y in 13..30 {
+            assert!(inked(6, y), "outer stroke at 6,{}", y);
+        }
+        for x in 8..16 {
+            assert!(inked(x, 15), "inner stroke at {},15", x);
+        }
+        for y in 15..30 {
+            assert!(inked(8, y), "inner stroke at 8,{}", y);
+        }
+        assert!(!inked(5, 13));
+        assert!(!inked(6, 12));
+        assert!(!inked(7, 15));
+        assert!(!inked(8, 14));
+        assert!(!inked(7, 14));
+    }
+
+    #[test]
+    fn block_elements_divide_the_cell() {
+        let buffer = draw('\u{2584}');
+        for y in 0..30 {
+            for x in 0..16 {
+                let expected = if y >= 15 { 0xff } else { 0 };
+                assert_eq!(alpha(&buffer, x, y), expected, "pixel {},{}", x, y);
+            }
+        }
+
+        let buffer = draw('\u{2592}');
+        assert!((0..30).all(|y| (0..16).all(|x| alpha(&buffer, x, y) == 0x80)));
+    }
+
+    #[test]
+    fn every_character_in_the_range_is_drawn() {
+        for c in 0x2500..=0x259f {
+            let c = std::char::from_u32(c).unwrap();
+            assert!(is_custom_glyph(c));
+            let buffer = draw(c);
+            let ink = (0..30)
+                .flat_map(|y| (0..16).map(move |x| (x, y)))
+                .filter(|&(x, y)| alpha(&buffer, x, y) != 0)
+                .count();
+            assert!(ink > 0, "{:?} has no ink", c);
+        }
+    }
+}
@@ -8,11 +8,16 @@ in vec3 o_hsv;
 in vec4 o_bg_color;
 in vec4 o_cursor_color;
 in vec4 o_fg_color;
+in vec4 o_underline_color;
+in vec2 o_clip;
+in float o_y;
 
 uniform mat4 projection;
 uniform bool window_bg_layer;
 uniform bool bg_and_line_layer;
 uniform bool has_background_image;
+uniform bool linear_blending;
+uniform bool subpixel_aa;
 
 uniform sampler2D atlas_nearest_sampler;
 uniform sampler2D atlas_linear_sampler;
@@ -35,6 +40,14 @@ vec4 multiply(vec4 src, vec4 dst) {
       dst.a);
 }
 
+vec3 srgb_to_linear(vec3 c) {
+  return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(0.04045, c));
+}
+
+vec3 linear_to_srgb(vec3 c) {
+  return mix(c * 12.92, 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, c));
+}
+
 vec3 rgb2hsv(vec3 c)
 {
     vec4 K = vec4(0.0, -1.0 / 3.0, 2.0 / 3.0, -1.0);
@@ -60,6 +73,12 @@ vec4 apply_hsv(vec4 c)
 }
 
 void main() {
+  // Parts of rows that are moved out of their pane while it is
+  // smooth scrolling are clipped
+  if (o_clip.x < o_clip.y && (o_y < o_clip.x || o_y > o_clip.y)) {
+    discard;
+  }
+
   if (window_bg_layer) {
     if (o_has_color == 2.0) {
       // We're the window background image.
@@ -89,9 +108,11 @@ void main() {
     vec4 under_color = texture(atlas_nearest_sampler, o_underline);
     if (under_color.a != 0.0) {
         // if the underline glyph isn't transparent in this position then
-        // we take the text fg color, otherwise we'll leave the color
-        // at the background color.
-        color = o_fg_color;
+        // we blend in the underline color (which is the text fg color
+        // unless the cell specifies otherwise) according to the coverage
+        // of the glyph, otherwise we'll leave the color at the background
+        // color.
+        color = mix(color, o_underline_color, under_color.a);
     }
 
     // Similar to the above: if the cursor texture isn't transparent
@@ -118,7 +139,48 @@ void main() {
           // instead; this avoids generating shadowy anti-aliasing artifacts
           // for something that should otherwise be invisible.
           color = vec4(0.0, 0.0, 0.0, 0.0);
+        } else if (o_bg_color.a == 1.0 && (subpixel_aa || linear_blending)) {
+          // Subpixel antialiased glyphs have separate coverage for
+          // each of the red, green and blue channels, and the alpha
+          // channel holds the largest of them.  Blend each channel by
+          // its own coverage; the color is scaled so that blending
+          // it over the background with the alpha of the glyph
+          // produces the per-channel mix.
+          // This assumes that the glyph is drawn over its own cell
+          // background, so the edges of a glyph that overhangs a cell
+          // with a different background are mixed with the wrong color.
+          // Grayscale glyphs only take this path for linear blending;
+          // otherwise they are tinted by multiply below, as they
+          // always have been.
+          // The atlas is an sRGB texture, so sampling it decoded the
+          // color channels but not the alpha; re-encode them to
+          // get back the coverage that the rasterizer produced.
+          vec3 coverage = min(linear_to_srgb(color.rgb), vec3(color.a));
+          float alpha = max(coverage.r, max(coverage.g, coverage.b));
+          if (alpha == 0.0) {
+            color = vec4(0.0, 0.0, 0.0, 0.0);
+          } else if (linear_blending) {
+            // Apply the coverage to the linear fg and bg colors
+            vec3 target = linear_to_srgb(mix(
+              srgb_to_linear(o_bg_color.rgb),
+              srgb_to_linear(o_fg_color.rgb),
+              coverage));
+            // Find the smallest alpha that can reach the target
+            // without the color going out of range
+            vec3 delta = abs(target - o_bg_color.rgb);
+            vec3 span = max(abs(o_fg_color.rgb - o_bg_color.rgb), vec3(1.0 / 255.0));
+            vec3 needed = delta / span;
+            alpha = clamp(max(needed.r, max(needed.g, needed.b)), alpha, 1.0);
+            color = vec4(o_bg_color.rgb + (target - o_bg_color.rgb) / alpha, alpha);
+          } else {
+            color = vec4(
+              o_bg_color.rgb + (o_fg_color.rgb - o_bg_color.rgb) * coverage / alpha,
+              alpha);
+          }
         } else {
+          // Grayscale glyphs, and glyphs over a translucent background
+          // for which the per-channel blend above cannot be computed,
+          // are tinted by their overall coverage
           color = multiply(o_fg_color, color);
         }
       }
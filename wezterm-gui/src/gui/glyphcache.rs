@@ -1,4 +1,9 @@
+use super::customglyph::draw_custom_glyph;
+use super::utilsprites::RenderMetrics;
+use crate::stats::HitCounts;
 use ::window::bitmaps::atlas::{Atlas, Sprite};
+#[cfg(test)]
+use ::window::bitmaps::ImageTexture;
 use ::window::bitmaps::{Image, Texture2d};
 use ::window::glium::backend::Context as GliumContext;
 use ::window::glium::texture::SrgbTexture2d;
@@ -8,6 +13,7 @@ use euclid::num::Zero;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Arc;
+use termwiz::color::RgbColor;
 use termwiz::image::ImageData;
 use wezterm_font::units::*;
 use wezterm_font::{FontConfiguration, GlyphInfo};
@@ -17,6 +23,9 @@ pub struct GlyphKey {
     pub font_idx: usize,
     pub glyph_pos: u32,
     pub style: TextStyle,
+    /// The color of the text, for color glyphs that have layers
+    /// drawn in it; None for every other glyph
+    pub foreground: Option<RgbColor>,
 }
 
 /// We'd like to avoid allocating when resolving from the cache
@@ -29,6 +38,7 @@ pub struct BorrowedGlyphKey<'a> {
     pub font_idx: usize,
     pub glyph_pos: u32,
     pub style: &'a TextStyle,
+    pub foreground: Option<RgbColor>,
 }
 
 impl<'a> BorrowedGlyphKey<'a> {
@@ -37,6 +47,7 @@ impl<'a> BorrowedGlyphKey<'a> {
             font_idx: self.font_idx,
             glyph_pos: self.glyph_pos,
             style: self.style.clone(),
+            foreground: self.foreground,
         }
     }
 }
@@ -51,6 +62,7 @@ impl GlyphKeyTrait for GlyphKey {
             font_idx: self.font_idx,
             glyph_pos: self.glyph_pos,
             style: &self.style,
+            foreground: self.foreground,
         }
     }
 }
@@ -85,6 +97,9 @@ impl<'a> std::hash::Hash for (dyn GlyphKeyTrait + 'a) {
 /// The image data may be None for whitespace glyphs.
 pub struct CachedGlyph<T: Texture2d> {
     pub has_color: bool,
+    /// True if the glyph has color layers that are drawn in the
+    /// color of the text
+    pub uses_foreground: bool,
     pub x_offset: PixelLength,
     pub y_offset: PixelLength,
     pub bearing_x: PixelLength,
@@ -93,10 +108,31 @@ pub struct CachedGlyph<T: Texture2d> {
     pub scale: f64,
 }
 
+impl<T: Texture2d> CachedGlyph<T> {
+    /// Returns a copy of the glyph whose sprite refers to `texture`,
+    /// which the atlas has grown into
+    fn with_texture(&self, texture: &Rc<T>) -> Self {
+        Self {
+            has_color: self.has_color,
+            uses_foreground: self.uses_foreground,
+            x_offset: self.x_offset,
+            y_offset: self.y_offset,
+            bearing_x: self.bearing_x,
+            bearing_y: self.bearing_y,
+            texture: self
+                .texture
+                .as_ref()
+                .map(|sprite| sprite.with_texture(texture)),
+            scale: self.scale,
+        }
+    }
+}
+
 impl<T: Texture2d> std::fmt::Debug for CachedGlyph<T> {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
         fmt.debug_struct("CachedGlyph")
             .field("has_color", &self.has_color)
+            .field("uses_foreground", &self.uses_foreground)
             .field("x_offset", &self.x_offset)
             .field("y_offset", &self.y_offset)
             .field("bearing_x", &self.bearing_x)
@@ -107,11 +143,51 @@ impl<T: Texture2d> std::fmt::Debug for CachedGlyph<T> {
     }
 }
 
+/// Describes the contents of a `GlyphCache`, which is useful when
+/// diagnosing why the texture atlas is running out of space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlyphCacheStats {
+    /// The number of glyphs, including custom glyphs, in the cache
+    pub glyphs: usize,
+    /// The number of images in the cache
+    pub images: usize,
+    /// The width and height of the texture atlas, in pixels
+    pub atlas_size: usize,
+    /// The number of pixels of the atlas that are in use
+    pub atlas_used_area: usize,
+}
+
+impl std::fmt::Display for GlyphCacheStats {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let total = self.atlas_size * self.atlas_size;
+        write!(
+            fmt,
+            "{} glyphs, {} images, {}x{} atlas {:.1}% used",
+            self.glyphs,
+            self.images,
+            self.atlas_size,
+            self.atlas_size,
+            if total == 0 {
+                0.
+            } else {
+                100. * self.atlas_used_area as f64 / total as f64
+            }
+        )
+    }
+}
+
+/// When more than this fraction of the atlas is in use, the atlas
+/// is grown before painting, rather than waiting for an allocation
+/// to fail part way through painting and then starting over.
+const ATLAS_HIGH_WATER_MARK: f64 = 0.9;
+
 pub struct GlyphCache<T: Texture2d> {
     glyph_cache: HashMap<GlyphKey, Rc<CachedGlyph<T>>>,
     pub atlas: Atlas<T>,
     fonts: Rc<FontConfiguration>,
     image_cache: HashMap<usize, Sprite<T>>,
+    custom_glyph_cache: HashMap<char, Rc<CachedGlyph<T>>>,
+    pub counts: HitCounts,
 }
 
 impl GlyphCache<SrgbTexture2d> {
@@ -133,55 +209,209 @@ impl GlyphCache<SrgbTexture2d> {
             fonts: Rc::clone(fonts),
             glyph_cache: HashMap::new(),
             image_cache: HashMap::new(),
+            custom_glyph_cache: HashMap::new(),
+            atlas,
+            counts: HitCounts::default(),
+        })
+    }
+
+    /// Grow the atlas into a new texture of the specified size,
+    /// keeping the glyphs that have already been cached
+    pub fn grow_gl(&mut self, backend: &Rc<GliumContext>, size: usize) -> anyhow::Result<()> {
+        let surface = Rc::new(SrgbTexture2d::empty_with_format(
+            backend,
+            glium::texture::SrgbFormat::U8U8U8U8,
+            glium::texture::MipmapsOption::NoMipmap,
+            size as u32,
+            size as u32,
+        )?);
+        self.grow_atlas(&surface)
+    }
+}
+
+#[cfg(test)]
+impl GlyphCache<ImageTexture> {
+    /// Create a cache whose atlas is held in memory, rather than on the GPU
+    pub fn new_in_memory(fonts: &Rc<FontConfiguration>, size: usize) -> anyhow::Result<Self> {
+        let surface = Rc::new(ImageTexture::new(size, size));
+        let atlas = Atlas::new(&surface).expect("failed to create new texture atlas");
+
+        Ok(Self {
+            fonts: Rc::clone(fonts),
+            glyph_cache: HashMap::new(),
+            image_cache: HashMap::new(),
+            custom_glyph_cache: HashMap::new(),
             atlas,
+            counts: HitCounts::default(),
         })
     }
+}
+
+impl<T: Texture2d> GlyphCache<T> {
+    pub fn stats(&self) -> GlyphCacheStats {
+        GlyphCacheStats {
+            glyphs: self.glyph_cache.len() + self.custom_glyph_cache.len(),
+            images: self.image_cache.len(),
+            atlas_size: self.atlas.size(),
+            atlas_used_area: self.atlas.used_area(),
+        }
+    }
+
+    /// Returns true if the atlas is full enough that it should be
+    /// grown before painting
+    pub fn atlas_is_nearly_full(&self) -> bool {
+        let size = self.atlas.size();
+        self.atlas.used_area() as f64 > (size * size) as f64 * ATLAS_HIGH_WATER_MARK
+    }
+
+    /// Grow the atlas into `texture`, which must be larger than the
+    /// current texture.  The glyphs and images that are already in
+    /// the atlas are copied across, so that they remain cached.
+    pub fn grow_atlas(&mut self, texture: &Rc<T>) -> anyhow::Result<()> {
+        self.atlas.grow(texture)?;
+
+        for glyph in self
+            .glyph_cache
+            .values_mut()
+            .chain(self.custom_glyph_cache.values_mut())
+        {
+            *glyph = Rc::new(glyph.with_texture(texture));
+        }
+        for sprite in self.image_cache.values_mut() {
+            *sprite = sprite.with_texture(texture);
+        }
+        Ok(())
+    }
 
     pub fn clear(&mut self) {
         self.atlas.clear();
         self.image_cache.clear();
         self.glyph_cache.clear();
+        self.custom_glyph_cache.clear();
     }
-}
 
-impl<T: Texture2d> GlyphCache<T> {
     /// Resolve a glyph from the cache, rendering the glyph on-demand if
     /// the cache doesn't already hold the desired glyph.
+    /// `foreground` is the color of the text, which is only used by
+    /// color glyphs that have layers drawn in it.
     pub fn cached_glyph(
         &mut self,
         info: &GlyphInfo,
         style: &TextStyle,
+        foreground: RgbColor,
     ) -> anyhow::Result<Rc<CachedGlyph<T>>> {
         let key = BorrowedGlyphKey {
             font_idx: info.font_idx,
             glyph_pos: info.glyph_pos,
             style,
+            foreground: None,
         };
+        let glyph = self.cached_glyph_for_key(info, key)?;
+        if !glyph.uses_foreground {
+            return Ok(glyph);
+        }
+
+        // The text color is part of the rasterized glyph, so
+        // there is an entry for each color it is used in.  The entry
+        // without a color just records that, and has no texture.
+        self.cached_glyph_for_key(
+            info,
+            BorrowedGlyphKey {
+                foreground: Some(foreground),
+                ..key
+            },
+        )
+    }
 
+    fn cached_glyph_for_key(
+        &mut self,
+        info: &GlyphInfo,
+        key: BorrowedGlyphKey,
+    ) -> anyhow::Result<Rc<CachedGlyph<T>>> {
         if let Some(entry) = self.glyph_cache.get(&key as &dyn GlyphKeyTrait) {
+            self.counts.hits += 1;
             return Ok(Rc::clone(entry));
         }
+        self.counts.misses += 1;
 
-        let glyph = self.load_glyph(info, style)?;
+        let glyph = self.load_glyph(info, key.style, key.foreground)?;
         self.glyph_cache.insert(key.to_owned(), Rc::clone(&glyph));
         Ok(glyph)
     }
 
+    /// Resolve a box drawing or block element character that we draw
+    /// ourselves rather than using the font, rendering it on-demand if
+    /// the cache doesn't already hold it.
+    /// The glyph fills the cell described by `metrics`.
+    pub fn cached_custom_glyph(
+        &mut self,
+        c: char,
+        metrics: &RenderMetrics,
+    ) -> anyhow::Result<Rc<CachedGlyph<T>>> {
+        if let Some(entry) = self.custom_glyph_cache.get(&c) {
+            return Ok(Rc::clone(entry));
+        }
+
+        let mut buffer = Image::new(
+            metrics.cell_size.width as usize,
+            metrics.cell_size.height as usize,
+        );
+        if !draw_custom_glyph(&mut buffer, metrics, c) {
+            anyhow::bail!("no custom glyph for {:?}", c);
+        }
+
+        // The renderer places the top of the glyph `bearing_y` above
+        // the descender, so this puts it at the top of the cell
+        let glyph = Rc::new(CachedGlyph {
+            has_color: false,
+            uses_foreground: false,
+            texture: Some(self.atlas.allocate(&buffer)?),
+            x_offset: PixelLength::zero(),
+            y_offset: PixelLength::zero(),
+            bearing_x: PixelLength::zero(),
+            bearing_y: PixelLength::new(metrics.cell_size.height as f64) + metrics.descender,
+            scale: 1.0,
+        });
+        self.custom_glyph_cache.insert(c, Rc::clone(&glyph));
+        Ok(glyph)
+    }
+
     /// Perform the load and render of a glyph
     #[allow(clippy::float_cmp)]
     fn load_glyph(
         &mut self,
         info: &GlyphInfo,
         style: &TextStyle,
+        foreground: Option<RgbColor>,
     ) -> anyhow::Result<Rc<CachedGlyph<T>>> {
         let base_metrics;
         let idx_metrics;
         let glyph;
+        let uses_foreground;
 
         {
             let font = self.fonts.resolve_font(style)?;
+            uses_foreground = font.uses_foreground(info.glyph_pos, info.font_idx)?;
+            if uses_foreground && foreground.is_none() {
+                // Don't rasterize the glyph until the color of the
+                // text is known
+                return Ok(Rc::new(CachedGlyph {
+                    has_color: true,
+                    uses_foreground,
+                    texture: None,
+                    x_offset: PixelLength::zero(),
+                    y_offset: PixelLength::zero(),
+                    bearing_x: PixelLength::zero(),
+                    bearing_y: PixelLength::zero(),
+                    scale: 1.0,
+                }));
+            }
             base_metrics = font.metrics();
-            glyph = font.rasterize_glyph(info.glyph_pos, info.font_idx)?;
+            // The color only matters to glyphs that use it
+            let foreground = foreground
+                .map(|c| (c.red, c.green, c.blue, 0xff))
+                .unwrap_or((0xff, 0xff, 0xff, 0xff));
+            glyph = font.rasterize_glyph(info.glyph_pos, info.font_idx, foreground)?;
 
             idx_metrics = font.metrics_for_idx(info.font_idx)?;
         }
@@ -204,6 +434,7 @@ impl<T: Texture2d> GlyphCache<T> {
             // a whitespace glyph
             CachedGlyph {
                 has_color: glyph.has_color,
+                uses_foreground,
                 texture: None,
                 x_offset: info.x_offset * scale,
                 y_offset: info.y_offset * scale,
@@ -243,6 +474,7 @@ impl<T: Texture2d> GlyphCache<T> {
 
             let g = CachedGlyph {
                 has_color: glyph.has_color,
+                uses_foreground,
                 texture: Some(tex),
                 x_offset,
                 y_offset,
@@ -287,3 +519,145 @@ impl<T: Texture2d> GlyphCache<T> {
         Ok(sprite)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use config::FontLocatorSelection;
+
+    #[test]
+    fn stats_track_cached_glyphs() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = Rc::new(FontConfiguration::new());
+        let mut cache = GlyphCache::new_in_memory(&fonts, 256).unwrap();
+
+        let empty = cache.stats();
+        assert_eq!(
+            empty,
+            GlyphCacheStats {
+                glyphs: 0,
+                images: 0,
+                atlas_size: 256,
+                atlas_used_area: 0,
+            }
+        );
+
+        let style = TextStyle::default();
+        let font = fonts.resolve_font(&style).unwrap();
+        let infos = font.shape("abcdef").unwrap();
+        assert_eq!(infos.len(), 6);
+
+        let mut area = 0;
+        for (n, info) in infos.iter().enumerate() {
+            let glyph = cache
+                .cached_glyph(info, &style, RgbColor::default())
+                .unwrap();
+            let sprite = glyph.texture.as_ref().unwrap();
+            // Each sprite is reserved along with a pixel of padding
+            // on each side
+            area +=
+                (sprite.coords.size.width as usize + 2) * (sprite.coords.size.height as usize + 2);
+
+            let stats = cache.stats();
+            assert_eq!(stats.glyphs, n + 1);
+            assert_eq!(stats.atlas_used_area, area);
+        }
+
+        // Resolving a glyph that is already cached doesn't change anything
+        cache
+            .cached_glyph(&infos[0], &style, RgbColor::default())
+            .unwrap();
+        assert_eq!(cache.stats().glyphs, infos.len());
+        assert_eq!(cache.stats().atlas_used_area, area);
+
+        cache.clear();
+        assert_eq!(cache.stats(), empty);
+    }
+
+    #[test]
+    fn growing_the_atlas_keeps_the_cached_glyphs() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = Rc::new(FontConfiguration::new());
+        let mut cache = GlyphCache::new_in_memory(&fonts, 64).unwrap();
+        let metrics = RenderMetrics::for_cell(14, 14, 3);
+
+        // Each glyph reserves 16x16 pixels including its padding, so
+        // the atlas passes the high water mark after 15 of them
+        let mut cached = vec![];
+        for c in '\u{2500}'..='\u{257f}' {
+            if cache.atlas_is_nearly_full() {
+                break;
+            }
+            if let Ok(glyph) = cache.cached_custom_glyph(c, &metrics) {
+                cached.push((c, glyph.texture.as_ref().unwrap().coords));
+            }
+        }
+        assert!(cache.atlas_is_nearly_full());
+        let stats = cache.stats();
+
+        let texture = Rc::new(ImageTexture::new(128, 128));
+        cache.grow_atlas(&texture).unwrap();
+        assert!(!cache.atlas_is_nearly_full());
+        assert_eq!(
+            cache.stats(),
+            GlyphCacheStats {
+                atlas_size: 128,
+                ..stats
+            }
+        );
+
+        // The glyphs are still cached, at the same place in the atlas,
+        // but now refer to the new texture
+        for (c, coords) in cached {
+            let glyph = cache.cached_custom_glyph(c, &metrics).unwrap();
+            let sprite = glyph.texture.as_ref().unwrap();
+            assert_eq!(sprite.coords, coords);
+            assert!(Rc::ptr_eq(&sprite.texture, &texture));
+        }
+        assert_eq!(cache.stats().glyphs, stats.glyphs);
+
+        // And there is room for more
+        cache.atlas.allocate(&Image::new(14, 14)).unwrap();
+    }
+
+    #[test]
+    fn growing_the_atlas_copies_the_glyph_bitmaps() {
+        FontLocatorSelection::ConfigDirsOnly.set_default();
+        let fonts = Rc::new(FontConfiguration::new());
+        let mut cache = GlyphCache::new_in_memory(&fonts, 64).unwrap();
+        let metrics = RenderMetrics::for_cell(14, 14, 3);
+
+        // Reads the pixels that the texture coordinates of the sprite
+        // refer to, as the renderer would sample them
+        let read_sprite = |sprite: &Sprite<ImageTexture>| {
+            let coords = sprite.texture_coords();
+            let side = sprite.texture.width() as f32;
+            let rect = Rect::new(
+                Point::new(
+                    (coords.min_x() * side).round() as isize,
+                    (coords.min_y() * side).round() as isize,
+                ),
+                Size::new(
+                    (coords.size.width * side).round() as isize,
+                    (coords.size.height * side).round() as isize,
+                ),
+            );
+            let mut image = Image::new(rect.size.width as usize, rect.size.height as usize);
+            sprite.texture.read(rect, &mut image);
+            image
+        };
+
+        let glyph = cache.cached_custom_glyph('\u{253c}', &metrics).unwrap();
+        let before = read_sprite(glyph.texture.as_ref().unwrap());
+        assert!(before.pixels().iter().any(|&pixel| pixel != 0));
+
+        cache
+            .grow_atlas(&Rc::new(ImageTexture::new(256, 256)))
+            .unwrap();
+
+        let glyph = cache.cached_custom_glyph('\u{253c}', &metrics).unwrap();
+        let after = read_sprite(glyph.texture.as_ref().unwrap());
+        assert_eq!(after.image_dimensions(), before.image_dimensions());
+        assert_eq!(after.pixels(), before.pixels());
+    }
+}
@@ -1,10 +1,13 @@
 use ::window::*;
 use anyhow::Error;
 pub use config::FrontEndSelection;
+use mux::window::WindowId as MuxWindowId;
 use mux::{Mux, MuxNotification};
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 
+mod customglyph;
 mod glyphcache;
 mod overlay;
 mod quad;
@@ -15,6 +18,7 @@ mod shapecache;
 mod tabbar;
 mod termwindow;
 mod utilsprites;
+mod windowstate;
 
 pub use selection::SelectionMode;
 pub use termwindow::set_window_class;
@@ -22,6 +26,7 @@ pub use termwindow::TermWindow;
 
 pub struct GuiFrontEnd {
     connection: Rc<Connection>,
+    known_windows: RefCell<HashMap<MuxWindowId, Window>>,
 }
 
 impl Drop for GuiFrontEnd {
@@ -58,16 +63,27 @@ impl GuiFrontEnd {
         }
 
         let connection = Connection::init()?;
-        let front_end = Rc::new(GuiFrontEnd { connection });
+        let front_end = Rc::new(GuiFrontEnd {
+            connection,
+            known_windows: RefCell::new(HashMap::new()),
+        });
         let mux = Mux::get().expect("mux started and running on main thread");
         let fe = Rc::downgrade(&front_end);
         mux.subscribe(move |n| {
-            if let Some(_fe) = fe.upgrade() {
+            if let Some(fe) = fe.upgrade() {
                 match n {
                     MuxNotification::WindowCreated(mux_window_id) => {
                         termwindow::TermWindow::new_window(mux_window_id).ok();
                     }
                     MuxNotification::PaneOutput(_) => {}
+                    MuxNotification::Alert { pane_id, alert } => {
+                        let mux = Mux::get().unwrap();
+                        if let Some((_domain, mux_window_id, _tab)) = mux.resolve_pane_id(pane_id) {
+                            if let Some(window) = fe.known_window(mux_window_id) {
+                                termwindow::TermWindow::deliver_alert(&window, alert);
+                            }
+                        }
+                    }
                 }
                 true
             } else {
@@ -77,6 +93,20 @@ impl GuiFrontEnd {
         Ok(front_end)
     }
 
+    /// Records the gui window that is currently displaying the
+    /// mux window `mux_window_id`, so that alerts raised by its
+    /// panes can be delivered to it.
+    pub fn record_known_window(&self, mux_window_id: MuxWindowId, window: Window) {
+        let mux = Mux::get().unwrap();
+        let mut known_windows = self.known_windows.borrow_mut();
+        known_windows.retain(|id, _| mux.get_window(*id).is_some());
+        known_windows.insert(mux_window_id, window);
+    }
+
+    fn known_window(&self, mux_window_id: MuxWindowId) -> Option<Window> {
+        self.known_windows.borrow().get(&mux_window_id).cloned()
+    }
+
     pub fn run_forever(&self) -> anyhow::Result<()> {
         self.connection
             .schedule_timer(std::time::Duration::from_millis(200), move || {
@@ -111,6 +141,16 @@ pub fn shutdown() {
     FRONT_END.with(|f| drop(f.borrow_mut().take()));
 }
 
+#[cfg(windows)]
+pub fn set_window_frame_theme(theme: config::WindowFrameTheme) {
+    use ::window::os::windows::{set_frame_theme, FrameTheme};
+    set_frame_theme(match theme {
+        config::WindowFrameTheme::System => FrameTheme::System,
+        config::WindowFrameTheme::Dark => FrameTheme::Dark,
+        config::WindowFrameTheme::Light => FrameTheme::Light,
+    });
+}
+
 pub fn try_new(sel: FrontEndSelection) -> Result<Rc<GuiFrontEnd>, Error> {
     let front_end = match sel {
         FrontEndSelection::Software => GuiFrontEnd::try_new_swrast(),
@@ -0,0 +1,88 @@
+use mux::pane::PaneId;
+use mux::termwiztermtab::TermWizTerminal;
+use mux::Mux;
+use termwiz::color::ColorAttribute;
+use termwiz::input::{InputEvent, KeyCode, KeyEvent};
+use termwiz::surface::{Change, Position};
+use termwiz::terminal::Terminal;
+
+/// Returns true if pasting `text` should be confirmed by the user
+/// first.  Text containing a newline may cause a shell to run
+/// commands as soon as it is pasted, unless the application has
+/// enabled bracketed paste mode and can tell that it was pasted.
+pub fn paste_needs_confirmation(text: &str, bracketed_paste: bool) -> bool {
+    !bracketed_paste && text.contains(|c| c == '\n' || c == '\r')
+}
+
+pub fn confirm_paste(
+    pane_id: PaneId,
+    text: String,
+    mut term: TermWizTerminal,
+) -> anyhow::Result<()> {
+    term.set_raw_mode()?;
+
+    let num_lines = text.lines().count();
+    let changes = vec![
+        Change::ClearScreen(ColorAttribute::Default),
+        Change::CursorPosition {
+            x: Position::Absolute(0),
+            y: Position::Absolute(0),
+        },
+        Change::Text(format!(
+            "The text to be pasted spans {} lines and may run commands.\r\n\
+             Really paste it? [y/n]\r\n",
+            num_lines
+        )),
+    ];
+
+    term.render(&changes)?;
+    term.flush()?;
+
+    while let Ok(Some(event)) = term.poll_input(None) {
+        match event {
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('y'),
+                ..
+            }) => {
+                promise::spawn::spawn_into_main_thread(async move {
+                    let mux = Mux::get().unwrap();
+                    if let Some(pane) = mux.get_pane(pane_id) {
+                        pane.trickle_paste(text).ok();
+                    }
+                })
+                .detach();
+                break;
+            }
+            InputEvent::Key(KeyEvent {
+                key: KeyCode::Char('n'),
+                ..
+            })
+            | InputEvent::Key(KeyEvent {
+                key: KeyCode::Escape,
+                ..
+            }) => {
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn multiline_paste_needs_confirmation() {
+        assert!(!paste_needs_confirmation("ls -l", false));
+        assert!(paste_needs_confirmation("ls -l\n", false));
+        assert!(paste_needs_confirmation("cd /tmp\rrm -rf *", false));
+        assert!(paste_needs_confirmation("one\ntwo", false));
+
+        // The application can tell that bracketed text was pasted
+        assert!(!paste_needs_confirmation("one\ntwo\n", true));
+        assert!(!paste_needs_confirmation("", false));
+    }
+}
@@ -6,15 +6,19 @@ use std::pin::Pin;
 use std::rc::Rc;
 
 mod confirm_close_pane;
+mod confirm_paste;
 mod copy;
 mod launcher;
+mod quickselect;
 mod search;
 mod tabnavigator;
 
 pub use confirm_close_pane::confirm_close_pane;
 pub use confirm_close_pane::confirm_close_tab;
+pub use confirm_paste::{confirm_paste, paste_needs_confirmation};
 pub use copy::CopyOverlay;
 pub use launcher::launcher;
+pub use quickselect::QuickSelectOverlay;
 pub use search::SearchOverlay;
 pub use tabnavigator::tab_navigator;
 
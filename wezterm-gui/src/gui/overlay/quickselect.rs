@@ -0,0 +1,580 @@
+use crate::gui::termwindow::{working_dir_from_url, TermWindow};
+use config::configuration;
+use mux::domain::DomainId;
+use mux::pane::{Pane, PaneId};
+use mux::renderable::*;
+use mux::Mux;
+use portable_pty::PtySize;
+use rangeset::RangeSet;
+use regex::Regex;
+use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use termwiz::cell::CellAttributes;
+use termwiz::color::AnsiColor;
+use termwiz::hyperlink::Rule;
+use url::Url;
+use wezterm_term::color::ColorPalette;
+use wezterm_term::{Clipboard, KeyCode, KeyModifiers, Line, MouseEvent, StableRowIndex};
+use window::WindowOps;
+
+/// The patterns that are matched in addition to the hyperlink_rules
+/// and the quick_select_patterns from the configuration
+const DEFAULT_PATTERNS: &[&str] = &[
+    // file system paths
+    r"(?:[.\w\-@~]+)?(?:/[.\w\-@]+)+",
+    // git hashes and other hex strings
+    r"\b[0-9a-f]{7,40}\b",
+    // IPv4 addresses
+    r"\b\d{1,3}(?:\.\d{1,3}){3}\b",
+];
+
+/// Overlays short labels on the text in the viewport that matches the
+/// hyperlink rules or the quick select patterns.  Typing a label copies
+/// the text that it labels to the clipboard, or opens it when the last
+/// character of the label is typed with SHIFT held down and the text
+/// is something that can be opened.
+pub struct QuickSelectOverlay {
+    renderer: RefCell<QuickSelectRenderable>,
+    delegate: Rc<dyn Pane>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct QuickSelectMatch {
+    row: StableRowIndex,
+    /// The cells that hold the matched text
+    range: Range<usize>,
+    text: String,
+    /// What to open when the match is selected; this is the expanded
+    /// link for a hyperlink rule, or otherwise the text itself
+    uri: String,
+    label: String,
+}
+
+struct QuickSelectRenderable {
+    delegate: Rc<dyn Pane>,
+    matches: Vec<QuickSelectMatch>,
+    /// The start of the label that the user has typed so far
+    selected: String,
+    dirty_results: RangeSet<StableRowIndex>,
+
+    /// We use this to cancel ourselves later
+    window: ::window::Window,
+}
+
+impl QuickSelectOverlay {
+    pub fn with_pane(term_window: &TermWindow, pane: &Rc<dyn Pane>) -> Rc<dyn Pane> {
+        let config = configuration();
+
+        let mut patterns = vec![];
+        for pattern in DEFAULT_PATTERNS
+            .iter()
+            .copied()
+            .chain(config.quick_select_patterns.iter().map(String::as_str))
+        {
+            match Regex::new(pattern) {
+                Ok(re) => patterns.push(re),
+                Err(err) => log::error!("invalid quick_select_patterns {}: {}", pattern, err),
+            }
+        }
+
+        let dims = pane.get_dimensions();
+        let top = term_window
+            .get_viewport(pane.pane_id())
+            .unwrap_or(dims.physical_top);
+        let (top, lines) = pane.get_lines(top..top + dims.viewport_rows as StableRowIndex);
+        let mut matches = collect_matches(
+            lines
+                .iter()
+                .enumerate()
+                .map(|(idx, line)| (top + idx as StableRowIndex, line)),
+            &config.hyperlink_rules,
+            &patterns,
+        );
+        assign_labels(&mut matches, &config.quick_select_alphabet);
+
+        let mut dirty_results = RangeSet::default();
+        for m in &matches {
+            dirty_results.add(m.row);
+        }
+
+        Rc::new(QuickSelectOverlay {
+            renderer: RefCell::new(QuickSelectRenderable {
+                delegate: Rc::clone(pane),
+                matches,
+                selected: String::new(),
+                dirty_results,
+                window: term_window.window.clone().unwrap(),
+            }),
+            delegate: Rc::clone(pane),
+        })
+    }
+}
+
+impl Pane for QuickSelectOverlay {
+    fn pane_id(&self) -> PaneId {
+        self.delegate.pane_id()
+    }
+
+    fn get_title(&self) -> String {
+        format!("Quick select: {}", self.delegate.get_title())
+    }
+
+    fn send_paste(&self, _text: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn reader(&self) -> anyhow::Result<Box<dyn std::io::Read + Send>> {
+        panic!("do not call reader on QuickSelectOverlay");
+    }
+
+    fn writer(&self) -> RefMut<dyn std::io::Write> {
+        self.delegate.writer()
+    }
+
+    fn resize(&self, size: PtySize) -> anyhow::Result<()> {
+        self.delegate.resize(size)
+    }
+
+    fn key_down(&self, key: KeyCode, mods: KeyModifiers) -> anyhow::Result<()> {
+        match (key, mods) {
+            (KeyCode::Escape, KeyModifiers::NONE)
+            | (KeyCode::Char('c'), KeyModifiers::CTRL)
+            | (KeyCode::Char('g'), KeyModifiers::CTRL) => self.renderer.borrow().close(),
+            (KeyCode::Backspace, KeyModifiers::NONE) => {
+                let mut r = self.renderer.borrow_mut();
+                r.selected.pop();
+                r.mark_all_dirty();
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE) | (KeyCode::Char(c), KeyModifiers::SHIFT) => {
+                let open = mods == KeyModifiers::SHIFT || c.is_uppercase();
+                self.renderer
+                    .borrow_mut()
+                    .select(c.to_ascii_lowercase(), open);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn mouse_event(&self, event: MouseEvent) -> anyhow::Result<()> {
+        self.delegate.mouse_event(event)
+    }
+
+    fn advance_bytes(&self, buf: &[u8]) {
+        self.delegate.advance_bytes(buf)
+    }
+
+    fn is_dead(&self) -> bool {
+        self.delegate.is_dead()
+    }
+
+    fn palette(&self) -> ColorPalette {
+        self.delegate.palette()
+    }
+
+    fn domain_id(&self) -> DomainId {
+        self.delegate.domain_id()
+    }
+
+    fn erase_scrollback(&self) {
+        self.delegate.erase_scrollback()
+    }
+
+    fn is_mouse_grabbed(&self) -> bool {
+        false
+    }
+
+    fn set_clipboard(&self, clipboard: &Arc<dyn Clipboard>) {
+        self.delegate.set_clipboard(clipboard)
+    }
+
+    fn get_current_working_dir(&self) -> Option<Url> {
+        self.delegate.get_current_working_dir()
+    }
+
+    fn get_cursor_position(&self) -> StableCursorPosition {
+        // Hide the cursor so that it doesn't obscure a label
+        StableCursorPosition {
+            visibility: termwiz::surface::CursorVisibility::Hidden,
+            ..self.delegate.get_cursor_position()
+        }
+    }
+
+    fn get_dirty_lines(&self, lines: Range<StableRowIndex>) -> RangeSet<StableRowIndex> {
+        let mut dirty = self.delegate.get_dirty_lines(lines.clone());
+        dirty.add_set(&self.renderer.borrow().dirty_results);
+        dirty.intersection_with_range(lines)
+    }
+
+    fn get_lines(&self, lines: Range<StableRowIndex>) -> (StableRowIndex, Vec<Line>) {
+        let (top, mut lines) = self.delegate.get_lines(lines);
+        let mut renderer = self.renderer.borrow_mut();
+
+        let mut label_attrs = CellAttributes::default();
+        label_attrs.set_reverse(true);
+
+        for (idx, line) in lines.iter_mut().enumerate() {
+            let stable_idx = idx as StableRowIndex + top;
+            renderer.dirty_results.remove(stable_idx);
+
+            for m in renderer
+                .matches
+                .iter()
+                .filter(|m| m.row == stable_idx && m.label.starts_with(&renderer.selected))
+            {
+                for cell_idx in m.range.clone() {
+                    if let Some(cell) = line.cells_mut_for_attr_changes_only().get_mut(cell_idx) {
+                        cell.attrs_mut()
+                            .set_background(AnsiColor::Fuschia)
+                            .set_foreground(AnsiColor::Black)
+                            .set_reverse(false);
+                    }
+                }
+                line.overlay_text_with_attribute(m.range.start, &m.label, label_attrs.clone());
+            }
+        }
+
+        (top, lines)
+    }
+
+    fn get_dimensions(&self) -> RenderableDimensions {
+        self.delegate.get_dimensions()
+    }
+}
+
+impl QuickSelectRenderable {
+    fn close(&self) {
+        TermWindow::schedule_cancel_overlay_for_pane(self.window.clone(), self.delegate.pane_id());
+    }
+
+    fn mark_all_dirty(&mut self) {
+        for m in &self.matches {
+            self.dirty_results.add(m.row);
+        }
+    }
+
+    /// Adds `c` to the label typed so far.  When that completes a label,
+    /// the text that it labels is copied, or opened when `open` is true
+    /// and it can be opened, and the overlay is closed.  Characters that
+    /// don't continue any of the labels are ignored.
+    fn select(&mut self, c: char, open: bool) {
+        let mut selected = self.selected.clone();
+        selected.push(c);
+
+        if let Some(m) = self.matches.iter().find(|m| m.label == selected) {
+            let target = if open {
+                let cwd = self
+                    .delegate
+                    .get_current_working_dir()
+                    .as_ref()
+                    .and_then(working_dir_from_url)
+                    .map(PathBuf::from);
+                open_target(&m.uri, cwd.as_deref())
+            } else {
+                None
+            };
+            if let Some(uri) = target {
+                let pane_id = self.delegate.pane_id();
+                self.window.apply(move |term_window, _window| {
+                    if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
+                        let mux = Mux::get().expect("to be called on main thread");
+                        if let Some(pane) = mux.get_pane(pane_id) {
+                            term_window.open_uri(&pane, uri.clone());
+                        }
+                    }
+                    Ok(())
+                });
+            } else {
+                let text = m.text.clone();
+                self.window.apply(move |_term_window, window| {
+                    window.set_clipboard(::window::Clipboard::Clipboard, text.clone());
+                    Ok(())
+                });
+            }
+            self.close();
+        } else if self.matches.iter().any(|m| m.label.starts_with(&selected)) {
+            self.selected = selected;
+            self.mark_all_dirty();
+        }
+    }
+}
+
+/// Returns what to open for a match whose link is `uri`: the link
+/// itself when it is a URL, or the path of the file that it names when
+/// that exists, resolving relative paths against `cwd`.  Returns None
+/// for text that can't be opened, such as a hash.
+fn open_target(uri: &str, cwd: Option<&Path>) -> Option<String> {
+    if Url::parse(uri).is_ok() {
+        return Some(uri.to_string());
+    }
+    let path = match cwd {
+        Some(cwd) => cwd.join(uri),
+        None => PathBuf::from(uri),
+    };
+    if path.is_absolute() && path.exists() {
+        Some(path.to_string_lossy().into_owned())
+    } else {
+        None
+    }
+}
+
+/// Finds the text in `lines`, which are paired with their stable row
+/// indices, that matches either the hyperlink `rules` or the `patterns`.
+/// Where matches overlap, the one that starts first wins, and of those
+/// that start at the same place, the longest.
+fn collect_matches<'a>(
+    lines: impl Iterator<Item = (StableRowIndex, &'a Line)>,
+    rules: &[Rule],
+    patterns: &[Regex],
+) -> Vec<QuickSelectMatch> {
+    let mut matches = vec![];
+
+    for (row, line) in lines {
+        let mut text = String::new();
+        // The byte offset into text, the index and the width of each cell
+        let mut cells = vec![];
+        for (cell_idx, cell) in line.visible_cells() {
+            cells.push((text.len(), cell_idx, cell.width()));
+            text.push_str(cell.str());
+        }
+
+        let cell_at = |byte_idx: usize| {
+            let idx = match cells.binary_search_by(|c| c.0.cmp(&byte_idx)) {
+                Ok(idx) => idx,
+                Err(idx) => idx - 1,
+            };
+            cells[idx]
+        };
+
+        let mut candidates: Vec<(Range<usize>, String)> = vec![];
+        for m in Rule::match_hyperlinks(&text, rules) {
+            candidates.push((m.range, m.link.uri().to_string()));
+        }
+        for re in patterns {
+            for m in re.find_iter(&text) {
+                candidates.push((m.range(), m.as_str().to_string()));
+            }
+        }
+        candidates.sort_by_key(|(range, _)| (range.start, std::cmp::Reverse(range.end)));
+
+        let mut end = 0;
+        for (range, uri) in candidates {
+            if range.is_empty() || range.start < end {
+                continue;
+            }
+            end = range.end;
+
+            let (_, start_cell, _) = cell_at(range.start);
+            let (_, last_cell, last_width) = cell_at(range.end - 1);
+            matches.push(QuickSelectMatch {
+                row,
+                range: start_cell..last_cell + last_width,
+                text: text[range].to_string(),
+                uri,
+                label: String::new(),
+            });
+        }
+    }
+
+    matches
+}
+
+/// Labels the matches so that identical text shares a label.  The text
+/// nearest to the bottom of the screen, which is usually the most recent
+/// output, gets the shortest labels.
+fn assign_labels(matches: &mut [QuickSelectMatch], alphabet: &str) {
+    let mut texts = vec![];
+    for m in matches.iter().rev() {
+        if !texts.contains(&m.text) {
+            texts.push(m.text.clone());
+        }
+    }
+
+    let labels = compute_labels(texts.len(), alphabet);
+    let labels: HashMap<String, String> = texts.into_iter().zip(labels).collect();
+    for m in matches.iter_mut() {
+        m.label = labels[&m.text].clone();
+    }
+}
+
+/// Computes `num_matches` labels made of the characters in `alphabet`.
+/// The labels are as short as possible, and none of them is a prefix of
+/// another, so that a label is selected as soon as it has been typed.
+/// Upper case is reserved for opening the match, so the labels are
+/// always lower case.
+fn compute_labels(num_matches: usize, alphabet: &str) -> Vec<String> {
+    let mut chars: Vec<char> = vec![];
+    for c in alphabet.chars().map(|c| c.to_ascii_lowercase()) {
+        if !c.is_whitespace() && !chars.contains(&c) {
+            chars.push(c);
+        }
+    }
+    if chars.len() < 2 {
+        log::error!(
+            "quick_select_alphabet {:?} needs at least two distinct characters",
+            alphabet
+        );
+        chars = ('a'..='z').collect();
+    }
+
+    // Each pass replaces the shortest label with the labels that
+    // extend it by each character, until there are enough of them
+    let mut labels = vec![String::new()];
+    let mut offset = 0;
+    while labels.len() - offset < num_matches || offset == 0 {
+        let prefix = labels[offset].clone();
+        offset += 1;
+        for c in &chars {
+            labels.push(format!("{}{}", prefix, c));
+        }
+    }
+
+    labels.drain(offset..).take(num_matches).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn labels_are_short_and_prefix_free() {
+        assert!(compute_labels(0, "abc").is_empty());
+        assert_eq!(compute_labels(3, "abc"), vec!["a", "b", "c"]);
+        assert_eq!(compute_labels(2, "abc"), vec!["a", "b"]);
+        assert_eq!(compute_labels(4, "abc"), vec!["b", "c", "aa", "ab"]);
+        assert_eq!(compute_labels(5, "abc"), vec!["b", "c", "aa", "ab", "ac"]);
+        assert_eq!(
+            compute_labels(6, "abc"),
+            vec!["c", "aa", "ab", "ac", "ba", "bb"]
+        );
+
+        // Upper case and repeated characters are folded together
+        assert_eq!(compute_labels(2, "AaBb"), vec!["a", "b"]);
+
+        for &num in &[1, 26, 27, 100, 700] {
+            let labels = compute_labels(num, "asdfqwerzxcvjklmiuopghtybn");
+            assert_eq!(labels.len(), num);
+            let shortest_possible = match num {
+                0..=26 => 1,
+                27..=676 => 2,
+                _ => 3,
+            };
+            assert!(labels.iter().all(|l| l.len() <= shortest_possible));
+            for (i, a) in labels.iter().enumerate() {
+                for (j, b) in labels.iter().enumerate() {
+                    assert!(
+                        i == j || !b.starts_with(a.as_str()),
+                        "{} is a prefix of {}",
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+    }
+
+    fn default_patterns() -> Vec<Regex> {
+        DEFAULT_PATTERNS
+            .iter()
+            .map(|p| Regex::new(p).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn collect_matches_on_screen() {
+        let attrs = CellAttributes::default();
+        let lines: Vec<Line> = [
+            "$ git log --oneline",
+            "3baafd5 Fix clusters",
+            "see https://wezfurlong.org/wezterm/ for more",
+            "nothing to see here",
+            "edit src/main.rs or ping 10.0.0.1",
+            "中文 /tmp/x",
+        ]
+        .iter()
+        .map(|s| Line::from_text(s, &attrs))
+        .collect();
+        let rules = vec![Rule::new(r"\b\w+://(?:[\w.-]+)\.[a-z]{2,15}\S*\b", "$0").unwrap()];
+
+        let matches = collect_matches(
+            lines
+                .iter()
+                .enumerate()
+                .map(|(idx, line)| (idx as StableRowIndex + 100, line)),
+            &rules,
+            &default_patterns(),
+        );
+        let found: Vec<_> = matches
+            .iter()
+            .map(|m| (m.row, m.range.clone(), m.text.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (101, 0..7, "3baafd5"),
+                (102, 4..34, "https://wezfurlong.org/wezterm"),
+                (104, 5..16, "src/main.rs"),
+                (104, 25..33, "10.0.0.1"),
+                // The wide characters take two cells each
+                (105, 5..11, "/tmp/x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_urls_and_existing_files_are_opened() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+
+        assert_eq!(
+            open_target("https://wezfurlong.org/wezterm/", Some(dir)).as_deref(),
+            Some("https://wezfurlong.org/wezterm/")
+        );
+        assert_eq!(
+            open_target("src/main.rs", Some(dir)),
+            Some(dir.join("src/main.rs").to_string_lossy().into_owned())
+        );
+        let absolute = dir.join("Cargo.toml").to_string_lossy().into_owned();
+        assert_eq!(open_target(&absolute, None), Some(absolute.clone()));
+        assert_eq!(
+            open_target(&absolute, Some(Path::new("/nope"))),
+            Some(absolute)
+        );
+
+        // Hashes, addresses and missing files are copied instead
+        assert_eq!(open_target("3baafd5", Some(dir)), None);
+        assert_eq!(open_target("10.0.0.1", Some(dir)), None);
+        assert_eq!(open_target("src/nope.rs", Some(dir)), None);
+        // A relative path can't be resolved without a working directory
+        assert_eq!(open_target("src/main.rs", None), None);
+    }
+
+    #[test]
+    fn identical_text_shares_a_label() {
+        let attrs = CellAttributes::default();
+        let lines: Vec<Line> = ["cd /tmp/a", "ls /tmp/b", "rm /tmp/a"]
+            .iter()
+            .map(|s| Line::from_text(s, &attrs))
+            .collect();
+        let mut matches = collect_matches(
+            lines
+                .iter()
+                .enumerate()
+                .map(|(idx, line)| (idx as StableRowIndex, line)),
+            &[],
+            &default_patterns(),
+        );
+        assign_labels(&mut matches, "ab");
+        let labels: Vec<_> = matches
+            .iter()
+            .map(|m| (m.text.as_str(), m.label.as_str()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![("/tmp/a", "a"), ("/tmp/b", "b"), ("/tmp/a", "a")]
+        );
+    }
+}
@@ -15,6 +15,10 @@ pub const V_TOP_RIGHT: usize = 1;
 pub const V_BOT_LEFT: usize = 2;
 pub const V_BOT_RIGHT: usize = 3;
 
+/// The number of quads that cover the window padding: one each
+/// for the top, bottom, left and right edges
+pub const PADDING_QUADS: usize = 4;
+
 #[derive(Copy, Clone, Default)]
 pub struct Vertex {
     // Physical position of the corner of the character cell
@@ -30,7 +34,11 @@ pub struct Vertex {
     pub cursor_color: (f32, f32, f32, f32),
     pub bg_color: (f32, f32, f32, f32),
     pub fg_color: (f32, f32, f32, f32),
+    pub underline_color: (f32, f32, f32, f32),
     pub hsv: (f32, f32, f32),
+    // The top and bottom of the region outside of which nothing
+    // is drawn; there is no clipping unless top < bottom
+    pub clip: (f32, f32),
     // We use a float for this because I can't get
     // bool or integer values to work:
     // "bool can't be an in in the vertex shader"
@@ -55,7 +63,9 @@ pub struct Vertex {
     cursor_color,
     bg_color,
     fg_color,
+    underline_color,
     hsv,
+    clip,
     has_color
 );
 
@@ -67,9 +77,38 @@ pub struct Quads {
     pub cols: usize,
     /// row number to vertex index for the first vertex on that row
     pub row_starts: Vec<usize>,
+    /// row number to the y position of the top of that row
+    pub row_tops: Vec<f32>,
+    /// row number to vertex index for the first vertex of a spare
+    /// row that is drawn below that row while a pane that ends there
+    /// is smooth scrolling, and is hidden otherwise
+    pub overflow_row_starts: Vec<usize>,
+    pub cell_height: f32,
     /// The vertex index for the first vertex of the scroll bar thumb
     pub scroll_thumb: usize,
     pub background_image: usize,
+    /// The vertex index for the first of the PADDING_QUADS quads
+    pub padding: usize,
+}
+
+/// How the quads of a row are moved while its pane is smooth scrolling
+#[derive(Debug, Clone, Copy)]
+pub struct RowShift {
+    /// How far to move the row down from its usual position, in pixels
+    pub y_offset: f32,
+    /// The top and bottom of the pane; the parts of the row that
+    /// are moved outside of these aren't drawn
+    pub clip: (f32, f32),
+}
+
+impl RowShift {
+    /// Puts a row back in its usual position, unclipped
+    pub fn restore() -> Self {
+        Self {
+            y_offset: 0.,
+            clip: (0., 0.),
+        }
+    }
 }
 
 pub struct MappedQuads<'a> {
@@ -95,6 +134,71 @@ impl<'a> MappedQuads<'a> {
         })
     }
 
+    /// Like `cell`, but when `shift` is specified the quad is moved
+    /// from its usual position in the grid and clipped to the pane.
+    /// Used to shift rows by a fraction of a cell while smooth scrolling.
+    pub fn cell_with_y_offset<'b>(
+        &'b mut self,
+        x: usize,
+        y: usize,
+        shift: Option<RowShift>,
+    ) -> anyhow::Result<Quad<'b>> {
+        let top = self.row_top(y)?;
+        let cell_height = self.quads.cell_height;
+        let mut quad = self.cell(x, y)?;
+        if let Some(shift) = shift {
+            quad.shift(top + shift.y_offset, cell_height, shift.clip);
+        }
+        Ok(quad)
+    }
+
+    /// Returns the quad in the spare row below row `y`, which shows
+    /// the part of the row after the last row of a pane that scrolls
+    /// into view while the pane is smooth scrolling
+    pub fn overflow_cell<'b>(
+        &'b mut self,
+        x: usize,
+        y: usize,
+        shift: RowShift,
+    ) -> anyhow::Result<Quad<'b>> {
+        if x >= self.quads.cols {
+            anyhow::bail!("column {} is outside of the vertex buffer range", x);
+        }
+        let top = self.row_top(y)? + self.quads.cell_height;
+        let start = self.quads.overflow_row_starts[y] + x * VERTICES_PER_CELL;
+
+        let mut quad = Quad {
+            vert: &mut self.mapping[start..start + VERTICES_PER_CELL],
+        };
+        quad.shift(top + shift.y_offset, self.quads.cell_height, shift.clip);
+        Ok(quad)
+    }
+
+    /// Hides all of the spare rows, which are only shown while
+    /// a pane is smooth scrolling
+    pub fn hide_overflow(&mut self) {
+        for &start in &self.quads.overflow_row_starts {
+            for x in 0..self.quads.cols {
+                let start = start + x * VERTICES_PER_CELL;
+                let mut quad = Quad {
+                    vert: &mut self.mapping[start..start + VERTICES_PER_CELL],
+                };
+                let (left, top, right, _) = quad.get_position();
+                quad.set_position(left, top, right, top);
+                quad.set_texture_adjust(0., 0., 0., 0.);
+            }
+        }
+    }
+
+    /// Returns the y position of the top of row `y`
+    pub fn row_top(&self, y: usize) -> anyhow::Result<f32> {
+        self.quads
+            .row_tops
+            .get(y)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("line {} is outside the vertex buffer range", y))
+    }
+
     pub fn scroll_thumb<'b>(&'b mut self) -> Quad<'b> {
         let start = self.quads.scroll_thumb;
         Quad {
@@ -108,6 +212,15 @@ impl<'a> MappedQuads<'a> {
             vert: &mut self.mapping[start..start + VERTICES_PER_CELL],
         }
     }
+
+    /// Returns the padding quad with the specified index,
+    /// which must be less than PADDING_QUADS
+    pub fn padding<'b>(&'b mut self, idx: usize) -> Quad<'b> {
+        let start = self.quads.padding + idx * VERTICES_PER_CELL;
+        Quad {
+            vert: &mut self.mapping[start..start + VERTICES_PER_CELL],
+        }
+    }
 }
 
 impl Quads {
@@ -178,6 +291,13 @@ impl<'a> Quad<'a> {
         }
     }
 
+    pub fn set_underline_color(&mut self, color: Color) {
+        let color = color.to_tuple_rgba();
+        for v in self.vert.iter_mut() {
+            v.underline_color = color;
+        }
+    }
+
     /// Assign the underline texture coordinates for the cell
     pub fn set_underline(&mut self, coords: TextureRect) {
         self.vert[V_TOP_LEFT].underline = (coords.min_x(), coords.min_y());
@@ -209,7 +329,6 @@ impl<'a> Quad<'a> {
         }
     }
 
-    #[allow(unused)]
     pub fn get_position(&self) -> (f32, f32, f32, f32) {
         let top_left = self.vert[V_TOP_LEFT].position;
         let bottom_right = self.vert[V_BOT_RIGHT].position;
@@ -222,4 +341,19 @@ impl<'a> Quad<'a> {
         self.vert[V_BOT_LEFT].position = (left, bottom);
         self.vert[V_BOT_RIGHT].position = (right, bottom);
     }
+
+    /// Nothing outside of `top` and `bottom` is drawn for this quad,
+    /// unless `top` isn't less than `bottom`
+    pub fn set_clip(&mut self, top: f32, bottom: f32) {
+        for v in self.vert.iter_mut() {
+            v.clip = (top, bottom);
+        }
+    }
+
+    /// Moves the quad vertically so that its top is at `top`
+    fn shift(&mut self, top: f32, height: f32, clip: (f32, f32)) {
+        let (left, _, right, _) = self.get_position();
+        self.set_position(left, top, right, top + height);
+        self.set_clip(clip.0, clip.1);
+    }
 }
@@ -1,6 +1,7 @@
-use super::glyphcache::GlyphCache;
+use super::glyphcache::{GlyphCache, GlyphCacheStats};
 use super::quad::*;
 use super::utilsprites::{RenderMetrics, UtilSprites};
+use crate::stats::HitCounts;
 use ::window::bitmaps::atlas::OutOfTextureSpace;
 use ::window::glium::backend::Context as GliumContext;
 use ::window::glium::texture::SrgbTexture2d;
@@ -28,6 +29,9 @@ pub struct OpenGLRenderState {
     pub glyph_vertex_buffer: RefCell<VertexBuffer<Vertex>>,
     pub glyph_index_buffer: IndexBuffer<u32>,
     pub quads: Quads,
+    /// Set when the texture atlas could not be grown, so that we
+    /// don't keep trying (and failing) on every paint
+    pub atlas_cannot_grow: bool,
 }
 
 impl OpenGLRenderState {
@@ -41,7 +45,7 @@ impl OpenGLRenderState {
     ) -> anyhow::Result<Self> {
         loop {
             let glyph_cache = RefCell::new(GlyphCache::new_gl(&context, fonts, atlas_size)?);
-            let result = UtilSprites::new(&mut *glyph_cache.borrow_mut(), metrics);
+            let result = UtilSprites::new(&mut glyph_cache.borrow_mut().atlas, metrics);
             match result {
                 Ok(util_sprites) => {
                     let mut errors = vec![];
@@ -86,6 +90,7 @@ impl OpenGLRenderState {
                         glyph_vertex_buffer: RefCell::new(glyph_vertex_buffer),
                         glyph_index_buffer,
                         quads,
+                        atlas_cannot_grow: false,
                     });
                 }
                 Err(OutOfTextureSpace { size: Some(size) }) => {
@@ -98,6 +103,12 @@ impl OpenGLRenderState {
         }
     }
 
+    /// Returns the largest width and height of texture that the GPU
+    /// is able to use
+    pub fn max_texture_size(&self) -> usize {
+        self.context.get_capabilities().max_texture_size as usize
+    }
+
     pub fn advise_of_window_size_change(
         &mut self,
         metrics: &RenderMetrics,
@@ -167,6 +178,7 @@ impl OpenGLRenderState {
 
         let mut quads = Quads::default();
         quads.cols = num_cols;
+        quads.cell_height = cell_height;
 
         let mut define_quad = |left, top, right, bottom| -> u32 {
             // Remember starting index for this position
@@ -209,6 +221,19 @@ impl OpenGLRenderState {
         quads.background_image =
             define_quad(width / -2.0, height / -2.0, width / 2.0, height / 2.0) as usize;
 
+        // The padding surrounds the cells; these are drawn over the
+        // background when window_padding_color calls for it
+        let window_left = width / -2.0;
+        let window_top = height / -2.0;
+        let cells_left = window_left + padding_left;
+        let cells_top = window_top + padding_top;
+        let cells_right = cells_left + (num_cols as f32 * cell_width);
+        let cells_bottom = cells_top + (num_rows as f32 * cell_height);
+        quads.padding = define_quad(window_left, window_top, width / 2.0, cells_top) as usize;
+        define_quad(window_left, cells_bottom, width / 2.0, height / 2.0);
+        define_quad(window_left, cells_top, cells_left, cells_bottom);
+        define_quad(cells_right, cells_top, width / 2.0, cells_bottom);
+
         for y in 0..=num_rows {
             let y_pos = (height / -2.0) + (y as f32 * cell_height) + padding_top;
 
@@ -219,6 +244,21 @@ impl OpenGLRenderState {
                 if x == 0 {
                     // build row -> vertex mapping
                     quads.row_starts.push(idx as usize);
+                    quads.row_tops.push(y_pos);
+                }
+            }
+        }
+
+        // The spare rows start out with no height, so that they're hidden
+        for y in 0..=num_rows {
+            let y_pos = (height / -2.0) + ((y + 1) as f32 * cell_height) + padding_top;
+
+            for x in 0..num_cols {
+                let x_pos = (width / -2.0) + (x as f32 * cell_width) + padding_left;
+
+                let idx = define_quad(x_pos, y_pos, x_pos + cell_width, y_pos);
+                if x == 0 {
+                    quads.overflow_row_starts.push(idx as usize);
                 }
             }
         }
@@ -251,7 +291,8 @@ impl RenderState {
             RenderState::GL(gl) => {
                 let mut glyph_cache = gl.glyph_cache.borrow_mut();
                 glyph_cache.clear();
-                gl.util_sprites = UtilSprites::new(&mut glyph_cache, metrics)?;
+                metrics::counter!("gui.atlas.clear", 1);
+                gl.util_sprites = UtilSprites::new(&mut glyph_cache.atlas, metrics)?;
             }
         };
         Ok(())
@@ -267,14 +308,91 @@ impl RenderState {
             RenderState::Software(_) => {}
             RenderState::GL(gl) => {
                 let size = size.unwrap_or_else(|| gl.glyph_cache.borrow().atlas.size());
+                metrics::gauge!("gui.atlas.size", size as i64);
                 let mut glyph_cache = GlyphCache::new_gl(&gl.context, fonts, size)?;
-                gl.util_sprites = UtilSprites::new(&mut glyph_cache, metrics)?;
+                gl.util_sprites = UtilSprites::new(&mut glyph_cache.atlas, metrics)?;
                 *gl.glyph_cache.borrow_mut() = glyph_cache;
+                gl.atlas_cannot_grow = false;
             }
         };
         Ok(())
     }
 
+    /// Grow the texture atlas to the specified size, copying across
+    /// the glyphs that it already holds, rather than having to render
+    /// them all again as `recreate_texture_atlas` would.
+    /// Fails if `size` is larger than the GPU supports.
+    pub fn grow_texture_atlas(&mut self, size: usize) -> anyhow::Result<()> {
+        if let RenderState::GL(gl) = self {
+            let max_size = gl.max_texture_size();
+            if size > max_size {
+                anyhow::bail!(
+                    "cannot grow the texture atlas to {} as the largest \
+                     texture supported by the GPU is {}",
+                    size,
+                    max_size
+                );
+            }
+            let mut glyph_cache = gl.glyph_cache.borrow_mut();
+            metrics::gauge!("gui.atlas.size", size as i64);
+            glyph_cache.grow_gl(&gl.context, size)?;
+            gl.util_sprites = gl.util_sprites.with_texture(&glyph_cache.atlas.texture());
+        }
+        Ok(())
+    }
+
+    /// If the texture atlas is nearly full, grow it to twice its size,
+    /// so that painting the next frame is unlikely to run out of space
+    /// part way through.  The atlas is not grown beyond the largest
+    /// texture that the GPU supports, and once growing it has failed
+    /// it is not attempted again until the atlas is recreated.
+    /// Returns true if the atlas was grown.
+    pub fn grow_texture_atlas_if_nearly_full(&mut self) -> anyhow::Result<bool> {
+        let size = match self {
+            RenderState::GL(gl) => {
+                if gl.atlas_cannot_grow {
+                    return Ok(false);
+                }
+                let glyph_cache = gl.glyph_cache.borrow();
+                if !glyph_cache.atlas_is_nearly_full() {
+                    return Ok(false);
+                }
+                let size = glyph_cache.atlas.size() * 2;
+                if size > gl.max_texture_size() {
+                    gl.atlas_cannot_grow = true;
+                    return Ok(false);
+                }
+                size
+            }
+            RenderState::Software(_) => return Ok(false),
+        };
+        if let Err(err) = self.grow_texture_atlas(size) {
+            if let RenderState::GL(gl) = self {
+                gl.atlas_cannot_grow = true;
+            }
+            return Err(err);
+        }
+        Ok(true)
+    }
+
+    /// Returns the statistics for the glyph cache, or `None` when
+    /// using the software renderer, which has no glyph cache
+    pub fn glyph_cache_stats(&self) -> Option<GlyphCacheStats> {
+        match self {
+            RenderState::Software(_) => None,
+            RenderState::GL(gl) => Some(gl.glyph_cache.borrow().stats()),
+        }
+    }
+
+    /// Returns the glyph cache hit counts accumulated since the
+    /// last call, resetting them to zero
+    pub fn take_glyph_cache_counts(&self) -> HitCounts {
+        match self {
+            RenderState::Software(_) => HitCounts::default(),
+            RenderState::GL(gl) => std::mem::take(&mut gl.glyph_cache.borrow_mut().counts),
+        }
+    }
+
     pub fn advise_of_window_size_change(
         &mut self,
         metrics: &RenderMetrics,
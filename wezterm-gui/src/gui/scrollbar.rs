@@ -22,6 +22,48 @@ pub struct ThumbInfo {
     pub rows: usize,
 }
 
+impl ThumbInfo {
+    /// The smallest height that we'll allow for the thumb, in pixels
+    const MIN_HEIGHT: f32 = 10.;
+
+    /// Compute the geometry of the thumb.
+    /// `scroll_top` is the number of rows that the viewport has been
+    /// scrolled back from the bottom of the scrollback.
+    /// `scrollback_rows` is the total number of rows, including those
+    /// in the viewport, of which `viewport_rows` are visible.
+    /// `track_height` is the height of the scrollbar, in pixels.
+    pub fn compute(
+        scroll_top: StableRowIndex,
+        scrollback_rows: usize,
+        viewport_rows: usize,
+        track_height: usize,
+    ) -> Self {
+        // Until the scrollback fills up, the viewport is the whole
+        // of the scrollable area.
+        let scroll_size = scrollback_rows.max(viewport_rows).max(1) as f32;
+        let track_height = track_height as f32;
+
+        let thumb_size = (viewport_rows as f32 / scroll_size) * track_height;
+
+        let (thumb_size, rows) = if thumb_size < Self::MIN_HEIGHT {
+            let scale = Self::MIN_HEIGHT / thumb_size.max(f32::EPSILON);
+            let rows = viewport_rows as f32 * scale;
+            (Self::MIN_HEIGHT, rows.round() as usize)
+        } else {
+            (thumb_size, viewport_rows)
+        };
+
+        let thumb_top =
+            (1. - (scroll_top + rows as StableRowIndex) as f32 / scroll_size) * track_height;
+
+        Self {
+            top: thumb_top.max(0.).ceil() as usize,
+            height: thumb_size.ceil() as usize,
+            rows,
+        }
+    }
+}
+
 impl ScrollHit {
     /// Given a mouse y value, determine whether the cursor is above, over
     /// or below the thumb.
@@ -57,30 +99,12 @@ impl ScrollHit {
             .physical_top
             .saturating_sub(viewport.unwrap_or(render_dims.physical_top));
 
-        let scroll_size = render_dims.scrollback_rows;
-
-        let thumb_size = (size.rows as f32 / scroll_size as f32) * dims.pixel_height as f32;
-
-        const MIN_HEIGHT: f32 = 10.;
-        let (thumb_size, rows) = if thumb_size < MIN_HEIGHT {
-            let scale = MIN_HEIGHT / thumb_size;
-            let rows = size.rows as f32 * scale;
-            (MIN_HEIGHT, rows as usize)
-        } else {
-            (thumb_size, size.rows as usize)
-        };
-
-        let thumb_top = (1. - (scroll_top + rows as StableRowIndex) as f32 / scroll_size as f32)
-            * size.pixel_height as f32;
-
-        let thumb_size = thumb_size.ceil() as usize;
-        let thumb_top = thumb_top.ceil() as usize;
-
-        ThumbInfo {
-            top: thumb_top,
-            height: thumb_size,
-            rows,
-        }
+        ThumbInfo::compute(
+            scroll_top,
+            render_dims.scrollback_rows,
+            size.rows as usize,
+            dims.pixel_height,
+        )
     }
 
     /// Given a new thumb top coordinate (produced by dragging the thumb),
@@ -102,3 +126,48 @@ impl ScrollHit {
             .saturating_add(rows_from_top as StableRowIndex)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn thumb_without_scrollback() {
+        // The thumb fills the track when there is nothing to scroll
+        let info = ThumbInfo::compute(0, 24, 24, 480);
+        assert_eq!((info.top, info.height, info.rows), (0, 480, 24));
+
+        // and also before the scrollback has filled the viewport
+        let info = ThumbInfo::compute(0, 10, 24, 480);
+        assert_eq!((info.top, info.height, info.rows), (0, 480, 24));
+    }
+
+    #[test]
+    fn thumb_size_and_position() {
+        // The viewport is a tenth of the scrollback, so the thumb
+        // is a tenth of the height of the track
+        let info = ThumbInfo::compute(0, 240, 24, 480);
+        assert_eq!((info.top, info.height, info.rows), (432, 48, 24));
+
+        // Scrolled back by half of the scrollback
+        let info = ThumbInfo::compute(120, 240, 24, 480);
+        assert_eq!((info.top, info.height), (192, 48));
+
+        // Scrolled all the way to the top
+        let info = ThumbInfo::compute(216, 240, 24, 480);
+        assert_eq!((info.top, info.height), (0, 48));
+    }
+
+    #[test]
+    fn thumb_has_minimum_height() {
+        let info = ThumbInfo::compute(0, 24_000, 24, 480);
+        assert_eq!(info.height, 10);
+        // The thumb stands in for more rows than are in the viewport
+        assert_eq!(info.rows, 500);
+        // and still sits at the bottom of the track
+        assert_eq!(info.top + info.height, 480);
+
+        let info = ThumbInfo::compute(24_000 - 500, 24_000, 24, 480);
+        assert_eq!(info.top, 0);
+    }
+}
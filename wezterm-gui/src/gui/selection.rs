@@ -1,10 +1,16 @@
 // The range_plus_one lint can't see when the LHS is not compatible with
 // and inclusive range
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
+use config::configuration;
 use mux::pane::Pane;
 use std::cmp::Ordering;
+use std::fmt::Write;
 use std::ops::Range;
-use termwiz::surface::line::DoubleClickRange;
+use termwiz::cell::{Blink, Cell, CellAttributes, Intensity, Underline};
+use termwiz::color::{ColorAttribute, ColorSpec};
+use termwiz::escape::csi::{Sgr, CSI};
+use termwiz::surface::line::{DoubleClickRange, Line};
+use wezterm_term::color::ColorPalette;
 use wezterm_term::{SemanticZone, StableRowIndex};
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
@@ -16,7 +22,7 @@ pub struct Selection {
     pub range: Option<SelectionRange>,
 }
 
-pub use config::keyassignment::SelectionMode;
+pub use config::keyassignment::{CopyFormat, SelectionMode};
 
 impl Selection {
     #[allow(dead_code)]
@@ -51,14 +57,11 @@ pub struct SelectionRange {
     pub end: SelectionCoordinate,
 }
 
-// TODO: expose is_double_click_word in config file
-fn is_double_click_word(s: &str) -> bool {
-    match s.len() {
-        1 => match s.chars().nth(0).unwrap() {
-            ' ' | '\t' | '\n' | '{' | '[' | '}' | ']' | '(' | ')' | '"' | '\'' => false,
-            _ => true,
-        },
-        0 => false,
+fn is_double_click_word(s: &str, word_boundary: &str) -> bool {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => !word_boundary.contains(c),
+        (None, _) => false,
         _ => true,
     }
 }
@@ -129,12 +132,14 @@ impl SelectionRange {
 
     /// Computes the selection range for the word around the specified coords
     pub fn word_around(start: SelectionCoordinate, pane: &dyn Pane) -> Self {
+        let word_boundary = configuration().selection_word_boundary.clone();
+        let is_word = |s: &str| is_double_click_word(s, &word_boundary);
         let (first, lines) = pane.get_lines(start.y..start.y + 1);
 
         // TODO: if selection_range.start.x == 0, search backwards for wrapping
         // lines too.
 
-        match lines[0].compute_double_click_range(start.x, is_double_click_word) {
+        match lines[0].compute_double_click_range(start.x, is_word) {
             DoubleClickRange::Range(click_range) => Self {
                 start: SelectionCoordinate {
                     x: click_range.start,
@@ -161,7 +166,7 @@ impl SelectionRange {
                     if first != y_cont {
                         break;
                     }
-                    match lines[0].compute_double_click_range(0, is_double_click_word) {
+                    match lines[0].compute_double_click_range(0, &is_word) {
                         DoubleClickRange::Range(range_end) => {
                             if range_end.end > range_end.start {
                                 end_coord = SelectionCoordinate {
@@ -262,3 +267,417 @@ impl SelectionRange {
         }
     }
 }
+
+/// The selected cells of a single line
+#[derive(Debug, Clone)]
+pub struct SelectedLine {
+    /// The selected cells, excluding those hidden by a preceding
+    /// double-width cell
+    pub cells: Vec<Cell>,
+    /// true if the line wraps onto the next one, so that the text
+    /// continues without a line break
+    pub wrapped: bool,
+}
+
+impl SelectedLine {
+    /// Returns the cells to format, without the trailing whitespace
+    /// if `trim` is true
+    fn cells(&self, trim: bool) -> &[Cell] {
+        if !trim {
+            return &self.cells;
+        }
+        let len = self
+            .cells
+            .iter()
+            .rposition(|cell| !cell.str().trim().is_empty())
+            .map_or(0, |idx| idx + 1);
+        &self.cells[..len]
+    }
+}
+
+/// Extracts the selected cells from `lines`, which hold the rows
+/// of `range` starting with `first_row`.
+pub fn selected_lines(
+    range: &SelectionRange,
+    first_row: StableRowIndex,
+    lines: &[Line],
+) -> Vec<SelectedLine> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(idx, line)| {
+            let cols = range.cols_for_row(first_row + idx as StableRowIndex);
+            let last_col_idx = cols.end.min(line.cells().len()).saturating_sub(1);
+            let cells = line
+                .visible_cells()
+                .filter(|(n, _)| cols.contains(n))
+                .map(|(_, cell)| cell.clone())
+                .collect();
+            // TODO: should really test for any unicode whitespace
+            let wrapped = matches!(
+                line.cells().get(last_col_idx),
+                Some(cell) if cell.attrs().wrapped() && cell.str() != " "
+            );
+            SelectedLine { cells, wrapped }
+        })
+        .collect()
+}
+
+/// Formats the selected lines for the clipboard
+pub fn format_selection(
+    lines: &[SelectedLine],
+    format: CopyFormat,
+    palette: &ColorPalette,
+) -> String {
+    format_lines(lines, format, true, palette)
+}
+
+/// Formats whole lines, such as those of the screen and its scrollback,
+/// as one line of text per row.  The trailing whitespace of each row is
+/// removed if `trim` is true.
+pub fn format_screen(
+    lines: &[Line],
+    format: CopyFormat,
+    trim: bool,
+    palette: &ColorPalette,
+) -> String {
+    let lines: Vec<SelectedLine> = lines
+        .iter()
+        .map(|line| SelectedLine {
+            cells: line.visible_cells().map(|(_, cell)| cell.clone()).collect(),
+            wrapped: false,
+        })
+        .collect();
+    format_lines(&lines, format, trim, palette)
+}
+
+fn format_lines(
+    lines: &[SelectedLine],
+    format: CopyFormat,
+    trim: bool,
+    palette: &ColorPalette,
+) -> String {
+    match format {
+        CopyFormat::Text => join_lines(lines, |line, s| {
+            for cell in line.cells(trim) {
+                s.push_str(cell.str());
+            }
+        }),
+        CopyFormat::Ansi => join_lines(lines, |line, s| append_ansi_line(line, trim, s)),
+        CopyFormat::Html => format!(
+            "<pre>{}</pre>",
+            join_lines(lines, |line, s| append_html_line(line, trim, palette, s))
+        ),
+    }
+}
+
+/// Joins the lines with line breaks, except where a line wrapped
+/// onto the next one
+fn join_lines<F: FnMut(&SelectedLine, &mut String)>(
+    lines: &[SelectedLine],
+    mut append: F,
+) -> String {
+    let mut s = String::new();
+    let mut last_was_wrapped = false;
+    for line in lines {
+        if !s.is_empty() && !last_was_wrapped {
+            s.push('\n');
+        }
+        append(line, &mut s);
+        last_was_wrapped = line.wrapped;
+    }
+    s
+}
+
+fn color_spec(color: ColorAttribute) -> ColorSpec {
+    match color {
+        ColorAttribute::TrueColorWithPaletteFallback(rgb, _)
+        | ColorAttribute::TrueColorWithDefaultFallback(rgb) => ColorSpec::TrueColor(rgb),
+        ColorAttribute::PaletteIndex(idx) => ColorSpec::PaletteIndex(idx),
+        ColorAttribute::Default => ColorSpec::Default,
+    }
+}
+
+/// Returns the escape sequences that reset the rendition and then
+/// apply `attrs`
+fn ansi_rendition(attrs: &CellAttributes) -> String {
+    let mut sgr = vec![Sgr::Reset];
+    if attrs.intensity() != Intensity::Normal {
+        sgr.push(Sgr::Intensity(attrs.intensity()));
+    }
+    if attrs.underline() != Underline::None {
+        sgr.push(Sgr::Underline(attrs.underline()));
+    }
+    if attrs.blink() != Blink::None {
+        sgr.push(Sgr::Blink(attrs.blink()));
+    }
+    if attrs.italic() {
+        sgr.push(Sgr::Italic(true));
+    }
+    if attrs.reverse() {
+        sgr.push(Sgr::Inverse(true));
+    }
+    if attrs.invisible() {
+        sgr.push(Sgr::Invisible(true));
+    }
+    if attrs.strikethrough() {
+        sgr.push(Sgr::StrikeThrough(true));
+    }
+    if attrs.overline() {
+        sgr.push(Sgr::Overline(true));
+    }
+    if attrs.foreground != ColorAttribute::Default {
+        sgr.push(Sgr::Foreground(color_spec(attrs.foreground)));
+    }
+    if attrs.background != ColorAttribute::Default {
+        sgr.push(Sgr::Background(color_spec(attrs.background)));
+    }
+    if attrs.underline_color() != ColorAttribute::Default {
+        sgr.push(Sgr::UnderlineColor(color_spec(attrs.underline_color())));
+    }
+    sgr.into_iter()
+        .map(|sgr| CSI::Sgr(sgr).to_string())
+        .collect()
+}
+
+fn append_ansi_line(line: &SelectedLine, trim: bool, s: &mut String) {
+    let default_rendition = ansi_rendition(&CellAttributes::default());
+    let mut rendition = default_rendition.clone();
+    for cell in line.cells(trim) {
+        let cell_rendition = ansi_rendition(cell.attrs());
+        if cell_rendition != rendition {
+            s.push_str(&cell_rendition);
+            rendition = cell_rendition;
+        }
+        s.push_str(cell.str());
+    }
+    // Don't let the colors bleed into the next line
+    if rendition != default_rendition {
+        s.push_str(&default_rendition);
+    }
+}
+
+/// Returns the inline css that applies `attrs`
+fn html_style(attrs: &CellAttributes, palette: &ColorPalette) -> String {
+    let mut fg = palette.resolve_fg(attrs.foreground);
+    let mut bg = palette.resolve_bg(attrs.background);
+    if attrs.reverse() {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    let mut style = format!(
+        "color:{};background-color:{}",
+        fg.to_rgb_string(),
+        bg.to_rgb_string()
+    );
+    match attrs.intensity() {
+        Intensity::Bold => style.push_str(";font-weight:bold"),
+        Intensity::Half => style.push_str(";opacity:0.5"),
+        Intensity::Normal => {}
+    }
+    if attrs.italic() {
+        style.push_str(";font-style:italic");
+    }
+    if attrs.invisible() {
+        style.push_str(";visibility:hidden");
+    }
+    let decorations: Vec<&str> = [
+        (attrs.underline() != Underline::None, "underline"),
+        (attrs.strikethrough(), "line-through"),
+        (attrs.overline(), "overline"),
+    ]
+    .iter()
+    .filter(|(enabled, _)| *enabled)
+    .map(|(_, decoration)| *decoration)
+    .collect();
+    if !decorations.is_empty() {
+        write!(style, ";text-decoration:{}", decorations.join(" ")).ok();
+    }
+    style
+}
+
+fn append_html_line(line: &SelectedLine, trim: bool, palette: &ColorPalette, s: &mut String) {
+    let mut style: Option<String> = None;
+    for cell in line.cells(trim) {
+        let cell_style = html_style(cell.attrs(), palette);
+        if style.as_ref() != Some(&cell_style) {
+            if style.is_some() {
+                s.push_str("</span>");
+            }
+            write!(s, "<span style=\"{}\">", cell_style).ok();
+            style = Some(cell_style);
+        }
+        for c in cell.str().chars() {
+            match c {
+                '&' => s.push_str("&amp;"),
+                '<' => s.push_str("&lt;"),
+                '>' => s.push_str("&gt;"),
+                '"' => s.push_str("&quot;"),
+                c => s.push(c),
+            }
+        }
+    }
+    if style.is_some() {
+        s.push_str("</span>");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::color::AnsiColor;
+
+    fn word_at(text: &str, col: usize, word_boundary: &str) -> String {
+        let line = Line::from_text(text, &CellAttributes::default());
+        match line.compute_double_click_range(col, |s| is_double_click_word(s, word_boundary)) {
+            DoubleClickRange::Range(r) | DoubleClickRange::RangeWithWrap(r) => {
+                line.columns_as_str(r)
+            }
+        }
+    }
+
+    #[test]
+    fn word_boundary() {
+        let boundary = " \t\n{}[]()\"'";
+        let text = "ls -l (/usr/bin/env) 'quoted'";
+        assert_eq!(word_at(text, 0, boundary), "ls");
+        assert_eq!(word_at(text, 1, boundary), "ls");
+        assert_eq!(word_at(text, 4, boundary), "-l");
+        assert_eq!(word_at(text, 10, boundary), "/usr/bin/env");
+        assert_eq!(word_at(text, 23, boundary), "quoted");
+        // Clicking on a boundary character selects nothing
+        assert_eq!(word_at(text, 2, boundary), "");
+        assert_eq!(word_at(text, 6, boundary), "");
+
+        // A custom boundary splits the path into its components
+        let boundary = " /()";
+        assert_eq!(word_at(text, 10, boundary), "usr");
+        assert_eq!(word_at(text, 13, boundary), "bin");
+        assert_eq!(word_at(text, 22, boundary), "'quoted'");
+    }
+
+    fn select_all(lines: &[Line]) -> Vec<SelectedLine> {
+        let range = SelectionRange {
+            start: SelectionCoordinate { x: 0, y: 0 },
+            end: SelectionCoordinate {
+                x: usize::MAX,
+                y: lines.len() as StableRowIndex - 1,
+            },
+        };
+        selected_lines(&range, 0, lines)
+    }
+
+    #[test]
+    fn plain_text_selection() {
+        let palette = ColorPalette::default();
+        let attrs = CellAttributes::default();
+        let lines = [
+            Line::from_text("hi   ", &attrs),
+            Line::from_text_with_wrapped_last_col("wrapp", &attrs),
+            Line::from_text("ed  ", &attrs),
+        ];
+        let selected = select_all(&lines);
+        assert_eq!(
+            format_selection(&selected, CopyFormat::Text, &palette),
+            "hi\nwrapped"
+        );
+
+        // Only the selected columns are included
+        let range = SelectionRange {
+            start: SelectionCoordinate { x: 1, y: 0 },
+            end: SelectionCoordinate { x: 2, y: 1 },
+        };
+        let selected = selected_lines(&range, 0, &lines);
+        assert_eq!(
+            format_selection(&selected, CopyFormat::Text, &palette),
+            "i\nwra"
+        );
+    }
+
+    #[test]
+    fn ansi_selection_reproduces_colors() {
+        let palette = ColorPalette::default();
+        let mut line = Line::from_text("abc  ", &CellAttributes::default());
+        line.cells_mut()[0]
+            .attrs_mut()
+            .set_foreground(AnsiColor::Maroon);
+        line.cells_mut()[1]
+            .attrs_mut()
+            .set_background(AnsiColor::Navy)
+            .set_intensity(Intensity::Bold);
+        let lines = [line.clone(), line];
+        let selected = select_all(&lines);
+
+        let line = "\x1b[0m\x1b[31ma\x1b[0m\x1b[1m\x1b[44mb\x1b[0mc";
+        assert_eq!(
+            format_selection(&selected, CopyFormat::Ansi, &palette),
+            format!("{}\n{}", line, line)
+        );
+        // The plain text is unchanged by the colors
+        assert_eq!(
+            format_selection(&selected, CopyFormat::Text, &palette),
+            "abc\nabc"
+        );
+    }
+
+    #[test]
+    fn html_selection_escapes_text() {
+        let palette = ColorPalette::default();
+        let mut line = Line::from_text("<a&b>", &CellAttributes::default());
+        line.cells_mut()[4]
+            .attrs_mut()
+            .set_foreground(AnsiColor::Maroon);
+        let selected = select_all(&[line]);
+
+        let default_style = html_style(&CellAttributes::default(), &palette);
+        let mut red = CellAttributes::default();
+        red.set_foreground(AnsiColor::Maroon);
+        let red_style = html_style(&red, &palette);
+        assert_ne!(default_style, red_style);
+        assert_eq!(
+            format_selection(&selected, CopyFormat::Html, &palette),
+            format!(
+                "<pre><span style=\"{}\">&lt;a&amp;b</span><span style=\"{}\">&gt;</span></pre>",
+                default_style, red_style
+            )
+        );
+    }
+
+    #[test]
+    fn screen_dump() {
+        let palette = ColorPalette::default();
+        let attrs = CellAttributes::default();
+        let mut red = Line::from_text("red  ", &attrs);
+        for cell in red.cells_mut() {
+            cell.attrs_mut().set_background(AnsiColor::Maroon);
+        }
+        let lines = [
+            Line::from_text("$ ls   ", &attrs),
+            Line::from_text_with_wrapped_last_col("wrapp", &attrs),
+            Line::from_text("ed", &attrs),
+            Line::from_text("     ", &attrs),
+            red,
+        ];
+
+        // Each row is dumped on a line of its own, even when it wrapped
+        assert_eq!(
+            format_screen(&lines, CopyFormat::Text, true, &palette),
+            "$ ls\nwrapp\ned\n\nred"
+        );
+        assert_eq!(
+            format_screen(&lines, CopyFormat::Text, false, &palette),
+            "$ ls   \nwrapp\ned\n     \nred  "
+        );
+
+        let reset = "\x1b[0m";
+        let red = format!("{}\x1b[41m", reset);
+        assert_eq!(
+            format_screen(&lines[4..], CopyFormat::Ansi, true, &palette),
+            format!("{}red{}", red, reset)
+        );
+        // The colored trailing whitespace is kept along with its color
+        assert_eq!(
+            format_screen(&lines[4..], CopyFormat::Ansi, false, &palette),
+            format!("{}red  {}", red, reset)
+        );
+    }
+}
@@ -1,4 +1,30 @@
+use crate::stats::HitCounts;
+use anyhow::anyhow;
 use config::TextStyle;
+use lru::LruCache;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wezterm_font::shaper::GlyphInfo;
+
+/// Maps a run of text in a particular style to the glyphs that
+/// the shaper produced for it
+pub struct ShapeCache {
+    cache: LruCache<ShapeCacheKey, anyhow::Result<Rc<Vec<GlyphInfo>>>>,
+    pub counts: HitCounts,
+}
+
+impl ShapeCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            cache: LruCache::new(capacity),
+            counts: HitCounts::default(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+}
 
 #[derive(PartialEq, Eq, Hash)]
 pub struct ShapeCacheKey {
@@ -71,3 +97,104 @@ impl<'a> std::hash::Hash for (dyn ShapeCacheKeyTrait + 'a) {
         self.key().hash(state)
     }
 }
+
+/// Returns the shaped glyphs for `key`, consulting the cache first so
+/// that unchanged text isn't re-shaped on every paint.  On a miss,
+/// `shape` is called and its result recorded; errors are recorded too,
+/// so that text that fails to shape isn't retried each time.
+pub fn cached_shape<F>(
+    cache: &RefCell<ShapeCache>,
+    key: BorrowedShapeCacheKey,
+    shape: F,
+) -> anyhow::Result<Rc<Vec<GlyphInfo>>>
+where
+    F: FnOnce() -> anyhow::Result<Vec<GlyphInfo>>,
+{
+    let lookup: &dyn ShapeCacheKeyTrait = &key;
+    {
+        let mut cache = cache.borrow_mut();
+        let result = match cache.cache.get(lookup) {
+            Some(Ok(info)) => Some(Ok(Rc::clone(info))),
+            Some(Err(err)) => Some(Err(anyhow!("cached shaper error: {}", err))),
+            None => None,
+        };
+        match result {
+            Some(result) => {
+                cache.counts.hits += 1;
+                return result;
+            }
+            None => cache.counts.misses += 1,
+        }
+    }
+
+    match shape() {
+        Ok(info) => {
+            let info = Rc::new(info);
+            cache
+                .borrow_mut()
+                .cache
+                .put(key.to_owned(), Ok(Rc::clone(&info)));
+            Ok(info)
+        }
+        Err(err) => {
+            let res = anyhow!("shaper error: {}", err);
+            cache.borrow_mut().cache.put(key.to_owned(), Err(err));
+            Err(res)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn shaping_is_cached() {
+        let cache = RefCell::new(ShapeCache::new(16));
+        let style = TextStyle::default();
+        let calls = Cell::new(0);
+        let shape = || {
+            calls.set(calls.get() + 1);
+            Ok(vec![])
+        };
+
+        let key = BorrowedShapeCacheKey {
+            style: &style,
+            text: "hello",
+        };
+        cached_shape(&cache, key, shape).unwrap();
+        assert_eq!(calls.get(), 1);
+
+        // The second time around the shaper isn't invoked
+        cached_shape(&cache, key, shape).unwrap();
+        assert_eq!(calls.get(), 1);
+        assert_eq!(cache.borrow().counts, HitCounts { hits: 1, misses: 1 });
+
+        // Different text is shaped separately
+        let other = BorrowedShapeCacheKey {
+            style: &style,
+            text: "world",
+        };
+        cached_shape(&cache, other, shape).unwrap();
+        assert_eq!(calls.get(), 2);
+
+        // Errors are remembered as well
+        let failing = BorrowedShapeCacheKey {
+            style: &style,
+            text: "oops",
+        };
+        let fail = || {
+            calls.set(calls.get() + 1);
+            Err(anyhow!("no glyphs"))
+        };
+        assert!(cached_shape(&cache, failing, fail).is_err());
+        assert!(cached_shape(&cache, failing, fail).is_err());
+        assert_eq!(calls.get(), 3);
+
+        // Reloading the config clears the cache
+        cache.borrow_mut().clear();
+        cached_shape(&cache, key, shape).unwrap();
+        assert_eq!(calls.get(), 4);
+    }
+}
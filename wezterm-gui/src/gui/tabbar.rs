@@ -17,9 +17,51 @@ pub struct TabBarState {
 pub enum TabBarItem {
     None,
     Tab(usize),
+    CloseTab(usize),
     NewTabButton,
 }
 
+/// Maps between rows of the window and rows of the terminal,
+/// taking the position of the tab bar into account
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TabBarRows {
+    /// The window row that holds the tab bar, if it is shown
+    pub tab_bar_row: Option<usize>,
+    /// The number of window rows above the first terminal row
+    pub first_line_offset: usize,
+}
+
+impl TabBarRows {
+    pub fn new(show_tab_bar: bool, tab_bar_at_bottom: bool, terminal_rows: usize) -> Self {
+        match (show_tab_bar, tab_bar_at_bottom) {
+            (false, _) => Self {
+                tab_bar_row: None,
+                first_line_offset: 0,
+            },
+            (true, false) => Self {
+                tab_bar_row: Some(0),
+                first_line_offset: 1,
+            },
+            (true, true) => Self {
+                tab_bar_row: Some(terminal_rows),
+                first_line_offset: 0,
+            },
+        }
+    }
+
+    /// Returns true if the window row is the one holding the tab bar
+    pub fn is_tab_bar_row(&self, window_row: i64) -> bool {
+        self.tab_bar_row.map(|row| row as i64) == Some(window_row)
+    }
+
+    /// Converts a window row into a row relative to the top of the terminal
+    pub fn terminal_row(&self, window_row: i64) -> i64 {
+        window_row
+            .saturating_sub(self.first_line_offset as i64)
+            .max(0)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 struct TabEntry {
     item: TabBarItem,
@@ -55,9 +97,6 @@ impl TabBarState {
         // Where the `+` sign will spawn a new tab (or show a context
         // menu with tab creation options) and the other three chars
         // are symbols representing minimize, maximize and close.
-        let per_tab_overhead = 2;
-        let system_overhead = 3;
-
         let tab_titles: Vec<String> = window
             .iter()
             .enumerate()
@@ -88,6 +127,31 @@ impl TabBarState {
                 }
             })
             .collect();
+
+        Self::from_titles(
+            title_width,
+            mouse_x,
+            &tab_titles,
+            window.get_active_idx(),
+            colors,
+            config.tab_max_width,
+        )
+    }
+
+    /// Lay out the tab bar for the specified tab titles
+    fn from_titles(
+        title_width: usize,
+        mouse_x: Option<usize>,
+        tab_titles: &[String],
+        active_tab_no: usize,
+        colors: Option<&TabBarColors>,
+        tab_max_width: usize,
+    ) -> Self {
+        // Each tab has a space either side of its title, followed
+        // by the close button and another space
+        let per_tab_overhead = 4;
+        let system_overhead = 3;
+
         let titles_len: usize = tab_titles.iter().map(|s| unicode_column_width(s)).sum();
         let number_of_tabs = tab_titles.len();
 
@@ -100,13 +164,12 @@ impl TabBarState {
             // We need to clamp the length to balance them out
             available_cells / number_of_tabs
         }
-        .min(config.tab_max_width);
+        .min(tab_max_width);
 
         let colors = colors.cloned().unwrap_or_else(TabBarColors::default);
 
         let mut line = Line::with_width(title_width);
 
-        let active_tab_no = window.get_active_idx();
         let mut x = 0;
         let mut items = vec![];
 
@@ -140,6 +203,19 @@ impl TabBarState {
                 x += 1;
             }
 
+            line.set_cell(x, Cell::new(' ', cell_attrs.clone()));
+            x += 1;
+
+            // The close button has its own hit region, which must be
+            // tested ahead of the rest of the tab
+            items.push(TabEntry {
+                item: TabBarItem::CloseTab(tab_idx),
+                x,
+                width: 1,
+            });
+            line.set_cell(x, Cell::new('x', cell_attrs.clone()));
+            x += 1;
+
             line.set_cell(x, Cell::new(' ', cell_attrs));
             x += 1;
 
@@ -197,3 +273,59 @@ impl TabBarState {
         TabBarItem::None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tab_bar(titles: &[&str]) -> TabBarState {
+        let titles: Vec<String> = titles.iter().map(|s| s.to_string()).collect();
+        TabBarState::from_titles(80, None, &titles, 0, None, 16)
+    }
+
+    #[test]
+    fn close_button_hit_test() {
+        let bar = tab_bar(&["one  ", "two  "]);
+        assert_eq!(bar.line().as_str().trim_end(), " one   x  two   x   +");
+
+        // The body of each tab activates it
+        assert_eq!(bar.hit_test(0), TabBarItem::Tab(0));
+        assert_eq!(bar.hit_test(3), TabBarItem::Tab(0));
+        assert_eq!(bar.hit_test(6), TabBarItem::Tab(0));
+        assert_eq!(bar.hit_test(8), TabBarItem::Tab(0));
+        assert_eq!(bar.hit_test(9), TabBarItem::Tab(1));
+        assert_eq!(bar.hit_test(17), TabBarItem::Tab(1));
+
+        // Only the x closes it
+        assert_eq!(bar.hit_test(7), TabBarItem::CloseTab(0));
+        assert_eq!(bar.hit_test(16), TabBarItem::CloseTab(1));
+
+        assert_eq!(bar.hit_test(19), TabBarItem::NewTabButton);
+        assert_eq!(bar.hit_test(30), TabBarItem::None);
+    }
+
+    #[test]
+    fn tab_bar_rows() {
+        // A window 25 rows tall, 24 of which are used by the terminal
+        // when the tab bar is shown
+        let rows = TabBarRows::new(true, false, 24);
+        assert!(rows.is_tab_bar_row(0));
+        assert!(!rows.is_tab_bar_row(24));
+        assert_eq!(rows.terminal_row(1), 0);
+        assert_eq!(rows.terminal_row(24), 23);
+
+        let rows = TabBarRows::new(true, true, 24);
+        assert!(!rows.is_tab_bar_row(0));
+        assert!(rows.is_tab_bar_row(24));
+        assert_eq!(rows.terminal_row(0), 0);
+        assert_eq!(rows.terminal_row(23), 23);
+
+        for &at_bottom in &[false, true] {
+            let rows = TabBarRows::new(false, at_bottom, 25);
+            assert_eq!(rows.tab_bar_row, None);
+            assert!(!rows.is_tab_bar_row(0));
+            assert_eq!(rows.terminal_row(0), 0);
+            assert_eq!(rows.terminal_row(24), 24);
+        }
+    }
+}
@@ -1,15 +1,18 @@
 #![cfg_attr(feature = "cargo-clippy", allow(clippy::range_plus_one))]
+use super::customglyph::is_custom_glyph;
 use super::quad::*;
 use super::renderstate::*;
 use super::utilsprites::RenderMetrics;
 use crate::gui::overlay::{
-    confirm_close_pane, confirm_close_tab, launcher, start_overlay, start_overlay_pane,
-    tab_navigator, CopyOverlay, SearchOverlay,
+    confirm_close_pane, confirm_close_tab, confirm_paste, launcher, paste_needs_confirmation,
+    start_overlay, start_overlay_pane, tab_navigator, CopyOverlay, QuickSelectOverlay,
+    SearchOverlay,
 };
 use crate::gui::scrollbar::*;
 use crate::gui::selection::*;
 use crate::gui::shapecache::*;
-use crate::gui::tabbar::{TabBarItem, TabBarState};
+use crate::gui::tabbar::{TabBarItem, TabBarRows, TabBarState};
+use crate::gui::windowstate::WindowState;
 use crate::scripting::guiwin::GuiWin;
 use crate::scripting::pane::PaneObject;
 use ::wezterm_term::input::MouseButton as TMB;
@@ -27,8 +30,9 @@ use anyhow::{anyhow, bail, ensure};
 use config::keyassignment::{
     InputMap, KeyAssignment, MouseEventTrigger, SpawnCommand, SpawnTabDomain,
 };
-use config::{configuration, ConfigHandle};
-use lru::LruCache;
+use config::{
+    configuration, Config, ConfigHandle, FontAntiAliasing, TextStyle, WindowPaddingColor,
+};
 use mux::activity::Activity;
 use mux::domain::{DomainId, DomainState};
 use mux::pane::{Pane, PaneId};
@@ -36,33 +40,51 @@ use mux::renderable::{RenderableDimensions, StableCursorPosition};
 use mux::tab::{PositionedPane, PositionedSplit, SplitDirection, TabId};
 use mux::window::WindowId as MuxWindowId;
 use mux::Mux;
+use percent_encoding::percent_decode_str;
 use portable_pty::{CommandBuilder, PtySize};
+use rangeset::RangeSet;
 use std::any::Any;
 use std::cell::{RefCell, RefMut};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::ops::{Add, Range, Sub};
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 use termwiz::color::{ColorAttribute, RgbColor};
 use termwiz::hyperlink::Hyperlink;
 use termwiz::image::ImageData;
-use termwiz::surface::{CursorShape, CursorVisibility};
+use termwiz::surface::{CursorShape, CursorVisibility, LineSize};
+use url::Url;
 use wezterm_font::shaper::GlyphInfo;
 use wezterm_font::units::*;
 use wezterm_font::FontConfiguration;
 use wezterm_term::color::ColorPalette;
 use wezterm_term::input::LastMouseClick;
-use wezterm_term::{CellAttributes, Line, StableRowIndex, TerminalConfiguration};
+use wezterm_term::{
+    Blink, CellAttributes, ClipboardSelection, Line, StableRowIndex, TerminalConfiguration,
+};
 
 const ATLAS_SIZE: usize = 128;
+/// The largest number of rows or columns that a pty is resized to
+const MAX_PTY_ROWS_AND_COLS: u16 = 4096;
+/// How long a smooth scroll takes to reach its destination
+const SMOOTH_SCROLL_DURATION: Duration = Duration::from_millis(150);
+/// How long to wait for the window to reach the size requested when
+/// the scale changed before giving up on it
+const SCALE_RESIZE_TIMEOUT: Duration = Duration::from_secs(1);
 
 lazy_static::lazy_static! {
     static ref WINDOW_CLASS: Mutex<String> = Mutex::new("org.wezfurlong.wezterm".to_owned());
 }
 
+/// The saved window state is only applied to the first window
+/// that we open
+static RESTORED_WINDOW_STATE: AtomicBool = AtomicBool::new(false);
+
 pub fn set_window_class(cls: &str) {
     *WINDOW_CLASS.lock().unwrap() = cls.to_owned();
 }
@@ -84,12 +106,30 @@ struct RenderScreenLineOpenGLParams<'a> {
     dims: &'a RenderableDimensions,
     config: &'a ConfigHandle,
     pos: &'a PositionedPane,
+    /// If is_some(), where to move the quads of the line while
+    /// the pane is smooth scrolling
+    shift: Option<RowShift>,
+    /// True for the line after the last row of the pane, which
+    /// is partly scrolled into view while smooth scrolling
+    overflow: bool,
 
     cursor_border_color: Color,
     foreground: Color,
     is_active: bool,
 }
 
+impl<'a> RenderScreenLineOpenGLParams<'a> {
+    /// Returns the quad for the specified column of the line
+    fn quad<'b>(&self, quads: &'b mut MappedQuads, column: usize) -> anyhow::Result<Quad<'b>> {
+        let x = column + self.pos.left;
+        let y = self.line_idx + self.pos.top;
+        match self.shift {
+            Some(shift) if self.overflow => quads.overflow_cell(x, y - 1, shift),
+            shift => quads.cell_with_y_offset(x, y, shift),
+        }
+    }
+}
+
 struct ComputeCellFgBgParams<'a> {
     stable_line_idx: Option<StableRowIndex>,
     cell_idx: usize,
@@ -97,6 +137,10 @@ struct ComputeCellFgBgParams<'a> {
     selection: &'a Range<usize>,
     fg_color: Color,
     bg_color: Color,
+    /// The color of the underline, if it differs from fg_color
+    underline_color: Option<Color>,
+    blink: Blink,
+    intensity: wezterm_term::Intensity,
     palette: &'a ColorPalette,
     is_active_pane: bool,
     config: &'a ConfigHandle,
@@ -105,6 +149,7 @@ struct ComputeCellFgBgParams<'a> {
 struct ComputeCellFgBgResult {
     fg_color: Color,
     bg_color: Color,
+    underline_color: Color,
     cursor_shape: Option<CursorShape>,
 }
 
@@ -122,15 +167,34 @@ pub struct ClipboardHelper {
     clipboard_contents: Arc<Mutex<Option<String>>>,
 }
 
+/// Keeps the clipboard_contents cache in step with the clipboard
+/// when it is set from within wezterm.  Only the clipboard is cached,
+/// as that is what OSC 52 queries are answered from.
+fn update_clipboard_cache(
+    cache: &Mutex<Option<String>>,
+    selection: ClipboardSelection,
+    data: &Option<String>,
+) {
+    if selection == ClipboardSelection::Clipboard {
+        cache
+            .lock()
+            .unwrap()
+            .replace(data.clone().unwrap_or_else(String::new));
+    }
+}
+
 impl wezterm_term::Clipboard for ClipboardHelper {
     fn get_contents(&self) -> anyhow::Result<String> {
         // Even though we could request the clipboard contents using a call
         // like `self.window.get_clipboard().wait()` here, that requires
         // that the event loop be processed to do its work.
         // Since we are typically called in a blocking fashion on the
-        // event loop, we have to manually arrange to populate the
-        // clipboard_contents cache prior to calling the code that
-        // might call us.
+        // event loop, we instead answer from the clipboard_contents
+        // cache, which TermWindow::refresh_clipboard_contents fills in
+        // when the window is focused, and which set_contents keeps up
+        // to date.  A change that another application makes to the
+        // clipboard while the window remains focused isn't seen until
+        // the window is next focused.
         Ok(self
             .clipboard_contents
             .lock()
@@ -140,8 +204,19 @@ impl wezterm_term::Clipboard for ClipboardHelper {
             .unwrap_or_else(String::new))
     }
 
-    fn set_contents(&self, data: Option<String>) -> anyhow::Result<()> {
-        self.window.set_clipboard(data.unwrap_or_else(String::new));
+    fn set_contents(
+        &self,
+        selection: ClipboardSelection,
+        data: Option<String>,
+    ) -> anyhow::Result<()> {
+        update_clipboard_cache(&self.clipboard_contents, selection, &data);
+        self.window.set_clipboard(
+            match selection {
+                ClipboardSelection::Clipboard => Clipboard::Clipboard,
+                ClipboardSelection::PrimarySelection => Clipboard::PrimarySelection,
+            },
+            data.unwrap_or_else(String::new),
+        );
         Ok(())
     }
 }
@@ -179,6 +254,35 @@ impl PrevCursorPos {
     }
 }
 
+/// Tracks whether the mouse cursor has been hidden because the
+/// user is typing
+#[derive(Debug, Default, Clone, Copy)]
+struct MouseCursorHider {
+    hidden: bool,
+}
+
+impl MouseCursorHider {
+    /// Called when a key is typed; returns true if the mouse cursor
+    /// should be hidden now
+    fn key_typed(&mut self, enabled: bool) -> bool {
+        if !enabled || self.is_hidden() {
+            return false;
+        }
+        self.hidden = true;
+        true
+    }
+
+    /// Called when the mouse is moved or clicked, which shows the
+    /// mouse cursor again
+    fn mouse_activity(&mut self) {
+        self.hidden = false;
+    }
+
+    fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+}
+
 #[derive(Default, Clone)]
 pub struct PaneState {
     /// If is_some(), the top row of the visible screen.
@@ -190,6 +294,309 @@ pub struct PaneState {
     /// contents, we're overlaying a little internal application
     /// tab.  We'll also route input to it.
     pub overlay: Option<Rc<dyn Pane>>,
+    /// If is_some(), the viewport is smooth scrolling towards
+    /// its current position.
+    scroll_animation: Option<ScrollAnimation>,
+    /// The rows that held text with the blink attribute when the
+    /// pane was last painted
+    blinking_rows: Vec<StableRowIndex>,
+    /// The rows that were made dirty only to repaint the blinking
+    /// cursor or text since the pane was last painted
+    blink_dirty_rows: RangeSet<StableRowIndex>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ScrollAnimation {
+    /// The (possibly fractional) top row that was displayed when
+    /// the animation started
+    from: f32,
+    start: Instant,
+}
+
+/// Returns the top row to display `elapsed` into a smooth scroll
+/// from `from` to `to`, or None once the animation has finished
+/// and the viewport should be displayed at `to`.
+/// The scroll eases out, so that it starts quickly and then
+/// settles into place.
+fn smooth_scroll_position(
+    from: f32,
+    to: f32,
+    elapsed: Duration,
+    duration: Duration,
+) -> Option<f32> {
+    if elapsed >= duration {
+        return None;
+    }
+    let t = elapsed.as_secs_f32() / duration.as_secs_f32();
+    let eased = 1. - (1. - t).powi(3);
+    Some(from + (to - from) * eased)
+}
+
+/// Returns the number of rows or columns to give the pty, keeping it
+/// between 1 and MAX_PTY_ROWS_AND_COLS; extreme resizes, or minimizing
+/// the window, can briefly produce sizes outside that range that some
+/// programs handle poorly.
+fn pty_dimension(cells: usize) -> u16 {
+    cells.clamp(1, MAX_PTY_ROWS_AND_COLS as usize) as u16
+}
+
+fn saturating_u16(value: usize) -> u16 {
+    value.min(u16::MAX as usize) as u16
+}
+
+/// Returns true if a tab whose size is `current` needs to be resized
+/// to `size`.  The tab keeps its pixel dimensions as a whole number of
+/// cells, so they are compared the same way rather than as given.
+fn tab_needs_resize(current: PtySize, size: PtySize) -> bool {
+    if size.rows == 0 || size.cols == 0 {
+        // The tab ignores these anyway
+        return false;
+    }
+    let pixel_width = size.cols * (size.pixel_width / size.cols);
+    let pixel_height = size.rows * (size.pixel_height / size.rows);
+    current
+        != PtySize {
+            pixel_width,
+            pixel_height,
+            ..size
+        }
+}
+
+/// The window size that was requested in order to preserve the rows
+/// and columns across a scale change
+#[derive(Debug, Clone, Copy)]
+struct PendingScaleResize {
+    dimensions: Dimensions,
+    requested: Instant,
+}
+
+impl PendingScaleResize {
+    /// Returns true if the window has had long enough to reach the
+    /// requested size.  If it hasn't by now, the window manager has
+    /// most likely refused the request.
+    fn is_expired(&self, now: Instant) -> bool {
+        now.duration_since(self.requested) >= SCALE_RESIZE_TIMEOUT
+    }
+}
+
+/// Returns true if `dimensions` are reported while the window is still
+/// on its way to the size that was requested in order to preserve the
+/// rows and columns across a scale change.
+fn is_intermediate_resize(
+    pending: Option<&PendingScaleResize>,
+    dimensions: &Dimensions,
+    now: Instant,
+) -> bool {
+    matches!(pending, Some(pending) if !pending.is_expired(now)
+        && (pending.dimensions.pixel_width, pending.dimensions.pixel_height)
+            != (dimensions.pixel_width, dimensions.pixel_height))
+}
+
+/// The parts of the window layout that determine how many terminal
+/// cells fit into the window, and how large the window needs to be
+/// to hold a given number of them.
+#[derive(Debug, Clone, Copy)]
+struct CellLayout {
+    cell_width: usize,
+    cell_height: usize,
+    /// The total of the left and right padding
+    horizontal_padding: usize,
+    /// The total of the top and bottom padding
+    vertical_padding: usize,
+    show_tab_bar: bool,
+}
+
+impl CellLayout {
+    fn tab_bar_rows(&self) -> usize {
+        if self.show_tab_bar {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// The pty size for the cells that fit into a window of the given size
+    fn pty_size_for_window(&self, pixel_width: usize, pixel_height: usize) -> PtySize {
+        let avail_width = pixel_width.saturating_sub(self.horizontal_padding);
+        let avail_height = pixel_height.saturating_sub(self.vertical_padding);
+
+        let rows = (avail_height / self.cell_height.max(1)).saturating_sub(self.tab_bar_rows());
+        let cols = avail_width / self.cell_width.max(1);
+
+        PtySize {
+            rows: pty_dimension(rows),
+            cols: pty_dimension(cols),
+            pixel_height: saturating_u16(avail_height),
+            pixel_width: saturating_u16(avail_width),
+        }
+    }
+
+    /// The pty size and the window size that hold exactly `cells`.
+    /// The pty size is the one that pty_size_for_window computes for
+    /// that window size, so that the resize that follows once the
+    /// window reaches it doesn't change the size of the tabs again.
+    fn window_for_cells(&self, cells: RowsAndCols) -> (PtySize, usize, usize) {
+        let rows = pty_dimension(cells.rows) as usize + self.tab_bar_rows();
+        let cols = pty_dimension(cells.cols) as usize;
+
+        let pixel_width = (cols * self.cell_width) + self.horizontal_padding;
+        let pixel_height = (rows * self.cell_height) + self.vertical_padding;
+
+        (
+            self.pty_size_for_window(pixel_width, pixel_height),
+            pixel_width,
+            pixel_height,
+        )
+    }
+}
+
+/// The values that can be substituted into the window_title_template
+struct WindowTitleParams<'a> {
+    /// The 1-based index of the active tab
+    index: usize,
+    /// The number of tabs in the window
+    count: usize,
+    title: &'a str,
+    cwd: &'a str,
+}
+
+/// Expands the `{index}`, `{count}`, `{title}` and `{cwd}` placeholders
+/// in `template`.  Other text, including unknown placeholders, is left
+/// as it is.  The values are not expanded further, so a title that
+/// happens to contain a placeholder is shown as it is.
+fn expand_window_title_template(template: &str, params: &WindowTitleParams) -> String {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        let end = match placeholder.find('}') {
+            Some(end) => end,
+            None => {
+                rest = placeholder;
+                break;
+            }
+        };
+        match &placeholder[1..end] {
+            "index" => result.push_str(&params.index.to_string()),
+            "count" => result.push_str(&params.count.to_string()),
+            "title" => result.push_str(params.title),
+            "cwd" => result.push_str(params.cwd),
+            _ => result.push_str(&placeholder[..=end]),
+        }
+        rest = &placeholder[end + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Removes control characters from a title set by an application,
+/// so that it can't smuggle escape sequences into the window title,
+/// and limits it to `max_len` characters.
+/// This is applied to every title, not just when the
+/// window_title_template or window_title_max_length are set.
+fn sanitize_title(title: &str, max_len: Option<usize>) -> String {
+    let chars = title.chars().filter(|c| !c.is_control());
+    match max_len {
+        Some(max_len) => chars.take(max_len).collect(),
+        None => chars.collect(),
+    }
+}
+
+/// Returns the command that the domain should spawn for `spawn`,
+/// or None if it specifies no arguments, in which case the domain
+/// spawns its default program
+fn spawn_command_builder(spawn: &SpawnCommand) -> Option<CommandBuilder> {
+    let args = spawn.args.as_ref()?;
+    let mut builder = CommandBuilder::from_argv(args.iter().map(Into::into).collect());
+    for (k, v) in spawn.set_environment_variables.iter() {
+        builder.env(k, v);
+    }
+    if let Some(cwd) = spawn.cwd.as_ref() {
+        builder.cwd(cwd);
+    }
+    Some(builder)
+}
+
+/// Returns the local path of the working directory that a pane
+/// reported via OSC 7, which is where new panes are spawned
+pub(crate) fn working_dir_from_url(url: &Url) -> Option<String> {
+    if url.scheme() != "file" {
+        return None;
+    }
+    // The path may have spaces and other characters percent-encoded
+    let path = percent_decode_str(url.path())
+        .decode_utf8()
+        .ok()?
+        .into_owned();
+    // On Windows the file URI can produce a path like:
+    // `/C:\Users` which is valid in a file URI, but the leading slash
+    // is not liked by the windows file APIs, so we strip it off here.
+    let bytes = path.as_bytes();
+    if bytes.len() > 2 && bytes[0] == b'/' && bytes[2] == b':' {
+        Some(path[1..].to_owned())
+    } else {
+        Some(path)
+    }
+}
+
+/// Returns the viewport to use when scrolling to `position`, keeping
+/// it within the scrollback.  None means that the viewport follows the
+/// bottom of the output, which is also where positions at or beyond the
+/// bottom end up.
+fn clamp_viewport(
+    position: Option<StableRowIndex>,
+    dims: &RenderableDimensions,
+) -> Option<StableRowIndex> {
+    let pos = position?.max(dims.scrollback_top);
+    // Drop out of scrolling mode if we're off the bottom
+    if pos >= dims.physical_top {
+        None
+    } else {
+        Some(pos)
+    }
+}
+
+/// Returns the rows to include in a dump of the screen: those that are
+/// painted for the viewport, or everything from the top of the scrollback
+/// to the bottom of the screen.
+fn dump_screen_range(
+    viewport: Option<StableRowIndex>,
+    dims: &RenderableDimensions,
+    scrollback: bool,
+) -> Range<StableRowIndex> {
+    let bottom = dims.physical_top + dims.viewport_rows as StableRowIndex;
+    if scrollback {
+        dims.scrollback_top..bottom
+    } else {
+        let top = viewport.unwrap_or(dims.physical_top);
+        top..top + dims.viewport_rows as StableRowIndex
+    }
+}
+
+/// Returns the row that needs to be repainted when the cursor blinks,
+/// or None if the cursor isn't visible within `viewport`.
+fn cursor_blink_row(
+    cursor: &StableCursorPosition,
+    viewport: &Range<StableRowIndex>,
+) -> Option<StableRowIndex> {
+    if cursor.visibility == CursorVisibility::Visible && viewport.contains(&cursor.y) {
+        Some(cursor.y)
+    } else {
+        None
+    }
+}
+
+/// Returns true if any of the selected rows have changed.  Rows that
+/// were made dirty only to repaint blinking don't count as changed,
+/// otherwise the blinking cursor would clear the selection.
+fn selection_rows_changed(
+    selection_rows: Range<StableRowIndex>,
+    dirty: &RangeSet<StableRowIndex>,
+    blink_dirty_rows: &RangeSet<StableRowIndex>,
+) -> bool {
+    let changed = dirty.difference(blink_dirty_rows);
+    selection_rows.into_iter().any(|row| changed.contains(row))
 }
 
 #[derive(Default, Clone)]
@@ -198,6 +605,36 @@ pub struct TabState {
     /// contents, we're overlaying a little internal application
     /// tab.  We'll also route input to it.
     pub overlay: Option<Rc<dyn Pane>>,
+    /// The font scale set by IncreaseFontSize and friends while the
+    /// tab was active, if any; the window uses the scale of its
+    /// active tab.
+    pub font_scale: Option<f64>,
+}
+
+/// Returns the font scale of the tab `tab_id`.  Tabs whose font size
+/// hasn't been changed have the default scale of 1.0.
+fn tab_font_scale(tab_state: &HashMap<TabId, TabState>, tab_id: TabId) -> f64 {
+    tab_state
+        .get(&tab_id)
+        .and_then(|state| state.font_scale)
+        .unwrap_or(1.0)
+}
+
+/// Returns the font scale that the window must switch to when the
+/// tab `tab_id` is activated, or None if the window, whose current
+/// scale is `window_scale`, already uses the scale of that tab.
+#[allow(clippy::float_cmp)]
+fn font_scale_on_activation(
+    tab_state: &HashMap<TabId, TabState>,
+    tab_id: TabId,
+    window_scale: f64,
+) -> Option<f64> {
+    let font_scale = tab_font_scale(tab_state, tab_id);
+    if font_scale != window_scale {
+        Some(font_scale)
+    } else {
+        None
+    }
 }
 
 pub struct TermWindow {
@@ -207,8 +644,17 @@ pub struct TermWindow {
     fonts: Rc<FontConfiguration>,
     /// Window dimensions and dpi
     dimensions: Dimensions,
+    /// The position of the window, if the system has told us
+    window_position: Option<ScreenPoint>,
+    /// When the position or size of the window last changed,
+    /// if that hasn't been saved yet
+    window_state_changed: Option<Instant>,
     /// Terminal dimensions
     terminal_size: PtySize,
+    /// The window size that was requested to preserve the rows and
+    /// columns when the scale last changed, until the window reaches
+    /// it or we give up waiting
+    pending_scale_resize: Option<PendingScaleResize>,
     pub mux_window_id: MuxWindowId,
     render_metrics: RenderMetrics,
     render_state: RenderState,
@@ -222,7 +668,15 @@ pub struct TermWindow {
     last_mouse_terminal_coords: (usize, StableRowIndex),
     scroll_drag_start: Option<isize>,
     split_drag_start: Option<PositionedSplit>,
+    /// The index of the tab that is being dragged in the tab bar
+    tab_drag_start: Option<usize>,
     config_generation: usize,
+    /// True if rows were moved from their usual positions by smooth
+    /// scrolling when the window was last painted
+    rows_shifted: bool,
+    /// The generation of the font_dirs that the shaped and
+    /// rasterized glyphs came from
+    font_dirs_generation: usize,
     prev_cursor: PrevCursorPos,
     last_scroll_info: RenderableDimensions,
 
@@ -243,9 +697,18 @@ pub struct TermWindow {
     /// The URL over which we are currently hovering
     current_highlight: Option<Arc<Hyperlink>>,
 
-    shape_cache: RefCell<LruCache<ShapeCacheKey, anyhow::Result<Rc<Vec<GlyphInfo>>>>>,
+    mouse_cursor: MouseCursorHider,
+
+    shape_cache: RefCell<ShapeCache>,
 
     last_blink_paint: Instant,
+    /// The blink phase of text with the blink attribute is measured
+    /// from this point in time
+    text_blink_epoch: Instant,
+    last_text_blink_paint: Instant,
+
+    /// When the visual bell was last triggered, while it is fading out
+    visual_bell_start: Option<Instant>,
 
     palette: Option<ColorPalette>,
 }
@@ -295,6 +758,8 @@ impl WindowCallbacks for TermWindow {
         if self.focused.is_none() {
             self.last_mouse_click = None;
             self.current_mouse_button = None;
+        } else {
+            self.refresh_clipboard_contents();
         }
 
         // Reset the cursor blink phase
@@ -308,6 +773,20 @@ impl WindowCallbacks for TermWindow {
         }
     }
 
+    fn dropped_files(&mut self, paths: Vec<PathBuf>) {
+        let pane = match self.get_active_pane_or_overlay() {
+            Some(pane) => pane,
+            None => return,
+        };
+        let text = paths
+            .iter()
+            .map(|path| quote_dropped_file(&path.to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        pane.writer().write_all(text.as_bytes()).ok();
+        self.maybe_scroll_to_bottom_for_input(&pane);
+    }
+
     fn mouse_event(&mut self, event: &MouseEvent, context: &dyn WindowOps) {
         let pane = match self.get_active_pane_or_overlay() {
             Some(pane) => pane,
@@ -328,13 +807,19 @@ impl WindowCallbacks for TermWindow {
             .max(0)
             / self.render_metrics.cell_size.height) as i64;
 
-        let first_line_offset = if self.show_tab_bar { 1 } else { 0 };
+        let tab_bar_rows = self.tab_bar_rows();
         self.last_mouse_coords = (x, y);
 
-        let in_tab_bar = self.show_tab_bar && y == 0 && event.coords.y >= 0;
+        if matches!(event.kind, WMEK::Move | WMEK::Press(_)) {
+            // Show the mouse cursor again if it was hidden while
+            // typing; the handlers below set the appropriate shape
+            self.mouse_cursor.mouse_activity();
+        }
+
+        let in_tab_bar = tab_bar_rows.is_tab_bar_row(y) && event.coords.y >= 0;
         let in_scroll_bar = self.show_scroll_bar && x >= self.terminal_size.cols as usize;
         // y position relative to top of viewport (not including tab bar)
-        let term_y = y.saturating_sub(first_line_offset);
+        let term_y = tab_bar_rows.terminal_row(y);
 
         match event.kind {
             WMEK::Release(ref press) => {
@@ -347,6 +832,10 @@ impl WindowCallbacks for TermWindow {
                     // Completed a split drag
                     return;
                 }
+                if press == &MousePress::Left && self.tab_drag_start.take().is_some() {
+                    // Completed a tab drag
+                    return;
+                }
             }
 
             WMEK::Press(ref press) => {
@@ -375,7 +864,7 @@ impl WindowCallbacks for TermWindow {
                     .get_viewport(pane.pane_id())
                     .unwrap_or(dims.physical_top)
                     .saturating_sub(amount.into());
-                self.set_viewport(pane.pane_id(), Some(position), dims);
+                self.scroll_viewport(pane.pane_id(), Some(position), dims);
                 context.invalidate();
                 return;
             }
@@ -434,6 +923,27 @@ impl WindowCallbacks for TermWindow {
 
                     return;
                 }
+
+                if let Some(from_idx) = self.tab_drag_start {
+                    // Dragging a tab; move it to the position under the mouse
+                    if let TabBarItem::Tab(to_idx) = self.tab_bar.hit_test(x) {
+                        if to_idx != from_idx {
+                            let mux = Mux::get().unwrap();
+                            if let Some(mut window) = mux.get_window_mut(self.mux_window_id) {
+                                match window.move_tab(from_idx, to_idx) {
+                                    Ok(()) => self.tab_drag_start = Some(to_idx),
+                                    Err(err) => {
+                                        log::error!("unable to move tab: {:#}", err);
+                                        self.tab_drag_start = None;
+                                    }
+                                }
+                            }
+                            self.update_title();
+                            context.invalidate();
+                        }
+                    }
+                    return;
+                }
             }
             _ => {}
         }
@@ -459,6 +969,14 @@ impl WindowCallbacks for TermWindow {
             return;
         }
         self.scaling_changed(dimensions, self.fonts.get_font_scale());
+        self.window_state_changed = Some(Instant::now());
+    }
+
+    fn moved(&mut self, position: ScreenPoint) {
+        if self.window_position != Some(position) {
+            self.window_position = Some(position);
+            self.window_state_changed = Some(Instant::now());
+        }
     }
 
     fn key_event(&mut self, window_key: &KeyEvent, context: &dyn WindowOps) -> bool {
@@ -544,8 +1062,11 @@ impl WindowCallbacks for TermWindow {
                         && !config.send_composed_key_when_alt_is_pressed);
 
                     if bypass_compose && pane.key_down(key, raw_modifiers).is_ok() {
-                        if !key.is_modifier() && self.pane_state(pane.pane_id()).overlay.is_none() {
-                            self.maybe_scroll_to_bottom_for_input(&pane);
+                        if !key.is_modifier() {
+                            self.hide_mouse_cursor_while_typing(context);
+                            if self.pane_state(pane.pane_id()).overlay.is_none() {
+                                self.maybe_scroll_to_bottom_for_input(&pane);
+                            }
                         }
                         context.invalidate();
                         return true;
@@ -585,8 +1106,11 @@ impl WindowCallbacks for TermWindow {
                     }
                     true
                 } else if pane.key_down(key, modifiers).is_ok() {
-                    if !key.is_modifier() && self.pane_state(pane.pane_id()).overlay.is_none() {
-                        self.maybe_scroll_to_bottom_for_input(&pane);
+                    if !key.is_modifier() {
+                        self.hide_mouse_cursor_while_typing(context);
+                        if self.pane_state(pane.pane_id()).overlay.is_none() {
+                            self.maybe_scroll_to_bottom_for_input(&pane);
+                        }
                     }
                     context.invalidate();
                     true
@@ -635,7 +1159,10 @@ impl WindowCallbacks for TermWindow {
             fonts: Rc::clone(&self.fonts),
             render_metrics: self.render_metrics.clone(),
             dimensions,
+            window_position: self.window_position,
+            window_state_changed: self.window_state_changed,
             terminal_size: self.terminal_size.clone(),
+            pending_scale_resize: None,
             render_state,
             input_map: InputMap::new(),
             leader_is_down: None,
@@ -646,7 +1173,10 @@ impl WindowCallbacks for TermWindow {
             last_mouse_terminal_coords: self.last_mouse_terminal_coords.clone(),
             scroll_drag_start: self.scroll_drag_start.clone(),
             split_drag_start: self.split_drag_start.clone(),
+            tab_drag_start: self.tab_drag_start,
             config_generation: self.config_generation,
+            rows_shifted: self.rows_shifted,
+            font_dirs_generation: self.font_dirs_generation,
             prev_cursor: self.prev_cursor.clone(),
             last_scroll_info: self.last_scroll_info.clone(),
             clipboard_contents: Arc::clone(&clipboard_contents),
@@ -655,8 +1185,12 @@ impl WindowCallbacks for TermWindow {
             current_mouse_button: self.current_mouse_button.clone(),
             last_mouse_click: self.last_mouse_click.clone(),
             current_highlight: self.current_highlight.clone(),
-            shape_cache: RefCell::new(LruCache::new(65536)),
+            mouse_cursor: MouseCursorHider::default(),
+            shape_cache: RefCell::new(ShapeCache::new(65536)),
             last_blink_paint: Instant::now(),
+            text_blink_epoch: self.text_blink_epoch,
+            last_text_blink_paint: Instant::now(),
+            visual_bell_start: None,
         });
         prior_window.close();
 
@@ -674,6 +1208,7 @@ impl WindowCallbacks for TermWindow {
             Self::apply_icon(&window)?;
             Self::start_periodic_maintenance(window.clone());
             Self::setup_clipboard(&window, mux_window_id, clipboard_contents);
+            Self::register_for_alerts(&window, mux_window_id);
 
             window.enable_opengl();
             drop(activity); // Keep the activity outstanding until we get here
@@ -734,7 +1269,8 @@ impl WindowCallbacks for TermWindow {
         let start = std::time::Instant::now();
 
         {
-            let palette = self.palette();
+            let palette = self.palette().clone();
+            let palette = self.apply_visual_bell(palette, &config);
             let background_alpha = (config.window_background_opacity * 255.0) as u8;
             let background = rgbcolor_alpha_to_window_color(palette.background, background_alpha);
 
@@ -742,28 +1278,54 @@ impl WindowCallbacks for TermWindow {
             frame.clear_color_srgb(r, g, b, a);
         }
 
-        for pass in 0.. {
-            match self.paint_opengl_pass() {
+        // Grow the atlas before it fills up, rather than discovering
+        // that it is full part way through painting, and having to
+        // throw away the glyphs and paint everything again
+        match self.render_state.grow_texture_atlas_if_nearly_full() {
+            Ok(true) => {
+                if let Some(stats) = self.render_state.glyph_cache_stats() {
+                    log::debug!("grew texture atlas: {}", stats);
+                }
+                metrics::counter!("gui.atlas.grow", 1);
+            }
+            Ok(false) => {}
+            Err(err) => log::error!("Failed to grow texture atlas: {}", err),
+        }
+
+        let restore_rows = std::mem::replace(&mut self.rows_shifted, false);
+        let mut cleared = false;
+        loop {
+            match self.paint_opengl_pass(restore_rows) {
                 Ok(_) => break,
                 Err(err) => {
                     if let Some(&OutOfTextureSpace { size: Some(size) }) =
                         err.downcast_ref::<OutOfTextureSpace>()
                     {
-                        let result = if pass == 0 {
-                            // Let's try clearing out the atlas and trying again
-                            self.render_state.clear_texture_atlas(&self.render_metrics)
-                        } else {
-                            log::error!("grow texture atlas to {}", size);
-                            self.recreate_texture_atlas(Some(size))
-                        };
-
-                        if let Err(err) = result {
-                            log::error!(
-                                "Failed to {} texture: {}",
-                                if pass == 0 { "clear" } else { "resize" },
-                                err
-                            );
-                            break;
+                        if let Some(stats) = self.render_state.glyph_cache_stats() {
+                            log::debug!("out of texture space: {}", stats);
+                        }
+                        // Grow the atlas, keeping the glyphs that it already
+                        // holds.  Only if it can't grow any further do we
+                        // throw them away and start over with an empty atlas
+                        log::debug!("grow texture atlas to {}", size);
+                        match self.render_state.grow_texture_atlas(size) {
+                            Ok(()) => {
+                                metrics::counter!("gui.atlas.grow", 1);
+                            }
+                            Err(err) if !cleared => {
+                                log::debug!("Failed to resize texture ({}), clearing it", err);
+                                cleared = true;
+                                if let Err(err) =
+                                    self.render_state.clear_texture_atlas(&self.render_metrics)
+                                {
+                                    log::error!("Failed to clear texture: {}", err);
+                                    break;
+                                }
+                            }
+                            Err(err) => {
+                                log::error!("Failed to resize texture: {}", err);
+                                break;
+                            }
                         }
                     } else {
                         log::error!("paint_opengl_pass failed: {}", err);
@@ -774,6 +1336,8 @@ impl WindowCallbacks for TermWindow {
         }
 
         self.call_draw(frame).ok();
+        self.report_cache_counts();
+        metrics::counter!("gui.paint.frames", 1);
         log::debug!("paint_pane_opengl elapsed={:?}", start.elapsed());
         metrics::value!("gui.paint.opengl", start.elapsed());
         self.update_title();
@@ -892,6 +1456,7 @@ impl TermWindow {
         );
 
         let render_state = RenderState::Software(SoftwareRenderState::new()?);
+        let font_dirs_generation = fontconfig.font_dirs_generation();
 
         let clipboard_contents = Arc::new(Mutex::new(None));
 
@@ -909,7 +1474,10 @@ impl TermWindow {
                 fonts: fontconfig,
                 render_metrics,
                 dimensions,
+                window_position: None,
+                window_state_changed: None,
                 terminal_size,
+                pending_scale_resize: None,
                 render_state,
                 input_map: InputMap::new(),
                 leader_is_down: None,
@@ -920,7 +1488,10 @@ impl TermWindow {
                 last_mouse_terminal_coords: (0, 0),
                 scroll_drag_start: None,
                 split_drag_start: None,
+                tab_drag_start: None,
                 config_generation: config.generation(),
+                rows_shifted: false,
+                font_dirs_generation,
                 prev_cursor: PrevCursorPos::new(),
                 last_scroll_info: RenderableDimensions::default(),
                 clipboard_contents: Arc::clone(&clipboard_contents),
@@ -929,14 +1500,23 @@ impl TermWindow {
                 current_mouse_button: None,
                 last_mouse_click: None,
                 current_highlight: None,
-                shape_cache: RefCell::new(LruCache::new(65536)),
+                mouse_cursor: MouseCursorHider::default(),
+                shape_cache: RefCell::new(ShapeCache::new(65536)),
                 last_blink_paint: Instant::now(),
+                text_blink_epoch: Instant::now(),
+                last_text_blink_paint: Instant::now(),
+                visual_bell_start: None,
             }),
         )?;
 
         Self::apply_icon(&window)?;
         Self::start_periodic_maintenance(window.clone());
         Self::setup_clipboard(&window, mux_window_id, clipboard_contents);
+        Self::register_for_alerts(&window, mux_window_id);
+
+        if config.restore_window_state && !RESTORED_WINDOW_STATE.swap(true, Ordering::Relaxed) {
+            Self::restore_window_state(&window);
+        }
 
         window.enable_opengl();
 
@@ -965,6 +1545,54 @@ impl TermWindow {
         }
     }
 
+    /// Arranges for alerts raised by the panes in this window to be
+    /// delivered to `handle_alert`.
+    /// The front end holds the single mux subscription for alerts; we
+    /// just need to tell it which gui window now displays our mux window.
+    fn register_for_alerts(window: &Window, mux_window_id: MuxWindowId) {
+        if let Some(fe) = crate::gui::front_end() {
+            fe.record_known_window(mux_window_id, window.clone());
+        }
+    }
+
+    pub fn deliver_alert(window: &Window, alert: wezterm_term::Alert) {
+        window.apply(move |myself, window| {
+            if let Some(myself) = myself.downcast_mut::<Self>() {
+                myself.handle_alert(alert, window);
+            }
+            Ok(())
+        });
+    }
+
+    fn handle_alert(&mut self, alert: wezterm_term::Alert, window: &dyn WindowOps) {
+        match alert {
+            wezterm_term::Alert::Bell => {
+                let config = configuration();
+                if config.audible_bell {
+                    Connection::get().unwrap().beep();
+                }
+                if config.visual_bell {
+                    self.visual_bell_start = Some(Instant::now());
+                    window.invalidate();
+                }
+            }
+        }
+    }
+
+    /// While the visual bell is fading out, tints the background
+    /// of the palette towards the foreground color
+    fn apply_visual_bell(&self, mut palette: ColorPalette, config: &ConfigHandle) -> ColorPalette {
+        if let Some(start) = self.visual_bell_start {
+            let intensity = visual_bell_intensity(
+                start.elapsed(),
+                Duration::from_millis(config.visual_bell_duration_ms),
+            );
+            palette.background =
+                blend_rgbcolor(palette.background, palette.foreground, intensity / 2.);
+        }
+        palette
+    }
+
     fn apply_icon(window: &Window) -> anyhow::Result<()> {
         let icon_image =
             image::load_from_memory(include_bytes!("../../../assets/icon/terminal.png"))?;
@@ -978,6 +1606,44 @@ impl TermWindow {
         Ok(())
     }
 
+    fn restore_window_state(window: &Window) {
+        if let Some(state) = WindowState::load(&*WINDOW_CLASS.lock().unwrap()) {
+            let screens = Connection::get().unwrap().screens();
+            let state = state.clamp_to_screens(&screens);
+            log::trace!("restoring window state {:?}", state);
+            window.set_inner_size(state.width, state.height);
+            window.set_window_position(ScreenPoint::new(state.x, state.y));
+        }
+    }
+
+    /// Saves the position and size of the window once they have
+    /// settled down, so that we don't write to disk continually
+    /// while the user is dragging the window around.
+    fn save_window_state(&mut self, config: &ConfigHandle) {
+        let changed = match self.window_state_changed {
+            Some(changed) => changed,
+            None => return,
+        };
+        if changed.elapsed() < Duration::from_millis(500) {
+            return;
+        }
+        self.window_state_changed = None;
+        if !config.restore_window_state {
+            return;
+        }
+        if let Some(position) = self.window_position {
+            let state = WindowState {
+                x: position.x,
+                y: position.y,
+                width: self.dimensions.pixel_width,
+                height: self.dimensions.pixel_height,
+            };
+            if let Err(err) = state.save(&*WINDOW_CLASS.lock().unwrap()) {
+                log::warn!("failed to save window state: {:#}", err);
+            }
+        }
+    }
+
     fn start_periodic_maintenance(window: Window) {
         Connection::get().unwrap().schedule_timer(
             std::time::Duration::from_millis(35),
@@ -1002,46 +1668,63 @@ impl TermWindow {
 
         let config = configuration();
 
+        self.save_window_state(&config);
+
+        if let Some(start) = self.visual_bell_start {
+            // Keep repainting while the flash fades out, and once
+            // more after it has finished to restore the background
+            if start.elapsed() >= Duration::from_millis(config.visual_bell_duration_ms) {
+                self.visual_bell_start = None;
+            }
+            needs_invalidate = true;
+        }
+
+        if let Some(pending) = self.pending_scale_resize {
+            if pending.is_expired(Instant::now()) {
+                // The window never reached the size that we asked for
+                // when the scale changed, so fit the cells to the size
+                // that it has instead
+                self.pending_scale_resize = None;
+                let dimensions = self.dimensions;
+                self.apply_dimensions(&dimensions, None);
+                needs_invalidate = true;
+            }
+        }
+
         let panes = self.get_panes_to_render();
         if panes.is_empty() {
             self.window.as_ref().unwrap().close();
             return Ok(());
         }
 
-        for pos in panes {
-            // If blinking is permitted, and the cursor shape is set
-            // to a blinking variant, and it's been longer than the
-            // blink rate interval, then invalidate and redraw
-            // so that we will re-evaluate the cursor visibility.
-            // This is pretty heavyweight: it would be nice to only invalidate
-            // the line on which the cursor resides, and then only if the cursor
-            // is within the viewport.
-            if config.cursor_blink_rate != 0 && pos.is_active && self.focused.is_some() {
-                let shape = config
-                    .default_cursor_style
-                    .effective_shape(pos.pane.get_cursor_position().shape);
-                if shape.is_blinking() {
-                    let now = Instant::now();
-                    if now.duration_since(self.last_blink_paint)
-                        > Duration::from_millis(config.cursor_blink_rate)
-                    {
-                        needs_invalidate = true;
-                        self.last_blink_paint = now;
-                    }
-                }
+        // Blinking text changes phase at the faster of the enabled
+        // blink rates
+        let text_blink_interval = [config.text_blink_rate, config.text_blink_rate_rapid]
+            .iter()
+            .filter(|&&rate| rate != 0)
+            .min()
+            .map(|&rate| Duration::from_millis(rate));
+        let text_blink_due = match text_blink_interval {
+            Some(interval) if self.last_text_blink_paint.elapsed() >= interval => {
+                self.last_text_blink_paint = Instant::now();
+                true
             }
+            _ => false,
+        };
 
+        for pos in panes {
             // If the model is dirty, arrange to re-paint
             let dims = pos.pane.get_dimensions();
             let viewport = self
                 .get_viewport(pos.pane.pane_id())
                 .unwrap_or(dims.physical_top);
             let visible_range = viewport..viewport + dims.viewport_rows as StableRowIndex;
-            let dirty = pos.pane.get_dirty_lines(visible_range);
+            let dirty = pos.pane.get_dirty_lines(visible_range.clone());
 
             if !dirty.is_empty() {
                 if pos.pane.downcast_ref::<SearchOverlay>().is_none()
                     && pos.pane.downcast_ref::<CopyOverlay>().is_none()
+                    && pos.pane.downcast_ref::<QuickSelectOverlay>().is_none()
                 {
                     // If any of the changed lines intersect with the
                     // selection, then we need to clear the selection, but not
@@ -1049,15 +1732,21 @@ impl TermWindow {
                     // marks lines as dirty to force invalidate them for
                     // highlighting purpose but also manipulates the selection
                     // and we want to allow it to retain the selection it made!
-
-                    let clear_selection = if let Some(selection_range) =
-                        self.selection(pos.pane.pane_id()).range.as_ref()
-                    {
-                        let selection_rows = selection_range.rows();
-                        selection_rows.into_iter().any(|row| dirty.contains(row))
-                    } else {
-                        false
-                    };
+                    // The quick select overlay marks lines as dirty in the
+                    // same way, and shouldn't disturb the selection either.
+
+                    let state = self.pane_state(pos.pane.pane_id());
+                    let clear_selection =
+                        if let Some(selection_range) = state.selection.range.as_ref() {
+                            selection_rows_changed(
+                                selection_range.rows(),
+                                &dirty,
+                                &state.blink_dirty_rows,
+                            )
+                        } else {
+                            false
+                        };
+                    drop(state);
 
                     if clear_selection {
                         self.selection(pos.pane.pane_id()).range.take();
@@ -1067,14 +1756,61 @@ impl TermWindow {
 
                 needs_invalidate = true;
             }
-        }
 
-        if let Some(mut mux_window) = mux.get_window_mut(self.mux_window_id) {
-            if mux_window.check_and_reset_invalidated() {
-                needs_invalidate = true;
+            // If blinking is permitted, and the cursor shape is set
+            // to a blinking variant, and it's been longer than the
+            // blink rate interval, then dirty the line on which the
+            // cursor resides and redraw so that we will re-evaluate the
+            // cursor visibility.  There is nothing to repaint if the
+            // cursor isn't within the viewport.
+            // The row is remembered so that the dirty check above
+            // doesn't mistake the blink for a change to the selection.
+            if config.cursor_blink_rate != 0 && pos.is_active && self.focused.is_some() {
+                let cursor = pos.pane.get_cursor_position();
+                let shape = config.default_cursor_style.effective_shape(cursor.shape);
+                if shape.is_blinking() {
+                    let now = Instant::now();
+                    if now.duration_since(self.last_blink_paint)
+                        > Duration::from_millis(config.cursor_blink_rate)
+                    {
+                        self.last_blink_paint = now;
+                        if let Some(row) = cursor_blink_row(&cursor, &visible_range) {
+                            pos.pane.make_line_dirty(row);
+                            self.pane_state(pos.pane.pane_id())
+                                .blink_dirty_rows
+                                .add(row);
+                            needs_invalidate = true;
+                        }
+                    }
+                }
+            }
+
+            // Similarly, when blinking text changes phase, repaint the
+            // rows that held blinking text when the pane was last painted.
+            if text_blink_due {
+                let mut state = self.pane_state(pos.pane.pane_id());
+                let rows = state.blinking_rows.clone();
+                for row in rows {
+                    if visible_range.contains(&row) {
+                        pos.pane.make_line_dirty(row);
+                        state.blink_dirty_rows.add(row);
+                        needs_invalidate = true;
+                    }
+                }
             }
         }
 
+        let tabs_changed = mux
+            .get_window_mut(self.mux_window_id)
+            .map(|mut mux_window| mux_window.check_and_reset_invalidated())
+            .unwrap_or(false);
+        if tabs_changed {
+            // Spawning or closing a tab activates another tab without
+            // going through activate_tab
+            self.apply_active_tab_font_scale();
+            needs_invalidate = true;
+        }
+
         if needs_invalidate {
             self.window.as_ref().unwrap().invalidate();
         }
@@ -1195,13 +1931,33 @@ impl TermWindow {
 
     fn recreate_texture_atlas(&mut self, size: Option<usize>) -> anyhow::Result<()> {
         self.render_state
-            .recreate_texture_atlas(&self.fonts, &self.render_metrics, size)
+            .recreate_texture_atlas(&self.fonts, &self.render_metrics, size)?;
+        metrics::counter!("gui.atlas.recreate", 1);
+        Ok(())
     }
 
     fn check_for_config_reload(&mut self) {
         if self.config_generation != configuration().generation() {
             self.config_was_reloaded();
         }
+        if self.font_dirs_generation != self.fonts.font_dirs_generation() {
+            self.font_dirs_were_changed();
+        }
+    }
+
+    /// Fonts were added to or removed from the font_dirs, so discard
+    /// everything that was shaped and rasterized from the old fonts,
+    /// in the same way as when the config is reloaded
+    fn font_dirs_were_changed(&mut self) {
+        self.font_dirs_generation = self.fonts.font_dirs_generation();
+        self.shape_cache.borrow_mut().clear();
+        let dimensions = self.dimensions;
+        let cell_dims = self.current_cell_dimensions();
+        self.apply_scale_change(&dimensions, self.fonts.get_font_scale());
+        self.apply_dimensions(&dimensions, Some(cell_dims));
+        if let Some(window) = self.window.as_ref() {
+            window.invalidate();
+        }
     }
 
     fn palette(&mut self) -> &ColorPalette {
@@ -1214,6 +1970,7 @@ impl TermWindow {
     fn config_was_reloaded(&mut self) {
         let config = configuration();
         self.config_generation = config.generation();
+        self.font_dirs_generation = self.fonts.font_dirs_generation();
         self.palette.take();
 
         #[cfg(target_os = "macos")]
@@ -1223,6 +1980,7 @@ impl TermWindow {
         #[cfg(windows)]
         {
             ::window::os::windows::use_dead_keys(config.use_dead_keys);
+            crate::gui::set_window_frame_theme(config.window_frame_theme);
         }
 
         self.window_background = reload_background_image(&config, &self.window_background);
@@ -1283,7 +2041,7 @@ impl TermWindow {
 
         let new_tab_bar = TabBarState::new(
             self.terminal_size.cols as usize,
-            if self.last_mouse_coords.1 == 0 {
+            if self.tab_bar_rows().is_tab_bar_row(self.last_mouse_coords.1) {
                 Some(self.last_mouse_coords.0)
             } else {
                 None
@@ -1310,27 +2068,46 @@ impl TermWindow {
 
         let panes = self.get_panes_to_render();
         if let Some(pos) = panes.iter().find(|p| p.is_active) {
-            let title = pos.pane.get_title();
+            let title = sanitize_title(&pos.pane.get_title(), config.window_title_max_length);
 
             if let Some(window) = self.window.as_ref() {
-                let show_tab_bar;
-                if num_tabs == 1 {
+                let zoomed = if pos.is_zoomed { "[Z] " } else { "" };
+                if let Some(template) = config.window_title_template.as_ref() {
+                    let cwd = pos
+                        .pane
+                        .get_current_working_dir()
+                        .as_ref()
+                        .and_then(working_dir_from_url)
+                        .unwrap_or_default();
                     window.set_title(&format!(
                         "{}{}",
-                        if pos.is_zoomed { "[Z] " } else { "" },
-                        title
+                        zoomed,
+                        expand_window_title_template(
+                            template,
+                            &WindowTitleParams {
+                                index: tab_no + 1,
+                                count: num_tabs,
+                                title: &title,
+                                cwd: &sanitize_title(&cwd, None),
+                            }
+                        )
                     ));
-                    show_tab_bar = config.enable_tab_bar && !config.hide_tab_bar_if_only_one_tab;
+                } else if num_tabs == 1 {
+                    window.set_title(&format!("{}{}", zoomed, title));
                 } else {
                     window.set_title(&format!(
                         "{}[{}/{}] {}",
-                        if pos.is_zoomed { "[Z] " } else { "" },
+                        zoomed,
                         tab_no + 1,
                         num_tabs,
                         title
                     ));
-                    show_tab_bar = config.enable_tab_bar;
                 }
+                let show_tab_bar = if num_tabs == 1 {
+                    config.enable_tab_bar && !config.hide_tab_bar_if_only_one_tab
+                } else {
+                    config.enable_tab_bar
+                };
 
                 // If the number of tabs changed and caused the tab bar to
                 // hide/show, then we'll need to resize things.  It is simplest
@@ -1347,7 +2124,8 @@ impl TermWindow {
         let cursor = pane.get_cursor_position();
         if let Some(win) = self.window.as_ref() {
             let config = configuration();
-            let top = pane.get_dimensions().physical_top + if self.show_tab_bar { -1 } else { 0 };
+            let top = pane.get_dimensions().physical_top
+                - self.tab_bar_rows().first_line_offset as StableRowIndex;
             let r = Rect::new(
                 Point::new(
                     (cursor.x.max(0) as isize * self.render_metrics.cell_size.width)
@@ -1388,6 +2166,7 @@ impl TermWindow {
                 tab.focus_changed(true);
             }
 
+            self.apply_active_tab_font_scale();
             self.update_title();
             self.update_scrollbar();
         }
@@ -1403,11 +2182,9 @@ impl TermWindow {
         let max = window.len();
         ensure!(max > 0, "no more tabs");
 
-        let active = window.get_active_idx() as isize;
-        let tab = active + delta;
-        let tab = if tab < 0 { max as isize + tab } else { tab };
+        let tab = relative_tab_index(window.get_active_idx(), delta, max);
         drop(window);
-        self.activate_tab((tab as usize % max) as isize)
+        self.activate_tab(tab as isize)
     }
 
     fn move_tab(&mut self, tab_idx: usize) -> anyhow::Result<()> {
@@ -1420,12 +2197,7 @@ impl TermWindow {
         ensure!(max > 0, "no more tabs");
 
         let active = window.get_active_idx();
-
-        ensure!(tab_idx < max, "cannot move a tab out of range");
-
-        let tab_inst = window.remove_by_idx(active);
-        window.insert(tab_idx, &tab_inst);
-        window.set_active(tab_idx);
+        window.move_tab(active, tab_idx)?;
 
         drop(window);
         self.update_title();
@@ -1534,6 +2306,51 @@ impl TermWindow {
         promise::spawn::spawn(future).detach();
     }
 
+    /// Opens `link` using the handler for it that is registered with
+    /// the system, unless an `open-uri` event handler takes care of it.
+    pub fn open_uri(&self, pane: &Rc<dyn Pane>, link: String) {
+        // We need to ensure that we spawn the `open` call outside of the context
+        // of our window loop; on Windows it can cause a panic due to
+        // triggering our WndProc recursively.
+        // We get that assurance for free as part of the async dispatch that we
+        // perform below; here we allow the user to define an `open-uri` event
+        // handler that can bypass the normal `open::that` functionality.
+        let window = GuiWin::new(self);
+        let pane = PaneObject::new(pane);
+
+        async fn open_uri(
+            lua: Option<Rc<mlua::Lua>>,
+            window: GuiWin,
+            pane: PaneObject,
+            link: String,
+        ) -> anyhow::Result<()> {
+            let default_click = match lua {
+                Some(lua) => {
+                    let args = lua.pack_multi((window, pane, link.clone()))?;
+                    config::lua::emit_event(&lua, ("open-uri".to_string(), args))
+                        .await
+                        .map_err(|e| {
+                            log::error!("while processing open-uri event: {:#}", e);
+                            e
+                        })?
+                }
+                None => true,
+            };
+            if default_click {
+                log::error!("clicking {}", link);
+                if let Err(err) = open::that(&link) {
+                    log::error!("failed to open {}: {:?}", link, err);
+                }
+            }
+            Ok(())
+        }
+
+        promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
+            open_uri(lua, window, pane, link)
+        }))
+        .detach();
+    }
+
     fn scroll_to_prompt(&mut self, amount: isize) -> anyhow::Result<()> {
         let pane = match self.get_active_pane_or_overlay() {
             Some(pane) => pane,
@@ -1543,14 +2360,14 @@ impl TermWindow {
         let position = self
             .get_viewport(pane.pane_id())
             .unwrap_or(dims.physical_top);
-        let mut zones = pane.get_semantic_zones()?;
-        zones.retain(|zone| zone.semantic_type == wezterm_term::SemanticType::Prompt);
-        let idx = match zones.binary_search_by(|zone| zone.start_y.cmp(&position)) {
-            Ok(idx) | Err(idx) => idx,
-        };
-        let idx = ((idx as isize) + amount).max(0) as usize;
-        if let Some(zone) = zones.get(idx) {
-            self.set_viewport(pane.pane_id(), Some(zone.start_y), dims);
+        let prompt_rows: Vec<StableRowIndex> = pane
+            .get_semantic_zones()?
+            .into_iter()
+            .filter(|zone| zone.semantic_type == wezterm_term::SemanticType::Prompt)
+            .map(|zone| zone.start_y)
+            .collect();
+        if let Some(row) = relative_prompt_row(&prompt_rows, position, amount) {
+            self.set_viewport(pane.pane_id(), Some(row), dims);
         }
 
         if let Some(win) = self.window.as_ref() {
@@ -1559,6 +2376,11 @@ impl TermWindow {
         Ok(())
     }
 
+    fn scroll_to_top(&mut self, pane: &Rc<dyn Pane>) {
+        let dims = pane.get_dimensions();
+        self.scroll_viewport(pane.pane_id(), Some(dims.scrollback_top), dims);
+    }
+
     fn scroll_by_page(&mut self, amount: isize) -> anyhow::Result<()> {
         let pane = match self.get_active_pane_or_overlay() {
             Some(pane) => pane,
@@ -1569,7 +2391,7 @@ impl TermWindow {
             .get_viewport(pane.pane_id())
             .unwrap_or(dims.physical_top)
             .saturating_add(amount * dims.viewport_rows as isize);
-        self.set_viewport(pane.pane_id(), Some(position), dims);
+        self.scroll_viewport(pane.pane_id(), Some(position), dims);
         if let Some(win) = self.window.as_ref() {
             win.invalidate();
         }
@@ -1585,16 +2407,7 @@ impl TermWindow {
         let max = window.len();
         ensure!(max > 0, "no more tabs");
 
-        let active = window.get_active_idx();
-        let tab = active as isize + delta;
-        let tab = if tab < 0 {
-            0usize
-        } else if tab >= max as isize {
-            max - 1
-        } else {
-            tab as usize
-        };
-
+        let tab = relative_tab_index(window.get_active_idx(), delta, max);
         drop(window);
         self.move_tab(tab)
     }
@@ -1687,35 +2500,10 @@ impl TermWindow {
                     )
                 })?)
             } else {
-                match cwd {
-                    Some(url) if url.scheme() == "file" => {
-                        let path = url.path().to_string();
-                        // On Windows the file URI can produce a path like:
-                        // `/C:\Users` which is valid in a file URI, but the leading slash
-                        // is not liked by the windows file APIs, so we strip it off here.
-                        let bytes = path.as_bytes();
-                        if bytes.len() > 2 && bytes[0] == b'/' && bytes[2] == b':' {
-                            Some(path[1..].to_owned())
-                        } else {
-                            Some(path)
-                        }
-                    }
-                    Some(_) | None => None,
-                }
+                cwd.as_ref().and_then(working_dir_from_url)
             };
 
-            let cmd_builder = if let Some(args) = spawn.args {
-                let mut builder = CommandBuilder::from_argv(args.iter().map(Into::into).collect());
-                for (k, v) in spawn.set_environment_variables.iter() {
-                    builder.env(k, v);
-                }
-                if let Some(cwd) = spawn.cwd {
-                    builder.cwd(cwd);
-                }
-                Some(builder)
-            } else {
-                None
-            };
+            let cmd_builder = spawn_command_builder(&spawn);
 
             match spawn_where {
                 SpawnWhere::SplitPane(direction) => {
@@ -1772,31 +2560,115 @@ impl TermWindow {
         );
     }
 
-    fn selection_text(&self, pane: &Rc<dyn Pane>) -> String {
-        let mut s = String::new();
-        if let Some(sel) = self
+    /// Returns the selected cells of each of the selected lines
+    fn selection_cells(&self, pane: &Rc<dyn Pane>) -> Vec<SelectedLine> {
+        match self
             .selection(pane.pane_id())
             .range
             .as_ref()
             .map(|r| r.normalize())
         {
-            let mut last_was_wrapped = false;
-            let (first_row, lines) = pane.get_lines(sel.rows());
-            for (idx, line) in lines.iter().enumerate() {
-                let cols = sel.cols_for_row(first_row + idx as StableRowIndex);
-                let last_col_idx = cols.end.min(line.cells().len()).saturating_sub(1);
-                if !s.is_empty() && !last_was_wrapped {
-                    s.push('\n');
-                }
-                s.push_str(line.columns_as_str(cols).trim_end());
+            Some(sel) => {
+                let (first_row, lines) = pane.get_lines(sel.rows());
+                selected_lines(&sel, first_row, &lines)
+            }
+            None => vec![],
+        }
+    }
+
+    fn selection_as(&self, pane: &Rc<dyn Pane>, format: CopyFormat) -> String {
+        format_selection(&self.selection_cells(pane), format, &pane.palette())
+    }
+
+    fn selection_text(&self, pane: &Rc<dyn Pane>) -> String {
+        self.selection_as(pane, CopyFormat::Text)
+    }
+
+    /// Dumps the screen, and optionally the scrollback, of the pane
+    /// to a file or to the clipboard
+    fn dump_screen(&self, pane: &Rc<dyn Pane>, dump: &config::keyassignment::DumpScreen) {
+        let range = dump_screen_range(
+            self.get_viewport(pane.pane_id()),
+            &pane.get_dimensions(),
+            dump.scrollback,
+        );
+        let (_, lines) = pane.get_lines(range);
+        let format = if dump.ansi {
+            CopyFormat::Ansi
+        } else {
+            CopyFormat::Text
+        };
+        let mut text = format_screen(
+            &lines,
+            format,
+            !dump.keep_trailing_whitespace,
+            &pane.palette(),
+        );
 
-                let last_cell = &line.cells()[last_col_idx];
-                // TODO: should really test for any unicode whitespace
-                last_was_wrapped = last_cell.attrs().wrapped() && last_cell.str() != " ";
+        match &dump.path {
+            Some(path) => {
+                text.push('\n');
+                if let Err(err) = std::fs::write(path, text) {
+                    log::error!("failed to dump screen to {}: {:#}", path.display(), err);
+                }
+            }
+            None => {
+                self.window
+                    .as_ref()
+                    .unwrap()
+                    .set_clipboard(Clipboard::Clipboard, text);
             }
         }
+    }
+
+    /// Publishes the text of a completed selection to the clipboard(s)
+    fn complete_selection(&self, text: String) -> anyhow::Result<()> {
+        let window = self.window.as_ref().unwrap();
+        let clipboard = ClipboardHelper {
+            window: window.clone(),
+            clipboard_contents: Arc::clone(&self.clipboard_contents),
+        };
+        set_completed_selection(&clipboard, text, configuration().copy_on_select)?;
+        window.invalidate();
+        Ok(())
+    }
+
+    /// Pastes text into the pane, asking the user to confirm first
+    /// if confirm_multiline_paste is enabled and the text could run
+    /// commands in the pane.
+    fn paste_into_pane(&mut self, pane: &Rc<dyn Pane>, text: String) {
+        if configuration().confirm_multiline_paste
+            && paste_needs_confirmation(&text, pane.is_bracketed_paste_enabled())
+        {
+            let (overlay, future) = start_overlay_pane(self, pane, move |pane_id, term| {
+                confirm_paste(pane_id, text, term)
+            });
+            self.assign_overlay_for_pane(pane.pane_id(), overlay);
+            promise::spawn::spawn(future).detach();
+        } else {
+            pane.trickle_paste(text).ok();
+        }
+    }
 
-        s
+    /// Fetches the clipboard into the clipboard_contents cache that
+    /// OSC 52 queries are answered from.  The clipboard may have been
+    /// changed by another application while we weren't focused.
+    fn refresh_clipboard_contents(&self) {
+        if !configuration().enable_osc52_clipboard_reading {
+            return;
+        }
+        let future = self
+            .window
+            .as_ref()
+            .unwrap()
+            .get_clipboard(Clipboard::Clipboard);
+        let clipboard_contents = Arc::clone(&self.clipboard_contents);
+        promise::spawn::spawn(async move {
+            if let Ok(clip) = future.await {
+                clipboard_contents.lock().unwrap().replace(clip);
+            }
+        })
+        .detach();
     }
 
     fn paste_from_clipboard(&mut self, pane: &Rc<dyn Pane>, clipboard: Clipboard) {
@@ -1809,13 +2681,11 @@ impl TermWindow {
                     .apply(move |term_window, _window| {
                         let clip = clip.clone();
                         if let Some(term_window) = term_window.downcast_mut::<TermWindow>() {
-                            if let Some(pane) =
-                                term_window.pane_state(pane_id).overlay.clone().or_else(|| {
-                                    let mux = Mux::get().unwrap();
-                                    mux.get_pane(pane_id)
-                                })
-                            {
-                                pane.trickle_paste(clip).ok();
+                            let overlay = term_window.pane_state(pane_id).overlay.clone();
+                            if let Some(overlay) = overlay {
+                                overlay.trickle_paste(clip).ok();
+                            } else if let Some(pane) = Mux::get().unwrap().get_pane(pane_id) {
+                                term_window.paste_into_pane(&pane, clip);
                             }
                         }
                         Ok(())
@@ -1861,7 +2731,13 @@ impl TermWindow {
                 self.window
                     .as_ref()
                     .unwrap()
-                    .set_clipboard(self.selection_text(pane));
+                    .set_clipboard(Clipboard::Clipboard, self.selection_text(pane));
+            }
+            CopyAs(format) => {
+                self.window
+                    .as_ref()
+                    .unwrap()
+                    .set_clipboard(Clipboard::Clipboard, self.selection_as(pane, *format));
             }
             Paste => {
                 self.paste_from_clipboard(pane, Clipboard::default());
@@ -1897,6 +2773,11 @@ impl TermWindow {
             MoveTabRelative(n) => self.move_tab_relative(*n)?,
             ScrollByPage(n) => self.scroll_by_page(*n)?,
             ScrollToPrompt(n) => self.scroll_to_prompt(*n)?,
+            ScrollToTop => self.scroll_to_top(pane),
+            ScrollToBottom => {
+                let dims = pane.get_dimensions();
+                self.scroll_viewport(pane.pane_id(), None, dims);
+            }
             ShowTabNavigator => self.show_tab_navigator(),
             ShowLauncher => self.show_launcher(),
             HideApplication => {
@@ -1913,47 +2794,8 @@ impl TermWindow {
             }
             OpenLinkAtMouseCursor => {
                 // They clicked on a link, so let's open it!
-                // We need to ensure that we spawn the `open` call outside of the context
-                // of our window loop; on Windows it can cause a panic due to
-                // triggering our WndProc recursively.
-                // We get that assurance for free as part of the async dispatch that we
-                // perform below; here we allow the user to define an `open-uri` event
-                // handler that can bypass the normal `open::that` functionality.
                 if let Some(link) = self.current_highlight.as_ref().cloned() {
-                    let window = GuiWin::new(self);
-                    let pane = PaneObject::new(pane);
-
-                    async fn open_uri(
-                        lua: Option<Rc<mlua::Lua>>,
-                        window: GuiWin,
-                        pane: PaneObject,
-                        link: String,
-                    ) -> anyhow::Result<()> {
-                        let default_click = match lua {
-                            Some(lua) => {
-                                let args = lua.pack_multi((window, pane, link.clone()))?;
-                                config::lua::emit_event(&lua, ("open-uri".to_string(), args))
-                                    .await
-                                    .map_err(|e| {
-                                        log::error!("while processing open-uri event: {:#}", e);
-                                        e
-                                    })?
-                            }
-                            None => true,
-                        };
-                        if default_click {
-                            log::error!("clicking {}", link);
-                            if let Err(err) = open::that(&link) {
-                                log::error!("failed to open {}: {:?}", link, err);
-                            }
-                        }
-                        Ok(())
-                    }
-
-                    promise::spawn::spawn(config::with_lua_config_on_main_thread(move |lua| {
-                        open_uri(lua, window, pane, link.uri().to_string())
-                    }))
-                    .detach();
+                    self.open_uri(pane, link.uri().to_string());
                 }
             }
             EmitEvent(name) => {
@@ -1987,10 +2829,10 @@ impl TermWindow {
             CompleteSelectionOrOpenLinkAtMouseCursor => {
                 let text = self.selection_text(pane);
                 if !text.is_empty() {
-                    let window = self.window.as_ref().unwrap();
-                    window.set_clipboard(text);
-                    window.invalidate();
-                } else {
+                    self.complete_selection(text)?;
+                } else if configuration().open_link_modifier == termwiz::input::Modifiers::NONE {
+                    // When open_link_modifier is set, links are opened
+                    // from mouse_event_terminal instead
                     return self
                         .perform_key_assignment(pane, &KeyAssignment::OpenLinkAtMouseCursor);
                 }
@@ -1998,13 +2840,16 @@ impl TermWindow {
             CompleteSelection => {
                 let text = self.selection_text(pane);
                 if !text.is_empty() {
-                    let window = self.window.as_ref().unwrap();
-                    window.set_clipboard(text);
-                    window.invalidate();
+                    self.complete_selection(text)?;
                 }
             }
+            DumpScreen(dump) => self.dump_screen(pane, dump),
             ClearScrollback => {
                 pane.erase_scrollback();
+                // The viewport may have been showing lines that were
+                // just cleared; clamp it to what remains
+                let viewport = self.get_viewport(pane.pane_id());
+                self.set_viewport(pane.pane_id(), viewport, pane.get_dimensions());
                 let window = self.window.as_ref().unwrap();
                 window.invalidate();
             }
@@ -2020,6 +2865,12 @@ impl TermWindow {
                     self.assign_overlay_for_pane(pane.pane_id(), copy);
                 }
             }
+            QuickSelect => {
+                if let Some(pane) = self.get_active_pane_no_overlay() {
+                    let quick_select = QuickSelectOverlay::with_pane(self, &pane);
+                    self.assign_overlay_for_pane(pane.pane_id(), quick_select);
+                }
+            }
             AdjustPaneSize(direction, amount) => {
                 let mux = Mux::get().unwrap();
                 let tab = match mux.get_active_tab_for_window(self.mux_window_id) {
@@ -2060,7 +2911,7 @@ impl TermWindow {
 
     fn apply_scale_change(&mut self, dimensions: &Dimensions, font_scale: f64) {
         self.fonts
-            .change_scaling(font_scale, dimensions.dpi as f64 / 96.);
+            .change_scaling(font_scale, dimensions.scale_factor());
         self.render_metrics = RenderMetrics::new(&self.fonts);
 
         self.recreate_texture_atlas(None)
@@ -2085,52 +2936,40 @@ impl TermWindow {
         // change to the tab size.
 
         let config = configuration();
+        let layout = CellLayout {
+            cell_width: self.render_metrics.cell_size.width as usize,
+            cell_height: self.render_metrics.cell_size.height as usize,
+            horizontal_padding: (config.window_padding.left + self.effective_right_padding(&config))
+                as usize,
+            vertical_padding: (config.window_padding.top + config.window_padding.bottom) as usize,
+            show_tab_bar: self.show_tab_bar,
+        };
+        let pending_scale_resize = self.pending_scale_resize.take();
 
         let (size, dims) = if let Some(cell_dims) = scale_changed_cells {
             // Scaling preserves existing terminal dimensions, yielding a new
             // overall set of window dimensions
-            let size = PtySize {
-                rows: cell_dims.rows as u16,
-                cols: cell_dims.cols as u16,
-                pixel_height: cell_dims.rows as u16 * self.render_metrics.cell_size.height as u16,
-                pixel_width: cell_dims.cols as u16 * self.render_metrics.cell_size.width as u16,
-            };
-
-            let rows = size.rows + if self.show_tab_bar { 1 } else { 0 };
-            let cols = size.cols;
-
-            let pixel_height = (rows * self.render_metrics.cell_size.height as u16)
-                + (config.window_padding.top + config.window_padding.bottom);
-
-            let pixel_width = (cols * self.render_metrics.cell_size.width as u16)
-                + (config.window_padding.left + self.effective_right_padding(&config));
+            let (size, pixel_width, pixel_height) = layout.window_for_cells(cell_dims);
 
             let dims = Dimensions {
-                pixel_width: pixel_width as usize,
-                pixel_height: pixel_height as usize,
+                pixel_width,
+                pixel_height,
                 dpi: dimensions.dpi,
             };
 
             (size, dims)
+        } else if is_intermediate_resize(pending_scale_resize.as_ref(), dimensions, Instant::now())
+        {
+            // The window hasn't yet reached the size that we asked for
+            // when the scale changed; recomputing the cells from this
+            // size would resize the tabs twice, and may leave them off
+            // by a row or column, so keep the preserved size until the
+            // window catches up.
+            self.pending_scale_resize = pending_scale_resize;
+            (self.terminal_size, *dimensions)
         } else {
             // Resize of the window dimensions may result in changed terminal dimensions
-            let avail_width = dimensions.pixel_width.saturating_sub(
-                (config.window_padding.left + self.effective_right_padding(&config)) as usize,
-            );
-            let avail_height = dimensions.pixel_height.saturating_sub(
-                (config.window_padding.top + config.window_padding.bottom) as usize,
-            );
-
-            let rows = (avail_height / self.render_metrics.cell_size.height as usize)
-                .saturating_sub(if self.show_tab_bar { 1 } else { 0 });
-            let cols = avail_width / self.render_metrics.cell_size.width as usize;
-
-            let size = PtySize {
-                rows: rows as u16,
-                cols: cols as u16,
-                pixel_height: avail_height as u16,
-                pixel_width: avail_width as u16,
-            };
+            let size = layout.pty_size_for_window(dimensions.pixel_width, dimensions.pixel_height);
 
             (size, *dimensions)
         };
@@ -2157,7 +2996,9 @@ impl TermWindow {
         let mux = Mux::get().unwrap();
         if let Some(window) = mux.get_window(self.mux_window_id) {
             for tab in window.iter() {
-                tab.resize(size);
+                if tab_needs_resize(tab.get_size(), size) {
+                    tab.resize(size);
+                }
             }
         };
         self.update_title();
@@ -2166,6 +3007,10 @@ impl TermWindow {
         if let Some(cell_dims) = scale_changed_cells {
             if let Some(window) = self.window.as_ref() {
                 log::error!("scale changed so resize to {:?} {:?}", cell_dims, dims);
+                self.pending_scale_resize = Some(PendingScaleResize {
+                    dimensions: dims,
+                    requested: Instant::now(),
+                });
                 window.set_inner_size(dims.pixel_width, dims.pixel_height);
             }
         }
@@ -2194,14 +3039,43 @@ impl TermWindow {
         self.apply_dimensions(&dimensions, scale_changed_cells);
     }
 
+    /// Changes the font scale of the active tab, and so of the window
+    fn set_active_tab_font_scale(&mut self, font_scale: f64) {
+        let mux = Mux::get().unwrap();
+        if let Some(tab) = mux.get_active_tab_for_window(self.mux_window_id) {
+            self.tab_state(tab.tab_id()).font_scale = Some(font_scale);
+        }
+        self.scaling_changed(self.dimensions, font_scale);
+    }
+
+    /// Applies the font scale of the active tab to the window, when it
+    /// differs from that of the tab that was active before.  As with
+    /// the other font size changes, the rows and columns are preserved
+    /// and the window is resized to fit them.
+    fn apply_active_tab_font_scale(&mut self) {
+        let mux = Mux::get().unwrap();
+        let tab_id = match mux.get_active_tab_for_window(self.mux_window_id) {
+            Some(tab) => tab.tab_id(),
+            None => return,
+        };
+        let font_scale = font_scale_on_activation(
+            &self.tab_state.borrow(),
+            tab_id,
+            self.fonts.get_font_scale(),
+        );
+        if let Some(font_scale) = font_scale {
+            self.scaling_changed(self.dimensions, font_scale);
+        }
+    }
+
     fn decrease_font_size(&mut self) {
-        self.scaling_changed(self.dimensions, self.fonts.get_font_scale() * 0.9);
+        self.set_active_tab_font_scale(self.fonts.get_font_scale() * 0.9);
     }
     fn increase_font_size(&mut self) {
-        self.scaling_changed(self.dimensions, self.fonts.get_font_scale() * 1.1);
+        self.set_active_tab_font_scale(self.fonts.get_font_scale() * 1.1);
     }
     fn reset_font_size(&mut self) {
-        self.scaling_changed(self.dimensions, 1.);
+        self.set_active_tab_font_scale(1.);
     }
 
     fn close_current_pane(&mut self, confirm: bool) {
@@ -2257,6 +3131,14 @@ impl TermWindow {
         self.activate_tab_relative(0)
     }
 
+    fn tab_bar_rows(&self) -> TabBarRows {
+        TabBarRows::new(
+            self.show_tab_bar,
+            configuration().tab_bar_at_bottom,
+            self.terminal_size.rows as usize,
+        )
+    }
+
     fn effective_right_padding(&self, config: &ConfigHandle) -> u16 {
         effective_right_padding(config, &self.render_metrics)
     }
@@ -2279,44 +3161,23 @@ impl TermWindow {
         let foreground = rgbcolor_to_window_color(palette.split);
         let background = rgbcolor_alpha_to_window_color(
             palette.background,
-            if self.window_background.is_some() || config.window_background_opacity != 1.0 {
-                0x00
-            } else {
-                (config.text_background_opacity * 255.0) as u8
-            },
+            cell_background_alpha(
+                config.text_background_opacity,
+                self.window_background.is_some() || config.window_background_opacity != 1.0,
+                true,
+            ),
         );
 
         let style = self.fonts.match_style(&config, &CellAttributes::default());
-        let glyph_info = {
-            let key = BorrowedShapeCacheKey { style, text };
-            match self.lookup_cached_shape(&key) {
-                Some(Ok(info)) => info,
-                Some(Err(err)) => return Err(err),
-                None => {
-                    let font = self.fonts.resolve_font(style)?;
-                    match font.shape(text) {
-                        Ok(info) => {
-                            self.shape_cache
-                                .borrow_mut()
-                                .put(key.to_owned(), Ok(Rc::new(info)));
-                            self.lookup_cached_shape(&key).unwrap().unwrap()
-                        }
-                        Err(err) => {
-                            let res = anyhow!("shaper error: {}", err);
-                            self.shape_cache.borrow_mut().put(key.to_owned(), Err(err));
-                            return Err(res);
-                        }
-                    }
-                }
-            }
-        };
-        let first_row_offset = if self.show_tab_bar { 1 } else { 0 };
+        let glyph_info = self.shape_text(style, text)?;
+        let first_row_offset = self.tab_bar_rows().first_line_offset;
 
         for info in glyph_info.iter() {
-            let glyph = gl_state
-                .glyph_cache
-                .borrow_mut()
-                .cached_glyph(info, style)?;
+            let glyph =
+                gl_state
+                    .glyph_cache
+                    .borrow_mut()
+                    .cached_glyph(info, style, palette.split)?;
 
             let left = (glyph.x_offset + glyph.bearing_x).get() as f32;
             let top = ((PixelLength::new(self.render_metrics.cell_size.height as f64)
@@ -2373,9 +3234,17 @@ impl TermWindow {
         Ok(())
     }
 
-    fn paint_opengl_pass(&mut self) -> anyhow::Result<()> {
+    /// `restore_rows` is true if rows were moved by smooth scrolling
+    /// when the window was last painted, and must be put back
+    fn paint_opengl_pass(&mut self, restore_rows: bool) -> anyhow::Result<()> {
         let panes = self.get_panes_to_render();
 
+        if restore_rows {
+            let gl_state = self.render_state.opengl();
+            let mut vb = gl_state.glyph_vertex_buffer.borrow_mut();
+            gl_state.quads.map(&mut vb).hide_overflow();
+        }
+
         if let Some(pane) = self.get_active_pane_or_overlay() {
             let splits = self.get_splits();
             for split in &splits {
@@ -2387,18 +3256,23 @@ impl TermWindow {
             if pos.is_active {
                 self.update_text_cursor(&pos.pane);
             }
-            self.paint_pane_opengl(&pos)?;
+            self.paint_pane_opengl(&pos, restore_rows)?;
         }
 
         Ok(())
     }
 
-    fn paint_pane_opengl(&mut self, pos: &PositionedPane) -> anyhow::Result<()> {
+    fn paint_pane_opengl(
+        &mut self,
+        pos: &PositionedPane,
+        restore_rows: bool,
+    ) -> anyhow::Result<()> {
         let config = configuration();
-        let palette = pos.pane.palette();
+        let palette = self.apply_visual_bell(pos.pane.palette(), &config);
 
         let background_color = palette.resolve_bg(wezterm_term::color::ColorAttribute::Default);
-        let first_line_offset = if self.show_tab_bar { 1 } else { 0 };
+        let tab_bar_rows = self.tab_bar_rows();
+        let first_line_offset = tab_bar_rows.first_line_offset;
 
         let cursor = pos.pane.get_cursor_position();
         if pos.is_active {
@@ -2408,33 +3282,76 @@ impl TermWindow {
         let current_viewport = self.get_viewport(pos.pane.pane_id());
         let (stable_top, lines);
         let dims = pos.pane.get_dimensions();
+        let viewport_top = current_viewport.unwrap_or(dims.physical_top);
+
+        // While smooth scrolling, the top of the display may be part
+        // way through a row; start from that row and shift everything
+        // up by the fraction that is scrolled out of view.
+        let (first_row, y_offset) =
+            match self.animated_viewport_top(pos.pane.pane_id(), viewport_top) {
+                Some(top) => {
+                    self.schedule_animation_frame(&config);
+                    self.rows_shifted = true;
+                    let cell_height = self.render_metrics.cell_size.height as f32;
+                    (
+                        top.floor() as StableRowIndex,
+                        Some((top.floor() - top) * cell_height),
+                    )
+                }
+                None => (viewport_top, None),
+            };
 
         {
-            let stable_range = match current_viewport {
-                Some(top) => top..top + dims.viewport_rows as StableRowIndex,
-                None => dims.physical_top..dims.physical_top + dims.viewport_rows as StableRowIndex,
-            };
+            // While scrolling, part of the row after the last one
+            // is scrolled into view at the bottom of the pane
+            let num_rows = dims.viewport_rows + if y_offset.is_some() { 1 } else { 0 };
+            let stable_range = first_row..first_row + num_rows as StableRowIndex;
 
             let (top, vp_lines) = pos.pane.get_lines(stable_range);
             stable_top = top;
             lines = vp_lines;
         }
+        {
+            let mut state = self.pane_state(pos.pane.pane_id());
+            state.blinking_rows = blinking_rows(stable_top, &lines);
+            state.blink_dirty_rows = RangeSet::new();
+        }
 
         let gl_state = self.render_state.opengl();
         let mut vb = gl_state.glyph_vertex_buffer.borrow_mut();
         let mut quads = gl_state.quads.map(&mut vb);
 
+        // Rows are only moved, and clipped to the pane, while it is
+        // smooth scrolling and on the frame after that puts them back
+        let restore = if restore_rows {
+            Some(RowShift::restore())
+        } else {
+            None
+        };
+        let shift = match y_offset {
+            Some(y_offset) => {
+                let pane_top = quads.row_top(first_line_offset + pos.top)?;
+                let pane_height =
+                    dims.viewport_rows as f32 * self.render_metrics.cell_size.height as f32;
+                Some(RowShift {
+                    y_offset,
+                    clip: (pane_top, pane_top + pane_height),
+                })
+            }
+            None => restore,
+        };
+
         let cursor_border_color = rgbcolor_to_window_color(palette.cursor_border);
         let foreground = rgbcolor_to_window_color(palette.foreground);
 
-        if self.show_tab_bar && pos.index == 0 {
+        if let (Some(tab_bar_row), 0) = (tab_bar_rows.tab_bar_row, pos.index) {
             let tab_dims = RenderableDimensions {
                 cols: self.terminal_size.cols as _,
                 ..dims
             };
             self.render_screen_line_opengl(
                 RenderScreenLineOpenGLParams {
-                    line_idx: 0,
+                    line_idx: tab_bar_row,
                     stable_line_idx: None,
                     line: self.tab_bar.line(),
                     selection: 0..0,
@@ -2445,6 +3362,8 @@ impl TermWindow {
                     cursor_border_color,
                     foreground,
                     pos,
+                    shift: restore,
+                    overflow: false,
                     is_active: true,
                 },
                 &mut quads,
@@ -2510,7 +3429,13 @@ impl TermWindow {
 
             if let Some(im) = self.window_background.as_ref() {
                 let sprite = gl_state.glyph_cache.borrow_mut().cached_image(im, None)?;
-                quad.set_texture(sprite.texture_coords());
+                quad.set_texture(background_image_texture_rect(
+                    sprite.texture_coords(),
+                    sprite.coords.size.width as f32,
+                    sprite.coords.size.height as f32,
+                    self.dimensions.pixel_width as f32,
+                    self.dimensions.pixel_height as f32,
+                ));
                 quad.set_is_background_image();
             } else {
                 quad.set_texture(white_space);
@@ -2523,6 +3448,47 @@ impl TermWindow {
             quad.set_bg_color(color);
         }
 
+        if pos.is_active {
+            let bottom_left_background = lines
+                .get(dims.viewport_rows.saturating_sub(1))
+                .or_else(|| lines.last())
+                .and_then(|line| line.cells().first())
+                .map(|cell| cell.attrs().background);
+            let padding_color = resolve_window_padding_color(
+                config.window_padding_color,
+                &palette,
+                bottom_left_background,
+            );
+            let background_alpha = (config.window_background_opacity * 255.0) as u8;
+            let white_space = gl_state.util_sprites.white_space.texture_coords();
+
+            for idx in 0..PADDING_QUADS {
+                let mut quad = quads.padding(idx);
+                quad.set_texture(white_space);
+                quad.set_texture_adjust(0., 0., 0., 0.);
+                quad.set_underline(white_space);
+                quad.set_cursor(white_space);
+                quad.set_hsv(None);
+                match padding_color {
+                    Some(color) => {
+                        let color = rgbcolor_alpha_to_window_color(color, background_alpha);
+                        quad.set_is_background();
+                        quad.set_cursor_color(color);
+                        quad.set_fg_color(color);
+                        quad.set_bg_color(color);
+                    }
+                    None => {
+                        // Leave the window background showing through
+                        let clear = rgbcolor_alpha_to_window_color(palette.background, 0);
+                        quad.set_has_color(false);
+                        quad.set_cursor_color(clear);
+                        quad.set_fg_color(clear);
+                        quad.set_bg_color(clear);
+                    }
+                }
+            }
+        }
+
         let selrange = self.selection(pos.pane.pane_id()).range.clone();
 
         for (line_idx, line) in lines.iter().enumerate() {
@@ -2544,6 +3510,8 @@ impl TermWindow {
                     cursor_border_color,
                     foreground,
                     pos,
+                    shift,
+                    overflow: line_idx == dims.viewport_rows,
                     is_active: pos.is_active,
                 },
                 &mut quads,
@@ -2644,6 +3612,7 @@ impl TermWindow {
         )?;
 
         // Pass 3: Draw glyphs
+        let config = configuration();
         frame.draw(
             &*vb,
             &gl_state.glyph_index_buffer,
@@ -2655,6 +3624,8 @@ impl TermWindow {
                 window_bg_layer: false,
                 bg_and_line_layer: false,
                 has_background_image: has_background_image,
+                linear_blending: config.font_linear_blending,
+                subpixel_aa: config.font_antialias == FontAntiAliasing::Subpixel,
             },
             &draw_params,
         )?;
@@ -2662,17 +3633,29 @@ impl TermWindow {
         Ok(())
     }
 
-    fn lookup_cached_shape(
-        &self,
-        key: &dyn ShapeCacheKeyTrait,
-    ) -> Option<anyhow::Result<Rc<Vec<GlyphInfo>>>> {
-        match self.shape_cache.borrow_mut().get(key) {
-            Some(Ok(info)) => Some(Ok(Rc::clone(info))),
-            Some(Err(err)) => Some(Err(anyhow!("cached shaper error: {}", err))),
-            None => None,
+    /// Collects the cache hit counts accumulated while painting,
+    /// and reports them as metrics when running a render benchmark
+    fn report_cache_counts(&self) {
+        let shapes = std::mem::take(&mut self.shape_cache.borrow_mut().counts);
+        let glyphs = self.render_state.take_glyph_cache_counts();
+        if crate::benchmark::is_active() {
+            metrics::counter!("gui.shape_cache.hit", shapes.hits);
+            metrics::counter!("gui.shape_cache.miss", shapes.misses);
+            metrics::counter!("glyph_cache.glyph.hit", glyphs.hits);
+            metrics::counter!("glyph_cache.glyph.miss", glyphs.misses);
         }
     }
 
+    /// Shapes `text` in the specified style, reusing the glyphs from
+    /// the last time that we saw it if possible
+    fn shape_text(&self, style: &TextStyle, text: &str) -> anyhow::Result<Rc<Vec<GlyphInfo>>> {
+        cached_shape(
+            &self.shape_cache,
+            BorrowedShapeCacheKey { style, text },
+            || self.fonts.resolve_font(style)?.shape(text),
+        )
+    }
+
     /// "Render" a line of the terminal screen into the vertex buffer.
     /// This is nominally a matter of setting the fg/bg color and the
     /// texture coordinates for a given glyph.  There's a little bit
@@ -2685,6 +3668,9 @@ impl TermWindow {
         let gl_state = self.render_state.opengl();
 
         let num_cols = params.dims.cols;
+        let line_size = params.line.line_size();
+        let cell_width = self.render_metrics.cell_size.width as f32;
+        let cell_height = self.render_metrics.cell_size.height as f32;
 
         let hsv = if params.is_active {
             None
@@ -2704,11 +3690,10 @@ impl TermWindow {
         // individual cells to a single double-wide cell then we might leave the second
         // one of the pair with the glyph from the prior viewport position.
         for cell_idx in 0..num_cols {
-            let mut quad =
-                match quads.cell(cell_idx + params.pos.left, params.line_idx + params.pos.top) {
-                    Ok(quad) => quad,
-                    Err(_) => break,
-                };
+            let mut quad = match params.quad(quads, cell_idx) {
+                Ok(quad) => quad,
+                Err(_) => break,
+            };
 
             quad.set_texture(white_space);
             quad.set_texture_adjust(0., 0., 0., 0.);
@@ -2729,31 +3714,12 @@ impl TermWindow {
 
             let bg_is_default = attrs.background == ColorAttribute::Default;
             let bg_color = params.palette.resolve_bg(attrs.background);
-            let fg_color = match attrs.foreground {
-                wezterm_term::color::ColorAttribute::Default => {
-                    if let Some(fg) = style.foreground {
-                        fg
-                    } else {
-                        params.palette.resolve_fg(attrs.foreground)
-                    }
-                }
-                wezterm_term::color::ColorAttribute::PaletteIndex(idx)
-                    if idx < 8 && params.config.bold_brightens_ansi_colors =>
-                {
-                    // For compatibility purposes, switch to a brighter version
-                    // of one of the standard ANSI colors when Bold is enabled.
-                    // This lifts black to dark grey.
-                    let idx = if attrs.intensity() == wezterm_term::Intensity::Bold {
-                        idx + 8
-                    } else {
-                        idx
-                    };
-                    params
-                        .palette
-                        .resolve_fg(wezterm_term::color::ColorAttribute::PaletteIndex(idx))
-                }
-                _ => params.palette.resolve_fg(attrs.foreground),
-            };
+            let fg_color = cell_foreground_color(
+                attrs,
+                style.foreground,
+                params.palette,
+                params.config.bold_brightens_ansi_colors,
+            );
 
             let (fg_color, bg_color, bg_is_default) = {
                 let mut fg = fg_color;
@@ -2769,42 +3735,38 @@ impl TermWindow {
             };
 
             let glyph_color = rgbcolor_to_window_color(fg_color);
+            let underline_color = cell_underline_color(attrs, params.palette);
 
             let bg_color = rgbcolor_alpha_to_window_color(
                 bg_color,
-                if window_is_transparent && bg_is_default {
-                    0x00
-                } else {
-                    (params.config.text_background_opacity * 255.0) as u8
-                },
+                cell_background_alpha(
+                    params.config.text_background_opacity,
+                    window_is_transparent,
+                    bg_is_default,
+                ),
             );
 
             // Shape the printable text from this cluster
-            let glyph_info = {
-                let key = BorrowedShapeCacheKey {
-                    style,
-                    text: &cluster.text,
-                };
-                match self.lookup_cached_shape(&key) {
-                    Some(Ok(info)) => info,
-                    Some(Err(err)) => return Err(err),
-                    None => {
+            let glyph_info = self.shape_text(style, &cluster.text)?;
+
+            // Break up any ligature that sits under the cursor
+            let glyph_info = if params.config.cursor_splits_ligatures
+                && params.stable_line_idx == Some(params.cursor.y)
+                && params.cursor.visibility == CursorVisibility::Visible
+            {
+                match cluster
+                    .byte_to_cell_idx
+                    .iter()
+                    .position(|&cell_idx| cell_idx == params.cursor.x)
+                {
+                    Some(byte_idx) => {
                         let font = self.fonts.resolve_font(style)?;
-                        match font.shape(&cluster.text) {
-                            Ok(info) => {
-                                self.shape_cache
-                                    .borrow_mut()
-                                    .put(key.to_owned(), Ok(Rc::new(info)));
-                                self.lookup_cached_shape(&key).unwrap().unwrap()
-                            }
-                            Err(err) => {
-                                let res = anyhow!("shaper error: {}", err);
-                                self.shape_cache.borrow_mut().put(key.to_owned(), Err(err));
-                                return Err(res);
-                            }
-                        }
+                        Rc::new(font.split_ligature_at(&cluster.text, &glyph_info, byte_idx)?)
                     }
+                    None => glyph_info,
                 }
+            } else {
+                glyph_info
             };
 
             for info in glyph_info.iter() {
@@ -2827,10 +3789,28 @@ impl TermWindow {
                     continue;
                 }
 
-                let glyph = gl_state
-                    .glyph_cache
-                    .borrow_mut()
-                    .cached_glyph(info, style)?;
+                // Box drawing and block elements may be drawn from
+                // geometry rather than from the font, so that they
+                // join up with their neighbors
+                let custom_glyph = if params.config.custom_block_glyphs && info.num_cells == 1 {
+                    cluster.text[info.cluster as usize..]
+                        .chars()
+                        .next()
+                        .filter(|&c| is_custom_glyph(c))
+                } else {
+                    None
+                };
+
+                let glyph = match custom_glyph {
+                    Some(c) => gl_state
+                        .glyph_cache
+                        .borrow_mut()
+                        .cached_custom_glyph(c, &self.render_metrics)?,
+                    None => gl_state
+                        .glyph_cache
+                        .borrow_mut()
+                        .cached_glyph(info, style, fg_color)?,
+                };
 
                 let left = (glyph.x_offset + glyph.bearing_x).get() as f32;
                 let top = ((PixelLength::new(self.render_metrics.cell_size.height as f64)
@@ -2855,7 +3835,8 @@ impl TermWindow {
                 for glyph_idx in 0..info.num_cells as usize {
                     let cell_idx = cell_idx + glyph_idx;
 
-                    if cell_idx >= num_cols {
+                    let columns = display_columns_for_cell(line_size, cell_idx);
+                    if columns.end > num_cols {
                         // terminal line data is wider than the window.
                         // This happens for example while live resizing the window
                         // smaller than the terminal.
@@ -2867,6 +3848,7 @@ impl TermWindow {
                     let ComputeCellFgBgResult {
                         fg_color: glyph_color,
                         bg_color,
+                        underline_color,
                         cursor_shape,
                     } = self.compute_cell_fg_bg(ComputeCellFgBgParams {
                         stable_line_idx: params.stable_line_idx,
@@ -2875,11 +3857,43 @@ impl TermWindow {
                         selection: &params.selection,
                         fg_color: glyph_color,
                         bg_color,
+                        underline_color,
+                        blink: attrs.blink(),
+                        intensity: attrs.intensity(),
                         palette: params.palette,
                         is_active_pane: params.pos.is_active,
                         config: params.config,
                     });
 
+                    // On a double width line the cell also covers the next
+                    // display column; that shares the background, underline
+                    // and cursor while the glyph is stretched across both
+                    // from the first column.
+                    for column in columns.start + 1..columns.end {
+                        let mut quad = match quads
+                            .cell(column + params.pos.left, params.line_idx + params.pos.top)
+                        {
+                            Ok(quad) => quad,
+                            Err(_) => break,
+                        };
+
+                        quad.set_fg_color(glyph_color);
+                        quad.set_bg_color(bg_color);
+                        quad.set_texture(white_space);
+                        quad.set_texture_adjust(0., 0., 0., 0.);
+                        quad.set_underline(underline_tex_rect);
+                        quad.set_underline_color(underline_color);
+                        quad.set_hsv(hsv);
+                        quad.set_has_color(false);
+                        quad.set_cursor(
+                            gl_state
+                                .util_sprites
+                                .cursor_sprite(cursor_shape)
+                                .texture_coords(),
+                        );
+                        quad.set_cursor_color(params.cursor_border_color);
+                    }
+
                     if let Some(image) = attrs.image() {
                         // Render iTerm2 style image attributes
 
@@ -2926,9 +3940,7 @@ impl TermWindow {
 
                         let texture_rect = TextureRect::new(origin, size);
 
-                        let mut quad = match quads
-                            .cell(cell_idx + params.pos.left, params.line_idx + params.pos.top)
-                        {
+                        let mut quad = match params.quad(quads, columns.start) {
                             Ok(quad) => quad,
                             Err(_) => break,
                         };
@@ -2937,7 +3949,12 @@ impl TermWindow {
                         quad.set_fg_color(glyph_color);
                         quad.set_bg_color(bg_color);
                         quad.set_texture(texture_rect);
-                        quad.set_texture_adjust(0., 0., 0., 0.);
+                        quad.set_texture_adjust(
+                            0.,
+                            0.,
+                            (columns.len() - 1) as f32 * cell_width,
+                            0.,
+                        );
                         quad.set_underline(white_space);
                         quad.set_has_color(true);
                         quad.set_cursor(
@@ -2968,14 +3985,19 @@ impl TermWindow {
                     let texture_rect = texture.texture.to_texture_coords(pixel_rect);
 
                     let left = if glyph_idx == 0 { left } else { 0.0 };
-                    let bottom = (pixel_rect.size.height as f32 * glyph.scale as f32) + top
-                        - self.render_metrics.cell_size.height as f32;
-                    let right = pixel_rect.size.width as f32 + left
-                        - self.render_metrics.cell_size.width as f32;
+                    let (rect, texture_rect) = scale_glyph_for_line_size(
+                        line_size,
+                        cell_height,
+                        GlyphRect {
+                            left,
+                            top,
+                            right: pixel_rect.size.width as f32 + left,
+                            bottom: (pixel_rect.size.height as f32 * glyph.scale as f32) + top,
+                        },
+                        texture_rect,
+                    );
 
-                    let mut quad = match quads
-                        .cell(cell_idx + params.pos.left, params.line_idx + params.pos.top)
-                    {
+                    let mut quad = match params.quad(quads, columns.start) {
                         Ok(quad) => quad,
                         Err(_) => break,
                     };
@@ -2983,8 +4005,14 @@ impl TermWindow {
                     quad.set_fg_color(glyph_color);
                     quad.set_bg_color(bg_color);
                     quad.set_texture(texture_rect);
-                    quad.set_texture_adjust(left, top, right, bottom);
+                    quad.set_texture_adjust(
+                        rect.left,
+                        rect.top,
+                        rect.right - cell_width,
+                        rect.bottom - cell_height,
+                    );
                     quad.set_underline(underline_tex_rect);
+                    quad.set_underline_color(underline_color);
                     quad.set_hsv(hsv);
                     quad.set_has_color(glyph.has_color);
                     quad.set_cursor(
@@ -3005,7 +4033,12 @@ impl TermWindow {
         // the right pane with its prior contents instead of showing the
         // cleared lines from the shell in the main screen.
 
-        for cell_idx in last_cell_idx.unwrap_or(0) + 1..num_cols {
+        for cell_idx in last_cell_idx.unwrap_or(0) + 1.. {
+            let columns = display_columns_for_cell(line_size, cell_idx);
+            if columns.start >= num_cols {
+                break;
+            }
+
             // Even though we don't have a cell for these, they still
             // hold the cursor or the selection so we need to compute
             // the colors in the usual way.
@@ -3014,6 +4047,7 @@ impl TermWindow {
                 fg_color: glyph_color,
                 bg_color,
                 cursor_shape,
+                ..
             } = self.compute_cell_fg_bg(ComputeCellFgBgParams {
                 stable_line_idx: params.stable_line_idx,
                 cell_idx,
@@ -3022,37 +4056,41 @@ impl TermWindow {
                 fg_color: params.foreground,
                 bg_color: rgbcolor_alpha_to_window_color(
                     params.palette.resolve_bg(ColorAttribute::Default),
-                    if window_is_transparent {
-                        0x00
-                    } else {
-                        (params.config.text_background_opacity * 255.0) as u8
-                    },
+                    cell_background_alpha(
+                        params.config.text_background_opacity,
+                        window_is_transparent,
+                        true,
+                    ),
                 ),
+                underline_color: None,
+                blink: Blink::None,
+                intensity: wezterm_term::Intensity::Normal,
                 palette: params.palette,
                 is_active_pane: params.pos.is_active,
                 config: params.config,
             });
 
-            let mut quad =
-                match quads.cell(cell_idx + params.pos.left, params.line_idx + params.pos.top) {
+            for column in columns.start..columns.end.min(num_cols) {
+                let mut quad = match params.quad(quads, column) {
                     Ok(quad) => quad,
                     Err(_) => break,
                 };
 
-            quad.set_bg_color(bg_color);
-            quad.set_fg_color(glyph_color);
-            quad.set_texture(white_space);
-            quad.set_texture_adjust(0., 0., 0., 0.);
-            quad.set_underline(white_space);
-            quad.set_has_color(false);
-            quad.set_hsv(hsv);
-            quad.set_cursor(
-                gl_state
-                    .util_sprites
-                    .cursor_sprite(cursor_shape)
-                    .texture_coords(),
-            );
-            quad.set_cursor_color(params.cursor_border_color);
+                quad.set_bg_color(bg_color);
+                quad.set_fg_color(glyph_color);
+                quad.set_texture(white_space);
+                quad.set_texture_adjust(0., 0., 0., 0.);
+                quad.set_underline(white_space);
+                quad.set_has_color(false);
+                quad.set_hsv(hsv);
+                quad.set_cursor(
+                    gl_state
+                        .util_sprites
+                        .cursor_sprite(cursor_shape)
+                        .texture_coords(),
+                );
+                quad.set_cursor_color(params.cursor_border_color);
+            }
         }
 
         Ok(())
@@ -3075,60 +4113,82 @@ impl TermWindow {
                     .config
                     .default_cursor_style
                     .effective_shape(params.cursor.shape);
-                // Work out the blinking shape if its a blinking cursor and it hasn't been disabled
-                // and the window is focused.
-                let blinking = params.is_active_pane
-                    && shape.is_blinking()
-                    && params.config.cursor_blink_rate != 0
-                    && self.focused.is_some();
-                if blinking {
-                    // Divide the time since we last moved by the blink rate.
-                    // If the result is even then the cursor is "on", else it
-                    // is "off"
-                    let now = std::time::Instant::now();
-                    let milli_uptime = now
-                        .duration_since(self.prev_cursor.last_cursor_movement())
-                        .as_millis();
-                    let ticks = milli_uptime / params.config.cursor_blink_rate as u128;
-                    (
+                (
+                    shape,
+                    cursor_blink_visibility(
                         shape,
-                        if (ticks & 1) == 0 {
-                            CursorVisibility::Visible
-                        } else {
-                            CursorVisibility::Hidden
-                        },
-                    )
-                } else {
-                    (shape, CursorVisibility::Visible)
-                }
+                        params.config.cursor_blink_rate,
+                        self.focused.is_some() && params.is_active_pane,
+                        self.prev_cursor.last_cursor_movement().elapsed(),
+                    ),
+                )
             } else {
                 (params.cursor.shape, CursorVisibility::Hidden)
             };
 
-        let (fg_color, bg_color) = match (
+        // Whether the cell is drawn in the colors of the block cursor
+        let block_cursor_colors = self.focused.is_some()
+            && params.is_active_pane
+            && visibility == CursorVisibility::Visible
+            && matches!(
+                cursor_shape,
+                CursorShape::BlinkingBlock | CursorShape::SteadyBlock
+            );
+
+        let (fg_color, bg_color, underline_color) = match (
             selected,
             self.focused.is_some() && params.is_active_pane,
             cursor_shape,
             visibility,
         ) {
             // Selected text overrides colors
-            (true, _, _, CursorVisibility::Hidden) => (
-                rgbcolor_to_window_color(params.palette.selection_fg),
-                rgbcolor_to_window_color(params.palette.selection_bg),
-            ),
+            (true, _, _, CursorVisibility::Hidden) => {
+                let fg = rgbcolor_to_window_color(params.palette.selection_fg);
+                (
+                    fg,
+                    rgbcolor_to_window_color(params.palette.selection_bg),
+                    fg,
+                )
+            }
             // Cursor cell overrides colors
             (_, true, CursorShape::BlinkingBlock, CursorVisibility::Visible)
-            | (_, true, CursorShape::SteadyBlock, CursorVisibility::Visible) => (
-                rgbcolor_to_window_color(params.palette.cursor_fg),
-                rgbcolor_to_window_color(params.palette.cursor_bg),
+            | (_, true, CursorShape::SteadyBlock, CursorVisibility::Visible) => {
+                let fg = rgbcolor_to_window_color(params.palette.cursor_fg);
+                (fg, rgbcolor_to_window_color(params.palette.cursor_bg), fg)
+            }
+            // Blinking text in its "off" phase is drawn in the background color
+            _ if !text_blink_is_visible(
+                params.blink,
+                params.config.text_blink_rate,
+                params.config.text_blink_rate_rapid,
+                self.text_blink_epoch.elapsed(),
+            ) =>
+            {
+                (params.bg_color, params.bg_color, params.bg_color)
+            }
+            // Normally, render the cell as configured (or if the window is unfocused).
+            // The underline uses the text color unless the cell specifies otherwise.
+            _ => (
+                params.fg_color,
+                params.bg_color,
+                params.underline_color.unwrap_or(params.fg_color),
             ),
-            // Normally, render the cell as configured (or if the window is unfocused)
-            _ => (params.fg_color, params.bg_color),
         };
 
+        let (fg_color, underline_color) = adjust_text_colors(
+            fg_color,
+            bg_color,
+            underline_color,
+            params.intensity,
+            block_cursor_colors,
+            self.focused.is_some(),
+            params.config,
+        );
+
         ComputeCellFgBgResult {
             fg_color,
             bg_color,
+            underline_color,
             cursor_shape: if visibility == CursorVisibility::Visible {
                 Some(cursor_shape)
             } else {
@@ -3157,23 +4217,73 @@ impl TermWindow {
         self.pane_state(pane_id).viewport
     }
 
-    pub fn set_viewport(
+    /// Like set_viewport, but animates the change of position
+    /// if smooth_scroll is enabled
+    fn scroll_viewport(
         &mut self,
         pane_id: PaneId,
         position: Option<StableRowIndex>,
         dims: RenderableDimensions,
     ) {
-        let pos = match position {
-            Some(pos) => {
-                // Drop out of scrolling mode if we're off the bottom
-                if pos >= dims.physical_top {
-                    None
-                } else {
-                    Some(pos.max(dims.scrollback_top))
-                }
-            }
-            None => None,
+        let viewport_top = self.get_viewport(pane_id).unwrap_or(dims.physical_top);
+        let from = self
+            .animated_viewport_top(pane_id, viewport_top)
+            .unwrap_or(viewport_top as f32);
+
+        self.set_viewport(pane_id, position, dims);
+
+        if !configuration().smooth_scroll {
+            return;
+        }
+        let to = self.get_viewport(pane_id).unwrap_or(dims.physical_top) as f32;
+        self.pane_state(pane_id).scroll_animation = if (to - from).abs() > f32::EPSILON {
+            Some(ScrollAnimation {
+                from,
+                start: Instant::now(),
+            })
+        } else {
+            None
         };
+    }
+
+    /// If the pane is smooth scrolling towards `viewport_top`, returns
+    /// the top row that should currently be displayed
+    fn animated_viewport_top(&self, pane_id: PaneId, viewport_top: StableRowIndex) -> Option<f32> {
+        let mut state = self.pane_state(pane_id);
+        let animation = state.scroll_animation?;
+        let top = smooth_scroll_position(
+            animation.from,
+            viewport_top as f32,
+            animation.start.elapsed(),
+            SMOOTH_SCROLL_DURATION,
+        );
+        if top.is_none() {
+            state.scroll_animation = None;
+        }
+        top
+    }
+
+    /// Arranges to repaint the window after one frame of animation
+    fn schedule_animation_frame(&self, config: &ConfigHandle) {
+        let window = match self.window.as_ref() {
+            Some(window) => window.clone(),
+            None => return,
+        };
+        let frame = Duration::from_secs(1) / u32::from(config.animation_fps.max(1));
+        promise::spawn::spawn(async move {
+            smol::Timer::after(frame).await;
+            window.invalidate();
+        })
+        .detach();
+    }
+
+    pub fn set_viewport(
+        &mut self,
+        pane_id: PaneId,
+        position: Option<StableRowIndex>,
+        dims: RenderableDimensions,
+    ) {
+        let pos = clamp_viewport(position, &dims);
 
         let mut state = self.pane_state(pane_id);
         if pos != state.viewport {
@@ -3197,6 +4307,10 @@ impl TermWindow {
             WMEK::Press(MousePress::Left) => match self.tab_bar.hit_test(x) {
                 TabBarItem::Tab(tab_idx) => {
                     self.activate_tab(tab_idx as isize).ok();
+                    self.tab_drag_start = Some(tab_idx);
+                }
+                TabBarItem::CloseTab(tab_idx) => {
+                    self.close_tab_idx(tab_idx).ok();
                 }
                 TabBarItem::NewTabButton => {
                     self.spawn_tab(&SpawnTabDomain::CurrentPaneDomain);
@@ -3204,13 +4318,13 @@ impl TermWindow {
                 TabBarItem::None => {}
             },
             WMEK::Press(MousePress::Middle) => match self.tab_bar.hit_test(x) {
-                TabBarItem::Tab(tab_idx) => {
+                TabBarItem::Tab(tab_idx) | TabBarItem::CloseTab(tab_idx) => {
                     self.close_tab_idx(tab_idx).ok();
                 }
                 TabBarItem::NewTabButton | TabBarItem::None => {}
             },
             WMEK::Press(MousePress::Right) => match self.tab_bar.hit_test(x) {
-                TabBarItem::Tab(_) => {
+                TabBarItem::Tab(_) | TabBarItem::CloseTab(_) => {
                     self.show_tab_navigator();
                 }
                 TabBarItem::NewTabButton => {
@@ -3481,12 +4595,27 @@ impl TermWindow {
             .unwrap_or(dims.physical_top)
             + y as StableRowIndex;
 
-        self.last_mouse_terminal_coords = (x, stable_row); // FIXME: per-pane
-
         let (top, mut lines) = pane.get_lines(stable_row..stable_row + 1);
+
+        // The column reported to the terminal is the one under the
+        // mouse, but selection and hyperlinks work in terms of the
+        // cell that holds the glyph drawn there
+        let mut cell_x = x;
+        if top == stable_row {
+            if let Some(line) = lines.get(0) {
+                // Each cell of a double width line covers two display columns
+                if line.line_size().is_double_width() {
+                    x /= 2;
+                }
+                cell_x = cell_index_for_column(line, x);
+            }
+        }
+
+        self.last_mouse_terminal_coords = (cell_x, stable_row); // FIXME: per-pane
+
         let new_highlight = if top == stable_row {
             if let Some(line) = lines.get_mut(0) {
-                if let Some(cell) = line.cells().get(x) {
+                if let Some(cell) = line.cells().get(cell_x) {
                     cell.attrs().hyperlink().cloned()
                 } else {
                     None
@@ -3583,6 +4712,22 @@ impl TermWindow {
                 modifiers -= window_mods_to_termwiz_mods(ignore_grab_modifier);
             }
 
+            if event_trigger_type
+                == (MouseEventTrigger::Up {
+                    streak: 1,
+                    button: TMB::Left,
+                })
+                && click_opens_link(
+                    configuration().open_link_modifier,
+                    modifiers,
+                    self.current_highlight.is_some(),
+                )
+            {
+                self.perform_key_assignment(&pane, &KeyAssignment::OpenLinkAtMouseCursor)
+                    .ok();
+                return;
+            }
+
             if let Some(action) = self
                 .input_map
                 .lookup_mouse(event_trigger_type.clone(), modifiers)
@@ -3642,6 +4787,13 @@ impl TermWindow {
         }
     }
 
+    fn hide_mouse_cursor_while_typing(&mut self, context: &dyn WindowOps) {
+        let enabled = configuration().hide_mouse_cursor_when_typing;
+        if self.mouse_cursor.key_typed(enabled) {
+            context.set_cursor(None);
+        }
+    }
+
     fn scroll_to_bottom(&mut self, pane: &Rc<dyn Pane>) {
         self.pane_state(pane.pane_id()).viewport = None;
     }
@@ -3773,6 +4925,135 @@ impl TermWindow {
     }
 }
 
+/// Publishes the text of a completed selection.  It always becomes
+/// the primary selection, and is also copied to the clipboard when
+/// `copy_on_select` is enabled.
+fn set_completed_selection(
+    clipboard: &dyn wezterm_term::Clipboard,
+    text: String,
+    copy_on_select: bool,
+) -> anyhow::Result<()> {
+    if copy_on_select {
+        clipboard.set_contents(ClipboardSelection::Clipboard, Some(text.clone()))?;
+    }
+    clipboard.set_contents(ClipboardSelection::PrimarySelection, Some(text))
+}
+
+/// Returns the color that should be used to draw the text of a cell
+/// with the specified attributes.  `style_foreground` is the color
+/// from the matching font rule, which applies to text that is the
+/// default color.
+fn cell_foreground_color(
+    attrs: &CellAttributes,
+    style_foreground: Option<RgbColor>,
+    palette: &ColorPalette,
+    bold_brightens_ansi_colors: bool,
+) -> RgbColor {
+    match attrs.foreground {
+        ColorAttribute::Default => {
+            if let Some(fg) = style_foreground {
+                fg
+            } else {
+                palette.resolve_fg(attrs.foreground)
+            }
+        }
+        ColorAttribute::PaletteIndex(idx) if idx < 8 && bold_brightens_ansi_colors => {
+            // For compatibility purposes, switch to a brighter version
+            // of one of the standard ANSI colors when Bold is enabled.
+            // This lifts black to dark grey.
+            let idx = if attrs.intensity() == wezterm_term::Intensity::Bold {
+                idx + 8
+            } else {
+                idx
+            };
+            palette.resolve_fg(ColorAttribute::PaletteIndex(idx))
+        }
+        _ => palette.resolve_fg(attrs.foreground),
+    }
+}
+
+/// Adjusts the colors of the text and underline of a cell once its
+/// final background color is known, returning the new text and
+/// underline colors.
+/// Half intensity text is faded by `dim_factor`, and all of the text
+/// is faded by `unfocused_text_dim` while the window isn't `focused`;
+/// the colors of a cell that is drawn in the block cursor colors are
+/// left alone.  Finally, the contrast of the text is raised to
+/// `minimum_contrast_ratio`, so the fading never takes the text
+/// below that ratio.
+fn adjust_text_colors(
+    fg_color: Color,
+    bg_color: Color,
+    underline_color: Color,
+    intensity: wezterm_term::Intensity,
+    block_cursor_colors: bool,
+    focused: bool,
+    config: &Config,
+) -> (Color, Color) {
+    // Text that is deliberately drawn in the background color, such
+    // as blinking text in its "off" phase, is left that way.
+    let hidden = fg_color.0 == bg_color.0;
+
+    let (fg_color, underline_color) = if block_cursor_colors {
+        (fg_color, underline_color)
+    } else {
+        // Fade half intensity text toward its final background, which
+        // takes into account reverse video and the selection colors.
+        let fg_color = half_intensity_color(fg_color, bg_color, intensity, config.dim_factor);
+        let underline_color =
+            half_intensity_color(underline_color, bg_color, intensity, config.dim_factor);
+
+        // Fade the text toward the background to show that the window
+        // doesn't have the focus
+        let dim = config.unfocused_text_dim;
+        if !focused && dim > 0. {
+            (
+                fg_color.lerp(bg_color, dim),
+                underline_color.lerp(bg_color, dim),
+            )
+        } else {
+            (fg_color, underline_color)
+        }
+    };
+
+    // Raise the contrast of the text against its final background
+    let min_contrast = config.minimum_contrast_ratio;
+    let fg_color = if min_contrast > 1.0 && !hidden {
+        fg_color.ensure_contrast_ratio(bg_color, min_contrast)
+    } else {
+        fg_color
+    };
+    (fg_color, underline_color)
+}
+
+/// Returns the color to draw half intensity (SGR 2) text in: `color`
+/// moved `dim_factor` of the way toward `bg_color`.  Text of any other
+/// intensity is drawn in `color`.
+/// `dim_factor` is clamped to the range 0.0 to 1.0.
+fn half_intensity_color(
+    color: Color,
+    bg_color: Color,
+    intensity: wezterm_term::Intensity,
+    dim_factor: f32,
+) -> Color {
+    let dim_factor = dim_factor.clamp(0., 1.);
+    if intensity == wezterm_term::Intensity::Half && dim_factor > 0. {
+        color.lerp(bg_color, dim_factor)
+    } else {
+        color
+    }
+}
+
+/// Returns the color that should be used to draw the underline for
+/// a cell with the specified attributes, or None if the underline
+/// should be drawn in the same color as the text.
+fn cell_underline_color(attrs: &CellAttributes, palette: &ColorPalette) -> Option<Color> {
+    match attrs.underline_color() {
+        ColorAttribute::Default => None,
+        color => Some(rgbcolor_to_window_color(palette.resolve_fg(color))),
+    }
+}
+
 fn rgbcolor_to_window_color(color: RgbColor) -> Color {
     rgbcolor_alpha_to_window_color(color, 0xff)
 }
@@ -3781,6 +5062,314 @@ fn rgbcolor_alpha_to_window_color(color: RgbColor, alpha: u8) -> Color {
     Color::rgba(color.red, color.green, color.blue, alpha)
 }
 
+/// Computes the index of the tab that is `delta` positions away from
+/// `active` in a window with `num_tabs` tabs, wrapping around at
+/// either end.
+fn relative_tab_index(active: usize, delta: isize, num_tabs: usize) -> usize {
+    (active as isize + delta).rem_euclid(num_tabs as isize) as usize
+}
+
+/// Computes the row of the prompt that is `amount` prompts away from
+/// the top of the viewport, given the rows at which the prompts start
+/// in ascending order.  When the viewport starts between two prompts,
+/// -1 moves to the prompt above it and 1 to the prompt below.  Moving past
+/// the first or last prompt stops there rather than wrapping around,
+/// and `None` is returned if there is no prompt in that direction.
+fn relative_prompt_row(
+    prompt_rows: &[StableRowIndex],
+    top: StableRowIndex,
+    amount: isize,
+) -> Option<StableRowIndex> {
+    let last = prompt_rows.len() as isize - 1;
+    if last < 0 {
+        return None;
+    }
+    let idx = match prompt_rows.binary_search(&top) {
+        Ok(idx) => idx as isize + amount,
+        // idx is that of the first prompt below the top of the viewport
+        Err(idx) if amount < 0 => idx as isize + amount,
+        Err(idx) => idx as isize - 1 + amount,
+    };
+    let row = prompt_rows[idx.clamp(0, last) as usize];
+    let moved_the_right_way = match amount.cmp(&0) {
+        std::cmp::Ordering::Less => row < top,
+        std::cmp::Ordering::Greater => row > top,
+        std::cmp::Ordering::Equal => true,
+    };
+    if moved_the_right_way {
+        Some(row)
+    } else {
+        None
+    }
+}
+
+/// Decides whether releasing a single left click should open the
+/// hyperlink under the mouse cursor when `open_link_modifier` is set.
+/// The link is only opened if exactly those modifiers are held.
+/// When `open_link_modifier` is not set, this is left to the
+/// `CompleteSelectionOrOpenLinkAtMouseCursor` mouse binding.
+fn click_opens_link(
+    open_link_modifier: termwiz::input::Modifiers,
+    modifiers: termwiz::input::Modifiers,
+    has_highlight: bool,
+) -> bool {
+    has_highlight
+        && open_link_modifier != termwiz::input::Modifiers::NONE
+        && modifiers == open_link_modifier
+}
+
+/// Computes how strongly the visual bell should tint the window,
+/// given how long ago it was triggered.  The flash starts at full
+/// intensity and fades linearly to nothing over `duration`.
+fn visual_bell_intensity(elapsed: Duration, duration: Duration) -> f32 {
+    if elapsed >= duration {
+        0.
+    } else {
+        1. - (elapsed.as_millis() as f32 / duration.as_millis() as f32)
+    }
+}
+
+/// Linearly interpolates between two colors; `amount` of 0.0
+/// returns `a` and 1.0 returns `b`.
+fn blend_rgbcolor(a: RgbColor, b: RgbColor, amount: f32) -> RgbColor {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount).round() as u8;
+    RgbColor::new(
+        mix(a.red, b.red),
+        mix(a.green, b.green),
+        mix(a.blue, b.blue),
+    )
+}
+
+/// Works out whether a visible cursor is currently in the "on" phase
+/// of its blink cycle.  The cursor only blinks when it has a blinking
+/// shape, blinking hasn't been disabled via a zero `blink_rate`, and
+/// the pane is active in a focused window; otherwise it is steady.
+fn cursor_blink_visibility(
+    shape: CursorShape,
+    blink_rate: u64,
+    focused: bool,
+    since_last_movement: Duration,
+) -> CursorVisibility {
+    if !focused || !shape.is_blinking() || blink_rate == 0 {
+        return CursorVisibility::Visible;
+    }
+    // Divide the time since we last moved by the blink rate.
+    // If the result is even then the cursor is "on", else it
+    // is "off"
+    let ticks = since_last_movement.as_millis() / blink_rate as u128;
+    if (ticks & 1) == 0 {
+        CursorVisibility::Visible
+    } else {
+        CursorVisibility::Hidden
+    }
+}
+
+/// Works out whether text with the specified blink attribute is
+/// currently in the "on" phase of its blink cycle, given the time
+/// that has elapsed since the blink epoch.  Slow and rapid blinking
+/// text cycle at their respective rates; a rate of 0 disables that
+/// kind of blinking so that the text is always visible.
+fn text_blink_is_visible(blink: Blink, slow_rate: u64, rapid_rate: u64, elapsed: Duration) -> bool {
+    let rate = match blink {
+        Blink::None => return true,
+        Blink::Slow => slow_rate,
+        Blink::Rapid => rapid_rate,
+    };
+    if rate == 0 {
+        return true;
+    }
+    let ticks = elapsed.as_millis() / rate as u128;
+    (ticks & 1) == 0
+}
+
+/// Returns the rows of `lines`, the first of which is `top`, that
+/// contain text with the blink attribute
+fn blinking_rows(top: StableRowIndex, lines: &[Line]) -> Vec<StableRowIndex> {
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| {
+            line.cells()
+                .iter()
+                .any(|cell| cell.attrs().blink() != Blink::None)
+        })
+        .map(|(idx, _)| top + idx as StableRowIndex)
+        .collect()
+}
+
+/// Computes the portion of the background image texture to display
+/// so that the image covers the whole window while preserving its
+/// aspect ratio; the excess is cropped equally from both sides.
+fn background_image_texture_rect(
+    coords: TextureRect,
+    image_width: f32,
+    image_height: f32,
+    window_width: f32,
+    window_height: f32,
+) -> TextureRect {
+    if image_width <= 0. || image_height <= 0. || window_width <= 0. || window_height <= 0. {
+        return coords;
+    }
+    let scale = (window_width / image_width).max(window_height / image_height);
+    let visible_width = (window_width / scale) / image_width;
+    let visible_height = (window_height / scale) / image_height;
+
+    TextureRect::new(
+        TextureCoord::new(
+            coords.origin.x + coords.size.width * (1. - visible_width) / 2.,
+            coords.origin.y + coords.size.height * (1. - visible_height) / 2.,
+        ),
+        TextureSize::new(
+            coords.size.width * visible_width,
+            coords.size.height * visible_height,
+        ),
+    )
+}
+
+/// Returns the color to fill the window padding with, or None if the
+/// padding should show the window background, as it does by default.
+/// `bottom_left_background` is the background of the bottom left cell
+/// of the active pane.
+fn resolve_window_padding_color(
+    padding_color: WindowPaddingColor,
+    palette: &ColorPalette,
+    bottom_left_background: Option<ColorAttribute>,
+) -> Option<RgbColor> {
+    match padding_color {
+        WindowPaddingColor::DefaultBackground => None,
+        WindowPaddingColor::Color(color) => Some(color),
+        WindowPaddingColor::BottomLeftCell => match bottom_left_background {
+            None | Some(ColorAttribute::Default) => None,
+            Some(color) => Some(palette.resolve_bg(color)),
+        },
+    }
+}
+
+/// Computes the alpha for the background quad of a cell.
+/// When the window is transparent (either because of
+/// window_background_opacity or a background image), cells with
+/// the default background are left clear so that the window
+/// background shows through, while other cells are scaled by
+/// text_background_opacity.
+fn cell_background_alpha(
+    text_background_opacity: f32,
+    window_is_transparent: bool,
+    bg_is_default: bool,
+) -> u8 {
+    if window_is_transparent && bg_is_default {
+        0x00
+    } else {
+        (text_background_opacity * 255.0) as u8
+    }
+}
+
+/// Returns the index of the cell that covers column `x` of `line`.
+/// A wide glyph, such as CJK or emoji, is held by its first cell and
+/// covers the columns of the cells that follow it, so a click on any
+/// of those belongs to the glyph.
+fn cell_index_for_column(line: &Line, x: usize) -> usize {
+    match line.visible_cells().take_while(|(idx, _)| *idx <= x).last() {
+        Some((idx, cell)) if x < idx + cell.width() => idx,
+        _ => x,
+    }
+}
+
+/// Returns the range of display columns occupied by the cell at
+/// `cell_idx` on a line of the specified size
+fn display_columns_for_cell(line_size: LineSize, cell_idx: usize) -> Range<usize> {
+    if line_size.is_double_width() {
+        cell_idx * 2..cell_idx * 2 + 2
+    } else {
+        cell_idx..cell_idx + 1
+    }
+}
+
+/// The extent of a glyph in pixels, relative to the top left corner
+/// of the display cell from which it is rendered
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GlyphRect {
+    left: f32,
+    top: f32,
+    right: f32,
+    bottom: f32,
+}
+
+/// Takes the rect and texture of a glyph positioned for a normal
+/// line and scales it up for a line of the specified size.
+/// Each half of a double height line renders only the part of the
+/// glyph that falls within its own row, so the rect and texture
+/// are clipped to that row.
+fn scale_glyph_for_line_size(
+    line_size: LineSize,
+    cell_height: f32,
+    rect: GlyphRect,
+    texture: TextureRect,
+) -> (GlyphRect, TextureRect) {
+    let scaled = GlyphRect {
+        left: rect.left * 2.,
+        right: rect.right * 2.,
+        ..rect
+    };
+
+    let (offset, min_y, max_y) = match line_size {
+        LineSize::Normal => return (rect, texture),
+        LineSize::DoubleWidth => return (scaled, texture),
+        LineSize::DoubleHeightTop => (0., std::f32::NEG_INFINITY, cell_height),
+        LineSize::DoubleHeightBottom => (cell_height, 0., std::f32::INFINITY),
+    };
+
+    let top = rect.top * 2. - offset;
+    let bottom = rect.bottom * 2. - offset;
+    let clipped_top = top.max(min_y).min(max_y);
+    let clipped_bottom = bottom.max(min_y).min(max_y);
+
+    let (tex_top, tex_bottom) = if bottom > top {
+        (
+            (clipped_top - top) / (bottom - top),
+            (clipped_bottom - top) / (bottom - top),
+        )
+    } else {
+        (0., 0.)
+    };
+
+    (
+        GlyphRect {
+            top: clipped_top,
+            bottom: clipped_bottom,
+            ..scaled
+        },
+        TextureRect::new(
+            TextureCoord::new(
+                texture.origin.x,
+                texture.origin.y + tex_top * texture.size.height,
+            ),
+            TextureSize::new(
+                texture.size.width,
+                (tex_bottom - tex_top) * texture.size.height,
+            ),
+        ),
+    )
+}
+
+/// Quotes a path so that the shell treats it as a single word.
+/// Paths made up only of characters that have no special meaning
+/// to the shell are left as they are.
+fn quote_dropped_file(path: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=+,@%".contains(c);
+    if !path.is_empty() && path.chars().all(is_plain) {
+        return path.to_string();
+    }
+    if cfg!(windows) {
+        // Windows file names cannot contain double quotes, and
+        // both cmd and powershell accept them around a path
+        format!("\"{}\"", path)
+    } else {
+        // Nothing is special inside single quotes, so the only
+        // thing that needs escaping is the single quote itself
+        format!("'{}'", path.replace('\'', "'\\''"))
+    }
+}
+
 fn window_mods_to_termwiz_mods(modifiers: ::window::Modifiers) -> termwiz::input::Modifiers {
     let mut result = termwiz::input::Modifiers::NONE;
     if modifiers.contains(::window::Modifiers::SHIFT) {
@@ -3803,3 +5392,1007 @@ fn window_mods_to_termwiz_mods(modifiers: ::window::Modifiers) -> termwiz::input
     }
     result
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use termwiz::color::AnsiColor;
+    use wezterm_term::Underline;
+
+    #[test]
+    fn pty_size_is_clamped() {
+        assert_eq!(pty_dimension(0), 1);
+        assert_eq!(pty_dimension(1), 1);
+        assert_eq!(pty_dimension(80), 80);
+        assert_eq!(pty_dimension(4096), 4096);
+        assert_eq!(pty_dimension(4097), 4096);
+        // Large enough to wrap around if it were cast to u16
+        assert_eq!(pty_dimension(70_000), 4096);
+
+        assert_eq!(saturating_u16(1024), 1024);
+        assert_eq!(saturating_u16(100_000), u16::MAX);
+    }
+
+    #[test]
+    fn tabs_are_only_resized_when_their_size_differs() {
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 640,
+            pixel_height: 384,
+        };
+        assert!(!tab_needs_resize(size, size));
+
+        let taller = PtySize { rows: 25, ..size };
+        assert!(tab_needs_resize(size, taller));
+        assert!(tab_needs_resize(taller, size));
+
+        // Pixels beyond the last whole cell aren't kept by the tab
+        let partial = PtySize {
+            pixel_width: 645,
+            pixel_height: 390,
+            ..size
+        };
+        assert!(!tab_needs_resize(size, partial));
+
+        let empty = PtySize { rows: 0, ..size };
+        assert!(!tab_needs_resize(size, empty));
+    }
+
+    #[test]
+    fn dpi_change_preserves_rows_and_cols() {
+        let cells = RowsAndCols {
+            rows: 50,
+            cols: 120,
+        };
+        // The same font at 96 and then at 144 dpi
+        let at_96_dpi = CellLayout {
+            cell_width: 8,
+            cell_height: 17,
+            horizontal_padding: 0,
+            vertical_padding: 0,
+            show_tab_bar: true,
+        };
+        let at_144_dpi = CellLayout {
+            cell_width: 12,
+            cell_height: 25,
+            horizontal_padding: 10,
+            vertical_padding: 4,
+            ..at_96_dpi
+        };
+
+        let (_, width, height) = at_96_dpi.window_for_cells(cells);
+        let before = at_96_dpi.pty_size_for_window(width, height);
+        assert_eq!((before.rows, before.cols), (50, 120));
+
+        let (size, width, height) = at_144_dpi.window_for_cells(cells);
+        assert_eq!((size.rows, size.cols), (50, 120));
+        assert_eq!((width, height), (120 * 12 + 10, 51 * 25 + 4));
+        // Once the window reaches the requested size, the cells
+        // computed from it are the ones that were preserved
+        let after = at_144_dpi.pty_size_for_window(width, height);
+        assert_eq!((after.rows, after.cols), (50, 120));
+        assert_eq!(after, size);
+
+        let target = Dimensions {
+            pixel_width: width,
+            pixel_height: height,
+            dpi: 144,
+        };
+        // The window may report its old size before it is resized
+        let intermediate = Dimensions {
+            pixel_width: 120 * 8,
+            pixel_height: 51 * 17,
+            dpi: 144,
+        };
+        let requested = Instant::now();
+        let pending = PendingScaleResize {
+            dimensions: target,
+            requested,
+        };
+        assert!(is_intermediate_resize(
+            Some(&pending),
+            &intermediate,
+            requested
+        ));
+        assert!(!is_intermediate_resize(Some(&pending), &target, requested));
+        assert!(!is_intermediate_resize(None, &intermediate, requested));
+    }
+
+    #[test]
+    fn refused_scale_resize_is_given_up_on() {
+        let target = Dimensions {
+            pixel_width: 1450,
+            pixel_height: 1279,
+            dpi: 144,
+        };
+        let requested = Instant::now();
+        let pending = PendingScaleResize {
+            dimensions: target,
+            requested,
+        };
+
+        // The window manager ignored set_inner_size, so the window
+        // keeps its old size
+        let refused = Dimensions {
+            pixel_width: 960,
+            pixel_height: 867,
+            dpi: 144,
+        };
+        let soon = requested + Duration::from_millis(100);
+        assert!(!pending.is_expired(soon));
+        assert!(is_intermediate_resize(Some(&pending), &refused, soon));
+
+        // Once we stop waiting, the next resize is a real one
+        let later = requested + SCALE_RESIZE_TIMEOUT;
+        assert!(pending.is_expired(later));
+        let resized = Dimensions {
+            pixel_width: 1200,
+            pixel_height: 900,
+            dpi: 144,
+        };
+        assert!(!is_intermediate_resize(Some(&pending), &resized, later));
+    }
+
+    #[test]
+    fn window_title_template_placeholders() {
+        let params = WindowTitleParams {
+            index: 2,
+            count: 3,
+            title: "vim",
+            cwd: "/home/user",
+        };
+        assert_eq!(
+            expand_window_title_template("[{index}/{count}] {title} - {cwd}", &params),
+            "[2/3] vim - /home/user"
+        );
+        assert_eq!(expand_window_title_template("{title}", &params), "vim");
+        assert_eq!(expand_window_title_template("wezterm", &params), "wezterm");
+        // Unknown and unterminated placeholders are left alone
+        assert_eq!(
+            expand_window_title_template("{tab} {title", &params),
+            "{tab} {title"
+        );
+
+        // Placeholders within the values aren't expanded
+        let params = WindowTitleParams {
+            index: 1,
+            count: 1,
+            title: "{cwd}",
+            cwd: "{title}",
+        };
+        assert_eq!(
+            expand_window_title_template("{title} {cwd}", &params),
+            "{cwd} {title}"
+        );
+    }
+
+    #[test]
+    fn window_title_sanitizing() {
+        assert_eq!(
+            sanitize_title("vim \x1b[31mfile\x07\n", None),
+            "vim [31mfile"
+        );
+        assert_eq!(sanitize_title("caf\u{e9}\u{9b}", None), "caf\u{e9}");
+        assert_eq!(sanitize_title("a long title", Some(6)), "a long");
+        assert_eq!(sanitize_title("short", Some(60)), "short");
+    }
+
+    #[test]
+    fn spawn_command_carries_args_cwd_and_env() {
+        let mut spawn = SpawnCommand {
+            args: Some(vec!["htop".to_string(), "-d".to_string(), "10".to_string()]),
+            cwd: Some(PathBuf::from("/tmp")),
+            ..Default::default()
+        };
+        spawn
+            .set_environment_variables
+            .insert("HTOPRC".to_string(), "/tmp/htoprc".to_string());
+
+        let mut expected = CommandBuilder::new("htop");
+        expected.args(&["-d", "10"]);
+        expected.env("HTOPRC", "/tmp/htoprc");
+        expected.cwd("/tmp");
+        assert_eq!(spawn_command_builder(&spawn), Some(expected));
+
+        // Without args, the domain spawns its default program
+        let spawn = SpawnCommand {
+            cwd: Some(PathBuf::from("/tmp")),
+            ..Default::default()
+        };
+        assert_eq!(spawn_command_builder(&spawn), None);
+    }
+
+    #[derive(Debug)]
+    struct TestTermConfig;
+    impl TerminalConfiguration for TestTermConfig {
+        fn color_palette(&self) -> ColorPalette {
+            ColorPalette::default()
+        }
+    }
+
+    #[test]
+    fn spawn_uses_the_cwd_reported_by_osc7() {
+        let mut term = wezterm_term::Terminal::new(
+            2,
+            10,
+            80,
+            32,
+            Arc::new(TestTermConfig),
+            "WezTerm",
+            "test",
+            Box::new(Vec::new()),
+        );
+        term.advance_bytes("\x1b]7;file://myhost/home/user/my%20project\x1b\\");
+        let cwd = term.get_current_dir().cloned().unwrap();
+        assert_eq!(
+            working_dir_from_url(&cwd).as_deref(),
+            Some("/home/user/my project")
+        );
+
+        let windows = Url::parse("file:///C:/Users/me").unwrap();
+        assert_eq!(
+            working_dir_from_url(&windows).as_deref(),
+            Some("C:/Users/me")
+        );
+
+        let remote = Url::parse("https://example.com/home/user").unwrap();
+        assert_eq!(working_dir_from_url(&remote), None);
+    }
+
+    #[test]
+    fn scrollback_viewport_positions() {
+        // 100 lines of scrollback above a 24 row viewport
+        let dims = RenderableDimensions {
+            cols: 80,
+            viewport_rows: 24,
+            scrollback_rows: 124,
+            physical_top: 100,
+            scrollback_top: 0,
+        };
+
+        // ScrollToTop
+        assert_eq!(clamp_viewport(Some(dims.scrollback_top), &dims), Some(0));
+        // ScrollToBottom
+        assert_eq!(clamp_viewport(None, &dims), None);
+        assert_eq!(clamp_viewport(Some(dims.physical_top), &dims), None);
+        // Scrolling part way up, and beyond either end
+        assert_eq!(clamp_viewport(Some(50), &dims), Some(50));
+        assert_eq!(clamp_viewport(Some(-10), &dims), Some(0));
+        assert_eq!(clamp_viewport(Some(200), &dims), None);
+
+        // ClearScrollback leaves just the viewport, so a viewport that
+        // was scrolled up ends up following the bottom of the output
+        let cleared = RenderableDimensions {
+            scrollback_rows: 24,
+            scrollback_top: 100,
+            ..dims
+        };
+        assert_eq!(clamp_viewport(Some(50), &cleared), None);
+        assert_eq!(clamp_viewport(Some(cleared.scrollback_top), &cleared), None);
+    }
+
+    #[test]
+    fn dump_screen_rows() {
+        let dims = RenderableDimensions {
+            cols: 80,
+            viewport_rows: 24,
+            scrollback_rows: 124,
+            physical_top: 100,
+            scrollback_top: 0,
+        };
+        assert_eq!(dump_screen_range(None, &dims, false), 100..124);
+        // The rows that are shown when scrolled up
+        assert_eq!(dump_screen_range(Some(50), &dims, false), 50..74);
+        // The scrollback goes down to the bottom of the screen,
+        // wherever the viewport is
+        assert_eq!(dump_screen_range(None, &dims, true), 0..124);
+        assert_eq!(dump_screen_range(Some(50), &dims, true), 0..124);
+    }
+
+    #[test]
+    fn clipboard_cache_follows_clipboard_writes() {
+        let cache = Mutex::new(None);
+
+        update_clipboard_cache(
+            &cache,
+            ClipboardSelection::Clipboard,
+            &Some("hello".to_string()),
+        );
+        assert_eq!(cache.lock().unwrap().as_deref(), Some("hello"));
+
+        // Setting the primary selection leaves the clipboard alone
+        update_clipboard_cache(
+            &cache,
+            ClipboardSelection::PrimarySelection,
+            &Some("primary".to_string()),
+        );
+        assert_eq!(cache.lock().unwrap().as_deref(), Some("hello"));
+
+        // Clearing the clipboard empties the cache
+        update_clipboard_cache(&cache, ClipboardSelection::Clipboard, &None);
+        assert_eq!(cache.lock().unwrap().as_deref(), Some(""));
+    }
+
+    #[test]
+    fn blinking_does_not_clear_the_selection() {
+        let mut dirty = RangeSet::new();
+        dirty.add(15);
+        let mut blink_dirty_rows = RangeSet::new();
+
+        // A real change within the selection clears it
+        assert!(selection_rows_changed(10..20, &dirty, &blink_dirty_rows));
+        assert!(!selection_rows_changed(0..10, &dirty, &blink_dirty_rows));
+
+        // ...but the cursor or text blinking on that row does not
+        blink_dirty_rows.add(15);
+        assert!(!selection_rows_changed(10..20, &dirty, &blink_dirty_rows));
+
+        // A change to another selected row still does
+        dirty.add(12);
+        assert!(selection_rows_changed(10..20, &dirty, &blink_dirty_rows));
+    }
+
+    #[test]
+    fn cursor_blink_only_repaints_the_cursor_row() {
+        let viewport = 10..20;
+        let mut cursor = StableCursorPosition {
+            y: 15,
+            ..Default::default()
+        };
+        assert_eq!(cursor_blink_row(&cursor, &viewport), Some(15));
+
+        // Scrolled out of view: nothing to repaint
+        cursor.y = 25;
+        assert_eq!(cursor_blink_row(&cursor, &viewport), None);
+        cursor.y = 9;
+        assert_eq!(cursor_blink_row(&cursor, &viewport), None);
+
+        cursor.y = 10;
+        cursor.visibility = CursorVisibility::Hidden;
+        assert_eq!(cursor_blink_row(&cursor, &viewport), None);
+    }
+
+    #[test]
+    fn underline_color_is_separate_from_glyph_color() {
+        let palette = ColorPalette::default();
+        let mut line = Line::from_text("ab", &CellAttributes::default());
+        line.cells_mut()[0]
+            .attrs_mut()
+            .set_foreground(AnsiColor::Navy)
+            .set_underline(Underline::Single);
+        line.cells_mut()[1]
+            .attrs_mut()
+            .set_foreground(AnsiColor::Navy)
+            .set_underline(Underline::Curly)
+            .set_underline_color(AnsiColor::Red);
+
+        let clusters = line.cluster();
+        assert_eq!(clusters.len(), 2);
+
+        // Without an underline color, the underline is drawn in the glyph color
+        assert!(cell_underline_color(&clusters[0].attrs, &palette).is_none());
+
+        let glyph_color =
+            rgbcolor_to_window_color(palette.resolve_fg(clusters[1].attrs.foreground));
+        let red = rgbcolor_to_window_color(palette.resolve_fg(AnsiColor::Red.into()));
+        let underline_color = cell_underline_color(&clusters[1].attrs, &palette).unwrap();
+        assert_eq!(underline_color.0, red.0);
+        assert_ne!(underline_color.0, glyph_color.0);
+    }
+
+    #[test]
+    fn bold_brightens_ansi_colors() {
+        let palette = ColorPalette::default();
+        let mut attrs = CellAttributes::default();
+        attrs
+            .set_foreground(AnsiColor::Maroon)
+            .set_intensity(wezterm_term::Intensity::Bold);
+        let maroon = palette.resolve_fg(AnsiColor::Maroon.into());
+        let red = palette.resolve_fg(AnsiColor::Red.into());
+        assert_ne!(maroon, red);
+
+        // Bold palette index 1 is shown as its bright version, index 9
+        assert_eq!(cell_foreground_color(&attrs, None, &palette, true), red);
+        assert_eq!(cell_foreground_color(&attrs, None, &palette, false), maroon);
+
+        // Without bold, the color is unchanged either way
+        attrs.set_intensity(wezterm_term::Intensity::Normal);
+        assert_eq!(cell_foreground_color(&attrs, None, &palette, true), maroon);
+        assert_eq!(cell_foreground_color(&attrs, None, &palette, false), maroon);
+    }
+
+    #[test]
+    fn clicks_on_wide_glyphs_map_to_their_first_cell() {
+        let line = Line::from_text("a\u{4e2d}b\u{1f600}e\u{301}", &CellAttributes::default());
+        // a, the wide CJK character and its trailing cell, b, the
+        // wide emoji and its trailing cell, then e with a combining
+        // accent in a single cell
+        assert_eq!(line.cells().len(), 7);
+
+        let cell_width = 8;
+        let column = |pixel_x: usize| cell_index_for_column(&line, pixel_x / cell_width);
+
+        assert_eq!(column(4), 0);
+        // Both halves of the CJK character
+        assert_eq!(column(8), 1);
+        assert_eq!(column(2 * 8 + 3), 1);
+        assert_eq!(column(3 * 8), 3);
+        // Both halves of the emoji
+        assert_eq!(column(4 * 8 + 7), 4);
+        assert_eq!(column(5 * 8), 4);
+        assert_eq!(column(6 * 8), 6);
+        // Beyond the end of the line
+        assert_eq!(column(20 * 8), 20);
+    }
+
+    #[test]
+    fn half_intensity_is_dimmed() {
+        use wezterm_term::Intensity;
+
+        let fg = Color::rgb(0xff, 0xff, 0xff);
+        let bg = Color::rgb(0, 0, 0);
+
+        // The text is faded toward its background in linear light
+        assert_eq!(
+            half_intensity_color(fg, bg, Intensity::Half, 0.5).as_rgba(),
+            (0xbc, 0xbc, 0xbc, 0xff)
+        );
+        assert_eq!(
+            half_intensity_color(fg, bg, Intensity::Half, 0.25).as_rgba(),
+            (0xe1, 0xe1, 0xe1, 0xff)
+        );
+
+        // Out of range factors are clamped
+        assert_eq!(
+            half_intensity_color(fg, bg, Intensity::Half, 2.).as_rgba(),
+            bg.as_rgba()
+        );
+        assert_eq!(
+            half_intensity_color(fg, bg, Intensity::Half, -1.).as_rgba(),
+            fg.as_rgba()
+        );
+
+        // Only half intensity is dimmed, and only if configured
+        assert_eq!(
+            half_intensity_color(fg, bg, Intensity::Normal, 0.5).as_rgba(),
+            fg.as_rgba()
+        );
+        assert_eq!(
+            half_intensity_color(fg, bg, Intensity::Bold, 0.5).as_rgba(),
+            fg.as_rgba()
+        );
+        assert_eq!(
+            half_intensity_color(fg, bg, Intensity::Half, 0.).as_rgba(),
+            fg.as_rgba()
+        );
+
+        // With reverse video, the text is faded toward the color that
+        // is then its background, which was its foreground color
+        assert_eq!(
+            half_intensity_color(bg, fg, Intensity::Half, 0.25).as_rgba(),
+            (0x89, 0x89, 0x89, 0xff)
+        );
+    }
+
+    #[test]
+    fn unfocused_text_is_dimmed() {
+        use wezterm_term::Intensity;
+
+        let mut config = Config::default_config();
+        config.unfocused_text_dim = 0.5;
+        let fg = Color::rgb(0xff, 0, 0);
+        let bg = Color::rgb(0, 0, 0xff);
+        let colors = |config: &Config, block_cursor_colors, focused| {
+            let (fg, underline) = adjust_text_colors(
+                fg,
+                bg,
+                fg,
+                Intensity::Normal,
+                block_cursor_colors,
+                focused,
+                config,
+            );
+            (fg.as_rgba(), underline.as_rgba())
+        };
+
+        // Halfway between the text and its background, in linear light
+        let midpoint = (0xbc, 0, 0xbc, 0xff);
+        assert_eq!(colors(&config, false, false), (midpoint, midpoint));
+
+        // Neither the focused window nor the cursor cell is dimmed
+        assert_eq!(colors(&config, false, true), (fg.as_rgba(), fg.as_rgba()));
+        assert_eq!(colors(&config, true, false), (fg.as_rgba(), fg.as_rgba()));
+
+        config.unfocused_text_dim = 0.;
+        assert_eq!(colors(&config, false, false), (fg.as_rgba(), fg.as_rgba()));
+    }
+
+    #[test]
+    fn dimmed_text_keeps_the_minimum_contrast() {
+        use wezterm_term::Intensity;
+
+        let mut config = Config::default_config();
+        config.unfocused_text_dim = 0.9;
+        config.dim_factor = 0.9;
+        config.minimum_contrast_ratio = 4.5;
+        let fg = Color::rgb(0xff, 0xff, 0xff);
+        let bg = Color::rgb(0, 0, 0);
+        let contrast = |intensity, focused| {
+            let (fg, _) = adjust_text_colors(fg, bg, fg, intensity, false, focused, &config);
+            fg.contrast_ratio(bg)
+        };
+
+        // The contrast is raised after the text is faded
+        assert!(contrast(Intensity::Normal, false) >= 4.5 - 0.01);
+        assert!(contrast(Intensity::Half, true) >= 4.5 - 0.01);
+
+        // Text drawn in its background color stays hidden
+        let (hidden, _) = adjust_text_colors(bg, bg, bg, Intensity::Normal, false, false, &config);
+        assert_eq!(hidden.as_rgba(), bg.as_rgba());
+    }
+
+    #[test]
+    fn background_alpha_leaves_glyphs_opaque() {
+        let palette = ColorPalette::default();
+        let bg = palette.resolve_bg(ColorAttribute::Default);
+        let fg = palette.resolve_fg(ColorAttribute::Default);
+
+        // An opaque window fills every cell background
+        let alpha = cell_background_alpha(1.0, false, true);
+        assert_eq!(
+            rgbcolor_alpha_to_window_color(bg, alpha).to_tuple_rgba().3,
+            1.0
+        );
+
+        // A transparent window lets default backgrounds show through
+        // and scales the others by text_background_opacity
+        let alpha = cell_background_alpha(0.5, true, true);
+        assert_eq!(
+            rgbcolor_alpha_to_window_color(bg, alpha).to_tuple_rgba().3,
+            0.0
+        );
+        let alpha = cell_background_alpha(0.5, true, false);
+        assert_eq!(alpha, 127);
+
+        // Glyphs are always opaque
+        assert_eq!(rgbcolor_to_window_color(fg).to_tuple_rgba().3, 1.0);
+    }
+
+    #[test]
+    fn window_padding_color() {
+        let palette = ColorPalette::default();
+        let navy = ColorAttribute::PaletteIndex(AnsiColor::Navy as u8);
+        let red = RgbColor::new(0xff, 0, 0);
+
+        // By default the padding shows the window background,
+        // whatever the bottom left cell looks like
+        let default = WindowPaddingColor::default();
+        assert_eq!(resolve_window_padding_color(default, &palette, None), None);
+        assert_eq!(
+            resolve_window_padding_color(default, &palette, Some(navy)),
+            None
+        );
+
+        let color = WindowPaddingColor::Color(red);
+        assert_eq!(
+            resolve_window_padding_color(color, &palette, Some(navy)),
+            Some(red)
+        );
+
+        let cell = WindowPaddingColor::BottomLeftCell;
+        assert_eq!(
+            resolve_window_padding_color(cell, &palette, Some(navy)),
+            Some(palette.resolve_bg(navy))
+        );
+        // A cell with the default background behaves like the default
+        assert_eq!(
+            resolve_window_padding_color(cell, &palette, Some(ColorAttribute::Default)),
+            None
+        );
+        assert_eq!(resolve_window_padding_color(cell, &palette, None), None);
+    }
+
+    #[test]
+    fn tabs_have_independent_font_scales() {
+        let mut tab_state: HashMap<TabId, TabState> = HashMap::new();
+        assert_eq!(tab_font_scale(&tab_state, 1), 1.0);
+        assert_eq!(font_scale_on_activation(&tab_state, 1, 1.0), None);
+
+        // Zoom the first tab, then switch to the second, which
+        // restores the default scale
+        tab_state.entry(1).or_default().font_scale = Some(1.1 * 1.1);
+        assert_eq!(
+            font_scale_on_activation(&tab_state, 2, 1.1 * 1.1),
+            Some(1.0)
+        );
+
+        // Shrink the second tab, then switch back to the first,
+        // which brings back its own scale
+        tab_state.entry(2).or_default().font_scale = Some(0.9);
+        assert_eq!(
+            font_scale_on_activation(&tab_state, 1, 0.9),
+            Some(1.1 * 1.1)
+        );
+        assert_eq!(
+            font_scale_on_activation(&tab_state, 2, 1.1 * 1.1),
+            Some(0.9)
+        );
+
+        // Activating the tab whose scale is in use changes nothing
+        assert_eq!(font_scale_on_activation(&tab_state, 1, 1.1 * 1.1), None);
+    }
+
+    #[test]
+    fn relative_tab_index_wraps() {
+        assert_eq!(relative_tab_index(1, 1, 3), 2);
+        assert_eq!(relative_tab_index(1, -1, 3), 0);
+        assert_eq!(relative_tab_index(2, 1, 3), 0);
+        assert_eq!(relative_tab_index(0, -1, 3), 2);
+        assert_eq!(relative_tab_index(0, -4, 3), 2);
+        assert_eq!(relative_tab_index(2, 7, 3), 0);
+        assert_eq!(relative_tab_index(0, 1, 1), 0);
+    }
+
+    #[test]
+    fn relative_prompt_row_stops_at_the_ends() {
+        let prompts = [10, 20, 30];
+
+        // From a prompt
+        assert_eq!(relative_prompt_row(&prompts, 20, 1), Some(30));
+        assert_eq!(relative_prompt_row(&prompts, 20, -1), Some(10));
+        assert_eq!(relative_prompt_row(&prompts, 10, 2), Some(30));
+
+        // From the output between two prompts
+        assert_eq!(relative_prompt_row(&prompts, 15, 1), Some(20));
+        assert_eq!(relative_prompt_row(&prompts, 15, -1), Some(10));
+        assert_eq!(relative_prompt_row(&prompts, 15, 0), Some(10));
+
+        // Moving past the ends stops at the first or last prompt
+        assert_eq!(relative_prompt_row(&prompts, 20, -5), Some(10));
+        assert_eq!(relative_prompt_row(&prompts, 10, 5), Some(30));
+
+        // But never moves in the wrong direction
+        assert_eq!(relative_prompt_row(&prompts, 10, -1), None);
+        assert_eq!(relative_prompt_row(&prompts, 5, -1), None);
+        assert_eq!(relative_prompt_row(&prompts, 5, 1), Some(10));
+        assert_eq!(relative_prompt_row(&prompts, 30, 1), None);
+        assert_eq!(relative_prompt_row(&prompts, 40, 1), None);
+        assert_eq!(relative_prompt_row(&prompts, 40, -1), Some(30));
+
+        assert_eq!(relative_prompt_row(&[], 40, -1), None);
+    }
+
+    #[test]
+    fn open_link_modifier() {
+        use termwiz::input::Modifiers as Mods;
+
+        // Without a modifier configured, the mouse bindings decide
+        assert!(!click_opens_link(Mods::NONE, Mods::NONE, true));
+
+        assert!(click_opens_link(Mods::CTRL, Mods::CTRL, true));
+        assert!(!click_opens_link(Mods::CTRL, Mods::CTRL, false));
+        assert!(!click_opens_link(Mods::CTRL, Mods::NONE, true));
+        assert!(!click_opens_link(
+            Mods::CTRL,
+            Mods::CTRL | Mods::SHIFT,
+            true
+        ));
+    }
+
+    #[test]
+    fn visual_bell_fades_out() {
+        let duration = Duration::from_millis(200);
+        assert_eq!(
+            visual_bell_intensity(Duration::from_millis(0), duration),
+            1.
+        );
+        assert_eq!(
+            visual_bell_intensity(Duration::from_millis(50), duration),
+            0.75
+        );
+        assert_eq!(
+            visual_bell_intensity(Duration::from_millis(100), duration),
+            0.5
+        );
+        assert_eq!(visual_bell_intensity(duration, duration), 0.);
+        assert_eq!(
+            visual_bell_intensity(Duration::from_millis(500), duration),
+            0.
+        );
+        // A zero duration disables the flash
+        assert_eq!(
+            visual_bell_intensity(Duration::from_millis(0), Duration::from_millis(0)),
+            0.
+        );
+
+        let black = RgbColor::new(0, 0, 0);
+        let white = RgbColor::new(0xff, 0xff, 0xff);
+        assert_eq!(blend_rgbcolor(black, white, 0.), black);
+        assert_eq!(blend_rgbcolor(black, white, 1.), white);
+        assert_eq!(
+            blend_rgbcolor(black, white, 0.5),
+            RgbColor::new(0x80, 0x80, 0x80)
+        );
+    }
+
+    #[test]
+    fn cursor_is_steady_when_unfocused() {
+        let off_phase = Duration::from_millis(1200);
+        assert_eq!(
+            cursor_blink_visibility(CursorShape::BlinkingBlock, 800, true, off_phase),
+            CursorVisibility::Hidden
+        );
+        assert_eq!(
+            cursor_blink_visibility(CursorShape::BlinkingBlock, 800, false, off_phase),
+            CursorVisibility::Visible
+        );
+        assert_eq!(
+            cursor_blink_visibility(CursorShape::BlinkingBlock, 0, true, off_phase),
+            CursorVisibility::Visible
+        );
+        assert_eq!(
+            cursor_blink_visibility(CursorShape::SteadyBlock, 800, true, off_phase),
+            CursorVisibility::Visible
+        );
+    }
+
+    #[test]
+    fn text_blink_phase() {
+        let visible =
+            |blink, millis| text_blink_is_visible(blink, 500, 250, Duration::from_millis(millis));
+
+        // Slow blinking text is on for the first 500ms, then off
+        assert!(visible(Blink::Slow, 0));
+        assert!(visible(Blink::Slow, 499));
+        assert!(!visible(Blink::Slow, 500));
+        assert!(!visible(Blink::Slow, 999));
+        assert!(visible(Blink::Slow, 1000));
+
+        // Rapid blinking text cycles twice as often
+        assert!(visible(Blink::Rapid, 0));
+        assert!(!visible(Blink::Rapid, 250));
+        assert!(visible(Blink::Rapid, 500));
+        assert!(!visible(Blink::Rapid, 750));
+
+        // Text that doesn't blink is always visible
+        assert!(visible(Blink::None, 500));
+
+        // A rate of 0 disables blinking
+        assert!(text_blink_is_visible(
+            Blink::Slow,
+            0,
+            250,
+            Duration::from_millis(500)
+        ));
+    }
+
+    #[test]
+    fn blinking_rows_are_remembered_by_stable_index() {
+        let plain = CellAttributes::default();
+        let mut blinking = CellAttributes::default();
+        blinking.set_blink(Blink::Rapid);
+        let lines = vec![
+            Line::from_text("plain", &plain),
+            Line::from_text("blink", &blinking),
+            Line::from_text("plain", &plain),
+            Line::from_text("blink", &blinking),
+        ];
+        assert_eq!(blinking_rows(10, &lines), vec![11, 13]);
+        assert!(blinking_rows(10, &lines[..1]).is_empty());
+    }
+
+    #[test]
+    fn background_image_is_cropped_to_window() {
+        let coords = TextureRect::new(TextureCoord::new(0.5, 0.), TextureSize::new(0.5, 0.25));
+
+        // Same aspect ratio: the whole image is used
+        assert_eq!(
+            background_image_texture_rect(coords, 200., 100., 800., 400.),
+            coords
+        );
+
+        // A wide image in a square window is cropped at the sides
+        assert_eq!(
+            background_image_texture_rect(coords, 200., 100., 300., 300.),
+            TextureRect::new(TextureCoord::new(0.625, 0.), TextureSize::new(0.25, 0.25))
+        );
+
+        // A tall image in a wide window is cropped at the top and bottom
+        assert_eq!(
+            background_image_texture_rect(coords, 100., 200., 200., 100.),
+            TextureRect::new(
+                TextureCoord::new(0.5, 0.09375),
+                TextureSize::new(0.5, 0.0625)
+            )
+        );
+    }
+
+    #[derive(Default)]
+    struct RecordingClipboard {
+        calls: RefCell<Vec<(ClipboardSelection, Option<String>)>>,
+    }
+
+    impl wezterm_term::Clipboard for RecordingClipboard {
+        fn get_contents(&self) -> anyhow::Result<String> {
+            Ok(String::new())
+        }
+
+        fn set_contents(
+            &self,
+            selection: ClipboardSelection,
+            data: Option<String>,
+        ) -> anyhow::Result<()> {
+            self.calls.borrow_mut().push((selection, data));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn completed_selection_sets_primary() {
+        let clip = RecordingClipboard::default();
+        set_completed_selection(&clip, "hello".to_string(), false).unwrap();
+        assert_eq!(
+            *clip.calls.borrow(),
+            vec![(
+                ClipboardSelection::PrimarySelection,
+                Some("hello".to_string())
+            )]
+        );
+
+        let clip = RecordingClipboard::default();
+        set_completed_selection(&clip, "hello".to_string(), true).unwrap();
+        assert_eq!(
+            *clip.calls.borrow(),
+            vec![
+                (ClipboardSelection::Clipboard, Some("hello".to_string())),
+                (
+                    ClipboardSelection::PrimarySelection,
+                    Some("hello".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn double_width_line_geometry() {
+        assert_eq!(display_columns_for_cell(LineSize::Normal, 3), 3..4);
+        assert_eq!(display_columns_for_cell(LineSize::DoubleWidth, 3), 6..8);
+        assert_eq!(display_columns_for_cell(LineSize::DoubleHeightTop, 0), 0..2);
+        assert_eq!(
+            display_columns_for_cell(LineSize::DoubleHeightBottom, 1),
+            2..4
+        );
+
+        // A glyph in a 10x20 cell, with a texture that spans the
+        // top half of the atlas
+        let rect = GlyphRect {
+            left: 1.,
+            top: 4.,
+            right: 9.,
+            bottom: 16.,
+        };
+        let texture = TextureRect::new(TextureCoord::new(0., 0.), TextureSize::new(0.5, 0.5));
+
+        assert_eq!(
+            scale_glyph_for_line_size(LineSize::Normal, 20., rect, texture),
+            (rect, texture)
+        );
+
+        // Double width stretches the glyph across two display cells
+        assert_eq!(
+            scale_glyph_for_line_size(LineSize::DoubleWidth, 20., rect, texture),
+            (
+                GlyphRect {
+                    left: 2.,
+                    top: 4.,
+                    right: 18.,
+                    bottom: 16.,
+                },
+                texture
+            )
+        );
+
+        // Double height shows the upper and lower part of the doubled
+        // glyph in each half, splitting the glyph at 12/24 of its height
+        assert_eq!(
+            scale_glyph_for_line_size(LineSize::DoubleHeightTop, 20., rect, texture),
+            (
+                GlyphRect {
+                    left: 2.,
+                    top: 8.,
+                    right: 18.,
+                    bottom: 20.,
+                },
+                TextureRect::new(TextureCoord::new(0., 0.), TextureSize::new(0.5, 0.25))
+            )
+        );
+        assert_eq!(
+            scale_glyph_for_line_size(LineSize::DoubleHeightBottom, 20., rect, texture),
+            (
+                GlyphRect {
+                    left: 2.,
+                    top: 0.,
+                    right: 18.,
+                    bottom: 12.,
+                },
+                TextureRect::new(TextureCoord::new(0., 0.25), TextureSize::new(0.5, 0.25))
+            )
+        );
+    }
+
+    #[test]
+    fn typing_hides_the_mouse_cursor_until_it_moves() {
+        let mut cursor = MouseCursorHider::default();
+        assert!(!cursor.is_hidden());
+
+        assert!(cursor.key_typed(true));
+        assert!(cursor.is_hidden());
+        // Already hidden; no need to hide it again
+        assert!(!cursor.key_typed(true));
+        assert!(cursor.is_hidden());
+
+        cursor.mouse_activity();
+        assert!(!cursor.is_hidden());
+        cursor.mouse_activity();
+        assert!(!cursor.is_hidden());
+
+        assert!(cursor.key_typed(true));
+        assert!(cursor.is_hidden());
+        cursor.mouse_activity();
+
+        // Typing leaves the cursor alone when the option is disabled
+        assert!(!cursor.key_typed(false));
+        assert!(!cursor.is_hidden());
+    }
+
+    #[test]
+    fn smooth_scroll_eases_to_the_target() {
+        let duration = Duration::from_millis(150);
+        let frame = Duration::from_millis(16);
+
+        for &(from, to) in &[(100., 80.), (80., 100.), (10.5, 11.)] {
+            let mut prior = from;
+            let mut elapsed = Duration::from_millis(0);
+            while let Some(top) = smooth_scroll_position(from, to, elapsed, duration) {
+                // Never moves away from the target
+                assert!((top - prior) * (to - from) >= 0.);
+                prior = top;
+                elapsed += frame;
+            }
+            // The last frame is close enough to the target that
+            // snapping to it isn't noticeable
+            assert!((to - prior).abs() <= (to - from).abs() / 100.);
+            assert_eq!(smooth_scroll_position(from, to, duration, duration), None);
+            assert_eq!(
+                smooth_scroll_position(from, to, elapsed * 2, duration),
+                None
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn dropped_files_are_shell_quoted() {
+        assert_eq!(quote_dropped_file("/tmp/file.txt"), "/tmp/file.txt");
+        assert_eq!(
+            quote_dropped_file("/tmp/with space.txt"),
+            "'/tmp/with space.txt'"
+        );
+        assert_eq!(
+            quote_dropped_file("/tmp/it's \"quoted\""),
+            "'/tmp/it'\\''s \"quoted\"'"
+        );
+        assert_eq!(quote_dropped_file("/tmp/$HOME"), "'/tmp/$HOME'");
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn dropped_files_are_shell_quoted() {
+        assert_eq!(quote_dropped_file("C:/file.txt"), "C:/file.txt");
+        assert_eq!(
+            quote_dropped_file("C:\\Program Files\\it's.txt"),
+            "\"C:\\Program Files\\it's.txt\""
+        );
+    }
+}
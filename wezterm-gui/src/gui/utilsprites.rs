@@ -1,9 +1,9 @@
-use super::glyphcache::GlyphCache;
-use ::window::bitmaps::atlas::{OutOfTextureSpace, Sprite};
+use ::window::bitmaps::atlas::{Atlas, OutOfTextureSpace, Sprite};
 use ::window::bitmaps::{BitmapImage, Image, Texture2d};
 use ::window::*;
 use std::rc::Rc;
 use termwiz::surface::CursorShape;
+use wezterm_font::shaper::FontMetrics;
 use wezterm_font::units::*;
 use wezterm_font::FontConfiguration;
 use wezterm_term::Underline;
@@ -23,22 +23,37 @@ impl RenderMetrics {
         let metrics = fonts
             .default_font_metrics()
             .expect("failed to get font metrics!?");
+        let config = config::configuration();
 
+        Self::with_font_metrics(&metrics, config.line_height, config.cell_width)
+    }
+
+    /// Computes the metrics for cells sized from the font `metrics`,
+    /// scaled by the `line_height` and `cell_width` multipliers.
+    /// Any extra height is split evenly above and below the text by
+    /// raising the baseline by half of the extra amount.
+    pub fn with_font_metrics(metrics: &FontMetrics, line_height: f64, cell_width: f64) -> Self {
+        let font_cell_height = metrics.cell_height.get().ceil();
         let (cell_height, cell_width) = (
-            metrics.cell_height.get().ceil() as usize,
-            metrics.cell_width.get().ceil() as usize,
+            (font_cell_height * line_height).round().max(1.) as usize,
+            (metrics.cell_width.get().ceil() * cell_width)
+                .round()
+                .max(1.) as usize,
         );
 
+        let descender =
+            metrics.descender - PixelLength::new((cell_height as f64 - font_cell_height) / 2.);
+
         let underline_height = metrics.underline_thickness.get().round().max(1.) as isize;
 
         let descender_row =
-            (cell_height as f64 + (metrics.descender - metrics.underline_position).get()) as isize;
+            (cell_height as f64 + (descender - metrics.underline_position).get()) as isize;
         let descender_plus_two =
             (2 * underline_height + descender_row).min(cell_height as isize - 1);
         let strike_row = descender_row / 2;
 
         Self {
-            descender: metrics.descender,
+            descender,
             descender_row,
             descender_plus_two,
             strike_row,
@@ -48,29 +63,160 @@ impl RenderMetrics {
     }
 }
 
+#[cfg(test)]
+impl RenderMetrics {
+    /// Metrics for a cell of the specified size whose baseline is
+    /// `descender` pixels above the bottom, for tests that don't
+    /// load a font.
+    pub fn for_cell(width: isize, height: isize, descender: isize) -> Self {
+        let descender_row = height - descender;
+        Self {
+            descender: PixelLength::new(-(descender as f64)),
+            descender_row,
+            descender_plus_two: (descender_row + 2).min(height - 1),
+            underline_height: 1,
+            strike_row: descender_row / 2,
+            cell_size: Size::new(width, height),
+        }
+    }
+}
+
+/// Draw a wavy line in the underline area of the cell, for use
+/// with `Underline::Curly`.  The wave completes exactly one period
+/// across the width of the cell so that it joins up seamlessly with
+/// the wave drawn in adjacent cells.
+fn draw_curly_underline(buffer: &mut Image, metrics: &RenderMetrics, color: Color) {
+    let (width, height) = (metrics.cell_size.width, metrics.cell_size.height);
+    let thickness = metrics.underline_height;
+    // The wave spans the space occupied by a double underline, but
+    // we move it up if that would push it outside of the cell
+    let amplitude = 2 * thickness;
+    let lowest = (metrics.descender_row + amplitude)
+        .min(height - thickness)
+        .max(0);
+    let highest = (lowest - amplitude).max(0);
+
+    for x in 0..width {
+        // Sample the wave at the center of the pixel, and anti-alias
+        // by spreading the stroke over the rows that it straddles
+        let phase = 2. * std::f64::consts::PI * (x as f64 + 0.5) / width as f64;
+        let top = highest as f64 + (lowest - highest) as f64 * (1. - phase.cos()) / 2.;
+        let bottom = top + thickness as f64;
+        for y in top.floor() as isize..bottom.ceil() as isize {
+            if y < 0 || y >= height {
+                continue;
+            }
+            let coverage = (bottom.min(y as f64 + 1.) - top.max(y as f64))
+                .max(0.)
+                .min(1.);
+            let ink = Color::rgba(0xff, 0xff, 0xff, (255. * coverage).round() as u8);
+            let pix = buffer.pixel_mut(x as usize, y as usize);
+            *pix = ink
+                .composite(Color(*pix), Operator::MultiplyThenOver(color))
+                .0;
+        }
+    }
+}
+
 pub struct UtilSprites<T: Texture2d> {
     pub white_space: Sprite<T>,
     pub single_underline: Sprite<T>,
     pub double_underline: Sprite<T>,
+    pub curly_underline: Sprite<T>,
     pub strike_through: Sprite<T>,
     pub single_and_strike: Sprite<T>,
     pub double_and_strike: Sprite<T>,
+    pub curly_and_strike: Sprite<T>,
     pub cursor_box: Sprite<T>,
     pub cursor_i_beam: Sprite<T>,
     pub cursor_underline: Sprite<T>,
     pub overline: Sprite<T>,
     pub single_under_over: Sprite<T>,
     pub double_under_over: Sprite<T>,
+    pub curly_under_over: Sprite<T>,
     pub strike_over: Sprite<T>,
     pub single_strike_over: Sprite<T>,
     pub double_strike_over: Sprite<T>,
+    pub curly_strike_over: Sprite<T>,
+}
+
+/// Returns the area of the cell that is filled in to draw a bar
+/// cursor that is `thickness` pixels wide.
+fn cursor_bar_rect(cell_size: Size, thickness: usize) -> Rect {
+    let width = (thickness as isize).clamp(1, cell_size.width);
+    Rect::new(Point::new(0, 0), Size::new(width, cell_size.height))
+}
+
+/// Returns the area of the cell that is filled in to draw an underline
+/// cursor that is `thickness` pixels tall.
+fn cursor_underline_rect(cell_size: Size, thickness: usize) -> Rect {
+    let height = (thickness as isize).clamp(1, cell_size.height);
+    Rect::new(
+        Point::new(0, cell_size.height - height),
+        Size::new(cell_size.width, height),
+    )
+}
+
+/// Draw the outline of the cell, for use as the cursor when the
+/// window is unfocused.  The top and bottom edges are `underline_height`
+/// pixels thick and the left and right edges are `border_width` pixels
+/// wide.
+fn draw_cursor_box(buffer: &mut Image, metrics: &RenderMetrics, border_width: usize, color: Color) {
+    let cell_rect = Rect::new(Point::new(0, 0), metrics.cell_size);
+    for i in 0..metrics.underline_height {
+        // Top border
+        buffer.draw_line(
+            Point::new(cell_rect.origin.x, cell_rect.origin.y + i),
+            Point::new(
+                cell_rect.origin.x + metrics.cell_size.width,
+                cell_rect.origin.y + i,
+            ),
+            color,
+            Operator::Source,
+        );
+        // Bottom border
+        buffer.draw_line(
+            Point::new(
+                cell_rect.origin.x,
+                cell_rect.origin.y + metrics.cell_size.height.saturating_sub(1 + i),
+            ),
+            Point::new(
+                cell_rect.origin.x + metrics.cell_size.width,
+                cell_rect.origin.y + metrics.cell_size.height.saturating_sub(1 + i),
+            ),
+            color,
+            Operator::Source,
+        );
+    }
+    for i in 0..border_width {
+        // Left border
+        buffer.draw_line(
+            Point::new(cell_rect.origin.x + i as isize, cell_rect.origin.y),
+            Point::new(
+                cell_rect.origin.x + i as isize,
+                cell_rect.origin.y + metrics.cell_size.height,
+            ),
+            color,
+            Operator::Source,
+        );
+        // Right border
+        buffer.draw_line(
+            Point::new(
+                cell_rect.origin.x + metrics.cell_size.width.saturating_sub(1 + i as isize),
+                cell_rect.origin.y,
+            ),
+            Point::new(
+                cell_rect.origin.x + metrics.cell_size.width.saturating_sub(1 + i as isize),
+                cell_rect.origin.y + metrics.cell_size.height,
+            ),
+            color,
+            Operator::Source,
+        );
+    }
 }
 
 impl<T: Texture2d> UtilSprites<T> {
-    pub fn new(
-        glyph_cache: &mut GlyphCache<T>,
-        metrics: &RenderMetrics,
-    ) -> Result<Self, OutOfTextureSpace> {
+    pub fn new(atlas: &mut Atlas<T>, metrics: &RenderMetrics) -> Result<Self, OutOfTextureSpace> {
         let mut buffer = Image::new(
             metrics.cell_size.width as usize,
             metrics.cell_size.height as usize,
@@ -82,7 +228,7 @@ impl<T: Texture2d> UtilSprites<T> {
         let cell_rect = Rect::new(Point::new(0, 0), metrics.cell_size);
 
         buffer.clear_rect(cell_rect, black);
-        let white_space = glyph_cache.atlas.allocate(&buffer)?;
+        let white_space = atlas.allocate(&buffer)?;
 
         let draw_single = |buffer: &mut Image| {
             for row in 0..metrics.underline_height {
@@ -163,56 +309,76 @@ impl<T: Texture2d> UtilSprites<T> {
 
         buffer.clear_rect(cell_rect, black);
         draw_overline(&mut buffer);
-        let overline = glyph_cache.atlas.allocate(&buffer)?;
+        let overline = atlas.allocate(&buffer)?;
 
         buffer.clear_rect(cell_rect, black);
         draw_single(&mut buffer);
-        let single_underline = glyph_cache.atlas.allocate(&buffer)?;
+        let single_underline = atlas.allocate(&buffer)?;
 
         buffer.clear_rect(cell_rect, black);
         draw_overline(&mut buffer);
         draw_single(&mut buffer);
-        let single_under_over = glyph_cache.atlas.allocate(&buffer)?;
+        let single_under_over = atlas.allocate(&buffer)?;
 
         buffer.clear_rect(cell_rect, black);
         draw_double(&mut buffer);
-        let double_underline = glyph_cache.atlas.allocate(&buffer)?;
+        let double_underline = atlas.allocate(&buffer)?;
 
         buffer.clear_rect(cell_rect, black);
         draw_overline(&mut buffer);
         draw_double(&mut buffer);
-        let double_under_over = glyph_cache.atlas.allocate(&buffer)?;
+        let double_under_over = atlas.allocate(&buffer)?;
+
+        buffer.clear_rect(cell_rect, black);
+        draw_curly_underline(&mut buffer, metrics, white);
+        let curly_underline = atlas.allocate(&buffer)?;
+
+        buffer.clear_rect(cell_rect, black);
+        draw_overline(&mut buffer);
+        draw_curly_underline(&mut buffer, metrics, white);
+        let curly_under_over = atlas.allocate(&buffer)?;
 
         buffer.clear_rect(cell_rect, black);
         draw_strike(&mut buffer);
-        let strike_through = glyph_cache.atlas.allocate(&buffer)?;
+        let strike_through = atlas.allocate(&buffer)?;
 
         buffer.clear_rect(cell_rect, black);
         draw_overline(&mut buffer);
         draw_strike(&mut buffer);
-        let strike_over = glyph_cache.atlas.allocate(&buffer)?;
+        let strike_over = atlas.allocate(&buffer)?;
 
         buffer.clear_rect(cell_rect, black);
         draw_single(&mut buffer);
         draw_strike(&mut buffer);
-        let single_and_strike = glyph_cache.atlas.allocate(&buffer)?;
+        let single_and_strike = atlas.allocate(&buffer)?;
 
         buffer.clear_rect(cell_rect, black);
         draw_overline(&mut buffer);
         draw_single(&mut buffer);
         draw_strike(&mut buffer);
-        let single_strike_over = glyph_cache.atlas.allocate(&buffer)?;
+        let single_strike_over = atlas.allocate(&buffer)?;
 
         buffer.clear_rect(cell_rect, black);
         draw_double(&mut buffer);
         draw_strike(&mut buffer);
-        let double_and_strike = glyph_cache.atlas.allocate(&buffer)?;
+        let double_and_strike = atlas.allocate(&buffer)?;
 
         buffer.clear_rect(cell_rect, black);
         draw_overline(&mut buffer);
         draw_double(&mut buffer);
         draw_strike(&mut buffer);
-        let double_strike_over = glyph_cache.atlas.allocate(&buffer)?;
+        let double_strike_over = atlas.allocate(&buffer)?;
+
+        buffer.clear_rect(cell_rect, black);
+        draw_curly_underline(&mut buffer, metrics, white);
+        draw_strike(&mut buffer);
+        let curly_and_strike = atlas.allocate(&buffer)?;
+
+        buffer.clear_rect(cell_rect, black);
+        draw_overline(&mut buffer);
+        draw_curly_underline(&mut buffer, metrics, white);
+        draw_strike(&mut buffer);
+        let curly_strike_over = atlas.allocate(&buffer)?;
 
         // Derive a width for the border box from the underline height,
         // but aspect ratio adjusted for width.
@@ -221,110 +387,80 @@ impl<T: Texture2d> UtilSprites<T> {
             .ceil() as usize;
 
         buffer.clear_rect(cell_rect, black);
-        for i in 0..metrics.underline_height {
-            // Top border
-            buffer.draw_line(
-                Point::new(cell_rect.origin.x, cell_rect.origin.y + i),
-                Point::new(
-                    cell_rect.origin.x + metrics.cell_size.width,
-                    cell_rect.origin.y + i,
-                ),
-                white,
-                Operator::Source,
-            );
-            // Bottom border
-            buffer.draw_line(
-                Point::new(
-                    cell_rect.origin.x,
-                    cell_rect.origin.y + metrics.cell_size.height.saturating_sub(1 + i),
-                ),
-                Point::new(
-                    cell_rect.origin.x + metrics.cell_size.width,
-                    cell_rect.origin.y + metrics.cell_size.height.saturating_sub(1 + i),
-                ),
-                white,
-                Operator::Source,
-            );
-        }
-        for i in 0..border_width {
-            // Left border
-            buffer.draw_line(
-                Point::new(cell_rect.origin.x + i as isize, cell_rect.origin.y),
-                Point::new(
-                    cell_rect.origin.x + i as isize,
-                    cell_rect.origin.y + metrics.cell_size.height,
-                ),
-                white,
-                Operator::Source,
-            );
-            // Right border
-            buffer.draw_line(
-                Point::new(
-                    cell_rect.origin.x + metrics.cell_size.width.saturating_sub(1 + i as isize),
-                    cell_rect.origin.y,
-                ),
-                Point::new(
-                    cell_rect.origin.x + metrics.cell_size.width.saturating_sub(1 + i as isize),
-                    cell_rect.origin.y + metrics.cell_size.height,
-                ),
-                white,
-                Operator::Source,
-            );
-        }
-        let cursor_box = glyph_cache.atlas.allocate(&buffer)?;
+        draw_cursor_box(&mut buffer, metrics, border_width, white);
+        let cursor_box = atlas.allocate(&buffer)?;
+
+        let cursor_thickness = config::configuration().cursor_thickness.map(usize::from);
 
         buffer.clear_rect(cell_rect, black);
-        for i in 0..border_width * 2 {
-            // Left border
-            buffer.draw_line(
-                Point::new(cell_rect.origin.x + i as isize, cell_rect.origin.y),
-                Point::new(
-                    cell_rect.origin.x + i as isize,
-                    cell_rect.origin.y + metrics.cell_size.height,
-                ),
-                white,
-                Operator::Source,
-            );
-        }
-        let cursor_i_beam = glyph_cache.atlas.allocate(&buffer)?;
+        buffer.clear_rect(
+            cursor_bar_rect(
+                metrics.cell_size,
+                cursor_thickness.unwrap_or(border_width * 2),
+            ),
+            white,
+        );
+        let cursor_i_beam = atlas.allocate(&buffer)?;
 
         buffer.clear_rect(cell_rect, black);
-        for i in 0..metrics.underline_height {
-            // Bottom border
-            buffer.draw_line(
-                Point::new(
-                    cell_rect.origin.x,
-                    cell_rect.origin.y + metrics.cell_size.height.saturating_sub(1 + i),
-                ),
-                Point::new(
-                    cell_rect.origin.x + metrics.cell_size.width,
-                    cell_rect.origin.y + metrics.cell_size.height.saturating_sub(1 + i),
-                ),
-                white,
-                Operator::Source,
-            );
-        }
-        let cursor_underline = glyph_cache.atlas.allocate(&buffer)?;
+        buffer.clear_rect(
+            cursor_underline_rect(
+                metrics.cell_size,
+                cursor_thickness.unwrap_or(metrics.underline_height as usize),
+            ),
+            white,
+        );
+        let cursor_underline = atlas.allocate(&buffer)?;
 
         Ok(Self {
             white_space,
             single_underline,
             double_underline,
+            curly_underline,
             strike_through,
             single_and_strike,
             double_and_strike,
+            curly_and_strike,
             cursor_box,
             cursor_i_beam,
             cursor_underline,
             overline,
             single_under_over,
             double_under_over,
+            curly_under_over,
             strike_over,
             single_strike_over,
             double_strike_over,
+            curly_strike_over,
         })
     }
 
+    /// Returns a copy of the sprites that refer to `texture`, which
+    /// the atlas has grown into
+    pub fn with_texture(&self, texture: &Rc<T>) -> Self {
+        Self {
+            white_space: self.white_space.with_texture(texture),
+            single_underline: self.single_underline.with_texture(texture),
+            double_underline: self.double_underline.with_texture(texture),
+            curly_underline: self.curly_underline.with_texture(texture),
+            strike_through: self.strike_through.with_texture(texture),
+            single_and_strike: self.single_and_strike.with_texture(texture),
+            double_and_strike: self.double_and_strike.with_texture(texture),
+            curly_and_strike: self.curly_and_strike.with_texture(texture),
+            cursor_box: self.cursor_box.with_texture(texture),
+            cursor_i_beam: self.cursor_i_beam.with_texture(texture),
+            cursor_underline: self.cursor_underline.with_texture(texture),
+            overline: self.overline.with_texture(texture),
+            single_under_over: self.single_under_over.with_texture(texture),
+            double_under_over: self.double_under_over.with_texture(texture),
+            curly_under_over: self.curly_under_over.with_texture(texture),
+            strike_over: self.strike_over.with_texture(texture),
+            single_strike_over: self.single_strike_over.with_texture(texture),
+            double_strike_over: self.double_strike_over.with_texture(texture),
+            curly_strike_over: self.curly_strike_over.with_texture(texture),
+        }
+    }
+
     /// Figure out what we're going to draw for the underline.
     /// If the current cell is part of the current URL highlight
     /// then we want to show the underline.
@@ -353,6 +489,10 @@ impl<T: Texture2d> UtilSprites<T> {
             (false, true, Underline::None, false) => &self.strike_through,
             (false, true, Underline::Single, false) => &self.single_and_strike,
             (false, true, Underline::Double, false) => &self.double_and_strike,
+            (true, false, Underline::Curly, false) => &self.double_underline,
+            (true, true, Underline::Curly, false) => &self.single_and_strike,
+            (false, false, Underline::Curly, false) => &self.curly_underline,
+            (false, true, Underline::Curly, false) => &self.curly_and_strike,
 
             (true, false, Underline::None, true) => &self.single_under_over,
             (true, false, Underline::Single, true) => &self.double_under_over,
@@ -366,6 +506,10 @@ impl<T: Texture2d> UtilSprites<T> {
             (false, true, Underline::None, true) => &self.strike_over,
             (false, true, Underline::Single, true) => &self.single_strike_over,
             (false, true, Underline::Double, true) => &self.double_strike_over,
+            (true, false, Underline::Curly, true) => &self.double_under_over,
+            (true, true, Underline::Curly, true) => &self.single_strike_over,
+            (false, false, Underline::Curly, true) => &self.curly_under_over,
+            (false, true, Underline::Curly, true) => &self.curly_strike_over,
         }
     }
 
@@ -383,3 +527,175 @@ impl<T: Texture2d> UtilSprites<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::window::bitmaps::ImageTexture;
+
+    #[test]
+    fn curly_underline_is_wavy() {
+        let metrics = RenderMetrics::for_cell(16, 30, 6);
+        let mut buffer = Image::new(16, 30);
+        draw_curly_underline(&mut buffer, &metrics, Color::rgb(0xff, 0xff, 0xff));
+
+        let alpha = |x: usize, y: usize| Color(*buffer.pixel(x, y)).as_rgba().3 as f64;
+
+        // Returns the vertical center of the ink in the specified column
+        let ink_center = |x: usize| {
+            let total: f64 = (0..30).map(|y| alpha(x, y)).sum();
+            assert!(total > 0., "column {} has no ink", x);
+            (0..30).map(|y| y as f64 * alpha(x, y)).sum::<f64>() / total
+        };
+
+        // Nothing is drawn above the underline area
+        for y in 0..metrics.descender_row as usize {
+            for x in 0..16 {
+                assert_eq!(alpha(x, y), 0., "unexpected ink at {},{}", x, y);
+            }
+        }
+
+        // The stroke is continuous and dips in the middle of the cell.
+        // It is symmetrical so that it joins up with its neighbors.
+        let centers: Vec<f64> = (0..16).map(ink_center).collect();
+        assert!(centers[8] - centers[0] >= 1.5, "{:?}", centers);
+        for (left, right) in centers.iter().zip(centers.iter().rev()) {
+            assert!((left - right).abs() < 0.1, "{:?}", centers);
+        }
+    }
+
+    #[test]
+    fn cursor_box_is_an_outline() {
+        let metrics = RenderMetrics::for_cell(16, 30, 6);
+        let mut buffer = Image::new(16, 30);
+        draw_cursor_box(&mut buffer, &metrics, 2, Color::rgb(0xff, 0xff, 0xff));
+
+        let inked = |x: usize, y: usize| Color(*buffer.pixel(x, y)).as_rgba().3 != 0;
+
+        for y in 0..30 {
+            for x in 0..16 {
+                let on_border = y == 0 || y == 29 || x < 2 || x >= 14;
+                assert_eq!(inked(x, y), on_border, "pixel {},{}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn overline_is_drawn_along_the_top_of_the_cell() {
+        let metrics = RenderMetrics::for_cell(16, 30, 6);
+        let texture = Rc::new(ImageTexture::new(512, 512));
+        let mut atlas = Atlas::new(&texture).unwrap();
+        let sprites = UtilSprites::new(&mut atlas, &metrics).unwrap();
+
+        let row_has_ink = |sprite: &Sprite<ImageTexture>, y: usize| {
+            let image = texture.image.borrow();
+            let origin = sprite.coords.origin;
+            (0..16).any(|x| {
+                let pixel = *image.pixel(origin.x as usize + x, origin.y as usize + y);
+                Color(pixel).as_rgba().3 != 0
+            })
+        };
+
+        assert!(row_has_ink(&sprites.overline, 0));
+        for y in 1..30 {
+            assert!(!row_has_ink(&sprites.overline, y), "row {}", y);
+        }
+
+        // Every combination of the other line attributes adds the
+        // overline when, and only when, it is set
+        for &highlight in &[false, true] {
+            for &strike in &[false, true] {
+                for &underline in &[
+                    Underline::None,
+                    Underline::Single,
+                    Underline::Double,
+                    Underline::Curly,
+                ] {
+                    for &overline in &[false, true] {
+                        let sprite = sprites.select_sprite(highlight, strike, underline, overline);
+                        assert_eq!(
+                            row_has_ink(sprite, 0),
+                            overline,
+                            "highlight={} strike={} underline={:?} overline={}",
+                            highlight,
+                            strike,
+                            underline,
+                            overline
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cursor_bar_and_underline_have_the_requested_thickness() {
+        let cell_size = Size::new(16, 30);
+
+        assert_eq!(
+            cursor_bar_rect(cell_size, 2),
+            Rect::new(Point::new(0, 0), Size::new(2, 30))
+        );
+        assert_eq!(
+            cursor_underline_rect(cell_size, 3),
+            Rect::new(Point::new(0, 27), Size::new(16, 3))
+        );
+
+        // The thickness is limited to the size of the cell, and
+        // the cursor is always at least one pixel thick
+        assert_eq!(
+            cursor_bar_rect(cell_size, 20),
+            Rect::new(Point::new(0, 0), cell_size)
+        );
+        assert_eq!(
+            cursor_underline_rect(cell_size, 40),
+            Rect::new(Point::new(0, 0), cell_size)
+        );
+        assert_eq!(
+            cursor_bar_rect(cell_size, 0),
+            Rect::new(Point::new(0, 0), Size::new(1, 30))
+        );
+        assert_eq!(
+            cursor_underline_rect(cell_size, 0),
+            Rect::new(Point::new(0, 29), Size::new(16, 1))
+        );
+    }
+
+    #[test]
+    fn line_height_adds_leading_around_the_text() {
+        let font_metrics = FontMetrics {
+            cell_width: PixelLength::new(10.),
+            cell_height: PixelLength::new(20.),
+            descender: PixelLength::new(-4.),
+            underline_thickness: PixelLength::new(1.),
+            underline_position: PixelLength::new(-2.),
+        };
+
+        let normal = RenderMetrics::with_font_metrics(&font_metrics, 1.0, 1.0);
+        assert_eq!(normal.cell_size, Size::new(10, 20));
+        assert_eq!(normal.descender, PixelLength::new(-4.));
+
+        let tall = RenderMetrics::with_font_metrics(&font_metrics, 1.2, 1.0);
+        assert_eq!(tall.cell_size, Size::new(10, 24));
+
+        // The baseline sits 16px from the top of the normal cell;
+        // the 4px of extra leading puts 2px above and 2px below
+        let baseline = |m: &RenderMetrics| m.cell_size.height as f64 + m.descender.get();
+        assert_eq!(baseline(&normal), 16.);
+        assert_eq!(baseline(&tall), 18.);
+        assert_eq!(
+            tall.cell_size.height as f64 - baseline(&tall),
+            normal.cell_size.height as f64 - baseline(&normal) + 2.
+        );
+
+        // The underline stays in the same position relative to the baseline
+        assert_eq!(
+            tall.descender_row - baseline(&tall) as isize,
+            normal.descender_row - baseline(&normal) as isize
+        );
+
+        let wide = RenderMetrics::with_font_metrics(&font_metrics, 1.0, 1.5);
+        assert_eq!(wide.cell_size, Size::new(15, 20));
+        assert_eq!(wide.descender, normal.descender);
+    }
+}
@@ -6,10 +6,12 @@ in vec2 tex;
 in vec2 underline;
 in vec4 bg_color;
 in vec4 fg_color;
+in vec4 underline_color;
 in float has_color;
 in vec2 cursor;
 in vec4 cursor_color;
 in vec3 hsv;
+in vec2 clip;
 
 uniform mat4 projection;
 uniform bool window_bg_layer;
@@ -24,6 +26,9 @@ out vec3 o_hsv;
 out vec4 o_bg_color;
 out vec4 o_cursor_color;
 out vec4 o_fg_color;
+out vec4 o_underline_color;
+out vec2 o_clip;
+out float o_y;
 
 // Returns a position that is outside of the viewport,
 // such that this vertex effectively won't contribute
@@ -37,11 +42,14 @@ void main() {
     o_tex = tex;
     o_has_color = has_color;
     o_fg_color = fg_color;
+    o_underline_color = underline_color;
     o_bg_color = bg_color;
     o_underline = underline;
     o_cursor = cursor;
     o_cursor_color = cursor_color;
     o_hsv = hsv;
+    o_clip = clip;
+    o_y = position.y;
 
     if (window_bg_layer) {
       if (o_has_color == 2.0) {
@@ -61,5 +69,6 @@ void main() {
     } else {
       // Use only the adjusted cell position to render the glyph
       gl_Position = projection * vec4(position + adjust, 0.0, 1.0);
+      o_y = position.y + adjust.y;
     }
 }
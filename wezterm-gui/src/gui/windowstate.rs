@@ -0,0 +1,192 @@
+//! Remembers the position and size of the window between runs.
+//! The state is recorded in the cache directory, keyed by the
+//! window class, so that instances started with different classes
+//! don't stomp on each other.
+use ::window::{ScreenPoint, ScreenRect};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowState {
+    /// The position of the top left corner of the client area,
+    /// in screen coordinates
+    pub x: isize,
+    pub y: isize,
+    /// The size of the client area, in pixels
+    pub width: usize,
+    pub height: usize,
+}
+
+fn state_path() -> PathBuf {
+    config::CACHE_DIR.join("window-state.json")
+}
+
+fn load_all() -> HashMap<String, WindowState> {
+    let path = state_path();
+    match std::fs::read(&path) {
+        Ok(data) => match serde_json::from_slice(&data) {
+            Ok(states) => states,
+            Err(err) => {
+                log::warn!("ignoring window state {}: {}", path.display(), err);
+                HashMap::new()
+            }
+        },
+        Err(_) => HashMap::new(),
+    }
+}
+
+impl WindowState {
+    /// Returns the saved state for the specified window class, if any
+    pub fn load(class: &str) -> Option<Self> {
+        load_all().get(class).copied()
+    }
+
+    /// Records the state for the specified window class
+    pub fn save(&self, class: &str) -> anyhow::Result<()> {
+        let mut states = load_all();
+        if states.get(class) == Some(self) {
+            return Ok(());
+        }
+        states.insert(class.to_string(), *self);
+
+        let path = state_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating {}", parent.display()))?;
+        }
+        let data = serde_json::to_vec(&states)?;
+        std::fs::write(&path, data).with_context(|| format!("writing {}", path.display()))?;
+        Ok(())
+    }
+
+    fn rect(&self) -> ScreenRect {
+        ScreenRect::new(
+            ScreenPoint::new(self.x, self.y),
+            euclid::size2(self.width as isize, self.height as isize),
+        )
+    }
+
+    /// Adjusts the state so that the window is entirely contained
+    /// within one of the `screens`; the screen that the window overlaps
+    /// the most is chosen, falling back to the first screen if it
+    /// doesn't overlap any of them.  The window is shrunk if it is
+    /// larger than that screen.
+    /// If the system didn't tell us about any screens, the state is
+    /// returned unchanged.
+    pub fn clamp_to_screens(&self, screens: &[ScreenRect]) -> Self {
+        let rect = self.rect();
+        let screen = screens
+            .iter()
+            .filter_map(|screen| {
+                rect.intersection(screen)
+                    .map(|overlap| (overlap.size.area(), screen))
+            })
+            .max_by_key(|(area, _)| *area)
+            .map(|(_, screen)| screen)
+            .or_else(|| screens.first());
+
+        let screen = match screen {
+            Some(screen) => screen,
+            None => return *self,
+        };
+
+        let width = rect.size.width.min(screen.size.width);
+        let height = rect.size.height.min(screen.size.height);
+        let x = rect
+            .origin
+            .x
+            .max(screen.min_x())
+            .min(screen.max_x() - width);
+        let y = rect
+            .origin
+            .y
+            .max(screen.min_y())
+            .min(screen.max_y() - height);
+
+        Self {
+            x,
+            y,
+            width: width as usize,
+            height: height as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn screen(x: isize, y: isize, width: isize, height: isize) -> ScreenRect {
+        ScreenRect::new(ScreenPoint::new(x, y), euclid::size2(width, height))
+    }
+
+    fn state(x: isize, y: isize, width: usize, height: usize) -> WindowState {
+        WindowState {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn clamp_to_screens() {
+        let primary = screen(0, 0, 1920, 1080);
+        let secondary = screen(1920, 0, 1280, 1024);
+
+        // Entirely visible: left alone
+        assert_eq!(
+            state(100, 100, 800, 600).clamp_to_screens(&[primary]),
+            state(100, 100, 800, 600)
+        );
+
+        // Partly off the bottom right of the only monitor
+        assert_eq!(
+            state(1500, 900, 800, 600).clamp_to_screens(&[primary]),
+            state(1120, 480, 800, 600)
+        );
+
+        // Partly off the top left
+        assert_eq!(
+            state(-200, -50, 800, 600).clamp_to_screens(&[primary]),
+            state(0, 0, 800, 600)
+        );
+
+        // Straddling two monitors: moved onto the one it mostly covers
+        assert_eq!(
+            state(1700, 100, 800, 600).clamp_to_screens(&[primary, secondary]),
+            state(1920, 100, 800, 600)
+        );
+        assert_eq!(
+            state(1500, 100, 800, 600).clamp_to_screens(&[primary, secondary]),
+            state(1120, 100, 800, 600)
+        );
+
+        // On a monitor that is no longer connected
+        assert_eq!(
+            state(2200, 100, 800, 600).clamp_to_screens(&[primary]),
+            state(1120, 100, 800, 600)
+        );
+
+        // In the area below the shorter monitor, which none of the
+        // monitors cover even though it is within the root window
+        assert_eq!(
+            state(2000, 1030, 800, 40).clamp_to_screens(&[primary, secondary]),
+            state(1120, 1030, 800, 40)
+        );
+
+        // Larger than the monitor
+        assert_eq!(
+            state(-10, -10, 2560, 1440).clamp_to_screens(&[primary]),
+            state(0, 0, 1920, 1080)
+        );
+
+        // No information about the screens
+        assert_eq!(
+            state(5000, 5000, 800, 600).clamp_to_screens(&[]),
+            state(5000, 5000, 800, 600)
+        );
+    }
+}
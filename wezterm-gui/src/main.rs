@@ -14,6 +14,7 @@ use structopt::StructOpt;
 use wezterm_client::domain::{ClientDomain, ClientDomainConfig};
 use wezterm_gui_subcommands::*;
 
+mod benchmark;
 mod gui;
 mod markdown;
 mod scripting;
@@ -253,10 +254,15 @@ fn run_terminal_gui(config: config::ConfigHandle, opts: StartCommand) -> anyhow:
     opts.font_locator
         .unwrap_or(config.font_locator)
         .set_default();
-    opts.font_shaper.unwrap_or(config.font_shaper).set_default();
-    opts.font_rasterizer
-        .unwrap_or(config.font_rasterizer)
-        .set_default();
+    // The shaper and rasterizer are otherwise taken from the config
+    // each time that fonts are loaded, so only override them here when
+    // they were specified on the command line
+    if let Some(shaper) = opts.font_shaper {
+        shaper.set_default();
+    }
+    if let Some(rasterizer) = opts.font_rasterizer {
+        rasterizer.set_default();
+    }
 
     let need_builder = !opts.prog.is_empty() || opts.cwd.is_some();
 
@@ -291,6 +297,15 @@ fn run_terminal_gui(config: config::ConfigHandle, opts: StartCommand) -> anyhow:
     })
     .detach();
 
+    if let Some(seconds) = opts.benchmark_render {
+        promise::spawn::spawn(async move {
+            if let Err(err) = benchmark::run(std::time::Duration::from_secs(seconds)).await {
+                terminate_with_error(err);
+            }
+        })
+        .detach();
+    }
+
     maybe_show_configuration_error_window();
     gui.run_forever()
 }
@@ -427,6 +442,7 @@ fn run() -> anyhow::Result<()> {
     #[cfg(windows)]
     {
         window::os::windows::use_dead_keys(config.use_dead_keys);
+        crate::gui::set_window_frame_theme(config.window_frame_theme);
     }
 
     match opts
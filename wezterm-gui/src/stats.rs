@@ -2,6 +2,7 @@ use config::configuration;
 use hdrhistogram::Histogram;
 use metrics::{Key, Recorder};
 use std::collections::HashMap;
+use std::io::Write;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
@@ -9,8 +10,16 @@ use tabout::{tabulate_output, Alignment, Column};
 
 static ENABLE_STAT_PRINT: AtomicBool = AtomicBool::new(true);
 
+lazy_static::lazy_static! {
+    /// Allows printing the stats on demand, rather than only
+    /// from the periodic logging thread.
+    static ref INNER: Mutex<Option<Arc<Mutex<Inner>>>> = Mutex::new(None);
+}
+
 struct Inner {
     histograms: HashMap<Key, Histogram<u64>>,
+    counters: HashMap<Key, u64>,
+    gauges: HashMap<Key, i64>,
 }
 
 fn pctile_latency(histogram: &Histogram<u64>, p: f64) -> Duration {
@@ -21,11 +30,33 @@ impl Inner {
     fn run(inner: Arc<Mutex<Inner>>) {
         let mut last_print = Instant::now();
 
+        loop {
+            std::thread::sleep(Duration::from_secs(10));
+            if !ENABLE_STAT_PRINT.load(Ordering::Acquire) {
+                break;
+            }
+
+            let seconds = configuration().periodic_stat_logging;
+            if seconds == 0 {
+                continue;
+            }
+            if last_print.elapsed() >= Duration::from_secs(seconds) {
+                inner.lock().unwrap().print(&mut std::io::stderr().lock());
+                last_print = Instant::now();
+            }
+        }
+    }
+
+    fn print<W: Write>(&self, out: &mut W) {
         let cols = vec![
             Column {
                 name: "STAT".to_string(),
                 alignment: Alignment::Left,
             },
+            Column {
+                name: "count".to_string(),
+                alignment: Alignment::Right,
+            },
             Column {
                 name: "p50".to_string(),
                 alignment: Alignment::Left,
@@ -40,51 +71,66 @@ impl Inner {
             },
         ];
 
-        loop {
-            std::thread::sleep(Duration::from_secs(10));
-            if !ENABLE_STAT_PRINT.load(Ordering::Acquire) {
-                break;
-            }
-
-            let seconds = configuration().periodic_stat_logging;
-            if seconds == 0 {
-                continue;
-            }
-            if last_print.elapsed() >= Duration::from_secs(seconds) {
-                let inner = inner.lock().unwrap();
-                let mut data = vec![];
-                for (key, histogram) in &inner.histograms {
-                    if key.name().ends_with(".size") {
-                        let p50 = histogram.value_at_percentile(50.);
-                        let p75 = histogram.value_at_percentile(75.);
-                        let p95 = histogram.value_at_percentile(95.);
-                        data.push(vec![
-                            key.to_string(),
-                            format!("{:.2?}", p50),
-                            format!("{:.2?}", p75),
-                            format!("{:.2?}", p95),
-                        ]);
-                    } else {
-                        let p50 = pctile_latency(histogram, 50.);
-                        let p75 = pctile_latency(histogram, 75.);
-                        let p95 = pctile_latency(histogram, 95.);
-                        data.push(vec![
-                            key.to_string(),
-                            format!("{:.2?}", p50),
-                            format!("{:.2?}", p75),
-                            format!("{:.2?}", p95),
-                        ]);
-                    }
-                }
-                data.sort_by(|a, b| a[0].cmp(&b[0]));
-                eprintln!();
-                tabulate_output(&cols, &data, &mut std::io::stderr().lock()).ok();
-                last_print = Instant::now();
+        let mut data = vec![];
+        for (key, histogram) in &self.histograms {
+            if key.name().ends_with(".size") {
+                let p50 = histogram.value_at_percentile(50.);
+                let p75 = histogram.value_at_percentile(75.);
+                let p95 = histogram.value_at_percentile(95.);
+                data.push(vec![
+                    key.to_string(),
+                    histogram.len().to_string(),
+                    format!("{:.2?}", p50),
+                    format!("{:.2?}", p75),
+                    format!("{:.2?}", p95),
+                ]);
+            } else {
+                let p50 = pctile_latency(histogram, 50.);
+                let p75 = pctile_latency(histogram, 75.);
+                let p95 = pctile_latency(histogram, 95.);
+                data.push(vec![
+                    key.to_string(),
+                    histogram.len().to_string(),
+                    format!("{:.2?}", p50),
+                    format!("{:.2?}", p75),
+                    format!("{:.2?}", p95),
+                ]);
             }
         }
+        for (key, value) in &self.counters {
+            data.push(vec![
+                key.to_string(),
+                value.to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+            ]);
+        }
+        for (key, value) in &self.gauges {
+            data.push(vec![
+                key.to_string(),
+                value.to_string(),
+                String::new(),
+                String::new(),
+                String::new(),
+            ]);
+        }
+        data.sort_by(|a, b| a[0].cmp(&b[0]));
+        writeln!(out).ok();
+        tabulate_output(&cols, &data, out).ok();
     }
 }
 
+/// Hit and miss counts for one of the caches that are consulted
+/// while painting.  These are plain counters rather than metrics so
+/// that counting costs nothing on the paint path; they are only
+/// reported as metrics when running with `--benchmark-render`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HitCounts {
+    pub hits: u64,
+    pub misses: u64,
+}
+
 pub struct Stats {
     inner: Arc<Mutex<Inner>>,
 }
@@ -94,6 +140,8 @@ impl Stats {
         Self {
             inner: Arc::new(Mutex::new(Inner {
                 histograms: HashMap::new(),
+                counters: HashMap::new(),
+                gauges: HashMap::new(),
             })),
         }
     }
@@ -101,20 +149,42 @@ impl Stats {
     pub fn init() -> anyhow::Result<()> {
         let stats = Self::new();
         let inner = Arc::clone(&stats.inner);
+        INNER.lock().unwrap().replace(Arc::clone(&inner));
         std::thread::spawn(move || Inner::run(inner));
         let rec = Box::new(stats);
         metrics::set_boxed_recorder(rec)
             .map_err(|e| anyhow::anyhow!("Failed to set metrics recorder:{}", e))
     }
+
+    /// Print the accumulated stats to `out` immediately
+    pub fn print_now<W: Write>(out: &mut W) {
+        if let Some(inner) = INNER.lock().unwrap().as_ref() {
+            inner.lock().unwrap().print(out);
+        }
+    }
+
+    /// Discard the accumulated stats
+    pub fn reset() {
+        if let Some(inner) = INNER.lock().unwrap().as_ref() {
+            let mut inner = inner.lock().unwrap();
+            inner.histograms.clear();
+            inner.counters.clear();
+            inner.gauges.clear();
+        }
+    }
 }
 
 impl Recorder for Stats {
     fn increment_counter(&self, key: Key, value: u64) {
         log::trace!("counter '{}' -> {}", key, value);
+        let mut inner = self.inner.lock().unwrap();
+        *inner.counters.entry(key).or_insert(0) += value;
     }
 
     fn update_gauge(&self, key: Key, value: i64) {
         log::trace!("gauge '{}' -> {}", key, value);
+        let mut inner = self.inner.lock().unwrap();
+        inner.gauges.insert(key, value);
     }
 
     fn record_histogram(&self, key: Key, value: u64) {
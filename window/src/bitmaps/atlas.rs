@@ -27,6 +27,10 @@ where
 
     /// Dimensions of the texture
     side: usize,
+
+    /// The number of pixels reserved by the sprites that have
+    /// been allocated, including their padding
+    used_area: usize,
 }
 
 impl<T> Atlas<T>
@@ -50,6 +54,7 @@ where
             texture: Rc::clone(texture),
             side,
             allocator,
+            used_area: 0,
         })
     }
 
@@ -89,6 +94,8 @@ where
             .allocator
             .allocate(AtlasSize::new(reserve_width, reserve_height))
         {
+            self.used_area += (reserve_width * reserve_height) as usize;
+
             let left = allocation.rectangle.min.x;
             let top = allocation.rectangle.min.y;
             let rect = Rect::new(
@@ -111,10 +118,47 @@ where
         }
     }
 
+    /// Move the sprites into `texture`, which must be at least as
+    /// large as the current texture, making room for more sprites.
+    /// The pixel coordinates of the sprites that have already been
+    /// allocated remain valid, but they continue to refer to the
+    /// old texture until they are rebound with `Sprite::with_texture`.
+    pub fn grow(&mut self, texture: &Rc<T>) -> Fallible<()> {
+        ensure!(
+            texture.width() == texture.height(),
+            "texture must be square!"
+        );
+        let side = texture.width();
+        ensure!(
+            side >= self.side,
+            "cannot shrink the atlas from {} to {}",
+            self.side,
+            side
+        );
+        let iside = side as isize;
+
+        let image = crate::Image::new(side, side);
+        let rect = Rect::new(Point::new(0, 0), Size::new(iside, iside));
+        texture.write(rect, &image);
+        self.texture.copy_to(texture);
+
+        self.allocator
+            .grow(AtlasSize::new(side.try_into()?, side.try_into()?));
+        self.texture = Rc::clone(texture);
+        self.side = side;
+        Ok(())
+    }
+
     pub fn size(&self) -> usize {
         self.side
     }
 
+    /// Returns the number of pixels that are reserved by the
+    /// sprites allocated from the atlas, including their padding
+    pub fn used_area(&self) -> usize {
+        self.used_area
+    }
+
     /// Zero out the texture, and forget all allocated regions
     pub fn clear(&mut self) {
         let iside = self.side as isize;
@@ -122,6 +166,7 @@ where
         let rect = Rect::new(Point::new(0, 0), Size::new(iside, iside));
         self.texture.write(rect, &image);
         self.allocator.clear();
+        self.used_area = 0;
     }
 }
 
@@ -163,6 +208,15 @@ where
     pub fn texture_coords(&self) -> TextureRect {
         self.texture.to_texture_coords(self.coords)
     }
+
+    /// Returns a copy of the sprite that refers to the same pixels
+    /// in `texture`, which the atlas has grown into
+    pub fn with_texture(&self, texture: &Rc<T>) -> Self {
+        Self {
+            texture: Rc::clone(texture),
+            coords: self.coords,
+        }
+    }
 }
 
 /// Represents a vertical slice through a sprite.
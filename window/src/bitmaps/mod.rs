@@ -25,6 +25,12 @@ pub trait Texture2d {
     /// The dimensions of the rectangle must match the source image
     fn read(&self, rect: Rect, im: &mut dyn BitmapImage);
 
+    /// Copy the whole of this texture to the top left corner of the
+    /// target texture, which must be at least as large as this one
+    fn copy_to(&self, target: &Self)
+    where
+        Self: Sized;
+
     /// Returns the width of the texture in pixels
     fn width(&self) -> usize;
 
@@ -98,6 +104,30 @@ impl Texture2d for SrgbTexture2d {
         unimplemented!();
     }
 
+    fn copy_to(&self, target: &Self) {
+        use glium::Surface;
+        let (width, height) = (SrgbTexture2d::width(self), SrgbTexture2d::height(self));
+        // Copy on the GPU, rather than reading the texture back and
+        // writing it out again, which would also round trip the pixels
+        // through the srgb conversion in `write`
+        self.as_surface().blit_color(
+            &glium::Rect {
+                left: 0,
+                bottom: 0,
+                width,
+                height,
+            },
+            &target.as_surface(),
+            &glium::BlitTarget {
+                left: 0,
+                bottom: 0,
+                width: width as i32,
+                height: height as i32,
+            },
+            glium::uniforms::MagnifySamplerFilter::Nearest,
+        );
+    }
+
     fn width(&self) -> usize {
         SrgbTexture2d::width(self) as usize
     }
@@ -601,8 +631,20 @@ impl Texture2d for ImageTexture {
         image.draw_image(rect.origin, None, im, Operator::Source);
     }
 
-    fn read(&self, _rect: Rect, _im: &mut dyn BitmapImage) {
-        unimplemented!();
+    fn read(&self, rect: Rect, im: &mut dyn BitmapImage) {
+        let image = self.image.borrow();
+        im.draw_image(Point::new(0, 0), Some(rect), &*image, Operator::Source);
+    }
+
+    fn copy_to(&self, target: &Self) {
+        let image = self.image.borrow();
+        target.write(
+            Rect::new(
+                Point::new(0, 0),
+                Size::new(self.width() as isize, self.height() as isize),
+            ),
+            &*image,
+        );
     }
 
     /// Returns the width of the texture in pixels
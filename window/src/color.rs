@@ -1,5 +1,5 @@
 use crate::Operator;
-use palette::{Blend, LinSrgb, LinSrgba, Srgb, Srgba};
+use palette::{Blend, Hsl, LinSrgb, LinSrgba, Mix, Srgb, Srgba};
 
 lazy_static::lazy_static! {
     static ref SRGB_TO_F32_TABLE: [f32;256] = generate_srgb8_to_linear_f32_table();
@@ -150,7 +150,9 @@ fn linear_f32_to_srgb8_vec(s: LinSrgba) -> Color {
         let mant_mask_4 = _mm_set1_epi32(0xff);
         let top_scale_4 = _mm_set1_epi32(0x02000000);
 
-        let f = _mm_set_ps(s.red, s.green, s.blue, s.alpha);
+        // Note that _mm_set_ps and _mm_set_epi32 take their arguments
+        // from the highest lane to the lowest, so red ends up in lane 0
+        let f = _mm_set_ps(s.alpha, s.blue, s.green, s.red);
 
         let clamped = _mm_min_ps(
             _mm_max_ps(f, _mm_castsi128_ps(clamp_min_4)),
@@ -160,10 +162,10 @@ fn linear_f32_to_srgb8_vec(s: LinSrgba) -> Color {
         let tabidx = _mm_srli_epi32(_mm_castps_si128(clamped), 20);
 
         let tabval = _mm_set_epi32(
-            *F32_TO_U8_TABLE.get_unchecked((i32_get(&tabidx, 0) - (127 - 13) * 8) as usize) as i32,
-            *F32_TO_U8_TABLE.get_unchecked((i32_get(&tabidx, 1) - (127 - 13) * 8) as usize) as i32,
-            *F32_TO_U8_TABLE.get_unchecked((i32_get(&tabidx, 2) - (127 - 13) * 8) as usize) as i32,
             *F32_TO_U8_TABLE.get_unchecked((i32_get(&tabidx, 3) - (127 - 13) * 8) as usize) as i32,
+            *F32_TO_U8_TABLE.get_unchecked((i32_get(&tabidx, 2) - (127 - 13) * 8) as usize) as i32,
+            *F32_TO_U8_TABLE.get_unchecked((i32_get(&tabidx, 1) - (127 - 13) * 8) as usize) as i32,
+            *F32_TO_U8_TABLE.get_unchecked((i32_get(&tabidx, 0) - (127 - 13) * 8) as usize) as i32,
         );
 
         let tabmult1 = _mm_srli_epi32(_mm_castps_si128(clamped), 12);
@@ -306,6 +308,90 @@ impl Color {
         c.into_format().into_components()
     }
 
+    /// Linearly interpolates between this color and `other` in linear
+    /// light; an `amount` of 0.0 returns this color and 1.0 returns `other`.
+    pub fn lerp(self, other: Color, amount: f32) -> Color {
+        let a: LinSrgba = self.into();
+        let b: LinSrgba = other.into();
+        a.mix(&b, amount.clamp(0., 1.)).into()
+    }
+
+    /// Returns the relative luminance of the color, as defined by WCAG 2.0.
+    /// It ranges from 0 for black to 1 for white.
+    pub fn relative_luminance(self) -> f32 {
+        let c: LinSrgb = self.into();
+        0.2126 * c.red + 0.7152 * c.green + 0.0722 * c.blue
+    }
+
+    /// Returns the WCAG 2.0 contrast ratio between two colors.
+    /// It ranges from 1 for colors of the same luminance to 21
+    /// for black against white.
+    pub fn contrast_ratio(self, other: Color) -> f32 {
+        let a = self.relative_luminance();
+        let b = other.relative_luminance();
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Returns this color, adjusted if necessary so that its contrast
+    /// ratio against `background` is at least `min_ratio`.
+    /// Only the lightness is changed, so that the hue is preserved,
+    /// and the adjustment is as small as possible.  If the ratio
+    /// can't be reached, the lightness with the most contrast is used.
+    pub fn ensure_contrast_ratio(self, background: Color, min_ratio: f32) -> Color {
+        if self.contrast_ratio(background) >= min_ratio {
+            return self;
+        }
+
+        let alpha = self.as_rgba().3;
+        let hsl: Hsl = Srgb::from(self).into();
+        let with_lightness = |lightness: f32| -> Color {
+            let srgb: Srgb = Hsl::new(hsl.hue, hsl.saturation, lightness).into();
+            Color::from(srgb).with_alpha(alpha)
+        };
+
+        // Search between the current lightness and the extreme for
+        // the closest lightness that meets the ratio.  Luminance grows
+        // with the lightness, so the contrast only crosses the ratio
+        // once on the way to the extreme.
+        let search = |extreme: f32| -> Option<(f32, Color)> {
+            if with_lightness(extreme).contrast_ratio(background) < min_ratio {
+                return None;
+            }
+            let mut failing = hsl.lightness;
+            let mut passing = extreme;
+            for _ in 0..16 {
+                let mid = (failing + passing) / 2.;
+                if with_lightness(mid).contrast_ratio(background) >= min_ratio {
+                    passing = mid;
+                } else {
+                    failing = mid;
+                }
+            }
+            Some(((passing - hsl.lightness).abs(), with_lightness(passing)))
+        };
+
+        match (search(1.), search(0.)) {
+            (Some((lighter_delta, lighter)), Some((darker_delta, darker))) => {
+                if lighter_delta <= darker_delta {
+                    lighter
+                } else {
+                    darker
+                }
+            }
+            (Some((_, lighter)), None) => lighter,
+            (None, Some((_, darker))) => darker,
+            (None, None) => {
+                let lighter = with_lightness(1.);
+                let darker = with_lightness(0.);
+                if lighter.contrast_ratio(background) >= darker.contrast_ratio(background) {
+                    lighter
+                } else {
+                    darker
+                }
+            }
+        }
+    }
+
     /// Compute the composite of two colors according to the supplied operator.
     /// self is the src operand, dest is the dest operand.
     #[inline]
@@ -324,18 +410,187 @@ impl Color {
                 result
             }
             Operator::MultiplyThenOver(ref tint) => {
-                // First multiply by the tint color.  This colorizes the glyph.
-                let src: LinSrgba = self.into();
+                // The source is a coverage mask that colorizes the glyph
+                // with the tint color.  Subpixel antialiased glyphs have
+                // separate coverage for the red, green and blue channels,
+                // so each channel is blended by its own coverage.
+                // The coverage is limited by the alpha so that a white
+                // mask with partial alpha covers only by that alpha, and
+                // is scaled by the alpha of the tint so that a translucent
+                // tint lets the destination show through.
+                let (red, green, blue, alpha) = self.as_rgba();
                 let tint: LinSrgba = (*tint).into();
-                let mut tinted = src.multiply(tint);
-                // We take the alpha from the source.  This is important because
-                // we're using Multiply to tint the glyph and if we don't reset the
-                // alpha we tend to end up with a background square of the tint color.
-                tinted.alpha = src.alpha;
-                // Then blend the tinted glyph over the destination background
+                let coverage = |c: u8| f32::from(c.min(alpha)) / 255. * tint.alpha;
                 let dest: LinSrgba = dest.into();
-                tinted.over(dest).into()
+                let mix =
+                    |tint: f32, dest: f32, coverage: f32| tint * coverage + dest * (1. - coverage);
+                let src_alpha = f32::from(alpha) / 255. * tint.alpha;
+                LinSrgba::new(
+                    mix(tint.red, dest.red, coverage(red)),
+                    mix(tint.green, dest.green, coverage(green)),
+                    mix(tint.blue, dest.blue, coverage(blue)),
+                    src_alpha + dest.alpha * (1. - src_alpha),
+                )
+                .into()
             }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn multiply_then_over_blends_in_linear_light() {
+        let white = Color::rgba(0xff, 0xff, 0xff, 0xff);
+        let black = Color::rgba(0, 0, 0, 0xff);
+        let half = Color::rgba(0x80, 0x80, 0x80, 0x80);
+
+        // Half of the light of white is 0.5 in linear space, which
+        // is about 0.735 once encoded as sRGB, rather than the 0.5
+        // that blending the encoded values would produce
+        let (r, g, b, a) = half
+            .composite(black, Operator::MultiplyThenOver(white))
+            .as_rgba();
+        assert_eq!((r, g, b, a), (0xbc, 0xbc, 0xbc, 0xff));
+
+        // No coverage leaves the background alone
+        let none = Color::rgba(0, 0, 0, 0);
+        assert_eq!(
+            none.composite(black, Operator::MultiplyThenOver(white))
+                .as_rgba(),
+            black.as_rgba()
+        );
+    }
+
+    #[test]
+    fn multiply_then_over_applies_the_tint_alpha() {
+        let black = Color::rgba(0, 0, 0, 0xff);
+        let full = Color::rgba(0xff, 0xff, 0xff, 0xff);
+        let half = Color::rgba(0x80, 0x80, 0x80, 0x80);
+
+        // A fully transparent tint leaves the background alone
+        let clear = Color::rgba(0xff, 0xff, 0xff, 0);
+        assert_eq!(
+            full.composite(black, Operator::MultiplyThenOver(clear))
+                .as_rgba(),
+            black.as_rgba()
+        );
+
+        // Full coverage with a half transparent tint is the same as
+        // half coverage with an opaque one
+        let translucent = Color::rgba(0xff, 0xff, 0xff, 0x80);
+        let opaque = Color::rgba(0xff, 0xff, 0xff, 0xff);
+        assert_eq!(
+            full.composite(black, Operator::MultiplyThenOver(translucent))
+                .as_rgba(),
+            half.composite(black, Operator::MultiplyThenOver(opaque))
+                .as_rgba()
+        );
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn vectorized_srgb_encoding_matches_table() {
+        for &(red, green, blue, alpha) in &[
+            (0.5, 0.25, 0.125, 1.0),
+            (1.0, 0.0, 0.75, 0.5),
+            (0.01, 0.9, 0.3, 0.0),
+        ] {
+            let color = linear_f32_to_srgb8_vec(LinSrgba::new(red, green, blue, alpha));
+            assert_eq!(
+                color.as_rgba(),
+                (
+                    linear_f32_to_srgb8_using_table(red),
+                    linear_f32_to_srgb8_using_table(green),
+                    linear_f32_to_srgb8_using_table(blue),
+                    linear_f32_to_srgb8_using_table(alpha),
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn lerp_mixes_in_linear_light() {
+        let white = Color::rgb(0xff, 0xff, 0xff);
+        let red = Color::rgb(0xff, 0, 0);
+        let black = Color::rgb(0, 0, 0);
+
+        assert_eq!(white.lerp(black, 0.).as_rgba(), white.as_rgba());
+        assert_eq!(white.lerp(black, 1.).as_rgba(), black.as_rgba());
+        // The midpoint has half of the light of each channel
+        assert_eq!(white.lerp(black, 0.5).as_rgba(), (0xbc, 0xbc, 0xbc, 0xff));
+        assert_eq!(red.lerp(black, 0.5).as_rgba(), (0xbc, 0, 0, 0xff));
+    }
+
+    #[test]
+    fn ensure_contrast_ratio_adjusts_lightness() {
+        let hue = |c: Color| -> f32 {
+            let hsl: Hsl = Srgb::from(c).into();
+            hsl.hue.to_positive_degrees()
+        };
+
+        for &(fg, bg, ratio) in &[
+            // dark blue on black
+            (Color::rgb(0x00, 0x00, 0x80), Color::rgb(0, 0, 0), 4.5),
+            // dark red on a dark gray
+            (
+                Color::rgb(0x80, 0x10, 0x10),
+                Color::rgb(0x30, 0x30, 0x30),
+                3.,
+            ),
+            // yellow on white
+            (
+                Color::rgb(0xf0, 0xe0, 0x40),
+                Color::rgb(0xff, 0xff, 0xff),
+                4.5,
+            ),
+            // mid green on mid gray
+            (
+                Color::rgb(0x40, 0xa0, 0x40),
+                Color::rgb(0x80, 0x80, 0x80),
+                3.,
+            ),
+            // purple on a slightly different purple
+            (
+                Color::rgb(0x80, 0x40, 0xc0),
+                Color::rgb(0x70, 0x30, 0xb0),
+                7.,
+            ),
+        ] {
+            assert!(fg.contrast_ratio(bg) < ratio);
+            let adjusted = fg.ensure_contrast_ratio(bg, ratio);
+            assert!(
+                adjusted.contrast_ratio(bg) >= ratio,
+                "{:?} against {:?} has ratio {} < {}",
+                adjusted.as_rgba(),
+                bg.as_rgba(),
+                adjusted.contrast_ratio(bg),
+                ratio
+            );
+            let delta = (hue(adjusted) - hue(fg)).abs();
+            assert!(
+                delta.min(360. - delta) < 5.,
+                "hue of {:?} drifted from {:?}",
+                adjusted.as_rgba(),
+                fg.as_rgba()
+            );
+        }
+
+        // Colors that already pass are left alone
+        let fg = Color::rgb(0xc0, 0xc0, 0xc0);
+        let bg = Color::rgb(0, 0, 0);
+        assert_eq!(fg.ensure_contrast_ratio(bg, 4.5).as_rgba(), fg.as_rgba());
+
+        // Black against white is the most contrast there is
+        let white = Color::rgb(0xff, 0xff, 0xff);
+        assert!((bg.contrast_ratio(white) - 21.).abs() < 0.01);
+        assert_eq!(
+            Color::rgb(0x80, 0x80, 0x80)
+                .ensure_contrast_ratio(white, 25.)
+                .as_rgba(),
+            bg.as_rgba()
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::Connection;
+use crate::{Connection, ScreenRect};
 use anyhow::Result as Fallible;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -39,4 +39,14 @@ pub trait ConnectionOps {
 
     // TODO: return a handle that can be used to cancel the timer
     fn schedule_timer<F: FnMut() + 'static>(&self, interval: std::time::Duration, callback: F);
+
+    /// Returns the usable area of each of the screens (monitors) that
+    /// are attached to the system, in screen coordinates.
+    /// The list is empty if the system doesn't provide that information.
+    fn screens(&self) -> Vec<ScreenRect> {
+        vec![]
+    }
+
+    /// Play the system alert sound, if the system has one
+    fn beep(&self) {}
 }
@@ -1,5 +1,6 @@
 use promise::Future;
 use std::any::Any;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 pub mod bitmaps;
 pub mod color;
@@ -63,6 +64,17 @@ pub struct Dimensions {
     pub dpi: usize,
 }
 
+/// The dpi at which no scaling is applied
+pub const DEFAULT_DPI: usize = 96;
+
+impl Dimensions {
+    /// Returns the factor by which fonts and other dpi-relative
+    /// sizes should be scaled to appear at their nominal size
+    pub fn scale_factor(&self) -> f64 {
+        self.dpi as f64 / DEFAULT_DPI as f64
+    }
+}
+
 pub struct PixelUnit;
 pub type Point = euclid::Point2D<isize, PixelUnit>;
 pub type Rect = euclid::Rect<isize, PixelUnit>;
@@ -70,6 +82,7 @@ pub type Size = euclid::Size2D<isize, PixelUnit>;
 
 pub struct ScreenPixelUnit;
 pub type ScreenPoint = euclid::Point2D<isize, ScreenPixelUnit>;
+pub type ScreenRect = euclid::Rect<isize, ScreenPixelUnit>;
 
 pub trait PaintContext {
     fn get_dimensions(&self) -> Dimensions;
@@ -124,6 +137,11 @@ pub trait WindowCallbacks: Any {
     /// Called when the window is resized, or when the dpi has changed
     fn resize(&mut self, dimensions: Dimensions) {}
 
+    /// Called when the window is moved.  `position` is the location
+    /// of the top left corner of the client area, in screen coordinates.
+    /// Not all systems report this.
+    fn moved(&mut self, position: ScreenPoint) {}
+
     /// Called when window gains/loses focus
     fn focus_change(&mut self, focused: bool) {}
 
@@ -172,6 +190,18 @@ pub trait WindowCallbacks: Any {
         context.set_cursor(Some(MouseCursor::Arrow));
     }
 
+    /// Called while the user is composing text with an input method.
+    /// `preedit` is the text composed so far; it is empty when the
+    /// composition has been committed or cancelled.  Committed text
+    /// is delivered separately as a KeyCode::Composed key event.
+    /// Not all systems report this.
+    fn ime_composition(&mut self, preedit: &str) {}
+
+    /// Called when files are dragged from another application
+    /// and dropped onto the window.
+    /// Not all systems report this.
+    fn dropped_files(&mut self, paths: Vec<PathBuf>) {}
+
     /// Called when the window is created and allows the embedding
     /// app to reference the window and operate upon it.
     fn created(&mut self, window: &Window) {}
@@ -242,8 +272,10 @@ pub trait WindowOps {
     /// Initiate textual transfer from the clipboard
     fn get_clipboard(&self, clipboard: Clipboard) -> Future<String>;
 
-    /// Set some text in the clipboard
-    fn set_clipboard(&self, text: String) -> Future<()>;
+    /// Set some text in the clipboard.
+    /// Systems that don't have a primary selection ignore requests
+    /// to set it.
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()>;
 
     /// Set the icon for the window.
     /// Depending on the system this may be shown in its titlebar
@@ -310,3 +342,21 @@ pub fn prefer_egl(value: bool) {
 pub fn is_egl_preferred() -> bool {
     PREFER_EGL.load(Ordering::Acquire)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dpi_scale_factor() {
+        let dims = |dpi| Dimensions {
+            pixel_width: 800,
+            pixel_height: 600,
+            dpi,
+        };
+        assert_eq!(dims(96).scale_factor(), 1.0);
+        assert_eq!(dims(120).scale_factor(), 1.25);
+        assert_eq!(dims(144).scale_factor(), 1.5);
+        assert_eq!(dims(192).scale_factor(), 2.0);
+    }
+}
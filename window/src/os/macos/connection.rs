@@ -90,6 +90,15 @@ impl ConnectionOps for Connection {
         Ok(())
     }
 
+    fn beep(&self) {
+        extern "C" {
+            fn NSBeep();
+        }
+        unsafe {
+            NSBeep();
+        }
+    }
+
     fn hide_application(&self) {
         unsafe {
             let () = msg_send![self.ns_app, hide: self.ns_app];
@@ -595,8 +595,11 @@ impl WindowOps for Window {
         )
     }
 
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
         use clipboard::ClipboardProvider;
+        if clipboard == Clipboard::PrimarySelection {
+            return Future::ok(());
+        }
         Future::result(
             clipboard::ClipboardContext::new()
                 .and_then(|mut ctx| ctx.set_contents(text))
@@ -673,6 +676,8 @@ impl WindowOpsMut for WindowInner {
                     MouseCursor::SizeLeftRight => msg_send![ns_cursor_cls, resizeLeftRightCursor],
                 };
                 let () = msg_send![instance, set];
+            } else {
+                let () = msg_send![ns_cursor_cls, setHiddenUntilMouseMoves: YES];
             }
         }
     }
@@ -1385,6 +1390,21 @@ impl WindowView {
         }
     }
 
+    extern "C" fn did_move(this: &mut Object, _sel: Sel, _notification: id) {
+        let point = unsafe {
+            let window: id = msg_send![this, window];
+            let frame = NSWindow::frame(window);
+            let content_frame = NSWindow::contentRectForFrameRect_(window, frame);
+            cartesian_to_screen_point(NSPoint::new(
+                content_frame.origin.x,
+                content_frame.origin.y + content_frame.size.height,
+            ))
+        };
+        if let Some(this) = Self::get_this(this) {
+            this.inner.borrow_mut().callbacks.moved(point);
+        }
+    }
+
     extern "C" fn draw_rect(view: &mut Object, sel: Sel, dirty_rect: NSRect) {
         let frame = unsafe { NSView::frame(view as *mut _) };
         let backing_frame = unsafe { NSView::convertRectToBacking(view as *mut _, frame) };
@@ -1550,6 +1570,10 @@ impl WindowView {
                 sel!(windowDidResize:),
                 Self::did_resize as extern "C" fn(&mut Object, Sel, id),
             );
+            cls.add_method(
+                sel!(windowDidMove:),
+                Self::did_move as extern "C" fn(&mut Object, Sel, id),
+            );
             cls.add_method(
                 sel!(windowDidChangeScreen:),
                 Self::did_change_screen as extern "C" fn(&mut Object, Sel, id),
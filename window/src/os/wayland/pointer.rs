@@ -255,6 +255,16 @@ impl PointerDispatcher {
         let serial = serial.unwrap_or(inner.serial);
         self.auto_pointer.set_cursor(name, Some(serial)).ok();
     }
+
+    /// Hides the pointer while it is over our surfaces; the next call
+    /// to set_cursor makes it visible again
+    pub fn hide_cursor(&self, serial: Option<u32>) {
+        let inner = self.inner.lock().unwrap();
+        let serial = serial.unwrap_or(inner.serial);
+        // Setting a null surface is how wayland hides the pointer
+        let pointer: &wl_pointer::WlPointer = &self.auto_pointer;
+        pointer.set_cursor(serial, None, 0, 0);
+    }
 }
 
 #[derive(Clone, Debug, Copy, PartialEq, Eq)]
@@ -808,7 +808,11 @@ impl WindowOps for WaylandWindow {
         future
     }
 
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
+        if clipboard == Clipboard::PrimarySelection {
+            // The primary selection protocol isn't supported
+            return Future::ok(());
+        }
         WaylandConnection::with_window_inner(self.0, move |inner| {
             let text = text.clone();
             let conn = Connection::get().unwrap().wayland();
@@ -932,7 +936,11 @@ impl WindowOpsMut for WaylandWindowInner {
             Some(MouseCursor::SizeUpDown) => "ns-resize",
             Some(MouseCursor::SizeLeftRight) => "ew-resize",
             Some(MouseCursor::Text) => "text",
-            None => return,
+            None => {
+                let conn = Connection::get().unwrap().wayland();
+                conn.pointer.hide_cursor(None);
+                return;
+            }
         };
         let conn = Connection::get().unwrap().wayland();
         conn.pointer.set_cursor(cursor, None);
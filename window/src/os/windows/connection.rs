@@ -2,6 +2,7 @@
 use super::{HWindow, WindowInner};
 use crate::connection::ConnectionOps;
 use crate::spawn::*;
+use crate::{ScreenPoint, ScreenRect};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::convert::TryInto;
@@ -17,11 +18,39 @@ use winapi::um::winuser::*;
 pub struct Connection {
     event_handle: HANDLE,
     pub(crate) windows: RefCell<HashMap<HWindow, Rc<RefCell<WindowInner>>>>,
-    timers: RefCell<HashMap<UINT_PTR, UINT_PTR>>,
+    timers: RefCell<TimerRegistry>,
     #[cfg(feature = "opengl")]
     pub(crate) gl_connection: RefCell<Option<Rc<crate::egl::GlConnection>>>,
 }
 
+type TimerCallback = Rc<RefCell<dyn FnMut()>>;
+
+/// Tracks the callbacks for the timers created by schedule_timer,
+/// keyed by the timer id that SetTimer assigned to them
+#[derive(Default)]
+struct TimerRegistry {
+    callbacks: HashMap<UINT_PTR, TimerCallback>,
+}
+
+impl TimerRegistry {
+    fn add(&mut self, timer_id: UINT_PTR, callback: TimerCallback) {
+        self.callbacks.insert(timer_id, callback);
+    }
+
+    /// Returns the callback for the timer, if it is still registered
+    fn get(&self, timer_id: UINT_PTR) -> Option<TimerCallback> {
+        self.callbacks.get(&timer_id).map(Rc::clone)
+    }
+
+    /// Removes all of the timers, returning their ids
+    fn take_all(&mut self) -> Vec<UINT_PTR> {
+        self.callbacks
+            .drain()
+            .map(|(timer_id, _)| timer_id)
+            .collect()
+    }
+}
+
 impl ConnectionOps for Connection {
     fn terminate_message_loop(&self) {
         unsafe {
@@ -41,6 +70,7 @@ impl ConnectionOps for Connection {
                     // trigger `drop` handlers during shutdown and that
                     // can have bad interactions
                     self.windows.borrow_mut().clear();
+                    self.kill_all_timers();
                     return Ok(());
                 }
 
@@ -61,34 +91,69 @@ impl ConnectionOps for Connection {
             .as_millis()
             .try_into()
             .expect("duration is too large to fit in 32 bits");
-        let callback = Box::into_raw(Box::new(callback));
-
-        extern "system" fn timer_proc<F: FnMut()>(
-            _hwnd: HWND,
-            _msg: UINT,
-            timer_id: UINT_PTR,
-            _lparam: DWORD,
-        ) {
+
+        extern "system" fn timer_proc(_hwnd: HWND, _msg: UINT, timer_id: UINT_PTR, _lparam: DWORD) {
             let conn = Connection::get().unwrap();
-            if let Some(cb) = conn.timers.borrow().get(&timer_id).map(|cb| *cb) {
-                unsafe {
-                    let callback: *mut F = cb as _;
-                    (*callback)();
-                }
-            };
+            // Don't hold the borrow while calling the callback,
+            // as it may schedule another timer
+            let callback = conn.timers.borrow().get(timer_id);
+            if let Some(callback) = callback {
+                (&mut *callback.borrow_mut())();
+            }
         }
 
-        let timer_id = unsafe {
-            SetTimer(
-                std::ptr::null_mut(),
-                callback as UINT_PTR,
-                millis,
-                Some(timer_proc::<F>),
-            )
-        };
+        // With no window, the id passed in is ignored and a new
+        // one is returned
+        let timer_id = unsafe { SetTimer(null_mut(), 0, millis, Some(timer_proc)) };
+        if timer_id == 0 {
+            log::error!("SetTimer failed: {}", std::io::Error::last_os_error());
+            return;
+        }
         self.timers
             .borrow_mut()
-            .insert(timer_id, callback as UINT_PTR);
+            .add(timer_id, Rc::new(RefCell::new(callback)));
+    }
+
+    fn screens(&self) -> Vec<ScreenRect> {
+        unsafe extern "system" fn monitor_proc(
+            monitor: HMONITOR,
+            _hdc: HDC,
+            _rect: LPRECT,
+            data: LPARAM,
+        ) -> BOOL {
+            let screens = &mut *(data as *mut Vec<ScreenRect>);
+            let mut info: MONITORINFO = std::mem::zeroed();
+            info.cbSize = std::mem::size_of::<MONITORINFO>() as _;
+            if GetMonitorInfoW(monitor, &mut info) != 0 {
+                // Use the work area so that we don't count the task bar
+                let work = info.rcWork;
+                screens.push(ScreenRect::new(
+                    ScreenPoint::new(work.left as isize, work.top as isize),
+                    euclid::size2(
+                        (work.right - work.left) as isize,
+                        (work.bottom - work.top) as isize,
+                    ),
+                ));
+            }
+            TRUE
+        }
+
+        let mut screens: Vec<ScreenRect> = vec![];
+        unsafe {
+            EnumDisplayMonitors(
+                null_mut(),
+                null_mut(),
+                Some(monitor_proc),
+                &mut screens as *mut _ as LPARAM,
+            );
+        }
+        screens
+    }
+
+    fn beep(&self) {
+        unsafe {
+            MessageBeep(MB_OK);
+        }
     }
 }
 
@@ -98,12 +163,22 @@ impl Connection {
         Ok(Self {
             event_handle,
             windows: RefCell::new(HashMap::new()),
-            timers: RefCell::new(HashMap::new()),
+            timers: RefCell::new(TimerRegistry::default()),
             #[cfg(feature = "opengl")]
             gl_connection: RefCell::new(None),
         })
     }
 
+    /// Stops all of the timers, so that their callbacks cannot run
+    /// after the state they reference has been torn down
+    fn kill_all_timers(&self) {
+        for timer_id in self.timers.borrow_mut().take_all() {
+            unsafe {
+                KillTimer(null_mut(), timer_id);
+            }
+        }
+    }
+
     fn wait_message(&self) {
         unsafe {
             MsgWaitForMultipleObjects(1, &self.event_handle, 0, INFINITE, QS_ALLEVENTS);
@@ -140,3 +215,34 @@ impl Connection {
         future
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn timer_registry() {
+        let fired = Rc::new(RefCell::new(vec![]));
+        let mut timers = TimerRegistry::default();
+        for timer_id in 1..=2 {
+            let fired = Rc::clone(&fired);
+            timers.add(
+                timer_id,
+                Rc::new(RefCell::new(move || fired.borrow_mut().push(timer_id))),
+            );
+        }
+
+        (&mut *timers.get(2).unwrap().borrow_mut())();
+        (&mut *timers.get(1).unwrap().borrow_mut())();
+        assert_eq!(*fired.borrow(), vec![2, 1]);
+
+        assert!(timers.get(3).is_none());
+
+        let mut ids = timers.take_all();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2]);
+        assert!(timers.get(1).is_none());
+        assert!(timers.get(2).is_none());
+        assert!(timers.take_all().is_empty());
+    }
+}
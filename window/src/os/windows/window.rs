@@ -19,14 +19,16 @@ use std::convert::TryInto;
 use std::ffi::OsString;
 use std::io::{self, Error as IoError};
 use std::os::windows::ffi::OsStringExt;
+use std::path::PathBuf;
 use std::ptr::{null, null_mut};
 use std::rc::Rc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use winapi::shared::minwindef::*;
 use winapi::shared::ntdef::*;
 use winapi::shared::windef::*;
 use winapi::um::imm::*;
 use winapi::um::libloaderapi::GetModuleHandleW;
+use winapi::um::shellapi::{DragAcceptFiles, DragFinish, DragQueryFileW, HDROP};
 use winapi::um::wingdi::*;
 use winapi::um::winuser::*;
 use winreg::{enums::HKEY_CURRENT_USER, RegKey};
@@ -37,6 +39,32 @@ pub fn use_dead_keys(enable: bool) {
     USE_DEAD_KEYS.store(enable, Ordering::Relaxed);
 }
 
+/// Selects the theme for the title bar and window frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FrameTheme {
+    /// Follow the apps theme selected in the system settings
+    System,
+    Dark,
+    Light,
+}
+
+static FRAME_THEME: AtomicU8 = AtomicU8::new(FrameTheme::Dark as u8);
+
+/// Sets the frame theme used by subsequently created windows
+pub fn set_frame_theme(theme: FrameTheme) {
+    FRAME_THEME.store(theme as u8, Ordering::Relaxed);
+}
+
+fn frame_theme() -> FrameTheme {
+    match FRAME_THEME.load(Ordering::Relaxed) {
+        x if x == FrameTheme::System as u8 => FrameTheme::System,
+        x if x == FrameTheme::Light as u8 => FrameTheme::Light,
+        _ => FrameTheme::Dark,
+    }
+}
+
+const GCS_COMPSTR: DWORD = 0x8;
 const GCS_RESULTSTR: DWORD = 0x800;
 extern "system" {
     pub fn ImmGetCompositionStringW(himc: HIMC, index: DWORD, buf: LPVOID, buflen: DWORD) -> LONG;
@@ -63,6 +91,79 @@ pub(crate) struct WindowInner {
     dead_pending: Option<(Modifiers, u32)>,
 
     keyboard_info: KeyboardLayoutInfo,
+    /// The icon most recently set via set_icon, if any
+    icon: Option<Icon>,
+    /// Where the IME window should be placed, relative to the
+    /// top left of the client area
+    text_cursor: Option<Rect>,
+}
+
+/// Owns an icon handle created by CreateIconIndirect
+struct Icon(HICON);
+
+impl Drop for Icon {
+    fn drop(&mut self) {
+        unsafe {
+            DestroyIcon(self.0);
+        }
+    }
+}
+
+impl Icon {
+    /// Create an icon from a bgra32 image
+    fn from_image(image: &dyn BitmapImage) -> anyhow::Result<Self> {
+        let (width, height) = image.image_dimensions();
+
+        // The alpha channel of the color bitmap takes precedence,
+        // so the mask just needs to exist.  Monochrome bitmap rows
+        // are padded to a multiple of 16 bits.
+        let mask_stride = ((width + 15) / 16) * 2;
+        let mask_bits = vec![0u8; mask_stride * height];
+
+        unsafe {
+            let color = CreateBitmap(
+                width as i32,
+                height as i32,
+                1,
+                32,
+                image.pixel_data() as *const _,
+            );
+            let mask = CreateBitmap(
+                width as i32,
+                height as i32,
+                1,
+                1,
+                mask_bits.as_ptr() as *const _,
+            );
+
+            let mut info = ICONINFO {
+                fIcon: TRUE,
+                xHotspot: 0,
+                yHotspot: 0,
+                hbmMask: mask,
+                hbmColor: color,
+            };
+            let icon = if color.is_null() || mask.is_null() {
+                null_mut()
+            } else {
+                CreateIconIndirect(&mut info)
+            };
+            let err = IoError::last_os_error();
+
+            // The icon has its own copies of the bitmaps
+            if !color.is_null() {
+                DeleteObject(color as _);
+            }
+            if !mask.is_null() {
+                DeleteObject(mask as _);
+            }
+
+            if icon.is_null() {
+                bail!("CreateIconIndirect: {}", err);
+            }
+            Ok(Self(icon))
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -76,6 +177,17 @@ fn rect_height(r: &RECT) -> i32 {
     r.bottom - r.top
 }
 
+/// Computes the dimensions of the client area described by `rect`,
+/// which is measured in physical pixels, for a window whose monitor
+/// has the specified `dpi`
+fn client_rect_to_dimensions(rect: &RECT, dpi: u32) -> Dimensions {
+    Dimensions {
+        pixel_width: rect_width(rect).max(0) as usize,
+        pixel_height: rect_height(rect).max(0) as usize,
+        dpi: dpi as usize,
+    }
+}
+
 fn adjust_client_to_window_dimensions(width: usize, height: usize) -> (i32, i32) {
     let mut rect = RECT {
         left: 0,
@@ -88,6 +200,19 @@ fn adjust_client_to_window_dimensions(width: usize, height: usize) -> (i32, i32)
     (rect_width(&rect), rect_height(&rect))
 }
 
+/// Given the desired screen position of the client area, returns
+/// the position for the top left of the window frame, preserving the
+/// current offset between `client_origin` and `window_rect`.
+fn window_origin_for_client_origin(
+    coords: ScreenPoint,
+    client_origin: ScreenPoint,
+    window_rect: &RECT,
+) -> (i32, i32) {
+    let delta_x = client_origin.x as i32 - window_rect.left;
+    let delta_y = client_origin.y as i32 - window_rect.top;
+    (coords.x as i32 - delta_x, coords.y as i32 - delta_y)
+}
+
 fn rc_to_pointer(arc: &Rc<RefCell<WindowInner>>) -> *const RefCell<WindowInner> {
     let cloned = Rc::clone(arc);
     Rc::into_raw(cloned)
@@ -192,16 +317,9 @@ impl WindowInner {
             right: 0,
             top: 0,
         };
-        unsafe {
+        let current_dims = unsafe {
             GetClientRect(self.hwnd.0, &mut rect);
-        }
-        let pixel_width = rect_width(&rect) as usize;
-        let pixel_height = rect_height(&rect) as usize;
-
-        let current_dims = Dimensions {
-            pixel_width,
-            pixel_height,
-            dpi: unsafe { GetDpiForWindow(self.hwnd.0) as usize },
+            client_rect_to_dimensions(&rect, GetDpiForWindow(self.hwnd.0))
         };
 
         let same = self
@@ -314,6 +432,8 @@ impl Window {
             last_size: None,
             in_size_move: false,
             dead_pending: None,
+            icon: None,
+            text_cursor: None,
         }));
 
         // Careful: `raw` owns a ref to inner, but there is no Drop impl
@@ -328,8 +448,11 @@ impl Window {
             }
         };
 
-        enable_dark_mode(hwnd.0);
+        apply_frame_theme(hwnd.0);
         enable_blur_behind(hwnd.0);
+        unsafe {
+            DragAcceptFiles(hwnd.0, TRUE);
+        }
 
         Connection::get()
             .expect("Connection::init was not called")
@@ -379,9 +502,7 @@ impl WindowOpsMut for WindowInner {
     }
 
     fn invalidate(&mut self) {
-        unsafe {
-            InvalidateRect(self.hwnd.0, null(), 1);
-        }
+        invalidate_client_area(self.hwnd.0);
     }
 
     fn set_inner_size(&mut self, width: usize, height: usize) {
@@ -417,16 +538,16 @@ impl WindowOpsMut for WindowInner {
             GetWindowRect(hwnd.0, &mut rect);
 
             let origin = client_to_screen(hwnd.0, Point::new(0, 0));
-            let delta_x = origin.x as i32 - rect.left;
-            let delta_y = origin.y as i32 - rect.top;
+            let (x, y) = window_origin_for_client_origin(coords, origin, &rect);
 
-            MoveWindow(
+            SetWindowPos(
                 hwnd.0,
-                coords.x as i32 - delta_x,
-                coords.y as i32 - delta_y,
-                rect_width(&rect),
-                rect_height(&rect),
-                1,
+                hwnd.0,
+                x,
+                y,
+                0,
+                0,
+                SWP_NOACTIVATE | SWP_NOSIZE | SWP_NOZORDER,
             );
         }
     }
@@ -438,9 +559,28 @@ impl WindowOpsMut for WindowInner {
         }
     }
 
+    fn set_icon(&mut self, image: &dyn BitmapImage) {
+        let icon = match Icon::from_image(image) {
+            Ok(icon) => icon,
+            Err(err) => {
+                log::error!("failed to set window icon: {:#}", err);
+                return;
+            }
+        };
+        unsafe {
+            // The system scales the image for the title bar and
+            // for the taskbar and alt-tab switcher
+            SendMessageW(self.hwnd.0, WM_SETICON, ICON_SMALL as _, icon.0 as _);
+            SendMessageW(self.hwnd.0, WM_SETICON, ICON_BIG as _, icon.0 as _);
+        }
+        // The previous icon, if any, is destroyed now that it has been replaced
+        self.icon.replace(icon);
+    }
+
     fn set_text_cursor_position(&mut self, cursor: Rect) {
         let imc = ImmContext::get(self.hwnd.0);
         imc.set_position(cursor.origin.x.max(0) as i32, cursor.origin.y.max(0) as i32);
+        self.text_cursor.replace(cursor);
     }
 }
 
@@ -484,6 +624,13 @@ impl WindowOps for Window {
         })
     }
 
+    fn set_icon(&self, image: Image) -> Future<()> {
+        Connection::with_window_inner(self.0, move |inner| {
+            inner.set_icon(&image);
+            Ok(())
+        })
+    }
+
     fn set_text_cursor_position(&self, cursor: Rect) -> Future<()> {
         Connection::with_window_inner(self.0, move |inner| {
             inner.set_text_cursor_position(cursor);
@@ -525,18 +672,53 @@ impl WindowOps for Window {
     }
 
     fn get_clipboard(&self, _clipboard: Clipboard) -> Future<String> {
-        Future::result(
-            clipboard_win::get_clipboard_string()
-                .map(|s| s.replace("\r\n", "\n"))
-                .context("Error getting clipboard"),
-        )
+        Future::result(get_clipboard_text())
+    }
+
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
+        if clipboard == Clipboard::PrimarySelection {
+            return Future::ok(());
+        }
+        Future::result(set_clipboard_text(&text))
+    }
+}
+
+/// How many times to try opening the clipboard while another
+/// process has it open
+const CLIPBOARD_ATTEMPTS: usize = 10;
+
+/// Calls `func`, retrying for a short while if it fails because
+/// another application currently has the clipboard open.
+fn retry_clipboard<T, F: FnMut() -> io::Result<T>>(mut func: F) -> io::Result<T> {
+    let mut attempt = 1;
+    loop {
+        match func() {
+            Err(err)
+                if attempt < CLIPBOARD_ATTEMPTS
+                    && err.raw_os_error()
+                        == Some(winapi::shared::winerror::ERROR_ACCESS_DENIED as i32) =>
+            {
+                attempt += 1;
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            result => return result,
+        }
     }
+}
 
-    fn set_clipboard(&self, text: String) -> Future<()> {
-        Future::result(
-            clipboard_win::set_clipboard_string(&text).context("Error setting clipboard"),
-        )
+fn get_clipboard_text() -> anyhow::Result<String> {
+    // An empty clipboard, or one holding something other than
+    // text, pastes as nothing rather than being an error
+    if unsafe { IsClipboardFormatAvailable(CF_UNICODETEXT) } == 0 {
+        return Ok(String::new());
     }
+    retry_clipboard(clipboard_win::get_clipboard_string)
+        .map(|s| s.replace("\r\n", "\n"))
+        .context("Error getting clipboard")
+}
+
+fn set_clipboard_text(text: &str) -> anyhow::Result<()> {
+    retry_clipboard(|| clipboard_win::set_clipboard_string(text)).context("Error setting clipboard")
 }
 
 /// Set up bidirectional pointers:
@@ -593,10 +775,29 @@ fn enable_blur_behind(hwnd: HWND) {
     }
 }
 
-fn enable_dark_mode(hwnd: HWND) {
-    // Prefer to run in dark mode. This could be made configurable without
-    // a huge amount of effort, but I think it's fine to just be always
-    // dark mode by default :-p
+/// Returns true if the system is configured to use the light theme
+/// for applications.  Versions of Windows that predate the setting
+/// always use the light theme.
+fn apps_use_light_theme() -> bool {
+    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+    hkcu.open_subkey("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize")
+        .and_then(|key| key.get_value::<DWORD, _>("AppsUseLightTheme"))
+        .map(|value| value != 0)
+        .unwrap_or(true)
+}
+
+/// Decides whether the frame should use the dark theme; `light_system`
+/// is the result of `apps_use_light_theme` and is only consulted for
+/// FrameTheme::System.
+fn use_dark_frame(theme: FrameTheme, light_system: impl FnOnce() -> bool) -> bool {
+    match theme {
+        FrameTheme::Dark => true,
+        FrameTheme::Light => false,
+        FrameTheme::System => !light_system(),
+    }
+}
+
+fn apply_frame_theme(hwnd: HWND) {
     // Note that the MS terminal app uses the logic found here for this
     // stuff:
     // https://github.com/microsoft/terminal/blob/9b92986b49bed8cc41fde4d6ef080921c41e6d9e/src/interactivity/win32/windowtheme.cpp#L62
@@ -619,15 +820,19 @@ fn enable_dark_mode(hwnd: HWND) {
         pub fn SetWindowCompositionAttribute(hwnd: HWND, attrib: *mut WINDOWCOMPOSITIONATTRIBDATA) -> BOOL,
     );
 
+    let dark = use_dark_frame(frame_theme(), apps_use_light_theme);
+
     const DWMWA_USE_IMMERSIVE_DARK_MODE: DWORD = 19;
     unsafe {
-        SetWindowTheme(
-            hwnd as _,
-            wide_string("DarkMode_Explorer").as_slice().as_ptr(),
-            std::ptr::null_mut(),
-        );
+        if dark {
+            SetWindowTheme(
+                hwnd as _,
+                wide_string("DarkMode_Explorer").as_slice().as_ptr(),
+                std::ptr::null_mut(),
+            );
+        }
 
-        let mut enabled: BOOL = 1;
+        let mut enabled: BOOL = dark.into();
         DwmSetWindowAttribute(
             hwnd as _,
             DWMWA_USE_IMMERSIVE_DARK_MODE,
@@ -714,13 +919,48 @@ unsafe fn wm_windowposchanged(
     hwnd: HWND,
     _msg: UINT,
     _wparam: WPARAM,
-    _lparam: LPARAM,
+    lparam: LPARAM,
 ) -> Option<LRESULT> {
-    // let pos = &*(lparam as *const WINDOWPOS);
+    let pos = &*(lparam as *const WINDOWPOS);
+    if pos.flags & SWP_NOMOVE == 0 {
+        if let Some(inner) = rc_from_hwnd(hwnd) {
+            let inner = inner.borrow();
+            inner
+                .callbacks
+                .borrow_mut()
+                .moved(client_to_screen(hwnd, Point::new(0, 0)));
+        }
+    }
     wm_size(hwnd, 0, 0, 0)?;
     Some(0)
 }
 
+/// The window has moved to a monitor with a different dpi.
+/// Adopt the size and position suggested by the system; the resulting
+/// WM_WINDOWPOSCHANGED reports the new dpi via wm_size, which allows
+/// the fonts to be re-rasterized at the new scale.
+unsafe fn wm_dpichanged(
+    hwnd: HWND,
+    _msg: UINT,
+    _wparam: WPARAM,
+    lparam: LPARAM,
+) -> Option<LRESULT> {
+    let suggested = &*(lparam as *const RECT);
+    SetWindowPos(
+        hwnd,
+        null_mut(),
+        suggested.left,
+        suggested.top,
+        rect_width(suggested),
+        rect_height(suggested),
+        SWP_NOACTIVATE | SWP_NOZORDER,
+    );
+    // In case the suggested rect matched the current one and no
+    // WM_WINDOWPOSCHANGED was generated
+    wm_size(hwnd, 0, 0, 0);
+    Some(0)
+}
+
 unsafe fn wm_size(hwnd: HWND, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) -> Option<LRESULT> {
     let mut should_paint = false;
 
@@ -736,6 +976,28 @@ unsafe fn wm_size(hwnd: HWND, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) -> O
     None
 }
 
+unsafe fn wm_dropfiles(hwnd: HWND, _msg: UINT, wparam: WPARAM, _lparam: LPARAM) -> Option<LRESULT> {
+    let hdrop = wparam as HDROP;
+    // Passing 0xffffffff as the index returns the number of files
+    let count = DragQueryFileW(hdrop, 0xffff_ffff, null_mut(), 0);
+    let mut paths = vec![];
+    for idx in 0..count {
+        // The returned length excludes the terminating NUL
+        let len = DragQueryFileW(hdrop, idx, null_mut(), 0) as usize;
+        let mut buf = vec![0u16; len + 1];
+        DragQueryFileW(hdrop, idx, buf.as_mut_ptr(), buf.len() as u32);
+        buf.truncate(len);
+        paths.push(PathBuf::from(OsString::from_wide(&buf)));
+    }
+    DragFinish(hdrop);
+
+    if let Some(inner) = rc_from_hwnd(hwnd) {
+        let inner = inner.borrow();
+        inner.callbacks.borrow_mut().dropped_files(paths);
+    }
+    Some(0)
+}
+
 unsafe fn wm_set_focus(
     hwnd: HWND,
     _msg: UINT,
@@ -762,10 +1024,25 @@ unsafe fn wm_kill_focus(
     None
 }
 
-unsafe fn wm_paint(hwnd: HWND, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) -> Option<LRESULT> {
-    if let Some(inner) = rc_from_hwnd(hwnd) {
-        let inner = inner.borrow();
+/// Marks the whole client area as needing to be repainted;
+/// the system coalesces these into a single WM_PAINT
+fn invalidate_client_area(hwnd: HWND) {
+    unsafe {
+        InvalidateRect(hwnd, null(), 1);
+    }
+}
 
+/// Brackets painting with BeginPaint and EndPaint.
+/// Ending the paint validates the update region accumulated by
+/// invalidate_client_area; if it were skipped, the system would
+/// keep sending WM_PAINT.
+struct PaintGuard {
+    hwnd: HWND,
+    ps: PAINTSTRUCT,
+}
+
+impl PaintGuard {
+    unsafe fn begin(hwnd: HWND) -> Self {
         let mut ps = PAINTSTRUCT {
             fErase: 0,
             fIncUpdate: 0,
@@ -779,7 +1056,29 @@ unsafe fn wm_paint(hwnd: HWND, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) ->
             },
             rgbReserved: [0; 32],
         };
-        let dc = BeginPaint(hwnd, &mut ps);
+        BeginPaint(hwnd, &mut ps);
+        Self { hwnd, ps }
+    }
+
+    fn hdc(&self) -> HDC {
+        self.ps.hdc
+    }
+}
+
+impl Drop for PaintGuard {
+    fn drop(&mut self) {
+        unsafe {
+            EndPaint(self.hwnd, &self.ps);
+        }
+    }
+}
+
+unsafe fn wm_paint(hwnd: HWND, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) -> Option<LRESULT> {
+    if let Some(inner) = rc_from_hwnd(hwnd) {
+        let inner = inner.borrow();
+
+        let paint = PaintGuard::begin(hwnd);
+        let dc = paint.hdc();
 
         let mut rect = RECT {
             left: 0,
@@ -808,7 +1107,6 @@ unsafe fn wm_paint(hwnd: HWND, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) ->
 
                 inner.callbacks.borrow_mut().paint_opengl(&mut frame);
                 frame.finish().expect("frame.finish failed");
-                EndPaint(hwnd, &mut ps);
                 return Some(0);
             }
         }
@@ -839,8 +1137,6 @@ unsafe fn wm_paint(hwnd: HWND, _msg: UINT, _wparam: WPARAM, _lparam: LPARAM) ->
             );
         }
 
-        EndPaint(hwnd, &mut ps);
-
         Some(0)
     } else {
         None
@@ -917,16 +1213,20 @@ fn apply_mouse_cursor(cursor: Option<MouseCursor>) {
 
 unsafe fn mouse_button(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
     if let Some(inner) = rc_from_hwnd(hwnd) {
-        // To support dragging the window, capture when the left
-        // button goes down and release when it goes up.
+        let (modifiers, mouse_buttons) = mods_and_buttons(wparam);
+        // To support dragging, capture when a button goes down and
+        // release once all of the buttons have gone up.
         // Without this, the drag state can be confused when dragging
         // the mouse up outside of the client area.
-        if msg == WM_LBUTTONDOWN {
-            SetCapture(hwnd);
-        } else if msg == WM_LBUTTONUP {
-            ReleaseCapture();
+        match msg {
+            WM_LBUTTONDOWN | WM_RBUTTONDOWN | WM_MBUTTONDOWN => {
+                SetCapture(hwnd);
+            }
+            WM_LBUTTONUP | WM_RBUTTONUP | WM_MBUTTONUP if mouse_buttons.is_empty() => {
+                ReleaseCapture();
+            }
+            _ => {}
         }
-        let (modifiers, mouse_buttons) = mods_and_buttons(wparam);
         let coords = mouse_coords(lparam);
         let event = MouseEvent {
             kind: match msg {
@@ -990,6 +1290,18 @@ fn read_scroll_speed(name: &str) -> io::Result<i16> {
         .and_then(|v| v.parse().map_err(|_| io::ErrorKind::InvalidData.into()))
 }
 
+/// Converts a wheel `delta` into a number of positions to scroll,
+/// with the same sign as the delta.  Partial notches from high
+/// resolution wheels are accumulated in `remainder` so that they
+/// eventually add up to a whole position.
+fn wheel_position(delta: i16, scroll_speed: i16, remainder: &mut i16) -> i16 {
+    let mut position = delta * scroll_speed / WHEEL_DELTA;
+    *remainder += delta % WHEEL_DELTA;
+    position += *remainder / WHEEL_DELTA;
+    *remainder %= WHEEL_DELTA;
+    position
+}
+
 unsafe fn mouse_wheel(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
     if let Some(inner) = rc_from_hwnd(hwnd) {
         let (modifiers, mouse_buttons) = mods_and_buttons(wparam);
@@ -998,26 +1310,21 @@ unsafe fn mouse_wheel(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
         let screen_coords = ScreenPoint::new(coords.x, coords.y);
         let coords = screen_to_client(hwnd, screen_coords);
         let delta = GET_WHEEL_DELTA_WPARAM(wparam);
-        let scaled_delta = if msg == WM_MOUSEWHEEL {
-            delta * (*WHEEL_SCROLL_LINES)
-        } else {
-            delta * (*WHEEL_SCROLL_CHARS)
-        };
-        let mut position = scaled_delta / WHEEL_DELTA;
-        let remainder = delta % WHEEL_DELTA;
         let event = MouseEvent {
             kind: if msg == WM_MOUSEHWHEEL {
                 let mut inner = inner.borrow_mut();
-                inner.hscroll_remainder += remainder;
-                position += inner.hscroll_remainder / WHEEL_DELTA;
-                inner.hscroll_remainder %= WHEEL_DELTA;
-                MouseEventKind::HorzWheel(position)
+                MouseEventKind::HorzWheel(wheel_position(
+                    delta,
+                    *WHEEL_SCROLL_CHARS,
+                    &mut inner.hscroll_remainder,
+                ))
             } else {
                 let mut inner = inner.borrow_mut();
-                inner.vscroll_remainder += remainder;
-                position += inner.vscroll_remainder / WHEEL_DELTA;
-                inner.vscroll_remainder %= WHEEL_DELTA;
-                MouseEventKind::VertWheel(position)
+                MouseEventKind::VertWheel(wheel_position(
+                    delta,
+                    *WHEEL_SCROLL_LINES,
+                    &mut inner.vscroll_remainder,
+                ))
             },
             coords,
             screen_coords,
@@ -1068,6 +1375,38 @@ impl ImmContext {
             ImmSetCompositionWindow(self.imc, &mut cf);
         }
     }
+
+    /// Returns the composition string identified by `index`, which
+    /// is one of the GCS_XXX constants
+    pub fn get_composition_string(&self, index: DWORD) -> Option<String> {
+        unsafe {
+            // This returns a size in bytes even though it is for a buffer of u16!
+            let byte_size = ImmGetCompositionStringW(self.imc, index, std::ptr::null_mut(), 0);
+            if byte_size < 0 {
+                return None;
+            }
+            let mut wide_buf = vec![0u16; byte_size as usize / 2];
+            ImmGetCompositionStringW(
+                self.imc,
+                index,
+                wide_buf.as_mut_ptr() as *mut _,
+                byte_size as u32,
+            );
+            composition_string(&wide_buf)
+        }
+    }
+}
+
+/// Converts a composition string, which isn't NUL terminated,
+/// from the wide characters returned by the IMM functions
+fn composition_string(wide: &[u16]) -> Option<String> {
+    match String::from_utf16(wide) {
+        Ok(s) => Some(s),
+        Err(_) => {
+            log::error!("cannot represent IME as unicode string!?");
+            None
+        }
+    }
 }
 
 impl Drop for ImmContext {
@@ -1078,6 +1417,38 @@ impl Drop for ImmContext {
     }
 }
 
+unsafe fn ime_start_composition(
+    hwnd: HWND,
+    _msg: UINT,
+    _wparam: WPARAM,
+    _lparam: LPARAM,
+) -> Option<LRESULT> {
+    if let Some(inner) = rc_from_hwnd(hwnd) {
+        let inner = inner.borrow();
+        // Some input methods reset the position of their window
+        // when a new composition starts; put it back at the cursor
+        if let Some(cursor) = inner.text_cursor {
+            let imc = ImmContext::get(hwnd);
+            imc.set_position(cursor.origin.x.max(0) as i32, cursor.origin.y.max(0) as i32);
+        }
+    }
+    // Let the default processing show the composition window
+    None
+}
+
+unsafe fn ime_end_composition(
+    hwnd: HWND,
+    _msg: UINT,
+    _wparam: WPARAM,
+    _lparam: LPARAM,
+) -> Option<LRESULT> {
+    if let Some(inner) = rc_from_hwnd(hwnd) {
+        let inner = inner.borrow();
+        inner.callbacks.borrow_mut().ime_composition("");
+    }
+    None
+}
+
 unsafe fn ime_composition(
     hwnd: HWND,
     _msg: UINT,
@@ -1086,6 +1457,13 @@ unsafe fn ime_composition(
 ) -> Option<LRESULT> {
     if let Some(inner) = rc_from_hwnd(hwnd) {
         let inner = inner.borrow();
+        let imc = ImmContext::get(hwnd);
+
+        if (lparam as DWORD) & GCS_COMPSTR != 0 {
+            if let Some(preedit) = imc.get_composition_string(GCS_COMPSTR) {
+                inner.callbacks.borrow_mut().ime_composition(&preedit);
+            }
+        }
 
         if (lparam as DWORD) & GCS_RESULTSTR == 0 {
             // No finished result; continue with the default
@@ -1093,39 +1471,25 @@ unsafe fn ime_composition(
             return None;
         }
 
-        let imc = ImmContext::get(hwnd);
-
-        // This returns a size in bytes even though it is for a buffer of u16!
-        let byte_size = ImmGetCompositionStringW(imc.imc, GCS_RESULTSTR, std::ptr::null_mut(), 0);
-        if byte_size > 0 {
-            let word_size = byte_size as usize / 2;
-            let mut wide_buf = vec![0u16; word_size];
-            ImmGetCompositionStringW(
-                imc.imc,
-                GCS_RESULTSTR,
-                wide_buf.as_mut_ptr() as *mut _,
-                byte_size as u32,
-            );
-            match OsString::from_wide(&wide_buf).into_string() {
-                Ok(s) => {
-                    let key = KeyEvent {
-                        key: KeyCode::Composed(s),
-                        raw_key: None,
-                        raw_modifiers: Modifiers::NONE,
-                        modifiers: Modifiers::NONE,
-                        repeat_count: 1,
-                        key_is_down: true,
-                    }
-                    .normalize_shift();
-                    inner
-                        .callbacks
-                        .borrow_mut()
-                        .key_event(&key, &Window::from_hwnd(hwnd));
-
-                    return Some(1);
+        match imc.get_composition_string(GCS_RESULTSTR) {
+            Some(s) if !s.is_empty() => {
+                let key = KeyEvent {
+                    key: KeyCode::Composed(s),
+                    raw_key: None,
+                    raw_modifiers: Modifiers::NONE,
+                    modifiers: Modifiers::NONE,
+                    repeat_count: 1,
+                    key_is_down: true,
                 }
-                Err(_) => eprintln!("cannot represent IME as unicode string!?"),
-            };
+                .normalize_shift();
+                inner
+                    .callbacks
+                    .borrow_mut()
+                    .key_event(&key, &Window::from_hwnd(hwnd));
+
+                return Some(1);
+            }
+            _ => {}
         }
     }
     None
@@ -1429,6 +1793,80 @@ unsafe fn translate_message(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARA
     });
 }
 
+/// Maps a virtual key code to the corresponding KeyCode, without
+/// considering the keyboard layout; that is, this is the "raw" key
+fn vkey_to_keycode(vk: i32) -> Option<KeyCode> {
+    match vk {
+        0 => None,
+        VK_CANCEL => Some(KeyCode::Cancel),
+        VK_BACK => Some(KeyCode::Char('\u{8}')),
+        VK_TAB => Some(KeyCode::Char('\t')),
+        VK_CLEAR => Some(KeyCode::Clear),
+        VK_RETURN => Some(KeyCode::Char('\r')),
+        VK_SHIFT => Some(KeyCode::Shift),
+        VK_CONTROL => Some(KeyCode::Control),
+        VK_MENU => Some(KeyCode::Alt),
+        VK_PAUSE => Some(KeyCode::Pause),
+        VK_CAPITAL => Some(KeyCode::CapsLock),
+        VK_ESCAPE => Some(KeyCode::Char('\u{1b}')),
+        VK_SPACE => Some(KeyCode::Char(' ')),
+        VK_PRIOR => Some(KeyCode::PageUp),
+        VK_NEXT => Some(KeyCode::PageDown),
+        VK_END => Some(KeyCode::End),
+        VK_HOME => Some(KeyCode::Home),
+        VK_LEFT => Some(KeyCode::LeftArrow),
+        VK_UP => Some(KeyCode::UpArrow),
+        VK_RIGHT => Some(KeyCode::RightArrow),
+        VK_DOWN => Some(KeyCode::DownArrow),
+        VK_SELECT => Some(KeyCode::Select),
+        VK_PRINT => Some(KeyCode::Print),
+        VK_EXECUTE => Some(KeyCode::Execute),
+        VK_SNAPSHOT => Some(KeyCode::PrintScreen),
+        VK_INSERT => Some(KeyCode::Insert),
+        VK_DELETE => Some(KeyCode::Char('\u{7f}')),
+        VK_HELP => Some(KeyCode::Help),
+        // 0-9 happen to overlap with ascii
+        i @ 0x30..=0x39 => Some(KeyCode::Char(i as u8 as char)),
+        // a-z also overlap with ascii
+        i @ 0x41..=0x5a => Some(KeyCode::Char((i as u8 as char).to_ascii_lowercase())),
+        VK_LWIN => Some(KeyCode::LeftWindows),
+        VK_RWIN => Some(KeyCode::RightWindows),
+        VK_APPS => Some(KeyCode::Applications),
+        VK_SLEEP => Some(KeyCode::Sleep),
+        i @ VK_NUMPAD0..=VK_NUMPAD9 => Some(KeyCode::Numpad((i - VK_NUMPAD0) as u8)),
+        VK_MULTIPLY => Some(KeyCode::Multiply),
+        VK_ADD => Some(KeyCode::Add),
+        VK_SEPARATOR => Some(KeyCode::Separator),
+        VK_SUBTRACT => Some(KeyCode::Subtract),
+        VK_DECIMAL => Some(KeyCode::Decimal),
+        VK_DIVIDE => Some(KeyCode::Divide),
+        i @ VK_F1..=VK_F24 => Some(KeyCode::Function((1 + i - VK_F1) as u8)),
+        VK_NUMLOCK => Some(KeyCode::NumLock),
+        VK_SCROLL => Some(KeyCode::ScrollLock),
+        VK_LSHIFT => Some(KeyCode::LeftShift),
+        VK_RSHIFT => Some(KeyCode::RightShift),
+        VK_LCONTROL => Some(KeyCode::LeftControl),
+        VK_RCONTROL => Some(KeyCode::RightControl),
+        VK_LMENU => Some(KeyCode::LeftAlt),
+        VK_RMENU => Some(KeyCode::RightAlt),
+        VK_BROWSER_BACK => Some(KeyCode::BrowserBack),
+        VK_BROWSER_FORWARD => Some(KeyCode::BrowserForward),
+        VK_BROWSER_REFRESH => Some(KeyCode::BrowserRefresh),
+        VK_BROWSER_STOP => Some(KeyCode::BrowserStop),
+        VK_BROWSER_SEARCH => Some(KeyCode::BrowserSearch),
+        VK_BROWSER_FAVORITES => Some(KeyCode::BrowserFavorites),
+        VK_BROWSER_HOME => Some(KeyCode::BrowserHome),
+        VK_VOLUME_MUTE => Some(KeyCode::VolumeMute),
+        VK_VOLUME_DOWN => Some(KeyCode::VolumeDown),
+        VK_VOLUME_UP => Some(KeyCode::VolumeUp),
+        VK_MEDIA_NEXT_TRACK => Some(KeyCode::MediaNextTrack),
+        VK_MEDIA_PREV_TRACK => Some(KeyCode::MediaPrevTrack),
+        VK_MEDIA_STOP => Some(KeyCode::MediaStop),
+        VK_MEDIA_PLAY_PAUSE => Some(KeyCode::MediaPlayPause),
+        _ => None,
+    }
+}
+
 unsafe fn key(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<LRESULT> {
     if let Some(inner) = rc_from_hwnd(hwnd) {
         let mut inner = inner.borrow_mut();
@@ -1552,75 +1990,7 @@ unsafe fn key(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) -> Option<L
             }
 
             // Determine the raw, underlying key event
-            raw = match wparam as i32 {
-                0 => None,
-                VK_CANCEL => Some(KeyCode::Cancel),
-                VK_BACK => Some(KeyCode::Char('\u{8}')),
-                VK_TAB => Some(KeyCode::Char('\t')),
-                VK_CLEAR => Some(KeyCode::Clear),
-                VK_RETURN => Some(KeyCode::Char('\r')),
-                VK_SHIFT => Some(KeyCode::Shift),
-                VK_CONTROL => Some(KeyCode::Control),
-                VK_MENU => Some(KeyCode::Alt),
-                VK_PAUSE => Some(KeyCode::Pause),
-                VK_CAPITAL => Some(KeyCode::CapsLock),
-                VK_ESCAPE => Some(KeyCode::Char('\u{1b}')),
-                VK_SPACE => Some(KeyCode::Char(' ')),
-                VK_PRIOR => Some(KeyCode::PageUp),
-                VK_NEXT => Some(KeyCode::PageDown),
-                VK_END => Some(KeyCode::End),
-                VK_HOME => Some(KeyCode::Home),
-                VK_LEFT => Some(KeyCode::LeftArrow),
-                VK_UP => Some(KeyCode::UpArrow),
-                VK_RIGHT => Some(KeyCode::RightArrow),
-                VK_DOWN => Some(KeyCode::DownArrow),
-                VK_SELECT => Some(KeyCode::Select),
-                VK_PRINT => Some(KeyCode::Print),
-                VK_EXECUTE => Some(KeyCode::Execute),
-                VK_SNAPSHOT => Some(KeyCode::PrintScreen),
-                VK_INSERT => Some(KeyCode::Insert),
-                VK_DELETE => Some(KeyCode::Char('\u{7f}')),
-                VK_HELP => Some(KeyCode::Help),
-                // 0-9 happen to overlap with ascii
-                i @ 0x30..=0x39 => Some(KeyCode::Char(i as u8 as char)),
-                // a-z also overlap with ascii
-                i @ 0x41..=0x5a => Some(KeyCode::Char((i as u8 as char).to_ascii_lowercase())),
-                VK_LWIN => Some(KeyCode::LeftWindows),
-                VK_RWIN => Some(KeyCode::RightWindows),
-                VK_APPS => Some(KeyCode::Applications),
-                VK_SLEEP => Some(KeyCode::Sleep),
-                i @ VK_NUMPAD0..=VK_NUMPAD9 => Some(KeyCode::Numpad((i - VK_NUMPAD0) as u8)),
-                VK_MULTIPLY => Some(KeyCode::Multiply),
-                VK_ADD => Some(KeyCode::Add),
-                VK_SEPARATOR => Some(KeyCode::Separator),
-                VK_SUBTRACT => Some(KeyCode::Subtract),
-                VK_DECIMAL => Some(KeyCode::Decimal),
-                VK_DIVIDE => Some(KeyCode::Divide),
-                i @ VK_F1..=VK_F24 => Some(KeyCode::Function((1 + i - VK_F1) as u8)),
-                VK_NUMLOCK => Some(KeyCode::NumLock),
-                VK_SCROLL => Some(KeyCode::ScrollLock),
-                VK_LSHIFT => Some(KeyCode::LeftShift),
-                VK_RSHIFT => Some(KeyCode::RightShift),
-                VK_LCONTROL => Some(KeyCode::LeftControl),
-                VK_RCONTROL => Some(KeyCode::RightControl),
-                VK_LMENU => Some(KeyCode::LeftAlt),
-                VK_RMENU => Some(KeyCode::RightAlt),
-                VK_BROWSER_BACK => Some(KeyCode::BrowserBack),
-                VK_BROWSER_FORWARD => Some(KeyCode::BrowserForward),
-                VK_BROWSER_REFRESH => Some(KeyCode::BrowserRefresh),
-                VK_BROWSER_STOP => Some(KeyCode::BrowserStop),
-                VK_BROWSER_SEARCH => Some(KeyCode::BrowserSearch),
-                VK_BROWSER_FAVORITES => Some(KeyCode::BrowserFavorites),
-                VK_BROWSER_HOME => Some(KeyCode::BrowserHome),
-                VK_VOLUME_MUTE => Some(KeyCode::VolumeMute),
-                VK_VOLUME_DOWN => Some(KeyCode::VolumeDown),
-                VK_VOLUME_UP => Some(KeyCode::VolumeUp),
-                VK_MEDIA_NEXT_TRACK => Some(KeyCode::MediaNextTrack),
-                VK_MEDIA_PREV_TRACK => Some(KeyCode::MediaPrevTrack),
-                VK_MEDIA_STOP => Some(KeyCode::MediaStop),
-                VK_MEDIA_PLAY_PAUSE => Some(KeyCode::MediaPlayPause),
-                _ => None,
-            };
+            raw = vkey_to_keycode(wparam as i32);
 
             let is_modifier_only = raw.as_ref().map(|r| r.is_modifier()).unwrap_or(false);
             if is_modifier_only {
@@ -1796,11 +2166,15 @@ unsafe fn do_wnd_proc(hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM) ->
         WM_PAINT => wm_paint(hwnd, msg, wparam, lparam),
         WM_ENTERSIZEMOVE | WM_EXITSIZEMOVE => wm_enter_exit_size_move(hwnd, msg, wparam, lparam),
         WM_WINDOWPOSCHANGED => wm_windowposchanged(hwnd, msg, wparam, lparam),
+        WM_DPICHANGED => wm_dpichanged(hwnd, msg, wparam, lparam),
         WM_SETFOCUS => wm_set_focus(hwnd, msg, wparam, lparam),
         WM_KILLFOCUS => wm_kill_focus(hwnd, msg, wparam, lparam),
+        WM_DROPFILES => wm_dropfiles(hwnd, msg, wparam, lparam),
         WM_DEADCHAR | WM_KEYDOWN | WM_KEYUP | WM_SYSCHAR | WM_CHAR | WM_IME_CHAR | WM_SYSKEYUP
         | WM_SYSKEYDOWN => key(hwnd, msg, wparam, lparam),
+        WM_IME_STARTCOMPOSITION => ime_start_composition(hwnd, msg, wparam, lparam),
         WM_IME_COMPOSITION => ime_composition(hwnd, msg, wparam, lparam),
+        WM_IME_ENDCOMPOSITION => ime_end_composition(hwnd, msg, wparam, lparam),
         WM_MOUSEMOVE => mouse_move(hwnd, msg, wparam, lparam),
         WM_MOUSEHWHEEL | WM_MOUSEWHEEL => mouse_wheel(hwnd, msg, wparam, lparam),
         WM_LBUTTONDBLCLK | WM_RBUTTONDBLCLK | WM_MBUTTONDBLCLK | WM_LBUTTONDOWN | WM_LBUTTONUP
@@ -1838,3 +2212,221 @@ unsafe extern "system" fn wnd_proc(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn client_rect_dimensions() {
+        let rect = RECT {
+            left: 10,
+            top: 20,
+            right: 810,
+            bottom: 620,
+        };
+        assert_eq!(
+            client_rect_to_dimensions(&rect, 144),
+            Dimensions {
+                pixel_width: 800,
+                pixel_height: 600,
+                dpi: 144,
+            }
+        );
+
+        // A minimized window has an empty client area
+        let empty = RECT {
+            left: 0,
+            top: 0,
+            right: 0,
+            bottom: 0,
+        };
+        assert_eq!(
+            client_rect_to_dimensions(&empty, 96),
+            Dimensions {
+                pixel_width: 0,
+                pixel_height: 0,
+                dpi: 96,
+            }
+        );
+    }
+
+    #[test]
+    fn vkey_mapping() {
+        assert_eq!(vkey_to_keycode(VK_LEFT), Some(KeyCode::LeftArrow));
+        assert_eq!(vkey_to_keycode(VK_UP), Some(KeyCode::UpArrow));
+        assert_eq!(vkey_to_keycode(VK_RIGHT), Some(KeyCode::RightArrow));
+        assert_eq!(vkey_to_keycode(VK_DOWN), Some(KeyCode::DownArrow));
+        assert_eq!(vkey_to_keycode(VK_F1), Some(KeyCode::Function(1)));
+        assert_eq!(vkey_to_keycode(VK_F12), Some(KeyCode::Function(12)));
+        assert_eq!(vkey_to_keycode(VK_F24), Some(KeyCode::Function(24)));
+        // Letters map to their lowercase ascii equivalent; the
+        // layout and shift state are resolved separately
+        assert_eq!(vkey_to_keycode(0x41), Some(KeyCode::Char('a')));
+        assert_eq!(vkey_to_keycode(0x5a), Some(KeyCode::Char('z')));
+        assert_eq!(vkey_to_keycode(0x37), Some(KeyCode::Char('7')));
+        assert_eq!(vkey_to_keycode(VK_NUMPAD3), Some(KeyCode::Numpad(3)));
+        assert_eq!(vkey_to_keycode(VK_RETURN), Some(KeyCode::Char('\r')));
+        assert_eq!(vkey_to_keycode(VK_ESCAPE), Some(KeyCode::Char('\u{1b}')));
+        assert_eq!(vkey_to_keycode(0), None);
+    }
+
+    #[test]
+    fn wheel_delta_sign() {
+        let mut remainder = 0;
+        assert_eq!(wheel_position(WHEEL_DELTA, 3, &mut remainder), 3);
+        assert_eq!(wheel_position(-WHEEL_DELTA, 3, &mut remainder), -3);
+        assert_eq!(wheel_position(2 * WHEEL_DELTA, 1, &mut remainder), 2);
+        assert_eq!(remainder, 0);
+
+        // Partial notches accumulate until they make up a position
+        let mut remainder = 0;
+        assert_eq!(wheel_position(40, 1, &mut remainder), 0);
+        assert_eq!(wheel_position(40, 1, &mut remainder), 0);
+        assert_eq!(wheel_position(40, 1, &mut remainder), 1);
+        assert_eq!(remainder, 0);
+
+        let mut remainder = 0;
+        assert_eq!(wheel_position(-60, 1, &mut remainder), 0);
+        assert_eq!(wheel_position(-60, 1, &mut remainder), -1);
+        assert_eq!(remainder, 0);
+    }
+
+    #[test]
+    fn mouse_button_mask() {
+        assert_eq!(mods_and_buttons(0), (Modifiers::NONE, MouseButtons::NONE));
+        assert_eq!(
+            mods_and_buttons(MK_LBUTTON),
+            (Modifiers::NONE, MouseButtons::LEFT)
+        );
+        assert_eq!(
+            mods_and_buttons(MK_RBUTTON | MK_MBUTTON | MK_CONTROL),
+            (Modifiers::CTRL, MouseButtons::RIGHT | MouseButtons::MIDDLE)
+        );
+        assert_eq!(
+            mods_and_buttons(MK_SHIFT | MK_LBUTTON),
+            (Modifiers::SHIFT, MouseButtons::LEFT)
+        );
+    }
+
+    // This replaces the contents of the user's clipboard, so it
+    // only runs when explicitly requested via `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn clipboard_round_trip() {
+        let text = "clipboard 🦀 wörld";
+        if set_clipboard_text(text).is_err() {
+            // No clipboard available, eg: a headless CI session
+            return;
+        }
+        assert_eq!(get_clipboard_text().unwrap(), text);
+    }
+
+    #[test]
+    fn title_to_wide_string() {
+        assert_eq!(wide_string("wez"), vec![0x77, 0x65, 0x7a, 0]);
+        // Characters outside of the BMP take a surrogate pair
+        assert_eq!(wide_string("ü→🦀"), vec![0xfc, 0x2192, 0xd83e, 0xdd80, 0]);
+        assert_eq!(wide_string(""), vec![0]);
+    }
+
+    #[test]
+    fn client_to_window_size() {
+        let (width, height) = adjust_client_to_window_dimensions(800, 600);
+        assert!(width >= 800);
+        assert!(height > 600, "the caption adds to the height");
+
+        // The frame is a fixed size, independent of the client area
+        let (bigger_width, bigger_height) = adjust_client_to_window_dimensions(810, 620);
+        assert_eq!(bigger_width - width, 10);
+        assert_eq!(bigger_height - height, 20);
+    }
+
+    #[test]
+    fn client_to_window_position() {
+        // An 8px border and a 31px caption
+        let window_rect = RECT {
+            left: 100,
+            top: 50,
+            right: 916,
+            bottom: 689,
+        };
+        let client_origin = ScreenPoint::new(108, 81);
+
+        assert_eq!(
+            window_origin_for_client_origin(ScreenPoint::new(108, 81), client_origin, &window_rect),
+            (100, 50)
+        );
+        assert_eq!(
+            window_origin_for_client_origin(ScreenPoint::new(0, 0), client_origin, &window_rect),
+            (-8, -31)
+        );
+    }
+
+    #[test]
+    fn frame_theme_mapping() {
+        let unreachable = || panic!("only System consults the registry");
+        assert!(use_dark_frame(FrameTheme::Dark, unreachable));
+        assert!(!use_dark_frame(FrameTheme::Light, unreachable));
+        assert!(use_dark_frame(FrameTheme::System, || false));
+        assert!(!use_dark_frame(FrameTheme::System, || true));
+    }
+
+    #[test]
+    fn committed_composition() {
+        let text = "日本語🦀";
+        let wide: Vec<u16> = text.encode_utf16().collect();
+        let committed = composition_string(&wide).unwrap();
+        // This is the KeyCode::Composed text that is written to the pane
+        assert_eq!(committed.as_bytes(), text.as_bytes());
+
+        assert_eq!(composition_string(&[]), Some(String::new()));
+        // An unpaired surrogate
+        assert_eq!(composition_string(&[0xd83e]), None);
+    }
+
+    #[test]
+    fn paint_validates_invalidated_region() {
+        let has_update_region = |hwnd| unsafe { GetUpdateRect(hwnd, null_mut(), 0) } != 0;
+
+        let class_name = wide_string("STATIC");
+        let hwnd = unsafe {
+            CreateWindowExW(
+                0,
+                class_name.as_ptr(),
+                null(),
+                WS_OVERLAPPEDWINDOW,
+                0,
+                0,
+                200,
+                100,
+                null_mut(),
+                null_mut(),
+                GetModuleHandleW(null()),
+                null_mut(),
+            )
+        };
+        if hwnd.is_null() {
+            // No desktop to create windows on
+            return;
+        }
+        unsafe {
+            ShowWindow(hwnd, SW_SHOWNA);
+        }
+
+        // Showing the window made it dirty
+        drop(unsafe { PaintGuard::begin(hwnd) });
+        assert!(!has_update_region(hwnd));
+
+        invalidate_client_area(hwnd);
+        invalidate_client_area(hwnd);
+        assert!(has_update_region(hwnd));
+
+        drop(unsafe { PaintGuard::begin(hwnd) });
+        assert!(!has_update_region(hwnd));
+
+        unsafe {
+            DestroyWindow(hwnd);
+        }
+    }
+}
@@ -4,6 +4,7 @@ use crate::os::x11::window::XWindowInner;
 use crate::os::Connection;
 use crate::spawn::*;
 use crate::timerlist::{TimerEntry, TimerList};
+use crate::{ScreenPoint, ScreenRect};
 use anyhow::{anyhow, bail, Context as _};
 use mio::unix::EventedFd;
 use mio::{Evented, Events, Poll, PollOpt, Ready, Token};
@@ -257,6 +258,47 @@ impl ConnectionOps for XConnection {
             interval,
         });
     }
+
+    fn screens(&self) -> Vec<ScreenRect> {
+        let screen = match self
+            .conn()
+            .get_setup()
+            .roots()
+            .nth(self.screen_num as usize)
+        {
+            Some(screen) => screen,
+            None => return vec![],
+        };
+
+        // Ask RandR for the individual monitors, so that areas of the
+        // root window that no monitor covers aren't considered visible
+        match xcb::randr::get_monitors(self.conn(), screen.root(), true).get_reply() {
+            Ok(reply) if reply.n_monitors() > 0 => reply
+                .monitors()
+                .map(|monitor| {
+                    ScreenRect::new(
+                        ScreenPoint::new(monitor.x() as isize, monitor.y() as isize),
+                        euclid::size2(monitor.width() as isize, monitor.height() as isize),
+                    )
+                })
+                .collect(),
+            _ => {
+                // The root window spans all of the monitors that make up the screen
+                vec![ScreenRect::new(
+                    ScreenPoint::new(0, 0),
+                    euclid::size2(
+                        screen.width_in_pixels() as isize,
+                        screen.height_in_pixels() as isize,
+                    ),
+                )]
+            }
+        }
+    }
+
+    fn beep(&self) {
+        xcb::bell(self.conn(), 0);
+        self.conn().flush();
+    }
 }
 
 impl XConnection {
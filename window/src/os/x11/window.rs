@@ -32,11 +32,28 @@ impl Drop for XcbCursor {
 
 #[derive(Default)]
 struct CopyAndPaste {
-    owned: Option<String>,
+    owned_clipboard: Option<String>,
+    owned_primary: Option<String>,
     request: Option<Promise<String>>,
     time: u32,
 }
 
+impl CopyAndPaste {
+    fn owned(&self, clipboard: Clipboard) -> Option<&String> {
+        match clipboard {
+            Clipboard::Clipboard => self.owned_clipboard.as_ref(),
+            Clipboard::PrimarySelection => self.owned_primary.as_ref(),
+        }
+    }
+
+    fn owned_mut(&mut self, clipboard: Clipboard) -> &mut Option<String> {
+        match clipboard {
+            Clipboard::Clipboard => &mut self.owned_clipboard,
+            Clipboard::PrimarySelection => &mut self.owned_primary,
+        }
+    }
+}
+
 pub(crate) struct XWindowInner {
     window_id: xcb::xproto::Window,
     conn: Weak<XConnection>,
@@ -50,6 +67,9 @@ pub(crate) struct XWindowInner {
     cursor: Option<MouseCursor>,
     cursors: HashMap<Option<MouseCursor>, XcbCursor>,
     copy_and_paste: CopyAndPaste,
+    /// True when the window manager has placed us inside a frame
+    /// window, rather than leaving us as a child of the root
+    reparented: bool,
     #[cfg(feature = "opengl")]
     gl_state: Option<Rc<glium::backend::Context>>,
 }
@@ -283,30 +303,53 @@ impl XWindowInner {
         let cursor_id = match self.cursors.get(&cursor) {
             Some(cursor) => cursor.id,
             None => {
-                let id_no = match cursor.unwrap_or(MouseCursor::Arrow) {
-                    // `/usr/include/X11/cursorfont.h`
-                    MouseCursor::Arrow => 132,
-                    MouseCursor::Hand => 58,
-                    MouseCursor::Text => 152,
-                    MouseCursor::SizeUpDown => 116,
-                    MouseCursor::SizeLeftRight => 108,
-                };
-
                 let cursor_id: xcb::ffi::xcb_cursor_t = conn.generate_id();
-                xcb::create_glyph_cursor(
-                    &conn,
-                    cursor_id,
-                    conn.cursor_font_id,
-                    conn.cursor_font_id,
-                    id_no,
-                    id_no + 1,
-                    0xffff,
-                    0xffff,
-                    0xffff,
-                    0,
-                    0,
-                    0,
-                );
+                match cursor {
+                    Some(cursor) => {
+                        let id_no = match cursor {
+                            // `/usr/include/X11/cursorfont.h`
+                            MouseCursor::Arrow => 132,
+                            MouseCursor::Hand => 58,
+                            MouseCursor::Text => 152,
+                            MouseCursor::SizeUpDown => 116,
+                            MouseCursor::SizeLeftRight => 108,
+                        };
+
+                        xcb::create_glyph_cursor(
+                            &conn,
+                            cursor_id,
+                            conn.cursor_font_id,
+                            conn.cursor_font_id,
+                            id_no,
+                            id_no + 1,
+                            0xffff,
+                            0xffff,
+                            0xffff,
+                            0,
+                            0,
+                            0,
+                        );
+                    }
+                    None => {
+                        // Hide the cursor by using one made from a
+                        // single pixel that is masked out
+                        let pixmap = conn.generate_id();
+                        xcb::create_pixmap(&conn, 1, pixmap, self.window_id, 1, 1);
+                        let gc = conn.generate_id();
+                        xcb::create_gc(&conn, gc, pixmap, &[(xcb::GC_FOREGROUND, 0)]);
+                        xcb::poly_fill_rectangle(
+                            &conn,
+                            pixmap,
+                            gc,
+                            &[xcb::Rectangle::new(0, 0, 1, 1)],
+                        );
+                        xcb::free_gc(&conn, gc);
+                        xcb::create_cursor(
+                            &conn, cursor_id, pixmap, pixmap, 0, 0, 0, 0, 0, 0, 0, 0,
+                        );
+                        xcb::free_pixmap(&conn, pixmap);
+                    }
+                }
 
                 self.cursors.insert(
                     cursor,
@@ -347,7 +390,27 @@ impl XWindowInner {
                     pixel_width: self.width as usize,
                     pixel_height: self.height as usize,
                     dpi: 96,
-                })
+                });
+
+                // The coordinates in a real event are relative to our parent,
+                // which is typically a window manager frame.  The window
+                // manager sends a synthetic event with root coordinates when
+                // it moves us (ICCCM 4.1.5), so only trust real events while
+                // we are a direct child of the root window.
+                let synthetic = event.response_type() & 0x80 != 0;
+                if synthetic || !self.reparented {
+                    self.callbacks
+                        .moved(ScreenPoint::new(cfg.x() as isize, cfg.y() as isize));
+                }
+            }
+            xcb::REPARENT_NOTIFY => {
+                let reparent: &xcb::ReparentNotifyEvent = unsafe { xcb::cast_event(event) };
+                self.reparented = conn
+                    .get_setup()
+                    .roots()
+                    .nth(conn.screen_num as usize)
+                    .map(|screen| screen.root() != reparent.parent())
+                    .unwrap_or(true);
             }
             xcb::KEY_PRESS | xcb::KEY_RELEASE => {
                 let key_press: &xcb::KeyPressEvent = unsafe { xcb::cast_event(event) };
@@ -451,7 +514,7 @@ impl XWindowInner {
                 conn.windows.borrow_mut().remove(&self.window_id);
             }
             xcb::SELECTION_CLEAR => {
-                self.selection_clear()?;
+                self.selection_clear(unsafe { xcb::cast_event(event) })?;
             }
             xcb::SELECTION_REQUEST => {
                 self.selection_request(unsafe { xcb::cast_event(event) })?;
@@ -483,35 +546,52 @@ impl XWindowInner {
         Ok(())
     }
 
+    fn selection_atom(conn: &XConnection, clipboard: Clipboard) -> xcb::Atom {
+        // Note that under xwayland, access to the primary selection is
+        // forbidden by default citing a security concern.
+        match clipboard {
+            Clipboard::Clipboard => conn.atom_clipboard,
+            Clipboard::PrimarySelection => xcb::ATOM_PRIMARY,
+        }
+    }
+
+    fn clipboard_for_atom(conn: &XConnection, selection: xcb::Atom) -> Option<Clipboard> {
+        if selection == xcb::ATOM_PRIMARY {
+            Some(Clipboard::PrimarySelection)
+        } else if selection == conn.atom_clipboard {
+            Some(Clipboard::Clipboard)
+        } else {
+            None
+        }
+    }
+
     /// If we own the selection, make sure that the X server reflects
     /// that and vice versa.
-    fn update_selection_owner(&mut self) {
+    fn update_selection_owner(&mut self, clipboard: Clipboard) {
         let conn = self.conn();
-        for &selection in &[xcb::ATOM_PRIMARY, conn.atom_clipboard] {
-            let current_owner = xcb::get_selection_owner(&conn, selection)
-                .get_reply()
-                .unwrap()
-                .owner();
-            if self.copy_and_paste.owned.is_none() && current_owner == self.window_id {
-                // We don't have a selection but X thinks we do; disown it!
-                xcb::set_selection_owner(&conn, xcb::NONE, selection, self.copy_and_paste.time);
-            } else if self.copy_and_paste.owned.is_some() && current_owner != self.window_id {
-                // We have the selection but X doesn't think we do; assert it!
-                xcb::set_selection_owner(
-                    &conn,
-                    self.window_id,
-                    selection,
-                    self.copy_and_paste.time,
-                );
-            }
+        let selection = Self::selection_atom(&conn, clipboard);
+        let owned = self.copy_and_paste.owned(clipboard).is_some();
+        let current_owner = xcb::get_selection_owner(&conn, selection)
+            .get_reply()
+            .unwrap()
+            .owner();
+        if !owned && current_owner == self.window_id {
+            // We don't have a selection but X thinks we do; disown it!
+            xcb::set_selection_owner(&conn, xcb::NONE, selection, self.copy_and_paste.time);
+        } else if owned && current_owner != self.window_id {
+            // We have the selection but X doesn't think we do; assert it!
+            xcb::set_selection_owner(&conn, self.window_id, selection, self.copy_and_paste.time);
         }
         conn.flush();
     }
 
-    fn selection_clear(&mut self) -> anyhow::Result<()> {
-        self.copy_and_paste.owned.take();
-        self.copy_and_paste.request.take();
-        self.update_selection_owner();
+    fn selection_clear(&mut self, clear: &xcb::SelectionClearEvent) -> anyhow::Result<()> {
+        let conn = self.conn();
+        if let Some(clipboard) = Self::clipboard_for_atom(&conn, clear.selection()) {
+            self.copy_and_paste.owned_mut(clipboard).take();
+            self.copy_and_paste.request.take();
+            self.update_selection_owner(clipboard);
+        }
         Ok(())
     }
 
@@ -558,7 +638,9 @@ impl XWindowInner {
             // We don't and won't do any conversion from UTF-8 to
             // whatever STRING represents; let's just assume that
             // the other end is going to handle it correctly.
-            if let Some(text) = self.copy_and_paste.owned.as_ref() {
+            if let Some(text) = Self::clipboard_for_atom(&conn, request.selection())
+                .and_then(|clipboard| self.copy_and_paste.owned(clipboard))
+            {
                 xcb::xproto::change_property(
                     &conn,
                     xcb::xproto::PROP_MODE_REPLACE as u8,
@@ -723,7 +805,7 @@ impl XWindow {
             let setup = conn.conn().get_setup();
             let screen = setup
                 .roots()
-                .nth(conn.screen_num() as usize)
+                .nth(conn.screen_num as usize)
                 .ok_or_else(|| anyhow!("no screen?"))?;
 
             window_id = conn.conn().generate_id();
@@ -795,6 +877,7 @@ impl XWindow {
                 buffer_image,
                 cursor: None,
                 cursors: HashMap::new(),
+                reparented: false,
                 #[cfg(feature = "opengl")]
                 gl_state: None,
             }))
@@ -1017,7 +1100,7 @@ impl WindowOps for XWindow {
         let mut promise = Some(promise);
         XConnection::with_window_inner(self.0, move |inner| {
             let mut promise = promise.take().unwrap();
-            if let Some(text) = inner.copy_and_paste.owned.as_ref() {
+            if let Some(text) = inner.copy_and_paste.owned(clipboard) {
                 promise.ok(text.to_owned());
 
                 // Cancel any outstanding promise from the other branch
@@ -1031,12 +1114,7 @@ impl WindowOps for XWindow {
                 xcb::convert_selection(
                     &conn,
                     inner.window_id,
-                    // Note that under xwayland, access to the primary selection is
-                    // forbidden by default citing a security concern.
-                    match clipboard {
-                        Clipboard::Clipboard => conn.atom_clipboard,
-                        Clipboard::PrimarySelection => xcb::ATOM_PRIMARY,
-                    },
+                    XWindowInner::selection_atom(&conn, clipboard),
                     conn.atom_utf8_string,
                     conn.atom_xsel_data,
                     inner.copy_and_paste.time,
@@ -1049,10 +1127,13 @@ impl WindowOps for XWindow {
     }
 
     /// Set some text in the clipboard
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
         XConnection::with_window_inner(self.0, move |inner| {
-            inner.copy_and_paste.owned.replace(text.clone());
-            inner.update_selection_owner();
+            inner
+                .copy_and_paste
+                .owned_mut(clipboard)
+                .replace(text.clone());
+            inner.update_selection_owner(clipboard);
             Ok(())
         })
     }
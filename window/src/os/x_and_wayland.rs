@@ -7,7 +7,7 @@ use crate::os::wayland::connection::WaylandConnection;
 use crate::os::wayland::window::WaylandWindow;
 use crate::os::x11::connection::XConnection;
 use crate::os::x11::window::XWindow;
-use crate::{Clipboard, MouseCursor, ScreenPoint, WindowCallbacks, WindowOps};
+use crate::{Clipboard, MouseCursor, ScreenPoint, ScreenRect, WindowCallbacks, WindowOps};
 use promise::*;
 use std::any::Any;
 use std::rc::Rc;
@@ -112,6 +112,22 @@ impl ConnectionOps for Connection {
             Self::Wayland(w) => w.schedule_timer(interval, callback),
         }
     }
+
+    fn screens(&self) -> Vec<ScreenRect> {
+        match self {
+            Self::X11(x) => x.screens(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.screens(),
+        }
+    }
+
+    fn beep(&self) {
+        match self {
+            Self::X11(x) => x.beep(),
+            #[cfg(feature = "wayland")]
+            Self::Wayland(w) => w.beep(),
+        }
+    }
 }
 
 impl Window {
@@ -232,11 +248,11 @@ impl WindowOps for Window {
             Self::Wayland(w) => w.get_clipboard(clipboard),
         }
     }
-    fn set_clipboard(&self, text: String) -> Future<()> {
+    fn set_clipboard(&self, clipboard: Clipboard, text: String) -> Future<()> {
         match self {
-            Self::X11(x) => x.set_clipboard(text),
+            Self::X11(x) => x.set_clipboard(clipboard, text),
             #[cfg(feature = "wayland")]
-            Self::Wayland(w) => w.set_clipboard(text),
+            Self::Wayland(w) => w.set_clipboard(clipboard, text),
         }
     }
 }